@@ -0,0 +1,5 @@
+fn main() {
+	#[cfg(feature = "grpc")]
+	tonic_build::compile_protos("proto/light_client.proto")
+		.expect("gRPC service definitions should compile");
+}
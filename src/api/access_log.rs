@@ -0,0 +1,100 @@
+//! Structured access logs of the HTTP server.
+//!
+//! Each sampled request is logged with method, path, status, latency and API key ID,
+//! which is a short hash of the `X-API-Key` header, so the keys are never logged.
+//! Requests to the excluded paths (e.g. health checks) are not logged.
+
+use std::sync::Arc;
+use tracing::info;
+use warp::log::{Info, Log};
+
+use crate::types::RuntimeConfig;
+
+/// Target of the access log events, which can be used to filter them
+pub const ACCESS_LOG_TARGET: &str = "avail_light::api::access";
+
+/// Number of the hex characters of the API key hash used as the API key ID
+const API_KEY_ID_LENGTH: usize = 16;
+
+#[derive(Clone, Debug, Default)]
+pub struct AccessLogConfig {
+	/// Share of the requests which are logged, from 0 to 1
+	pub sample_rate: f64,
+	/// Paths of the requests which are not logged
+	pub excluded_paths: Vec<String>,
+}
+
+impl From<&RuntimeConfig> for AccessLogConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		AccessLogConfig {
+			sample_rate: val.http_access_log_sample_rate.clamp(0.0, 1.0),
+			excluded_paths: val.http_access_log_excluded_paths.clone(),
+		}
+	}
+}
+
+impl AccessLogConfig {
+	/// Checks if request to the path is logged, given the random sample from the `[0, 1)` range
+	fn is_logged(&self, path: &str, sample: f64) -> bool {
+		sample < self.sample_rate && !self.excluded_paths.iter().any(|excluded| excluded == path)
+	}
+}
+
+/// Short hash of the API key, identifying the key without exposing it
+fn api_key_id(api_key: &str) -> String {
+	let mut id = hex::encode(sp_core::blake2_256(api_key.as_bytes()));
+	id.truncate(API_KEY_ID_LENGTH);
+	id
+}
+
+/// Access log filter, to be applied to the routes with `Filter::with`
+pub fn log(cfg: AccessLogConfig) -> Log<impl Fn(Info) + Clone> {
+	let cfg = Arc::new(cfg);
+	warp::log::custom(move |request: Info| {
+		if !cfg.is_logged(request.path(), rand::random::<f64>()) {
+			return;
+		}
+		let api_key_id = request
+			.request_headers()
+			.get("x-api-key")
+			.and_then(|value| value.to_str().ok())
+			.map(api_key_id);
+		info!(
+			target: ACCESS_LOG_TARGET,
+			method = %request.method(),
+			path = request.path(),
+			status = request.status().as_u16(),
+			latency_ms = request.elapsed().as_secs_f64() * 1000.0,
+			api_key_id = api_key_id.as_deref().unwrap_or("-"),
+			"HTTP request"
+		);
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{api_key_id, AccessLogConfig};
+
+	#[test]
+	fn access_log_sampling() {
+		let cfg = AccessLogConfig {
+			sample_rate: 0.25,
+			excluded_paths: vec!["/health".to_string()],
+		};
+		assert!(cfg.is_logged("/v2/status", 0.1));
+		assert!(!cfg.is_logged("/v2/status", 0.25));
+		assert!(!cfg.is_logged("/health", 0.1));
+
+		let disabled = AccessLogConfig::default();
+		assert!(!disabled.is_logged("/v2/status", 0.0));
+	}
+
+	#[test]
+	fn api_key_id_hides_key() {
+		let id = api_key_id("secret");
+		assert_eq!(id.len(), 16);
+		assert!(!id.contains("secret"));
+		assert_eq!(id, api_key_id("secret"));
+		assert_ne!(id, api_key_id("other"));
+	}
+}
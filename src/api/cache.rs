@@ -0,0 +1,239 @@
+//! Read cache for the API handlers.
+//!
+//! Frequently polled values (verified cell counts and application data) are cached for a short time,
+//! so heavy API polling doesn't contend with the block processing for the database.
+//! Only existing values are cached, so newly processed blocks become visible immediately,
+//! while cached values can be stale for at most the configured TTL.
+
+use color_eyre::Result;
+use std::{
+	collections::HashMap,
+	hash::Hash,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use crate::{
+	data::{Database, Key},
+	telemetry::{MetricValue, Metrics},
+};
+
+/// Capacity bounded map with entries expiring after the TTL
+struct TtlCache<K, V> {
+	ttl: Duration,
+	capacity: usize,
+	entries: HashMap<K, (Instant, V)>,
+}
+
+impl<K: Eq + Hash + Copy, V: Clone> TtlCache<K, V> {
+	fn new(ttl: Duration, capacity: usize) -> Self {
+		TtlCache {
+			ttl,
+			capacity,
+			entries: HashMap::new(),
+		}
+	}
+
+	fn is_expired(&self, inserted: Instant, now: Instant) -> bool {
+		now.saturating_duration_since(inserted) >= self.ttl
+	}
+
+	fn get(&self, key: &K, now: Instant) -> Option<V> {
+		self.entries
+			.get(key)
+			.filter(|(inserted, _)| !self.is_expired(*inserted, now))
+			.map(|(_, value)| value.clone())
+	}
+
+	/// Inserts the value, evicting expired entries first and the oldest entry if cache is still full
+	fn insert(&mut self, key: K, value: V, now: Instant) {
+		if self.capacity == 0 {
+			return;
+		}
+		if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+			let ttl = self.ttl;
+			self.entries
+				.retain(|_, (inserted, _)| now.saturating_duration_since(*inserted) < ttl);
+		}
+		if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+			let oldest = self
+				.entries
+				.iter()
+				.min_by_key(|(_, (inserted, _))| *inserted)
+				.map(|(key, _)| *key);
+			if let Some(oldest) = oldest {
+				self.entries.remove(&oldest);
+			}
+		}
+		self.entries.insert(key, (now, value));
+	}
+}
+
+struct Caches {
+	cell_counts: Mutex<TtlCache<u32, u32>>,
+	app_data: Mutex<TtlCache<(u32, u32), Vec<Vec<u8>>>>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+/// Shared read cache, disabled if created with zero TTL or capacity
+#[derive(Clone, Default)]
+pub struct ReadCache {
+	caches: Option<Arc<Caches>>,
+}
+
+impl ReadCache {
+	pub fn new(ttl: Duration, capacity: usize) -> Self {
+		if ttl.is_zero() || capacity == 0 {
+			return ReadCache::default();
+		}
+		ReadCache {
+			caches: Some(Arc::new(Caches {
+				cell_counts: Mutex::new(TtlCache::new(ttl, capacity)),
+				app_data: Mutex::new(TtlCache::new(ttl, capacity)),
+				hits: AtomicU64::new(0),
+				misses: AtomicU64::new(0),
+			})),
+		}
+	}
+
+	fn get_or_load<K, V>(
+		caches: &Caches,
+		cache: &Mutex<TtlCache<K, V>>,
+		key: K,
+		load: impl FnOnce() -> Result<Option<V>>,
+	) -> Result<Option<V>>
+	where
+		K: Eq + Hash + Copy,
+		V: Clone,
+	{
+		let now = Instant::now();
+		if let Some(value) = cache
+			.lock()
+			.expect("Lock should be acquired")
+			.get(&key, now)
+		{
+			caches.hits.fetch_add(1, Ordering::Relaxed);
+			return Ok(Some(value));
+		}
+		caches.misses.fetch_add(1, Ordering::Relaxed);
+		// Lock is not held while reading from the database
+		let value = load()?;
+		if let Some(value) = &value {
+			let mut cache = cache.lock().expect("Lock should be acquired");
+			cache.insert(key, value.clone(), now);
+		}
+		Ok(value)
+	}
+
+	/// Returns verified cell count of the given block
+	pub fn verified_cell_count(&self, db: &impl Database, block_num: u32) -> Result<Option<u32>> {
+		let load = || db.get(Key::VerifiedCellCount(block_num));
+		match &self.caches {
+			Some(caches) => Self::get_or_load(caches, &caches.cell_counts, block_num, load),
+			None => load(),
+		}
+	}
+
+	/// Returns application data of the given application and block
+	pub fn app_data(
+		&self,
+		db: &impl Database,
+		app_id: u32,
+		block_num: u32,
+	) -> Result<Option<Vec<Vec<u8>>>> {
+		let load = || db.get(Key::AppData(app_id, block_num));
+		match &self.caches {
+			Some(caches) => Self::get_or_load(caches, &caches.app_data, (app_id, block_num), load),
+			None => load(),
+		}
+	}
+
+	/// Returns hit rate (in percents) since the last call, or `None` if there were no lookups
+	pub fn take_hit_rate(&self) -> Option<f64> {
+		let caches = self.caches.as_ref()?;
+		let hits = caches.hits.swap(0, Ordering::Relaxed);
+		let misses = caches.misses.swap(0, Ordering::Relaxed);
+		let total = hits + misses;
+		(total > 0).then(|| hits as f64 * 100.0 / total as f64)
+	}
+}
+
+/// Periodically records cache hit rate metric. Returns immediately if cache is disabled.
+pub async fn record_hit_rate(cache: ReadCache, metrics: Arc<impl Metrics>, interval: Duration) {
+	if cache.caches.is_none() {
+		return;
+	}
+	let mut interval = tokio::time::interval(interval);
+	loop {
+		interval.tick().await;
+		if let Some(hit_rate) = cache.take_hit_rate() {
+			metrics.record(MetricValue::ApiCacheHitRate(hit_rate)).await;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{ReadCache, TtlCache};
+	use crate::data::{mem_db::MemoryDB, Database, Key};
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn ttl_cache_expiration() {
+		let mut cache = TtlCache::new(Duration::from_secs(2), 10);
+		let now = Instant::now();
+		cache.insert(1u32, 10u32, now);
+		assert_eq!(cache.get(&1, now + Duration::from_secs(1)), Some(10));
+		assert_eq!(cache.get(&1, now + Duration::from_secs(2)), None);
+	}
+
+	#[test]
+	fn ttl_cache_capacity() {
+		let mut cache = TtlCache::new(Duration::from_secs(10), 2);
+		let now = Instant::now();
+		cache.insert(1u32, 1u32, now);
+		cache.insert(2, 2, now + Duration::from_secs(1));
+		cache.insert(3, 3, now + Duration::from_secs(2));
+		// Oldest entry is evicted
+		assert_eq!(cache.get(&1, now + Duration::from_secs(2)), None);
+		assert_eq!(cache.get(&2, now + Duration::from_secs(2)), Some(2));
+		assert_eq!(cache.get(&3, now + Duration::from_secs(2)), Some(3));
+		assert_eq!(cache.entries.len(), 2);
+	}
+
+	#[test]
+	fn read_cache_hits() {
+		let db = MemoryDB::default();
+		let cache = ReadCache::new(Duration::from_secs(60), 10);
+		assert_eq!(cache.take_hit_rate(), None);
+
+		// Missing values are not cached
+		assert_eq!(cache.verified_cell_count(&db, 1).unwrap(), None);
+		db.put(Key::VerifiedCellCount(1), 8u32).unwrap();
+		assert_eq!(cache.verified_cell_count(&db, 1).unwrap(), Some(8));
+		// Cached value is returned within the TTL
+		db.put(Key::VerifiedCellCount(1), 9u32).unwrap();
+		assert_eq!(cache.verified_cell_count(&db, 1).unwrap(), Some(8));
+		assert_eq!(cache.take_hit_rate(), Some(100.0 / 3.0));
+		assert_eq!(cache.take_hit_rate(), None);
+
+		db.put(Key::AppData(1, 1), vec![vec![1u8]]).unwrap();
+		assert_eq!(cache.app_data(&db, 1, 1).unwrap(), Some(vec![vec![1u8]]));
+		assert_eq!(cache.app_data(&db, 2, 1).unwrap(), None);
+	}
+
+	#[test]
+	fn disabled_cache() {
+		let db = MemoryDB::default();
+		let cache = ReadCache::new(Duration::ZERO, 10);
+		db.put(Key::VerifiedCellCount(1), 8u32).unwrap();
+		assert_eq!(cache.verified_cell_count(&db, 1).unwrap(), Some(8));
+		db.put(Key::VerifiedCellCount(1), 9u32).unwrap();
+		assert_eq!(cache.verified_cell_count(&db, 1).unwrap(), Some(9));
+		assert_eq!(cache.take_hit_rate(), None);
+	}
+}
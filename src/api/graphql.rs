@@ -0,0 +1,352 @@
+//! GraphQL endpoint for flexible queries over the light client store.
+//!
+//! # Endpoints
+//!
+//! * `/graphql` - executes GraphQL queries (`POST`) and serves GraphiQL playground (`GET`)
+//!
+//! Exposed queries are `block`, `blocks` (range query with optional status and confidence filters),
+//! `confidence`, `appData` and `networkStats`.
+
+use crate::{
+	api::v2::types::{block_status, BlockStatus},
+	confidence::Confidence,
+	data::{Database, Key},
+	network::p2p,
+	types::{OptionBlockRange, RuntimeConfig, State},
+};
+use async_graphql::{
+	http::GraphiQLSource, Context, EmptyMutation, EmptySubscription, Enum, Object, Schema,
+	SimpleObject,
+};
+use async_graphql_warp::GraphQLResponse;
+use avail_subxt::primitives;
+use codec::Encode;
+use color_eyre::{eyre::eyre, Result};
+use sp_core::{blake2_256, H256};
+use std::{
+	convert::Infallible,
+	marker::PhantomData,
+	sync::{Arc, Mutex},
+};
+use warp::{http::Response as HttpResponse, Filter, Rejection, Reply};
+
+/// Maximum number of blocks which can be returned by a single range query
+const MAX_BLOCKS_RANGE: u32 = 100;
+
+#[derive(Enum, Copy, Clone, Eq, PartialEq, Debug)]
+#[graphql(name = "BlockStatus")]
+pub enum GraphQLBlockStatus {
+	Unavailable,
+	Pending,
+	VerifyingHeader,
+	VerifyingConfidence,
+	VerifyingData,
+	Incomplete,
+	Finished,
+}
+
+impl From<BlockStatus> for GraphQLBlockStatus {
+	fn from(value: BlockStatus) -> Self {
+		match value {
+			BlockStatus::Unavailable => GraphQLBlockStatus::Unavailable,
+			BlockStatus::Pending => GraphQLBlockStatus::Pending,
+			BlockStatus::VerifyingHeader => GraphQLBlockStatus::VerifyingHeader,
+			BlockStatus::VerifyingConfidence => GraphQLBlockStatus::VerifyingConfidence,
+			BlockStatus::VerifyingData => GraphQLBlockStatus::VerifyingData,
+			BlockStatus::Incomplete => GraphQLBlockStatus::Incomplete,
+			BlockStatus::Finished => GraphQLBlockStatus::Finished,
+		}
+	}
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct BlockInfo {
+	pub number: u32,
+	pub hash: Option<String>,
+	pub status: GraphQLBlockStatus,
+	pub confidence: Option<f64>,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct AppDataInfo {
+	pub block_number: u32,
+	pub app_id: u32,
+	pub available: bool,
+	pub transactions_count: usize,
+	pub size: usize,
+}
+
+#[derive(SimpleObject, Debug)]
+pub struct NetworkStats {
+	pub latest_block: u32,
+	pub first_verified_block: Option<u32>,
+	pub last_verified_block: Option<u32>,
+	pub connected_peers: usize,
+	pub kademlia_map_size: usize,
+}
+
+/// Shared data available to query resolvers
+pub struct QueryContext<T: Database> {
+	pub db: T,
+	pub cfg: RuntimeConfig,
+	pub state: Arc<Mutex<State>>,
+	pub p2p_client: p2p::Client,
+}
+
+pub struct QueryRoot<T>(PhantomData<T>);
+
+impl<T> Default for QueryRoot<T> {
+	fn default() -> Self {
+		QueryRoot(PhantomData)
+	}
+}
+
+fn block_info(
+	context: &QueryContext<impl Database>,
+	block_number: u32,
+) -> Result<Option<BlockInfo>> {
+	let Some(header) = context
+		.db
+		.get::<primitives::Header>(Key::BlockHeader(block_number))?
+	else {
+		return Ok(None);
+	};
+
+	let hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+	let status = {
+		let state = context.state.lock().expect("Lock should be acquired");
+		block_status(
+			&context.cfg.sync_start_block,
+			&state,
+			block_number,
+			header.extension,
+		)
+	};
+
+	let Some(status) = status else {
+		return Ok(None);
+	};
+
+	let confidence = context
+		.db
+		.get(Key::VerifiedCellCount(block_number))?
+		.map(|count| Confidence::from_cell_count(count, context.cfg.withheld_fraction))
+		.map(f64::from);
+
+	Ok(Some(BlockInfo {
+		number: block_number,
+		hash: Some(format!("{hash:?}")),
+		status: status.into(),
+		confidence,
+	}))
+}
+
+#[Object(name = "Query")]
+impl<T: Database + Send + Sync + 'static> QueryRoot<T> {
+	/// Returns block information for the given block number
+	async fn block(
+		&self,
+		ctx: &Context<'_>,
+		number: u32,
+	) -> async_graphql::Result<Option<BlockInfo>> {
+		let context = ctx.data::<QueryContext<T>>()?;
+		Ok(block_info(context, number)?)
+	}
+
+	/// Returns blocks in the inclusive range, optionally filtered by status and minimal confidence
+	async fn blocks(
+		&self,
+		ctx: &Context<'_>,
+		from: u32,
+		to: u32,
+		status: Option<GraphQLBlockStatus>,
+		min_confidence: Option<f64>,
+	) -> async_graphql::Result<Vec<BlockInfo>> {
+		let context = ctx.data::<QueryContext<T>>()?;
+		if from > to {
+			return Err(eyre!("Invalid range: {from} > {to}").into());
+		}
+		if to - from >= MAX_BLOCKS_RANGE {
+			return Err(eyre!("Range is limited to {MAX_BLOCKS_RANGE} blocks").into());
+		}
+
+		let mut blocks = vec![];
+		for block_number in from..=to {
+			let Some(block) = block_info(context, block_number)? else {
+				continue;
+			};
+			if status.is_some_and(|status| status != block.status) {
+				continue;
+			}
+			if min_confidence.is_some_and(|min| block.confidence.unwrap_or(0.0) < min) {
+				continue;
+			}
+			blocks.push(block);
+		}
+		Ok(blocks)
+	}
+
+	/// Returns confidence for the given block number, if calculated
+	async fn confidence(
+		&self,
+		ctx: &Context<'_>,
+		number: u32,
+	) -> async_graphql::Result<Option<f64>> {
+		let context = ctx.data::<QueryContext<T>>()?;
+		let count = context.db.get(Key::VerifiedCellCount(number))?;
+		let withheld_fraction = context.cfg.withheld_fraction;
+		Ok(count
+			.map(|count| Confidence::from_cell_count(count, withheld_fraction))
+			.map(f64::from))
+	}
+
+	/// Returns app data availability for the given block and application
+	async fn app_data(
+		&self,
+		ctx: &Context<'_>,
+		number: u32,
+		app_id: Option<u32>,
+	) -> async_graphql::Result<AppDataInfo> {
+		let context = ctx.data::<QueryContext<T>>()?;
+		let app_id = app_id.or(context.cfg.app_id).unwrap_or(0);
+		let data: Option<Vec<Vec<u8>>> = context.db.get(Key::AppData(app_id, number))?;
+
+		Ok(AppDataInfo {
+			block_number: number,
+			app_id,
+			available: data.is_some(),
+			transactions_count: data.as_ref().map_or(0, Vec::len),
+			size: data
+				.as_ref()
+				.map_or(0, |data| data.iter().map(Vec::len).sum()),
+		})
+	}
+
+	/// Returns basic network and verification statistics
+	async fn network_stats(&self, ctx: &Context<'_>) -> async_graphql::Result<NetworkStats> {
+		let context = ctx.data::<QueryContext<T>>()?;
+		let (latest_block, first_verified_block, last_verified_block) = {
+			let state = context.state.lock().expect("Lock should be acquired");
+			(
+				state.latest,
+				state.confidence_achieved.first(),
+				state.confidence_achieved.last(),
+			)
+		};
+		let connected_peers = context.p2p_client.count_dht_entries().await?;
+		let kademlia_map_size = context.p2p_client.get_kademlia_map_size().await?;
+
+		Ok(NetworkStats {
+			latest_block,
+			first_verified_block,
+			last_verified_block,
+			connected_peers,
+			kademlia_map_size,
+		})
+	}
+}
+
+pub type LightClientSchema<T> = Schema<QueryRoot<T>, EmptyMutation, EmptySubscription>;
+
+pub fn schema<T: Database + Send + Sync + 'static>(
+	context: QueryContext<T>,
+) -> LightClientSchema<T> {
+	Schema::build(QueryRoot::default(), EmptyMutation, EmptySubscription)
+		.data(context)
+		.finish()
+}
+
+pub fn routes<T: Database + Clone + Send + Sync + 'static>(
+	context: QueryContext<T>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	let schema = schema(context);
+
+	let graphql_post = warp::path!("graphql")
+		.and(warp::post())
+		.and(async_graphql_warp::graphql(schema))
+		.and_then(
+			|(schema, request): (LightClientSchema<T>, async_graphql::Request)| async move {
+				Ok::<_, Infallible>(GraphQLResponse::from(schema.execute(request).await))
+			},
+		);
+
+	let graphiql = warp::path!("graphql").and(warp::get()).map(|| {
+		HttpResponse::builder()
+			.header("content-type", "text/html")
+			.body(GraphiQLSource::build().endpoint("/graphql").finish())
+	});
+
+	graphiql.or(graphql_post)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{schema, QueryContext};
+	use crate::{
+		data::{mem_db, Database, Key},
+		network::p2p,
+		types::{RuntimeConfig, State},
+	};
+	use serde_json::json;
+	use std::sync::{Arc, Mutex};
+	use tokio::sync::mpsc;
+
+	fn context(db: mem_db::MemoryDB) -> QueryContext<mem_db::MemoryDB> {
+		let (command_sender, _) = mpsc::unbounded_channel();
+		QueryContext {
+			db,
+			cfg: RuntimeConfig::default(),
+			state: Arc::new(Mutex::new(State::default())),
+			p2p_client: p2p::Client::new(command_sender, 1, 1),
+		}
+	}
+
+	#[tokio::test]
+	async fn app_data_query() {
+		let db = mem_db::MemoryDB::default();
+		db.put(Key::AppData(1, 5), vec![vec![1u8, 2, 3], vec![4u8]])
+			.unwrap();
+		let schema = schema(context(db));
+
+		let response = schema
+			.execute("{ appData(number: 5, appId: 1) { available transactionsCount size } }")
+			.await;
+		assert!(response.errors.is_empty());
+		assert_eq!(
+			serde_json::to_value(&response.data).unwrap(),
+			json!({ "appData": { "available": true, "transactionsCount": 2, "size": 4 } })
+		);
+
+		let response = schema
+			.execute("{ appData(number: 6, appId: 1) { available transactionsCount } }")
+			.await;
+		assert_eq!(
+			serde_json::to_value(&response.data).unwrap(),
+			json!({ "appData": { "available": false, "transactionsCount": 0 } })
+		);
+	}
+
+	#[tokio::test]
+	async fn blocks_query_invalid_range() {
+		let schema = schema(context(mem_db::MemoryDB::default()));
+
+		let response = schema
+			.execute("{ blocks(from: 10, to: 1) { number } }")
+			.await;
+		assert_eq!(response.errors.len(), 1);
+
+		let response = schema
+			.execute("{ blocks(from: 0, to: 1000) { number } }")
+			.await;
+		assert_eq!(response.errors.len(), 1);
+
+		let response = schema
+			.execute("{ blocks(from: 0, to: 10) { number } }")
+			.await;
+		assert!(response.errors.is_empty());
+		assert_eq!(
+			serde_json::to_value(&response.data).unwrap(),
+			json!({ "blocks": [] })
+		);
+	}
+}
@@ -0,0 +1,242 @@
+//! gRPC server for confidence and app data retrieval.
+//!
+//! Service definition is located in `proto/light_client.proto`.
+//!
+//! # Methods
+//!
+//! * `GetConfidence` - returns calculated confidence for a given block number
+//! * `StreamVerifiedBlocks` - streams blocks once their confidence is achieved
+//! * `GetAppData` - returns encoded extrinsics for a given block number and app ID
+
+use crate::{
+	confidence::Confidence,
+	data::{Database, Key},
+	shutdown::Controller,
+	types::BlockVerified,
+};
+use color_eyre::{eyre::WrapErr, Result};
+use futures::{FutureExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, pin::Pin, str::FromStr};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{info, warn};
+
+pub mod proto {
+	tonic::include_proto!("avail.light.v1");
+}
+
+use proto::{
+	light_client_server::{LightClient, LightClientServer},
+	AppDataRequest, AppDataResponse, ConfidenceRequest, ConfidenceResponse,
+	StreamVerifiedBlocksRequest, VerifiedBlock,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct GrpcConfig {
+	/// Enables gRPC server (default: false)
+	pub grpc_server_enable: bool,
+	/// Light client gRPC server host name (default: 127.0.0.1)
+	pub grpc_server_host: String,
+	/// Light client gRPC server port (default: 7008)
+	pub grpc_server_port: u16,
+}
+
+impl Default for GrpcConfig {
+	fn default() -> Self {
+		Self {
+			grpc_server_enable: false,
+			grpc_server_host: "127.0.0.1".to_string(),
+			grpc_server_port: 7008,
+		}
+	}
+}
+
+pub struct LightClientService<T: Database> {
+	db: T,
+	app_id: Option<u32>,
+	withheld_fraction: f64,
+	block_sender: broadcast::Sender<BlockVerified>,
+}
+
+impl From<&BlockVerified> for VerifiedBlock {
+	fn from(block: &BlockVerified) -> Self {
+		VerifiedBlock {
+			block_number: block.block_num,
+			header_hash: block.header_hash.as_bytes().to_vec(),
+			confidence: block.confidence.map(f64::from),
+		}
+	}
+}
+
+fn internal(error: color_eyre::Report) -> Status {
+	Status::internal(format!("{error:#}"))
+}
+
+type VerifiedBlocksStream = Pin<Box<dyn Stream<Item = Result<VerifiedBlock, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<T: Database + Send + Sync + 'static> LightClient for LightClientService<T> {
+	async fn get_confidence(
+		&self,
+		request: Request<ConfidenceRequest>,
+	) -> Result<Response<ConfidenceResponse>, Status> {
+		let block_number = request.into_inner().block_number;
+
+		let count: u32 = self
+			.db
+			.get(Key::VerifiedCellCount(block_number))
+			.map_err(internal)?
+			.ok_or_else(|| Status::not_found("Confidence is not available"))?;
+
+		Ok(Response::new(ConfidenceResponse {
+			block_number,
+			confidence: Confidence::from_cell_count(count, self.withheld_fraction).value(),
+		}))
+	}
+
+	type StreamVerifiedBlocksStream = VerifiedBlocksStream;
+
+	async fn stream_verified_blocks(
+		&self,
+		_: Request<StreamVerifiedBlocksRequest>,
+	) -> Result<Response<Self::StreamVerifiedBlocksStream>, Status> {
+		let stream = BroadcastStream::new(self.block_sender.subscribe()).filter_map(|block| {
+			futures::future::ready(match block {
+				Ok(block) => Some(Ok(VerifiedBlock::from(&block))),
+				Err(error) => {
+					warn!("Verified blocks stream lagged: {error}");
+					None
+				},
+			})
+		});
+
+		Ok(Response::new(Box::pin(stream)))
+	}
+
+	async fn get_app_data(
+		&self,
+		request: Request<AppDataRequest>,
+	) -> Result<Response<AppDataResponse>, Status> {
+		let AppDataRequest {
+			block_number,
+			app_id,
+		} = request.into_inner();
+
+		let Some(app_id) = app_id.or(self.app_id) else {
+			return Err(Status::invalid_argument("App ID is not provided"));
+		};
+
+		let extrinsics: Vec<Vec<u8>> = self
+			.db
+			.get(Key::AppData(app_id, block_number))
+			.map_err(internal)?
+			.ok_or_else(|| Status::not_found("App data is not available"))?;
+
+		Ok(Response::new(AppDataResponse {
+			block_number,
+			app_id,
+			extrinsics,
+		}))
+	}
+}
+
+/// Runs gRPC server until shutdown is triggered
+pub async fn run(
+	cfg: GrpcConfig,
+	db: impl Database + Send + Sync + 'static,
+	app_id: Option<u32>,
+	withheld_fraction: f64,
+	block_sender: broadcast::Sender<BlockVerified>,
+	shutdown: Controller<String>,
+) -> Result<()> {
+	let GrpcConfig {
+		grpc_server_host: host,
+		grpc_server_port: port,
+		..
+	} = cfg;
+
+	let addr = SocketAddr::from_str(&format!("{host}:{port}"))
+		.wrap_err("Unable to parse gRPC host address from config")?;
+
+	let service = LightClientService {
+		db,
+		app_id,
+		withheld_fraction,
+		block_sender,
+	};
+
+	info!("gRPC running on http://{host}:{port}");
+	let shutdown_signal = shutdown.triggered_shutdown().map(|_| ());
+	Server::builder()
+		.add_service(LightClientServer::new(service))
+		.serve_with_shutdown(addr, shutdown_signal)
+		.await
+		.wrap_err("gRPC server failed")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{proto::light_client_server::LightClient, proto::*, LightClientService};
+	use crate::{
+		data::{mem_db, Database, Key},
+		utils::DEFAULT_WITHHELD_FRACTION,
+	};
+	use tokio::sync::broadcast;
+	use tonic::{Code, Request};
+
+	fn service(app_id: Option<u32>) -> LightClientService<mem_db::MemoryDB> {
+		let (block_sender, _) = broadcast::channel(1);
+		let db = mem_db::MemoryDB::default();
+		db.put(Key::VerifiedCellCount(1), 1u32).unwrap();
+		db.put(Key::AppData(1, 1), vec![vec![1u8, 2]]).unwrap();
+		LightClientService {
+			db,
+			app_id,
+			withheld_fraction: DEFAULT_WITHHELD_FRACTION,
+			block_sender,
+		}
+	}
+
+	#[tokio::test]
+	async fn get_confidence() {
+		let service = service(None);
+		let response = service
+			.get_confidence(Request::new(ConfidenceRequest { block_number: 1 }))
+			.await
+			.unwrap()
+			.into_inner();
+		assert_eq!(response.confidence, 50.0);
+
+		let status = service
+			.get_confidence(Request::new(ConfidenceRequest { block_number: 2 }))
+			.await
+			.unwrap_err();
+		assert_eq!(status.code(), Code::NotFound);
+	}
+
+	#[tokio::test]
+	async fn get_app_data() {
+		let service = service(Some(1));
+		let response = service
+			.get_app_data(Request::new(AppDataRequest {
+				block_number: 1,
+				app_id: None,
+			}))
+			.await
+			.unwrap()
+			.into_inner();
+		assert_eq!(response.extrinsics, vec![vec![1u8, 2]]);
+
+		let status = service(None)
+			.get_app_data(Request::new(AppDataRequest {
+				block_number: 1,
+				app_id: None,
+			}))
+			.await
+			.unwrap_err();
+		assert_eq!(status.code(), Code::InvalidArgument);
+	}
+}
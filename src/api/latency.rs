@@ -0,0 +1,175 @@
+//! Latency of the HTTP API routes.
+//!
+//! Latencies of the handled requests are collected per route, where path parameters are replaced
+//! with placeholders, and p50, p95 and p99 percentiles are periodically recorded as metrics.
+//! Requests slower than the configured threshold are logged with their path and query parameters.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use tracing::warn;
+use warp::{
+	http::Method,
+	path::FullPath,
+	reply::{Reply, Response},
+	Filter, Rejection,
+};
+
+use crate::{
+	telemetry::{MetricValue, Metrics},
+	utils::percentile,
+};
+
+/// Maximum number of latencies kept per route between the recordings
+const MAX_ROUTE_SAMPLES: usize = 10_000;
+
+/// Minimum length of the path segment which is considered an identifier
+const MIN_ID_SEGMENT_LENGTH: usize = 16;
+
+/// Replaces numeric and identifier path segments with placeholders,
+/// so latencies of the same route are grouped together
+fn route(path: &str) -> String {
+	path.split('/')
+		.map(|segment| {
+			if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) {
+				":number"
+			} else if segment.len() >= MIN_ID_SEGMENT_LENGTH {
+				":id"
+			} else {
+				segment
+			}
+		})
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// Latencies (in milliseconds) of the handled requests, per route
+#[derive(Clone, Default)]
+pub struct RouteLatencies(Arc<Mutex<HashMap<String, Vec<f64>>>>);
+
+impl RouteLatencies {
+	fn observe(&self, path: &str, latency: Duration) {
+		let mut routes = self.0.lock().expect("Lock should be acquired");
+		let latencies = routes.entry(route(path)).or_default();
+		if latencies.len() < MAX_ROUTE_SAMPLES {
+			latencies.push(latency.as_secs_f64() * 1000.0);
+		}
+	}
+
+	/// Returns p50, p95 and p99 latencies per route since the last call
+	pub fn take_percentiles(&self) -> Vec<(String, [f64; 3])> {
+		let routes = std::mem::take(&mut *self.0.lock().expect("Lock should be acquired"));
+		routes
+			.into_iter()
+			.filter(|(_, latencies)| !latencies.is_empty())
+			.map(|(route, mut latencies)| {
+				latencies.sort_by(f64::total_cmp);
+				let percentiles = [50.0, 95.0, 99.0].map(|p| percentile(&latencies, p));
+				(route, percentiles)
+			})
+			.collect()
+	}
+}
+
+/// Wraps the routes, observing latencies of the handled requests and logging the slow ones
+pub fn observe<F, R>(
+	routes: F,
+	latencies: RouteLatencies,
+	slow_request_threshold: Option<Duration>,
+) -> impl Filter<Extract = (Response,), Error = Rejection> + Clone
+where
+	F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+	R: Reply,
+{
+	let query = warp::query::raw().or(warp::any().map(String::new)).unify();
+	warp::any()
+		.map(Instant::now)
+		.and(warp::method())
+		.and(warp::path::full())
+		.and(query)
+		.and(routes)
+		.map(
+			move |started: Instant, method: Method, path: FullPath, query: String, reply: R| {
+				let response = reply.into_response();
+				let latency = started.elapsed();
+				latencies.observe(path.as_str(), latency);
+				if slow_request_threshold.is_some_and(|threshold| latency >= threshold) {
+					warn!(
+						method = %method,
+						path = path.as_str(),
+						query = query.as_str(),
+						status = response.status().as_u16(),
+						latency_ms = latency.as_secs_f64() * 1000.0,
+						"Slow HTTP request"
+					);
+				}
+				response
+			},
+		)
+}
+
+/// Periodically records latency percentiles of the routes
+pub async fn record_latencies(
+	latencies: RouteLatencies,
+	metrics: Arc<impl Metrics>,
+	interval: Duration,
+) {
+	let mut interval = tokio::time::interval(interval);
+	loop {
+		interval.tick().await;
+		for (route, [p50, p95, p99]) in latencies.take_percentiles() {
+			metrics
+				.record(MetricValue::ApiRouteLatencyP50(route.clone(), p50))
+				.await;
+			metrics
+				.record(MetricValue::ApiRouteLatencyP95(route.clone(), p95))
+				.await;
+			metrics
+				.record(MetricValue::ApiRouteLatencyP99(route, p99))
+				.await;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{route, RouteLatencies};
+	use std::time::Duration;
+
+	#[test]
+	fn route_placeholders() {
+		assert_eq!(route("/v2/blocks/42/data"), "/v2/blocks/:number/data");
+		assert_eq!(route("/v1/confidence/7"), "/v1/confidence/:number");
+		assert_eq!(
+			route("/v2/ws/0f6c1f0e-4f1f-4a6b-9a3e-8c7f2a9c1d2e"),
+			"/v2/ws/:id"
+		);
+		assert_eq!(route("/v2/status"), "/v2/status");
+	}
+
+	#[test]
+	fn route_latency_percentiles() {
+		let latencies = RouteLatencies::default();
+		for millis in 1..=100 {
+			latencies.observe(
+				&format!("/v1/confidence/{millis}"),
+				Duration::from_millis(millis),
+			);
+		}
+		latencies.observe("/v2/status", Duration::from_millis(5));
+
+		let mut percentiles = latencies.take_percentiles();
+		percentiles.sort_by(|a, b| a.0.cmp(&b.0));
+		assert_eq!(percentiles.len(), 2);
+		assert_eq!(percentiles[0].0, "/v1/confidence/:number");
+		let [p50, p95, p99] = percentiles[0].1;
+		assert!((p50 - 50.0).abs() < 0.01);
+		assert!((p95 - 95.0).abs() < 0.01);
+		assert!((p99 - 99.0).abs() < 0.01);
+		assert_eq!(percentiles[1].1.map(f64::round), [5.0; 3]);
+
+		assert!(latencies.take_percentiles().is_empty());
+	}
+}
@@ -1,3 +1,29 @@
+pub mod access_log;
+pub mod cache;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod latency;
 pub mod server;
 mod v1;
 pub mod v2;
+
+use crate::features::{Feature, Features};
+use warp::{Filter, Rejection};
+
+/// Rejects the request with `404 Not Found` unless the feature is enabled
+fn with_feature(
+	features: &Features,
+	feature: Feature,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+	let enabled = features.is_enabled(feature);
+	warp::any()
+		.and_then(move || async move {
+			match enabled {
+				true => Ok(()),
+				false => Err(warp::reject::not_found()),
+			}
+		})
+		.untuple_one()
+}
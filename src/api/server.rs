@@ -7,27 +7,43 @@
 //! * `/v1/latest_block` - returns latest processed block
 //! * `/v1/confidence/{block_number}` - returns calculated confidence for a given block number
 //! * `/v1/appdata/{block_number}` - returns decoded extrinsic data for configured app_id and given block number
+//! * `/v1/blocks?verified_after={timestamp}` - returns blocks verified after a given time
+//! * `/v1/blocks?since_cursor={cursor}` - returns blocks verified after a given cursor, with the new cursor
+//! * `/v1/blocks/{block_number}/apps` - returns apps which posted data in a given block
+//! * `/v1/dataroot/{block_number}` - returns data root of a given block with verification status
+//! * `/v1/apps/{app_id}/usage` - returns reconstructed and stored bytes of a given app
+//! * `/v1/verify` - schedules verification of a list or range of blocks, returns job ID
+//! * `/v1/jobs/{job_id}` - returns progress of a given verification job
+//! * `/graphql` - GraphQL endpoint, available with `graphql` feature
 
-use crate::api::v2;
+use crate::api::{access_log, cache::ReadCache, latency::RouteLatencies, v2};
+use crate::bulk_verification::BulkVerification;
 use crate::data::Database;
+use crate::decoder::Decoders;
 use crate::network::p2p;
 use crate::shutdown::Controller;
-use crate::types::IdentityConfig;
+use crate::types::{BlockVerified, IdentityConfig};
 use crate::{
 	api::v1,
 	network::rpc::{self},
 	types::{RuntimeConfig, State},
 };
-use color_eyre::eyre::WrapErr;
-use futures::{Future, FutureExt};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use futures::{future::join_all, Future, FutureExt};
 use std::{
 	net::SocketAddr,
 	str::FromStr,
 	sync::{Arc, Mutex},
+	time::Duration,
 };
+use tokio::sync::broadcast;
 use tracing::info;
 use warp::{Filter, Reply};
 
+#[derive(Clone)]
 pub struct Server<T: Database> {
 	pub db: T,
 	pub cfg: RuntimeConfig,
@@ -39,6 +55,11 @@ pub struct Server<T: Database> {
 	pub ws_clients: v2::types::WsClients,
 	pub shutdown: Controller<String>,
 	pub p2p_client: p2p::Client,
+	pub block_sender: broadcast::Sender<BlockVerified>,
+	pub decoders: Decoders,
+	pub cache: ReadCache,
+	pub latencies: RouteLatencies,
+	pub bulk_verification: BulkVerification<T>,
 }
 
 fn health_route() -> impl Filter<Extract = impl Reply, Error = warp::Rejection> + Clone {
@@ -48,22 +69,52 @@ fn health_route() -> impl Filter<Extract = impl Reply, Error = warp::Rejection>
 		.map(|_| warp::reply::with_status("", warp::http::StatusCode::OK))
 }
 
+/// Parses the primary server address and the additional addresses
+fn socket_addresses(host: &str, port: u16, additional: &[String]) -> Result<Vec<SocketAddr>> {
+	let primary = SocketAddr::from_str(&format!("{host}:{port}"))
+		.wrap_err_with(|| format!("Invalid HTTP server address {host}:{port}"))?;
+	let mut addresses = vec![primary];
+	for address in additional {
+		let address = SocketAddr::from_str(address)
+			.wrap_err_with(|| format!("Invalid HTTP server address {address}"))?;
+		addresses.push(address);
+	}
+	Ok(addresses)
+}
+
 impl<T: Database + Clone + Send + Sync + 'static> Server<T> {
-	/// Creates a HTTP server that needs to be spawned into a runtime
-	pub fn bind(self) -> impl Future<Output = ()> {
+	/// Binds HTTP server to all configured addresses.
+	/// Returns future serving all of them, which needs to be spawned into a runtime.
+	pub fn bind(self) -> Result<impl Future<Output = ()>> {
 		let RuntimeConfig {
 			http_server_host: host,
 			http_server_port: port,
+			http_server_addresses,
+			http_slow_request_threshold,
 			app_id,
 			..
 		} = self.cfg.clone();
+		let addresses = socket_addresses(&host, port, &http_server_addresses)?;
+		let access_log = access_log::log((&self.cfg).into());
 
 		let v1_api = v1::routes(
+			self.version.clone(),
+			self.network_version.clone(),
 			self.db.clone(),
 			app_id,
 			self.state.clone(),
 			self.cfg.clone(),
+			self.decoders.clone(),
+			self.cache.clone(),
+			self.bulk_verification.clone(),
 		);
+		#[cfg(feature = "graphql")]
+		let graphql_api = crate::api::graphql::routes(crate::api::graphql::QueryContext {
+			db: self.db.clone(),
+			cfg: self.cfg.clone(),
+			state: self.state.clone(),
+			p2p_client: self.p2p_client.clone(),
+		});
 		let v2_api = v2::routes(
 			self.version.clone(),
 			self.network_version.clone(),
@@ -74,6 +125,8 @@ impl<T: Database + Clone + Send + Sync + 'static> Server<T> {
 			self.ws_clients.clone(),
 			self.db.clone(),
 			self.p2p_client.clone(),
+			self.block_sender.clone(),
+			self.cache.clone(),
 		);
 
 		let cors = warp::cors()
@@ -81,16 +134,57 @@ impl<T: Database + Clone + Send + Sync + 'static> Server<T> {
 			.allow_header("content-type")
 			.allow_methods(vec!["GET", "POST", "DELETE"]);
 
-		let routes = health_route().or(v1_api).or(v2_api).with(cors);
+		let routes = health_route().or(v1_api).or(v2_api);
+		#[cfg(feature = "graphql")]
+		let routes = routes.or(graphql_api);
+		let routes = crate::api::latency::observe(
+			routes,
+			self.latencies.clone(),
+			http_slow_request_threshold.map(Duration::from_millis),
+		);
+		let routes = routes.with(cors).with(access_log);
 
-		let addr = SocketAddr::from_str(format!("{host}:{port}").as_str())
-			.wrap_err("Unable to parse host address from config")
-			.unwrap();
-		info!("RPC running on http://{host}:{port}");
-		// warp graceful shutdown expects a signal that is [`Future<Output = ()>`]
-		let shutdown_signal = self.shutdown.triggered_shutdown().map(|_| ());
-		let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(addr, shutdown_signal);
+		let mut servers = vec![];
+		for addr in addresses {
+			// warp graceful shutdown expects a signal that is [`Future<Output = ()>`]
+			let shutdown_signal = self.shutdown.triggered_shutdown().map(|_| ());
+			let (addr, server) = warp::serve(routes.clone())
+				.try_bind_with_graceful_shutdown(addr, shutdown_signal)
+				.map_err(|error| {
+					eyre!(
+						"Cannot bind HTTP server to {addr}: {error}. \
+						Check that the address belongs to this host, and that the port is not used by another process \
+						(or a previous instance of the light client)"
+					)
+				})?;
+			info!("RPC running on http://{addr}");
+			servers.push(server);
+		}
+
+		Ok(join_all(servers).map(|_| ()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::socket_addresses;
+
+	#[test]
+	fn server_addresses() {
+		let additional = vec!["0.0.0.0:7008".to_string(), "[::1]:7009".to_string()];
+		let addresses = socket_addresses("127.0.0.1", 7007, &additional).unwrap();
+		let addresses = addresses
+			.iter()
+			.map(ToString::to_string)
+			.collect::<Vec<_>>();
+		assert_eq!(
+			addresses,
+			vec!["127.0.0.1:7007", "0.0.0.0:7008", "[::1]:7009"]
+		);
 
-		server
+		let error = socket_addresses("localhost", 7007, &[]).unwrap_err();
+		assert!(error.to_string().contains("localhost:7007"));
+		let invalid = vec!["0.0.0.0".to_string()];
+		assert!(socket_addresses("127.0.0.1", 7007, &invalid).is_err());
 	}
 }
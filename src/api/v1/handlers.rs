@@ -1,84 +1,411 @@
-use super::types::{AppDataQuery, ClientResponse, ConfidenceResponse, LatestBlockResponse, Status};
+use super::types::{
+	AppDataQuery, BlockApp, BlockAppsResponse, ClientResponse, ConfidenceQuery, ConfidenceResponse,
+	Cursor, LatestBlockResponse, Status, StorageResponse, VerifiedBlock, VerifiedBlocksQuery,
+	VerifiedBlocksResponse, VerifyRequest, VerifyResponse, VersionResponse, WaitQuery,
+	WaitResponse,
+};
 use crate::{
-	api::v1::types::{Extrinsics, ExtrinsicsDataResponse},
-	data::{Database, Key},
-	network::rpc::cell_count_for_confidence,
+	api::{
+		cache::ReadCache,
+		v1::types::{Extrinsics, ExtrinsicsDataResponse},
+	},
+	block_state::{self, BlockState},
+	bulk_verification::{BulkVerification, JobStatus, MAX_PENDING_JOBS},
+	confidence::Confidence,
+	data::{AppDataUsage, Database, Key},
+	data_root::{self, DataRootVerification},
+	decoder::Decoders,
+	features::Features,
+	report,
 	types::{Mode, OptionBlockRange, RuntimeConfig, State},
-	utils::calculate_confidence,
+	utils::{app_data_hash, extract_app_lookup, extrinsic_hash, unix_timestamp_nanos},
 };
 use avail_subxt::{
 	api::runtime_types::{da_control::pallet::Call, da_runtime::RuntimeCall},
-	primitives::AppUncheckedExtrinsic,
+	primitives::{AppUncheckedExtrinsic, Header},
 };
 use base64::{engine::general_purpose, Engine};
 use codec::Decode;
-use color_eyre::{eyre::WrapErr, Result};
-use num::{BigUint, FromPrimitive};
-use std::sync::{Arc, Mutex};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use serde::Serialize;
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{
+	sync::Semaphore,
+	time::{self, Instant},
+};
 use tracing::{debug, info};
 
-fn serialised_confidence(block: u32, factor: f64) -> Option<String> {
-	let block_big: BigUint = FromPrimitive::from_u64(block as u64)?;
-	let factor_big: BigUint = FromPrimitive::from_u64((10f64.powi(7) * factor) as u64)?;
-	let shifted: BigUint = block_big << 32 | factor_big;
-	Some(shifted.to_str_radix(10))
-}
+/// Maximum number of the verified blocks listed in a single response
+const MAX_LISTED_BLOCKS: usize = 10_000;
+
+/// Blocks verified within this time are not listed by cursor
+const CURSOR_SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Waiting time for the block confidence, if not given in the request
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum waiting time for the block confidence
+const MAX_WAIT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Interval in which the confidence of the awaited block is checked
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Maximum number of the requests waiting for the block confidence at the same time
+pub const MAX_CONCURRENT_WAITS: usize = 1000;
 
 pub fn mode(app_id: Option<u32>) -> ClientResponse<Mode> {
 	ClientResponse::Normal(Mode::from(app_id))
 }
 
+pub fn version(
+	version: String,
+	network_version: String,
+	cfg: RuntimeConfig,
+) -> ClientResponse<VersionResponse> {
+	ClientResponse::Normal(VersionResponse {
+		version,
+		network_version,
+		features: Features::from(&cfg).list(),
+	})
+}
+
+fn is_synced(block_num: u32, state: &Arc<Mutex<State>>) -> bool {
+	let state = state.lock().unwrap();
+	match &state.confidence_achieved {
+		Some(range) => block_num <= range.last,
+		None => false,
+	}
+}
+
+/// Checks if the block without application data is not sampled
+fn is_empty(db: &impl Database, block_num: u32) -> bool {
+	matches!(block_state::get(db, block_num), Ok(Some(BlockState::Empty)))
+}
+
+fn empty_block<T: Serialize>(block_num: u32) -> ClientResponse<T> {
+	ClientResponse::BadRequest(format!(
+		"Block {block_num} has no application data, it is not sampled"
+	))
+}
+
 pub fn confidence(
 	block_num: u32,
+	query: ConfidenceQuery,
 	db: impl Database,
+	cache: ReadCache,
 	state: Arc<Mutex<State>>,
 	cfg: RuntimeConfig,
 ) -> ClientResponse<ConfidenceResponse> {
-	fn is_synced(block_num: u32, state: Arc<Mutex<State>>) -> bool {
-		let state = state.lock().unwrap();
-		match &state.confidence_achieved {
-			Some(range) => block_num <= range.last,
-			None => false,
-		}
-	}
-
 	info!("Got request for confidence for block {block_num}");
 
-	let count = match db.get(Key::VerifiedCellCount(block_num)) {
+	let count = match cache.verified_cell_count(&db, block_num) {
 		Ok(Some(count)) => count,
-		Ok(None) if is_synced(block_num, state) => cell_count_for_confidence(cfg.confidence),
+		Ok(None) if is_empty(&db, block_num) => return empty_block(block_num),
+		Ok(None) if is_synced(block_num, &state) => {
+			cfg.confidence.cell_count(cfg.withheld_fraction)
+		},
 		Ok(None) => return ClientResponse::NotFinalized,
 		Err(error) => return ClientResponse::Error(error),
 	};
 
-	let confidence = calculate_confidence(count);
-	let serialised_confidence = serialised_confidence(block_num, confidence);
+	let verified_at = match db.get::<u64>(Key::VerifiedAt(block_num)) {
+		Ok(verified_at) => verified_at,
+		Err(error) => return ClientResponse::Error(error),
+	};
+
+	if let Some(as_of) = query.as_of {
+		match verified_at {
+			Some(verified_at) if verified_at <= as_of => (),
+			Some(_) => return ClientResponse::NotFound,
+			None => {
+				return ClientResponse::BadRequest(format!(
+					"Verification time of block {block_num} is not known"
+				))
+			},
+		}
+	}
+
+	let confidence = Confidence::from_cell_count(count, cfg.withheld_fraction);
+	let serialised_confidence = confidence.serialised(block_num);
+
+	let p2p_confidence = match report::block_p2p_confidence(&db, block_num, cfg.withheld_fraction) {
+		Ok(p2p_confidence) => p2p_confidence,
+		Err(error) => return ClientResponse::Error(error),
+	};
+
+	let sampled_cells = match query.verbose {
+		Some(true) => match db.get(Key::SampledCells(block_num)) {
+			Ok(sampled_cells) => Some(sampled_cells.unwrap_or_default()),
+			Err(error) => return ClientResponse::Error(error),
+		},
+		_ => None,
+	};
 
 	let response = ClientResponse::Normal(ConfidenceResponse {
 		block: block_num,
 		confidence,
 		serialised_confidence,
+		p2p_confidence,
+		sampled_cells,
+		verified_at,
 	});
 	info!("Returning confidence: {response:?}");
 	response
 }
 
+/// Parses waiting time with the `ms`, `s` or `m` unit, or in seconds if the unit is omitted
+fn parse_timeout(timeout: &str) -> Option<Duration> {
+	let (value, unit) = match timeout.find(|c: char| !c.is_ascii_digit()) {
+		Some(index) => timeout.split_at(index),
+		None => (timeout, "s"),
+	};
+	let value = value.parse::<u64>().ok()?;
+	match unit {
+		"ms" => Some(Duration::from_millis(value)),
+		"s" => Some(Duration::from_secs(value)),
+		"m" => Some(Duration::from_secs(value.checked_mul(60)?)),
+		_ => None,
+	}
+}
+
+/// Waits until the block confidence reaches the threshold, or the timeout expires
+pub async fn wait(
+	block_num: u32,
+	query: WaitQuery,
+	db: impl Database,
+	state: Arc<Mutex<State>>,
+	cfg: RuntimeConfig,
+	waits: Arc<Semaphore>,
+) -> ClientResponse<WaitResponse> {
+	let threshold = query
+		.confidence
+		.map(Confidence::new)
+		.unwrap_or(cfg.confidence);
+	if !(0.0..=100.0).contains(&threshold.value()) {
+		return ClientResponse::BadRequest(format!(
+			"Confidence {threshold} is not between 0 and 100"
+		));
+	}
+
+	let timeout = match query.timeout.as_deref().map(parse_timeout) {
+		Some(Some(timeout)) if timeout <= MAX_WAIT_TIMEOUT => timeout,
+		Some(Some(_)) => {
+			return ClientResponse::BadRequest(format!(
+				"Timeout cannot exceed {}s",
+				MAX_WAIT_TIMEOUT.as_secs()
+			))
+		},
+		Some(None) => return ClientResponse::BadRequest("Invalid timeout".to_string()),
+		None => DEFAULT_WAIT_TIMEOUT,
+	};
+
+	let Ok(_permit) = waits.try_acquire_owned() else {
+		return ClientResponse::TooManyRequests(format!(
+			"Cannot wait for more than {MAX_CONCURRENT_WAITS} blocks at the same time"
+		));
+	};
+
+	debug!("Waiting for confidence {threshold} of block {block_num}");
+	let deadline = Instant::now() + timeout;
+	loop {
+		let count = match db.get::<u32>(Key::VerifiedCellCount(block_num)) {
+			Ok(Some(count)) => Some(count),
+			Ok(None) if is_empty(&db, block_num) => return empty_block(block_num),
+			Ok(None) if is_synced(block_num, &state) => {
+				Some(cfg.confidence.cell_count(cfg.withheld_fraction))
+			},
+			Ok(None) => None,
+			Err(error) => return ClientResponse::Error(error),
+		};
+
+		let confidence =
+			count.map(|count| Confidence::from_cell_count(count, cfg.withheld_fraction));
+		let reached = confidence.is_some_and(|confidence| confidence.reaches(threshold));
+		let response = WaitResponse {
+			block: block_num,
+			confidence,
+			reached,
+		};
+		if reached {
+			return ClientResponse::Normal(response);
+		}
+
+		let now = Instant::now();
+		if now >= deadline {
+			return ClientResponse::Normal(response);
+		}
+		time::sleep(WAIT_POLL_INTERVAL.min(deadline - now)).await;
+	}
+}
+
+/// Hash of the API key, under which consumer cursor is persisted
+fn api_key_hash(api_key: &str) -> String {
+	hex::encode(sp_core::blake2_256(api_key.as_bytes()))
+}
+
+/// Resolves the cursor after which verified blocks are listed.
+/// Cursor given in the query is persisted as acknowledged by the consumer with the API key,
+/// and persisted cursor is used if neither cursor nor time is given in the query.
+fn start_cursor(
+	query: &VerifiedBlocksQuery,
+	api_key: Option<&str>,
+	db: &impl Database,
+) -> std::result::Result<Cursor, ClientResponse<VerifiedBlocksResponse>> {
+	match (query.verified_after, &query.since_cursor, api_key) {
+		(Some(_), Some(_), _) => Err(ClientResponse::BadRequest(
+			"Either verified_after or since_cursor can be set".to_string(),
+		)),
+		(Some(verified_after), None, _) => Ok(Cursor {
+			verified_at: verified_after,
+			block: u32::MAX,
+		}),
+		(None, Some(since_cursor), api_key) => {
+			let cursor = Cursor::decode(since_cursor)
+				.map_err(|error| ClientResponse::BadRequest(error.to_string()))?;
+			if let Some(api_key) = api_key {
+				db.put(
+					Key::ConsumerCursor(api_key_hash(api_key)),
+					since_cursor.clone(),
+				)
+				.wrap_err("Failed to store consumer cursor")
+				.map_err(ClientResponse::Error)?;
+			}
+			Ok(cursor)
+		},
+		(None, None, Some(api_key)) => db
+			.get::<String>(Key::ConsumerCursor(api_key_hash(api_key)))
+			.and_then(|cursor| cursor.as_deref().map(Cursor::decode).transpose())
+			.map(Option::unwrap_or_default)
+			.map_err(ClientResponse::Error),
+		(None, None, None) => Ok(Cursor::default()),
+	}
+}
+
+/// Lists the verified blocks after the cursor or given time, ordered by verification time.
+/// Blocks are iterated from the cursor using the verification time index, so all verified blocks are listed.
+/// When listing by cursor, the most recently verified blocks are held back, so that blocks which
+/// verification time is stored concurrently with the request are not skipped.
+fn list_verified_blocks(
+	query: VerifiedBlocksQuery,
+	api_key: Option<String>,
+	consumer_api_keys: &[String],
+	withheld_fraction: f64,
+	db: impl Database,
+) -> ClientResponse<VerifiedBlocksResponse> {
+	if let Some(api_key) = &api_key {
+		if !consumer_api_keys.contains(api_key) {
+			return ClientResponse::Unauthorized("Unknown API key".to_string());
+		}
+	}
+	let cursor = match start_cursor(&query, api_key.as_deref(), &db) {
+		Ok(cursor) => cursor,
+		Err(response) => return response,
+	};
+	info!("Got request for blocks verified after {cursor:?}");
+	let verified_before = match query.verified_after {
+		Some(_) => u64::MAX,
+		None => unix_timestamp_nanos().saturating_sub(CURSOR_SETTLE_TIME.as_nanos() as u64),
+	};
+	let limit = query
+		.limit
+		.unwrap_or(MAX_LISTED_BLOCKS)
+		.min(MAX_LISTED_BLOCKS);
+
+	let mut blocks = vec![];
+	let mut position = cursor;
+	'listing: while blocks.len() < limit {
+		let entries = match db.verified_after(position.verified_at, position.block, limit) {
+			Ok(entries) if entries.is_empty() => break,
+			Ok(entries) => entries,
+			Err(error) => return ClientResponse::Error(error),
+		};
+		for (verified_at, block_num) in entries {
+			if verified_at > verified_before {
+				break 'listing;
+			}
+			position = Cursor {
+				verified_at,
+				block: block_num,
+			};
+			// Index entry is stale if the block was verified again later
+			match db.get::<u64>(Key::VerifiedAt(block_num)) {
+				Ok(Some(stored)) if stored == verified_at => (),
+				Ok(_) => continue,
+				Err(error) => return ClientResponse::Error(error),
+			}
+			let count = match db.get::<u32>(Key::VerifiedCellCount(block_num)) {
+				Ok(count) => count,
+				Err(error) => return ClientResponse::Error(error),
+			};
+			blocks.push(VerifiedBlock {
+				block: block_num,
+				confidence: count
+					.map(|count| Confidence::from_cell_count(count, withheld_fraction)),
+				verified_at,
+			});
+			if blocks.len() == limit {
+				break 'listing;
+			}
+		}
+	}
+
+	let cursor = blocks.last().map_or(cursor, |block| Cursor {
+		verified_at: block.verified_at,
+		block: block.block,
+	});
+	debug!("Returning {} verified blocks", blocks.len());
+	ClientResponse::Normal(VerifiedBlocksResponse {
+		blocks,
+		cursor: cursor.encode(),
+	})
+}
+
+/// Lists the verified blocks on the blocking thread, since database is iterated
+pub async fn verified_blocks(
+	query: VerifiedBlocksQuery,
+	api_key: Option<String>,
+	db: impl Database + Send + 'static,
+	cfg: RuntimeConfig,
+) -> ClientResponse<VerifiedBlocksResponse> {
+	tokio::task::spawn_blocking(move || {
+		list_verified_blocks(
+			query,
+			api_key,
+			&cfg.consumer_api_keys,
+			cfg.withheld_fraction,
+			db,
+		)
+	})
+	.await
+	.unwrap_or_else(|error| ClientResponse::Error(eyre!("Listing verified blocks failed: {error}")))
+}
+
 pub fn status(
 	app_id: Option<u32>,
 	state: Arc<Mutex<State>>,
 	db: impl Database,
+	cache: ReadCache,
+	cfg: RuntimeConfig,
 ) -> ClientResponse<Status> {
 	let state = state.lock().unwrap();
 	let Some(last) = state.confidence_achieved.last() else {
 		return ClientResponse::NotFound;
 	};
-	let res = match db.get(Key::VerifiedCellCount(last)) {
+	let res = match cache.verified_cell_count(&db, last) {
 		Ok(Some(count)) => {
-			let confidence = calculate_confidence(count);
+			let confidence = Confidence::from_cell_count(count, cfg.withheld_fraction);
 			ClientResponse::Normal(Status {
 				block_num: last,
 				confidence,
 				app_id,
+				reachability: state.reachability,
+				public_address: state.public_address.clone(),
 			})
 		},
 		Ok(None) => ClientResponse::NotFound,
@@ -89,6 +416,18 @@ pub fn status(
 	res
 }
 
+pub fn storage(state: Arc<Mutex<State>>, db: impl Database) -> ClientResponse<StorageResponse> {
+	info!("Got request for storage statistics");
+	let last_pruned_at = state.lock().unwrap().last_pruned_at;
+	match db.stats() {
+		Ok(stats) => ClientResponse::Normal(StorageResponse {
+			stats,
+			last_pruned_at,
+		}),
+		Err(error) => ClientResponse::Error(error),
+	}
+}
+
 pub fn latest_block(state: Arc<Mutex<State>>) -> ClientResponse<LatestBlockResponse> {
 	info!("Got request for latest block");
 	let state = state.lock().unwrap();
@@ -98,12 +437,76 @@ pub fn latest_block(state: Arc<Mutex<State>>) -> ClientResponse<LatestBlockRespo
 	}
 }
 
+pub fn block_apps(
+	block_num: u32,
+	db: impl Database,
+	cache: ReadCache,
+) -> ClientResponse<BlockAppsResponse> {
+	info!("Got request for apps in block {block_num}");
+	let header: Header = match db.get(Key::BlockHeader(block_num)) {
+		Ok(Some(header)) => header,
+		Ok(None) => return ClientResponse::NotFound,
+		Err(error) => return ClientResponse::Error(error),
+	};
+
+	let app_ids = match extract_app_lookup(&header.extension) {
+		Ok(lookup) => lookup.map(|lookup| lookup.apps_in_block()),
+		Err(error) => return ClientResponse::Error(error),
+	};
+
+	let mut apps = vec![];
+	for app_id in app_ids.unwrap_or_default() {
+		let reconstructed = match cache.app_data(&db, app_id, block_num) {
+			Ok(data) => data.is_some(),
+			Err(error) => return ClientResponse::Error(error),
+		};
+		apps.push(BlockApp {
+			app_id,
+			reconstructed,
+		});
+	}
+
+	let response = ClientResponse::Normal(BlockAppsResponse {
+		block: block_num,
+		apps,
+	});
+	debug!("Returning apps in block: {response:?}");
+	response
+}
+
+pub fn data_root(block_num: u32, db: impl Database) -> ClientResponse<DataRootVerification> {
+	info!("Got request for data root of block {block_num}");
+	let header: Header = match db.get(Key::BlockHeader(block_num)) {
+		Ok(Some(header)) => header,
+		Ok(None) => return ClientResponse::NotFound,
+		Err(error) => return ClientResponse::Error(error),
+	};
+
+	let response = match data_root::verify(&db, &header) {
+		Ok(verification) => ClientResponse::Normal(verification),
+		Err(error) => ClientResponse::Error(error),
+	};
+	debug!("Returning data root: {response:?}");
+	response
+}
+
+pub fn app_usage(app_id: u32, db: impl Database) -> ClientResponse<AppDataUsage> {
+	info!("Got request for data usage of app {app_id}");
+	match db.get(Key::AppDataUsage(app_id)) {
+		Ok(Some(usage)) => ClientResponse::Normal(usage),
+		Ok(None) => ClientResponse::NotFound,
+		Err(error) => ClientResponse::Error(error),
+	}
+}
+
 pub fn appdata(
 	block_num: u32,
 	query: AppDataQuery,
 	db: impl Database,
+	cache: ReadCache,
 	app_id: Option<u32>,
 	state: Arc<Mutex<State>>,
+	decoders: Decoders,
 ) -> ClientResponse<ExtrinsicsDataResponse> {
 	fn decode_app_data_to_extrinsics(
 		data: Result<Option<Vec<Vec<u8>>>>,
@@ -130,28 +533,49 @@ pub fn appdata(
 	let state = state.lock().unwrap();
 	let last = state.confidence_achieved.last();
 	let decode = query.decode.unwrap_or(false);
-	let res = match decode_app_data_to_extrinsics(
-		db.get(Key::AppData(app_id.unwrap_or(0u32), block_num)),
-	) {
+	let app_id = app_id.unwrap_or(0u32);
+	let app_data = cache.app_data(&db, app_id, block_num);
+	let (extrinsic_hashes, data_hash) = match &app_data {
+		Ok(Some(data)) => (
+			data.iter().map(|xt| extrinsic_hash(xt)).collect(),
+			app_data_hash(data),
+		),
+		_ => Default::default(),
+	};
+	let res = match decode_app_data_to_extrinsics(app_data) {
 		Ok(Some(data)) => {
 			if !decode {
 				ClientResponse::Normal(ExtrinsicsDataResponse {
 					block: block_num,
 					extrinsics: Extrinsics::Encoded(data),
+					extrinsic_hashes,
+					data_hash,
 				})
 			} else {
-				let xts = data
-					.iter()
-					.flat_map(|xt| match &xt.function {
-						RuntimeCall::DataAvailability(Call::submit_data { data, .. }) => Some(data),
-						_ => None,
-					})
-					.map(|data| general_purpose::STANDARD.encode(data.0.as_slice()))
-					.collect::<Vec<_>>();
-				ClientResponse::Normal(ExtrinsicsDataResponse {
-					block: block_num,
-					extrinsics: Extrinsics::Decoded(xts),
-				})
+				let payloads = data.iter().flat_map(|xt| match &xt.function {
+					RuntimeCall::DataAvailability(Call::submit_data { data, .. }) => Some(data),
+					_ => None,
+				});
+				let extrinsics = match decoders.get(app_id) {
+					Some(decoder) => payloads
+						.map(|data| decoder.decode(data.0.as_slice()))
+						.collect::<Result<Vec<_>>>()
+						.map(Extrinsics::Plugin),
+					None => Ok(Extrinsics::Decoded(
+						payloads
+							.map(|data| general_purpose::STANDARD.encode(data.0.as_slice()))
+							.collect::<Vec<_>>(),
+					)),
+				};
+				match extrinsics {
+					Ok(extrinsics) => ClientResponse::Normal(ExtrinsicsDataResponse {
+						block: block_num,
+						extrinsics,
+						extrinsic_hashes,
+						data_hash,
+					}),
+					Err(error) => ClientResponse::Error(error),
+				}
 			}
 		},
 
@@ -164,3 +588,155 @@ pub fn appdata(
 	debug!("Returning AppData: {res:?}");
 	res
 }
+
+pub fn verify(
+	request: VerifyRequest,
+	state: Arc<Mutex<State>>,
+	cfg: RuntimeConfig,
+	bulk_verification: BulkVerification<impl Database>,
+) -> ClientResponse<VerifyResponse> {
+	info!("Got request for verification of {} blocks", request.len());
+	if request.is_empty() {
+		return ClientResponse::BadRequest("No blocks to verify".to_string());
+	}
+	let max_blocks = cfg.bulk_verification_max_blocks;
+	if request.len() > max_blocks {
+		return ClientResponse::BadRequest(format!(
+			"At most {max_blocks} blocks can be verified at once"
+		));
+	}
+
+	let latest = state.lock().unwrap().latest;
+	let blocks = request.blocks();
+	if let Some(block_num) = blocks.iter().find(|&&block_num| block_num > latest) {
+		return ClientResponse::BadRequest(format!(
+			"Block {block_num} is not available, latest block is {latest}"
+		));
+	}
+
+	match bulk_verification.submit(blocks) {
+		Ok(Some(job_id)) => {
+			info!("Scheduled verification job {job_id}");
+			ClientResponse::Normal(VerifyResponse { job_id })
+		},
+		Ok(None) => ClientResponse::TooManyRequests(format!(
+			"At most {MAX_PENDING_JOBS} verification jobs can be pending"
+		)),
+		Err(error) => ClientResponse::Error(error),
+	}
+}
+
+pub fn job(
+	job_id: u64,
+	bulk_verification: BulkVerification<impl Database>,
+) -> ClientResponse<JobStatus> {
+	debug!("Got request for verification job {job_id}");
+	match bulk_verification.status(job_id) {
+		Ok(Some(status)) => ClientResponse::Normal(status),
+		Ok(None) => ClientResponse::NotFound,
+		Err(error) => ClientResponse::Error(error),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_timeout, wait};
+	use crate::{
+		api::v1::types::{ClientResponse, WaitQuery},
+		data::{mem_db::MemoryDB, Database, Key},
+		types::{RuntimeConfig, State},
+	};
+	use std::{
+		sync::{Arc, Mutex},
+		time::Duration,
+	};
+	use tokio::sync::Semaphore;
+
+	fn query(confidence: Option<f64>, timeout: &str) -> WaitQuery {
+		WaitQuery {
+			confidence,
+			timeout: Some(timeout.to_string()),
+		}
+	}
+
+	#[test]
+	fn parse_timeouts() {
+		assert_eq!(parse_timeout("500ms"), Some(Duration::from_millis(500)));
+		assert_eq!(parse_timeout("30s"), Some(Duration::from_secs(30)));
+		assert_eq!(parse_timeout("30"), Some(Duration::from_secs(30)));
+		assert_eq!(parse_timeout("2m"), Some(Duration::from_secs(120)));
+		assert_eq!(parse_timeout("999999999999999999m"), None);
+		assert_eq!(parse_timeout("99999999999999999999"), None);
+		assert_eq!(parse_timeout("1h"), None);
+		assert_eq!(parse_timeout("m"), None);
+		assert_eq!(parse_timeout(""), None);
+	}
+
+	#[tokio::test]
+	async fn wait_for_confidence() {
+		let db = MemoryDB::default();
+		db.put(Key::VerifiedCellCount(1), 20u32).unwrap();
+		let state = Arc::new(Mutex::new(State::default()));
+		let cfg = RuntimeConfig::default();
+		let waits = Arc::new(Semaphore::new(1));
+
+		let response = wait(
+			1,
+			query(Some(90.0), "10s"),
+			db.clone(),
+			state.clone(),
+			cfg.clone(),
+			waits.clone(),
+		)
+		.await;
+		assert!(matches!(response, ClientResponse::Normal(response) if response.reached));
+		assert_eq!(waits.available_permits(), 1);
+
+		// Timed out wait is not an error
+		let response = wait(
+			2,
+			query(None, "10ms"),
+			db.clone(),
+			state.clone(),
+			cfg.clone(),
+			waits.clone(),
+		)
+		.await;
+		assert!(matches!(
+			response,
+			ClientResponse::Normal(response) if !response.reached && response.confidence.is_none()
+		));
+
+		let response = wait(
+			1,
+			query(None, "301s"),
+			db.clone(),
+			state.clone(),
+			cfg.clone(),
+			waits,
+		)
+		.await;
+		assert!(matches!(response, ClientResponse::BadRequest(_)));
+		let response = wait(
+			1,
+			query(Some(101.0), "1s"),
+			db.clone(),
+			state.clone(),
+			cfg.clone(),
+			Arc::new(Semaphore::new(1)),
+		)
+		.await;
+		assert!(matches!(response, ClientResponse::BadRequest(_)));
+
+		let response = wait(
+			1,
+			query(Some(90.0), "1s"),
+			db,
+			state,
+			cfg,
+			Arc::new(Semaphore::new(0)),
+		)
+		.await;
+		assert!(matches!(response, ClientResponse::TooManyRequests(_)));
+	}
+}
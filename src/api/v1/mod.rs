@@ -1,13 +1,18 @@
 use crate::{
+	api::{cache::ReadCache, with_feature},
+	bulk_verification::BulkVerification,
 	data::Database,
+	decoder::Decoders,
+	features::{Feature, Features},
 	types::{RuntimeConfig, State},
 };
 
-use self::types::AppDataQuery;
+use self::types::{AppDataQuery, ConfidenceQuery, VerifiedBlocksQuery, WaitQuery};
 use std::{
 	convert::Infallible,
 	sync::{Arc, Mutex},
 };
+use tokio::sync::Semaphore;
 use warp::{Filter, Rejection, Reply};
 
 mod handlers;
@@ -37,38 +42,137 @@ fn with_cfg(
 	warp::any().map(move || cfg.clone())
 }
 
-pub fn routes(
-	db: impl Database + Clone + Send,
+fn with_decoders(
+	decoders: Decoders,
+) -> impl Filter<Extract = (Decoders,), Error = Infallible> + Clone {
+	warp::any().map(move || decoders.clone())
+}
+
+fn with_cache(cache: ReadCache) -> impl Filter<Extract = (ReadCache,), Error = Infallible> + Clone {
+	warp::any().map(move || cache.clone())
+}
+
+fn with_bulk_verification<T: Database + Clone + Send + Sync>(
+	bulk_verification: BulkVerification<T>,
+) -> impl Filter<Extract = (BulkVerification<T>,), Error = Infallible> + Clone {
+	warp::any().map(move || bulk_verification.clone())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn routes<T: Database + Clone + Send + Sync>(
+	version: String,
+	network_version: String,
+	db: T,
 	app_id: Option<u32>,
 	state: Arc<Mutex<State>>,
 	cfg: RuntimeConfig,
+	decoders: Decoders,
+	cache: ReadCache,
+	bulk_verification: BulkVerification<T>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	let mode = warp::path!("v1" / "mode")
 		.and(with_app_id(app_id))
 		.map(handlers::mode);
 
+	let version = warp::path!("v1" / "version")
+		.and(warp::any().map(move || version.clone()))
+		.and(warp::any().map(move || network_version.clone()))
+		.and(with_cfg(cfg.clone()))
+		.map(handlers::version);
+
 	let latest_block = warp::path!("v1" / "latest_block")
 		.and(with_state(state.clone()))
 		.map(handlers::latest_block);
 
 	let confidence = warp::path!("v1" / "confidence" / u32)
+		.and(warp::query::<ConfidenceQuery>())
 		.and(with_db(db.clone()))
+		.and(with_cache(cache.clone()))
 		.and(with_state(state.clone()))
-		.and(with_cfg(cfg))
+		.and(with_cfg(cfg.clone()))
 		.map(handlers::confidence);
 
+	let waits = Arc::new(Semaphore::new(handlers::MAX_CONCURRENT_WAITS));
+	let wait = warp::path!("v1" / "wait" / u32)
+		.and(warp::query::<WaitQuery>())
+		.and(with_db(db.clone()))
+		.and(with_state(state.clone()))
+		.and(with_cfg(cfg.clone()))
+		.and(warp::any().map(move || waits.clone()))
+		.then(handlers::wait);
+
 	let appdata = (warp::path!("v1" / "appdata" / u32))
 		.and(warp::query::<AppDataQuery>())
 		.and(with_db(db.clone()))
+		.and(with_cache(cache.clone()))
 		.and(with_app_id(app_id))
 		.and(with_state(state.clone()))
+		.and(with_decoders(decoders))
 		.map(handlers::appdata);
 
+	let verified_blocks = warp::path!("v1" / "blocks")
+		.and(warp::query::<VerifiedBlocksQuery>())
+		.and(warp::header::optional::<String>("x-api-key"))
+		.and(with_db(db.clone()))
+		.and(with_cfg(cfg.clone()))
+		.then(handlers::verified_blocks);
+
+	let block_apps = warp::path!("v1" / "blocks" / u32 / "apps")
+		.and(with_db(db.clone()))
+		.and(with_cache(cache.clone()))
+		.map(handlers::block_apps);
+
+	let data_root = warp::path!("v1" / "dataroot" / u32)
+		.and(with_db(db.clone()))
+		.map(handlers::data_root);
+
+	let app_usage = warp::path!("v1" / "apps" / u32 / "usage")
+		.and(with_db(db.clone()))
+		.map(handlers::app_usage);
+
+	let storage = warp::path!("v1" / "storage")
+		.and(with_state(state.clone()))
+		.and(with_db(db.clone()))
+		.map(handlers::storage);
+
 	let status = warp::path!("v1" / "status")
 		.and(with_app_id(app_id))
-		.and(with_state(state))
+		.and(with_state(state.clone()))
 		.and(with_db(db))
+		.and(with_cache(cache))
+		.and(with_cfg(cfg.clone()))
 		.map(handlers::status);
 
-	warp::get().and(mode.or(latest_block).or(confidence).or(appdata).or(status))
+	let features = Features::from(&cfg);
+
+	let job = warp::path!("v1" / "jobs" / u64)
+		.and(with_feature(&features, Feature::BulkVerification))
+		.and(with_bulk_verification(bulk_verification.clone()))
+		.map(handlers::job);
+
+	let verify = warp::path!("v1" / "verify")
+		.and(with_feature(&features, Feature::BulkVerification))
+		.and(warp::post())
+		.and(warp::body::json())
+		.and(with_state(state))
+		.and(with_cfg(cfg))
+		.and(with_bulk_verification(bulk_verification))
+		.map(handlers::verify);
+
+	warp::get()
+		.and(
+			mode.or(version)
+				.or(latest_block)
+				.or(confidence)
+				.or(wait)
+				.or(appdata)
+				.or(verified_blocks)
+				.or(block_apps)
+				.or(data_root)
+				.or(app_usage)
+				.or(storage)
+				.or(status)
+				.or(job),
+		)
+		.or(verify)
 }
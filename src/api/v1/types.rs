@@ -1,5 +1,12 @@
-use avail_subxt::primitives::AppUncheckedExtrinsic;
-use color_eyre::Report;
+use crate::{
+	confidence::Confidence,
+	data::{SampledCell, StorageStats},
+	features::Feature,
+	network::p2p::Reachability,
+};
+use avail_subxt::{primitives::AppUncheckedExtrinsic, utils::H256};
+use base64::{engine::general_purpose, Engine};
+use color_eyre::{eyre::eyre, Report, Result};
 use hyper::StatusCode;
 use serde::{Deserialize, Serialize};
 
@@ -12,14 +19,109 @@ where
 	NotFound,
 	NotFinalized,
 	InProcess,
+	BadRequest(String),
+	/// API key is not configured
+	Unauthorized(String),
+	/// Too many requests are being processed
+	TooManyRequests(String),
 	Error(Report),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConfidenceResponse {
 	pub block: u32,
-	pub confidence: f64,
+	pub confidence: Confidence,
 	pub serialised_confidence: Option<String>,
+	/// Confidence computed only from the cells fetched from the DHT
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub p2p_confidence: Option<Confidence>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sampled_cells: Option<Vec<SampledCell>>,
+	/// Unix timestamp in nanoseconds, when confidence was established
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub verified_at: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ConfidenceQuery {
+	pub verbose: Option<bool>,
+	/// Unix timestamp in nanoseconds, returns confidence only if it was established by then
+	pub as_of: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct WaitQuery {
+	/// Confidence threshold in percents, defaults to the configured confidence
+	pub confidence: Option<f64>,
+	/// Maximum waiting time (e.g. `30s`, `500ms` or `2m`), defaults to 30 seconds
+	pub timeout: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaitResponse {
+	pub block: u32,
+	/// Last known confidence, if block is verified
+	pub confidence: Option<Confidence>,
+	pub reached: bool,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct VerifiedBlocksQuery {
+	/// Unix timestamp in nanoseconds
+	pub verified_after: Option<u64>,
+	/// Cursor returned by the previous request
+	pub since_cursor: Option<String>,
+	/// Maximum number of returned blocks
+	pub limit: Option<usize>,
+}
+
+/// Position in the verified blocks, ordered by verification time and block number.
+/// Encoded into the opaque URL safe string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+	pub verified_at: u64,
+	pub block: u32,
+}
+
+impl Cursor {
+	pub fn encode(&self) -> String {
+		let bytes = [
+			self.verified_at.to_be_bytes().as_slice(),
+			self.block.to_be_bytes().as_slice(),
+		]
+		.concat();
+		general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+	}
+
+	pub fn decode(cursor: &str) -> Result<Self> {
+		let bytes: [u8; 12] = general_purpose::URL_SAFE_NO_PAD
+			.decode(cursor)
+			.ok()
+			.and_then(|bytes| bytes.try_into().ok())
+			.ok_or_else(|| eyre!("Invalid cursor {cursor}"))?;
+		let (verified_at, block) = bytes.split_at(8);
+		Ok(Cursor {
+			verified_at: u64::from_be_bytes(verified_at.try_into()?),
+			block: u32::from_be_bytes(block.try_into()?),
+		})
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifiedBlock {
+	pub block: u32,
+	/// Confidence is not set for the blocks processed by the fat client, which doesn't sample cells
+	pub confidence: Option<Confidence>,
+	/// Unix timestamp in nanoseconds, when confidence was established
+	pub verified_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifiedBlocksResponse {
+	/// Blocks ordered by verification time
+	pub blocks: Vec<VerifiedBlock>,
+	/// Cursor after the last returned block, used to request the following blocks
+	pub cursor: String,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -27,12 +129,18 @@ pub struct ConfidenceResponse {
 pub enum Extrinsics {
 	Encoded(Vec<AppUncheckedExtrinsic>),
 	Decoded(Vec<String>),
+	/// Payloads decoded by the decoder registered for the app
+	Plugin(Vec<serde_json::Value>),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ExtrinsicsDataResponse {
 	pub block: u32,
 	pub extrinsics: Extrinsics,
+	/// Hashes of the extrinsics, which match the on-chain extrinsic hashes
+	pub extrinsic_hashes: Vec<H256>,
+	/// Hash of the concatenated extrinsics
+	pub data_hash: H256,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -40,11 +148,43 @@ pub struct LatestBlockResponse {
 	pub latest_block: u32,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionResponse {
+	pub version: String,
+	pub network_version: String,
+	/// Enabled experimental features
+	pub features: Vec<Feature>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Status {
 	pub block_num: u32,
-	pub confidence: f64,
+	pub confidence: Confidence,
 	pub app_id: Option<u32>,
+	/// Public reachability of the node on the P2P network
+	pub reachability: Reachability,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub public_address: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct StorageResponse {
+	#[serde(flatten)]
+	pub stats: StorageStats,
+	/// Unix timestamp in seconds of the last pruning of the DHT records
+	pub last_pruned_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockApp {
+	pub app_id: u32,
+	pub reconstructed: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockAppsResponse {
+	pub block: u32,
+	pub apps: Vec<BlockApp>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -52,6 +192,42 @@ pub struct AppDataQuery {
 	pub decode: Option<bool>,
 }
 
+/// Blocks to verify, either as a list of block numbers or as an inclusive range
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyRequest {
+	Blocks(Vec<u32>),
+	Range { first: u32, last: u32 },
+}
+
+impl VerifyRequest {
+	pub fn blocks(self) -> Vec<u32> {
+		match self {
+			VerifyRequest::Blocks(blocks) => blocks,
+			VerifyRequest::Range { first, last } => (first..=last).collect(),
+		}
+	}
+
+	/// Number of requested blocks
+	pub fn len(&self) -> usize {
+		match self {
+			VerifyRequest::Blocks(blocks) => blocks.len(),
+			VerifyRequest::Range { first, last } => {
+				(*last as usize + 1).saturating_sub(*first as usize)
+			},
+		}
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifyResponse {
+	pub job_id: u64,
+}
+
 impl<T: Send + Serialize> warp::Reply for ClientResponse<T> {
 	fn into_response(self) -> warp::reply::Response {
 		match self {
@@ -73,6 +249,18 @@ impl<T: Send + Serialize> warp::Reply for ClientResponse<T> {
 				StatusCode::UNAUTHORIZED,
 			)
 			.into_response(),
+			ClientResponse::BadRequest(message) => {
+				warp::reply::with_status(warp::reply::json(&message), StatusCode::BAD_REQUEST)
+					.into_response()
+			},
+			ClientResponse::Unauthorized(message) => {
+				warp::reply::with_status(warp::reply::json(&message), StatusCode::UNAUTHORIZED)
+					.into_response()
+			},
+			ClientResponse::TooManyRequests(message) => {
+				warp::reply::with_status(warp::reply::json(&message), StatusCode::TOO_MANY_REQUESTS)
+					.into_response()
+			},
 			ClientResponse::Error(e) => warp::reply::with_status(
 				warp::reply::json(&e.to_string()),
 				StatusCode::INTERNAL_SERVER_ERROR,
@@ -1,21 +1,33 @@
 use super::{
 	transactions,
 	types::{
-		block_status, filter_fields, Block, BlockStatus, DataQuery, DataResponse, DataTransaction,
-		Error, FieldsQueryParameter, Header, Status, SubmitResponse, Subscription, SubscriptionId,
-		Transaction, Version, WsClients,
+		block_status, filter_fields, Block, BlockStateResponse, BlockStatus, DataQuery,
+		DataResponse, DataTransaction, Error, FieldsQueryParameter, Header, InclusionProof,
+		InclusionProofQuery, MessageSchema, ReplayQuery, ReportQuery, Status, SubmitResponse,
+		Subscription, SubscriptionId, Transaction, Version, WsClients,
 	},
 	ws,
 };
 use crate::{
-	api::v2::types::{ErrorCode, InternalServerError},
+	api::{
+		cache::ReadCache,
+		v2::types::{ErrorCode, InternalServerError},
+	},
+	block_state,
+	confidence::Confidence,
 	data::{Database, Key},
-	types::{RuntimeConfig, State},
-	utils::calculate_confidence,
+	network::rpc,
+	proof,
+	report::{self, SignedReport},
+	types::{IdentityConfig, RuntimeConfig, State},
+	utils::{app_data_hash, extract_app_lookup, extract_kate},
 };
-use avail_subxt::primitives;
+use avail_subxt::{primitives, utils::H256};
+use codec::Encode;
 use color_eyre::{eyre::eyre, Result};
 use hyper::StatusCode;
+use kate_recovery::{commitments, matrix::Dimensions};
+use sp_core::blake2_256;
 use std::{
 	convert::Infallible,
 	sync::{Arc, Mutex},
@@ -48,11 +60,14 @@ pub async fn submit(
 pub async fn ws(
 	subscription_id: String,
 	ws: Ws,
+	schema: MessageSchema,
+	replay: ReplayQuery,
 	clients: WsClients,
 	version: Version,
 	config: RuntimeConfig,
 	submitter: Option<Arc<impl transactions::Submit + Clone + Send + Sync + 'static>>,
 	state: Arc<Mutex<State>>,
+	db: impl Database + Send + Sync + 'static,
 ) -> Result<impl Reply, Rejection> {
 	if !clients.has_subscription(&subscription_id).await {
 		return Err(warp::reject::not_found());
@@ -62,11 +77,14 @@ pub async fn ws(
 		ws::connect(
 			subscription_id,
 			web_socket,
+			schema,
+			replay.from_block,
 			clients,
 			version,
 			config,
 			submitter.clone(),
 			state.clone(),
+			db,
 		)
 	}))
 }
@@ -94,6 +112,7 @@ pub async fn block(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
 	db: impl Database,
+	cache: ReadCache,
 ) -> Result<impl Reply, Error> {
 	let state = state.lock().expect("Lock should be acquired");
 	let sync_start_block = &config.sync_start_block;
@@ -105,12 +124,38 @@ pub async fn block(
 		.and_then(|extension| block_status(sync_start_block, &state, block_number, extension))
 		.ok_or(Error::not_found())?;
 
-	let confidence = db
-		.get(Key::VerifiedCellCount(block_number))
+	let confidence = cache
+		.verified_cell_count(&db, block_number)
+		.map_err(Error::internal_server_error)?
+		.map(|count| Confidence::from_cell_count(count, config.withheld_fraction));
+	let p2p_confidence = report::block_p2p_confidence(&db, block_number, config.withheld_fraction)
+		.map_err(Error::internal_server_error)?;
+
+	let degraded = db
+		.get::<bool>(Key::Degraded(block_number))
 		.map_err(Error::internal_server_error)?
-		.map(calculate_confidence);
+		.unwrap_or(false);
 
-	Ok(Block::new(block_status, confidence))
+	Ok(Block::new(
+		block_status,
+		confidence,
+		p2p_confidence,
+		degraded,
+	))
+}
+
+pub async fn block_state(
+	block_number: u32,
+	db: impl Database,
+) -> Result<BlockStateResponse, Error> {
+	let state = block_state::get(&db, block_number)
+		.map_err(Error::internal_server_error)?
+		.ok_or(Error::not_found())?;
+
+	Ok(BlockStateResponse {
+		block_number,
+		state,
+	})
 }
 
 pub async fn block_header(
@@ -148,6 +193,7 @@ pub async fn block_data(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
 	db: impl Database,
+	cache: ReadCache,
 ) -> Result<DataResponse, Error> {
 	let state = state.lock().expect("Lock should be acquired");
 
@@ -165,17 +211,19 @@ pub async fn block_data(
 		return Err(Error::bad_request_unknown("Block data is not available"));
 	};
 
-	let data = db
-		.get::<Vec<Vec<u8>>>(Key::AppData(app_id, block_number))
+	let data = cache
+		.app_data(&db, app_id, block_number)
 		.map_err(Error::internal_server_error)?;
 
 	let Some(data) = data else {
 		return Ok(DataResponse {
 			block_number,
+			data_hash: None,
 			data_transactions: vec![],
 		});
 	};
 
+	let data_hash = app_data_hash(&data);
 	let mut data_transactions: Vec<DataTransaction> = data
 		.into_iter()
 		.map(DataTransaction::try_from)
@@ -188,10 +236,85 @@ pub async fn block_data(
 
 	Ok(DataResponse {
 		block_number,
+		data_hash: Some(data_hash),
 		data_transactions,
 	})
 }
 
+pub async fn report(
+	query: ReportQuery,
+	version: Version,
+	config: RuntimeConfig,
+	identity_config: IdentityConfig,
+	db: impl Database + Send + 'static,
+) -> Result<SignedReport, Error> {
+	let ReportQuery { first, last } = query;
+	if first > last {
+		return Err(Error::bad_request_unknown("Invalid block range"));
+	}
+	if last - first >= report::MAX_BLOCKS {
+		return Err(Error::bad_request_unknown(&format!(
+			"Report can contain at most {} blocks",
+			report::MAX_BLOCKS
+		)));
+	}
+
+	// Database is read for every block in the range, so report is generated on the blocking thread
+	tokio::task::spawn_blocking(move || {
+		report::generate(&db, first..=last, version.version, config.withheld_fraction)
+			.and_then(|report| report::sign(report, &identity_config.avail_key_pair))
+	})
+	.await
+	.map_err(|error| Error::internal_server_error(eyre!("Report generation failed: {error}")))?
+	.map_err(Error::internal_server_error)
+}
+
+pub async fn inclusion_proof(
+	block_number: u32,
+	query: InclusionProofQuery,
+	rpc_client: rpc::Client,
+	db: impl Database,
+) -> Result<InclusionProof, Error> {
+	let header = db
+		.get::<primitives::Header>(Key::BlockHeader(block_number))
+		.map_err(Error::internal_server_error)?
+		.ok_or(Error::not_found())?;
+
+	let (Some((rows, cols, _, commitment)), Some(lookup)) = (
+		extract_kate(&header.extension),
+		extract_app_lookup(&header.extension).map_err(Error::internal_server_error)?,
+	) else {
+		return Err(Error::bad_request_unknown("Block has no data"));
+	};
+
+	let dimensions = Dimensions::new(rows, cols)
+		.ok_or_else(|| Error::internal_server_error(eyre!("Invalid dimensions")))?;
+	let commitments = commitments::from_slice(&commitment)
+		.map_err(|error| Error::internal_server_error(error.into()))?;
+
+	let extrinsics = db
+		.get::<Vec<Vec<u8>>>(Key::AppData(query.app_id, block_number))
+		.map_err(Error::internal_server_error)?
+		.ok_or_else(|| Error::bad_request_unknown("App data is not available"))?;
+
+	if query.index >= extrinsics.len() {
+		return Err(Error::not_found());
+	}
+
+	let positions =
+		proof::extrinsic_positions(&lookup, dimensions, query.app_id, &extrinsics, query.index)
+			.map_err(Error::internal_server_error)?;
+
+	let block_hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+	let cells = rpc_client
+		.request_kate_proof(block_hash, &positions)
+		.await
+		.map_err(Error::internal_server_error)?;
+
+	InclusionProof::new(block_number, block_hash, &query, &cells, &commitments)
+		.map_err(Error::internal_server_error)
+}
+
 pub async fn handle_rejection(error: Rejection) -> Result<impl Reply, Rejection> {
 	if error.find::<InternalServerError>().is_some() {
 		return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
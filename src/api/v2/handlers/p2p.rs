@@ -1,6 +1,6 @@
 use crate::{
 	api::v2::types::Error,
-	network::p2p::{self, LocalInfo},
+	network::p2p::{self, LocalInfo, Reachability},
 };
 use libp2p::{swarm::DialError, Multiaddr, PeerId};
 use serde::{Deserialize, Serialize};
@@ -16,6 +16,9 @@ pub struct Listeners {
 pub struct PeerInfoResponse {
 	peer_id: String,
 	listeners: Listeners,
+	reachability: Reachability,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	public_address: Option<String>,
 }
 
 impl Reply for PeerInfoResponse {
@@ -32,6 +35,8 @@ impl From<LocalInfo> for PeerInfoResponse {
 				local: value.local_listeners,
 				external: value.external_listeners,
 			},
+			reachability: value.reachability,
+			public_address: value.public_address,
 		}
 	}
 }
@@ -9,17 +9,22 @@ use warp::{Filter, Rejection, Reply};
 
 use self::{
 	handlers::{handle_rejection, log_internal_server_error},
-	types::{DataQuery, PublishMessage, Version, WsClients},
+	types::{
+		DataQuery, InclusionProofQuery, MessageSchema, PublishMessage, ReplayQuery, ReportQuery,
+		Version, WsClients,
+	},
 };
 
 use crate::{
-	api::v2::types::Topic,
+	api::{cache::ReadCache, v2::types::Topic, with_feature},
 	data::Database,
+	features::{Feature, Features},
 	network::{p2p, rpc::Client},
-	types::{IdentityConfig, RuntimeConfig, State},
+	types::{BlockVerified, IdentityConfig, RuntimeConfig, State},
 };
 
 mod handlers;
+mod sse;
 mod transactions;
 pub mod types;
 mod ws;
@@ -37,6 +42,10 @@ fn with_db<T: Database + Clone + Send>(
 	warp::any().map(move || db.clone())
 }
 
+fn with_cache(cache: ReadCache) -> impl Filter<Extract = (ReadCache,), Error = Infallible> + Clone {
+	warp::any().map(move || cache.clone())
+}
+
 fn with_ws_clients(
 	clients: WsClients,
 ) -> impl Filter<Extract = (WsClients,), Error = Infallible> + Clone {
@@ -66,16 +75,28 @@ fn block_route(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
 	db: impl Database + Clone + Send,
+	cache: ReadCache,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	warp::path!("v2" / "blocks" / u32)
 		.and(warp::get())
 		.and(warp::any().map(move || config.clone()))
 		.and(warp::any().map(move || state.clone()))
 		.and(with_db(db))
+		.and(with_cache(cache))
 		.then(handlers::block)
 		.map(log_internal_server_error)
 }
 
+fn block_state_route(
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "blocks" / u32 / "state")
+		.and(warp::get())
+		.and(with_db(db))
+		.then(handlers::block_state)
+		.map(log_internal_server_error)
+}
+
 fn block_header_route(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
@@ -94,6 +115,7 @@ fn block_data_route(
 	config: RuntimeConfig,
 	state: Arc<Mutex<State>>,
 	db: impl Database + Clone + Send,
+	cache: ReadCache,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	warp::path!("v2" / "blocks" / u32 / "data")
 		.and(warp::get())
@@ -101,10 +123,45 @@ fn block_data_route(
 		.and(warp::any().map(move || config.clone()))
 		.and(warp::any().map(move || state.clone()))
 		.and(with_db(db))
+		.and(with_cache(cache))
 		.then(handlers::block_data)
 		.map(log_internal_server_error)
 }
 
+fn report_route(
+	version: Version,
+	config: RuntimeConfig,
+	identity_config: IdentityConfig,
+	db: impl Database + Clone + Send + 'static,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "report")
+		.and(with_feature(
+			&Features::from(&config),
+			Feature::SignedReports,
+		))
+		.and(warp::get())
+		.and(warp::query::<ReportQuery>())
+		.and(warp::any().map(move || version.clone()))
+		.and(warp::any().map(move || config.clone()))
+		.and(warp::any().map(move || identity_config.clone()))
+		.and(with_db(db))
+		.then(handlers::report)
+		.map(log_internal_server_error)
+}
+
+fn inclusion_proof_route(
+	rpc_client: Client,
+	db: impl Database + Clone + Send,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "blocks" / u32 / "proof")
+		.and(warp::get())
+		.and(warp::query::<InclusionProofQuery>())
+		.and(warp::any().map(move || rpc_client.clone()))
+		.and(with_db(db))
+		.then(handlers::inclusion_proof)
+		.map(log_internal_server_error)
+}
+
 fn submit_route(
 	submitter: Option<Arc<impl transactions::Submit + Clone + Send + Sync>>,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
@@ -153,17 +210,38 @@ fn ws_route(
 	config: RuntimeConfig,
 	submitter: Option<Arc<impl transactions::Submit + Clone + Send + Sync + 'static>>,
 	state: Arc<Mutex<State>>,
+	db: impl Database + Clone + Send + Sync + 'static,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	warp::path!("v2" / "ws" / String)
 		.and(warp::ws())
+		.and(warp::query::<MessageSchema>())
+		.and(warp::query::<ReplayQuery>())
 		.and(with_ws_clients(clients))
 		.and(warp::any().map(move || version.clone()))
 		.and(warp::any().map(move || config.clone()))
 		.and(warp::any().map(move || submitter.clone()))
 		.and(warp::any().map(move || state.clone()))
+		.and(with_db(db))
 		.and_then(handlers::ws)
 }
 
+fn events_route(
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database + Clone + Send,
+	block_sender: broadcast::Sender<BlockVerified>,
+) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+	warp::path!("v2" / "events")
+		.and(warp::get())
+		.and(warp::header::optional::<u32>("last-event-id"))
+		.and(warp::query::<MessageSchema>())
+		.and(warp::any().map(move || config.clone()))
+		.and(warp::any().map(move || state.clone()))
+		.and(with_db(db))
+		.and(warp::any().map(move || block_sender.clone()))
+		.map(sse::events)
+}
+
 pub async fn publish<T: Clone + TryInto<PublishMessage>>(
 	topic: Topic,
 	mut receiver: broadcast::Receiver<T>,
@@ -211,8 +289,10 @@ pub fn routes(
 	identity_config: IdentityConfig,
 	rpc_client: Client,
 	ws_clients: WsClients,
-	db: impl Database + Clone + Send,
+	db: impl Database + Clone + Send + Sync + 'static,
 	p2p_client: p2p::Client,
+	block_sender: broadcast::Sender<BlockVerified>,
+	cache: ReadCache,
 ) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
 	let version = Version {
 		version,
@@ -221,6 +301,8 @@ pub fn routes(
 
 	let app_id = config.app_id.as_ref();
 
+	let proof_rpc_client = rpc_client.clone();
+	let report_identity_config = identity_config.clone();
 	let submitter = app_id.map(|&app_id| {
 		Arc::new(transactions::Submitter {
 			rpc_client,
@@ -231,16 +313,47 @@ pub fn routes(
 
 	version_route(version.clone())
 		.or(status_route(config.clone(), state.clone()))
-		.or(block_route(config.clone(), state.clone(), db.clone()))
+		.or(block_route(
+			config.clone(),
+			state.clone(),
+			db.clone(),
+			cache.clone(),
+		))
+		.or(block_state_route(db.clone()))
 		.or(block_header_route(
 			config.clone(),
 			state.clone(),
 			db.clone(),
 		))
-		.or(block_data_route(config.clone(), state.clone(), db.clone()))
+		.or(block_data_route(
+			config.clone(),
+			state.clone(),
+			db.clone(),
+			cache,
+		))
+		.or(inclusion_proof_route(proof_rpc_client, db.clone()))
+		.or(report_route(
+			version.clone(),
+			config.clone(),
+			report_identity_config,
+			db.clone(),
+		))
 		.or(subscriptions_route(ws_clients.clone()))
+		.or(events_route(
+			config.clone(),
+			state.clone(),
+			db.clone(),
+			block_sender,
+		))
 		.or(submit_route(submitter.clone()))
-		.or(ws_route(ws_clients, version, config, submitter, state))
+		.or(ws_route(
+			ws_clients,
+			version,
+			config,
+			submitter,
+			state,
+			db.clone(),
+		))
 		.or(p2p_local_info_route(p2p_client.clone()))
 		.or(p2p_peers_dial_route(p2p_client.clone()))
 		.recover(handle_rejection)
@@ -250,10 +363,13 @@ pub fn routes(
 mod tests {
 	use super::{transactions, types::Transaction};
 	use crate::{
+		api::cache::ReadCache,
 		api::v2::types::{
-			DataField, ErrorCode, SubmitResponse, Subscription, SubscriptionId, Topic, Version,
-			WsClients, WsError, WsResponse,
+			ConfidenceMessage, DataField, ErrorCode, PublishMessage, SubmitResponse, Subscription,
+			SubscriptionId, Topic, Version, WsClients, WsError, WsResponse,
 		},
+		block_state::BlockState,
+		confidence::Confidence,
 		data::Key,
 		data::{mem_db, Database},
 		types::{BlockRange, OptionBlockRange, RuntimeConfig, State},
@@ -321,6 +437,26 @@ mod tests {
 		assert_eq!(response.body(), &expected);
 	}
 
+	#[tokio::test]
+	async fn status_route_degraded() {
+		let state = Arc::new(Mutex::new(State {
+			degraded: true,
+			..Default::default()
+		}));
+		let route = super::status_route(RuntimeConfig::default(), state);
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/status")
+			.reply(&route)
+			.await;
+
+		let expected = format!(
+			r#"{{"modes":["light"],"genesis_hash":"{:x?}","network":"{NETWORK}","blocks":{{"latest":0}},"degraded":true}}"#,
+			H256::default()
+		);
+		assert_eq!(response.body(), &expected);
+	}
+
 	#[tokio::test]
 	async fn status_route() {
 		let runtime_config = RuntimeConfig {
@@ -374,7 +510,7 @@ mod tests {
 			state.latest = latest;
 		}
 		let db = mem_db::MemoryDB::default();
-		let route = super::block_route(config, state, db);
+		let route = super::block_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path(&format!("/v2/blocks/{block_number}"))
@@ -396,7 +532,7 @@ mod tests {
 		}
 		let db = mem_db::MemoryDB::default();
 		_ = db.put(Key::BlockHeader(10), incomplete_header());
-		let route = super::block_route(config, state, db);
+		let route = super::block_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path("/v2/blocks/10")
@@ -423,7 +559,7 @@ mod tests {
 		let db = mem_db::MemoryDB::default();
 		_ = db.put(Key::VerifiedCellCount(10), 4);
 		_ = db.put(Key::BlockHeader(10), header());
-		let route = super::block_route(config, state, db);
+		let route = super::block_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path("/v2/blocks/10")
@@ -437,6 +573,67 @@ mod tests {
 		);
 	}
 
+	#[tokio::test]
+	async fn block_route_degraded() {
+		let state = Arc::new(Mutex::new(State::default()));
+		{
+			let mut state = state.lock().unwrap();
+			state.latest = 10;
+			state.header_verified.set(10);
+			state.data_verified.set(10);
+			// Degraded flag is reported per block, not from the current connectivity
+			state.degraded = true;
+		}
+		let db = mem_db::MemoryDB::default();
+		_ = db.put(Key::VerifiedCellCount(10), 4);
+		_ = db.put(Key::BlockHeader(10), header());
+		let route = super::block_route(
+			RuntimeConfig::default(),
+			state,
+			db.clone(),
+			ReadCache::default(),
+		);
+		let request = || warp::test::request().method("GET").path("/v2/blocks/10");
+
+		let response = request().reply(&route).await;
+		assert_eq!(
+			response.body(),
+			r#"{"status":"finished","confidence":93.75}"#
+		);
+
+		_ = db.put(Key::Degraded(10), true);
+		let response = request().reply(&route).await;
+		assert_eq!(
+			response.body(),
+			r#"{"status":"finished","confidence":93.75,"degraded":true}"#
+		);
+	}
+
+	#[tokio::test]
+	async fn block_state_route_failed() {
+		let db = mem_db::MemoryDB::default();
+		let route = super::block_state_route(db.clone());
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/10/state")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+		let reason = "Failed to fetch 2 cells".to_string();
+		_ = db.put(Key::BlockState(10), BlockState::Failed(reason));
+		let response = warp::test::request()
+			.method("GET")
+			.path("/v2/blocks/10/state")
+			.reply(&route)
+			.await;
+		assert_eq!(response.status(), StatusCode::OK);
+		assert_eq!(
+			response.body(),
+			r#"{"block_number":10,"state":"failed","reason":"Failed to fetch 2 cells"}"#
+		);
+	}
+
 	#[test_case(0, r#"Block header is not available"#  ; "Block is unavailable")]
 	#[test_case(6, r#"Block header is not available"#  ; "Block is pending")]
 	#[test_case(10, r#"Block header is not available"#  ; "Block is in verifying-header state")]
@@ -563,7 +760,7 @@ mod tests {
 		}));
 		let db = mem_db::MemoryDB::default();
 		_ = db.put(Key::BlockHeader(block_number), header());
-		let route = super::block_data_route(config, state, db);
+		let route = super::block_data_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path(&format!("/v2/blocks/{block_number}/data"))
@@ -581,7 +778,7 @@ mod tests {
 			..Default::default()
 		}));
 		let db = mem_db::MemoryDB::default();
-		let route = super::block_data_route(config, state, db);
+		let route = super::block_data_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path("/v2/blocks/11/data")
@@ -605,7 +802,7 @@ mod tests {
 		}));
 		let db = mem_db::MemoryDB::default();
 		_ = db.put(Key::BlockHeader(5), header());
-		let route = super::block_data_route(config, state, db);
+		let route = super::block_data_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path("/v2/blocks/5/data")
@@ -645,7 +842,7 @@ mod tests {
 			]],
 		);
 		_ = db.put(Key::BlockHeader(5), header());
-		let route = super::block_data_route(config, state, db);
+		let route = super::block_data_route(config, state, db, ReadCache::default());
 		let response = warp::test::request()
 			.method("GET")
 			.path("/v2/blocks/5/data")
@@ -654,7 +851,7 @@ mod tests {
 		assert_eq!(response.status(), StatusCode::OK);
 		assert_eq!(
 			response.body(),
-			r#"{"block_number":5,"data_transactions":[{"data":"dGVzdAo=","extrinsic":"vQGEANQ1k8cV/dMcYRQavQSpn9aCLIVYhUzN45pWhOelbaJ9ATIMK7ATKhdJRt/GtGciPPa4MYxxrurlX0cSXJ65qIx+DL+cMuoIBESJBZxe0QepaT4/AXr9w3Ct7xVJo/BqbYMABAAEHQEUdGVzdAo="}]}"#
+			r#"{"block_number":5,"data_hash":"0x2cc86c8e42a3f0cfb45c2e8b48e3be2a73aa7f9439cfe4cf95725e9f7cb71007","data_transactions":[{"data":"dGVzdAo=","extrinsic":"vQGEANQ1k8cV/dMcYRQavQSpn9aCLIVYhUzN45pWhOelbaJ9ATIMK7ATKhdJRt/GtGciPPa4MYxxrurlX0cSXJ65qIx+DL+cMuoIBESJBZxe0QepaT4/AXr9w3Ct7xVJo/BqbYMABAAEHQEUdGVzdAo=","hash":"0x2cc86c8e42a3f0cfb45c2e8b48e3be2a73aa7f9439cfe4cf95725e9f7cb71007"}]}"#
 		);
 	}
 
@@ -766,6 +963,7 @@ mod tests {
 				config.clone(),
 				submitter.map(Arc::new),
 				state.clone(),
+				mem_db::MemoryDB::default(),
 			);
 			let ws_client = warp::test::ws()
 				.path(&format!("/v2/ws/{client_uuid}"))
@@ -783,6 +981,61 @@ mod tests {
 		}
 	}
 
+	async fn recv_block_number(ws_client: &mut warp::test::WsClient) -> u64 {
+		let message = ws_client.recv().await.unwrap();
+		let message: serde_json::Value = serde_json::from_str(message.to_str().unwrap()).unwrap();
+		assert_eq!(message["topic"], "confidence-achieved");
+		message["message"]["block_number"].as_u64().unwrap()
+	}
+
+	#[tokio::test]
+	async fn ws_route_replay() {
+		let client_uuid = uuid::Uuid::new_v4().to_string();
+		let clients = WsClients::default();
+		let subscription = Subscription {
+			topics: HashSet::from([Topic::ConfidenceAchieved]),
+			data_fields: HashSet::new(),
+		};
+		clients.subscribe(&client_uuid, subscription).await;
+
+		let db = mem_db::MemoryDB::default();
+		for block_number in 5..=8 {
+			db.put(Key::VerifiedCellCount(block_number), 10u32).unwrap();
+		}
+		let state = Arc::new(Mutex::new(State::default()));
+		state.lock().unwrap().latest = 8;
+
+		let route = super::ws_route(
+			clients.clone(),
+			v1(),
+			RuntimeConfig::default(),
+			None::<Arc<MockSubmitter>>,
+			state,
+			db,
+		);
+		let mut ws_client = warp::test::ws()
+			.path(&format!("/v2/ws/{client_uuid}?from_block=7"))
+			.handshake(route)
+			.await
+			.expect("handshake");
+
+		assert_eq!(recv_block_number(&mut ws_client).await, 7);
+		assert_eq!(recv_block_number(&mut ws_client).await, 8);
+
+		// live message of the replayed block is skipped
+		for block_number in [8, 9] {
+			let message = ConfidenceMessage::new(block_number, Some(Confidence::new(99.9)));
+			clients
+				.publish(
+					&Topic::ConfidenceAchieved,
+					PublishMessage::ConfidenceAchieved(message),
+				)
+				.await
+				.unwrap();
+		}
+		assert_eq!(recv_block_number(&mut ws_client).await, 9);
+	}
+
 	#[tokio::test]
 	async fn ws_route_version() {
 		let mut test = MockSetup::new(RuntimeConfig::default(), None).await;
@@ -0,0 +1,162 @@
+use super::types::{ConfidenceMessage, MessageSchema, PublishMessage};
+use crate::{
+	confidence::Confidence,
+	data::{Database, Key},
+	types::{BlockVerified, OptionBlockRange, RuntimeConfig, State},
+};
+use color_eyre::Result;
+use futures::{stream, Stream, StreamExt};
+use std::{
+	convert::Infallible,
+	sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tracing::{error, warn};
+use warp::{sse::Event, Reply};
+
+/// Maximum number of blocks replayed on resume using `Last-Event-ID` header,
+/// or on WebSocket connection with `from_block` query parameter
+pub const MAX_REPLAY_BLOCKS: u32 = 1024;
+
+const CONFIDENCE_ACHIEVED_EVENT: &str = "confidence-achieved";
+
+/// Returns confidence messages for verified blocks after the given last event ID,
+/// read from the persistent store.
+pub fn replay(
+	last_event_id: u32,
+	withheld_fraction: f64,
+	state: &Arc<Mutex<State>>,
+	db: &impl Database,
+) -> Result<Vec<(u32, PublishMessage)>> {
+	let last = {
+		let state = state.lock().expect("Lock should be acquired");
+		state.confidence_achieved.last()
+	};
+	let Some(last) = last else {
+		return Ok(vec![]);
+	};
+
+	let first = last_event_id
+		.saturating_add(1)
+		.max(last.saturating_sub(MAX_REPLAY_BLOCKS - 1));
+
+	let mut messages = vec![];
+	for block_number in first..=last {
+		let Some(count) = db.get(Key::VerifiedCellCount(block_number))? else {
+			continue;
+		};
+		let confidence = Confidence::from_cell_count(count, withheld_fraction);
+		let message = ConfidenceMessage::new(block_number, Some(confidence));
+		messages.push((block_number, PublishMessage::ConfidenceAchieved(message)));
+	}
+	Ok(messages)
+}
+
+fn event(
+	block_number: u32,
+	message: PublishMessage,
+	schema: &MessageSchema,
+) -> Option<Result<Event, Infallible>> {
+	match message.to_json(schema) {
+		Ok(data) => Some(Ok(Event::default()
+			.id(block_number.to_string())
+			.event(CONFIDENCE_ACHIEVED_EVENT)
+			.data(data))),
+		Err(error) => {
+			error!("Cannot serialize server sent event: {error:#}");
+			None
+		},
+	}
+}
+
+fn live(
+	receiver: broadcast::Receiver<BlockVerified>,
+	after: Option<u32>,
+	schema: MessageSchema,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+	BroadcastStream::new(receiver).filter_map(move |block| async move {
+		let block = match block {
+			Ok(block) => block,
+			Err(error) => {
+				warn!("Server sent events stream lagged: {error}");
+				return None;
+			},
+		};
+		if after.is_some_and(|after| block.block_num <= after) {
+			return None;
+		}
+		let block_number = block.block_num;
+		let message = PublishMessage::try_from(block).ok()?;
+		event(block_number, message, &schema)
+	})
+}
+
+pub fn events(
+	last_event_id: Option<u32>,
+	schema: MessageSchema,
+	config: RuntimeConfig,
+	state: Arc<Mutex<State>>,
+	db: impl Database,
+	block_sender: broadcast::Sender<BlockVerified>,
+) -> impl Reply {
+	// Subscribe before replaying, so no block is missed in between
+	let receiver = block_sender.subscribe();
+
+	let replayed = last_event_id
+		.map(|last_event_id| replay(last_event_id, config.withheld_fraction, &state, &db))
+		.transpose()
+		.unwrap_or_else(|error| {
+			error!("Cannot replay server sent events: {error:#}");
+			None
+		})
+		.unwrap_or_default();
+
+	let after = replayed
+		.last()
+		.map(|&(block_number, _)| block_number)
+		.or(last_event_id);
+
+	let replayed = stream::iter(replayed).filter_map(move |(block_number, message)| async move {
+		event(block_number, message, &schema)
+	});
+
+	warp::sse::reply(warp::sse::keep_alive().stream(replayed.chain(live(receiver, after, schema))))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::replay;
+	use crate::{
+		api::v2::types::PublishMessage,
+		data::{mem_db, Database, Key},
+		types::{OptionBlockRange, State},
+		utils::DEFAULT_WITHHELD_FRACTION,
+	};
+	use std::sync::{Arc, Mutex};
+
+	#[test]
+	fn replay_from_last_event_id() {
+		let db = mem_db::MemoryDB::default();
+		let state = Arc::new(Mutex::new(State::default()));
+		{
+			let mut state = state.lock().unwrap();
+			state.confidence_achieved.set(1);
+			state.confidence_achieved.set(5);
+		}
+		for block_number in [1, 2, 3, 5] {
+			db.put(Key::VerifiedCellCount(block_number), 10u32).unwrap();
+		}
+
+		let messages = replay(2, DEFAULT_WITHHELD_FRACTION, &state, &db).unwrap();
+		let blocks = messages.iter().map(|(block, _)| *block).collect::<Vec<_>>();
+		assert_eq!(blocks, vec![3, 5]);
+		assert!(messages
+			.iter()
+			.all(|(_, message)| matches!(message, PublishMessage::ConfidenceAchieved(_))));
+
+		assert!(replay(5, DEFAULT_WITHHELD_FRACTION, &state, &db)
+			.unwrap()
+			.is_empty());
+	}
+}
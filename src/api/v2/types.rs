@@ -10,14 +10,22 @@ use color_eyre::{
 };
 use derive_more::From;
 use hyper::{http, StatusCode};
-use kate_recovery::{com::AppData, commitments, config, matrix::Partition};
+use kate_recovery::{
+	com::AppData,
+	commitments, config,
+	data::Cell,
+	matrix::{Partition, Position},
+};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use sp_core::{blake2_256, H256};
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeSet, HashMap, HashSet},
 	sync::Arc,
 };
-use tokio::sync::{mpsc::UnboundedSender, RwLock};
+use tokio::{
+	sync::{mpsc::UnboundedSender, RwLock},
+	time::Instant,
+};
 use uuid::Uuid;
 use warp::{
 	ws::{self, Message},
@@ -25,11 +33,15 @@ use warp::{
 };
 
 use crate::{
+	block_state::BlockState,
+	confidence::Confidence,
 	network::rpc::Event as RpcEvent,
+	report::SignedReport,
 	types::{
-		self, block_matrix_partition_format, BlockVerified, OptionBlockRange, RuntimeConfig, State,
+		self, block_matrix_partition_format, BlockVerified, ConfidenceTimeout, OptionBlockRange,
+		RuntimeConfig, State,
 	},
-	utils::{decode_app_data, OptionalExtension},
+	utils::{decode_app_data, extrinsic_hash, OptionalExtension},
 };
 
 #[derive(Debug)]
@@ -80,10 +92,24 @@ pub struct Blocks {
 	pub available: Option<BlockRange>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub app_data: Option<BlockRange>,
+	/// Range of the gossiped headers, which finality is not verified
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub unverified_headers: Option<BlockRange>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub historical_sync: Option<HistoricalSync>,
 }
 
+/// Backoff from the rate limiting full node
+#[derive(Serialize, Deserialize)]
+pub struct RpcBackoff {
+	/// Current backoff in seconds
+	pub backoff: f64,
+	/// Seconds until the RPC calls are resumed
+	pub remaining: f64,
+	/// Number of rate limited responses since the start
+	pub rate_limited: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Status {
 	pub modes: Vec<Mode>,
@@ -97,6 +123,11 @@ pub struct Status {
 		with = "block_matrix_partition_format"
 	)]
 	pub partition: Option<Partition>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub rpc_backoff: Option<RpcBackoff>,
+	/// All full nodes are unreachable, blocks are verified from the DHT only
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub degraded: bool,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
@@ -177,11 +208,24 @@ impl Status {
 			latest: state.latest,
 			available: state.confidence_achieved.as_ref().map(From::from),
 			app_data: state.data_verified.as_ref().map(From::from),
+			unverified_headers: state.header_unverified.as_ref().map(From::from),
 			historical_sync,
 		};
 
 		let node = state.connected_node.clone();
 
+		let rpc_backoff = state
+			.rpc_backoff()
+			.filter(|backoff| backoff.is_active())
+			.map(|backoff| RpcBackoff {
+				backoff: backoff.backoff.as_secs_f64(),
+				remaining: backoff
+					.remaining(Instant::now())
+					.unwrap_or_default()
+					.as_secs_f64(),
+				rate_limited: backoff.rate_limited,
+			});
+
 		Status {
 			modes: config.into(),
 			app_id: config.app_id,
@@ -189,6 +233,8 @@ impl Status {
 			network: node.network(),
 			blocks,
 			partition: config.block_matrix_partition,
+			rpc_backoff,
+			degraded: state.degraded,
 		}
 	}
 }
@@ -221,12 +267,13 @@ impl Reply for Status {
 	}
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum Topic {
 	HeaderVerified,
 	ConfidenceAchieved,
 	DataVerified,
+	ConfidenceTimeout,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash)]
@@ -270,7 +317,14 @@ pub fn block_status(
 		return None;
 	};
 
-	let first_block = state.header_verified.first().unwrap_or(state.latest);
+	let first_block = [
+		state.header_verified.first(),
+		state.header_unverified.first(),
+	]
+	.into_iter()
+	.flatten()
+	.min()
+	.unwrap_or(state.latest);
 	let first_sync_block = sync_start_block.unwrap_or(first_block);
 
 	if block_number < first_sync_block {
@@ -305,7 +359,8 @@ pub fn block_status(
 		if state.header_verified.contains(block_number) {
 			return Some(BlockStatus::VerifyingConfidence);
 		}
-		if state.latest == block_number {
+		// Finality of the gossiped headers is not verified
+		if state.latest == block_number || state.header_unverified.contains(block_number) {
 			return Some(BlockStatus::VerifyingHeader);
 		}
 	}
@@ -316,12 +371,28 @@ pub fn block_status(
 #[derive(Serialize, Deserialize, PartialEq)]
 pub struct Block {
 	pub status: BlockStatus,
-	pub confidence: Option<f64>,
+	pub confidence: Option<Confidence>,
+	/// Confidence computed only from the cells fetched from the DHT
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub p2p_confidence: Option<Confidence>,
+	/// Set if the block was sampled while all full nodes were unreachable, so confidence is computed from the DHT only
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub degraded: bool,
 }
 
 impl Block {
-	pub fn new(status: BlockStatus, confidence: Option<f64>) -> Self {
-		Self { status, confidence }
+	pub fn new(
+		status: BlockStatus,
+		confidence: Option<Confidence>,
+		p2p_confidence: Option<Confidence>,
+		degraded: bool,
+	) -> Self {
+		Self {
+			status,
+			confidence,
+			p2p_confidence,
+			degraded,
+		}
 	}
 }
 
@@ -331,6 +402,19 @@ impl Reply for Block {
 	}
 }
 
+#[derive(Serialize)]
+pub struct BlockStateResponse {
+	pub block_number: u32,
+	#[serde(flatten)]
+	pub state: BlockState,
+}
+
+impl Reply for BlockStateResponse {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
 impl TryFrom<avail_subxt::primitives::Header> for HeaderMessage {
 	type Error = Report;
 
@@ -462,7 +546,16 @@ impl TryFrom<RpcEvent> for PublishMessage {
 pub struct ConfidenceMessage {
 	block_number: u32,
 	#[serde(skip_serializing_if = "Option::is_none")]
-	confidence: Option<f64>,
+	confidence: Option<Confidence>,
+}
+
+impl ConfidenceMessage {
+	pub fn new(block_number: u32, confidence: Option<Confidence>) -> Self {
+		ConfidenceMessage {
+			block_number,
+			confidence,
+		}
+	}
 }
 
 impl TryFrom<BlockVerified> for PublishMessage {
@@ -476,6 +569,30 @@ impl TryFrom<BlockVerified> for PublishMessage {
 	}
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConfidenceTimeoutMessage {
+	block_number: u32,
+	app_id: u32,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	confidence: Option<Confidence>,
+	threshold: Confidence,
+}
+
+impl TryFrom<ConfidenceTimeout> for PublishMessage {
+	type Error = Report;
+
+	fn try_from(value: ConfidenceTimeout) -> Result<Self, Self::Error> {
+		Ok(PublishMessage::ConfidenceTimeout(
+			ConfidenceTimeoutMessage {
+				block_number: value.block_num,
+				app_id: value.app_id,
+				confidence: value.confidence,
+				threshold: value.threshold,
+			},
+		))
+	}
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(try_from = "String")]
 pub struct FieldsQueryParameter(pub HashSet<DataField>);
@@ -501,6 +618,9 @@ pub struct DataQuery {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataResponse {
 	pub block_number: u32,
+	/// Hash of the concatenated extrinsics, omitted if there is no data
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub data_hash: Option<H256>,
 	pub data_transactions: Vec<DataTransaction>,
 }
 
@@ -510,6 +630,157 @@ impl Reply for DataResponse {
 	}
 }
 
+#[derive(Serialize, Deserialize)]
+pub struct InclusionProofQuery {
+	pub app_id: u32,
+	pub index: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ReportQuery {
+	pub first: u32,
+	pub last: u32,
+}
+
+impl Reply for SignedReport {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProofCell {
+	row: u32,
+	col: u16,
+	#[serde(with = "hex_prefixed")]
+	proof: Vec<u8>,
+	#[serde(with = "hex_prefixed")]
+	scalar: Vec<u8>,
+}
+
+impl TryFrom<&ProofCell> for Cell {
+	type Error = Report;
+
+	fn try_from(cell: &ProofCell) -> Result<Self, Self::Error> {
+		let content = [cell.proof.as_slice(), cell.scalar.as_slice()]
+			.concat()
+			.try_into()
+			.map_err(|_| eyre!("Cell ({}, {}) has invalid length", cell.row, cell.col))?;
+		Ok(Cell {
+			position: Position {
+				row: cell.row,
+				col: cell.col,
+			},
+			content,
+		})
+	}
+}
+
+impl From<&Cell> for ProofCell {
+	fn from(cell: &Cell) -> Self {
+		let (proof, scalar) = cell.content.split_at(config::COMMITMENT_SIZE);
+		ProofCell {
+			row: cell.position.row,
+			col: cell.position.col,
+			proof: proof.to_vec(),
+			scalar: scalar.to_vec(),
+		}
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RowCommitment {
+	row: u32,
+	commitment: Commitment,
+}
+
+impl RowCommitment {
+	pub fn new(row: u32, commitment: [u8; config::COMMITMENT_SIZE]) -> Self {
+		RowCommitment {
+			row,
+			commitment: Commitment(commitment),
+		}
+	}
+
+	/// Checks if the commitment matches the commitment of the same row in the given commitments
+	pub fn matches(&self, commitments: &[[u8; config::COMMITMENT_SIZE]]) -> bool {
+		commitments.get(self.row as usize) == Some(&self.commitment.0)
+	}
+
+	pub fn row(&self) -> u32 {
+		self.row
+	}
+}
+
+/// Cells with proofs and row commitments of the cells containing app extrinsic
+#[derive(Debug, Serialize, Clone)]
+pub struct InclusionProof {
+	pub block_number: u32,
+	pub block_hash: H256,
+	pub app_id: u32,
+	pub index: usize,
+	pub cells: Vec<ProofCell>,
+	pub commitments: Vec<RowCommitment>,
+}
+
+impl InclusionProof {
+	pub fn new(
+		block_number: u32,
+		block_hash: H256,
+		query: &InclusionProofQuery,
+		cells: &[Cell],
+		commitments: &[[u8; config::COMMITMENT_SIZE]],
+	) -> Result<Self> {
+		let rows = cells
+			.iter()
+			.map(|cell| cell.position.row)
+			.collect::<BTreeSet<_>>();
+		let commitments = rows
+			.into_iter()
+			.map(|row| {
+				commitments
+					.get(row as usize)
+					.map(|&commitment| RowCommitment {
+						row,
+						commitment: Commitment(commitment),
+					})
+					.ok_or_else(|| eyre!("Commitment for row {row} doesn't exist"))
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(InclusionProof {
+			block_number,
+			block_hash,
+			app_id: query.app_id,
+			index: query.index,
+			cells: cells.iter().map(ProofCell::from).collect(),
+			commitments,
+		})
+	}
+}
+
+impl Reply for InclusionProof {
+	fn into_response(self) -> warp::reply::Response {
+		warp::reply::json(&self).into_response()
+	}
+}
+
+mod hex_prefixed {
+	use serde::{de, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&format!("0x{}", hex::encode(bytes)))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+		let s = String::deserialize(deserializer)?;
+		let s = s
+			.strip_prefix("0x")
+			.ok_or_else(|| de::Error::custom("Expected a hex string with 0x prefix"))?;
+		hex::decode(s).map_err(de::Error::custom)
+	}
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DataMessage {
 	block_number: u32,
@@ -522,6 +793,8 @@ pub struct DataTransaction {
 	data: Option<Base64>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	extrinsic: Option<Base64>,
+	/// Extrinsic hash, which matches the on-chain extrinsic hash
+	hash: H256,
 }
 
 impl TryFrom<Vec<u8>> for DataTransaction {
@@ -530,6 +803,7 @@ impl TryFrom<Vec<u8>> for DataTransaction {
 	fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
 		Ok(DataTransaction {
 			data: decode_app_data(&value)?.map(Base64),
+			hash: extrinsic_hash(&value),
 			extrinsic: Some(Base64(value)),
 		})
 	}
@@ -569,9 +843,28 @@ pub enum PublishMessage {
 	HeaderVerified(Box<HeaderMessage>),
 	ConfidenceAchieved(ConfidenceMessage),
 	DataVerified(DataMessage),
+	ConfidenceTimeout(ConfidenceTimeoutMessage),
 }
 
 impl PublishMessage {
+	pub fn topic(&self) -> Topic {
+		match self {
+			PublishMessage::HeaderVerified(_) => Topic::HeaderVerified,
+			PublishMessage::ConfidenceAchieved(_) => Topic::ConfidenceAchieved,
+			PublishMessage::DataVerified(_) => Topic::DataVerified,
+			PublishMessage::ConfidenceTimeout(_) => Topic::ConfidenceTimeout,
+		}
+	}
+
+	pub fn block_number(&self) -> u32 {
+		match self {
+			PublishMessage::HeaderVerified(message) => message.block_number,
+			PublishMessage::ConfidenceAchieved(message) => message.block_number,
+			PublishMessage::DataVerified(message) => message.block_number,
+			PublishMessage::ConfidenceTimeout(message) => message.block_number,
+		}
+	}
+
 	fn apply_filter(&mut self, fields: &HashSet<DataField>) {
 		match self {
 			PublishMessage::HeaderVerified(_) => (),
@@ -579,6 +872,7 @@ impl PublishMessage {
 			PublishMessage::DataVerified(data) => {
 				filter_fields(&mut data.data_transactions, fields)
 			},
+			PublishMessage::ConfidenceTimeout(_) => (),
 		}
 	}
 }
@@ -592,11 +886,133 @@ impl TryFrom<PublishMessage> for Message {
 	}
 }
 
+/// Latest schema version of pushed messages. Schema version 1 is the original format, without `schema_version` field.
+pub const LATEST_SCHEMA_VERSION: u8 = 2;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PayloadFormat {
+	#[default]
+	Verbose,
+	Compact,
+}
+
+#[derive(Deserialize)]
+struct MessageSchemaQuery {
+	schema_version: Option<u8>,
+	format: Option<PayloadFormat>,
+}
+
+/// Format of pushed messages, negotiated using `schema_version` and `format` query parameters
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(try_from = "MessageSchemaQuery")]
+pub struct MessageSchema {
+	pub schema_version: u8,
+	pub format: PayloadFormat,
+}
+
+impl Default for MessageSchema {
+	fn default() -> Self {
+		MessageSchema {
+			schema_version: 1,
+			format: PayloadFormat::Verbose,
+		}
+	}
+}
+
+impl TryFrom<MessageSchemaQuery> for MessageSchema {
+	type Error = String;
+
+	fn try_from(value: MessageSchemaQuery) -> Result<Self, Self::Error> {
+		let schema_version = value.schema_version.unwrap_or(1);
+		if schema_version == 0 || schema_version > LATEST_SCHEMA_VERSION {
+			return Err(format!("Unsupported schema version {schema_version}"));
+		}
+		let format = value.format.unwrap_or_default();
+		if schema_version == 1 && format == PayloadFormat::Compact {
+			return Err("Compact format requires schema version 2 or higher".to_string());
+		}
+		Ok(MessageSchema {
+			schema_version,
+			format,
+		})
+	}
+}
+
+#[derive(Serialize)]
+#[serde(tag = "topic", content = "message", rename_all = "kebab-case")]
+enum CompactMessage {
+	HeaderVerified {
+		block_number: u32,
+		hash: H256,
+		parent_hash: H256,
+	},
+	ConfidenceAchieved(ConfidenceMessage),
+	DataVerified {
+		block_number: u32,
+		data: Vec<Base64>,
+	},
+	ConfidenceTimeout(ConfidenceTimeoutMessage),
+}
+
+impl From<PublishMessage> for CompactMessage {
+	fn from(value: PublishMessage) -> Self {
+		match value {
+			PublishMessage::HeaderVerified(message) => CompactMessage::HeaderVerified {
+				block_number: message.block_number,
+				hash: message.header.hash,
+				parent_hash: message.header.parent_hash,
+			},
+			PublishMessage::ConfidenceAchieved(message) => {
+				CompactMessage::ConfidenceAchieved(message)
+			},
+			PublishMessage::DataVerified(message) => CompactMessage::DataVerified {
+				block_number: message.block_number,
+				data: message
+					.data_transactions
+					.into_iter()
+					.filter_map(|transaction| transaction.data)
+					.collect(),
+			},
+			PublishMessage::ConfidenceTimeout(message) => {
+				CompactMessage::ConfidenceTimeout(message)
+			},
+		}
+	}
+}
+
+#[derive(Serialize)]
+struct VersionedMessage<T: Serialize> {
+	schema_version: u8,
+	#[serde(flatten)]
+	message: T,
+}
+
+impl PublishMessage {
+	/// Serializes message to JSON, according to the negotiated schema
+	pub fn to_json(self, schema: &MessageSchema) -> Result<String> {
+		let schema_version = schema.schema_version;
+		match (schema_version, schema.format) {
+			(1, _) => serde_json::to_string(&self),
+			(_, PayloadFormat::Verbose) => serde_json::to_string(&VersionedMessage {
+				schema_version,
+				message: self,
+			}),
+			(_, PayloadFormat::Compact) => serde_json::to_string(&VersionedMessage {
+				schema_version,
+				message: CompactMessage::from(self),
+			}),
+		}
+		.wrap_err("Cannot serialize publish message")
+	}
+}
+
 pub type Sender = UnboundedSender<Result<ws::Message, warp::Error>>;
 
 pub struct WsClient {
 	pub subscription: Subscription,
 	pub sender: Option<Sender>,
+	pub schema: MessageSchema,
 }
 
 impl WsClient {
@@ -604,6 +1020,7 @@ impl WsClient {
 		WsClient {
 			subscription,
 			sender: None,
+			schema: MessageSchema::default(),
 		}
 	}
 
@@ -611,10 +1028,10 @@ impl WsClient {
 		self.subscription.topics.contains(topic)
 	}
 
-	fn sender_with_data_fields(&self) -> Option<(&Sender, &HashSet<DataField>)> {
+	fn sender_with_data_fields(&self) -> Option<(&Sender, &HashSet<DataField>, &MessageSchema)> {
 		self.sender
 			.as_ref()
-			.map(|sender| (sender, &self.subscription.data_fields))
+			.map(|sender| (sender, &self.subscription.data_fields, &self.schema))
 	}
 }
 
@@ -631,6 +1048,43 @@ impl WsClients {
 		Ok(())
 	}
 
+	pub async fn set_schema(&self, subscription_id: &str, schema: MessageSchema) -> Result<()> {
+		let mut clients = self.0.write().await;
+		let Some(client) = clients.get_mut(subscription_id) else {
+			return Err(eyre!("Client is not subscribed"));
+		};
+		client.schema = schema;
+		Ok(())
+	}
+
+	/// Returns subscribed topics of the client
+	pub async fn topics(&self, subscription_id: &str) -> Option<HashSet<Topic>> {
+		let clients = self.0.read().await;
+		clients
+			.get(subscription_id)
+			.map(|client| client.subscription.topics.clone())
+	}
+
+	/// Serializes messages of the subscribed topics for the client, according to its data fields and schema
+	pub async fn serialize(
+		&self,
+		subscription_id: &str,
+		messages: Vec<PublishMessage>,
+	) -> Result<Vec<ws::Message>> {
+		let clients = self.0.read().await;
+		let Some(client) = clients.get(subscription_id) else {
+			return Err(eyre!("Client is not subscribed"));
+		};
+		messages
+			.into_iter()
+			.filter(|message| client.is_subscribed(&message.topic()))
+			.map(|mut message| {
+				message.apply_filter(&client.subscription.data_fields);
+				message.to_json(&client.schema).map(ws::Message::text)
+			})
+			.collect()
+	}
+
 	pub async fn has_subscription(&self, subscription_id: &str) -> bool {
 		self.0.read().await.contains_key(subscription_id)
 	}
@@ -646,15 +1100,13 @@ impl WsClients {
 			.iter()
 			.filter(|(_, client)| client.is_subscribed(topic))
 			.flat_map(|(_, client)| client.sender_with_data_fields())
-			.map(|(sender, data_fields)| {
+			.map(|(sender, data_fields, schema)| {
 				let mut message = message.clone();
 				message.apply_filter(data_fields);
 				message
-					.try_into()
-					.wrap_err("Cannot convert to ws message")
-					.and_then(|message: warp::ws::Message| {
-						sender.send(Ok(message)).wrap_err("Send failed")
-					})
+					.to_json(schema)
+					.map(ws::Message::text)
+					.and_then(|message| sender.send(Ok(message)).wrap_err("Send failed"))
 			})
 			.collect::<Vec<_>>())
 	}
@@ -666,6 +1118,12 @@ impl Default for WsClients {
 	}
 }
 
+/// Query parameters of the WebSocket connection, requesting replay of the messages from the given block
+#[derive(Deserialize, Default)]
+pub struct ReplayQuery {
+	pub from_block: Option<u32>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SubscriptionId {
 	pub subscription_id: String,
@@ -818,13 +1276,14 @@ mod tests {
 
 	use crate::{
 		api::v2::types::{BlockStatus, Header, HeaderMessage, PublishMessage},
+		confidence::Confidence,
 		types::{OptionBlockRange, State},
 		utils::OptionalExtension,
 	};
 
 	use super::{
 		block_status, Base64, ConfidenceMessage, DataField, DataMessage, DataTransaction,
-		Subscription, Topic, WsClients,
+		MessageSchema, PayloadFormat, Subscription, Topic, WsClients,
 	};
 
 	fn subscription(topics: Vec<Topic>, fields: Vec<DataField>) -> Subscription {
@@ -860,7 +1319,7 @@ mod tests {
 	fn confidence_achieved() -> PublishMessage {
 		PublishMessage::ConfidenceAchieved(ConfidenceMessage {
 			block_number: 1,
-			confidence: Some(1.0),
+			confidence: Some(Confidence::new(1.0)),
 		})
 	}
 
@@ -874,6 +1333,7 @@ mod tests {
 			data_transactions: vec![DataTransaction {
 				data: transaction_data(),
 				extrinsic: transaction_data(),
+				hash: H256::default(),
 			}],
 		})
 	}
@@ -944,6 +1404,39 @@ mod tests {
 		};
 	}
 
+	async fn schema(path: &str) -> Result<MessageSchema, warp::Rejection> {
+		warp::test::request()
+			.path(path)
+			.filter(&warp::query::<MessageSchema>())
+			.await
+	}
+
+	#[tokio::test]
+	async fn publish_message_schema() {
+		assert_eq!(schema("/").await.unwrap(), MessageSchema::default());
+		assert!(schema("/?schema_version=3").await.is_err());
+		assert!(schema("/?format=compact").await.is_err());
+
+		let verbose = schema("/?schema_version=2").await.unwrap();
+		let compact = schema("/?schema_version=2&format=compact").await.unwrap();
+		assert_eq!(compact.format, PayloadFormat::Compact);
+
+		assert_eq!(
+			confidence_achieved()
+				.to_json(&MessageSchema::default())
+				.unwrap(),
+			r#"{"topic":"confidence-achieved","message":{"block_number":1,"confidence":1.0}}"#
+		);
+		assert_eq!(
+			confidence_achieved().to_json(&verbose).unwrap(),
+			r#"{"schema_version":2,"topic":"confidence-achieved","message":{"block_number":1,"confidence":1.0}}"#
+		);
+		assert_eq!(
+			data_verified().to_json(&compact).unwrap(),
+			r#"{"schema_version":2,"topic":"data-verified","message":{"block_number":1,"data":["AAECAwQ="]}}"#
+		);
+	}
+
 	struct ExtensionNone;
 
 	impl OptionalExtension for ExtensionNone {
@@ -1051,6 +1544,27 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn block_status_unverified_header() {
+		let mut state = State::default();
+		state.latest = 10;
+		state.header_unverified.set(5);
+		state.header_unverified.set(8);
+		assert_eq!(
+			block_status(&None, &state, 4, ExtensionSome),
+			Some(BlockStatus::Unavailable)
+		);
+		assert_eq!(
+			block_status(&None, &state, 6, ExtensionSome),
+			Some(BlockStatus::VerifyingHeader)
+		);
+		state.confidence_achieved.set(6);
+		assert_eq!(
+			block_status(&None, &state, 6, ExtensionSome),
+			Some(BlockStatus::VerifyingData)
+		);
+	}
+
 	#[test]
 	fn block_status_verifying_confidence() {
 		let mut state = State::default();
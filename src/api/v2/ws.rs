@@ -1,40 +1,166 @@
 use super::{
+	sse::MAX_REPLAY_BLOCKS,
 	transactions,
-	types::{Payload, Request, Response, Status, Version, WsClients, WsError, WsResponse},
+	types::{
+		ConfidenceMessage, MessageSchema, Payload, PublishMessage, Request, Response, Status,
+		Topic, Version, WsClients, WsError, WsResponse,
+	},
 };
 use crate::{
 	api::v2::types::{Error, Sender},
+	confidence::Confidence,
+	data::{Database, Key},
 	types::{RuntimeConfig, State},
 };
+use avail_subxt::primitives::Header;
 use color_eyre::{eyre::WrapErr, Result};
-use futures::{FutureExt, StreamExt};
-use serde::Serialize;
-use std::sync::{Arc, Mutex};
+use futures::{stream, FutureExt, StreamExt};
+use kate_recovery::com::AppData;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::HashSet,
+	sync::{Arc, Mutex},
+};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::UnboundedReceiverStream;
-use tracing::{error, log::warn};
+use tracing::{error, info, log::warn};
 use warp::ws::{self, Message, WebSocket};
 
+/// Returns messages of the given topics for the blocks from the given block to the latest one,
+/// read from the persistent store. Confidence timeouts are not persisted, so they are not replayed.
+pub fn replay(
+	from_block: u32,
+	topics: &HashSet<Topic>,
+	app_id: Option<u32>,
+	withheld_fraction: f64,
+	state: &Arc<Mutex<State>>,
+	db: &impl Database,
+) -> Result<Vec<PublishMessage>> {
+	let latest = state.lock().expect("Lock should be acquired").latest;
+	let first = from_block.max(latest.saturating_sub(MAX_REPLAY_BLOCKS - 1));
+
+	let mut messages = vec![];
+	for block_number in first..=latest {
+		if topics.contains(&Topic::HeaderVerified) {
+			if let Some(header) = db.get::<Header>(Key::BlockHeader(block_number))? {
+				messages.push(PublishMessage::HeaderVerified(Box::new(header.try_into()?)));
+			}
+		}
+		if topics.contains(&Topic::ConfidenceAchieved) {
+			if let Some(count) = db.get(Key::VerifiedCellCount(block_number))? {
+				let confidence = Confidence::from_cell_count(count, withheld_fraction);
+				let message = ConfidenceMessage::new(block_number, Some(confidence));
+				messages.push(PublishMessage::ConfidenceAchieved(message));
+			}
+		}
+		if let (true, Some(app_id)) = (topics.contains(&Topic::DataVerified), app_id) {
+			if let Some(data) = db.get::<AppData>(Key::AppData(app_id, block_number))? {
+				messages.push((block_number, data).try_into()?);
+			}
+		}
+	}
+	Ok(messages)
+}
+
+/// Topic and block number of the pushed message, used to skip live messages which were already replayed
+#[derive(Deserialize)]
+struct PushedMessage {
+	topic: Topic,
+	message: PushedBlock,
+}
+
+#[derive(Deserialize)]
+struct PushedBlock {
+	block_number: u32,
+}
+
+fn is_replayed(message: &ws::Message, replayed: &HashSet<(Topic, u32)>) -> bool {
+	serde_json::from_slice::<PushedMessage>(message.as_bytes())
+		.map(|pushed| replayed.contains(&(pushed.topic, pushed.message.block_number)))
+		.unwrap_or(false)
+}
+
+/// Serialized messages replayed to the client, with topics and block numbers of the replayed messages
+async fn replay_messages(
+	from_block: u32,
+	subscription_id: &str,
+	clients: &WsClients,
+	config: &RuntimeConfig,
+	state: &Arc<Mutex<State>>,
+	db: &impl Database,
+) -> Result<(Vec<ws::Message>, HashSet<(Topic, u32)>)> {
+	let topics = clients.topics(subscription_id).await.unwrap_or_default();
+	let messages = replay(
+		from_block,
+		&topics,
+		config.app_id,
+		config.withheld_fraction,
+		state,
+		db,
+	)?;
+	let replayed = messages
+		.iter()
+		.map(|message| (message.topic(), message.block_number()))
+		.collect::<HashSet<_>>();
+	let messages = clients.serialize(subscription_id, messages).await?;
+	Ok((messages, replayed))
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn connect(
 	subscription_id: String,
 	web_socket: WebSocket,
+	schema: MessageSchema,
+	from_block: Option<u32>,
 	clients: WsClients,
 	version: Version,
 	config: RuntimeConfig,
 	submitter: Option<Arc<impl transactions::Submit + Clone + Send + Sync + 'static>>,
 	state: Arc<Mutex<State>>,
+	db: impl Database + Sync,
 ) {
 	let (web_socket_sender, mut web_socket_receiver) = web_socket.split();
-	let (sender, receiver) = mpsc::unbounded_channel();
-	let receiver_stream = UnboundedReceiverStream::new(receiver);
+	let (sender, mut receiver) = mpsc::unbounded_channel();
 
 	if let Err(error) = clients.set_sender(&subscription_id, sender.clone()).await {
 		error!("Cannot set sender: {error}");
 		return;
 	};
 
-	tokio::task::spawn(receiver_stream.forward(web_socket_sender).map(|result| {
+	if let Err(error) = clients.set_schema(&subscription_id, schema).await {
+		error!("Cannot set message schema: {error}");
+		return;
+	};
+
+	// Sender is set before replaying, so live messages published in between are queued,
+	// and those which were already replayed are skipped
+	let (replay, replayed) = match from_block {
+		Some(from_block) => {
+			replay_messages(from_block, &subscription_id, &clients, &config, &state, &db)
+				.await
+				.unwrap_or_else(|error| {
+					error!("Cannot replay messages: {error:#}");
+					Default::default()
+				})
+		},
+		None => Default::default(),
+	};
+	if !replay.is_empty() {
+		info!("Replaying {} messages to the client", replay.len());
+	}
+	// Only the messages queued during the replay are checked, later messages are sent as they are
+	let mut queued = vec![];
+	if !replayed.is_empty() {
+		while let Ok(message) = receiver.try_recv() {
+			if !matches!(&message, Ok(message) if is_replayed(message, &replayed)) {
+				queued.push(message);
+			}
+		}
+	}
+	let stream = stream::iter(replay.into_iter().map(Ok).chain(queued))
+		.chain(UnboundedReceiverStream::new(receiver));
+
+	tokio::task::spawn(stream.forward(web_socket_sender).map(|result| {
 		if let Err(error) = result {
 			error!("Error sending web socket message: {error}");
 		}
@@ -6,6 +6,7 @@
 //!
 //! Get app data rows from node
 //! Verify commitment equality for each row
+//! Reconstruct missing rows from DHT, and verify commitment equality for each reconstructed row
 //! Decode app data and store it into local database under the `app_id:block_number` key
 //!
 //! # Notes
@@ -13,41 +14,67 @@
 //! If application client fails to run or stops its execution, error is logged, and other tasks continue with execution.
 use async_trait::async_trait;
 use avail_core::AppId;
-use avail_subxt::utils::H256;
+use avail_subxt::{primitives::Header, utils::H256};
 use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Result,
 };
 use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
 use kate_recovery::{
-	com::{
-		app_specific_rows, columns_positions, decode_app_extrinsics, reconstruct_columns, AppData,
-		Percent,
-	},
+	com::{decode_app_extrinsics, reconstruct_columns, AppData},
 	commitments,
 	config::{self, CHUNK_SIZE},
 	data::{Cell, DataCell},
-	matrix::{Dimensions, Position},
+	matrix::{Dimensions, Position, RowIndex},
 };
 use mockall::automock;
-use rand::SeedableRng as _;
+use rand::{seq::SliceRandom, Rng, SeedableRng as _};
 use rand_chacha::ChaChaRng;
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	fmt::{self, Display, Formatter},
 	ops::Range,
 	sync::{Arc, Mutex},
+	time::{Duration, Instant},
 };
 use tokio::sync::broadcast;
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
 use crate::{
+	app_storage,
+	block_state::{self, BlockState},
+	confidence::Confidence,
 	data::{Database, Key},
+	jobs::{self, Job, JobKind, JobQueue},
 	network::{p2p::Client as P2pClient, rpc::Client as RpcClient},
 	proof,
+	proof_bundle::{self, DisputeConfig},
 	shutdown::Controller,
-	types::{AppClientConfig, BlockVerified, OptionBlockRange, State},
+	telemetry::{MetricValue, Metrics},
+	types::{AppClientConfig, BlockVerified, ConfidenceTimeout, OptionBlockRange, State},
 };
 
+/// Interval in which verified cell count is polled while waiting for the app confidence threshold
+const CONFIDENCE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval in which retry job queue is polled for ready jobs
+const RETRY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Duration after which retry job is leased again, if it's not completed
+const RETRY_LEASE: Duration = Duration::from_secs(300);
+
+/// Priorities of the retry jobs, blocks from the sync range are retried after the latest blocks
+const RETRY_PRIORITY_LATEST: u8 = 1;
+const RETRY_PRIORITY_SYNC: u8 = 0;
+
+/// Rows reconstructed from the DHT, with the verified cells from which they are reconstructed
+#[derive(Default)]
+struct ReconstructedRows {
+	rows: Vec<(u32, Vec<u8>)>,
+	/// Fetched cells of the reconstructed rows and of their columns, which proofs are verified
+	cells: Vec<Cell>,
+}
+
 #[async_trait]
 #[automock]
 trait Client {
@@ -58,7 +85,7 @@ trait Client {
 		dimensions: Dimensions,
 		commitments: &[[u8; config::COMMITMENT_SIZE]],
 		missing_rows: &[u32],
-	) -> Result<Vec<(u32, Vec<u8>)>>;
+	) -> Result<ReconstructedRows>;
 
 	async fn fetch_rows_from_dht(
 		&self,
@@ -73,6 +100,8 @@ trait Client {
 		dimensions: Dimensions,
 		block_hash: H256,
 	) -> Result<Vec<Option<Vec<u8>>>>;
+
+	async fn insert_rows_into_dht(&self, block: u32, rows: Vec<(RowIndex, Vec<u8>)>) -> Result<()>;
 }
 
 #[derive(Clone)]
@@ -90,11 +119,11 @@ impl Client for AppClient {
 		dimensions: Dimensions,
 		commitments: &[[u8; config::COMMITMENT_SIZE]],
 		missing_rows: &[u32],
-	) -> Result<Vec<(u32, Vec<u8>)>> {
+	) -> Result<ReconstructedRows> {
 		let missing_cells = dimensions.extended_rows_positions(missing_rows);
 
 		if missing_cells.is_empty() {
-			return Ok(vec![]);
+			return Ok(ReconstructedRows::default());
 		}
 
 		debug!(
@@ -118,21 +147,62 @@ impl Client for AppClient {
 			unfetched.len()
 		);
 
-		let mut rng = ChaChaRng::from_seed(Default::default());
-		let missing_cells =
-			columns_positions(dimensions, &unfetched, Percent::from_percent(66), &mut rng);
+		let columns = unfetched
+			.iter()
+			.map(|position| position.col)
+			.collect::<BTreeSet<_>>();
+		// Verified cells of the missing rows are used for reconstruction of their columns as well
+		let mut column_cells = fetched
+			.iter()
+			.filter(|cell| columns.contains(&cell.position.col))
+			.cloned()
+			.collect::<Vec<_>>();
+		let mut tried = missing_cells
+			.iter()
+			.map(|position| (position.row, position.col))
+			.collect::<HashSet<_>>();
 
-		let (missing_fetched, _) = fetch_verified(
-			pp,
-			&self.p2p_client,
+		let mut rng = ChaChaRng::from_seed(Default::default());
+		let mut requested = 0;
+		loop {
+			let mut verified = columns
+				.iter()
+				.map(|&col| (col, 0))
+				.collect::<BTreeMap<_, _>>();
+			for cell in &column_cells {
+				verified
+					.entry(cell.position.col)
+					.and_modify(|count| *count += 1);
+			}
+			let positions = column_positions(dimensions, &verified, &tried, &mut rng);
+			if positions.is_empty() {
+				break;
+			}
+			requested += positions.len();
+			tried.extend(
+				positions
+					.iter()
+					.map(|position| (position.row, position.col)),
+			);
+			let (missing_fetched, _) = fetch_verified(
+				pp.clone(),
+				&self.p2p_client,
+				block_number,
+				dimensions,
+				commitments,
+				&positions,
+			)
+			.await?;
+			column_cells.extend(missing_fetched);
+		}
+		debug!(
 			block_number,
-			dimensions,
-			commitments,
-			&missing_cells,
-		)
-		.await?;
+			"Fetched {} of {requested} requested column cells for {} columns",
+			column_cells.len(),
+			columns.len()
+		);
 
-		let reconstructed = reconstruct_columns(dimensions, &missing_fetched)?;
+		let reconstructed = reconstruct_columns(dimensions, &column_cells)?;
 
 		debug!(
 			block_number,
@@ -152,6 +222,14 @@ impl Client for AppClient {
 			reconstructed_cells.len()
 		);
 
+		// Cells of the missing rows fetched for the column reconstruction are already in the fetched cells
+		let mut cells = fetched.clone();
+		cells.extend(
+			column_cells
+				.into_iter()
+				.filter(|cell| !missing_rows.contains(&cell.position.row)),
+		);
+
 		let mut data_cells: Vec<DataCell> = fetched.into_iter().map(Into::into).collect::<Vec<_>>();
 
 		data_cells.append(&mut reconstructed_cells);
@@ -160,7 +238,7 @@ impl Client for AppClient {
 			(a.position.row, a.position.col).cmp(&(b.position.row, b.position.col))
 		});
 
-		missing_rows
+		let rows = missing_rows
 			.iter()
 			.map(|&row| {
 				let data = data_cells
@@ -175,7 +253,9 @@ impl Client for AppClient {
 
 				Ok((row, data))
 			})
-			.collect::<Result<Vec<_>>>()
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(ReconstructedRows { rows, cells })
 	}
 
 	async fn fetch_rows_from_dht(
@@ -205,6 +285,10 @@ impl Client for AppClient {
 		}
 		Ok(result)
 	}
+
+	async fn insert_rows_into_dht(&self, block: u32, rows: Vec<(RowIndex, Vec<u8>)>) -> Result<()> {
+		self.p2p_client.insert_rows_into_dht(block, rows).await
+	}
 }
 
 fn new_data_cell(row: usize, col: usize, data: &[u8]) -> Result<DataCell> {
@@ -250,6 +334,32 @@ fn data_cell(
 		.ok_or_else(|| eyre!("Data cell not found"))
 }
 
+/// Selects positions of the cells to fetch, so each of the columns has enough verified cells for reconstruction.
+/// Column can be reconstructed from any half of its extended cells, so for each column, only the number of
+/// cells missing to half of the column is selected, from the positions which are not tried yet.
+fn column_positions(
+	dimensions: Dimensions,
+	verified: &BTreeMap<u16, usize>,
+	tried: &HashSet<(u32, u16)>,
+	rng: &mut impl Rng,
+) -> Vec<Position> {
+	let extended_rows = dimensions.extended_rows();
+	let required = extended_rows as usize / config::EXTENSION_FACTOR;
+	verified
+		.iter()
+		.flat_map(|(&col, &count)| {
+			let candidates = (0..extended_rows)
+				.filter(|&row| !tried.contains(&(row, col)))
+				.map(|row| Position { row, col })
+				.collect::<Vec<_>>();
+			candidates
+				.choose_multiple(rng, required.saturating_sub(count))
+				.copied()
+				.collect::<Vec<_>>()
+		})
+		.collect()
+}
+
 async fn fetch_verified(
 	pp: Arc<PublicParameters>,
 	p2p_client: &P2pClient,
@@ -273,6 +383,42 @@ async fn fetch_verified(
 	Ok((fetched, unfetched))
 }
 
+/// Reconstructed rows don't match the header commitments, although the cells used for reconstruction are verified
+#[derive(Debug, Clone, PartialEq)]
+pub struct BadEncoding {
+	pub block_number: u32,
+	pub rows: Vec<u32>,
+}
+
+impl Display for BadEncoding {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Bad encoding of block {}, commitments of reconstructed rows {:?} don't match",
+			self.block_number, self.rows
+		)
+	}
+}
+
+impl std::error::Error for BadEncoding {}
+
+/// Exports proof bundle with the cells fetched from the DHT, from which the rows with bad encoding are reconstructed.
+/// Proofs of the cells are verified against the header commitments, so the bundle shows that the commitments
+/// don't match the rows encoded by the cells.
+fn export_bad_encoding(
+	cfg: &DisputeConfig,
+	db: &impl Database,
+	block_number: u32,
+	cells: Vec<Cell>,
+	reason: String,
+) -> Result<()> {
+	let header = db
+		.get::<Header>(Key::BlockHeader(block_number))?
+		.ok_or_else(|| eyre!("Header of block {block_number} is not found"))?;
+	proof_bundle::spawn_export(cfg.clone(), header, cells, reason);
+	Ok(())
+}
+
 #[instrument(skip_all, fields(block = block.block_num), level = "trace")]
 async fn process_block(
 	client: impl Client,
@@ -290,7 +436,7 @@ async fn process_block(
 	let dimensions = extension.dimensions;
 	let commitments = &extension.commitments;
 
-	let app_rows = app_specific_rows(lookup, dimensions, app_id);
+	let app_rows = lookup.rows_for_app(dimensions, app_id.0);
 
 	debug!(
 		block_number,
@@ -305,8 +451,14 @@ async fn process_block(
 	let dht_rows_count = dht_rows.iter().flatten().count();
 	debug!(block_number, "Fetched {dht_rows_count} app rows from DHT");
 
-	let (dht_verified_rows, dht_missing_rows) =
-		commitments::verify_equality(&pp, commitments, &dht_rows, lookup, dimensions, app_id)?;
+	let (dht_verified_rows, dht_missing_rows) = commitments::verify_equality(
+		&pp,
+		commitments,
+		&dht_rows,
+		lookup.inner(),
+		dimensions,
+		app_id,
+	)?;
 	debug!(
 		block_number,
 		"Verified {} app rows from DHT, missing {}",
@@ -326,11 +478,38 @@ async fn process_block(
 			.await?
 	};
 
-	let (rpc_verified_rows, mut missing_rows) =
-		commitments::verify_equality(&pp, commitments, &rpc_rows, lookup, dimensions, app_id)?;
+	let (rpc_verified_rows, mut missing_rows) = commitments::verify_equality(
+		&pp,
+		commitments,
+		&rpc_rows,
+		lookup.inner(),
+		dimensions,
+		app_id,
+	)?;
 	// Since verify_equality returns all missing rows, exclude DHT rows that are already verified
 	missing_rows.retain(|row| !dht_verified_rows.contains(row));
 
+	if cfg.row_records && !rpc_verified_rows.is_empty() {
+		let verified_rows = rpc_verified_rows
+			.iter()
+			.filter_map(|&row| {
+				let data = rpc_rows.get(row as usize).cloned().flatten()?;
+				Some((RowIndex(row), data))
+			})
+			.collect::<Vec<_>>();
+		debug!(
+			block_number,
+			"Publishing {} app rows fetched from RPC into DHT",
+			verified_rows.len()
+		);
+		if let Err(error) = client
+			.insert_rows_into_dht(block_number, verified_rows)
+			.await
+		{
+			debug!(block_number, "Error inserting rows into DHT: {error}");
+		}
+	}
+
 	debug!(
 		block_number,
 		"Verified {} app rows from RPC, missing {}",
@@ -371,8 +550,17 @@ async fn process_block(
 		missing_rows.len()
 	);
 
-	let dht_rows = client
-		.reconstruct_rows_from_dht(pp, block_number, dimensions, commitments, &missing_rows)
+	let ReconstructedRows {
+		rows: dht_rows,
+		cells: dht_cells,
+	} = client
+		.reconstruct_rows_from_dht(
+			pp.clone(),
+			block_number,
+			dimensions,
+			commitments,
+			&missing_rows,
+		)
 		.await?;
 
 	debug!(
@@ -381,29 +569,137 @@ async fn process_block(
 		dht_rows.len()
 	);
 
+	// Cells of the reconstructed rows are verified, but the rows are not, so their commitments are checked before decoding
+	let mut reconstructed_rows = vec![None; rows.len()];
+	let mut reconstructed_indices = vec![];
 	for (row_index, row) in dht_rows {
 		let i: usize = row_index.try_into()?;
-		rows[i] = Some(row);
+		reconstructed_rows[i] = Some(row);
+		reconstructed_indices.push(row_index);
+	}
+	let (_, unverified_rows) = commitments::verify_equality(
+		&pp,
+		commitments,
+		&reconstructed_rows,
+		lookup.inner(),
+		dimensions,
+		app_id,
+	)?;
+	let bad_rows = reconstructed_indices
+		.into_iter()
+		.filter(|row| unverified_rows.contains(row))
+		.collect::<Vec<_>>();
+	if !bad_rows.is_empty() {
+		let bad_encoding = BadEncoding {
+			block_number,
+			rows: bad_rows,
+		};
+		if let Some(disputes) = &cfg.disputes {
+			// Cells of the bad rows, and the cells of the other rows used for reconstruction of their columns
+			let cells = dht_cells
+				.into_iter()
+				.filter(|cell| {
+					let row = cell.position.row;
+					bad_encoding.rows.contains(&row) || !missing_rows.contains(&row)
+				})
+				.collect::<Vec<_>>();
+			let reason = bad_encoding.to_string();
+			if let Err(error) = export_bad_encoding(disputes, &db, block_number, cells, reason) {
+				error!(block_number, "Cannot export proof bundle: {error:#}");
+			}
+		}
+		return Err(bad_encoding.into());
+	}
+
+	for (i, row) in reconstructed_rows.into_iter().enumerate() {
+		if row.is_some() {
+			rows[i] = row;
+		}
 	}
 
 	let data_cells = data_cells_from_rows(rows)
 		.wrap_err("Failed to create data cells from rows got from RPC")?;
 
-	let data = decode_app_extrinsics(lookup, dimensions, data_cells, app_id)
+	let data = decode_app_extrinsics(lookup.inner(), dimensions, data_cells, app_id)
 		.wrap_err("Failed to decode app extrinsics")?;
 
 	debug!(block_number, "Storing data into database");
 
 	// store encoded App Data into the database
-	db.put(Key::AppData(app_id.0, block_number), data.clone())
-		.wrap_err("App Client failed to store App Data into database")?;
+	let usage = app_storage::store(&db, app_id.0, block_number, &data, cfg.storage_quota)?;
 
 	let bytes_count = data.iter().fold(0usize, |acc, x| acc + x.len());
-	debug!(block_number, "Stored {bytes_count} bytes into database");
+	debug!(
+		block_number,
+		stored_bytes = usage.stored_bytes,
+		"Stored {bytes_count} bytes into database"
+	);
 
 	Ok(data)
 }
 
+fn reached(confidence: Option<Confidence>, threshold: Confidence) -> bool {
+	confidence.is_some_and(|confidence| confidence.reaches(threshold))
+}
+
+/// Waits until block confidence reaches the threshold, polling verified cell count from the database.
+/// Returns last known confidence on timeout.
+async fn wait_for_confidence(
+	db: &impl Database,
+	block_number: u32,
+	confidence: Option<Confidence>,
+	threshold: Confidence,
+	timeout: Duration,
+	withheld_fraction: f64,
+) -> Result<(), Option<Confidence>> {
+	let deadline = Instant::now() + timeout;
+	let mut confidence = confidence;
+	while !reached(confidence, threshold) {
+		let now = Instant::now();
+		if now >= deadline {
+			return Err(confidence);
+		}
+		tokio::time::sleep(CONFIDENCE_POLL_INTERVAL.min(deadline - now)).await;
+		match db.get::<u32>(Key::VerifiedCellCount(block_number)) {
+			Ok(Some(count)) => {
+				confidence = Some(Confidence::from_cell_count(count, withheld_fraction))
+			},
+			Ok(None) => (),
+			Err(error) => debug!(block_number, "Cannot get verified cell count: {error}"),
+		}
+	}
+	Ok(())
+}
+
+/// Waits for the next ready retry job, and creates block from the stored header.
+/// Jobs for blocks without stored header are dropped.
+async fn next_retry(
+	queue: &JobQueue<impl Database>,
+	db: &impl Database,
+) -> Result<(BlockVerified, Job)> {
+	loop {
+		let Some(job) = queue.lease(jobs::now())? else {
+			tokio::time::sleep(RETRY_POLL_INTERVAL).await;
+			continue;
+		};
+		let JobKind::ReconstructAppData { block_number } = job.kind else {
+			warn!(job_id = job.id, "Dropping job of unexpected kind");
+			queue.complete(job.id)?;
+			continue;
+		};
+		let Some(header) = db.get::<Header>(Key::BlockHeader(block_number))? else {
+			warn!(
+				block_number,
+				"Dropping retry of block without stored header"
+			);
+			queue.complete(job.id)?;
+			continue;
+		};
+		let block = BlockVerified::try_from((header, None))?;
+		return Ok((block, job));
+	}
+}
+
 /// Runs application client.
 ///
 /// # Arguments
@@ -415,6 +711,9 @@ async fn process_block(
 /// * `app_id` - Application ID
 /// * `block_receive` - Channel used to receive header of verified block
 /// * `pp` - Public parameters (i.e. SRS) needed for proof verification
+/// * `confidence_timeout_sender` - Channel used to notify blocks which didn't reach the app confidence threshold
+///
+/// Failed reconstructions are scheduled into the persistent job queue, and retried with configured backoff.
 #[allow(clippy::too_many_arguments)]
 pub async fn run(
 	cfg: AppClientConfig,
@@ -427,6 +726,8 @@ pub async fn run(
 	state: Arc<Mutex<State>>,
 	sync_range: Range<u32>,
 	data_verified_sender: broadcast::Sender<(u32, AppData)>,
+	confidence_timeout_sender: broadcast::Sender<ConfidenceTimeout>,
+	metrics: Arc<impl Metrics>,
 	shutdown: Controller<String>,
 ) {
 	info!("Starting for app {app_id}...");
@@ -439,20 +740,40 @@ pub async fn run(
 		let mut state = state.lock().expect("State lock can be acquired");
 		match sync_range.contains(&block_number) {
 			true => state.sync_data_verified.set(block_number),
-			false => state.data_verified.set(block_number),
+			false => state.data_verified.advance(block_number),
 		}
 		if state.synced == Some(false) && sync_range.clone().last() == Some(block_number) {
 			state.synced.replace(true);
 		};
 	}
 
+	// in-flight block processing is cancelled on shutdown
+	let shutdown_token = shutdown.cancellation_token();
+
+	let retry_queue = JobQueue::new(
+		"app_client",
+		db.clone(),
+		cfg.retry_config.clone(),
+		RETRY_LEASE,
+	);
+
 	loop {
-		let block = match block_receive.recv().await {
-			Ok(block) => block,
-			Err(error) => {
-				error!("Cannot receive message: {error}");
-				let _ = shutdown.trigger_shutdown(format!("Cannot receive message: {error:#}"));
-				return;
+		let (block, retry) = tokio::select! {
+			received = block_receive.recv() => match received {
+				Ok(block) => (block, None),
+				Err(error) => {
+					error!("Cannot receive message: {error}");
+					let _ = shutdown.trigger_shutdown(format!("Cannot receive message: {error:#}"));
+					return;
+				},
+			},
+			retry = next_retry(&retry_queue, &db) => match retry {
+				Ok((block, job)) => (block, Some(job)),
+				Err(error) => {
+					error!("Cannot get retry job: {error:#}");
+					let _ = shutdown.trigger_shutdown(format!("Cannot get retry job: {error:#}"));
+					return;
+				},
 			},
 		};
 
@@ -460,35 +781,144 @@ pub async fn run(
 		let Some(extension) = &block.extension else {
 			info!(block_number, "Skipping block without header extension");
 			set_data_verified_state(state.clone(), &sync_range, block_number);
+			block_state::update(&db, block_number, BlockState::Finalized);
 			continue;
 		};
 		let dimensions = &extension.dimensions;
 
 		info!(block_number, "Block available: {dimensions:?}");
 
-		if extension.lookup.range_of(app_id).is_none() {
+		if extension.lookup.range_of(app_id.0).is_none() {
 			info!(
 				block_number,
 				"Skipping block with no cells for app {app_id}"
 			);
 			set_data_verified_state(state.clone(), &sync_range, block_number);
+			block_state::update(&db, block_number, BlockState::Finalized);
 			continue;
 		}
 
+		// Retried blocks already passed the confidence threshold
+		if let Some(threshold) = cfg.confidence_threshold.filter(|_| retry.is_none()) {
+			if let Err(confidence) = wait_for_confidence(
+				&db,
+				block_number,
+				block.confidence,
+				threshold,
+				cfg.confidence_timeout,
+				cfg.withheld_fraction,
+			)
+			.await
+			{
+				warn!(
+					block_number,
+					?confidence,
+					%threshold,
+					"Skipping block which didn't reach confidence threshold of app {app_id}"
+				);
+				let timeout = ConfidenceTimeout {
+					block_num: block_number,
+					app_id: app_id.0,
+					confidence,
+					threshold,
+				};
+				// Sending fails only if there are no subscribers
+				let _ = confidence_timeout_sender.send(timeout);
+				let reason = format!("Confidence threshold {threshold} not reached");
+				block_state::update(&db, block_number, BlockState::Failed(reason));
+				continue;
+			}
+		}
+
+		// Retried blocks are already counted
+		if retry.is_none() {
+			metrics
+				.record(MetricValue::AppBlocksWithData(app_id.0))
+				.await;
+		}
+
 		let app_client = AppClient {
 			p2p_client: network_client.clone(),
 			rpc_client: rpc_client.clone(),
 		};
-		let data =
-			match process_block(app_client, db.clone(), &cfg, app_id, &block, pp.clone()).await {
-				Ok(data) => data,
-				Err(error) => {
-					error!(block_number, "Cannot process block: {error}");
-					let _ = shutdown.trigger_shutdown(format!("Cannot process block: {error:#}"));
-					return;
-				},
-			};
+		let begin = Instant::now();
+		let process_block_result = tokio::select! {
+			result = process_block(app_client, db.clone(), &cfg, app_id, &block, pp.clone()) => result,
+			_ = shutdown_token.cancelled() => {
+				info!(block_number, "Block processing is cancelled");
+				// queries of the dropped fetches are still running in the event loop
+				if let Err(error) = network_client.finish_abandoned_queries().await {
+					debug!("Cannot finish abandoned DHT queries: {error:#}");
+				}
+				return;
+			},
+		};
+		let data = match process_block_result {
+			Ok(data) => {
+				let bytes = data.iter().map(|extrinsic| extrinsic.len() as u64).sum();
+				let latency = begin.elapsed().as_secs_f64();
+				metrics
+					.record(MetricValue::AppBytesReconstructed(app_id.0, bytes))
+					.await;
+				metrics
+					.record(MetricValue::AppReconstructionLatency(app_id.0, latency))
+					.await;
+				data
+			},
+			Err(error) => {
+				error!(block_number, "Cannot process block: {error:#}");
+				metrics
+					.record(MetricValue::AppReconstructionFailures(app_id.0))
+					.await;
+				// Bad encoding is a property of the block, so retrying reconstruction doesn't help
+				if let Some(bad_encoding) = error.downcast_ref::<BadEncoding>() {
+					warn!(block_number, rows = ?bad_encoding.rows, "Bad encoding detected");
+					metrics.record(MetricValue::AppBadEncodings(app_id.0)).await;
+					if let Some(job) = &retry {
+						if let Err(error) = retry_queue.complete(job.id) {
+							error!(block_number, "Cannot complete retry job: {error:#}");
+						}
+					}
+					block_state::update(&db, block_number, BlockState::Failed(error.to_string()));
+					continue;
+				}
+				let scheduled = match &retry {
+					Some(job) => retry_queue
+						.fail(job.id, jobs::now())
+						.map(|delay| delay.is_some()),
+					None => {
+						let priority = match sync_range.contains(&block_number) {
+							true => RETRY_PRIORITY_SYNC,
+							false => RETRY_PRIORITY_LATEST,
+						};
+						let kind = JobKind::ReconstructAppData { block_number };
+						retry_queue.push(kind, priority).map(|_| true)
+					},
+				};
+				match scheduled {
+					Ok(true) => info!(block_number, "Block reconstruction is scheduled for retry"),
+					Ok(false) => {
+						let reason = format!("Cannot process block: {error:#}");
+						block_state::update(&db, block_number, BlockState::Failed(reason));
+					},
+					Err(error) => {
+						error!(block_number, "Cannot schedule retry: {error:#}");
+						let _ =
+							shutdown.trigger_shutdown(format!("Cannot schedule retry: {error:#}"));
+						return;
+					},
+				}
+				continue;
+			},
+		};
+		if let Some(job) = &retry {
+			if let Err(error) = retry_queue.complete(job.id) {
+				error!(block_number, "Cannot complete retry job: {error:#}");
+			}
+		}
 		set_data_verified_state(state.clone(), &sync_range, block_number);
+		block_state::update(&db, block_number, BlockState::AppDataReconstructed);
+		block_state::update(&db, block_number, BlockState::Finalized);
 		if let Err(error) = data_verified_sender.send((block_number, data)) {
 			error!("Cannot send data verified message: {error}");
 			let _ =
@@ -504,9 +934,9 @@ mod tests {
 	use super::*;
 	use crate::{
 		data::mem_db,
-		types::{AppClientConfig, Extension, RuntimeConfig},
+		types::{AppClientConfig, DataLookup, Extension, RuntimeConfig},
+		utils::DEFAULT_WITHHELD_FRACTION,
 	};
-	use avail_core::DataLookup;
 	use hex_literal::hex;
 	use kate_recovery::{matrix::Dimensions, testnet};
 
@@ -524,8 +954,7 @@ mod tests {
 		]
 		.to_vec();
 
-		let id_lens: Vec<(u32, usize)> = vec![(0, 1), (1, 69)];
-		let lookup = DataLookup::from_id_and_len_iter(id_lens.into_iter()).unwrap();
+		let lookup = DataLookup::from_id_and_len(&[(0, 1), (1, 69)]).unwrap();
 		let block = BlockVerified {
 			header_hash: hex!("ec30fcc1f32db0f51ce6305c2601089741ea0b42853f402194b49b04bf936338")
 				.into(),
@@ -560,7 +989,7 @@ mod tests {
 		}
 		mock_client
 			.expect_reconstruct_rows_from_dht()
-			.returning(|_, _, _, _, _| Box::pin(async move { Ok(vec![]) }));
+			.returning(|_, _, _, _, _| Box::pin(async move { Ok(ReconstructedRows::default()) }));
 
 		process_block(mock_client, db, &cfg, AppId(1), &block, pp)
 			.await
@@ -581,8 +1010,7 @@ mod tests {
 		]
 		.to_vec();
 
-		let id_lens: Vec<(u32, usize)> = vec![(0, 1), (1, 11)];
-		let lookup = DataLookup::from_id_and_len_iter(id_lens.into_iter()).unwrap();
+		let lookup = DataLookup::from_id_and_len(&[(0, 1), (1, 11)]).unwrap();
 		let block = BlockVerified {
 			header_hash: hex!("5bc959e1d05c68f7e1b5bc3a83cfba4efe636ce7f86102c30bcd6a2794e75afe")
 				.into(),
@@ -621,13 +1049,174 @@ mod tests {
 					let kate_rows_clone = kate_rows.clone();
 					Box::pin(async move { Ok(kate_rows_clone) })
 				});
+			// Rows fetched from RPC are published as row records by default
+			mock_client
+				.expect_insert_rows_into_dht()
+				.returning(|_, _| Box::pin(async move { Ok(()) }));
 		}
 		mock_client
 			.expect_reconstruct_rows_from_dht()
-			.returning(|_, _, _, _, _| Box::pin(async move { Ok(vec![]) }));
+			.returning(|_, _, _, _, _| Box::pin(async move { Ok(ReconstructedRows::default()) }));
 
 		process_block(mock_client, db, &cfg, AppId(1), &block, pp)
 			.await
 			.unwrap();
 	}
+
+	const KATE_ROW_288: [u8; 512] = hex!("042c280403000ba3fa0ab887018000000000000000000000000000000000000004d904d1048400d43593c715fdd31c61141abd04a99fd6822c8558854ccde3009a5684e7a56da27d01a8cf58e1e9c735f93ebc7a94086aa27cfd77db173aac00803895886b8a4f49e85c68f469d570f0ed992750bf95329bb90ef56b45abcd009fedef0d9cbdd61c05a181d4013800041d0121033036343265356430346236003632353966363635666431353361613136646637343066323533373237386600613139316565393630343862663839393733343961303137353865346237610032643539663534353338393865626231643233626634353965363637613633003462313663663432326663393335336434623862623630386235393230653400353733663335663037303764333238616661343832316663656631363439660039643532653762353732356533303935643865656561356436633235333830006434658000000000000000000000000000000000000000000000000000000000346080be83f48ad1748c4ad339abdcb803368efdd1f65689619ff8c208755d0084eefcf837b61c479b3332059bc8e89b490a9d502baecaed448433d4e161710000a71cbb1a0387598e509d9fcab511022f437b0caf13591315c3f1bbf04f18009d83f014806210da6ee1d2f80cf0f9c08f1d132be042769015f6174fd2b24c00");
+
+	fn block_288(dimensions: Dimensions) -> BlockVerified {
+		let lookup = DataLookup::from_id_and_len(&[(0, 1), (1, 11)]).unwrap();
+		BlockVerified {
+			header_hash: hex!("5bc959e1d05c68f7e1b5bc3a83cfba4efe636ce7f86102c30bcd6a2794e75afe")
+				.into(),
+			block_num: 288,
+			extension: Some(Extension {
+				dimensions,
+				lookup,
+				commitments: [
+					[
+						165, 227, 207, 130, 59, 77, 78, 242, 184, 232, 114, 218, 145, 167, 149, 53,
+						89, 7, 230, 49, 85, 113, 218, 116, 43, 195, 144, 203, 149, 114, 106, 89,
+						73, 164, 17, 163, 3, 145, 173, 6, 119, 222, 17, 60, 251, 215, 40, 192,
+					],
+					[
+						165, 227, 207, 130, 59, 77, 78, 242, 184, 232, 114, 218, 145, 167, 149, 53,
+						89, 7, 230, 49, 85, 113, 218, 116, 43, 195, 144, 203, 149, 114, 106, 89,
+						73, 164, 17, 163, 3, 145, 173, 6, 119, 222, 17, 60, 251, 215, 40, 192,
+					],
+				]
+				.to_vec(),
+			}),
+			confidence: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_process_block_publishes_row_records() {
+		let cfg = AppClientConfig::from(&RuntimeConfig::default());
+		assert!(cfg.row_records);
+		let pp = Arc::new(testnet::public_params(1024));
+		let dimensions: Dimensions = Dimensions::new(1, 16).unwrap();
+		let mut mock_client = MockClient::new();
+		let db = mem_db::MemoryDB::default();
+		mock_client
+			.expect_fetch_rows_from_dht()
+			.returning(|_, _, _| Box::pin(async move { vec![None, None] }));
+		mock_client.expect_get_kate_rows().returning(|_, _, _| {
+			Box::pin(async move { Ok(vec![Some(KATE_ROW_288.to_vec()), None]) })
+		});
+		mock_client
+			.expect_insert_rows_into_dht()
+			.withf(|&block, rows| {
+				block == 288 && rows.len() == 1 && rows[0].0 .0 == 0 && rows[0].1 == KATE_ROW_288
+			})
+			.times(1)
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
+		mock_client
+			.expect_reconstruct_rows_from_dht()
+			.returning(|_, _, _, _, _| Box::pin(async move { Ok(ReconstructedRows::default()) }));
+
+		process_block(mock_client, db, &cfg, AppId(1), &block_288(dimensions), pp)
+			.await
+			.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_process_block_bad_encoding() {
+		let mut cfg = AppClientConfig::from(&RuntimeConfig::default());
+		cfg.disable_rpc = true;
+		let pp = Arc::new(testnet::public_params(1024));
+		let dimensions: Dimensions = Dimensions::new(1, 16).unwrap();
+		let mut mock_client = MockClient::new();
+		let db = mem_db::MemoryDB::default();
+		mock_client
+			.expect_fetch_rows_from_dht()
+			.returning(|_, _, _| Box::pin(async move { vec![None, None] }));
+		mock_client.expect_get_kate_rows().never();
+		mock_client
+			.expect_reconstruct_rows_from_dht()
+			.returning(|_, _, _, _, _| {
+				let mut row = KATE_ROW_288.to_vec();
+				row[0] ^= 1;
+				Box::pin(async move {
+					Ok(ReconstructedRows {
+						rows: vec![(0, row)],
+						cells: vec![],
+					})
+				})
+			});
+
+		let error = process_block(mock_client, db, &cfg, AppId(1), &block_288(dimensions), pp)
+			.await
+			.unwrap_err();
+		assert_eq!(
+			error.downcast_ref::<BadEncoding>(),
+			Some(&BadEncoding {
+				block_number: 288,
+				rows: vec![0]
+			})
+		);
+	}
+
+	#[test]
+	fn test_column_positions() {
+		let dimensions = Dimensions::new(2, 4).unwrap();
+		let mut rng = ChaChaRng::from_seed(Default::default());
+		let verified = BTreeMap::from([(1, 0), (2, 1), (3, 2)]);
+		let tried = HashSet::from([(0, 1), (1, 2)]);
+
+		let positions = column_positions(dimensions, &verified, &tried, &mut rng);
+		let count = |col| positions.iter().filter(|p| p.col == col).count();
+		// Half of the 4 extended rows is required for reconstruction
+		assert_eq!((count(1), count(2), count(3)), (2, 1, 0));
+		assert!(positions
+			.iter()
+			.all(|position| !tried.contains(&(position.row, position.col))));
+
+		// Only untried positions are left to select
+		let tried = HashSet::from([(0, 1), (1, 1), (2, 1)]);
+		let verified = BTreeMap::from([(1, 0)]);
+		let positions = column_positions(dimensions, &verified, &tried, &mut rng);
+		assert_eq!(positions, vec![Position { row: 3, col: 1 }]);
+	}
+
+	#[tokio::test]
+	async fn test_wait_for_confidence() {
+		let db = mem_db::MemoryDB::default();
+		let timeout = Duration::from_millis(10);
+		let threshold = Confidence::new(95.0);
+		assert!(wait_for_confidence(
+			&db,
+			1,
+			Some(Confidence::new(99.0)),
+			threshold,
+			timeout,
+			DEFAULT_WITHHELD_FRACTION
+		)
+		.await
+		.is_ok());
+		assert_eq!(
+			wait_for_confidence(
+				&db,
+				1,
+				Some(Confidence::new(90.0)),
+				threshold,
+				timeout,
+				DEFAULT_WITHHELD_FRACTION
+			)
+			.await,
+			Err(Some(Confidence::new(90.0)))
+		);
+		assert_eq!(
+			wait_for_confidence(&db, 1, None, threshold, timeout, DEFAULT_WITHHELD_FRACTION).await,
+			Err(None)
+		);
+		db.put(Key::VerifiedCellCount(1), 10u32).unwrap();
+		assert!(
+			wait_for_confidence(&db, 1, None, threshold, timeout, DEFAULT_WITHHELD_FRACTION)
+				.await
+				.is_ok()
+		);
+	}
 }
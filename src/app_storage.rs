@@ -0,0 +1,141 @@
+//! Accounting and quotas of the app data stored by the app client.
+//!
+//! Number of reconstructed and stored bytes is tracked per app. If storage quota is configured
+//! for the app, data of the oldest blocks is pruned after new data is stored, until stored bytes fit the quota.
+//! Data of the latest stored block is never pruned, even if it exceeds the quota by itself.
+
+use color_eyre::{eyre::WrapErr, Result};
+use tracing::{debug, warn};
+
+use crate::data::{AppDataUsage, Database, Key};
+
+fn size(data: &[Vec<u8>]) -> u64 {
+	data.iter().map(|extrinsic| extrinsic.len() as u64).sum()
+}
+
+/// Stores app data of the block, updates app data usage and prunes the oldest data above the quota
+pub fn store(
+	db: &impl Database,
+	app_id: u32,
+	block_number: u32,
+	data: &[Vec<u8>],
+	quota: Option<u64>,
+) -> Result<AppDataUsage> {
+	let mut usage = db
+		.get::<AppDataUsage>(Key::AppDataUsage(app_id))?
+		.unwrap_or_default();
+
+	// Data of the retried blocks is replaced
+	if let Some(previous) = db.get::<Vec<Vec<u8>>>(Key::AppData(app_id, block_number))? {
+		usage.stored_bytes = usage.stored_bytes.saturating_sub(size(&previous));
+		usage.stored_blocks = usage.stored_blocks.saturating_sub(1);
+	}
+
+	db.put(Key::AppData(app_id, block_number), data.to_vec())
+		.wrap_err("App Client failed to store App Data into database")?;
+
+	let bytes = size(data);
+	usage.reconstructed_bytes += bytes;
+	usage.stored_bytes += bytes;
+	usage.stored_blocks += 1;
+	usage.oldest_block = Some(
+		usage
+			.oldest_block
+			.map_or(block_number, |oldest| oldest.min(block_number)),
+	);
+
+	if let Some(quota) = quota {
+		prune(db, app_id, block_number, quota, &mut usage)?;
+	}
+
+	db.put(Key::AppDataUsage(app_id), usage.clone())
+		.wrap_err("Failed to store app data usage")?;
+	Ok(usage)
+}
+
+/// Deletes data of the oldest blocks before the given block, until stored bytes fit the quota
+fn prune(
+	db: &impl Database,
+	app_id: u32,
+	block_number: u32,
+	quota: u64,
+	usage: &mut AppDataUsage,
+) -> Result<()> {
+	let Some(oldest) = usage.oldest_block else {
+		return Ok(());
+	};
+	let mut next = oldest;
+	while usage.stored_bytes > quota && next < block_number {
+		if let Some(data) = db.get::<Vec<Vec<u8>>>(Key::AppData(app_id, next))? {
+			db.delete(Key::AppData(app_id, next))?;
+			usage.stored_bytes = usage.stored_bytes.saturating_sub(size(&data));
+			usage.stored_blocks = usage.stored_blocks.saturating_sub(1);
+			usage.pruned_blocks += 1;
+			debug!(
+				app_id,
+				block_number = next,
+				"Pruned app data above the quota"
+			);
+		}
+		next += 1;
+	}
+	usage.oldest_block = Some(next);
+	if usage.stored_bytes > quota {
+		warn!(
+			app_id,
+			block_number,
+			stored_bytes = usage.stored_bytes,
+			quota,
+			"App data of the latest block exceeds the storage quota"
+		);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::store;
+	use crate::data::{mem_db::MemoryDB, Database, Key};
+
+	#[test]
+	fn usage_and_quota() {
+		let db = MemoryDB::default();
+		let data = |length: usize| vec![vec![0u8; length]];
+
+		let usage = store(&db, 1, 10, &data(100), None).unwrap();
+		assert_eq!(usage.stored_bytes, 100);
+		assert_eq!(usage.oldest_block, Some(10));
+
+		// Retried block replaces stored data
+		let usage = store(&db, 1, 10, &data(120), None).unwrap();
+		assert_eq!(usage.reconstructed_bytes, 220);
+		assert_eq!(usage.stored_bytes, 120);
+		assert_eq!(usage.stored_blocks, 1);
+
+		store(&db, 1, 12, &data(100), Some(300)).unwrap();
+		let usage = store(&db, 1, 13, &data(100), Some(300)).unwrap();
+		assert_eq!(usage.stored_bytes, 220);
+		assert_eq!(usage.stored_blocks, 2);
+		assert_eq!(usage.pruned_blocks, 1);
+		assert_eq!(usage.oldest_block, Some(11));
+		assert!(db
+			.get::<Vec<Vec<u8>>>(Key::AppData(1, 10))
+			.unwrap()
+			.is_none());
+		assert!(db
+			.get::<Vec<Vec<u8>>>(Key::AppData(1, 12))
+			.unwrap()
+			.is_some());
+
+		// Latest block is kept even if it exceeds the quota
+		let usage = store(&db, 1, 14, &data(500), Some(300)).unwrap();
+		assert_eq!(usage.stored_bytes, 500);
+		assert_eq!(usage.stored_blocks, 1);
+		assert_eq!(usage.pruned_blocks, 3);
+
+		// Usage is tracked per app
+		let usage = store(&db, 2, 14, &data(10), Some(300)).unwrap();
+		assert_eq!(usage.stored_bytes, 10);
+		assert_eq!(usage.pruned_blocks, 0);
+	}
+}
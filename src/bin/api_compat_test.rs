@@ -2,7 +2,7 @@ use avail_light::{
 	data::rocks_db::RocksDB,
 	network::rpc,
 	shutdown::Controller,
-	types::{ExponentialConfig, RetryConfig, State},
+	types::{ConnectionConfig, ExponentialConfig, KeepAliveConfig, RetryConfig, State},
 };
 use clap::Parser;
 use color_eyre::{eyre::Context, Result};
@@ -33,8 +33,17 @@ async fn main() -> Result<()> {
 	});
 
 	let shutdown = Controller::new();
-	let (rpc_client, _, subscriptions) =
-		rpc::init(db, state, &[command_args.url], "DEV", retry_cfg, shutdown).await?;
+	let (rpc_client, _, subscriptions) = rpc::init(
+		db,
+		state,
+		&[command_args.url],
+		"DEV",
+		retry_cfg,
+		KeepAliveConfig::default(),
+		ConnectionConfig::default(),
+		shutdown,
+	)
+	.await?;
 	tokio::spawn(subscriptions.run());
 
 	let mut correct: bool = true;
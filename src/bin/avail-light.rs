@@ -2,16 +2,45 @@
 
 use avail_core::AppId;
 use avail_light::{
-	api,
+	api, block_time,
+	bulk_verification::BulkVerification,
+	confidence::Confidence,
 	consts::EXPECTED_SYSTEM_VERSION,
-	data::rocks_db::RocksDB,
+	data::{
+		encryption::{EncryptedDB, EncryptionSecret},
+		maintenance as db_maintenance,
+		rocks_db::RocksDB,
+		Database,
+	},
+	data_dir::DataDir,
+	default_config,
+	event_bus::{self, Event, EventBus},
+	export::{ExportConfig, ExportTarget},
+	export_gate,
+	features::{self, Feature, Features},
 	maintenance::StaticConfigParams,
-	network::{self, p2p, rpc},
+	network::{
+		self,
+		header_source::{self, HeaderSource, HeaderSourceKind},
+		p2p,
+		rpc::{self, header_mapping::HeaderMapping},
+	},
+	proof_bundle::{self, ProofBundle},
+	reconciliation::{self, Reconciliation},
+	self_test,
+	service::{self, PidFile},
 	shutdown::Controller,
+	supervisor::{self, Supervisor},
 	sync_client::SyncClient,
 	sync_finality::SyncFinality,
-	telemetry::{self, otlp::MetricAttributes, MetricCounter, Metrics},
-	types::{CliOpts, IdentityConfig, LibP2PConfig, Network, OtelConfig, RuntimeConfig, State},
+	systemd,
+	telemetry::{self, otlp::MetricAttributes, reporter, MetricCounter, Metrics},
+	types::{
+		self, CliOpts, Command, ConnectionConfig, DbCommand, IdentityConfig, KeepAliveConfig,
+		LibP2PConfig, MissedBlockConfig, Network, OtelConfig, OutputFormat, RuntimeConfig, State,
+		TelemetryReportConfig,
+	},
+	utils,
 };
 use clap::Parser;
 use color_eyre::{
@@ -24,14 +53,23 @@ use std::{
 	fs,
 	net::Ipv4Addr,
 	path::Path,
-	sync::{Arc, Mutex},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	time::Duration,
 };
 use tokio::sync::{broadcast, mpsc};
 use tracing::{error, info, metadata::ParseLevelError, trace, warn, Level, Subscriber};
-use tracing_subscriber::{fmt::format, EnvFilter, FmtSubscriber};
+use tracing_subscriber::{
+	fmt::{format, writer::BoxMakeWriter},
+	EnvFilter, FmtSubscriber,
+};
 
 #[cfg(feature = "network-analysis")]
 use avail_light::network::p2p::analyzer;
+#[cfg(feature = "tui")]
+use avail_light::tui;
 
 #[cfg(not(target_env = "msvc"))]
 use tikv_jemallocator::Jemalloc;
@@ -48,21 +86,40 @@ const CLIENT_ROLE: &str = if cfg!(feature = "crawl") {
 
 /// Light Client for Avail Blockchain
 
-fn json_subscriber(log_level: Level) -> impl Subscriber + Send + Sync {
+fn json_subscriber(log_level: Level, writer: BoxMakeWriter) -> impl Subscriber + Send + Sync {
 	FmtSubscriber::builder()
 		.json()
 		.with_env_filter(EnvFilter::new(format!("avail_light={log_level}")))
 		.with_span_events(format::FmtSpan::CLOSE)
+		.with_writer(writer)
 		.finish()
 }
 
-fn default_subscriber(log_level: Level) -> impl Subscriber + Send + Sync {
+fn default_subscriber(
+	log_level: Level,
+	writer: BoxMakeWriter,
+	ansi: bool,
+) -> impl Subscriber + Send + Sync {
 	FmtSubscriber::builder()
 		.with_env_filter(EnvFilter::new(format!("avail_light={log_level}")))
 		.with_span_events(format::FmtSpan::CLOSE)
+		.with_writer(writer)
+		.with_ansi(ansi)
 		.finish()
 }
 
+fn log_writer(log_file: Option<&str>) -> Result<BoxMakeWriter> {
+	let Some(log_file) = log_file else {
+		return Ok(BoxMakeWriter::new(std::io::stdout));
+	};
+	let file = fs::OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(log_file)
+		.wrap_err_with(|| format!("Failed to open log file {log_file}"))?;
+	Ok(BoxMakeWriter::new(Mutex::new(file)))
+}
+
 fn parse_log_level(log_level: &str, default: Level) -> (Level, Option<ParseLevelError>) {
 	log_level
 		.to_uppercase()
@@ -71,31 +128,115 @@ fn parse_log_level(log_level: &str, default: Level) -> (Level, Option<ParseLevel
 		.unwrap_or_else(|parse_err| (default, Some(parse_err)))
 }
 
-async fn run(shutdown: Controller<String>) -> Result<()> {
-	let opts = CliOpts::parse();
-
+async fn run(mut opts: CliOpts, shutdown: Controller<String>) -> Result<()> {
 	let mut cfg: RuntimeConfig = RuntimeConfig::default();
+	// Without a config file, default one is written to and loaded from the data directory
+	let default_config = match opts.config {
+		Some(_) => None,
+		None => {
+			let data_dir =
+				DataDir::new(opts.data_dir.as_deref(), &[&cfg.avail_path, &opts.identity]);
+			data_dir.prepare()?;
+			let network = opts.network.clone().unwrap_or(Network::Local);
+			let (path, written) = default_config::init(data_dir.path(), &network)?;
+			opts.config = Some(path.to_string_lossy().into_owned());
+			Some((path, written))
+		},
+	};
 	cfg.load_runtime_config(&opts)?;
 
 	let (log_level, parse_error) = parse_log_level(&cfg.log_level, Level::INFO);
 
+	let writer = log_writer(opts.log_file.as_deref())?;
+	// Dashboard takes over the terminal, so logs are shown in its log tail
+	#[cfg(feature = "tui")]
+	let tui_logs = opts.tui.then(tui::LogBuffer::default);
+	#[cfg(feature = "tui")]
+	let writer = match &tui_logs {
+		Some(logs) => BoxMakeWriter::new(logs.clone()),
+		None => writer,
+	};
 	if cfg.log_format_json {
-		tracing::subscriber::set_global_default(json_subscriber(log_level))
+		tracing::subscriber::set_global_default(json_subscriber(log_level, writer))
 			.expect("global json subscriber is set")
 	} else {
-		tracing::subscriber::set_global_default(default_subscriber(log_level))
+		let ansi = opts.log_file.is_none() && !opts.tui;
+		tracing::subscriber::set_global_default(default_subscriber(log_level, writer, ansi))
 			.expect("global default subscriber is set")
 	}
 
+	match default_config {
+		Some((path, true)) => info!(
+			"Default configuration written to {}, edit it and restart to change the configuration",
+			path.display()
+		),
+		Some((path, false)) => info!("Using default config file {}", path.display()),
+		None => (),
+	}
+
 	if opts.avail_passphrase.is_some() {
 		warn!("Using deprecated CLI parameter `--avail-passphrase`, use `--avail-suri` instead.");
 	}
 
+	for (option, feature) in features::deprecated_options(&cfg) {
+		warn!("Using deprecated configuration option `{option}`, add `{feature}` to `features` instead.");
+	}
+	let features = Features::from(&cfg);
+	if !features.list().is_empty() {
+		let names = features.list().iter().map(Feature::to_string);
+		info!(
+			"Enabled experimental features: {}",
+			names.collect::<Vec<_>>().join(", ")
+		);
+	}
+
+	utils::check_withheld_fraction(cfg.withheld_fraction)?;
+	let cell_count = cfg.confidence.cell_count(cfg.withheld_fraction);
+	info!(
+		"Sampling {cell_count} cells per block for {}% target confidence, reaching {:.4}% assuming {}% of cells is withheld",
+		cfg.confidence,
+		Confidence::from_cell_count(cell_count, cfg.withheld_fraction),
+		cfg.withheld_fraction * 100.0,
+	);
+
+	let data_dir = DataDir::new(cfg.data_dir.as_deref(), &[&cfg.avail_path, &opts.identity]);
+	data_dir.prepare()?;
+	let data_dir_lock = data_dir.lock(opts.force_unlock)?;
+	// Data directory stays locked until the shutdown is completed
+	tokio::spawn({
+		let shutdown = shutdown.clone();
+		async move {
+			shutdown.completed_shutdown().await;
+			drop(data_dir_lock);
+		}
+	});
+	info!("Using data directory {}", data_dir.path().display());
+	cfg.avail_path = data_dir.resolve(&cfg.avail_path);
+	if let Some(ExportConfig {
+		target: ExportTarget::Directory { path },
+		..
+	}) = cfg.export.as_mut()
+	{
+		*path = data_dir.resolve(path);
+	}
+	if let Some(path) = cfg.dispute_bundles_path.as_mut() {
+		*path = data_dir.resolve(path);
+	}
+
+	let encryption = match cfg.storage_encryption {
+		true => Some(EncryptionSecret::from_env()?.ok_or_else(|| {
+			eyre!("Storage encryption is enabled, but encryption key or passphrase is not set")
+		})?),
+		false => None,
+	};
+
+	let identity_path = data_dir.resolve(&opts.identity);
 	let identity_cfg = IdentityConfig::load_or_init(
-		&opts.identity,
+		&identity_path,
 		opts.avail_suri.or(opts.avail_passphrase).as_deref(),
+		encryption.as_ref(),
 	)?;
-	info!("Identity loaded from {}", &opts.identity);
+	info!("Identity loaded from {identity_path}");
 
 	let client_role = if cfg.is_fat_client() {
 		info!("Fat client mode");
@@ -125,11 +266,28 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		Err(eyre!("Bootstrap node list must not be empty. Either use a '--network' flag or add a list of bootstrap nodes in the configuration file"))?
 	}
 
-	let (db, _rocks_db) =
+	if let Some(label) = &cfg.operator_label {
+		types::validate_operator_label(label)?;
+	}
+
+	let (db, rocks_db) =
 		RocksDB::open(&cfg.avail_path).wrap_err("Avail Light could not initialize database")?;
+	let db = EncryptedDB::new(db, encryption).wrap_err("Cannot open encrypted database")?;
+	if db.is_encrypted() {
+		info!("Storage encryption is enabled");
+	}
 
 	let cfg_libp2p: LibP2PConfig = (&cfg).into();
 	let (id_keys, peer_id) = p2p::keypair(&cfg_libp2p)?;
+	if let Some(path) = &cfg_libp2p.pnet_key_file {
+		// Fail early, since swarm is built by the supervised event loop
+		p2p::read_pre_shared_key(path)?;
+		if cfg.ws_transport_enable {
+			Err(eyre!(
+				"Private network is not supported with the WebSocket transport"
+			))?
+		}
+	}
 
 	let metric_attributes = MetricAttributes {
 		role: client_role.into(),
@@ -152,6 +310,7 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			})
 			.unwrap_or("n/a".to_string()),
 		network: Network::name(&cfg.genesis_hash),
+		operator_label: cfg.operator_label.clone().unwrap_or("n/a".to_string()),
 	};
 
 	let cfg_otel: OtelConfig = (&cfg).into();
@@ -165,32 +324,126 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		.wrap_err("Unable to initialize OpenTelemetry service")?,
 	);
 
-	// Create sender channel for P2P event loop commands
-	let (p2p_event_loop_sender, p2p_event_loop_receiver) = mpsc::unbounded_channel();
+	if cfg.startup_jitter > 0 {
+		let delay = utils::random_delay(cfg.startup_jitter);
+		info!("Delaying connection to the full node by {delay:?}");
+		if let Err(shutdown_reason) = shutdown.with_cancel(tokio::time::sleep(delay)).await {
+			return Err(eyre!(shutdown_reason));
+		}
+	}
 
-	let p2p_event_loop = p2p::EventLoop::new(
-		cfg_libp2p,
-		&id_keys,
-		cfg.is_fat_client(),
-		cfg.ws_transport_enable,
+	let connection = ConnectionConfig::try_from(&cfg)?;
+	let state = Arc::new(Mutex::new(State::default()));
+	let (rpc_client, rpc_events, rpc_subscriptions) = rpc::init(
+		db.clone(),
+		state.clone(),
+		&cfg.full_node_ws,
+		&cfg.genesis_hash,
+		cfg.retry_config.clone(),
+		KeepAliveConfig::from(&cfg),
+		connection.clone(),
 		shutdown.clone(),
-		#[cfg(feature = "kademlia-rocksdb")]
-		_rocks_db,
-	);
+	)
+	.await?;
+	let rpc_client = rpc_client.with_cell_size(cfg.cell_size);
 
-	tokio::spawn(
-		shutdown.with_cancel(
-			p2p_event_loop
-				.await
-				.run(ot_metrics.clone(), p2p_event_loop_receiver),
-		),
-	);
+	if cfg.node_capability_check {
+		let host = state.lock().unwrap().connected_node.host.clone();
+		rpc::check_capabilities(&rpc_client, &host).await?;
+	}
+
+	// Stored state is reconciled before it is used by the P2P network
+	match reconciliation::check(db.clone(), &rpc_client, cfg.max_startup_backfill)
+		.await
+		.wrap_err("Cannot reconcile stored state with the chain")?
+	{
+		Reconciliation::Mismatch(reason) if opts.wipe => {
+			warn!("{reason}, removing stored chain data");
+			let removed = db_maintenance::wipe_chain_data(&rocks_db)?;
+			info!("Removed {removed} stored chain data entries");
+		},
+		Reconciliation::Mismatch(reason) => Err(eyre!(
+			"{reason}. Use the '--wipe' flag to remove the stored chain data"
+		))?,
+		Reconciliation::Continue { backfill }
+			if cfg.sync_start_block.is_none() && !backfill.is_empty() =>
+		{
+			info!("Backfilling blocks {backfill:?} finalized while the light client was stopped");
+			cfg.sync_start_block = Some(backfill.start);
+		},
+		Reconciliation::Continue { .. } | Reconciliation::Fresh => (),
+	}
+
+	// Create sender channel for P2P event loop commands
+	let (p2p_event_loop_sender, p2p_event_loop_receiver) = mpsc::unbounded_channel();
 
 	let p2p_client = p2p::Client::new(
 		p2p_event_loop_sender,
 		cfg.dht_parallelization_limit,
 		cfg.kad_record_ttl,
-	);
+	)
+	.with_references(cfg.dht_references.clone())
+	.with_published_records_window(cfg.published_records_window)
+	.with_cell_size(cfg.cell_size);
+
+	#[cfg(feature = "chaos")]
+	let p2p_client = match avail_light::network::chaos::Chaos::new(&cfg.chaos) {
+		Some(chaos) => {
+			warn!(
+				"Fault injection into DHT operations is enabled: {:?}",
+				cfg.chaos
+			);
+			p2p_client.with_chaos(chaos)
+		},
+		None => p2p_client,
+	};
+
+	let supervisor = Supervisor::new((&cfg).into(), shutdown.clone());
+
+	// Event loop is restarted by the supervisor if it crashes, with the same command channel
+	let p2p_event_loop_receiver = Arc::new(tokio::sync::Mutex::new(p2p_event_loop_receiver));
+	let p2p_event_loop_restarted = Arc::new(AtomicBool::new(false));
+	supervisor.spawn("p2p-event-loop", {
+		let cfg = cfg.clone();
+		let p2p_client = p2p_client.clone();
+		let ot_metrics = ot_metrics.clone();
+		let shutdown = shutdown.clone();
+		let db = db.clone();
+		move || {
+			if p2p_event_loop_restarted.swap(true, Ordering::Relaxed) {
+				// Commands are queued until the restarted event loop runs
+				tokio::spawn(shutdown.with_cancel(restart_p2p(
+					p2p_client.clone(),
+					cfg.clone(),
+					db.clone(),
+				)));
+			}
+			let cfg_libp2p = cfg_libp2p.clone();
+			let id_keys = id_keys.clone();
+			let is_fat_client = cfg.is_fat_client();
+			let ws_transport_enable = cfg.ws_transport_enable;
+			let shutdown = shutdown.clone();
+			#[cfg(feature = "kademlia-rocksdb")]
+			let rocks_db = rocks_db.clone();
+			let receiver = p2p_event_loop_receiver.clone();
+			let ot_metrics = ot_metrics.clone();
+			async move {
+				let p2p_event_loop = p2p::EventLoop::new(
+					cfg_libp2p,
+					&id_keys,
+					is_fat_client,
+					ws_transport_enable,
+					shutdown,
+					#[cfg(feature = "kademlia-rocksdb")]
+					rocks_db,
+				)
+				.await;
+				let mut receiver = receiver.lock().await;
+				p2p_event_loop.run(ot_metrics, &mut receiver).await;
+				Ok(())
+			}
+		}
+	});
 
 	// Start listening on provided port
 	p2p_client
@@ -201,11 +454,10 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 
 	let p2p_clone = p2p_client.to_owned();
 	let cfg_clone = cfg.to_owned();
+	let db_clone = db.clone();
 	tokio::spawn(shutdown.with_cancel(async move {
 		info!("Bootstraping the DHT with bootstrap nodes...");
-		let bs_result = p2p_clone
-			.bootstrap_on_startup(cfg_clone.bootstraps.iter().map(Into::into).collect())
-			.await;
+		let bs_result = bootstrap_p2p(&p2p_clone, &cfg_clone, &db_clone).await;
 		match bs_result {
 			Ok(_) => {
 				info!("Bootstrap done.");
@@ -216,6 +468,12 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		}
 	}));
 
+	if cfg.peer_store_size > 0 {
+		tokio::spawn(
+			shutdown.with_cancel(p2p::peer_store::persist(p2p_client.clone(), db.clone())),
+		);
+	}
+
 	#[cfg(feature = "network-analysis")]
 	tokio::task::spawn(shutdown.with_cancel(analyzer::start_traffic_analyzer(cfg.port, 10)));
 
@@ -225,30 +483,185 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	let public_params_len = hex::encode(raw_pp).len();
 	trace!("Public params ({public_params_len}): hash: {public_params_hash}");
 
-	let state = Arc::new(Mutex::new(State::default()));
-	let (rpc_client, rpc_events, rpc_subscriptions) = rpc::init(
-		db.clone(),
-		state.clone(),
-		&cfg.full_node_ws,
-		&cfg.genesis_hash,
-		cfg.retry_config.clone(),
-		shutdown.clone(),
-	)
-	.await?;
+	let rpc_client = match features.is_enabled(Feature::Paranoid) {
+		true => {
+			let cross_check_client = rpc::cross_check_client(
+				&cfg.full_node_ws,
+				&rpc_client.host(),
+				&cfg.genesis_hash,
+				cfg.retry_config.clone(),
+				connection.clone(),
+				shutdown.clone(),
+			)
+			.await?;
+			rpc_client.with_cross_check(cross_check_client.with_cell_size(cfg.cell_size))
+		},
+		false => rpc_client,
+	};
+
+	// While full nodes are unreachable, cells are sampled from the DHT only, until connectivity is restored
+	tokio::task::spawn(shutdown.with_cancel(rpc_client.clone().probe_while_degraded()));
+
+	// Subsystems publish their events to the event bus, to which the consumers subscribe
+	let events = EventBus::new(1 << 7);
+
+	// Consumers subscribe to the event bus before the producers are started, so no events are missed
+	let checkpoint_blocks = events.subscribe_blocks();
+	let maintenance_blocks = events.subscribe_blocks();
+	let peer_event_receiver = events.subscribe_peer_events();
+	let ws_headers = events.subscribe_headers();
+	let ws_blocks = events.subscribe_blocks();
+	let ws_app_data = cfg.app_id.map(|_| events.subscribe_app_data());
+	let export_app_data = cfg
+		.export
+		.as_ref()
+		.and(cfg.app_id)
+		.map(|_| events.subscribe_app_data());
+	let sink_events = (!cfg.event_sinks.is_empty()).then(|| {
+		let app_data = cfg.app_id.map(|_| events.subscribe_app_data());
+		(events.subscribe_blocks(), app_data)
+	});
+	#[cfg(feature = "postgres")]
+	let postgres_events = (
+		events.subscribe_blocks(),
+		cfg.app_id.map(|_| events.subscribe_app_data()),
+	);
+	let missed_blocks = cfg
+		.missed_block_webhook
+		.as_ref()
+		.map(|_| events.subscribe_missed_blocks());
 
 	// Subscribing to RPC events before first event is published
-	let publish_rpc_event_receiver = rpc_events.subscribe();
+	tokio::task::spawn(
+		shutdown.with_cancel(events.clone().forward(rpc_events.subscribe(), Event::from)),
+	);
 	let first_header_rpc_event_receiver = rpc_events.subscribe();
 	let client_rpc_event_receiver = rpc_events.subscribe();
 	#[cfg(feature = "crawl")]
 	let crawler_rpc_event_receiver = rpc_events.subscribe();
+	let prober_rpc_event_receiver = rpc_events.subscribe();
+	let watchdog_rpc_event_receiver = rpc_events.subscribe();
+	let block_time_rpc_event_receiver = rpc_events.subscribe();
 
 	// spawn the RPC Network task for Event Loop to run in the background
 	// and shut it down, without delays
+	let full_node_http = match cfg.full_node_http.is_empty() {
+		true => rpc::http_urls(&cfg.full_node_ws),
+		false => cfg.full_node_http.clone(),
+	};
+	let http_client = rpc::HttpClient::new(
+		cfg.rpc_max_response_size,
+		Duration::from_secs(cfg.rpc_http_timeout),
+		connection.proxies.clone(),
+	);
+	let mut header_poller = rpc::HeaderPoller::new(
+		full_node_http,
+		Duration::from_secs(cfg.header_polling_interval),
+		http_client.clone(),
+		state.clone(),
+	);
+	if let Some(mapping) = HeaderMapping::from_config(&cfg.header_mapping)? {
+		// Headers of the other sources are decoded without the mapping
+		let only_polling = match cfg.header_sources.as_slice() {
+			[] => cfg.header_mode == rpc::HeaderMode::Polling,
+			sources => sources
+				.iter()
+				.all(|kind| *kind == HeaderSourceKind::Polling),
+		};
+		if !only_polling {
+			return Err(eyre!(
+				"Header field mapping is supported only with the polling header source"
+			));
+		}
+		info!("Decoding polled headers using the header field mapping");
+		header_poller = header_poller.with_mapping(mapping);
+	}
+	// Finality is verified only for the headers received over the WebSocket subscription
+	let verifies_finality = cfg.header_sources.contains(&HeaderSourceKind::Subscription)
+		|| (cfg.header_sources.is_empty() && cfg.header_mode != rpc::HeaderMode::Polling);
+	if export_gate::requires_finality(&cfg) && !verifies_finality {
+		return Err(eyre!(
+			"Gating by finality requires the subscription header source, which verifies finality"
+		));
+	}
+	if cfg.header_cross_check && cfg.header_sources.len() < 2 {
+		return Err(eyre!(
+			"Header cross-check requires at least two header sources"
+		));
+	}
+	let header_source: Box<dyn HeaderSource> = match cfg.header_sources.as_slice() {
+		[] => match cfg.header_mode {
+			rpc::HeaderMode::Subscription => Box::new(rpc_subscriptions),
+			rpc::HeaderMode::Polling => Box::new(header_poller),
+			rpc::HeaderMode::SubscriptionWithPollingFallback => Box::new(
+				header_source::Fallback::new(Box::new(rpc_subscriptions), Box::new(header_poller)),
+			),
+		},
+		kinds => {
+			let mut rpc_subscriptions = Some(rpc_subscriptions);
+			let mut header_poller = Some(header_poller);
+			let mut sources: Vec<Box<dyn HeaderSource>> = vec![];
+			for kind in kinds {
+				let duplicate = || eyre!("Header source {kind:?} is configured more than once");
+				sources.push(match kind {
+					HeaderSourceKind::Subscription => {
+						Box::new(rpc_subscriptions.take().ok_or_else(duplicate)?)
+					},
+					HeaderSourceKind::Polling => {
+						Box::new(header_poller.take().ok_or_else(duplicate)?)
+					},
+					HeaderSourceKind::Gossipsub => Box::new(header_source::Gossip::new(
+						p2p_client.clone(),
+						&cfg.header_gossip_publishers,
+						state.clone(),
+					)?),
+				});
+			}
+			let combined = header_source::Combined::new(sources);
+			match cfg.header_cross_check {
+				true => Box::new(combined.with_cross_check(ot_metrics.clone())),
+				false => Box::new(combined),
+			}
+		},
+	};
+
+	// Trusted publishers keep delivering headers while all full nodes are unreachable
+	let header_source: Box<dyn HeaderSource> = match cfg.header_gossip_publishers.is_empty()
+		|| cfg.header_sources.contains(&HeaderSourceKind::Gossipsub)
+	{
+		true => header_source,
+		false => {
+			info!("Receiving headers over gossipsub while full nodes are unreachable");
+			let gossip = header_source::Gossip::new(
+				p2p_client.clone(),
+				&cfg.header_gossip_publishers,
+				state.clone(),
+			)?;
+			Box::new(header_source::Combined::new(vec![
+				header_source,
+				Box::new(gossip.while_degraded()),
+			]))
+		},
+	};
+
+	if features.is_enabled(Feature::GossipHeaders) {
+		if cfg.header_sources.contains(&HeaderSourceKind::Gossipsub) {
+			return Err(eyre!(
+				"Publishing header gossip is not supported with the gossipsub header source"
+			));
+		}
+		info!("Publishing finalized headers over gossipsub");
+		tokio::spawn(shutdown.with_cancel(header_source::publish(
+			p2p_client.clone(),
+			rpc_events.subscribe(),
+		)));
+	}
+
+	let header_events = rpc_events.clone();
 	let rpc_subscriptions_handle = tokio::spawn(shutdown.with_cancel(shutdown.with_trigger(
 		"Subscription loop failure triggered shutdown".to_string(),
-		async {
-			let result = rpc_subscriptions.run().await;
+		async move {
+			let result = header_source.run(header_events).await;
 			if let Err(ref err) = result {
 				error!(%err, "Subscription loop ended with error");
 			};
@@ -285,10 +698,64 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		},
 	};
 
+	if let Err(error) = cfg.cell_size.check(&block_header) {
+		warn!("Blocks cannot be verified with the configured cell size: {error}");
+	}
+
 	state.lock().unwrap().latest = block_header.number;
 	let sync_range = cfg.sync_range(block_header.number);
 
 	let ws_clients = api::v2::types::WsClients::default();
+	let (block_tx, _) = broadcast::channel::<avail_light::types::BlockVerified>(1 << 7);
+	let watchdog_block_receiver = block_tx.subscribe();
+	tokio::task::spawn(
+		shutdown.with_cancel(
+			events
+				.clone()
+				.forward(block_tx.subscribe(), Event::BlockVerified),
+		),
+	);
+
+	let genesis_hash = rpc_client.current_client().await.genesis_hash();
+	tokio::task::spawn(shutdown.with_cancel(reconciliation::store_checkpoints(
+		db.clone(),
+		genesis_hash,
+		checkpoint_blocks,
+	)));
+
+	let peer_events = p2p_client
+		.subscribe_peer_events()
+		.await
+		.wrap_err("Cannot subscribe to peer events")?;
+	tokio::task::spawn(shutdown.with_cancel(events.clone().forward(peer_events, Event::PeerEvent)));
+	tokio::task::spawn(shutdown.with_cancel(event_bus::count_peer_events(
+		peer_event_receiver,
+		ot_metrics.clone(),
+	)));
+
+	let decoders = avail_light::decoder::Decoders::default();
+	if let (Some(app_id), Some(name)) = (cfg.app_id, &cfg.app_data_decoder) {
+		decoders.register(app_id, avail_light::decoder::builtin(name)?);
+	}
+
+	let api_cache = api::cache::ReadCache::new(
+		Duration::from_secs(cfg.api_cache_ttl),
+		cfg.api_cache_capacity,
+	);
+	tokio::task::spawn(shutdown.with_cancel(api::cache::record_hit_rate(
+		api_cache.clone(),
+		ot_metrics.clone(),
+		Duration::from_secs(60),
+	)));
+
+	let api_latencies = api::latency::RouteLatencies::default();
+	tokio::task::spawn(shutdown.with_cancel(api::latency::record_latencies(
+		api_latencies.clone(),
+		ot_metrics.clone(),
+		Duration::from_secs(60),
+	)));
+
+	let bulk_verification = BulkVerification::new(db.clone());
 
 	// Spawn tokio task which runs one http server for handling RPC
 	let server = api::server::Server {
@@ -302,13 +769,32 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		ws_clients: ws_clients.clone(),
 		shutdown: shutdown.clone(),
 		p2p_client: p2p_client.clone(),
+		block_sender: block_tx.clone(),
+		decoders,
+		cache: api_cache,
+		latencies: api_latencies,
+		bulk_verification: bulk_verification.clone(),
 	};
-	tokio::task::spawn(shutdown.with_cancel(server.bind()));
+	supervisor.spawn("http-server", move || {
+		let server = server.clone();
+		async move {
+			server.bind()?.await;
+			Ok(())
+		}
+	});
 
-	let (block_tx, block_rx) = broadcast::channel::<avail_light::types::BlockVerified>(1 << 7);
+	let (confidence_timeout_tx, confidence_timeout_rx) =
+		broadcast::channel::<avail_light::types::ConfidenceTimeout>(1 << 7);
 
-	let data_rx = cfg.app_id.map(AppId).map(|app_id| {
-		let (data_tx, data_rx) = broadcast::channel::<(u32, AppData)>(1 << 7);
+	if let Some(app_id) = cfg.app_id.map(AppId) {
+		let (data_tx, _) = broadcast::channel::<(u32, AppData)>(1 << 7);
+		tokio::task::spawn(shutdown.with_cancel(events.clone().forward(
+			data_tx.subscribe(),
+			|(block_number, app_data)| Event::AppDataReady {
+				block_number,
+				app_data,
+			},
+		)));
 		tokio::task::spawn(shutdown.with_cancel(avail_light::app_client::run(
 			(&cfg).into(),
 			db.clone(),
@@ -320,31 +806,111 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			state.clone(),
 			sync_range.clone(),
 			data_tx,
+			confidence_timeout_tx.clone(),
+			ot_metrics.clone(),
 			shutdown.clone(),
 		)));
-		data_rx
-	});
+	}
+
+	match (cfg.export.clone(), cfg.app_id, export_app_data) {
+		(Some(export_cfg), Some(app_id), Some(export_rx)) => {
+			let db = db.clone();
+			let state = state.clone();
+			let withheld_fraction = cfg.withheld_fraction;
+			tokio::task::spawn(shutdown.with_cancel(async move {
+				if let Err(error) = avail_light::export::run(
+					export_cfg,
+					withheld_fraction,
+					db,
+					state,
+					app_id,
+					export_rx,
+				)
+				.await
+				{
+					error!("Exporter failed: {error:#}");
+				}
+			}));
+		},
+		(Some(_), ..) => {
+			warn!("Export is configured, but app_id is not set, nothing will be exported");
+		},
+		_ => (),
+	}
+
+	if let Some((block_receiver, data_receiver)) = sink_events {
+		let event_sinks = avail_light::sink::run(
+			cfg.event_sinks.clone(),
+			Duration::from_secs(cfg.event_sink_timeout),
+			http_client.clone(),
+			cfg.withheld_fraction,
+			db.clone(),
+			state.clone(),
+			cfg.app_id,
+			block_receiver,
+			data_receiver,
+		);
+		tokio::task::spawn(shutdown.with_cancel(async move {
+			if let Err(error) = event_sinks.await {
+				error!("Event sinks failed: {error:#}");
+			}
+		}));
+	}
+
+	#[cfg(feature = "postgres")]
+	tokio::task::spawn(shutdown.with_cancel(avail_light::postgres::run(
+		cfg.postgres.clone(),
+		cfg.retry_config.clone(),
+		cfg.withheld_fraction,
+		db.clone(),
+		state.clone(),
+		cfg.app_id,
+		postgres_events.0,
+		postgres_events.1,
+	)));
 
 	tokio::task::spawn(shutdown.with_cancel(api::v2::publish(
 		api::v2::types::Topic::HeaderVerified,
-		publish_rpc_event_receiver,
+		ws_headers,
 		ws_clients.clone(),
 	)));
 
 	tokio::task::spawn(shutdown.with_cancel(api::v2::publish(
 		api::v2::types::Topic::ConfidenceAchieved,
-		block_tx.subscribe(),
+		ws_blocks,
+		ws_clients.clone(),
+	)));
+
+	tokio::task::spawn(shutdown.with_cancel(api::v2::publish(
+		api::v2::types::Topic::ConfidenceTimeout,
+		confidence_timeout_rx,
 		ws_clients.clone(),
 	)));
 
-	if let Some(data_rx) = data_rx {
+	if let Some(ws_app_data) = ws_app_data {
 		tokio::task::spawn(shutdown.with_cancel(api::v2::publish(
 			api::v2::types::Topic::DataVerified,
-			data_rx,
+			ws_app_data,
 			ws_clients,
 		)));
 	}
 
+	#[cfg(feature = "grpc")]
+	if cfg.grpc.grpc_server_enable {
+		let shutdown_clone = shutdown.clone();
+		tokio::task::spawn(shutdown.with_cancel(shutdown.with_trigger(
+			"gRPC server failure triggered shutdown".to_string(),
+			api::grpc::run(
+				cfg.grpc.clone(),
+				db.clone(),
+				cfg.app_id,
+				cfg.withheld_fraction,
+				block_tx.clone(),
+				shutdown_clone,
+			),
+		)));
+	}
+
 	#[cfg(feature = "crawl")]
 	if cfg.crawl.crawl_block {
 		let partition = cfg.crawl.crawl_block_matrix_partition;
@@ -358,13 +924,59 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		)));
 	}
 
+	if cfg.replication_probe.replication_probe {
+		tokio::task::spawn(shutdown.with_cancel(avail_light::replication_prober::run(
+			prober_rpc_event_receiver,
+			p2p_client.clone(),
+			cfg.replication_probe.clone(),
+			ot_metrics.clone(),
+		)));
+	}
+
 	let sync_client = SyncClient::new(db.clone(), rpc_client.clone());
 
+	tokio::task::spawn(shutdown.with_cancel(avail_light::bulk_verification::run(
+		bulk_verification,
+		sync_client.clone(),
+		network::new(
+			p2p_client.clone(),
+			rpc_client.clone(),
+			pp.clone(),
+			cfg.disable_rpc,
+			None,
+		),
+		(&cfg).into(),
+		block_tx.clone(),
+		shutdown.cancellation_token(),
+	)));
+
+	if cfg.retention_check.retention_check {
+		// Retention is checked against the DHT only
+		let dht_network_client = network::new(
+			p2p_client.clone(),
+			rpc_client.clone(),
+			pp.clone(),
+			true,
+			None,
+		);
+		tokio::task::spawn(shutdown.with_cancel(avail_light::retention::run(
+			db.clone(),
+			sync_client.clone(),
+			dht_network_client,
+			cfg.retention_check.clone(),
+			cfg.withheld_fraction,
+			state.clone(),
+			ot_metrics.clone(),
+			shutdown.cancellation_token(),
+		)));
+	}
+
 	let sync_network_client = network::new(
 		p2p_client.clone(),
 		rpc_client.clone(),
 		pp.clone(),
 		cfg.disable_rpc,
+		None,
 	);
 
 	if cfg.sync_start_block.is_some() {
@@ -376,6 +988,7 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			sync_range,
 			block_tx.clone(),
 			state.clone(),
+			shutdown.cancellation_token(),
 		)));
 	}
 
@@ -396,18 +1009,31 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	}
 
 	let static_config_params = StaticConfigParams {
-		block_confidence_treshold: cfg.confidence,
+		block_confidence_treshold: cfg.confidence.value(),
 		replication_factor: cfg.replication_factor,
 		query_timeout: cfg.query_timeout,
 		pruning_interval: cfg.store_pruning_interval,
 		telemetry_flush_interval: cfg.ot_flush_block_interval,
 	};
 
+	#[cfg(feature = "tui")]
+	if let Some(logs) = tui_logs {
+		tokio::task::spawn(shutdown.with_cancel(tui::run(
+			db.clone(),
+			state.clone(),
+			p2p_client.clone(),
+			logs,
+			cfg.withheld_fraction,
+			shutdown.clone(),
+		)));
+	}
+
 	tokio::task::spawn(shutdown.with_cancel(avail_light::maintenance::run(
 		p2p_client.clone(),
 		ot_metrics.clone(),
-		block_rx,
+		maintenance_blocks,
 		static_config_params,
+		state.clone(),
 		shutdown.clone(),
 	)));
 
@@ -417,7 +1043,11 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 	};
 
 	if let Some(partition) = cfg.block_matrix_partition {
-		let fat_client = avail_light::fat_client::new(p2p_client.clone(), rpc_client.clone());
+		let mut fat_client = avail_light::fat_client::new(p2p_client.clone(), rpc_client.clone());
+		if cfg.fat_client_local_proofs {
+			info!("Fat client generates cell proofs locally");
+			fat_client = fat_client.with_local_proofs(pp.clone());
+		}
 
 		tokio::task::spawn(shutdown.with_cancel(avail_light::fat_client::run(
 			fat_client,
@@ -429,7 +1059,13 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 			shutdown.clone(),
 		)));
 	} else {
-		let light_network_client = network::new(p2p_client, rpc_client, pp, cfg.disable_rpc);
+		let light_network_client = network::new(
+			p2p_client,
+			rpc_client,
+			pp,
+			cfg.disable_rpc,
+			network::ExtraSampling::from_config(&cfg),
+		);
 
 		tokio::task::spawn(shutdown.with_cancel(avail_light::light_client::run(
 			db.clone(),
@@ -442,11 +1078,83 @@ async fn run(shutdown: Controller<String>) -> Result<()> {
 		)));
 	}
 
+	let missed_block_cfg = MissedBlockConfig::from(&cfg);
+	if let (Some(url), Some(missed_blocks)) = (missed_block_cfg.webhook_url.clone(), missed_blocks)
+	{
+		tokio::task::spawn(shutdown.with_cancel(block_time::post_missed_blocks(
+			url,
+			http_client.clone(),
+			missed_blocks,
+		)));
+	}
+
+	tokio::task::spawn(shutdown.with_cancel(block_time::run(
+		missed_block_cfg,
+		http_client.clone(),
+		block_time_rpc_event_receiver,
+		events.clone(),
+		ot_metrics.clone(),
+	)));
+
+	tokio::task::spawn(shutdown.with_cancel(reporter::run(
+		TelemetryReportConfig::from(&cfg),
+		http_client,
+		db.clone(),
+		state.clone(),
+		version.to_string(),
+	)));
+
 	ot_metrics.count(MetricCounter::Starts).await;
 
+	systemd::notify_ready();
+	tokio::task::spawn(shutdown.with_cancel(systemd::run(
+		Duration::from_secs(cfg.systemd_liveness_timeout),
+		watchdog_block_receiver,
+		watchdog_rpc_event_receiver,
+	)));
+
 	Ok(())
 }
 
+/// Dials known peers and bootstraps the DHT with the bootstrap nodes,
+/// falling back to the known peers if the bootstrap nodes are unreachable
+async fn bootstrap_p2p(
+	p2p_client: &p2p::Client,
+	cfg: &RuntimeConfig,
+	db: &impl Database,
+) -> Result<()> {
+	let known_peers = match cfg.peer_store_size {
+		0 => 0,
+		_ => p2p::peer_store::restore(p2p_client, db)
+			.await
+			.unwrap_or_else(|error| {
+				warn!("Cannot restore known peers: {error:#}");
+				0
+			}),
+	};
+	let bootstraps = cfg.bootstraps.iter().map(Into::into).collect();
+	match p2p_client.bootstrap_on_startup(bootstraps).await {
+		Err(error) if known_peers > 0 => {
+			warn!("Bootstrap nodes are unreachable, bootstrapping from the known peers: {error:#}");
+			p2p_client.bootstrap().await
+		},
+		result => result,
+	}
+}
+
+/// Restores listening and DHT bootstrap after the P2P event loop restart
+async fn restart_p2p(p2p_client: p2p::Client, cfg: RuntimeConfig, db: impl Database) {
+	let address = construct_multiaddress(cfg.ws_transport_enable, cfg.port);
+	if let Err(error) = p2p_client.start_listening(address).await {
+		error!("Listening after P2P event loop restart failed: {error:#}");
+		return;
+	}
+	match bootstrap_p2p(&p2p_client, &cfg, &db).await {
+		Ok(_) => info!("Bootstrap after P2P event loop restart done."),
+		Err(e) => warn!("Bootstrap after P2P event loop restart: {e:?}."),
+	}
+}
+
 fn construct_multiaddress(is_websocket: bool, port: u16) -> Multiaddr {
 	let tcp_multiaddress = Multiaddr::empty()
 		.with(Protocol::from(Ipv4Addr::UNSPECIFIED))
@@ -470,8 +1178,11 @@ fn install_panic_hooks(shutdown: Controller<String>) -> Result<()> {
 	eyre_hook.install()?;
 
 	std::panic::set_hook(Box::new(move |panic_info| {
-		// trigger shutdown to stop other tasks if panic occurs
-		let _ = shutdown.trigger_shutdown("Panic occurred, shuting down".to_string());
+		// trigger shutdown to stop other tasks if panic occurs,
+		// unless panicked task is restarted by the supervisor
+		if supervisor::supervised_task().is_none() {
+			let _ = shutdown.trigger_shutdown("Panic occurred, shuting down".to_string());
+		}
 
 		let msg = format!("{}", panic_hook.panic_report(panic_info));
 		error!("Error: {}", strip_ansi_escapes::strip_str(msg));
@@ -531,24 +1242,177 @@ async fn user_signal() {
 	}
 }
 
-#[tokio::main]
-pub async fn main() -> Result<()> {
-	let shutdown = Controller::new();
+/// Runs the self-test, exiting with non-zero status code if any of the checks failed
+fn self_test(opts: CliOpts, block: Option<u32>) -> Result<()> {
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.wrap_err("Failed to start async runtime")?;
+
+	let report = runtime.block_on(self_test::run(&opts, block));
+	match opts.output {
+		OutputFormat::Text => println!("{report}"),
+		OutputFormat::Json => println!("{}", report.to_json()),
+	}
+	if !report.passed() {
+		std::process::exit(1);
+	}
+	Ok(())
+}
 
-	// install custom panic hooks
-	install_panic_hooks(shutdown.clone())?;
+fn verify_bundle(opts: &CliOpts, file: &str) -> Result<()> {
+	let bundle = ProofBundle::load(Path::new(file))?;
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.wrap_err("Failed to start async runtime")?;
+
+	let public_parameters = Arc::new(kate_recovery::couscous::public_params());
+	let verdict = runtime.block_on(proof_bundle::verify(bundle, public_parameters))?;
+	match opts.output {
+		OutputFormat::Text => println!("{verdict}"),
+		OutputFormat::Json => println!("{}", verdict.to_json()),
+	}
+	if !verdict.passed() {
+		std::process::exit(1);
+	}
+	Ok(())
+}
 
-	// spawn a task to watch for ctrl-c signals from user to trigger the shutdown
-	tokio::spawn(shutdown.with_trigger("user signaled shutdown".to_string(), user_signal()));
+fn maintain_db(mut opts: CliOpts, command: DbCommand) -> Result<()> {
+	// Without a config file, default one from the data directory is used if it exists
+	if opts.config.is_none() {
+		let avail_path = RuntimeConfig::default().avail_path;
+		let data_dir = DataDir::new(opts.data_dir.as_deref(), &[&avail_path, &opts.identity]);
+		let path = data_dir.path().join(default_config::FILE_NAME);
+		if path.exists() {
+			opts.config = Some(path.to_string_lossy().into_owned());
+		}
+	}
+	let mut cfg = RuntimeConfig::default();
+	cfg.load_runtime_config(&opts)?;
 
-	if let Err(error) = run(shutdown.clone()).await {
-		error!("{error:#}");
-		return Err(error.wrap_err("Starting Light Client failed"));
+	let data_dir = DataDir::new(cfg.data_dir.as_deref(), &[&cfg.avail_path, &opts.identity]);
+	data_dir.prepare()?;
+	// Database must not be used by the running light client during the maintenance
+	let _data_dir_lock = data_dir.lock(opts.force_unlock)?;
+	let path = data_dir.resolve(&cfg.avail_path);
+	let report = match command {
+		DbCommand::Compact => db_maintenance::compact(&path)?,
+		DbCommand::Repair => db_maintenance::repair(&path)?,
 	};
+	match opts.output {
+		OutputFormat::Text => println!("{report}"),
+		OutputFormat::Json => println!("{}", report.to_json()),
+	}
+	Ok(())
+}
+
+fn start(opts: CliOpts, shutdown: Controller<String>) -> Result<()> {
+	let _pid_file = opts.pid_file.as_deref().map(PidFile::create).transpose()?;
+
+	let runtime = tokio::runtime::Builder::new_multi_thread()
+		.enable_all()
+		.build()
+		.wrap_err("Failed to start async runtime")?;
+
+	runtime.block_on(async {
+		// install custom panic hooks
+		install_panic_hooks(shutdown.clone())?;
+
+		// spawn a task to watch for ctrl-c signals from user to trigger the shutdown
+		tokio::spawn(shutdown.with_trigger("user signaled shutdown".to_string(), user_signal()));
+
+		if let Err(error) = run(opts, shutdown.clone()).await {
+			error!("{error:#}");
+			return Err(error.wrap_err("Starting Light Client failed"));
+		};
+
+		let reason = shutdown.completed_shutdown().await;
+		let _ = systemd::notify("STOPPING=1");
+
+		// we are not logging error here since expectation is
+		// to log terminating condition before sending message to this channel
+		Err(eyre!(reason).wrap_err("Running Light Client encountered an error"))
+	})
+}
+
+/// Prints error in the same format as the JSON log lines
+fn print_json_error(error: &color_eyre::Report) {
+	let line = serde_json::json!({
+		"timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+		"level": "ERROR",
+		"fields": { "message": format!("{error:#}") },
+		"target": "avail_light",
+	});
+	println!("{line}");
+}
+
+pub fn main() -> Result<()> {
+	let opts = CliOpts::parse();
+
+	// With JSON output, errors are printed as JSON lines to standard output as well
+	if opts.output == OutputFormat::Json {
+		if let Err(error) = start_with_opts(opts) {
+			print_json_error(&error);
+			std::process::exit(1);
+		}
+		return Ok(());
+	}
+	start_with_opts(opts)
+}
+
+fn start_with_opts(opts: CliOpts) -> Result<()> {
+	match opts.command {
+		Some(Command::SelfTest { block }) => return self_test(opts, block),
+		Some(Command::VerifyBundle { ref file }) => return verify_bundle(&opts, file),
+		Some(Command::Db { ref command }) => {
+			let command = command.clone();
+			return maintain_db(opts, command);
+		},
+		None => (),
+	}
+
+	#[cfg(windows)]
+	if opts.service {
+		return service::windows::run(|shutdown| start(CliOpts::parse(), shutdown));
+	}
 
-	let reason = shutdown.completed_shutdown().await;
+	if opts.tui && (opts.daemon || opts.service) {
+		return Err(eyre!(
+			"Terminal dashboard cannot be shown when running in the background"
+		));
+	}
+	#[cfg(not(feature = "tui"))]
+	if opts.tui {
+		return Err(eyre!(
+			"Terminal dashboard is not available, build with the `tui` feature"
+		));
+	}
+
+	if opts.daemon {
+		// Fail early, since errors are not visible once detached from the terminal
+		if let Some(pid_file) = &opts.pid_file {
+			PidFile::check(Path::new(pid_file))?;
+		}
+		#[cfg(unix)]
+		service::daemonize()?;
+		#[cfg(not(unix))]
+		return Err(eyre!(
+			"Daemon mode is supported only on Unix, use `--service` on Windows"
+		));
+	}
 
-	// we are not logging error here since expectation is
-	// to log terminating condition before sending message to this channel
-	Err(eyre!(reason).wrap_err("Running Light Client encountered an error"))
+	let opts_output = opts.output.clone();
+	let result = start(opts, Controller::new());
+	if supervisor::has_failed() {
+		if let Err(error) = result {
+			match opts_output {
+				OutputFormat::Text => eprintln!("Error: {error:?}"),
+				OutputFormat::Json => print_json_error(&error),
+			}
+		}
+		std::process::exit(supervisor::EXIT_CODE_SUPERVISOR);
+	}
+	result
 }
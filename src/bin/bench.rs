@@ -0,0 +1,315 @@
+//! Benchmark harness which runs multiple in-process light clients against a mock full node and in-memory DHT.
+//!
+//! Mock full node produces headers with configured matrix dimensions in configured block time.
+//! Light clients share in-memory DHT, cells missing in DHT are fetched from the mock full node
+//! and inserted into the DHT, mimicking the DHT with RPC fallback network client.
+//!
+//! Reported results are processed blocks per second, DHT and RPC fetch latencies and resident memory.
+
+use async_trait::async_trait;
+use avail_light::{
+	confidence::Confidence,
+	data::mem_db::MemoryDB,
+	light_client,
+	network::{self, FetchStats},
+	telemetry::{MetricCounter, MetricValue, Metrics},
+	types::{LightClientConfig, RuntimeConfig, State},
+	utils::percentile,
+};
+use avail_subxt::{
+	api::runtime_types::avail_core::{
+		data_lookup::compact::CompactDataLookup,
+		header::extension::{v3, HeaderExtension},
+		kate_commitment::v3::KateCommitment,
+	},
+	config::substrate::Digest,
+	primitives::Header,
+};
+use clap::Parser;
+use color_eyre::Result;
+use kate_recovery::{
+	config,
+	data::Cell,
+	matrix::{Dimensions, Position},
+};
+use rand::Rng;
+use sp_core::H256;
+use std::{
+	collections::HashSet,
+	sync::{Arc, Mutex, RwLock},
+	time::{Duration, Instant},
+};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Parser)]
+struct CommandArgs {
+	/// Number of in-process light clients
+	#[arg(long, default_value_t = 10)]
+	clients: usize,
+	/// Number of blocks produced by mock full node
+	#[arg(long, default_value_t = 20)]
+	blocks: u32,
+	/// Mock full node block time in milliseconds
+	#[arg(long, default_value_t = 100)]
+	block_time: u64,
+	/// Number of matrix rows
+	#[arg(long, default_value_t = 64)]
+	rows: u16,
+	/// Number of matrix columns
+	#[arg(long, default_value_t = 256)]
+	cols: u16,
+	/// Confidence which light clients need to achieve
+	#[arg(long, default_value_t = 99.9)]
+	confidence: f64,
+	/// Simulated DHT GET latency in milliseconds
+	#[arg(long, default_value_t = 50)]
+	dht_latency: u64,
+	/// Simulated RPC latency in milliseconds
+	#[arg(long, default_value_t = 20)]
+	rpc_latency: u64,
+	/// Probability of DHT GET failure for a cell which is stored in DHT
+	#[arg(long, default_value_t = 0.0)]
+	dht_failure_rate: f64,
+}
+
+/// Cell references stored in the in-memory DHT
+#[derive(Clone, Default)]
+struct InMemoryDHT(Arc<RwLock<HashSet<(u32, u32, u16)>>>);
+
+struct BenchNetworkClient {
+	dht: InMemoryDHT,
+	dht_latency: Duration,
+	rpc_latency: Duration,
+	dht_failure_rate: f64,
+}
+
+fn cell(position: Position) -> Cell {
+	Cell {
+		position,
+		content: [0u8; 80],
+	}
+}
+
+#[async_trait]
+impl network::Client for BenchNetworkClient {
+	async fn fetch_verified(
+		&self,
+		block_number: u32,
+		_: H256,
+		_: Dimensions,
+		_: &[[u8; config::COMMITMENT_SIZE]],
+		positions: &[Position],
+		_: &CancellationToken,
+	) -> Result<(Vec<Cell>, Vec<Position>, FetchStats)> {
+		let begin = Instant::now();
+		tokio::time::sleep(self.dht_latency).await;
+
+		let (dht_fetched, unfetched): (Vec<Position>, Vec<Position>) = {
+			let dht = self.dht.0.read().expect("Lock should be acquired");
+			let mut rng = rand::thread_rng();
+			positions.iter().partition(|&&position| {
+				dht.contains(&(block_number, position.row, position.col))
+					&& !rng.gen_bool(self.dht_failure_rate)
+			})
+		};
+		let dht_fetch_duration = begin.elapsed();
+
+		let mut fetched = dht_fetched.into_iter().map(cell).collect::<Vec<_>>();
+		let dht_fetched_count = fetched.len();
+
+		if unfetched.is_empty() {
+			let stats =
+				FetchStats::new(positions.len(), dht_fetched_count, dht_fetch_duration, None);
+			return Ok((fetched, vec![], stats));
+		}
+
+		let begin = Instant::now();
+		tokio::time::sleep(self.rpc_latency).await;
+		{
+			let mut dht = self.dht.0.write().expect("Lock should be acquired");
+			dht.extend(
+				unfetched
+					.iter()
+					.map(|position| (block_number, position.row, position.col)),
+			);
+		}
+		let rpc_fetched = unfetched.len();
+		fetched.extend(unfetched.into_iter().map(cell));
+
+		let stats = FetchStats::new(
+			positions.len(),
+			dht_fetched_count,
+			dht_fetch_duration,
+			Some((rpc_fetched, begin.elapsed())),
+		);
+		Ok((fetched, vec![], stats))
+	}
+}
+
+#[derive(Default)]
+struct BenchMetrics {
+	dht_fetch_durations: Mutex<Vec<f64>>,
+	rpc_fetch_durations: Mutex<Vec<f64>>,
+}
+
+#[async_trait]
+impl Metrics for BenchMetrics {
+	async fn count(&self, _: MetricCounter) {}
+
+	async fn record(&self, value: MetricValue) {
+		match value {
+			MetricValue::DHTFetchDuration(duration) => {
+				self.dht_fetch_durations.lock().unwrap().push(duration)
+			},
+			MetricValue::RPCFetchDuration(duration) => {
+				self.rpc_fetch_durations.lock().unwrap().push(duration)
+			},
+			_ => (),
+		}
+	}
+
+	async fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+fn header(block_number: u32, rows: u16, cols: u16) -> Header {
+	Header {
+		parent_hash: H256::from_low_u64_be(block_number.saturating_sub(1) as u64),
+		number: block_number,
+		state_root: H256::zero(),
+		extrinsics_root: H256::zero(),
+		digest: Digest { logs: vec![] },
+		extension: HeaderExtension::V3(v3::HeaderExtension {
+			commitment: KateCommitment {
+				rows,
+				cols,
+				data_root: H256::zero(),
+				commitment: vec![0u8; rows as usize * config::COMMITMENT_SIZE],
+			},
+			app_lookup: CompactDataLookup {
+				size: 1,
+				index: vec![],
+			},
+		}),
+	}
+}
+
+/// Runs mock full node which broadcasts finalized headers in configured block time
+async fn run_full_node(args: &CommandArgs, sender: broadcast::Sender<(Header, Instant)>) {
+	let mut interval = tokio::time::interval(Duration::from_millis(args.block_time));
+	for block_number in 1..=args.blocks {
+		interval.tick().await;
+		let _ = sender.send((header(block_number, args.rows, args.cols), Instant::now()));
+	}
+}
+
+async fn run_client(
+	mut receiver: broadcast::Receiver<(Header, Instant)>,
+	network_client: BenchNetworkClient,
+	metrics: Arc<BenchMetrics>,
+	cfg: Arc<LightClientConfig>,
+) -> Result<u32> {
+	let db = MemoryDB::default();
+	let state = Arc::new(Mutex::new(State::default()));
+	let mut processed = 0;
+	while let Ok((header, received_at)) = receiver.recv().await {
+		let confidence = light_client::process_block(
+			db.clone(),
+			&network_client,
+			&metrics,
+			&cfg,
+			header,
+			received_at,
+			state.clone(),
+			&CancellationToken::new(),
+		)
+		.await?;
+		if confidence.is_some() {
+			processed += 1;
+		}
+	}
+	Ok(processed)
+}
+
+/// Returns resident set size of the current process in kilobytes (Linux only)
+fn resident_memory() -> Option<u64> {
+	let status = std::fs::read_to_string("/proc/self/status").ok()?;
+	status
+		.lines()
+		.find(|line| line.starts_with("VmRSS:"))?
+		.split_whitespace()
+		.nth(1)?
+		.parse()
+		.ok()
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	let args = CommandArgs::parse();
+	let cfg = Arc::new(LightClientConfig::from(&RuntimeConfig {
+		confidence: Confidence::new(args.confidence),
+		..Default::default()
+	}));
+
+	let dht = InMemoryDHT::default();
+	let metrics = Arc::new(BenchMetrics::default());
+	let (sender, _) = broadcast::channel(args.blocks as usize);
+
+	let memory_before = resident_memory();
+	let clients = (0..args.clients)
+		.map(|_| {
+			let network_client = BenchNetworkClient {
+				dht: dht.clone(),
+				dht_latency: Duration::from_millis(args.dht_latency),
+				rpc_latency: Duration::from_millis(args.rpc_latency),
+				dht_failure_rate: args.dht_failure_rate,
+			};
+			tokio::spawn(run_client(
+				sender.subscribe(),
+				network_client,
+				metrics.clone(),
+				cfg.clone(),
+			))
+		})
+		.collect::<Vec<_>>();
+
+	let begin = Instant::now();
+	run_full_node(&args, sender).await;
+
+	let mut processed = 0;
+	for client in clients {
+		processed += client.await??;
+	}
+	let elapsed = begin.elapsed();
+	let memory_after = resident_memory();
+
+	let dht_fetch_durations = metrics.dht_fetch_durations.lock().unwrap().clone();
+	let rpc_fetch_durations = metrics.rpc_fetch_durations.lock().unwrap().clone();
+
+	println!("Clients: {}, blocks: {}", args.clients, args.blocks);
+	println!("Processed blocks: {processed} in {elapsed:?}");
+	println!(
+		"Blocks per second: {:.2}",
+		processed as f64 / elapsed.as_secs_f64()
+	);
+	for (name, mut durations) in [("DHT", dht_fetch_durations), ("RPC", rpc_fetch_durations)] {
+		if durations.is_empty() {
+			println!("{name} fetch latency (s): no samples");
+			continue;
+		}
+		durations.sort_by(f64::total_cmp);
+		println!(
+			"{name} fetch latency (s): p50 {:.4}, p90 {:.4}, p99 {:.4}, samples {}",
+			percentile(&durations, 50.0),
+			percentile(&durations, 90.0),
+			percentile(&durations, 99.0),
+			durations.len()
+		);
+	}
+	if let (Some(before), Some(after)) = (memory_before, memory_after) {
+		println!("Resident memory: {before} kB before, {after} kB after");
+	}
+	Ok(())
+}
@@ -0,0 +1,77 @@
+//! Metadata attached to the blocks by integrators.
+//!
+//! Embedders can attach small named metadata blobs to the blocks (e.g. time at which the block was processed
+//! by the indexer), which are persisted in a separate column family. Downstream consumers can check
+//! the metadata before processing the block, so blocks are not processed twice across restarts.
+//! Metadata is opaque to the light client and is not pruned with the block data.
+
+use color_eyre::{eyre::eyre, Result};
+
+use crate::data::{Database, Key};
+
+/// Maximum length of the metadata name in bytes
+pub const MAX_NAME_LENGTH: usize = 128;
+
+/// Maximum size of the metadata value in bytes
+pub const MAX_VALUE_SIZE: usize = 4 * 1024;
+
+fn check_name(name: &str) -> Result<()> {
+	if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+		return Err(eyre!(
+			"Metadata name must be between 1 and {MAX_NAME_LENGTH} bytes long"
+		));
+	}
+	Ok(())
+}
+
+/// Attaches metadata with the given name to the block, replacing the existing value
+pub fn put(db: &impl Database, block_number: u32, name: &str, value: &[u8]) -> Result<()> {
+	check_name(name)?;
+	if value.len() > MAX_VALUE_SIZE {
+		return Err(eyre!(
+			"Metadata value of {} bytes exceeds the limit of {MAX_VALUE_SIZE} bytes",
+			value.len()
+		));
+	}
+	db.put(
+		Key::BlockMetadata(block_number, name.to_string()),
+		value.to_vec(),
+	)
+}
+
+/// Gets metadata with the given name attached to the block
+pub fn get(db: &impl Database, block_number: u32, name: &str) -> Result<Option<Vec<u8>>> {
+	check_name(name)?;
+	db.get(Key::BlockMetadata(block_number, name.to_string()))
+}
+
+/// Removes metadata with the given name from the block
+pub fn delete(db: &impl Database, block_number: u32, name: &str) -> Result<()> {
+	check_name(name)?;
+	db.delete(Key::BlockMetadata(block_number, name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{delete, get, put, MAX_NAME_LENGTH, MAX_VALUE_SIZE};
+	use crate::data::mem_db::MemoryDB;
+
+	#[test]
+	fn block_metadata() {
+		let db = MemoryDB::default();
+		assert_eq!(get(&db, 1, "indexer").unwrap(), None);
+
+		put(&db, 1, "indexer", b"processed").unwrap();
+		put(&db, 1, "archiver", b"archived").unwrap();
+		assert_eq!(get(&db, 1, "indexer").unwrap(), Some(b"processed".to_vec()));
+		assert_eq!(get(&db, 2, "indexer").unwrap(), None);
+
+		delete(&db, 1, "indexer").unwrap();
+		assert_eq!(get(&db, 1, "indexer").unwrap(), None);
+		assert_eq!(get(&db, 1, "archiver").unwrap(), Some(b"archived".to_vec()));
+
+		assert!(put(&db, 1, "", b"value").is_err());
+		assert!(put(&db, 1, &"n".repeat(MAX_NAME_LENGTH + 1), b"value").is_err());
+		assert!(put(&db, 1, "indexer", &vec![0u8; MAX_VALUE_SIZE + 1]).is_err());
+	}
+}
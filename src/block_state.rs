@@ -0,0 +1,116 @@
+//! Processing state of the received blocks.
+//!
+//! Each block received by the light client goes through the following states,
+//! which are persisted in the database and exposed on the `/v2/blocks/{block_number}/state` endpoint:
+//!
+//! `Received` → `ProofsFetched` → `Verified` → `AppDataReconstructed` → `Finalized`
+//!
+//! `AppDataReconstructed` is skipped if application client is not running, or block has no data of the configured app.
+//! Blocks without application data, which commitments match the already sampled empty block, end up in the `Empty` state.
+//! Block processing can end up in the `Failed` state with the failure reason, from any non final state.
+//! Since headers can be received again (e.g. on restart), transition into `Received` is always allowed.
+
+use codec::{Decode, Encode};
+use color_eyre::{eyre::eyre, Result};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::data::{Database, Key};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+#[serde(tag = "state", content = "reason", rename_all = "kebab-case")]
+pub enum BlockState {
+	/// Block header is received
+	Received,
+	/// Cells and proofs for random sampling are fetched and proofs are verified
+	ProofsFetched,
+	/// Block confidence is calculated
+	Verified,
+	/// Application data is reconstructed and stored
+	AppDataReconstructed,
+	/// Block processing is completed
+	Finalized,
+	/// Block has no application data, and its commitments match the sampled empty block with the same dimensions
+	Empty,
+	/// Block processing failed with the given reason
+	Failed(String),
+}
+
+impl BlockState {
+	/// Checks if transition from the current state (if any) into the next state is valid
+	pub fn can_transition(current: Option<&BlockState>, next: &BlockState) -> bool {
+		use BlockState::*;
+
+		match (current, next) {
+			(_, Received) => true,
+			(Some(Received), ProofsFetched) => true,
+			(Some(ProofsFetched), Verified) => true,
+			(Some(Verified), AppDataReconstructed) => true,
+			(Some(Received | Verified | AppDataReconstructed), Finalized) => true,
+			(Some(Received), Empty) => true,
+			(Some(Finalized | Empty | Failed(_)), Failed(_)) => false,
+			(Some(_), Failed(_)) => true,
+			_ => false,
+		}
+	}
+}
+
+/// Gets the persisted processing state of the block
+pub fn get(db: &impl Database, block_number: u32) -> Result<Option<BlockState>> {
+	db.get(Key::BlockState(block_number))
+}
+
+/// Transitions block into the next state and persists it, fails if transition is not valid
+pub fn transition(db: &impl Database, block_number: u32, next: BlockState) -> Result<()> {
+	let current = get(db, block_number)?;
+	if !BlockState::can_transition(current.as_ref(), &next) {
+		return Err(eyre!(
+			"Invalid block {block_number} state transition from {current:?} to {next:?}"
+		));
+	}
+	db.put(Key::BlockState(block_number), next)
+}
+
+/// Transitions block into the next state, logging the failed transition.
+/// Block state is informational, so failed transition doesn't stop block processing.
+pub fn update(db: &impl Database, block_number: u32, next: BlockState) {
+	if let Err(error) = transition(db, block_number, next) {
+		debug!(block_number, "Cannot update block state: {error:#}");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{get, transition, BlockState};
+	use crate::data::mem_db::MemoryDB;
+	use test_case::test_case;
+
+	#[test_case(None, BlockState::Received => true)]
+	#[test_case(None, BlockState::Verified => false)]
+	#[test_case(Some(BlockState::Received), BlockState::ProofsFetched => true)]
+	#[test_case(Some(BlockState::Received), BlockState::Verified => false)]
+	#[test_case(Some(BlockState::Received), BlockState::Finalized => true)]
+	#[test_case(Some(BlockState::Verified), BlockState::Finalized => true)]
+	#[test_case(Some(BlockState::ProofsFetched), BlockState::Finalized => false)]
+	#[test_case(Some(BlockState::Received), BlockState::Empty => true)]
+	#[test_case(Some(BlockState::Empty), BlockState::Failed("timeout".into()) => false)]
+	#[test_case(Some(BlockState::Verified), BlockState::Failed("timeout".into()) => true)]
+	#[test_case(Some(BlockState::Finalized), BlockState::Failed("timeout".into()) => false)]
+	#[test_case(Some(BlockState::Finalized), BlockState::Received => true)]
+	fn can_transition(current: Option<BlockState>, next: BlockState) -> bool {
+		BlockState::can_transition(current.as_ref(), &next)
+	}
+
+	#[test]
+	fn transitions() {
+		let db = MemoryDB::default();
+		assert_eq!(get(&db, 1).unwrap(), None);
+		assert!(transition(&db, 1, BlockState::Verified).is_err());
+		transition(&db, 1, BlockState::Received).unwrap();
+		transition(&db, 1, BlockState::ProofsFetched).unwrap();
+		transition(&db, 1, BlockState::Verified).unwrap();
+		transition(&db, 1, BlockState::AppDataReconstructed).unwrap();
+		transition(&db, 1, BlockState::Finalized).unwrap();
+		assert_eq!(get(&db, 1).unwrap(), Some(BlockState::Finalized));
+	}
+}
@@ -0,0 +1,245 @@
+//! Expected block time tracking and missed block detection.
+//!
+//! Arrival times of finalized headers are tracked to estimate the expected block time,
+//! as a median of the recent inter-block intervals. If no header arrives within the expected
+//! block time multiplied by the configured factor, the chain head is probed over HTTP JSON-RPC,
+//! to distinguish a stalled chain from the broken connection to the full node.
+//! Missed blocks are counted in metrics and published to the event bus, from which they are
+//! optionally posted to the configured webhook.
+
+use color_eyre::Result;
+use hyper::{Body, Method, Request};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+use tokio::{
+	sync::broadcast::{self, error::RecvError},
+	time::{self, Instant},
+};
+use tracing::{debug, info, warn};
+
+use crate::{
+	event_bus::{self, EventBus},
+	network::rpc::{self, Event, HttpClient},
+	telemetry::{MetricCounter, MetricValue, Metrics},
+	types::MissedBlockConfig,
+};
+
+/// Number of the recent inter-block intervals used for the estimation
+const MAX_SAMPLES: usize = 32;
+
+/// Estimates expected block time from the arrival times of the headers
+pub struct BlockTimeTracker {
+	default: Duration,
+	last_header: Option<(u32, Instant)>,
+	intervals: VecDeque<Duration>,
+}
+
+impl BlockTimeTracker {
+	pub fn new(default: Duration) -> Self {
+		BlockTimeTracker {
+			default,
+			last_header: None,
+			intervals: VecDeque::with_capacity(MAX_SAMPLES),
+		}
+	}
+
+	/// Records header arrival, intervals are averaged over the blocks since the previous header
+	pub fn on_header(&mut self, block_number: u32, received_at: Instant) {
+		if let Some((last_number, last_received_at)) = self.last_header {
+			if block_number <= last_number {
+				return;
+			}
+			let blocks = block_number - last_number;
+			let interval = received_at.saturating_duration_since(last_received_at) / blocks;
+			if self.intervals.len() == MAX_SAMPLES {
+				self.intervals.pop_front();
+			}
+			self.intervals.push_back(interval);
+		}
+		self.last_header = Some((block_number, received_at));
+	}
+
+	/// Median of the recent intervals, or configured default if no intervals are recorded
+	pub fn expected(&self) -> Duration {
+		let mut intervals = self.intervals.iter().copied().collect::<Vec<_>>();
+		intervals.sort();
+		intervals
+			.get(intervals.len() / 2)
+			.copied()
+			.filter(|interval| !interval.is_zero())
+			.unwrap_or(self.default)
+	}
+
+	pub fn last_header(&self) -> Option<(u32, Instant)> {
+		self.last_header
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StallKind {
+	/// Full node is reachable, but its finalized head is not progressing
+	ChainStalled,
+	/// Full node finalized new blocks, which were not received over the connection
+	ConnectionBroken,
+	/// Full node cannot be probed
+	NodeUnreachable,
+}
+
+fn classify(last_block_number: Option<u32>, probed: &Result<u32>) -> StallKind {
+	match (last_block_number, probed) {
+		(_, Err(_)) => StallKind::NodeUnreachable,
+		(Some(last), Ok(probed)) if *probed <= last => StallKind::ChainStalled,
+		(_, Ok(_)) => StallKind::ConnectionBroken,
+	}
+}
+
+/// Missed block event, posted to the webhook
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MissedBlockEvent {
+	pub kind: StallKind,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub last_block_number: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub probed_block_number: Option<u32>,
+	/// Seconds since the last received header
+	pub elapsed: u64,
+	/// Estimated block time in seconds
+	pub expected_block_time: f64,
+}
+
+async fn post_webhook(client: &HttpClient, url: &str, event: &MissedBlockEvent) -> Result<()> {
+	let request = Request::builder()
+		.method(Method::POST)
+		.uri(url)
+		.header("content-type", "application/json")
+		.body(Body::from(serde_json::to_vec(event)?))?;
+	client.send(request).await?;
+	Ok(())
+}
+
+/// Tracks finalized headers and reports missed blocks. Returns immediately if detection is disabled.
+pub async fn run(
+	cfg: MissedBlockConfig,
+	client: HttpClient,
+	mut rpc_event_receiver: broadcast::Receiver<Event>,
+	events: EventBus,
+	metrics: Arc<impl Metrics>,
+) {
+	if cfg.multiplier == 0 {
+		debug!("Missed block detection is disabled");
+		return;
+	}
+
+	let mut tracker = BlockTimeTracker::new(cfg.expected_block_time);
+	let started_at = Instant::now();
+	let mut reported = false;
+
+	loop {
+		let last_received_at = tracker
+			.last_header()
+			.map_or(started_at, |(_, received_at)| received_at);
+		let deadline = last_received_at + tracker.expected() * cfg.multiplier;
+
+		tokio::select! {
+			result = rpc_event_receiver.recv() => match result {
+				Ok(Event::HeaderUpdate { header, received_at }) => {
+					tracker.on_header(header.number, Instant::from_std(received_at));
+					let expected = tracker.expected();
+					metrics.record(MetricValue::ExpectedBlockTime(expected.as_secs_f64())).await;
+					if std::mem::take(&mut reported) {
+						info!(block_number = header.number, "Finalized headers resumed");
+					}
+				},
+				Err(RecvError::Lagged(_)) => continue,
+				Err(RecvError::Closed) => return,
+			},
+			_ = time::sleep_until(deadline), if !reported => {
+				reported = true;
+				let last_block_number = tracker.last_header().map(|(number, _)| number);
+				let probed = rpc::finalized_block_number(&client, &cfg.probe_urls).await;
+				let event = MissedBlockEvent {
+					kind: classify(last_block_number, &probed),
+					last_block_number,
+					probed_block_number: probed.as_ref().ok().copied(),
+					elapsed: last_received_at.elapsed().as_secs(),
+					expected_block_time: tracker.expected().as_secs_f64(),
+				};
+				warn!(
+					kind = ?event.kind,
+					last_block_number,
+					probed_block_number = event.probed_block_number,
+					elapsed = event.elapsed,
+					"No finalized header received within expected block time"
+				);
+				let counter = match event.kind {
+					StallKind::ChainStalled => MetricCounter::ChainStalls,
+					_ => MetricCounter::ConnectionStalls,
+				};
+				metrics.count(counter).await;
+				events.publish(event_bus::Event::Degraded(event));
+			},
+		}
+	}
+}
+
+/// Posts missed block events to the webhook
+pub async fn post_missed_blocks(
+	url: String,
+	client: HttpClient,
+	mut receiver: broadcast::Receiver<MissedBlockEvent>,
+) {
+	loop {
+		match receiver.recv().await {
+			Ok(event) => {
+				if let Err(error) = post_webhook(&client, &url, &event).await {
+					warn!("Missed block webhook failed: {error:#}");
+				}
+			},
+			Err(RecvError::Lagged(_)) => continue,
+			Err(RecvError::Closed) => return,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{classify, BlockTimeTracker, StallKind};
+	use color_eyre::eyre::eyre;
+	use std::time::Duration;
+	use tokio::time::Instant;
+
+	#[test]
+	fn expected_block_time() {
+		let default = Duration::from_secs(20);
+		let mut tracker = BlockTimeTracker::new(default);
+		let start = Instant::now();
+		tracker.on_header(1, start);
+		assert_eq!(tracker.expected(), default);
+
+		tracker.on_header(2, start + Duration::from_secs(18));
+		tracker.on_header(3, start + Duration::from_secs(38));
+		// Interval over the skipped block is averaged
+		tracker.on_header(5, start + Duration::from_secs(80));
+		assert_eq!(tracker.expected(), Duration::from_secs(20));
+
+		// Outlier doesn't affect the median
+		tracker.on_header(6, start + Duration::from_secs(200));
+		assert_eq!(tracker.expected(), Duration::from_secs(21));
+
+		// Repeated and older headers are ignored
+		tracker.on_header(6, start + Duration::from_secs(300));
+		assert_eq!(tracker.last_header().map(|(number, _)| number), Some(6));
+	}
+
+	#[test]
+	fn stall_kind() {
+		assert_eq!(classify(Some(10), &Ok(10)), StallKind::ChainStalled);
+		assert_eq!(classify(Some(10), &Ok(12)), StallKind::ConnectionBroken);
+		assert_eq!(classify(None, &Ok(12)), StallKind::ConnectionBroken);
+		assert_eq!(
+			classify(Some(10), &Err(eyre!("timeout"))),
+			StallKind::NodeUnreachable
+		);
+	}
+}
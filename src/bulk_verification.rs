@@ -0,0 +1,383 @@
+//! Bulk verification of the historical blocks.
+//!
+//! Verification job is submitted with a list of block numbers, and blocks are verified one by one
+//! using the sync client pipeline, in the order of submission. Progress of the job can be polled
+//! until it is completed. Jobs are scheduled into the persistent [`JobQueue`], so pending jobs survive restarts,
+//! and number of pending jobs is limited. Completed jobs are retained for a limited time, up to a limited number.
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::sync::{broadcast, Notify};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::{
+	data::Database,
+	jobs::{self, Job, JobKind, JobQueue},
+	network,
+	sync_client::{self, Client},
+	types::{BlockVerified, ExponentialConfig, RetryConfig, SyncClientConfig},
+};
+
+/// Maximum number of pending jobs, above which submissions are rejected
+pub const MAX_PENDING_JOBS: usize = 16;
+
+/// Number of completed jobs retained for polling
+const MAX_COMPLETED_JOBS: usize = 100;
+
+/// Duration in seconds for which completed jobs are retained for polling
+const COMPLETED_JOB_RETENTION: u64 = 3600;
+
+/// Duration after which job is leased again, if it wasn't completed (e.g. on restart)
+const JOB_LEASE: Duration = Duration::from_secs(60);
+
+/// Interval in which the queue is checked for ready jobs, if no job is submitted
+const JOB_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobState {
+	Pending,
+	Running,
+	Completed,
+}
+
+/// Progress of the verification job
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JobStatus {
+	pub id: u64,
+	pub state: JobState,
+	/// Number of blocks to verify
+	pub total: usize,
+	/// Number of processed blocks
+	pub processed: usize,
+	/// Number of successfully verified blocks
+	pub verified: usize,
+	/// Blocks which failed verification
+	pub failed: Vec<u32>,
+	/// Unix timestamp in seconds of the job completion
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub completed_at: Option<u64>,
+}
+
+impl JobStatus {
+	fn pending(id: u64, total: usize) -> Self {
+		JobStatus {
+			id,
+			state: JobState::Pending,
+			total,
+			processed: 0,
+			verified: 0,
+			failed: vec![],
+			completed_at: None,
+		}
+	}
+}
+
+#[derive(Default)]
+struct Jobs {
+	statuses: BTreeMap<u64, JobStatus>,
+}
+
+impl Jobs {
+	fn update(&mut self, id: u64, update: impl FnOnce(&mut JobStatus)) {
+		if let Some(status) = self.statuses.get_mut(&id) {
+			update(status);
+		}
+	}
+
+	/// Removes completed jobs after the retention period, and the oldest completed jobs above the retention limit
+	fn prune(&mut self, now: u64) {
+		self.statuses.retain(|_, status| {
+			status
+				.completed_at
+				.is_none_or(|completed_at| completed_at + COMPLETED_JOB_RETENTION > now)
+		});
+		let completed = self
+			.statuses
+			.values()
+			.filter(|status| status.state == JobState::Completed)
+			.map(|status| status.id)
+			.collect::<Vec<_>>();
+		let excess = completed.len().saturating_sub(MAX_COMPLETED_JOBS);
+		for id in completed.into_iter().take(excess) {
+			self.statuses.remove(&id);
+		}
+	}
+}
+
+/// Registry of the verification jobs, shared between the API and the verification worker
+#[derive(Clone)]
+pub struct BulkVerification<T: Database> {
+	jobs: Arc<Mutex<Jobs>>,
+	queue: JobQueue<T>,
+	submitted: Arc<Notify>,
+}
+
+impl<T: Database> BulkVerification<T> {
+	/// Creates job registry, with jobs stored in the given database
+	pub fn new(db: T) -> Self {
+		// Jobs are not retried, since blocks which failed verification are reported in the job status
+		let no_retries = RetryConfig::Exponential(ExponentialConfig {
+			base: 1,
+			max_delay: 0,
+			retries: 0,
+		});
+		let queue = JobQueue::new("bulk_verification", db, no_retries, JOB_LEASE);
+		BulkVerification {
+			jobs: Arc::new(Mutex::new(Jobs::default())),
+			queue,
+			submitted: Arc::new(Notify::new()),
+		}
+	}
+
+	/// Schedules verification of the given blocks and returns job ID.
+	/// Returns `None` if there are already [`MAX_PENDING_JOBS`] pending jobs.
+	pub fn submit(&self, blocks: Vec<u32>) -> Result<Option<u64>> {
+		let mut jobs = self.jobs.lock().expect("Lock should be acquired");
+		if self.queue.len()? >= MAX_PENDING_JOBS {
+			return Ok(None);
+		}
+		let total = blocks.len();
+		let id = self.queue.push(JobKind::VerifyBlocks { blocks }, 0)?;
+		// Same job can be already scheduled, in which case its progress is kept
+		jobs.statuses
+			.entry(id)
+			.or_insert_with(|| JobStatus::pending(id, total));
+		self.submitted.notify_one();
+		Ok(Some(id))
+	}
+
+	/// Returns status of the job, if job exists
+	pub fn status(&self, id: u64) -> Result<Option<JobStatus>> {
+		let mut jobs = self.jobs.lock().expect("Lock should be acquired");
+		jobs.prune(jobs::now());
+		if let Some(status) = jobs.statuses.get(&id) {
+			return Ok(Some(status.clone()));
+		}
+		// Jobs scheduled before restart are pending until leased
+		let status = match self.queue.get(id)? {
+			Some(Job {
+				kind: JobKind::VerifyBlocks { blocks },
+				..
+			}) => Some(JobStatus::pending(id, blocks.len())),
+			_ => None,
+		};
+		Ok(status)
+	}
+
+	fn update(&self, id: u64, update: impl FnOnce(&mut JobStatus)) {
+		let mut jobs = self.jobs.lock().expect("Lock should be acquired");
+		jobs.update(id, update);
+	}
+
+	/// Waits for the next ready job, returning its ID and blocks to verify
+	async fn next_job(&self) -> Result<(u64, Vec<u32>)> {
+		loop {
+			let Some(job) = self.queue.lease(jobs::now())? else {
+				_ = tokio::time::timeout(JOB_POLL_INTERVAL, self.submitted.notified()).await;
+				continue;
+			};
+			let JobKind::VerifyBlocks { blocks } = job.kind else {
+				warn!(job_id = job.id, "Dropping job of unexpected kind");
+				self.queue.complete(job.id)?;
+				continue;
+			};
+			return Ok((job.id, blocks));
+		}
+	}
+
+	fn complete(&self, id: u64) -> Result<()> {
+		let mut jobs = self.jobs.lock().expect("Lock should be acquired");
+		let now = jobs::now();
+		jobs.update(id, |status| {
+			status.state = JobState::Completed;
+			status.completed_at = Some(now);
+		});
+		jobs.prune(now);
+		self.queue.complete(id)
+	}
+}
+
+/// Runs verification worker, processing submitted jobs one at a time.
+/// Worker stops once the token is cancelled, cancelling verification of the in-flight block.
+pub async fn run<T: Database>(
+	bulk_verification: BulkVerification<T>,
+	client: impl Client,
+	network_client: impl network::Client,
+	cfg: SyncClientConfig,
+	block_verified_sender: broadcast::Sender<BlockVerified>,
+	cancel: CancellationToken,
+) {
+	loop {
+		let (id, blocks) = match bulk_verification.next_job().await {
+			Ok(job) => job,
+			Err(error) => {
+				error!("Cannot get bulk verification job: {error:#}");
+				return;
+			},
+		};
+		info!(
+			job_id = id,
+			blocks = blocks.len(),
+			"Starting bulk verification"
+		);
+		{
+			// Status of the job scheduled before restart is created once leased
+			let mut jobs = bulk_verification
+				.jobs
+				.lock()
+				.expect("Lock should be acquired");
+			let status = jobs
+				.statuses
+				.entry(id)
+				.or_insert_with(|| JobStatus::pending(id, blocks.len()));
+			status.state = JobState::Running;
+		}
+
+		for block_number in blocks {
+			let result = sync_client::verify_block(
+				&client,
+				&network_client,
+				block_number,
+				&cfg,
+				block_verified_sender.clone(),
+				&cancel,
+			)
+			.await;
+			if cancel.is_cancelled() {
+				info!(job_id = id, block_number, "Bulk verification is cancelled");
+				return;
+			}
+			let verified = match result {
+				Ok(verified) => verified,
+				Err(error) => {
+					error!(job_id = id, block_number, "Cannot verify block: {error:#}");
+					false
+				},
+			};
+			bulk_verification.update(id, |status| {
+				status.processed += 1;
+				match verified {
+					true => status.verified += 1,
+					false => status.failed.push(block_number),
+				}
+			});
+		}
+
+		if let Err(error) = bulk_verification.complete(id) {
+			error!(
+				job_id = id,
+				"Cannot complete bulk verification job: {error:#}"
+			);
+		}
+		info!(job_id = id, "Bulk verification completed");
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		BulkVerification, JobState, COMPLETED_JOB_RETENTION, MAX_COMPLETED_JOBS, MAX_PENDING_JOBS,
+	};
+	use crate::{
+		data::mem_db::MemoryDB,
+		network::MockClient as MockNetworkClient,
+		sync_client::MockClient,
+		types::{RuntimeConfig, SyncClientConfig},
+	};
+	use color_eyre::eyre::eyre;
+	use tokio::sync::broadcast;
+	use tokio_util::sync::CancellationToken;
+
+	#[test]
+	fn submit_and_prune() {
+		let bulk_verification = BulkVerification::new(MemoryDB::default());
+		let id = bulk_verification.submit(vec![1, 2, 3]).unwrap().unwrap();
+		let status = bulk_verification.status(id).unwrap().unwrap();
+		assert_eq!(status.state, JobState::Pending);
+		assert_eq!(status.total, 3);
+		assert_eq!(bulk_verification.status(id + 1).unwrap(), None);
+
+		let mut jobs = bulk_verification.jobs.lock().unwrap();
+		let ids = (1..MAX_COMPLETED_JOBS as u64 + 3)
+			.map(|index| {
+				let id = id + index;
+				let mut status = jobs.statuses[&0].clone();
+				status.id = id;
+				status.state = JobState::Completed;
+				status.completed_at = Some(1000);
+				jobs.statuses.insert(id, status);
+				id
+			})
+			.collect::<Vec<_>>();
+		jobs.prune(1000);
+		// Pending job is retained, while the oldest completed jobs are removed
+		assert!(jobs.statuses.contains_key(&id));
+		assert!(!jobs.statuses.contains_key(&ids[0]));
+		assert!(!jobs.statuses.contains_key(&ids[1]));
+		assert_eq!(jobs.statuses.len(), MAX_COMPLETED_JOBS + 1);
+
+		// Completed jobs expire
+		jobs.prune(1000 + COMPLETED_JOB_RETENTION);
+		assert_eq!(jobs.statuses.len(), 1);
+		assert!(jobs.statuses.contains_key(&id));
+	}
+
+	#[test]
+	fn pending_jobs_limit() {
+		let db = MemoryDB::default();
+		let bulk_verification = BulkVerification::new(db.clone());
+		for block_number in 0..MAX_PENDING_JOBS as u32 {
+			assert!(bulk_verification
+				.submit(vec![block_number])
+				.unwrap()
+				.is_some());
+		}
+		assert_eq!(bulk_verification.submit(vec![100]).unwrap(), None);
+
+		// Pending jobs are persisted
+		let bulk_verification = BulkVerification::new(db);
+		let status = bulk_verification.status(0).unwrap().unwrap();
+		assert_eq!(status.state, JobState::Pending);
+		assert_eq!(status.total, 1);
+		assert_eq!(bulk_verification.submit(vec![100]).unwrap(), None);
+	}
+
+	#[tokio::test]
+	async fn failed_blocks() {
+		let bulk_verification = BulkVerification::new(MemoryDB::default());
+		let mut client = MockClient::new();
+		client
+			.expect_get_header_by_block_number()
+			.returning(|block_number| Err(eyre!("Header {block_number} not found")));
+		let (block_tx, _) = broadcast::channel(1);
+
+		let id = bulk_verification.submit(vec![1, 2]).unwrap().unwrap();
+		let worker = tokio::spawn(super::run(
+			bulk_verification.clone(),
+			client,
+			MockNetworkClient::new(),
+			SyncClientConfig::from(&RuntimeConfig::default()),
+			block_tx,
+			CancellationToken::new(),
+		));
+		while bulk_verification.status(id).unwrap().unwrap().state != JobState::Completed {
+			tokio::task::yield_now().await;
+		}
+		worker.abort();
+
+		let status = bulk_verification.status(id).unwrap().unwrap();
+		assert_eq!(status.processed, 2);
+		assert_eq!(status.verified, 0);
+		assert_eq!(status.failed, vec![1, 2]);
+		assert!(status.completed_at.is_some());
+		// Completed job is removed from the queue
+		assert!(bulk_verification.queue.is_empty().unwrap());
+	}
+}
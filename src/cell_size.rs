@@ -0,0 +1,188 @@
+//! Sizes of the matrix cells, which are parameters of the chain.
+//!
+//! Cell is the scalar (chunk) of the extended matrix followed by its KZG proof, and proof is of the same size
+//! as the row commitment in the block header. Cells fetched from the full nodes and from the DHT are decoded
+//! using the configured sizes, which are checked against the commitments of the received headers.
+//! Proofs are verified by `kate_recovery`, which supports only its own cell layout ([`CELL_WITH_PROOF_SIZE`]),
+//! so blocks of a chain with other cell sizes fail to be verified, and the light client logs it on startup.
+
+use avail_subxt::primitives::Header;
+use color_eyre::{eyre::eyre, Result};
+use kate_recovery::{
+	config,
+	data::Cell,
+	matrix::{Dimensions, Position},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{types::CELL_WITH_PROOF_SIZE, utils::extract_kate};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct CellSize {
+	/// Size of the cell scalar in bytes (default: 32)
+	#[serde(rename = "cell_chunk_size")]
+	pub chunk_size: usize,
+	/// Size of the cell proof in bytes, which is the size of the row commitment (default: 48)
+	#[serde(rename = "cell_proof_size")]
+	pub proof_size: usize,
+}
+
+impl Default for CellSize {
+	fn default() -> Self {
+		Self {
+			chunk_size: config::CHUNK_SIZE,
+			proof_size: config::COMMITMENT_SIZE,
+		}
+	}
+}
+
+impl CellSize {
+	/// Size of the cell scalar followed by its proof
+	pub fn cell_with_proof(&self) -> usize {
+		self.chunk_size + self.proof_size
+	}
+
+	/// Checks if cells of this size can be verified by `kate_recovery`
+	pub fn is_supported(&self) -> bool {
+		*self == Self::default()
+	}
+
+	/// Checks that cells of the given block are of this size and that they can be verified
+	pub fn check(&self, header: &Header) -> Result<()> {
+		let Some((rows, cols, _, commitment)) = extract_kate(&header.extension) else {
+			return Ok(());
+		};
+		let Some(dimensions) = Dimensions::new(rows, cols) else {
+			return Err(eyre!("Invalid block dimensions {rows}x{cols}"));
+		};
+		let extended_rows = dimensions.extended_rows() as usize;
+		if commitment.len() != extended_rows * self.proof_size {
+			return Err(eyre!(
+				"Block {} has {} bytes of commitments for {extended_rows} rows, expected {} bytes per row",
+				header.number,
+				commitment.len(),
+				self.proof_size
+			));
+		}
+		if !self.is_supported() {
+			return Err(eyre!(
+				"Cells of {} bytes chunks and {} bytes proofs cannot be verified, only {} and {} bytes are supported",
+				self.chunk_size,
+				self.proof_size,
+				config::CHUNK_SIZE,
+				config::COMMITMENT_SIZE
+			));
+		}
+		Ok(())
+	}
+
+	/// Creates cell from its proof and scalar
+	pub fn cell(&self, position: Position, proof: &[u8], chunk: &[u8]) -> Result<Cell> {
+		if proof.len() != self.proof_size || chunk.len() != self.chunk_size {
+			return Err(eyre!(
+				"Invalid cell of {} bytes proof and {} bytes chunk, expected {} and {} bytes",
+				proof.len(),
+				chunk.len(),
+				self.proof_size,
+				self.chunk_size
+			));
+		}
+		if !self.is_supported() {
+			return Err(eyre!(
+				"Cell size of {} bytes is not supported, expected {CELL_WITH_PROOF_SIZE} bytes",
+				self.cell_with_proof()
+			));
+		}
+		let mut content = [0u8; CELL_WITH_PROOF_SIZE];
+		content[..self.proof_size].copy_from_slice(proof);
+		content[self.proof_size..].copy_from_slice(chunk);
+		Ok(Cell { position, content })
+	}
+
+	/// Decodes cell from the proof followed by the scalar
+	pub fn decode(&self, position: Position, value: &[u8]) -> Result<Cell> {
+		if value.len() != self.cell_with_proof() {
+			return Err(eyre!(
+				"Invalid cell value length {}, expected {}",
+				value.len(),
+				self.cell_with_proof()
+			));
+		}
+		let (proof, chunk) = value.split_at(self.proof_size);
+		self.cell(position, proof, chunk)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::CellSize;
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		config::substrate::Digest,
+		primitives::Header,
+		utils::H256,
+	};
+	use kate_recovery::matrix::Position;
+
+	fn header(commitment: Vec<u8>) -> Header {
+		Header {
+			parent_hash: H256::zero(),
+			number: 1,
+			state_root: H256::zero(),
+			extrinsics_root: H256::zero(),
+			digest: Digest { logs: vec![] },
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment {
+					rows: 1,
+					cols: 4,
+					data_root: H256::zero(),
+					commitment,
+				},
+				app_lookup: CompactDataLookup {
+					size: 1,
+					index: vec![],
+				},
+			}),
+		}
+	}
+
+	#[test]
+	fn check_header() {
+		let cell_size = CellSize::default();
+		// Commitments are provided for the extended rows
+		assert!(cell_size.check(&header(vec![0u8; 96])).is_ok());
+		assert!(cell_size.check(&header(vec![0u8; 48])).is_err());
+		assert!(cell_size.check(&header(vec![0u8; 192])).is_err());
+
+		// Sizes match the chain, but cells cannot be verified
+		let cell_size = CellSize {
+			chunk_size: 32,
+			proof_size: 96,
+		};
+		assert!(cell_size.check(&header(vec![0u8; 192])).is_err());
+	}
+
+	#[test]
+	fn decode_cell() {
+		let position = Position { row: 1, col: 2 };
+		let mut value = vec![1u8; 48];
+		value.extend([2u8; 32]);
+
+		let cell = CellSize::default().decode(position, &value).unwrap();
+		assert_eq!(cell.position, position);
+		assert_eq!(&cell.content[..48], &[1u8; 48]);
+		assert_eq!(&cell.content[48..], &[2u8; 32]);
+
+		assert!(CellSize::default().decode(position, &value[1..]).is_err());
+		let cell_size = CellSize {
+			chunk_size: 31,
+			proof_size: 49,
+		};
+		assert!(cell_size.decode(position, &value).is_err());
+	}
+}
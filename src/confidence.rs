@@ -0,0 +1,121 @@
+//! Confidence that the block data is available.
+//!
+//! Confidence is calculated from the number of verified cells, assuming that the configured fraction
+//! of the cells is withheld (see `withheld_fraction` in [`crate::types::RuntimeConfig`]). It is expressed in percents,
+//! serialized as a plain number, and as a fixed-point number (with 7 decimals) combined with the block number
+//! in the `serialised_confidence` field of the V1 API.
+
+use num::{BigUint, FromPrimitive};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use tracing::info;
+
+/// Multiplier of the fixed-point confidence representation
+const FIXED_POINT_SCALE: f64 = 10_000_000.0;
+
+/// Number of cells required for 99.99% confidence, with the default withheld fraction
+pub const CELL_COUNT_99_99: u32 = 14;
+
+/// Confidence in percents that the block data is available
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Confidence(f64);
+
+impl Confidence {
+	/// Confidence from the value in percents
+	pub fn new(value: f64) -> Self {
+		Confidence(value)
+	}
+
+	/// Confidence after the given number of cells is verified, assuming that the given fraction of cells is withheld.
+	/// Each sampled cell is available with probability `1 - fraction`, so confidence is `1 - (1 - fraction)^count`.
+	pub fn from_cell_count(count: u32, withheld_fraction: f64) -> Self {
+		Confidence(100f64 * (1f64 - (1f64 - withheld_fraction).powi(count as i32)))
+	}
+
+	/// Confidence in percents
+	pub fn value(&self) -> f64 {
+		self.0
+	}
+
+	/// Checks if confidence reached the threshold
+	pub fn reaches(&self, threshold: Confidence) -> bool {
+		self.0 >= threshold.0
+	}
+
+	/// Number of cells which need to be verified to reach this confidence, assuming that the given fraction
+	/// of cells is withheld. Confidence outside of the 50-100% range falls back to 99.3%,
+	/// and the number of cells is limited to the one required for 99.99% confidence.
+	pub fn cell_count(&self, withheld_fraction: f64) -> u32 {
+		let max_cell_count = cells_required(99.99, withheld_fraction);
+		let mut cell_count: u32;
+		if !(50.0..=100f64).contains(&self.0) {
+			info!(
+				"confidence is {} invalid so taking default confidence of 99",
+				self.0
+			);
+			cell_count = cells_required(99.3, withheld_fraction);
+		} else {
+			cell_count = cells_required(self.0, withheld_fraction);
+		}
+		if cell_count <= 1 {
+			info!(
+				"confidence of {} is too low so taking confidence of 50.0",
+				self.0
+			);
+			cell_count = 1;
+		} else if cell_count > max_cell_count {
+			info!(
+				"confidence of {} is invalid so taking confidence of 99.99",
+				self.0
+			);
+			cell_count = max_cell_count;
+		}
+		cell_count
+	}
+
+	/// Fixed-point confidence in the lower 32 bits, and block number in the upper bits, as a decimal string
+	pub fn serialised(&self, block_number: u32) -> Option<String> {
+		let block: BigUint = FromPrimitive::from_u32(block_number)?;
+		let confidence: BigUint = FromPrimitive::from_u64((FIXED_POINT_SCALE * self.0) as u64)?;
+		Some((block << 32 | confidence).to_str_radix(10))
+	}
+}
+
+/// Number of cells which need to be verified to reach the given confidence in percents,
+/// assuming that the given fraction of cells is withheld.
+fn cells_required(confidence: f64, withheld_fraction: f64) -> u32 {
+	((1f64 - confidence / 100f64).log2() / (1f64 - withheld_fraction).log2()).ceil() as u32
+}
+
+impl From<Confidence> for f64 {
+	fn from(confidence: Confidence) -> Self {
+		confidence.0
+	}
+}
+
+impl Display for Confidence {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		Display::fmt(&self.0, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Confidence;
+
+	#[test]
+	fn confidence_threshold() {
+		assert!(Confidence::from_cell_count(25, 0.25).reaches(Confidence::new(99.9)));
+		assert!(Confidence::from_cell_count(1, 0.5).reaches(Confidence::new(50.0)));
+		assert!(!Confidence::from_cell_count(1, 0.5).reaches(Confidence::new(50.1)));
+	}
+
+	#[test]
+	fn serialised_confidence() {
+		let confidence = Confidence::from_cell_count(1, 0.5);
+		let expected = ((1u64 << 32) | 500_000_000).to_string();
+		assert_eq!(confidence.serialised(1), Some(expected));
+		assert_eq!(serde_json::to_string(&confidence).unwrap(), "50.0");
+	}
+}
@@ -1,11 +1,14 @@
+use avail_subxt::utils::H256;
 use codec::{Decode, Encode};
 use color_eyre::eyre::Result;
 use serde::{Deserialize, Serialize};
 use sp_core::ed25519;
 
+pub mod encryption;
+pub mod maintenance;
 pub mod rocks_db;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "bench"))]
 pub mod mem_db;
 
 pub trait Database {
@@ -26,6 +29,21 @@ pub trait Database {
 
 	/// Deletes value from the database for the given key.
 	fn delete(&self, key: Key) -> Result<()>;
+
+	/// Lists up to `limit` entries of the verification index after the given verification time and block number,
+	/// ordered by verification time and block number.
+	fn verified_after(
+		&self,
+		verified_at: u64,
+		block_number: u32,
+		limit: usize,
+	) -> Result<Vec<(u64, u32)>>;
+
+	/// Gets on-disk statistics of the database.
+	fn stats(&self) -> Result<StorageStats>;
+
+	/// Checks if there are no values stored in the database.
+	fn is_empty(&self) -> Result<bool>;
 }
 
 /// Column family for confidence factor
@@ -43,15 +61,186 @@ pub const STATE_CF: &str = "avail_light_state_cf";
 /// Column family for Kademlia store
 pub const KADEMLIA_STORE_CF: &str = "avail_light_kademlia_store_cf";
 
+/// Column family for sampled cells
+pub const SAMPLED_CELLS_CF: &str = "avail_light_sampled_cells_cf";
+
+/// Column family for block processing state
+pub const BLOCK_STATE_CF: &str = "avail_light_block_state_cf";
+
+/// Column family for block retention checks
+pub const RETENTION_CHECK_CF: &str = "avail_light_retention_check_cf";
+
+/// Column family for block verification timestamps
+pub const VERIFIED_AT_CF: &str = "avail_light_verified_at_cf";
+
+/// Column family for block metadata attached by integrators
+pub const BLOCK_METADATA_CF: &str = "avail_light_block_metadata_cf";
+
+/// Column family for the blocks sampled in degraded mode
+pub const DEGRADED_CF: &str = "avail_light_degraded_cf";
+
+/// Column family for the blocks indexed by the verification time
+pub const VERIFIED_INDEX_CF: &str = "avail_light_verified_index_cf";
+
+/// Column families of the database, with the names used in the storage statistics
+pub const COLUMN_FAMILIES: [(&str, &str); 12] = [
+	(BLOCK_HEADER_CF, "headers"),
+	(CONFIDENCE_FACTOR_CF, "confidence"),
+	(VERIFIED_AT_CF, "verified_at"),
+	(SAMPLED_CELLS_CF, "sampled_cells"),
+	(BLOCK_STATE_CF, "block_states"),
+	(RETENTION_CHECK_CF, "retention_checks"),
+	(BLOCK_METADATA_CF, "block_metadata"),
+	(DEGRADED_CF, "degraded"),
+	(VERIFIED_INDEX_CF, "verified_index"),
+	(APP_DATA_CF, "app_data"),
+	(KADEMLIA_STORE_CF, "dht_records"),
+	(STATE_CF, "state"),
+];
+
 /// Sync finality checkpoint key name
 const FINALITY_SYNC_CHECKPOINT_KEY: &str = "finality_sync_checkpoint";
 
+/// Prefix of the job queue key names
+const JOB_QUEUE_KEY_PREFIX: &str = "job_queue";
+
+/// Prefix of the app data usage key names
+const APP_DATA_USAGE_KEY_PREFIX: &str = "app_data_usage";
+
+/// Prefix of the consumer cursor key names
+const CONSUMER_CURSOR_KEY_PREFIX: &str = "consumer_cursor";
+
+/// Storage encryption header key name
+const STORAGE_ENCRYPTION_KEY: &str = "storage_encryption";
+
+/// Telemetry instance ID key name
+const TELEMETRY_INSTANCE_ID_KEY: &str = "telemetry_instance_id";
+
+/// Known peers key name
+const PEER_STORE_KEY: &str = "peer_store";
+
+/// Last compaction timestamp key name
+const LAST_COMPACTION_KEY: &str = "last_compaction";
+
+/// Chain checkpoint key name
+const CHAIN_CHECKPOINT_KEY: &str = "chain_checkpoint";
+
+/// Prefix of the export watermark key names
+const EXPORT_WATERMARK_KEY_PREFIX: &str = "export_watermark";
+
+/// Prefix of the empty block commitments key names
+const EMPTY_BLOCK_COMMITMENTS_KEY_PREFIX: &str = "empty_block_commitments";
+
 #[derive(Clone)]
 pub enum Key {
 	AppData(u32, u32),
 	BlockHeader(u32),
 	VerifiedCellCount(u32),
+	/// Unix timestamp in nanoseconds, when block confidence was stored
+	VerifiedAt(u32),
+	/// Block verified at the given unix timestamp in nanoseconds, iterated in order of verification
+	VerifiedIndex(u64, u32),
+	SampledCells(u32),
+	BlockState(u32),
+	/// Set if the block was sampled from the DHT only, while all full nodes were unreachable
+	Degraded(u32),
+	RetentionCheck(u32),
+	AppDataUsage(u32),
+	/// Metadata of the block with the given name
+	BlockMetadata(u32, String),
 	FinalitySyncCheckpoint,
+	/// Cursor of the verified blocks acknowledged by the consumer, with the given API key hash
+	ConsumerCursor(String),
+	JobQueue(&'static str),
+	StorageEncryption,
+	TelemetryInstanceId,
+	/// Peers to which the node was successfully connected, used for faster bootstrap after restart
+	PeerStore,
+	/// Unix timestamp in seconds of the last offline compaction, stored unencrypted
+	LastCompaction,
+	/// Latest verified block of the chain to which the database belongs
+	ChainCheckpoint,
+	/// Highest block emitted by the gated exporter with the given name
+	ExportWatermark(String),
+	/// Commitments of the sampled block without application data, with the given matrix rows and columns
+	EmptyBlockCommitments(u16, u16),
+}
+
+/// Stores the block verification time, and indexes the block by it
+pub fn put_verified_at(db: &impl Database, block_number: u32, verified_at: u64) -> Result<()> {
+	db.put(Key::VerifiedAt(block_number), verified_at)?;
+	db.put(Key::VerifiedIndex(verified_at, block_number), ())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Decode, Encode)]
+#[serde(rename_all = "lowercase")]
+pub enum CellSource {
+	DHT,
+	RPC,
+}
+
+/// Position of the sampled cell, with the source from which it was fetched
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct SampledCell {
+	pub row: u32,
+	pub col: u16,
+	pub source: CellSource,
+}
+
+/// Result of the latest retention re-check of the block
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct RetentionCheck {
+	/// Number of re-sampled cells
+	pub sampled: u32,
+	/// Number of re-sampled cells retrieved from the DHT and verified
+	pub retrieved: u32,
+	/// Unix timestamp of the check in seconds
+	pub checked_at: u64,
+}
+
+/// Bytes of the app data reconstructed and stored by the app client
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Decode, Encode)]
+pub struct AppDataUsage {
+	/// Total number of reconstructed bytes, including pruned data
+	pub reconstructed_bytes: u64,
+	/// Number of currently stored bytes
+	pub stored_bytes: u64,
+	/// Number of blocks with stored data
+	pub stored_blocks: u32,
+	/// First block which may have stored data
+	pub oldest_block: Option<u32>,
+	/// Number of blocks which data was pruned due to the storage quota
+	pub pruned_blocks: u32,
+}
+
+/// On-disk statistics of the column family
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ColumnFamilyStats {
+	pub name: &'static str,
+	/// Size of the column family files in bytes
+	pub size: u64,
+	/// Estimated number of entries
+	pub entries: u64,
+}
+
+/// On-disk statistics of the database
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct StorageStats {
+	pub column_families: Vec<ColumnFamilyStats>,
+	/// Oldest block with the stored header
+	pub oldest_block: Option<u32>,
+	/// Newest block with the stored header
+	pub newest_block: Option<u32>,
+	/// Unix timestamp in seconds of the last offline compaction
+	pub last_compaction: Option<u64>,
+}
+
+/// Latest verified block, used to check on startup that the connected node follows the same chain
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Decode, Encode)]
+pub struct ChainCheckpoint {
+	pub genesis_hash: H256,
+	pub block_number: u32,
+	pub block_hash: H256,
 }
 
 #[derive(Serialize, Deserialize, Debug, Decode, Encode)]
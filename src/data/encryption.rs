@@ -0,0 +1,296 @@
+//! Encryption of the persistent storage at rest.
+//!
+//! [`EncryptedDB`] wraps the [`Database`] and encrypts stored values with XChaCha20-Poly1305.
+//! Encryption key is either provided directly, or derived from the passphrase using Argon2,
+//! with random salt stored unencrypted along with the key check value.
+//! Encryption secret is read from the environment, so it is not stored in the configuration.
+
+use chacha20poly1305::{
+	aead::{Aead, KeyInit},
+	XChaCha20Poly1305, XNonce,
+};
+use codec::{Decode, Encode};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+use super::{Database, Key, StorageStats};
+
+/// Environment variable with hex encoded 32 bytes encryption key
+pub const STORAGE_KEY_ENV: &str = "AVAIL_LIGHT_STORAGE_KEY";
+/// Environment variable with encryption passphrase
+pub const STORAGE_PASSPHRASE_ENV: &str = "AVAIL_LIGHT_STORAGE_PASSPHRASE";
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const CHECK_VALUE: &[u8] = b"avail-light";
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+	let mut bytes = [0u8; N];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	bytes
+}
+
+/// Secret from which the encryption key is obtained
+#[derive(Clone)]
+pub enum EncryptionSecret {
+	Key([u8; KEY_LEN]),
+	Passphrase(String),
+}
+
+impl EncryptionSecret {
+	/// Reads encryption secret from the environment, key takes precedence over the passphrase
+	pub fn from_env() -> Result<Option<Self>> {
+		if let Ok(key) = env::var(STORAGE_KEY_ENV) {
+			let key = hex::decode(key.trim().trim_start_matches("0x"))
+				.wrap_err_with(|| format!("Invalid {STORAGE_KEY_ENV}"))?;
+			let key = key
+				.try_into()
+				.map_err(|_| eyre!("{STORAGE_KEY_ENV} must be {KEY_LEN} bytes long"))?;
+			return Ok(Some(EncryptionSecret::Key(key)));
+		}
+		Ok(env::var(STORAGE_PASSPHRASE_ENV)
+			.ok()
+			.map(EncryptionSecret::Passphrase))
+	}
+
+	fn cipher(&self, salt: &[u8]) -> Result<Cipher> {
+		let key = match self {
+			EncryptionSecret::Key(key) => *key,
+			EncryptionSecret::Passphrase(passphrase) => {
+				let mut key = [0u8; KEY_LEN];
+				argon2::Argon2::default()
+					.hash_password_into(passphrase.as_bytes(), salt, &mut key)
+					.map_err(|error| eyre!("Cannot derive encryption key: {error}"))?;
+				key
+			},
+		};
+		Ok(Cipher(XChaCha20Poly1305::new(
+			chacha20poly1305::Key::from_slice(&key),
+		)))
+	}
+
+	/// Encrypts the value into self-contained ciphertext, prefixed with the key derivation salt
+	pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+		let salt = random_bytes::<SALT_LEN>();
+		let ciphertext = self.cipher(&salt)?.encrypt(plaintext)?;
+		Ok([&salt[..], &ciphertext].concat())
+	}
+
+	/// Decrypts the value encrypted with [`EncryptionSecret::seal`]
+	pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>> {
+		if sealed.len() < SALT_LEN {
+			return Err(eyre!("Encrypted value is too short"));
+		}
+		let (salt, ciphertext) = sealed.split_at(SALT_LEN);
+		self.cipher(salt)?.decrypt(ciphertext)
+	}
+}
+
+#[derive(Clone)]
+struct Cipher(XChaCha20Poly1305);
+
+impl Cipher {
+	/// Encrypts the value, prefixing ciphertext with the random nonce
+	fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+		let nonce = random_bytes::<NONCE_LEN>();
+		let ciphertext = self
+			.0
+			.encrypt(XNonce::from_slice(&nonce), plaintext)
+			.map_err(|_| eyre!("Encryption failed"))?;
+		Ok([&nonce[..], &ciphertext].concat())
+	}
+
+	fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>> {
+		if data.len() < NONCE_LEN {
+			return Err(eyre!("Encrypted value is too short"));
+		}
+		let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+		self.0
+			.decrypt(XNonce::from_slice(nonce), ciphertext)
+			.map_err(|_| eyre!("Decryption failed, invalid encryption key or corrupted value"))
+	}
+}
+
+/// Unencrypted parameters of the encrypted storage
+#[derive(Serialize, Deserialize, Debug, Decode, Encode)]
+pub struct EncryptionHeader {
+	/// Salt used for the key derivation from the passphrase
+	pub salt: Vec<u8>,
+	/// Encrypted check value, used to verify the encryption key
+	pub check: Vec<u8>,
+}
+
+/// Database wrapper which encrypts values if the encryption secret is provided.
+/// Without the secret, values are stored unencrypted.
+#[derive(Clone)]
+pub struct EncryptedDB<T: Database> {
+	db: T,
+	cipher: Option<Cipher>,
+}
+
+impl<T: Database> EncryptedDB<T> {
+	/// Wraps the database, verifying the encryption secret against the stored header.
+	/// Header is created on first use, so encryption can be enabled only on the empty database,
+	/// and opening the database with unencrypted values using the encryption secret fails.
+	pub fn new(db: T, secret: Option<EncryptionSecret>) -> Result<Self> {
+		let header: Option<EncryptionHeader> = db.get(Key::StorageEncryption)?;
+		let cipher = match (secret, header) {
+			(None, None) => None,
+			(None, Some(_)) => {
+				return Err(eyre!(
+					"Storage is encrypted, encryption key or passphrase must be provided"
+				))
+			},
+			(Some(secret), Some(header)) => {
+				let cipher = secret.cipher(&header.salt)?;
+				cipher
+					.decrypt(&header.check)
+					.ok()
+					.filter(|check| check == CHECK_VALUE)
+					.ok_or_else(|| eyre!("Invalid storage encryption key or passphrase"))?;
+				Some(cipher)
+			},
+			(Some(_), None) if !db.is_empty()? => {
+				return Err(eyre!(
+					"Storage contains unencrypted data, encryption can be enabled only on the empty database"
+				))
+			},
+			(Some(secret), None) => {
+				let salt = random_bytes::<SALT_LEN>().to_vec();
+				let cipher = secret.cipher(&salt)?;
+				let check = cipher.encrypt(CHECK_VALUE)?;
+				db.put(Key::StorageEncryption, EncryptionHeader { salt, check })?;
+				Some(cipher)
+			},
+		};
+		Ok(EncryptedDB { db, cipher })
+	}
+
+	pub fn is_encrypted(&self) -> bool {
+		self.cipher.is_some()
+	}
+}
+
+impl<T: Database> Database for EncryptedDB<T> {
+	type Key = T::Key;
+
+	fn put<V>(&self, key: Key, value: V) -> Result<()>
+	where
+		V: Serialize + Encode,
+	{
+		let Some(cipher) = &self.cipher else {
+			return self.db.put(key, value);
+		};
+		let encrypted = cipher.encrypt(&value.encode())?;
+		self.db.put(key, encrypted)
+	}
+
+	fn get<V>(&self, key: Key) -> Result<Option<V>>
+	where
+		for<'a> V: Deserialize<'a> + Decode,
+	{
+		let Some(cipher) = &self.cipher else {
+			return self.db.get(key);
+		};
+		let Some(encrypted) = self.db.get::<Vec<u8>>(key)? else {
+			return Ok(None);
+		};
+		let value = cipher.decrypt(&encrypted)?;
+		V::decode(&mut &value[..])
+			.map(Some)
+			.wrap_err("Failed decoding decrypted value")
+	}
+
+	fn delete(&self, key: Key) -> Result<()> {
+		self.db.delete(key)
+	}
+
+	/// Index keys are not encrypted, so they are listed from the underlying database
+	fn verified_after(
+		&self,
+		verified_at: u64,
+		block_number: u32,
+		limit: usize,
+	) -> Result<Vec<(u64, u32)>> {
+		self.db.verified_after(verified_at, block_number, limit)
+	}
+
+	fn stats(&self) -> Result<StorageStats> {
+		self.db.stats()
+	}
+
+	fn is_empty(&self) -> Result<bool> {
+		self.db.is_empty()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{EncryptedDB, EncryptionSecret};
+	use crate::data::{mem_db::MemoryDB, Database, Key};
+	use codec::Encode;
+
+	#[test]
+	fn encrypted_values() {
+		let db = MemoryDB::default();
+		let secret = EncryptionSecret::Key([1u8; 32]);
+		let encrypted_db = EncryptedDB::new(db.clone(), Some(secret.clone())).unwrap();
+		assert!(encrypted_db.is_encrypted());
+
+		encrypted_db.put(Key::VerifiedCellCount(1), 10u32).unwrap();
+		let stored = db.get::<Vec<u8>>(Key::VerifiedCellCount(1)).unwrap();
+		assert_ne!(stored.unwrap(), 10u32.encode());
+		let value = encrypted_db.get::<u32>(Key::VerifiedCellCount(1)).unwrap();
+		assert_eq!(value, Some(10));
+
+		// Reopened with the same key
+		let encrypted_db = EncryptedDB::new(db, Some(secret)).unwrap();
+		let value = encrypted_db.get::<u32>(Key::VerifiedCellCount(1)).unwrap();
+		assert_eq!(value, Some(10));
+		assert_eq!(
+			encrypted_db.get::<u32>(Key::VerifiedCellCount(2)).unwrap(),
+			None
+		);
+	}
+
+	#[test]
+	fn invalid_secret() {
+		let db = MemoryDB::default();
+		let secret = EncryptionSecret::Passphrase("correct".to_string());
+		EncryptedDB::new(db.clone(), Some(secret)).unwrap();
+
+		let wrong = EncryptionSecret::Passphrase("wrong".to_string());
+		assert!(EncryptedDB::new(db.clone(), Some(wrong)).is_err());
+		assert!(EncryptedDB::new(db, None).is_err());
+	}
+
+	#[test]
+	fn unencrypted_passthrough() {
+		let db = MemoryDB::default();
+		let unencrypted_db = EncryptedDB::new(db.clone(), None).unwrap();
+		assert!(!unencrypted_db.is_encrypted());
+		unencrypted_db
+			.put(Key::VerifiedCellCount(1), 10u32)
+			.unwrap();
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(1)).unwrap(), Some(10));
+
+		// Encryption cannot be enabled after unencrypted values are stored
+		let secret = EncryptionSecret::Key([1u8; 32]);
+		assert!(EncryptedDB::new(db.clone(), Some(secret)).is_err());
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(1)).unwrap(), Some(10));
+	}
+
+	#[test]
+	fn seal_and_open() {
+		let secret = EncryptionSecret::Key([2u8; 32]);
+		let sealed = secret.seal(b"secret uri").unwrap();
+		assert_eq!(secret.open(&sealed).unwrap(), b"secret uri");
+		assert!(EncryptionSecret::Key([3u8; 32]).open(&sealed).is_err());
+	}
+}
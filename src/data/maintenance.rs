@@ -0,0 +1,437 @@
+//! Offline maintenance of the persistent store.
+//!
+//! [`compact`] removes orphaned entries, which are per-block entries of the blocks without stored
+//! header or processing state, and compacts all column families (dropping expired Kademlia records as well).
+//! [`repair`] first repairs the RocksDB files, then checks integrity of the entries, removing
+//! the ones which cannot be decoded, and continues with the compaction.
+//! Values of the encrypted storage are checked only for the structure of the encrypted value,
+//! since encryption secret is not needed for the maintenance.
+//! Maintenance must run while the light client is stopped.
+//! [`wipe_chain_data`] removes the data of the chain to which the database belongs, on startup,
+//! if the connected node follows a different chain.
+
+use avail_subxt::primitives::Header;
+use codec::{Decode, DecodeAll};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use rocksdb::{BoundColumnFamily, IteratorMode, Options, DB};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+	fmt::{self, Display, Formatter},
+	fs,
+	path::Path,
+	sync::Arc,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+	rocks_db::RocksDB, Database, Key, RetentionCheck, SampledCell, APP_DATA_CF, BLOCK_HEADER_CF,
+	BLOCK_METADATA_CF, BLOCK_STATE_CF, COLUMN_FAMILIES, CONFIDENCE_FACTOR_CF, DEGRADED_CF,
+	KADEMLIA_STORE_CF, RETENTION_CHECK_CF, SAMPLED_CELLS_CF, STATE_CF, VERIFIED_AT_CF,
+	VERIFIED_INDEX_CF,
+};
+use crate::block_state::BlockState;
+
+/// Column families keyed by the block number, which entries are orphaned without the block
+const BLOCK_COLUMN_FAMILIES: [&str; 5] = [
+	CONFIDENCE_FACTOR_CF,
+	SAMPLED_CELLS_CF,
+	RETENTION_CHECK_CF,
+	VERIFIED_AT_CF,
+	DEGRADED_CF,
+];
+
+/// Entry which failed the integrity check
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CorruptedEntry {
+	pub column_family: &'static str,
+	/// Hex encoded key of the entry
+	pub key: String,
+	pub error: String,
+}
+
+/// Outcome of the database maintenance
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+	/// Size of the database directory in bytes, before the maintenance
+	pub size_before: u64,
+	/// Size of the database directory in bytes, after the maintenance
+	pub size_after: u64,
+	/// Number of removed orphaned entries
+	pub orphans_removed: usize,
+	/// Removed entries which failed the integrity check (only checked on repair)
+	pub corrupted: Vec<CorruptedEntry>,
+}
+
+impl Report {
+	pub fn reclaimed(&self) -> u64 {
+		self.size_before.saturating_sub(self.size_after)
+	}
+
+	/// Report as a single JSON line, used with the JSON console output
+	pub fn to_json(&self) -> String {
+		json!({
+			"size_before": self.size_before,
+			"size_after": self.size_after,
+			"reclaimed": self.reclaimed(),
+			"orphans_removed": self.orphans_removed,
+			"corrupted": self.corrupted,
+		})
+		.to_string()
+	}
+}
+
+impl Display for Report {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		writeln!(f, "Orphaned entries removed: {}", self.orphans_removed)?;
+		writeln!(f, "Corrupted entries removed: {}", self.corrupted.len())?;
+		for entry in &self.corrupted {
+			writeln!(
+				f,
+				"  {} 0x{}: {}",
+				entry.column_family, entry.key, entry.error
+			)?;
+		}
+		write!(
+			f,
+			"Space reclaimed: {} bytes ({} bytes before, {} bytes after)",
+			self.reclaimed(),
+			self.size_before,
+			self.size_after
+		)
+	}
+}
+
+/// Total size of the files in the directory, including subdirectories
+fn directory_size(path: &Path) -> Result<u64> {
+	let mut size = 0;
+	for entry in fs::read_dir(path)? {
+		let entry = entry?;
+		let metadata = entry.metadata()?;
+		size += match metadata.is_dir() {
+			true => directory_size(&entry.path())?,
+			false => metadata.len(),
+		};
+	}
+	Ok(size)
+}
+
+fn column_family<'a>(db: &'a DB, name: &str) -> Result<Arc<BoundColumnFamily<'a>>> {
+	db.cf_handle(name)
+		.ok_or_else(|| eyre!("Couldn't get Column Family {name} handle from RocksDB"))
+}
+
+fn block_number(key: &[u8]) -> Option<u32> {
+	key.try_into().ok().map(u32::from_be_bytes)
+}
+
+/// Block number of the verified index key, which follows the verification time
+fn verified_index_block_number(key: &[u8]) -> Option<u32> {
+	(key.len() == 12).then(|| block_number(&key[8..]))?
+}
+
+/// Block number of the app data key, formatted as `{app_id}:{block_number}`
+fn app_data_block_number(key: &[u8]) -> Option<u32> {
+	let (app_id, block_number) = std::str::from_utf8(key).ok()?.split_once(':')?;
+	app_id.parse::<u32>().ok()?;
+	block_number.parse().ok()
+}
+
+fn decode<T: Decode>(value: &[u8]) -> Result<()> {
+	T::decode_all(&mut &value[..])
+		.map(|_| ())
+		.map_err(|error| eyre!("Cannot decode value: {error}"))
+}
+
+/// Checks the key and value of the entry in the given column family
+fn check_entry(column_family: &str, key: &[u8], value: &[u8], encrypted: bool) -> Result<()> {
+	let valid_key = match column_family {
+		APP_DATA_CF => app_data_block_number(key).is_some(),
+		BLOCK_METADATA_CF => key.len() > 4,
+		VERIFIED_INDEX_CF => verified_index_block_number(key).is_some(),
+		_ => block_number(key).is_some(),
+	};
+	if !valid_key {
+		return Err(eyre!("Invalid key"));
+	}
+
+	// Encrypted values are stored as encoded ciphertext
+	if encrypted {
+		return decode::<Vec<u8>>(value);
+	}
+	match column_family {
+		CONFIDENCE_FACTOR_CF => decode::<u32>(value),
+		BLOCK_HEADER_CF => decode::<Header>(value),
+		APP_DATA_CF => decode::<Vec<Vec<u8>>>(value),
+		SAMPLED_CELLS_CF => decode::<Vec<SampledCell>>(value),
+		BLOCK_STATE_CF => decode::<BlockState>(value),
+		RETENTION_CHECK_CF => decode::<RetentionCheck>(value),
+		VERIFIED_AT_CF => decode::<u64>(value),
+		DEGRADED_CF => decode::<bool>(value),
+		VERIFIED_INDEX_CF => decode::<()>(value),
+		BLOCK_METADATA_CF => decode::<Vec<u8>>(value),
+		_ => Ok(()),
+	}
+}
+
+/// Removes entries which fail the integrity check.
+/// State and Kademlia store column families are not checked, since their values have different types.
+fn remove_corrupted(db: &DB) -> Result<Vec<CorruptedEntry>> {
+	let (_, encryption_key): (Option<&str>, Vec<u8>) = Key::StorageEncryption.into();
+	let encrypted = db
+		.get_pinned_cf(&column_family(db, STATE_CF)?, encryption_key)?
+		.is_some();
+
+	let mut corrupted = vec![];
+	for (name, _) in COLUMN_FAMILIES {
+		if name == STATE_CF || name == KADEMLIA_STORE_CF {
+			continue;
+		}
+		let cf = column_family(db, name)?;
+		for entry in db.iterator_cf(&cf, IteratorMode::Start) {
+			let (key, value) = entry.wrap_err_with(|| format!("Cannot read {name} entries"))?;
+			if let Err(error) = check_entry(name, &key, &value, encrypted) {
+				db.delete_cf(&cf, &key)?;
+				corrupted.push(CorruptedEntry {
+					column_family: name,
+					key: hex::encode(&key),
+					error: error.to_string(),
+				});
+			}
+		}
+	}
+	Ok(corrupted)
+}
+
+/// Removes per-block entries and verified index entries of the blocks without stored header or processing state,
+/// and app data of the blocks without stored header
+fn remove_orphans(db: &DB) -> Result<usize> {
+	let headers = column_family(db, BLOCK_HEADER_CF)?;
+	let states = column_family(db, BLOCK_STATE_CF)?;
+	let has_header = |block_number: u32| -> Result<bool> {
+		Ok(db
+			.get_pinned_cf(&headers, block_number.to_be_bytes())?
+			.is_some())
+	};
+
+	let mut removed = 0;
+	for name in BLOCK_COLUMN_FAMILIES {
+		let cf = column_family(db, name)?;
+		for entry in db.iterator_cf(&cf, IteratorMode::Start) {
+			let (key, _) = entry.wrap_err_with(|| format!("Cannot read {name} entries"))?;
+			let Some(block_number) = block_number(&key) else {
+				continue;
+			};
+			if has_header(block_number)? || db.get_pinned_cf(&states, &key)?.is_some() {
+				continue;
+			}
+			db.delete_cf(&cf, &key)?;
+			removed += 1;
+		}
+	}
+
+	let index = column_family(db, VERIFIED_INDEX_CF)?;
+	for entry in db.iterator_cf(&index, IteratorMode::Start) {
+		let (key, _) = entry.wrap_err("Cannot read verified index entries")?;
+		let Some(block_number) = verified_index_block_number(&key) else {
+			continue;
+		};
+		let has_state = db
+			.get_pinned_cf(&states, block_number.to_be_bytes())?
+			.is_some();
+		if !has_header(block_number)? && !has_state {
+			db.delete_cf(&index, &key)?;
+			removed += 1;
+		}
+	}
+
+	let app_data = column_family(db, APP_DATA_CF)?;
+	for entry in db.iterator_cf(&app_data, IteratorMode::Start) {
+		let (key, _) = entry.wrap_err("Cannot read app data entries")?;
+		let Some(block_number) = app_data_block_number(&key) else {
+			continue;
+		};
+		if !has_header(block_number)? {
+			db.delete_cf(&app_data, &key)?;
+			removed += 1;
+		}
+	}
+	Ok(removed)
+}
+
+fn compact_column_families(db: &DB) -> Result<()> {
+	db.compact_range(None::<&[u8]>, None::<&[u8]>);
+	for (name, _) in COLUMN_FAMILIES {
+		db.compact_range_cf(&column_family(db, name)?, None::<&[u8]>, None::<&[u8]>);
+	}
+	Ok(())
+}
+
+fn maintain(path: &str, check_integrity: bool) -> Result<Report> {
+	if !Path::new(path).is_dir() {
+		return Err(eyre!("Database {path} doesn't exist"));
+	}
+	let mut report = Report {
+		size_before: directory_size(Path::new(path))?,
+		..Default::default()
+	};
+
+	if check_integrity {
+		DB::repair(&Options::default(), path)
+			.wrap_err_with(|| format!("Cannot repair database {path}"))?;
+	}
+
+	{
+		let (rocks_db, db) = RocksDB::open(path).wrap_err("Cannot open database")?;
+		if check_integrity {
+			report.corrupted = remove_corrupted(&db)?;
+		}
+		report.orphans_removed = remove_orphans(&db)?;
+		compact_column_families(&db)?;
+		// Timestamp is not encrypted, so it can be stored without the encryption secret
+		let compacted_at = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|duration| duration.as_secs())
+			.unwrap_or_default();
+		rocks_db
+			.put(Key::LastCompaction, compacted_at)
+			.wrap_err("Cannot store compaction timestamp")?;
+	}
+
+	report.size_after = directory_size(Path::new(path))?;
+	Ok(report)
+}
+
+/// Removes all entries which belong to the chain, keeping only the storage encryption header,
+/// telemetry instance ID and compaction timestamp. Returns the number of removed entries.
+pub fn wipe_chain_data(db: &DB) -> Result<usize> {
+	let kept: Vec<Vec<u8>> = [
+		Key::StorageEncryption,
+		Key::TelemetryInstanceId,
+		Key::LastCompaction,
+	]
+	.into_iter()
+	.map(|key| {
+		let (_, key): (Option<&str>, Vec<u8>) = key.into();
+		key
+	})
+	.collect();
+
+	let mut removed = 0;
+	for (name, _) in COLUMN_FAMILIES {
+		let cf = column_family(db, name)?;
+		for entry in db.iterator_cf(&cf, IteratorMode::Start) {
+			let (key, _) = entry.wrap_err_with(|| format!("Cannot read {name} entries"))?;
+			if name == STATE_CF && kept.iter().any(|kept| kept[..] == key[..]) {
+				continue;
+			}
+			db.delete_cf(&cf, &key)?;
+			removed += 1;
+		}
+	}
+	Ok(removed)
+}
+
+/// Removes orphaned entries and compacts the database
+pub fn compact(path: &str) -> Result<Report> {
+	maintain(path, false)
+}
+
+/// Repairs the database files, removes corrupted and orphaned entries, and compacts the database
+pub fn repair(path: &str) -> Result<Report> {
+	maintain(path, true)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{compact, repair, wipe_chain_data, VERIFIED_AT_CF};
+	use crate::{
+		block_state::BlockState,
+		data::{put_verified_at, rocks_db::RocksDB, Database, Key},
+	};
+	use std::fs;
+
+	#[test]
+	fn repair_removes_corrupted_and_orphaned_entries() {
+		let path = std::env::temp_dir().join(format!("avail_light_repair_{}", std::process::id()));
+		let path = path.to_str().unwrap();
+		let _ = fs::remove_dir_all(path);
+		{
+			let (db, rocks_db) = RocksDB::open(path).unwrap();
+			db.put(Key::BlockState(1), BlockState::Received).unwrap();
+			db.put(Key::VerifiedCellCount(1), 10u32).unwrap();
+			// Block 2 is neither stored nor processed
+			db.put(Key::VerifiedCellCount(2), 10u32).unwrap();
+			// Timestamp is stored as `u64`
+			let cf = rocks_db.cf_handle(VERIFIED_AT_CF).unwrap();
+			rocks_db
+				.put_cf(&cf, 1u32.to_be_bytes(), [1u8, 2, 3])
+				.unwrap();
+		}
+
+		let report = repair(path).unwrap();
+		assert_eq!(report.orphans_removed, 1);
+		assert_eq!(report.corrupted.len(), 1);
+		assert_eq!(report.corrupted[0].column_family, VERIFIED_AT_CF);
+		assert_eq!(report.corrupted[0].key, "00000001");
+
+		let (db, _) = RocksDB::open(path).unwrap();
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(1)).unwrap(), Some(10));
+		assert_eq!(db.get::<u32>(Key::VerifiedCellCount(2)).unwrap(), None);
+		assert_eq!(db.get::<u64>(Key::VerifiedAt(1)).unwrap(), None);
+		assert!(db.stats().unwrap().last_compaction.is_some());
+		drop(db);
+		fs::remove_dir_all(path).unwrap();
+	}
+
+	#[test]
+	fn verified_index_is_iterated_and_compacted() {
+		let path = std::env::temp_dir().join(format!("avail_light_index_{}", std::process::id()));
+		let path = path.to_str().unwrap();
+		let _ = fs::remove_dir_all(path);
+		{
+			let (db, _) = RocksDB::open(path).unwrap();
+			db.put(Key::BlockState(1), BlockState::Received).unwrap();
+			db.put(Key::BlockState(3), BlockState::Received).unwrap();
+			put_verified_at(&db, 1, 20).unwrap();
+			put_verified_at(&db, 3, 10).unwrap();
+			// Block 2 is neither stored nor processed
+			put_verified_at(&db, 2, 15).unwrap();
+			assert_eq!(
+				db.verified_after(0, 0, 10).unwrap(),
+				vec![(10, 3), (15, 2), (20, 1)]
+			);
+			assert_eq!(db.verified_after(10, 3, 1).unwrap(), vec![(15, 2)]);
+			assert_eq!(db.verified_after(20, 1, 10).unwrap(), vec![]);
+		}
+
+		let report = compact(path).unwrap();
+		assert_eq!(report.orphans_removed, 2);
+		let (db, _) = RocksDB::open(path).unwrap();
+		assert_eq!(db.verified_after(0, 0, 10).unwrap(), vec![(10, 3), (20, 1)]);
+		drop(db);
+		fs::remove_dir_all(path).unwrap();
+	}
+
+	#[test]
+	fn wipe_keeps_storage_settings() {
+		let path = std::env::temp_dir().join(format!("avail_light_wipe_{}", std::process::id()));
+		let path = path.to_str().unwrap();
+		let _ = fs::remove_dir_all(path);
+		{
+			let (db, rocks_db) = RocksDB::open(path).unwrap();
+			db.put(Key::BlockState(1), BlockState::Received).unwrap();
+			db.put(Key::VerifiedCellCount(1), 10u32).unwrap();
+			db.put(Key::PeerStore, vec![1u8]).unwrap();
+			db.put(Key::LastCompaction, 1u64).unwrap();
+			assert_eq!(wipe_chain_data(&rocks_db).unwrap(), 3);
+			assert_eq!(db.get::<u32>(Key::VerifiedCellCount(1)).unwrap(), None);
+			assert_eq!(db.get::<Vec<u8>>(Key::PeerStore).unwrap(), None);
+			assert_eq!(db.get::<u64>(Key::LastCompaction).unwrap(), Some(1));
+		}
+		fs::remove_dir_all(path).unwrap();
+	}
+}
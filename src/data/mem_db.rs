@@ -1,5 +1,10 @@
 use crate::data::{
-	Database, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF, FINALITY_SYNC_CHECKPOINT_KEY,
+	Database, Key, StorageStats, APP_DATA_CF, APP_DATA_USAGE_KEY_PREFIX, BLOCK_HEADER_CF,
+	BLOCK_METADATA_CF, BLOCK_STATE_CF, CHAIN_CHECKPOINT_KEY, CONFIDENCE_FACTOR_CF,
+	CONSUMER_CURSOR_KEY_PREFIX, DEGRADED_CF, EMPTY_BLOCK_COMMITMENTS_KEY_PREFIX,
+	EXPORT_WATERMARK_KEY_PREFIX, FINALITY_SYNC_CHECKPOINT_KEY, JOB_QUEUE_KEY_PREFIX,
+	LAST_COMPACTION_KEY, PEER_STORE_KEY, RETENTION_CHECK_CF, SAMPLED_CELLS_CF,
+	STORAGE_ENCRYPTION_KEY, TELEMETRY_INSTANCE_ID_KEY, VERIFIED_AT_CF, VERIFIED_INDEX_CF,
 };
 use color_eyre::eyre::{eyre, Result};
 use serde::{Deserialize, Serialize};
@@ -51,6 +56,36 @@ impl Database for MemoryDB {
 		map.remove(&key.into());
 		Ok(())
 	}
+
+	fn verified_after(
+		&self,
+		verified_at: u64,
+		block_number: u32,
+		limit: usize,
+	) -> Result<Vec<(u64, u32)>> {
+		let map = self.map.read().expect("Lock acquired");
+		let prefix = format!("{VERIFIED_INDEX_CF}:");
+		let mut entries = map
+			.keys()
+			.filter_map(|HashMapKey(key)| {
+				let (verified_at, block_number) = key.strip_prefix(&prefix)?.split_once(':')?;
+				Some((verified_at.parse().ok()?, block_number.parse().ok()?))
+			})
+			.filter(|&entry| entry > (verified_at, block_number))
+			.collect::<Vec<(u64, u32)>>();
+		entries.sort();
+		entries.truncate(limit);
+		Ok(entries)
+	}
+
+	/// Statistics are not tracked for the in-memory database
+	fn stats(&self) -> Result<StorageStats> {
+		Ok(StorageStats::default())
+	}
+
+	fn is_empty(&self) -> Result<bool> {
+		Ok(self.map.read().expect("Lock acquired").is_empty())
+	}
 }
 
 impl From<Key> for HashMapKey {
@@ -65,7 +100,40 @@ impl From<Key> for HashMapKey {
 			Key::VerifiedCellCount(block_number) => {
 				HashMapKey(format!("{CONFIDENCE_FACTOR_CF}:{block_number}"))
 			},
+			Key::VerifiedAt(block_number) => HashMapKey(format!("{VERIFIED_AT_CF}:{block_number}")),
+			Key::VerifiedIndex(verified_at, block_number) => {
+				HashMapKey(format!("{VERIFIED_INDEX_CF}:{verified_at}:{block_number}"))
+			},
+			Key::SampledCells(block_number) => {
+				HashMapKey(format!("{SAMPLED_CELLS_CF}:{block_number}"))
+			},
+			Key::BlockState(block_number) => HashMapKey(format!("{BLOCK_STATE_CF}:{block_number}")),
+			Key::Degraded(block_number) => HashMapKey(format!("{DEGRADED_CF}:{block_number}")),
+			Key::RetentionCheck(block_number) => {
+				HashMapKey(format!("{RETENTION_CHECK_CF}:{block_number}"))
+			},
+			Key::BlockMetadata(block_number, name) => {
+				HashMapKey(format!("{BLOCK_METADATA_CF}:{block_number}:{name}"))
+			},
 			Key::FinalitySyncCheckpoint => HashMapKey(FINALITY_SYNC_CHECKPOINT_KEY.to_string()),
+			Key::ConsumerCursor(api_key_hash) => {
+				HashMapKey(format!("{CONSUMER_CURSOR_KEY_PREFIX}:{api_key_hash}"))
+			},
+			Key::JobQueue(name) => HashMapKey(format!("{JOB_QUEUE_KEY_PREFIX}:{name}")),
+			Key::AppDataUsage(app_id) => {
+				HashMapKey(format!("{APP_DATA_USAGE_KEY_PREFIX}:{app_id}"))
+			},
+			Key::StorageEncryption => HashMapKey(STORAGE_ENCRYPTION_KEY.to_string()),
+			Key::TelemetryInstanceId => HashMapKey(TELEMETRY_INSTANCE_ID_KEY.to_string()),
+			Key::PeerStore => HashMapKey(PEER_STORE_KEY.to_string()),
+			Key::LastCompaction => HashMapKey(LAST_COMPACTION_KEY.to_string()),
+			Key::ChainCheckpoint => HashMapKey(CHAIN_CHECKPOINT_KEY.to_string()),
+			Key::ExportWatermark(name) => {
+				HashMapKey(format!("{EXPORT_WATERMARK_KEY_PREFIX}:{name}"))
+			},
+			Key::EmptyBlockCommitments(rows, cols) => HashMapKey(format!(
+				"{EMPTY_BLOCK_COMMITMENTS_KEY_PREFIX}:{rows}x{cols}"
+			)),
 		}
 	}
 }
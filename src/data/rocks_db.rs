@@ -1,13 +1,18 @@
 use crate::{
 	data::{
-		self, Key, APP_DATA_CF, BLOCK_HEADER_CF, CONFIDENCE_FACTOR_CF,
-		FINALITY_SYNC_CHECKPOINT_KEY, KADEMLIA_STORE_CF, STATE_CF,
+		self, ColumnFamilyStats, Key, StorageStats, APP_DATA_CF, APP_DATA_USAGE_KEY_PREFIX,
+		BLOCK_HEADER_CF, BLOCK_METADATA_CF, BLOCK_STATE_CF, CHAIN_CHECKPOINT_KEY, COLUMN_FAMILIES,
+		CONFIDENCE_FACTOR_CF, CONSUMER_CURSOR_KEY_PREFIX, DEGRADED_CF,
+		EMPTY_BLOCK_COMMITMENTS_KEY_PREFIX, EXPORT_WATERMARK_KEY_PREFIX,
+		FINALITY_SYNC_CHECKPOINT_KEY, JOB_QUEUE_KEY_PREFIX, KADEMLIA_STORE_CF, LAST_COMPACTION_KEY,
+		PEER_STORE_KEY, RETENTION_CHECK_CF, SAMPLED_CELLS_CF, STATE_CF, STORAGE_ENCRYPTION_KEY,
+		TELEMETRY_INSTANCE_ID_KEY, VERIFIED_AT_CF, VERIFIED_INDEX_CF,
 	},
 	network::p2p::ExpirationCompactionFilterFactory,
 };
 use codec::{Decode, Encode};
 use color_eyre::eyre::{eyre, Context, Result};
-use rocksdb::{ColumnFamilyDescriptor, Options};
+use rocksdb::{ColumnFamilyDescriptor, Direction, IteratorMode, Options};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -27,6 +32,13 @@ impl RocksDB {
 			ColumnFamilyDescriptor::new(APP_DATA_CF, Options::default()),
 			ColumnFamilyDescriptor::new(STATE_CF, Options::default()),
 			ColumnFamilyDescriptor::new(KADEMLIA_STORE_CF, kademlia_store_cf_opts),
+			ColumnFamilyDescriptor::new(SAMPLED_CELLS_CF, Options::default()),
+			ColumnFamilyDescriptor::new(BLOCK_STATE_CF, Options::default()),
+			ColumnFamilyDescriptor::new(RETENTION_CHECK_CF, Options::default()),
+			ColumnFamilyDescriptor::new(VERIFIED_AT_CF, Options::default()),
+			ColumnFamilyDescriptor::new(BLOCK_METADATA_CF, Options::default()),
+			ColumnFamilyDescriptor::new(DEGRADED_CF, Options::default()),
+			ColumnFamilyDescriptor::new(VERIFIED_INDEX_CF, Options::default()),
 		];
 
 		let mut db_opts = Options::default();
@@ -54,14 +66,73 @@ impl From<Key> for (Option<&'static str>, Vec<u8>) {
 				Some(CONFIDENCE_FACTOR_CF),
 				block_number.to_be_bytes().to_vec(),
 			),
+			Key::VerifiedAt(block_number) => {
+				(Some(VERIFIED_AT_CF), block_number.to_be_bytes().to_vec())
+			},
+			Key::VerifiedIndex(verified_at, block_number) => (
+				Some(VERIFIED_INDEX_CF),
+				verified_index_key(verified_at, block_number),
+			),
+			Key::SampledCells(block_number) => {
+				(Some(SAMPLED_CELLS_CF), block_number.to_be_bytes().to_vec())
+			},
+			Key::BlockState(block_number) => {
+				(Some(BLOCK_STATE_CF), block_number.to_be_bytes().to_vec())
+			},
+			Key::Degraded(block_number) => (Some(DEGRADED_CF), block_number.to_be_bytes().to_vec()),
+			Key::RetentionCheck(block_number) => (
+				Some(RETENTION_CHECK_CF),
+				block_number.to_be_bytes().to_vec(),
+			),
+			Key::BlockMetadata(block_number, name) => (
+				Some(BLOCK_METADATA_CF),
+				[block_number.to_be_bytes().as_slice(), name.as_bytes()].concat(),
+			),
 			Key::FinalitySyncCheckpoint => (
 				Some(STATE_CF),
 				FINALITY_SYNC_CHECKPOINT_KEY.as_bytes().to_vec(),
 			),
+			Key::ConsumerCursor(api_key_hash) => (
+				Some(STATE_CF),
+				format!("{CONSUMER_CURSOR_KEY_PREFIX}:{api_key_hash}").into_bytes(),
+			),
+			Key::JobQueue(name) => (
+				Some(STATE_CF),
+				format!("{JOB_QUEUE_KEY_PREFIX}:{name}").into_bytes(),
+			),
+			Key::AppDataUsage(app_id) => (
+				Some(STATE_CF),
+				format!("{APP_DATA_USAGE_KEY_PREFIX}:{app_id}").into_bytes(),
+			),
+			Key::StorageEncryption => (Some(STATE_CF), STORAGE_ENCRYPTION_KEY.as_bytes().to_vec()),
+			Key::TelemetryInstanceId => (
+				Some(STATE_CF),
+				TELEMETRY_INSTANCE_ID_KEY.as_bytes().to_vec(),
+			),
+			Key::PeerStore => (Some(STATE_CF), PEER_STORE_KEY.as_bytes().to_vec()),
+			Key::LastCompaction => (Some(STATE_CF), LAST_COMPACTION_KEY.as_bytes().to_vec()),
+			Key::ChainCheckpoint => (Some(STATE_CF), CHAIN_CHECKPOINT_KEY.as_bytes().to_vec()),
+			Key::ExportWatermark(name) => (
+				Some(STATE_CF),
+				format!("{EXPORT_WATERMARK_KEY_PREFIX}:{name}").into_bytes(),
+			),
+			Key::EmptyBlockCommitments(rows, cols) => (
+				Some(STATE_CF),
+				format!("{EMPTY_BLOCK_COMMITMENTS_KEY_PREFIX}:{rows}x{cols}").into_bytes(),
+			),
 		}
 	}
 }
 
+/// Big endian verification time followed by the block number, so entries are iterated in order of verification
+fn verified_index_key(verified_at: u64, block_number: u32) -> Vec<u8> {
+	[
+		verified_at.to_be_bytes().as_slice(),
+		&block_number.to_be_bytes(),
+	]
+	.concat()
+}
+
 impl data::Database for RocksDB {
 	type Key = RocksKey;
 
@@ -134,4 +205,102 @@ impl data::Database for RocksDB {
 			.delete_cf(&cf_handle, key)
 			.wrap_err("Delete operation with Column Family failed on RocksDB")
 	}
+
+	fn verified_after(
+		&self,
+		verified_at: u64,
+		block_number: u32,
+		limit: usize,
+	) -> Result<Vec<(u64, u32)>> {
+		let cf_handle = self
+			.db
+			.cf_handle(VERIFIED_INDEX_CF)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		let start = verified_index_key(verified_at, block_number);
+		let mut entries = vec![];
+		for entry in self
+			.db
+			.iterator_cf(&cf_handle, IteratorMode::From(&start, Direction::Forward))
+		{
+			let (key, _) = entry.wrap_err("Iterate operation failed on RocksDB")?;
+			if key[..] == start[..] {
+				continue;
+			}
+			if entries.len() == limit {
+				break;
+			}
+			let (verified_at, block_number) = key.split_at(8);
+			let verified_at = verified_at.try_into().map(u64::from_be_bytes);
+			let block_number = block_number.try_into().map(u32::from_be_bytes);
+			let (Ok(verified_at), Ok(block_number)) = (verified_at, block_number) else {
+				return Err(eyre!("Invalid verified index key"));
+			};
+			entries.push((verified_at, block_number));
+		}
+		Ok(entries)
+	}
+
+	fn stats(&self) -> Result<StorageStats> {
+		let mut stats = StorageStats::default();
+		for (cf, name) in COLUMN_FAMILIES {
+			let cf_handle = self
+				.db
+				.cf_handle(cf)
+				.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+			let property = |property: &str| -> Result<u64> {
+				Ok(self
+					.db
+					.property_int_value_cf(&cf_handle, property)?
+					.unwrap_or_default())
+			};
+			stats.column_families.push(ColumnFamilyStats {
+				name,
+				size: property("rocksdb.total-sst-files-size")?,
+				entries: property("rocksdb.estimate-num-keys")?,
+			});
+		}
+
+		// Header keys are big endian block numbers, so they are iterated in order of the blocks
+		let headers = self
+			.db
+			.cf_handle(BLOCK_HEADER_CF)
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		let block_number = |mode| -> Result<Option<u32>> {
+			let Some(entry) = self.db.iterator_cf(&headers, mode).next() else {
+				return Ok(None);
+			};
+			let (key, _) = entry?;
+			Ok((*key).try_into().ok().map(u32::from_be_bytes))
+		};
+		stats.oldest_block = block_number(IteratorMode::Start)?;
+		stats.newest_block = block_number(IteratorMode::End)?;
+
+		// Compaction timestamp is read directly, since it is stored unencrypted
+		let (cf, key): RocksKey = Key::LastCompaction.into();
+		let state = self
+			.db
+			.cf_handle(cf.unwrap_or(STATE_CF))
+			.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+		stats.last_compaction = self
+			.db
+			.get_cf(&state, key)?
+			.map(|value| u64::decode(&mut &value[..]))
+			.transpose()
+			.wrap_err("Failed decoding last compaction timestamp")?;
+		Ok(stats)
+	}
+
+	fn is_empty(&self) -> Result<bool> {
+		for (cf, _) in COLUMN_FAMILIES {
+			let cf_handle = self
+				.db
+				.cf_handle(cf)
+				.ok_or_else(|| eyre!("Couldn't get Column Family handle from RocksDB"))?;
+			if let Some(entry) = self.db.iterator_cf(&cf_handle, IteratorMode::Start).next() {
+				entry.wrap_err("Iterate operation failed on RocksDB")?;
+				return Ok(false);
+			}
+		}
+		Ok(true)
+	}
 }
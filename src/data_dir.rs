@@ -0,0 +1,280 @@
+//! Data directory of the light client.
+//!
+//! On-disk artifacts (database, identity file and exported data) configured with relative paths
+//! are stored under the data directory, while absolute paths are used as is.
+//! If data directory is not configured, platform specific default is used, unless the current directory
+//! already contains the state of the light client, in which case current directory is used.
+//! Data directory is locked while the light client is running, so it cannot be used by another instance.
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use std::{
+	env,
+	fs::{self, File, TryLockError},
+	io::Write,
+	path::{Path, PathBuf},
+	process,
+};
+use tracing::warn;
+
+use crate::service;
+
+/// Name of the light client directory within the platform data directory
+pub const APP_DIR: &str = "avail-light";
+
+/// Name of the lock file within the data directory
+pub const LOCK_FILE: &str = ".avail-light.lock";
+
+/// Platform specific default data directory:
+/// `$XDG_DATA_HOME/avail-light` or `~/.local/share/avail-light` on Linux,
+/// `~/Library/Application Support/avail-light` on macOS and `%APPDATA%\avail-light` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+	#[cfg(target_os = "macos")]
+	let base = env::var_os("HOME").map(|home| {
+		PathBuf::from(home)
+			.join("Library")
+			.join("Application Support")
+	});
+	#[cfg(windows)]
+	let base = env::var_os("APPDATA").map(PathBuf::from);
+	#[cfg(not(any(target_os = "macos", windows)))]
+	let base = env::var_os("XDG_DATA_HOME")
+		.map(PathBuf::from)
+		.filter(|path| path.is_absolute())
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+	base.map(|base| base.join(APP_DIR))
+}
+
+/// Exclusive advisory lock of the data directory, released when dropped or when the process exits
+#[derive(Debug)]
+pub struct DataDirLock {
+	_file: File,
+}
+
+#[derive(Clone, Debug)]
+pub struct DataDir {
+	path: PathBuf,
+}
+
+impl DataDir {
+	/// Uses configured data directory or the default one.
+	/// Current directory is used instead of the default, if any of the given legacy paths exists,
+	/// or if the default data directory cannot be determined.
+	pub fn new(configured: Option<&str>, legacy_paths: &[&str]) -> Self {
+		let path = match configured {
+			Some(path) => PathBuf::from(path),
+			None if legacy_paths.iter().any(|path| Path::new(path).exists()) => PathBuf::from("."),
+			None => default_path().unwrap_or_else(|| PathBuf::from(".")),
+		};
+		DataDir { path }
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Resolves relative path against the data directory
+	pub fn resolve(&self, path: &str) -> String {
+		if Path::new(path).is_absolute() {
+			return path.to_string();
+		}
+		self.path.join(path).to_string_lossy().into_owned()
+	}
+
+	/// Creates data directory if it doesn't exist, and checks that it is writable.
+	/// On Unix, created directory is accessible only by the owner,
+	/// and warning is logged if existing directory is writable by others.
+	pub fn prepare(&self) -> Result<()> {
+		let path = &self.path;
+		if !path.exists() {
+			fs::create_dir_all(path)
+				.wrap_err_with(|| format!("Cannot create data directory {}", path.display()))?;
+			#[cfg(unix)]
+			{
+				use std::os::unix::fs::PermissionsExt;
+				fs::set_permissions(path, fs::Permissions::from_mode(0o700)).wrap_err_with(
+					|| format!("Cannot set data directory {} permissions", path.display()),
+				)?;
+			}
+		}
+
+		let metadata = fs::metadata(path)
+			.wrap_err_with(|| format!("Cannot access data directory {}", path.display()))?;
+		if !metadata.is_dir() {
+			return Err(eyre!(
+				"Data directory {} is not a directory",
+				path.display()
+			));
+		}
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			if metadata.permissions().mode() & 0o002 != 0 {
+				warn!("Data directory {} is writable by others", path.display());
+			}
+		}
+
+		let probe = path.join(".avail-light-write-check");
+		fs::write(&probe, b"")
+			.wrap_err_with(|| format!("Data directory {} is not writable", path.display()))?;
+		let _ = fs::remove_file(probe);
+		Ok(())
+	}
+
+	/// Locks data directory and writes current process ID to the lock file.
+	/// Fails if data directory is locked by another running instance, reporting its process ID.
+	/// Lock is released by the operating system when the process exits, so a leftover lock file doesn't block startup.
+	/// On file systems without advisory locks, the process ID recorded in the lock file is the only protection,
+	/// and with `force_unlock` the lock file is replaced if the recorded process is no longer running.
+	/// Lock held by another process is never removed.
+	pub fn lock(&self, force_unlock: bool) -> Result<DataDirLock> {
+		let path = self.path.join(LOCK_FILE);
+		let recorded_pid = || {
+			fs::read_to_string(&path)
+				.ok()
+				.and_then(|pid| pid.trim().parse::<u32>().ok())
+		};
+
+		let open = || {
+			fs::OpenOptions::new()
+				.read(true)
+				.write(true)
+				.create(true)
+				.truncate(false)
+				.open(&path)
+				.wrap_err_with(|| format!("Cannot open lock file {}", path.display()))
+		};
+		let mut file = open()?;
+
+		match file.try_lock() {
+			Ok(()) => (),
+			Err(TryLockError::WouldBlock) => {
+				let running = recorded_pid()
+					.map(|pid| format!(" (pid {pid})"))
+					.unwrap_or_default();
+				return Err(eyre!(
+					"Light client is already running{running} with data directory {}",
+					self.path.display()
+				));
+			},
+			Err(TryLockError::Error(error)) if force_unlock => {
+				let running = |pid| pid != process::id() && service::is_running(pid);
+				if let Some(pid) = recorded_pid().filter(|&pid| running(pid)) {
+					return Err(error).wrap_err_with(|| {
+						format!(
+							"Cannot lock data directory {}, lock file is owned by running process (pid {pid})",
+							self.path.display()
+						)
+					});
+				}
+				warn!(
+					"Cannot lock data directory {} ({error}), replacing stale lock file",
+					self.path.display()
+				);
+				drop(file);
+				fs::remove_file(&path)
+					.wrap_err_with(|| format!("Cannot remove lock file {}", path.display()))?;
+				file = open()?;
+			},
+			Err(TryLockError::Error(error)) => {
+				return Err(error).wrap_err_with(|| {
+					format!(
+						"Cannot lock data directory {}, use `--force-unlock` to replace a stale lock file",
+						self.path.display()
+					)
+				})
+			},
+		}
+
+		file.set_len(0)
+			.and_then(|_| file.write_all(format!("{}\n", process::id()).as_bytes()))
+			.wrap_err_with(|| format!("Cannot write lock file {}", path.display()))?;
+		Ok(DataDirLock { _file: file })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{DataDir, LOCK_FILE};
+	use std::{fs, path::Path, process};
+
+	#[test]
+	fn resolve_paths() {
+		let data_dir = DataDir::new(Some("/var/lib/avail-light"), &[]);
+		assert_eq!(
+			data_dir.resolve("avail_path"),
+			"/var/lib/avail-light/avail_path"
+		);
+		assert_eq!(data_dir.resolve("/data/avail_path"), "/data/avail_path");
+	}
+
+	#[test]
+	fn legacy_current_directory() {
+		let legacy = std::env::temp_dir();
+		let legacy = legacy.to_str().unwrap();
+		assert_eq!(DataDir::new(None, &[legacy]).path(), Path::new("."));
+		// Configured data directory takes precedence
+		let data_dir = DataDir::new(Some("/data"), &[legacy]);
+		assert_eq!(data_dir.path(), Path::new("/data"));
+	}
+
+	#[test]
+	fn prepare_creates_directory() {
+		let path = std::env::temp_dir().join(format!("avail_light_data_{}", std::process::id()));
+		let _ = fs::remove_dir_all(&path);
+		let data_dir = DataDir::new(path.to_str(), &[]);
+		data_dir.prepare().unwrap();
+		assert!(path.is_dir());
+
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			let mode = fs::metadata(&path).unwrap().permissions().mode();
+			assert_eq!(mode & 0o777, 0o700);
+		}
+
+		// Preparing existing directory succeeds
+		data_dir.prepare().unwrap();
+		fs::remove_dir_all(&path).unwrap();
+	}
+
+	#[test]
+	fn prepare_fails_on_file() {
+		let path = std::env::temp_dir().join(format!("avail_light_file_{}", std::process::id()));
+		fs::write(&path, b"").unwrap();
+		assert!(DataDir::new(path.to_str(), &[]).prepare().is_err());
+		fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn lock_data_dir() {
+		let path = std::env::temp_dir().join(format!("avail_light_lock_{}", process::id()));
+		let _ = fs::remove_dir_all(&path);
+		let data_dir = DataDir::new(path.to_str(), &[]);
+		data_dir.prepare().unwrap();
+
+		let lock = data_dir.lock(false).unwrap();
+		let pid = fs::read_to_string(path.join(LOCK_FILE)).unwrap();
+		assert_eq!(pid.trim(), process::id().to_string());
+
+		let error = data_dir.lock(false).unwrap_err().to_string();
+		assert!(error.contains(&format!("already running (pid {})", process::id())));
+		// Held lock is never removed
+		let error = data_dir.lock(true).unwrap_err().to_string();
+		assert!(error.contains("already running"));
+		assert_eq!(
+			fs::read_to_string(path.join(LOCK_FILE)).unwrap().trim(),
+			process::id().to_string()
+		);
+		drop(lock);
+
+		// Lock is released when dropped, leftover lock file doesn't block locking
+		assert!(path.join(LOCK_FILE).exists());
+		let _lock = data_dir.lock(false).unwrap();
+		fs::remove_dir_all(&path).unwrap();
+	}
+}
@@ -0,0 +1,140 @@
+//! Verification of the block data root, as used by the bridge contracts.
+//!
+//! Data root is the Keccak hash of the concatenated blob root and bridge root.
+//! Blob root is the root of the binary Merkle tree, with Keccak hashes of submitted data blobs as leaves.
+//! Blob root can be computed only if data of all apps in the block is reconstructed by the light client.
+//! Bridge messages are not tracked by the light client, so data root is verified assuming empty bridge root.
+
+use avail_subxt::{primitives::Header, utils::H256};
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use sp_core::keccak_256;
+
+use crate::{
+	data::{Database, Key},
+	utils::{decode_app_data, extract_app_lookup, extract_kate},
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DataRootStatus {
+	/// Computed data root matches the data root from the header
+	Verified,
+	/// Computed data root doesn't match the data root from the header
+	Mismatch,
+	/// Data of some apps in the block is not reconstructed, so data root cannot be computed
+	Unverified,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DataRootVerification {
+	pub block: u32,
+	pub data_root: H256,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub computed_data_root: Option<H256>,
+	pub status: DataRootStatus,
+}
+
+/// Calculates root of the binary Merkle tree with Keccak hashes of the leaves,
+/// where last node of the level with odd number of nodes is promoted to the next level
+fn merkle_root(leaves: &[H256]) -> H256 {
+	let mut level = leaves
+		.iter()
+		.map(|leaf| H256(keccak_256(leaf.as_bytes())))
+		.collect::<Vec<_>>();
+	if level.is_empty() {
+		return H256::zero();
+	}
+	while level.len() > 1 {
+		level = level
+			.chunks(2)
+			.map(|pair| match pair {
+				[left, right] => H256(keccak_256(&[left.as_bytes(), right.as_bytes()].concat())),
+				[single] => *single,
+				_ => unreachable!("Chunks have one or two nodes"),
+			})
+			.collect();
+	}
+	level[0]
+}
+
+/// Calculates data root from the submitted data blobs, assuming empty bridge root
+pub fn calculate_data_root(blobs: &[Vec<u8>]) -> H256 {
+	let leaves = blobs
+		.iter()
+		.map(|blob| H256(keccak_256(blob)))
+		.collect::<Vec<_>>();
+	let blob_root = merkle_root(&leaves);
+	let bridge_root = H256::zero();
+	H256(keccak_256(
+		&[blob_root.as_bytes(), bridge_root.as_bytes()].concat(),
+	))
+}
+
+/// Verifies data root of the block with the given header, using reconstructed app data from the database
+pub fn verify(db: &impl Database, header: &Header) -> Result<DataRootVerification> {
+	let block = header.number;
+	let data_root = extract_kate(&header.extension)
+		.map(|(_, _, data_root, _)| data_root)
+		.unwrap_or_default();
+	let app_ids = extract_app_lookup(&header.extension)?
+		.map(|lookup| lookup.apps_in_block())
+		.unwrap_or_default();
+
+	let mut blobs = vec![];
+	for app_id in app_ids {
+		let Some(extrinsics) = db.get::<Vec<Vec<u8>>>(Key::AppData(app_id, block))? else {
+			return Ok(DataRootVerification {
+				block,
+				data_root,
+				computed_data_root: None,
+				status: DataRootStatus::Unverified,
+			});
+		};
+		for extrinsic in extrinsics {
+			blobs.extend(decode_app_data(&extrinsic)?);
+		}
+	}
+
+	let computed_data_root = calculate_data_root(&blobs);
+	let status = match computed_data_root == data_root {
+		true => DataRootStatus::Verified,
+		false => DataRootStatus::Mismatch,
+	};
+	Ok(DataRootVerification {
+		block,
+		data_root,
+		computed_data_root: Some(computed_data_root),
+		status,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{calculate_data_root, merkle_root};
+	use avail_subxt::utils::H256;
+	use sp_core::keccak_256;
+
+	fn hash(data: &[u8]) -> H256 {
+		H256(keccak_256(data))
+	}
+
+	#[test]
+	fn merkle_root_of_leaves() {
+		assert_eq!(merkle_root(&[]), H256::zero());
+
+		let (a, b, c) = (hash(b"a"), hash(b"b"), hash(b"c"));
+		assert_eq!(merkle_root(&[a]), hash(a.as_bytes()));
+
+		let ab = hash(&[hash(a.as_bytes()).0, hash(b.as_bytes()).0].concat());
+		assert_eq!(merkle_root(&[a, b]), ab);
+
+		let abc = hash(&[ab.0, hash(c.as_bytes()).0].concat());
+		assert_eq!(merkle_root(&[a, b, c]), abc);
+	}
+
+	#[test]
+	fn data_root_without_blobs() {
+		assert_eq!(calculate_data_root(&[]), hash(&[0u8; 64]));
+	}
+}
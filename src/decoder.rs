@@ -0,0 +1,102 @@
+//! Decoders of application data payloads.
+//!
+//! Application data is stored as opaque bytes. Embedders can register app specific decoders
+//! into [`Decoders`], and decoded payloads are served on the `/v1/appdata/{block_number}` endpoint
+//! when `decode` query parameter is set. Built-in `hex` and `utf8` decoders are available by name.
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use serde_json::Value;
+use std::{
+	collections::HashMap,
+	sync::{Arc, RwLock},
+};
+
+/// Decoder of the application data payload (data of the `submit_data` call)
+pub trait Decoder: Send + Sync {
+	/// Name of the decoder
+	fn name(&self) -> &str;
+
+	/// Decodes payload into JSON value served by the API
+	fn decode(&self, payload: &[u8]) -> Result<Value>;
+}
+
+/// Decodes payload into the `0x` prefixed hex string
+pub struct HexDecoder;
+
+impl Decoder for HexDecoder {
+	fn name(&self) -> &str {
+		"hex"
+	}
+
+	fn decode(&self, payload: &[u8]) -> Result<Value> {
+		Ok(Value::String(format!("0x{}", hex::encode(payload))))
+	}
+}
+
+/// Decodes payload into the UTF-8 string, fails if payload is not valid UTF-8
+pub struct Utf8Decoder;
+
+impl Decoder for Utf8Decoder {
+	fn name(&self) -> &str {
+		"utf8"
+	}
+
+	fn decode(&self, payload: &[u8]) -> Result<Value> {
+		let text = std::str::from_utf8(payload).wrap_err("Payload is not valid UTF-8")?;
+		Ok(Value::String(text.to_string()))
+	}
+}
+
+/// Returns built-in decoder with the given name
+pub fn builtin(name: &str) -> Result<Arc<dyn Decoder>> {
+	match name {
+		"hex" => Ok(Arc::new(HexDecoder)),
+		"utf8" => Ok(Arc::new(Utf8Decoder)),
+		_ => Err(eyre!("Unknown app data decoder {name}")),
+	}
+}
+
+/// Registry of the app specific payload decoders
+#[derive(Clone, Default)]
+pub struct Decoders(Arc<RwLock<HashMap<u32, Arc<dyn Decoder>>>>);
+
+impl Decoders {
+	/// Registers decoder for the given app, replacing previously registered one
+	pub fn register(&self, app_id: u32, decoder: Arc<dyn Decoder>) {
+		let mut decoders = self.0.write().expect("Lock should be acquired");
+		decoders.insert(app_id, decoder);
+	}
+
+	pub fn get(&self, app_id: u32) -> Option<Arc<dyn Decoder>> {
+		let decoders = self.0.read().expect("Lock should be acquired");
+		decoders.get(&app_id).cloned()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{builtin, Decoders};
+	use serde_json::json;
+
+	#[test]
+	fn builtin_decoders() {
+		let hex = builtin("hex").unwrap();
+		assert_eq!(hex.decode(b"avail").unwrap(), json!("0x617661696c"));
+		let utf8 = builtin("utf8").unwrap();
+		assert_eq!(utf8.decode(b"avail").unwrap(), json!("avail"));
+		assert!(utf8.decode(&[0xff, 0xfe]).is_err());
+		assert!(builtin("unknown").is_err());
+	}
+
+	#[test]
+	fn register_decoder() {
+		let decoders = Decoders::default();
+		assert!(decoders.get(1).is_none());
+		decoders.register(1, builtin("utf8").unwrap());
+		assert_eq!(decoders.get(1).unwrap().name(), "utf8");
+		assert!(decoders.get(2).is_none());
+	}
+}
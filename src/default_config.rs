@@ -0,0 +1,79 @@
+//! Default configuration file, embedded into the binary.
+//!
+//! If light client is started without a config file, commented default configuration of the selected network
+//! (local network if not selected) is written to `config.yaml` in the data directory, unless it already exists.
+//! That file is then loaded on each start without a config file, so it can be edited in place,
+//! and a single static binary is sufficient to start the light client. Command line flags override the file as usual.
+
+use color_eyre::{eyre::WrapErr, Result};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use crate::types::Network;
+
+/// Name of the default config file in the data directory
+pub const FILE_NAME: &str = "config.yaml";
+
+const TEMPLATE: &str = include_str!("default_config.yaml");
+
+/// Renders commented default configuration of the given network
+pub fn render(network: &Network) -> String {
+	let bootstraps = format!(
+		"[\"{}/p2p/{}\"]",
+		network.bootstrap_multiaddrr(),
+		network.bootstrap_peer_id()
+	);
+	TEMPLATE
+		.replace("${BOOTSTRAPS}", &bootstraps)
+		.replace("${FULL_NODE_WS}", network.full_node_ws())
+		.replace("${GENESIS_HASH}", network.genesis_hash())
+		.replace("${OT_COLLECTOR_ENDPOINT}", network.ot_collector_endpoint())
+}
+
+/// Writes default configuration of the given network to the data directory, if the file doesn't exist.
+/// Returns path of the config file, and whether it was written.
+pub fn init(data_dir: &Path, network: &Network) -> Result<(PathBuf, bool)> {
+	let path = data_dir.join(FILE_NAME);
+	if path.exists() {
+		return Ok((path, false));
+	}
+	fs::write(&path, render(network))
+		.wrap_err_with(|| format!("Cannot write default config file {}", path.display()))?;
+	Ok((path, true))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::init;
+	use crate::types::{Network, RuntimeConfig};
+	use clap::ValueEnum;
+	use std::fs;
+
+	#[test]
+	fn default_config_is_valid() {
+		let default = RuntimeConfig::default();
+		for network in Network::value_variants() {
+			let data_dir =
+				std::env::temp_dir().join(format!("default-config-{}", rand::random::<u64>()));
+			fs::create_dir_all(&data_dir).unwrap();
+
+			let (path, written) = init(&data_dir, network).unwrap();
+			assert!(written);
+			let cfg: RuntimeConfig = confy::load_path(&path).unwrap();
+			assert_eq!(cfg.full_node_ws, vec![network.full_node_ws().to_string()]);
+			assert_eq!(cfg.genesis_hash, network.genesis_hash());
+			assert_eq!(cfg.bootstraps.len(), 1);
+			assert_eq!(cfg.confidence, default.confidence);
+			assert_eq!(cfg.port, default.port);
+			assert_eq!(cfg.avail_path, default.avail_path);
+
+			// Existing file is not overwritten
+			fs::write(&path, "port = 38000").unwrap();
+			assert!(!init(&data_dir, network).unwrap().1);
+			assert_eq!(fs::read_to_string(&path).unwrap(), "port = 38000");
+			fs::remove_dir_all(&data_dir).unwrap();
+		}
+	}
+}
@@ -0,0 +1,200 @@
+//! Internal event bus, decoupling the subsystems.
+//!
+//! Producers (RPC header subscription, light and sync clients, application client, P2P network
+//! and missed block detection) publish events to the bus, and consumers (WebSocket push,
+//! event sinks, exporter, Postgres mirror, metrics and webhooks) subscribe to it, so new features
+//! don't need to be wired into the main loop. Each event kind is published to its own typed broadcast channel,
+//! so consumers receive only the events they subscribed to (e.g. [`EventBus::subscribe_blocks`]).
+
+use avail_subxt::primitives::Header;
+use kate_recovery::com::AppData;
+use std::{sync::Arc, time::Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::warn;
+
+use crate::{
+	block_time::MissedBlockEvent,
+	network::{p2p::PeerEvent, rpc},
+	telemetry::{MetricCounter, Metrics},
+	types::BlockVerified,
+};
+
+/// Events published to the bus
+#[derive(Clone, Debug)]
+pub enum Event {
+	/// Finalized header is received from the full node
+	HeaderReceived {
+		header: Header,
+		received_at: Instant,
+	},
+	/// Block confidence is achieved
+	BlockVerified(BlockVerified),
+	/// Application data of the block is verified
+	AppDataReady {
+		block_number: u32,
+		app_data: AppData,
+	},
+	/// Connection with the peer is established or closed
+	PeerEvent(PeerEvent),
+	/// No finalized header is received within the expected block time
+	Degraded(MissedBlockEvent),
+}
+
+impl From<rpc::Event> for Event {
+	fn from(event: rpc::Event) -> Self {
+		let rpc::Event::HeaderUpdate {
+			header,
+			received_at,
+		} = event;
+		Event::HeaderReceived {
+			header,
+			received_at,
+		}
+	}
+}
+
+/// Broadcast channels of the event kinds, so consumers receive only the events they subscribed to
+#[derive(Clone)]
+pub struct EventBus {
+	headers: broadcast::Sender<rpc::Event>,
+	blocks: broadcast::Sender<BlockVerified>,
+	app_data: broadcast::Sender<(u32, AppData)>,
+	peer_events: broadcast::Sender<PeerEvent>,
+	missed_blocks: broadcast::Sender<MissedBlockEvent>,
+}
+
+impl EventBus {
+	pub fn new(capacity: usize) -> Self {
+		EventBus {
+			headers: broadcast::channel(capacity).0,
+			blocks: broadcast::channel(capacity).0,
+			app_data: broadcast::channel(capacity).0,
+			peer_events: broadcast::channel(capacity).0,
+			missed_blocks: broadcast::channel(capacity).0,
+		}
+	}
+
+	/// Publishes the event to the channel of its kind, event is dropped if there are no subscribers
+	pub fn publish(&self, event: Event) {
+		match event {
+			Event::HeaderReceived {
+				header,
+				received_at,
+			} => {
+				_ = self.headers.send(rpc::Event::HeaderUpdate {
+					header,
+					received_at,
+				})
+			},
+			Event::BlockVerified(block) => _ = self.blocks.send(block),
+			Event::AppDataReady {
+				block_number,
+				app_data,
+			} => _ = self.app_data.send((block_number, app_data)),
+			Event::PeerEvent(event) => _ = self.peer_events.send(event),
+			Event::Degraded(event) => _ = self.missed_blocks.send(event),
+		}
+	}
+
+	/// Subscribes to the received finalized headers.
+	/// Events published before subscribing are not received, so consumers must subscribe before producers are started.
+	pub fn subscribe_headers(&self) -> broadcast::Receiver<rpc::Event> {
+		self.headers.subscribe()
+	}
+
+	/// Subscribes to the blocks which achieved confidence
+	pub fn subscribe_blocks(&self) -> broadcast::Receiver<BlockVerified> {
+		self.blocks.subscribe()
+	}
+
+	/// Subscribes to the verified application data
+	pub fn subscribe_app_data(&self) -> broadcast::Receiver<(u32, AppData)> {
+		self.app_data.subscribe()
+	}
+
+	/// Subscribes to the peer connection events
+	pub fn subscribe_peer_events(&self) -> broadcast::Receiver<PeerEvent> {
+		self.peer_events.subscribe()
+	}
+
+	/// Subscribes to the missed block events
+	pub fn subscribe_missed_blocks(&self) -> broadcast::Receiver<MissedBlockEvent> {
+		self.missed_blocks.subscribe()
+	}
+
+	/// Publishes the values received from the producer channel, converted into events
+	pub async fn forward<T: Clone>(
+		self,
+		mut receiver: broadcast::Receiver<T>,
+		into: fn(T) -> Event,
+	) {
+		loop {
+			match receiver.recv().await {
+				Ok(value) => self.publish(into(value)),
+				Err(RecvError::Lagged(skipped)) => {
+					warn!("Event bus producer lagged, {skipped} events skipped");
+				},
+				Err(RecvError::Closed) => return,
+			}
+		}
+	}
+}
+
+/// Counts peer disconnections received from the event bus
+pub async fn count_peer_events(
+	mut receiver: broadcast::Receiver<PeerEvent>,
+	metrics: Arc<impl Metrics>,
+) {
+	loop {
+		match receiver.recv().await {
+			Ok(PeerEvent::Disconnected(_)) => {
+				metrics.count(MetricCounter::PeerDisconnections).await
+			},
+			Ok(PeerEvent::Connected(_)) | Err(RecvError::Lagged(_)) => continue,
+			Err(RecvError::Closed) => return,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Event, EventBus};
+	use avail_subxt::utils::H256;
+	use tokio::sync::broadcast;
+
+	fn block_verified(block_num: u32) -> Event {
+		Event::BlockVerified(crate::types::BlockVerified {
+			header_hash: H256::zero(),
+			block_num,
+			extension: None,
+			confidence: None,
+		})
+	}
+
+	#[tokio::test]
+	async fn subscribe_to_event_kinds() {
+		let bus = EventBus::new(8);
+		let mut blocks = bus.subscribe_blocks();
+		let mut data = bus.subscribe_app_data();
+
+		let (sender, receiver) = broadcast::channel(8);
+		tokio::spawn(
+			bus.clone()
+				.forward(receiver, |block_number| Event::AppDataReady {
+					block_number,
+					app_data: vec![],
+				}),
+		);
+
+		bus.publish(block_verified(1));
+		sender.send(2u32).unwrap();
+		bus.publish(block_verified(3));
+
+		assert_eq!(blocks.recv().await.unwrap().block_num, 1);
+		assert_eq!(blocks.recv().await.unwrap().block_num, 3);
+		assert_eq!(data.recv().await.unwrap(), (2, vec![]));
+		// Events of other kinds are not received
+		assert!(blocks.try_recv().is_err());
+		assert!(data.try_recv().is_err());
+	}
+}
@@ -0,0 +1,291 @@
+//! Export of verified block headers and decoded application data.
+//!
+//! Exporter writes JSON objects to a local directory or to S3-compatible storage (with `s3-export` feature)
+//! using the following layout:
+//!
+//! * `{prefix}/headers/{block_number}.json` - block header, in the `/v2/blocks/{block_number}/header` format
+//! * `{prefix}/apps/{app_id}/{block_number}.json` - application data, in the `data-verified` message format
+//!
+//! Export can be gated (see [`crate::export_gate`]), so only blocks with sufficient confidence
+//! and/or confirmed finality are exported.
+
+use crate::{
+	api::v2::types::{DataTransaction, Header},
+	data::{Database, Key},
+	export_gate::{Gate, GateConfig, FINALITY_CHECK_INTERVAL},
+	types::State,
+};
+use async_trait::async_trait;
+use avail_subxt::primitives;
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use kate_recovery::com::AppData;
+use serde::{Deserialize, Serialize};
+use std::{
+	path::PathBuf,
+	sync::{Arc, Mutex},
+};
+use tokio::{sync::broadcast, time};
+use tracing::{error, info};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum ExportTarget {
+	/// Exports objects into the local directory
+	Directory { path: String },
+	/// Exports objects into the S3-compatible bucket, credentials are read from the environment
+	S3 {
+		bucket: String,
+		region: String,
+		/// Custom endpoint for S3-compatible storage (default: None)
+		endpoint: Option<String>,
+		/// Prefix of object keys (default: empty)
+		#[serde(default)]
+		prefix: String,
+	},
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExportConfig {
+	pub target: ExportTarget,
+	/// Export block headers along with application data (default: true)
+	#[serde(default = "default_export_headers")]
+	pub headers: bool,
+	/// Conditions which block must meet before it is exported (default: none)
+	#[serde(default)]
+	pub gate: GateConfig,
+}
+
+fn default_export_headers() -> bool {
+	true
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExportedAppData {
+	pub block_number: u32,
+	pub app_id: u32,
+	pub data_transactions: Vec<DataTransaction>,
+}
+
+#[async_trait]
+pub trait Storage {
+	async fn put(&self, key: &str, value: Vec<u8>) -> Result<()>;
+}
+
+pub struct Directory {
+	path: PathBuf,
+}
+
+#[async_trait]
+impl Storage for Directory {
+	async fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
+		let path = self.path.join(key);
+		if let Some(parent) = path.parent() {
+			tokio::fs::create_dir_all(parent)
+				.await
+				.wrap_err_with(|| format!("Cannot create directory {parent:?}"))?;
+		}
+		tokio::fs::write(&path, value)
+			.await
+			.wrap_err_with(|| format!("Cannot write file {path:?}"))
+	}
+}
+
+#[cfg(all(
+	feature = "s3-export",
+	not(any(feature = "native-tls", feature = "rustls"))
+))]
+compile_error!("S3 export requires either `native-tls` or `rustls` feature");
+
+#[cfg(feature = "s3-export")]
+pub struct S3 {
+	bucket: s3::Bucket,
+	prefix: String,
+}
+
+#[cfg(feature = "s3-export")]
+#[async_trait]
+impl Storage for S3 {
+	async fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
+		let key = match self.prefix.as_str() {
+			"" => key.to_string(),
+			prefix => format!("{}/{key}", prefix.trim_end_matches('/')),
+		};
+		self.bucket
+			.put_object_with_content_type(&key, &value, "application/json")
+			.await
+			.wrap_err_with(|| format!("Cannot put object {key}"))?;
+		Ok(())
+	}
+}
+
+pub fn storage(target: &ExportTarget) -> Result<Box<dyn Storage + Send + Sync>> {
+	match target {
+		ExportTarget::Directory { path } => Ok(Box::new(Directory {
+			path: PathBuf::from(path),
+		})),
+		#[cfg(feature = "s3-export")]
+		ExportTarget::S3 {
+			bucket,
+			region,
+			endpoint,
+			prefix,
+		} => {
+			let region = match endpoint {
+				Some(endpoint) => s3::Region::Custom {
+					region: region.clone(),
+					endpoint: endpoint.clone(),
+				},
+				None => region.parse().wrap_err("Invalid S3 region")?,
+			};
+			let credentials = s3::creds::Credentials::default()
+				.wrap_err("Cannot load S3 credentials from the environment")?;
+			let bucket = s3::Bucket::new(bucket, region, credentials)
+				.wrap_err("Cannot create S3 bucket client")?
+				.with_path_style();
+			Ok(Box::new(S3 {
+				bucket,
+				prefix: prefix.clone(),
+			}))
+		},
+		#[cfg(not(feature = "s3-export"))]
+		ExportTarget::S3 { .. } => Err(eyre!(
+			"Export to S3 requires light client to be compiled with `s3-export` feature"
+		)),
+	}
+}
+
+fn header_key(block_number: u32) -> String {
+	format!("headers/{block_number}.json")
+}
+
+fn app_data_key(app_id: u32, block_number: u32) -> String {
+	format!("apps/{app_id}/{block_number}.json")
+}
+
+async fn export_block(
+	storage: &(dyn Storage + Send + Sync),
+	db: &impl Database,
+	cfg: &ExportConfig,
+	app_id: u32,
+	block_number: u32,
+	app_data: AppData,
+) -> Result<()> {
+	if cfg.headers {
+		let header: Header = db
+			.get::<primitives::Header>(Key::BlockHeader(block_number))?
+			.ok_or_else(|| eyre!("Header for block {block_number} is not found"))?
+			.try_into()?;
+		let header = serde_json::to_vec(&header).wrap_err("Cannot serialize header")?;
+		storage.put(&header_key(block_number), header).await?;
+	}
+
+	let data_transactions = app_data
+		.into_iter()
+		.map(TryFrom::try_from)
+		.collect::<Result<Vec<_>>>()?;
+
+	let app_data = ExportedAppData {
+		block_number,
+		app_id,
+		data_transactions,
+	};
+	let app_data = serde_json::to_vec(&app_data).wrap_err("Cannot serialize app data")?;
+	storage
+		.put(&app_data_key(app_id, block_number), app_data)
+		.await
+}
+
+/// Runs exporter which writes verified application data for the followed app ID
+pub async fn run(
+	cfg: ExportConfig,
+	withheld_fraction: f64,
+	db: impl Database + Clone,
+	state: Arc<Mutex<State>>,
+	app_id: u32,
+	mut data_receiver: broadcast::Receiver<(u32, AppData)>,
+) -> Result<()> {
+	info!("Starting exporter...");
+	let storage = storage(&cfg.target)?;
+	let mut gate = Gate::new(
+		"export".to_string(),
+		cfg.gate.clone(),
+		withheld_fraction,
+		db.clone(),
+		state,
+	)?;
+	let mut finality_check = time::interval(FINALITY_CHECK_INTERVAL);
+
+	loop {
+		let received = tokio::select! {
+			_ = finality_check.tick() => None,
+			received = data_receiver.recv() => match received {
+				Ok(value) => Some(value),
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					error!("Exporter lagged, {skipped} blocks are not exported");
+					continue;
+				},
+				Err(broadcast::error::RecvError::Closed) => return Ok(()),
+			},
+		};
+
+		// Blocks of the gated exporter are queued by the gate until they can be exported
+		let blocks = match &mut gate {
+			None => received.into_iter().collect::<Vec<_>>(),
+			Some(gate) => {
+				if let Some((block_number, app_data)) = received {
+					gate.admit(block_number, (block_number, app_data));
+				}
+				gate.release().into_iter().map(|(_, block)| block).collect()
+			},
+		};
+
+		for (block_number, app_data) in blocks {
+			match export_block(&*storage, &db, &cfg, app_id, block_number, app_data).await {
+				Ok(()) => {
+					info!(block_number, "Block exported");
+					if let Some(gate) = &mut gate {
+						gate.emitted(block_number);
+					}
+				},
+				Err(error) => error!(block_number, "Cannot export block: {error:#}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{export_block, Directory, ExportConfig, ExportTarget, ExportedAppData};
+	use crate::data::mem_db;
+	use std::path::PathBuf;
+	use uuid::Uuid;
+
+	#[tokio::test]
+	async fn export_to_directory() {
+		let path = std::env::temp_dir().join(Uuid::new_v4().to_string());
+		let storage = Directory { path: path.clone() };
+		let cfg = ExportConfig {
+			target: ExportTarget::Directory {
+				path: path.to_string_lossy().to_string(),
+			},
+			headers: false,
+			gate: Default::default(),
+		};
+
+		export_block(&storage, &mem_db::MemoryDB::default(), &cfg, 1, 10, vec![])
+			.await
+			.unwrap();
+
+		let file = PathBuf::from(&path).join("apps/1/10.json");
+		let exported: ExportedAppData =
+			serde_json::from_slice(&std::fs::read(file).unwrap()).unwrap();
+		assert_eq!(exported.block_number, 10);
+		assert_eq!(exported.app_id, 1);
+		assert!(exported.data_transactions.is_empty());
+
+		std::fs::remove_dir_all(path).unwrap();
+	}
+}
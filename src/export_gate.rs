@@ -0,0 +1,295 @@
+//! Gating of the blocks emitted by the downstream exporters.
+//!
+//! Event sinks, Postgres mirror and exporter can be configured to emit a block only once its confidence
+//! reaches the threshold, and/or its finality is confirmed with the justification verified by the light client.
+//! Blocks below the confidence threshold are not emitted, while blocks waiting for their finality are queued
+//! by the gate of each exporter, and released once the finality is confirmed, or dropped after [`FINALITY_TIMEOUT`].
+//! Exporters release the queued blocks on each received block, and every [`FINALITY_CHECK_INTERVAL`].
+//! Each gated exporter persists its watermark (the highest emitted block),
+//! so blocks emitted before the restart are not emitted again.
+
+use color_eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+use crate::{
+	confidence::Confidence,
+	data::{Database, Key},
+	types::{RuntimeConfig, State},
+};
+
+/// Maximum time to wait for the finality of the block, after which the block is not emitted
+pub const FINALITY_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Interval in which the finality of the queued blocks is checked
+pub const FINALITY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum number of the items waiting for finality, oldest items are dropped when exceeded
+const MAX_PENDING: usize = 1024;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct GateConfig {
+	/// Minimum confidence in percents, blocks with lower confidence are not emitted (default: None)
+	pub min_confidence: Option<Confidence>,
+	/// Emit blocks only once their finality is confirmed, requires the `subscription` header source (default: false)
+	pub require_finality: bool,
+}
+
+impl GateConfig {
+	pub fn is_enabled(&self) -> bool {
+		self.min_confidence.is_some() || self.require_finality
+	}
+}
+
+/// Checks if any of the configured exporters emits blocks only once their finality is confirmed
+pub fn requires_finality(cfg: &RuntimeConfig) -> bool {
+	let mut gates = cfg
+		.event_sinks
+		.iter()
+		.map(|sink| sink.gate().clone())
+		.collect::<Vec<_>>();
+	gates.extend(cfg.export.as_ref().map(|export| export.gate.clone()));
+	#[cfg(feature = "postgres")]
+	gates.push(cfg.postgres.gate());
+	gates.iter().any(|gate| gate.require_finality)
+}
+
+/// Gate of the exporter with the given name, which watermark is persisted.
+/// Items of the admitted blocks are queued until they can be emitted.
+pub struct Gate<T: Database, I> {
+	name: String,
+	cfg: GateConfig,
+	/// Assumed fraction of the block cells withheld by an adversary, used to calculate confidence
+	withheld_fraction: f64,
+	db: T,
+	state: Arc<Mutex<State>>,
+	/// Watermark loaded on startup, blocks up to it were emitted before the restart
+	emitted_before: Option<u32>,
+	watermark: Option<u32>,
+	/// Items waiting for the finality of their blocks, with the deadline
+	pending: VecDeque<(u32, Instant, I)>,
+}
+
+impl<T: Database, I> Gate<T, I> {
+	/// Creates the gate if it is enabled in the configuration
+	pub fn new(
+		name: String,
+		cfg: GateConfig,
+		withheld_fraction: f64,
+		db: T,
+		state: Arc<Mutex<State>>,
+	) -> Result<Option<Self>> {
+		if !cfg.is_enabled() {
+			return Ok(None);
+		}
+		let watermark = db.get::<u32>(Key::ExportWatermark(name.clone()))?;
+		Ok(Some(Gate {
+			name,
+			cfg,
+			withheld_fraction,
+			db,
+			state,
+			emitted_before: watermark,
+			watermark,
+			pending: VecDeque::new(),
+		}))
+	}
+
+	fn has_confidence(&self, block_number: u32) -> Result<bool> {
+		let Some(min_confidence) = self.cfg.min_confidence else {
+			return Ok(true);
+		};
+		let count = self.db.get::<u32>(Key::VerifiedCellCount(block_number))?;
+		Ok(count.is_some_and(|count| {
+			Confidence::from_cell_count(count, self.withheld_fraction).reaches(min_confidence)
+		}))
+	}
+
+	fn is_final(&self, block_number: u32) -> bool {
+		if !self.cfg.require_finality {
+			return true;
+		}
+		let state = self.state.lock().expect("Lock should be acquired");
+		state.finality_synced
+			&& state
+				.finality_verified
+				.is_some_and(|verified| block_number <= verified)
+	}
+
+	/// Queues the item of the block, unless the block was already emitted or its confidence is below the threshold.
+	/// Queued items are taken with [`Gate::release`].
+	pub fn admit(&mut self, block_number: u32, item: I) {
+		if self
+			.emitted_before
+			.is_some_and(|emitted| block_number <= emitted)
+		{
+			debug!(
+				exporter = self.name,
+				block_number, "Block was already emitted"
+			);
+			return;
+		}
+
+		match self.has_confidence(block_number) {
+			Ok(true) => (),
+			Ok(false) => {
+				debug!(
+					exporter = self.name,
+					block_number, "Block confidence is below the threshold"
+				);
+				return;
+			},
+			Err(error) => {
+				warn!(
+					exporter = self.name,
+					block_number, "Cannot get block confidence: {error:#}"
+				);
+				return;
+			},
+		}
+
+		if self.pending.len() >= MAX_PENDING {
+			if let Some((dropped, _, _)) = self.pending.pop_front() {
+				warn!(
+					exporter = self.name,
+					block_number = dropped,
+					"Too many blocks are waiting for finality, block is not emitted"
+				);
+			}
+		}
+		let deadline = Instant::now() + FINALITY_TIMEOUT;
+		self.pending.push_back((block_number, deadline, item));
+	}
+
+	/// Takes the queued items which can be emitted, in order of admission.
+	/// Items which finality is not confirmed before the deadline are dropped.
+	pub fn release(&mut self) -> Vec<(u32, I)> {
+		let mut released = vec![];
+		while let Some(&(block_number, deadline, _)) = self.pending.front() {
+			if !self.is_final(block_number) {
+				if Instant::now() < deadline {
+					break;
+				}
+				warn!(
+					exporter = self.name,
+					block_number, "Block finality is not confirmed, block is not emitted"
+				);
+				self.pending.pop_front();
+				continue;
+			}
+			if let Some((block_number, _, item)) = self.pending.pop_front() {
+				released.push((block_number, item));
+			}
+		}
+		released
+	}
+
+	/// Advances the persisted watermark after the block is emitted
+	pub fn emitted(&mut self, block_number: u32) {
+		if self
+			.watermark
+			.is_some_and(|watermark| block_number <= watermark)
+		{
+			return;
+		}
+		self.watermark = Some(block_number);
+		if let Err(error) = self
+			.db
+			.put(Key::ExportWatermark(self.name.clone()), block_number)
+		{
+			warn!(
+				exporter = self.name,
+				"Cannot store export watermark: {error:#}"
+			);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Gate, GateConfig};
+	use crate::{
+		confidence::Confidence,
+		data::{mem_db::MemoryDB, Database, Key},
+		types::State,
+		utils::DEFAULT_WITHHELD_FRACTION,
+	};
+	use std::sync::{Arc, Mutex};
+
+	#[tokio::test]
+	async fn gate_blocks() {
+		let db = MemoryDB::default();
+		let state = Arc::new(Mutex::new(State::default()));
+		let cfg = GateConfig {
+			min_confidence: Some(Confidence::new(99.9)),
+			require_finality: true,
+		};
+		assert!(Gate::<_, ()>::new(
+			"test".to_string(),
+			GateConfig::default(),
+			DEFAULT_WITHHELD_FRACTION,
+			db.clone(),
+			state.clone()
+		)
+		.unwrap()
+		.is_none());
+
+		db.put(Key::VerifiedCellCount(1), 1u32).unwrap();
+		db.put(Key::VerifiedCellCount(2), 20u32).unwrap();
+		db.put(Key::VerifiedCellCount(3), 20u32).unwrap();
+		db.put(Key::VerifiedCellCount(4), 20u32).unwrap();
+		{
+			let mut state = state.lock().unwrap();
+			state.finality_synced = true;
+			state.finality_verified = Some(3);
+		}
+
+		let mut gate = Gate::new(
+			"test".to_string(),
+			cfg.clone(),
+			DEFAULT_WITHHELD_FRACTION,
+			db.clone(),
+			state.clone(),
+		)
+		.unwrap()
+		.unwrap();
+		gate.admit(1, "first");
+		gate.admit(2, "second");
+		assert_eq!(gate.release(), vec![(2, "second")]);
+		gate.emitted(2);
+		// Lower blocks don't move the watermark back
+		gate.emitted(1);
+		assert_eq!(
+			db.get::<u32>(Key::ExportWatermark("test".to_string()))
+				.unwrap(),
+			Some(2)
+		);
+
+		// Blocks emitted before the restart are not emitted again
+		let mut gate = Gate::new(
+			"test".to_string(),
+			cfg,
+			DEFAULT_WITHHELD_FRACTION,
+			db,
+			state.clone(),
+		)
+		.unwrap()
+		.unwrap();
+		gate.admit(2, "second");
+		gate.admit(3, "third");
+		assert_eq!(gate.release(), vec![(3, "third")]);
+
+		// Block waits for its finality without blocking the other blocks
+		gate.admit(4, "fourth");
+		assert_eq!(gate.release(), vec![]);
+		state.lock().unwrap().finality_verified = Some(4);
+		assert_eq!(gate.release(), vec![(4, "fourth")]);
+	}
+}
@@ -8,32 +8,43 @@
 //! # Notes
 //!
 //! In case delay is configured, block processing is delayed for configured time.
+//! In case local proof generation is enabled, cell proofs are computed from the rows fetched from the full node,
+//! so the partition can be served by nodes which provide row data without cell proofs.
 
 use async_trait::async_trait;
 use avail_subxt::{primitives::Header, utils::H256};
 use codec::Encode;
-use color_eyre::{eyre::WrapErr, Result};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
 use futures::future::join_all;
+use itertools::Itertools;
 use kate_recovery::{
-	data,
+	commitments, data,
 	matrix::{Dimensions, Partition, Position},
 };
 use kate_recovery::{data::Cell, matrix::RowIndex};
 use mockall::automock;
 use sp_core::blake2_256;
-use std::{sync::Arc, time::Instant};
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
 use crate::{
-	data::{Database, Key},
+	block_state::{self, BlockState},
+	data::{put_verified_at, Database, Key},
 	network::{
 		p2p::Client as P2pClient,
 		rpc::{Client as RpcClient, Event},
 	},
+	proof,
 	shutdown::Controller,
+	supervisor,
 	telemetry::{MetricCounter, MetricValue, Metrics},
 	types::{BlockVerified, ClientChannels, FatClientConfig},
-	utils::extract_kate,
+	utils::{extract_kate, unix_timestamp_nanos},
 };
 
 #[async_trait]
@@ -41,19 +52,85 @@ use crate::{
 pub trait Client {
 	async fn insert_cells_into_dht(&self, block: u32, cells: Vec<Cell>) -> Result<()>;
 	async fn insert_rows_into_dht(&self, block: u32, rows: Vec<(RowIndex, Vec<u8>)>) -> Result<()>;
-	async fn get_kate_proof(&self, hash: H256, positions: &[Position]) -> Result<Vec<Cell>>;
+	async fn get_kate_proof(
+		&self,
+		hash: H256,
+		commitments: &[[u8; 48]],
+		positions: &[Position],
+	) -> Result<Vec<Cell>>;
 }
 
 #[derive(Clone)]
 pub struct FatClient {
 	p2p_client: P2pClient,
 	rpc_client: RpcClient,
+	/// Public parameters used to generate cell proofs locally, if enabled
+	public_parameters: Option<Arc<PublicParameters>>,
 }
 
 pub fn new(p2p_client: P2pClient, rpc_client: RpcClient) -> FatClient {
 	FatClient {
 		p2p_client,
 		rpc_client,
+		public_parameters: None,
+	}
+}
+
+impl FatClient {
+	/// Generates cell proofs locally from the rows fetched from the full node, instead of requesting cell proofs
+	pub fn with_local_proofs(self, public_parameters: Arc<PublicParameters>) -> Self {
+		FatClient {
+			public_parameters: Some(public_parameters),
+			..self
+		}
+	}
+
+	/// Generates cells from the fetched rows, which are checked against the row commitments
+	async fn generate_cells(
+		&self,
+		public_parameters: Arc<PublicParameters>,
+		hash: H256,
+		commitments: &[[u8; 48]],
+		positions: &[Position],
+	) -> Result<Vec<Cell>> {
+		let mut cols_by_row = BTreeMap::<u32, Vec<u16>>::new();
+		for position in positions {
+			cols_by_row
+				.entry(position.row)
+				.or_default()
+				.push(position.col);
+		}
+		let rows = cols_by_row.keys().copied().collect::<Vec<_>>();
+		let row_commitments = rows
+			.iter()
+			.map(|&row| {
+				commitments
+					.get(row as usize)
+					.copied()
+					.ok_or_else(|| eyre!("Commitment of row {row} is missing"))
+			})
+			.collect::<Result<Vec<_>>>()?;
+		let rows_data = self.rpc_client.request_kate_rows(rows, hash).await?;
+		if rows_data.len() != cols_by_row.len() {
+			return Err(eyre!(
+				"Requested {} rows, received {}",
+				cols_by_row.len(),
+				rows_data.len()
+			));
+		}
+		// Proof generation is CPU bound, so it is not run on the async runtime
+		tokio::task::spawn_blocking(move || {
+			cols_by_row
+				.into_iter()
+				.zip(rows_data)
+				.zip(row_commitments)
+				.map(|(((row, cols), data), commitment)| {
+					proof::generate(&public_parameters, row, &data, &commitment, &cols)
+				})
+				.flatten_ok()
+				.collect::<Result<Vec<_>>>()
+		})
+		.await?
 	}
 }
 
@@ -67,11 +144,25 @@ impl Client for FatClient {
 		self.p2p_client.insert_rows_into_dht(block, rows).await
 	}
 
-	async fn get_kate_proof(&self, hash: H256, positions: &[Position]) -> Result<Vec<Cell>> {
-		self.rpc_client.request_kate_proof(hash, positions).await
+	async fn get_kate_proof(
+		&self,
+		hash: H256,
+		commitments: &[[u8; 48]],
+		positions: &[Position],
+	) -> Result<Vec<Cell>> {
+		match &self.public_parameters {
+			Some(public_parameters) => {
+				self.generate_cells(public_parameters.clone(), hash, commitments, positions)
+					.await
+			},
+			None => self.rpc_client.request_kate_proof(hash, positions).await,
+		}
 	}
 }
 
+/// Fetches the block partition and inserts it into the DHT.
+/// Fails if the token is cancelled, abandoning the in-flight RPC calls.
+#[allow(clippy::too_many_arguments)]
 pub async fn process_block(
 	client: &impl Client,
 	db: impl Database,
@@ -80,6 +171,7 @@ pub async fn process_block(
 	header: &Header,
 	received_at: Instant,
 	partition: Partition,
+	cancel: &CancellationToken,
 ) -> Result<()> {
 	metrics.count(MetricCounter::SessionBlocks).await;
 	metrics
@@ -91,7 +183,9 @@ pub async fn process_block(
 	let block_delay = received_at.elapsed().as_secs();
 	info!(block_number, block_delay, "Processing finalized block",);
 
-	let Some((rows, cols, _, _)) = extract_kate(&header.extension) else {
+	block_state::update(&db, block_number, BlockState::Received);
+
+	let Some((rows, cols, _, commitment)) = extract_kate(&header.extension) else {
 		info!(block_number, "Skipping block without header extension");
 		return Ok(());
 	};
@@ -133,7 +227,8 @@ pub async fn process_block(
 	let begin = Instant::now();
 	let mut rpc_fetched: Vec<Cell> = vec![];
 
-	let get_kate_proof = |&n| client.get_kate_proof(header_hash, n);
+	let commitments = commitments::from_slice(&commitment)?;
+	let get_kate_proof = |&n| client.get_kate_proof(header_hash, &commitments, n);
 
 	let rpc_batches = positions.chunks(cfg.max_cells_per_rpc).collect::<Vec<_>>();
 	let parallel_batches = rpc_batches
@@ -141,7 +236,13 @@ pub async fn process_block(
 		.map(|batch| join_all(batch.iter().map(get_kate_proof)));
 
 	for batch in parallel_batches {
-		for (i, result) in batch.await.into_iter().enumerate() {
+		let results = tokio::select! {
+			results = batch => results,
+			_ = cancel.cancelled() => {
+				return Err(eyre!("Fetching partition of block {block_number} is cancelled"));
+			},
+		};
+		for (i, result) in results.into_iter().enumerate() {
 			let batch_rpc_fetched =
 				result.wrap_err(format!("Failed to fetch cells from node RPC at batch {i}"))?;
 
@@ -170,6 +271,10 @@ pub async fn process_block(
 		))
 		.await;
 
+	if !cfg.row_records {
+		return Ok(());
+	}
+
 	if rpc_fetched.len() >= dimensions.cols().get().into() {
 		let data_cells = rpc_fetched
 			.iter()
@@ -209,6 +314,9 @@ pub async fn run(
 ) {
 	info!("Starting fat client...");
 
+	// in-flight block processing is cancelled on shutdown
+	let shutdown_token = shutdown.cancellation_token();
+
 	loop {
 		let (header, received_at) = match channels.rpc_event_receiver.recv().await {
 			Ok(event) => match event {
@@ -231,22 +339,51 @@ pub async fn run(
 			tokio::time::sleep(seconds).await;
 		}
 
-		if let Err(error) = process_block(
-			&client,
-			db.clone(),
-			&metrics,
-			&cfg,
-			&header,
-			received_at,
-			partition,
+		let process_block_result = supervisor::catch_panic(
+			"header processing",
+			process_block(
+				&client,
+				db.clone(),
+				&metrics,
+				&cfg,
+				&header,
+				received_at,
+				partition,
+				&shutdown_token,
+			),
 		)
-		.await
-		{
+		.await;
+		// Panic on a single malformed header should not stop the fat client
+		let process_block_result = match process_block_result {
+			Ok(result) => result,
+			Err(error) => {
+				error!(block_number = header.number, "Skipping block: {error:#}");
+				let reason = format!("{error:#}");
+				block_state::update(&db, header.number, BlockState::Failed(reason));
+				continue;
+			},
+		};
+		if let Err(error) = process_block_result {
+			if shutdown_token.is_cancelled() {
+				info!(
+					block_number = header.number,
+					"Block processing is cancelled"
+				);
+				return;
+			}
 			error!("Cannot process block: {error}");
 			let _ = shutdown.trigger_shutdown(format!("Cannot process block: {error:#}"));
 			return;
 		};
 
+		// Blocks are listed by verification time, without confidence since cells are not sampled
+		if let Err(error) = put_verified_at(&db, header.number, unix_timestamp_nanos()) {
+			error!(
+				block_number = header.number,
+				"Cannot store verification time: {error:#}"
+			);
+		}
+
 		let Ok(client_msg) = BlockVerified::try_from((header, None)) else {
 			error!("Cannot create message from header");
 			continue;
@@ -369,7 +506,7 @@ mod tests {
 		let mut mock_client = MockClient::new();
 		mock_client
 			.expect_get_kate_proof()
-			.returning(move |_, _| Box::pin(async move { Ok(DEFAULT_CELLS.to_vec()) }));
+			.returning(move |_, _, _| Box::pin(async move { Ok(DEFAULT_CELLS.to_vec()) }));
 		mock_client
 			.expect_insert_rows_into_dht()
 			.returning(|_, _| Box::pin(async move { Ok(()) }));
@@ -389,6 +526,42 @@ mod tests {
 			&default_header(),
 			Instant::now(),
 			entire_block(),
+			&CancellationToken::new(),
+		)
+		.await
+		.unwrap();
+	}
+
+	#[tokio::test]
+	async fn process_block_publishes_row_records() {
+		let db = mem_db::MemoryDB::default();
+		let mut mock_client = MockClient::new();
+		mock_client
+			.expect_get_kate_proof()
+			.returning(move |_, _, _| Box::pin(async move { Ok(DEFAULT_CELLS.to_vec()) }));
+		mock_client
+			.expect_insert_rows_into_dht()
+			.times(1)
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
+		mock_client
+			.expect_insert_cells_into_dht()
+			.returning(|_, _| Box::pin(async move { Ok(()) }));
+
+		let mut mock_metrics = telemetry::MockMetrics::new();
+		mock_metrics.expect_count().returning(|_| ());
+		mock_metrics.expect_record().returning(|_| ());
+
+		let mut cfg = FatClientConfig::from(&RuntimeConfig::default());
+		cfg.row_records = true;
+		process_block(
+			&mock_client,
+			db,
+			&Arc::new(mock_metrics),
+			&cfg,
+			&default_header(),
+			Instant::now(),
+			entire_block(),
+			&CancellationToken::new(),
 		)
 		.await
 		.unwrap();
@@ -0,0 +1,146 @@
+//! Runtime feature flags of the experimental modes.
+//!
+//! Experimental modes and endpoints are enabled independently, by listing them in the `features` configuration
+//! option, and enabled features are reported by the `/v1/version` endpoint. Endpoints of the disabled features
+//! respond with `404 Not Found`. Default features (`row-records`, which was always on before) are enabled
+//! unless listed in the `disabled_features` configuration option. Boolean options which enabled the modes
+//! before (`paranoid` and `header_gossip_publish`) are soft-deprecated: they are still honored,
+//! but a warning is logged on startup.
+
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeSet,
+	fmt::{self, Display, Formatter},
+};
+
+use crate::types::RuntimeConfig;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+	/// Fat client publishes rows of its partition, and application client publishes the app rows
+	/// fetched from the full node, into the DHT as row records
+	RowRecords,
+	/// Received finalized headers are published over gossipsub
+	GossipHeaders,
+	/// Proofs are requested from two different full nodes, using only the cells on which both nodes agree
+	Paranoid,
+	/// Bulk verification endpoints, `POST /v1/verify` and `GET /v1/jobs/{job_id}`
+	BulkVerification,
+	/// Availability reports signed with the Avail identity key, `GET /v2/report`
+	SignedReports,
+}
+
+impl Display for Feature {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Feature::RowRecords => "row-records",
+			Feature::GossipHeaders => "gossip-headers",
+			Feature::Paranoid => "paranoid",
+			Feature::BulkVerification => "bulk-verification",
+			Feature::SignedReports => "signed-reports",
+		})
+	}
+}
+
+/// Features enabled unless they are disabled in the configuration
+pub const DEFAULT_FEATURES: &[Feature] = &[Feature::RowRecords];
+
+/// Deprecated configuration options, which are enabled in the configuration, with the features replacing them
+pub fn deprecated_options(cfg: &RuntimeConfig) -> Vec<(&'static str, Feature)> {
+	[
+		("paranoid", cfg.paranoid, Feature::Paranoid),
+		(
+			"header_gossip_publish",
+			cfg.header_gossip_publish,
+			Feature::GossipHeaders,
+		),
+	]
+	.into_iter()
+	.filter(|&(_, enabled, _)| enabled)
+	.map(|(option, _, feature)| (option, feature))
+	.collect()
+}
+
+/// Features enabled in the configuration, including the default ones which are not disabled,
+/// and the ones enabled by the deprecated options
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Features(BTreeSet<Feature>);
+
+impl Features {
+	pub fn is_enabled(&self, feature: Feature) -> bool {
+		self.0.contains(&feature)
+	}
+
+	/// Enabled features, in the order of declaration
+	pub fn list(&self) -> Vec<Feature> {
+		self.0.iter().copied().collect()
+	}
+}
+
+impl From<&RuntimeConfig> for Features {
+	fn from(cfg: &RuntimeConfig) -> Self {
+		let deprecated = deprecated_options(cfg)
+			.into_iter()
+			.map(|(_, feature)| feature);
+		let defaults = DEFAULT_FEATURES
+			.iter()
+			.filter(|feature| !cfg.disabled_features.contains(feature));
+		Features(
+			defaults
+				.chain(cfg.features.iter())
+				.copied()
+				.chain(deprecated)
+				.collect(),
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{deprecated_options, Feature, Features};
+	use crate::types::RuntimeConfig;
+
+	#[test]
+	fn features_from_config() {
+		let mut cfg = RuntimeConfig::default();
+		assert_eq!(Features::from(&cfg).list(), vec![Feature::RowRecords]);
+
+		cfg.features = vec![Feature::Paranoid, Feature::RowRecords];
+		cfg.header_gossip_publish = true;
+		let features = Features::from(&cfg);
+		assert_eq!(
+			features.list(),
+			vec![
+				Feature::RowRecords,
+				Feature::GossipHeaders,
+				Feature::Paranoid
+			]
+		);
+		assert_eq!(
+			deprecated_options(&cfg),
+			vec![("header_gossip_publish", Feature::GossipHeaders)]
+		);
+
+		let features: Vec<Feature> = serde_json::from_str(r#"["row-records"]"#).unwrap();
+		assert_eq!(features, vec![Feature::RowRecords]);
+		assert_eq!(Feature::GossipHeaders.to_string(), "gossip-headers");
+	}
+
+	#[test]
+	fn disabled_default_features() {
+		let cfg = RuntimeConfig {
+			features: vec![Feature::Paranoid],
+			disabled_features: vec![Feature::RowRecords],
+			..Default::default()
+		};
+		assert_eq!(Features::from(&cfg).list(), vec![Feature::Paranoid]);
+
+		// Configured features don't replace the default ones
+		let cfg = RuntimeConfig {
+			features: vec![Feature::Paranoid],
+			..Default::default()
+		};
+		assert!(Features::from(&cfg).is_enabled(Feature::RowRecords));
+	}
+}
@@ -0,0 +1,238 @@
+//! Persistent queue of the background jobs.
+//!
+//! Jobs are stored in the database, so scheduled work survives restarts.
+//! Ready job with the highest priority is leased to the worker, and becomes ready again
+//! if it is not completed within the lease duration (e.g. if the worker has crashed).
+//! Failed jobs are rescheduled using configured backoff, and dropped once retries are exhausted.
+
+use codec::{Decode, Encode};
+use color_eyre::{eyre::WrapErr, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+	cmp::Reverse,
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+	data::{Database, Key},
+	types::RetryConfig,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Decode, Encode)]
+pub enum JobKind {
+	/// Reconstruct application data of the block
+	ReconstructAppData { block_number: u32 },
+	/// Verify the given blocks, submitted for bulk verification
+	VerifyBlocks { blocks: Vec<u32> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct Job {
+	pub id: u64,
+	pub kind: JobKind,
+	/// Jobs with higher priority are leased first
+	pub priority: u8,
+	/// Number of failed attempts
+	pub attempts: u32,
+	/// Unix timestamp in seconds, before which job is not leased
+	pub not_before: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Decode, Encode)]
+struct Jobs {
+	next_id: u64,
+	jobs: Vec<Job>,
+}
+
+/// Current Unix timestamp in seconds
+pub fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or_default()
+}
+
+/// Persistent job queue of the background subsystem
+#[derive(Clone)]
+pub struct JobQueue<T: Database> {
+	name: &'static str,
+	db: T,
+	backoff: RetryConfig,
+	lease: Duration,
+	lock: Arc<Mutex<()>>,
+}
+
+impl<T: Database> JobQueue<T> {
+	/// Creates job queue stored under the given name
+	pub fn new(name: &'static str, db: T, backoff: RetryConfig, lease: Duration) -> Self {
+		JobQueue {
+			name,
+			db,
+			backoff,
+			lease,
+			lock: Arc::new(Mutex::new(())),
+		}
+	}
+
+	fn load(&self) -> Result<Jobs> {
+		self.db
+			.get(Key::JobQueue(self.name))
+			.map(Option::unwrap_or_default)
+			.wrap_err_with(|| format!("Failed to load {} job queue", self.name))
+	}
+
+	fn store(&self, jobs: Jobs) -> Result<()> {
+		self.db
+			.put(Key::JobQueue(self.name), jobs)
+			.wrap_err_with(|| format!("Failed to store {} job queue", self.name))
+	}
+
+	/// Schedules job with given priority. If the same job is already scheduled,
+	/// its priority is raised if needed, and existing job ID is returned.
+	pub fn push(&self, kind: JobKind, priority: u8) -> Result<u64> {
+		let _lock = self.lock.lock().expect("Lock should be acquired");
+		let mut jobs = self.load()?;
+		if let Some(job) = jobs.jobs.iter_mut().find(|job| job.kind == kind) {
+			job.priority = job.priority.max(priority);
+			let id = job.id;
+			self.store(jobs)?;
+			return Ok(id);
+		}
+		let id = jobs.next_id;
+		jobs.next_id += 1;
+		jobs.jobs.push(Job {
+			id,
+			kind,
+			priority,
+			attempts: 0,
+			not_before: 0,
+		});
+		self.store(jobs)?;
+		Ok(id)
+	}
+
+	/// Leases ready job with the highest priority (oldest first), if any
+	pub fn lease(&self, now: u64) -> Result<Option<Job>> {
+		let _lock = self.lock.lock().expect("Lock should be acquired");
+		let mut jobs = self.load()?;
+		let Some(job) = jobs
+			.jobs
+			.iter_mut()
+			.filter(|job| job.not_before <= now)
+			.max_by_key(|job| (job.priority, Reverse(job.id)))
+		else {
+			return Ok(None);
+		};
+		job.not_before = now + self.lease.as_secs();
+		let job = job.clone();
+		self.store(jobs)?;
+		Ok(Some(job))
+	}
+
+	/// Returns scheduled job, if any
+	pub fn get(&self, id: u64) -> Result<Option<Job>> {
+		let jobs = self.load()?;
+		Ok(jobs.jobs.into_iter().find(|job| job.id == id))
+	}
+
+	/// Removes completed job from the queue
+	pub fn complete(&self, id: u64) -> Result<()> {
+		let _lock = self.lock.lock().expect("Lock should be acquired");
+		let mut jobs = self.load()?;
+		jobs.jobs.retain(|job| job.id != id);
+		self.store(jobs)
+	}
+
+	/// Reschedules failed job using configured backoff, and returns the delay.
+	/// Job is removed from the queue if retries are exhausted, in which case `None` is returned.
+	pub fn fail(&self, id: u64, now: u64) -> Result<Option<Duration>> {
+		let _lock = self.lock.lock().expect("Lock should be acquired");
+		let mut jobs = self.load()?;
+		let Some(index) = jobs.jobs.iter().position(|job| job.id == id) else {
+			return Ok(None);
+		};
+		let attempts = jobs.jobs[index].attempts;
+		let delay = self.backoff.clone().into_iter().nth(attempts as usize);
+		match delay {
+			Some(delay) => {
+				let job = &mut jobs.jobs[index];
+				job.attempts += 1;
+				job.not_before = now + delay.as_secs().max(1);
+			},
+			None => {
+				jobs.jobs.remove(index);
+			},
+		}
+		self.store(jobs)?;
+		Ok(delay)
+	}
+
+	/// Number of scheduled jobs
+	pub fn len(&self) -> Result<usize> {
+		self.load().map(|jobs| jobs.jobs.len())
+	}
+
+	pub fn is_empty(&self) -> Result<bool> {
+		self.len().map(|len| len == 0)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{JobKind, JobQueue};
+	use crate::{
+		data::mem_db::MemoryDB,
+		types::{ExponentialConfig, RetryConfig},
+	};
+	use std::time::Duration;
+
+	fn queue(db: MemoryDB) -> JobQueue<MemoryDB> {
+		let backoff = RetryConfig::Exponential(ExponentialConfig {
+			base: 2,
+			max_delay: 1000,
+			retries: 2,
+		});
+		JobQueue::new("test", db, backoff, Duration::from_secs(60))
+	}
+
+	fn reconstruct(block_number: u32) -> JobKind {
+		JobKind::ReconstructAppData { block_number }
+	}
+
+	#[test]
+	fn lease_by_priority() {
+		let queue = queue(MemoryDB::default());
+		let low = queue.push(reconstruct(1), 0).unwrap();
+		let high = queue.push(reconstruct(2), 1).unwrap();
+		assert_eq!(queue.push(reconstruct(1), 0).unwrap(), low);
+		assert_eq!(queue.len().unwrap(), 2);
+
+		assert_eq!(queue.lease(100).unwrap().unwrap().id, high);
+		assert_eq!(queue.lease(100).unwrap().unwrap().id, low);
+		// Both jobs are leased
+		assert!(queue.lease(100).unwrap().is_none());
+		// Lease expired
+		assert_eq!(queue.lease(160).unwrap().unwrap().id, high);
+
+		queue.complete(high).unwrap();
+		assert_eq!(queue.len().unwrap(), 1);
+	}
+
+	#[test]
+	fn fail_with_backoff() {
+		let db = MemoryDB::default();
+		let id = queue(db.clone()).push(reconstruct(1), 0).unwrap();
+
+		// Queue is persisted
+		let queue = queue(db);
+		assert!(queue.fail(id, 100).unwrap().is_some());
+		assert!(queue.lease(100).unwrap().is_none());
+		let job = queue.lease(10_000).unwrap().unwrap();
+		assert_eq!(job.attempts, 1);
+
+		assert!(queue.fail(id, 100).unwrap().is_some());
+		assert!(queue.fail(id, 100).unwrap().is_none());
+		assert!(queue.is_empty().unwrap());
+	}
+}
@@ -1,18 +1,50 @@
 pub mod api;
 pub mod app_client;
+pub mod app_storage;
+pub mod block_metadata;
+pub mod block_state;
+pub mod block_time;
+pub mod bulk_verification;
+pub mod cell_size;
+pub mod confidence;
 pub mod consts;
 #[cfg(feature = "crawl")]
 pub mod crawl_client;
 pub mod data;
+pub mod data_dir;
+pub mod data_root;
+pub mod decoder;
+pub mod default_config;
+pub mod event_bus;
+pub mod export;
+pub mod export_gate;
 pub mod fat_client;
+pub mod features;
 pub mod finality;
+pub mod jobs;
 pub mod light_client;
 pub mod maintenance;
 pub mod network;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod proof;
+pub mod proof_bundle;
+pub mod reconciliation;
+pub mod replication_prober;
+pub mod report;
+pub mod retention;
+pub mod self_test;
+pub mod service;
 pub mod shutdown;
+pub mod sink;
+pub mod supervisor;
 pub mod sync_client;
 pub mod sync_finality;
+pub mod systemd;
 pub mod telemetry;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(feature = "tui")]
+pub mod tui;
 pub mod types;
 pub mod utils;
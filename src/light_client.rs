@@ -19,27 +19,67 @@
 
 use avail_subxt::{primitives::Header, utils::H256};
 use codec::Encode;
-use color_eyre::{eyre::WrapErr, Result};
-use kate_recovery::{commitments, matrix::Dimensions};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use futures::{stream::FuturesOrdered, StreamExt};
+use kate_recovery::{commitments, config::CHUNK_SIZE, data::Cell, matrix::Dimensions};
 use sp_core::blake2_256;
 use std::{
+	collections::{BTreeSet, HashMap, VecDeque},
 	sync::{Arc, Mutex},
-	time::Instant,
+	time::{Duration, Instant},
 };
-use tracing::{error, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info, warn};
 
 use crate::{
-	data::{Database, Key},
+	block_state::{self, BlockState},
+	confidence::Confidence,
+	data::{self, CellSource, Database, Key, SampledCell},
 	network::{
 		self,
 		rpc::{self, Event},
 	},
+	proof_bundle,
 	shutdown::Controller,
+	supervisor,
 	telemetry::{MetricCounter, MetricValue, Metrics},
 	types::{self, ClientChannels, LightClientConfig, OptionBlockRange, State},
-	utils::{calculate_confidence, extract_kate},
+	utils::{extract_app_lookup, extract_kate, unix_timestamp_nanos},
 };
 
+/// Returns positions of fetched cells with their source, given that DHT fetched cells come first
+fn sampled_cells(fetched: &[Cell], dht_fetched: usize) -> Vec<SampledCell> {
+	fetched
+		.iter()
+		.enumerate()
+		.map(|(index, cell)| SampledCell {
+			row: cell.position.row,
+			col: cell.position.col,
+			source: match index < dht_fetched {
+				true => CellSource::DHT,
+				false => CellSource::RPC,
+			},
+		})
+		.collect()
+}
+
+/// Outcome of the block sampling stage
+#[derive(Debug, PartialEq)]
+pub enum Sampled {
+	/// Block has no header extension, so there is nothing to sample
+	Skipped,
+	/// Block has no application data, and its commitments match the sampled empty block, so it is not sampled
+	Empty,
+	/// Block cannot be verified, failure is already stored
+	Failed,
+	/// Block is verified with the given number of verified cells
+	Verified(u32),
+}
+
+/// Processes block in a single pass, by sampling and then storing it.
 pub async fn process_block(
 	db: impl Database,
 	network_client: &impl network::Client,
@@ -48,30 +88,90 @@ pub async fn process_block(
 	header: Header,
 	received_at: Instant,
 	state: Arc<Mutex<State>>,
-) -> Result<Option<f64>> {
+	cancel: &CancellationToken,
+) -> Result<Option<Confidence>> {
+	let sampled = sample_block(
+		&db,
+		network_client,
+		metrics,
+		cfg,
+		&header,
+		received_at,
+		cancel,
+	)
+	.await?;
+	let block_number = header.number;
+	let completed = !matches!(sampled, Sampled::Failed);
+	let confidence = store_block(&db, metrics, cfg, header, sampled).await?;
+	if completed {
+		let mut state = state.lock().unwrap();
+		state.confidence_achieved.advance(block_number);
+	}
+	Ok(confidence)
+}
+
+/// Checks if the block has no application data, from its data lookup or matrix dimensions
+fn is_empty_block(rows: u16, cols: u16, header: &Header) -> bool {
+	rows == 0
+		|| cols == 0
+		|| matches!(extract_app_lookup(&header.extension), Ok(Some(lookup)) if lookup.is_empty())
+}
+
+/// Checks if the commitments of the block without application data match the commitments of the already sampled
+/// empty block with the same dimensions. Blocks with the same commitments have the same matrix,
+/// so the block is available if the sampled one was. Blocks without matrix have no commitments.
+fn is_known_empty_block(db: &impl Database, rows: u16, cols: u16, commitment: &[u8]) -> bool {
+	if rows == 0 || cols == 0 {
+		return commitment.is_empty();
+	}
+	match db.get::<Vec<u8>>(Key::EmptyBlockCommitments(rows, cols)) {
+		Ok(known) => known.is_some_and(|known| known == commitment),
+		Err(error) => {
+			debug!("Cannot get commitments of the empty block: {error:#}");
+			false
+		},
+	}
+}
+
+/// Fetch and verify stage of the block processing, which can run concurrently for the subsequent blocks.
+/// Only per block data (sampled cells and block state) is stored.
+pub async fn sample_block(
+	db: &impl Database,
+	network_client: &impl network::Client,
+	metrics: &Arc<impl Metrics>,
+	cfg: &LightClientConfig,
+	header: &Header,
+	received_at: Instant,
+	cancel: &CancellationToken,
+) -> Result<Sampled> {
 	metrics.count(MetricCounter::SessionBlocks).await;
 	metrics
 		.record(MetricValue::BlockHeight(header.number))
 		.await;
 
 	let block_number = header.number;
-	let header_hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+	let header_hash = header_hash(header);
 
 	info!(
 		{ block_number, block_delay = received_at.elapsed().as_secs()},
 		"Processing finalized block",
 	);
 
+	block_state::update(db, block_number, BlockState::Received);
+
 	let (required, verified, unverified) = match extract_kate(&header.extension) {
 		None => {
 			info!("Skipping block without header extension");
-
-			state.lock().unwrap().confidence_achieved.set(block_number);
-
-			db.put(Key::BlockHeader(block_number), header)
-				.wrap_err("Light Client failed to store Block Header")?;
-
-			return Ok(None);
+			return Ok(Sampled::Skipped);
+		},
+		Some((rows, cols, _, ref commitment))
+			if cfg.skip_empty_blocks
+				&& is_empty_block(rows, cols, header)
+				&& is_known_empty_block(db, rows, cols, commitment) =>
+		{
+			info!(block_number, "Skipping sampling of the block without data");
+			metrics.count(MetricCounter::EmptyBlocksSkipped).await;
+			return Ok(Sampled::Empty);
 		},
 		Some((rows, cols, _, commitment)) => {
 			let Some(dimensions) = Dimensions::new(rows, cols) else {
@@ -79,23 +179,57 @@ pub async fn process_block(
 					block_number,
 					"Skipping block with invalid dimensions {rows}x{cols}",
 				);
-				return Ok(None);
+				let reason = format!("Invalid dimensions {rows}x{cols}");
+				block_state::update(db, block_number, BlockState::Failed(reason));
+				return Ok(Sampled::Failed);
 			};
 
 			if dimensions.cols().get() <= 2 {
 				error!(block_number, "more than 2 columns is required");
-				return Ok(None);
+				let reason = "More than 2 columns is required".to_string();
+				block_state::update(db, block_number, BlockState::Failed(reason));
+				return Ok(Sampled::Failed);
 			}
 
 			let commitments = commitments::from_slice(&commitment)?;
-			let cell_count = rpc::cell_count_for_confidence(cfg.confidence);
-			let positions = rpc::generate_random_cells(dimensions, cell_count);
+			let cell_count = cfg
+				.confidence
+				.cell_count(cfg.withheld_fraction)
+				.max(cfg.min_cell_count);
+			let lookup = extract_app_lookup(&header.extension)?;
+
+			metrics.record(MetricValue::BlockMatrixRows(rows)).await;
+			metrics.record(MetricValue::BlockMatrixCols(cols)).await;
+			if let Some(lookup) = &lookup {
+				let data_size = (lookup.len() as u64 * CHUNK_SIZE as u64).div_ceil(1024);
+				metrics.record(MetricValue::BlockDataSize(data_size)).await;
+			}
+
+			let app_rows = match cfg.sampling_app_id {
+				Some(app_id) => lookup
+					.map(|lookup| lookup.rows_for_app(dimensions, app_id))
+					.filter(|rows| !rows.is_empty()),
+				None => None,
+			};
+			let positions = match app_rows {
+				Some(rows) => {
+					debug!(block_number, "Sampling app rows: {rows:?}");
+					rpc::generate_random_cells_in_rows(dimensions, &rows, cell_count)
+				},
+				None if cfg.stratified_sampling => {
+					rpc::generate_stratified_cells(dimensions, cell_count)
+				},
+				None => rpc::generate_random_cells(dimensions, cell_count),
+			};
 			info!(
 				block_number,
 				"cells_requested" = positions.len(),
 				"Random cells generated: {}",
 				positions.len()
 			);
+			metrics
+				.record(MetricValue::BlockSampledCells(positions.len() as u32))
+				.await;
 
 			let (fetched, unfetched, fetch_stats) = network_client
 				.fetch_verified(
@@ -104,9 +238,31 @@ pub async fn process_block(
 					dimensions,
 					&commitments,
 					&positions,
+					cancel,
 				)
 				.await?;
 
+			if !fetch_stats.rpc_invalid_cells.is_empty() {
+				let reason = format!(
+					"Full node served {} cells with invalid proofs",
+					fetch_stats.rpc_invalid_cells.len()
+				);
+				error!(block_number, "{reason}");
+				if let Some(disputes) = &cfg.disputes {
+					let cells = fetch_stats.rpc_invalid_cells.clone();
+					proof_bundle::spawn_export(disputes.clone(), header.clone(), cells, reason);
+				}
+			}
+
+			// Confidence from the DHT cells only, shows how much sampling relies on the full node
+			let p2p_confidence =
+				Confidence::from_cell_count(fetch_stats.dht_fetched as u32, cfg.withheld_fraction)
+					.value();
+			debug!(block_number, p2p_confidence, "P2P confidence factor");
+			metrics
+				.record(MetricValue::BlockP2PConfidence(p2p_confidence))
+				.await;
+
 			metrics
 				.record(MetricValue::DHTFetched(fetch_stats.dht_fetched))
 				.await;
@@ -132,32 +288,90 @@ pub async fn process_block(
 					.record(MetricValue::RPCFetchDuration(rpc_fetch_duration))
 					.await;
 			}
+
+			let sampled_cells = sampled_cells(&fetched, fetch_stats.dht_fetched as usize);
+			db.put(Key::SampledCells(block_number), sampled_cells)
+				.wrap_err("Light Client failed to store sampled cells")?;
+			if fetch_stats.degraded {
+				db.put(Key::Degraded(block_number), true)
+					.wrap_err("Light Client failed to store degraded flag")?;
+			}
+			block_state::update(db, block_number, BlockState::ProofsFetched);
 			(positions.len(), fetched.len(), unfetched.len())
 		},
 	};
 
 	if required > verified {
 		error!(block_number, "Failed to fetch {} cells", unverified);
-		return Ok(None);
+		let reason = format!("Failed to fetch {unverified} cells");
+		block_state::update(db, block_number, BlockState::Failed(reason));
+		return Ok(Sampled::Failed);
 	}
 
+	Ok(Sampled::Verified(verified as u32))
+}
+
+/// Store stage of the block processing, which runs in order of the sampled blocks.
+/// Returns block confidence, if block is verified. Verified range is advanced by the caller,
+/// once the block and all the blocks received before it are processed.
+pub async fn store_block(
+	db: &impl Database,
+	metrics: &Arc<impl Metrics>,
+	cfg: &LightClientConfig,
+	header: Header,
+	sampled: Sampled,
+) -> Result<Option<Confidence>> {
+	let block_number = header.number;
+	let verified = match sampled {
+		Sampled::Skipped => {
+			block_state::update(db, block_number, BlockState::Finalized);
+
+			db.put(Key::BlockHeader(block_number), header)
+				.wrap_err("Light Client failed to store Block Header")?;
+
+			return Ok(None);
+		},
+		Sampled::Failed => return Ok(None),
+		// Empty block is not sampled, so its confidence is not stored
+		Sampled::Empty => {
+			block_state::update(db, block_number, BlockState::Empty);
+
+			db.put(Key::BlockHeader(block_number), header)
+				.wrap_err("Light Client failed to store Block Header")?;
+
+			return Ok(None);
+		},
+		Sampled::Verified(verified) => verified,
+	};
+
 	// write confidence factor into on-disk database
-	db.put(Key::VerifiedCellCount(block_number), verified as u32)
+	db.put(Key::VerifiedCellCount(block_number), verified)
 		.wrap_err("Light Client failed to store Confidence Factor")?;
+	data::put_verified_at(db, block_number, unix_timestamp_nanos())
+		.wrap_err("Light Client failed to store verification time")?;
 
-	state.lock().unwrap().confidence_achieved.set(block_number);
-
-	let confidence = calculate_confidence(verified as u32);
+	let confidence = Confidence::from_cell_count(verified, cfg.withheld_fraction);
 	info!(
 		block_number,
-		"confidence" = confidence,
+		"confidence" = confidence.value(),
 		"Confidence factor: {}",
 		confidence
 	);
 	metrics
-		.record(MetricValue::BlockConfidence(confidence))
+		.record(MetricValue::BlockConfidence(confidence.value()))
 		.await;
 
+	// Commitments of the sampled empty block are stored, so the next empty blocks with the same matrix are not sampled
+	if let Some((rows, cols, _, commitment)) = extract_kate(&header.extension) {
+		if cfg.skip_empty_blocks
+			&& is_empty_block(rows, cols, &header)
+			&& confidence.reaches(cfg.confidence)
+		{
+			db.put(Key::EmptyBlockCommitments(rows, cols), commitment)
+				.wrap_err("Light Client failed to store empty block commitments")?;
+		}
+	}
+
 	// push latest mined block's header into column family specified
 	// for keeping block headers, to be used
 	// later for verifying DHT stored data
@@ -169,9 +383,50 @@ pub async fn process_block(
 	db.put(Key::BlockHeader(block_number), header)
 		.wrap_err("Light Client failed to store Block Header")?;
 
+	block_state::update(db, block_number, BlockState::Verified);
+	if cfg.is_last_step {
+		block_state::update(db, block_number, BlockState::Finalized);
+	}
+
 	Ok(Some(confidence))
 }
 
+/// Maximum number of received blocks waiting for sampling
+const MAX_BACKLOG_SIZE: usize = 256;
+/// Maximum time a block waits in the backlog, while the app blocks received after it are prioritized
+const MAX_PRIORITIZATION_DELAY: Duration = Duration::from_secs(60);
+
+/// Tracks completion of the blocks, which can be processed out of order.
+/// Verified range is advanced only over the completed blocks, which are not preceded by the blocks in progress.
+#[derive(Default)]
+struct Completion {
+	in_progress: BTreeSet<u32>,
+	completed: BTreeSet<u32>,
+}
+
+impl Completion {
+	fn start(&mut self, block_number: u32) {
+		self.in_progress.insert(block_number);
+	}
+
+	/// Marks the block processing as finished, and returns the latest block
+	/// up to which the verified range can be advanced, if any.
+	fn complete(&mut self, block_number: u32, success: bool) -> Option<u32> {
+		self.in_progress.remove(&block_number);
+		if success {
+			self.completed.insert(block_number);
+		}
+		let ready = match self.in_progress.first() {
+			Some(&first) => {
+				let pending = self.completed.split_off(&first);
+				std::mem::replace(&mut self.completed, pending)
+			},
+			None => std::mem::take(&mut self.completed),
+		};
+		ready.last().copied()
+	}
+}
+
 /// Runs light client.
 ///
 /// # Arguments
@@ -193,59 +448,217 @@ pub async fn run(
 ) {
 	info!("Starting light client...");
 
+	// in-flight block processing is cancelled on shutdown
+	let shutdown_token = shutdown.cancellation_token();
+
+	// blocks are sampled concurrently up to the pipeline depth, and stored in order of sampling start
+	let mut sampling = FuturesOrdered::new();
+	// received blocks waiting for sampling, which are reordered if app blocks are prioritized
+	let mut backlog = VecDeque::new();
+	// blocks are marked as verified only when all the blocks received before them are processed
+	let mut completion = Completion::default();
+	// cancellation tokens of the blocks in processing, by header hash, so blocks abandoned by a reorg can be cancelled
+	let mut in_flight = HashMap::new();
+
 	loop {
-		let (header, received_at) = match channels.rpc_event_receiver.recv().await {
-			Ok(event) => match event {
-				Event::HeaderUpdate {
-					header,
-					received_at,
-				} => (header, received_at),
+		while sampling.len() < cfg.pipeline_depth {
+			let Some((header, received_at)) = next_block(&mut backlog, cfg.priority_app_id) else {
+				break;
+			};
+			let cancel = shutdown_token.child_token();
+			in_flight.insert(header_hash(&header), (header.number, cancel.clone()));
+			sampling.push_back(sample_stage(
+				&db,
+				&network_client,
+				&metrics,
+				&cfg,
+				header,
+				received_at,
+				cancel,
+			));
+		}
+
+		tokio::select! {
+			event = channels.rpc_event_receiver.recv(), if backlog.len() < MAX_BACKLOG_SIZE && (cfg.priority_app_id.is_some() || sampling.len() < cfg.pipeline_depth) => {
+				let (header, received_at) = match event {
+					Ok(Event::HeaderUpdate {
+						header,
+						received_at,
+					}) => (header, received_at),
+					Err(error) => {
+						error!("Cannot receive message: {error}");
+						return;
+					},
+				};
+				cancel_abandoned(&in_flight, &mut backlog, &header);
+				completion.start(header.number);
+				backlog.push_back((header, received_at));
 			},
-			Err(error) => {
-				error!("Cannot receive message: {error}");
-				return;
+			Some((header, cancel, result)) = sampling.next() => {
+				let block_number = header.number;
+				in_flight.remove(&header_hash(&header));
+				// block received after the reorg completes the height instead
+				if cancel.is_cancelled() && !shutdown_token.is_cancelled() {
+					info!(block_number, "Processing of the block abandoned by reorg is cancelled");
+					continue;
+				}
+				let mut completed = false;
+				let process_block_result = match result {
+					Ok(Ok(sampled)) => {
+						completed = !matches!(sampled, Sampled::Failed);
+						let store = store_block(&db, &metrics, &cfg, header.clone(), sampled);
+						supervisor::catch_panic("header processing", store).await
+					},
+					result => result.map(|result| result.map(|_| None)),
+				};
+				let completed = completed && matches!(process_block_result, Ok(Ok(_)));
+				if let Some(last) = completion.complete(block_number, completed) {
+					state.lock().unwrap().confidence_achieved.advance(last);
+				}
+				// Panic on a single malformed header should not stop the light client
+				let process_block_result = match process_block_result {
+					Ok(result) => result,
+					Err(error) => {
+						error!(block_number, "Skipping block: {error:#}");
+						let reason = format!("{error:#}");
+						block_state::update(&db, block_number, BlockState::Failed(reason));
+						continue;
+					},
+				};
+				let confidence = match process_block_result {
+					Ok(confidence) => confidence,
+					Err(_) if cancel.is_cancelled() => {
+						info!(block_number, "Block processing is cancelled");
+						return;
+					},
+					Err(error) => {
+						error!("Cannot process block: {error}");
+						let _ = shutdown.trigger_shutdown(format!("Cannot process block: {error:#}"));
+						return;
+					},
+				};
+
+				let Ok(client_msg) = types::BlockVerified::try_from((header, confidence)) else {
+					error!("Cannot create message from header");
+					continue;
+				};
+
+				// notify dht-based application client
+				// that newly mined block has been received
+				if let Err(error) = channels.block_sender.send(client_msg) {
+					error!("Cannot send block verified message: {error}");
+					continue;
+				}
 			},
-		};
+		}
+	}
+}
 
-		if let Some(seconds) = cfg.block_processing_delay.sleep_duration(received_at) {
-			metrics
-				.record(MetricValue::BlockProcessingDelay(seconds.as_secs_f64()))
-				.await;
-			info!("Sleeping for {seconds:?} seconds");
-			tokio::time::sleep(seconds).await;
+fn header_hash(header: &Header) -> H256 {
+	Encode::using_encoded(header, blake2_256).into()
+}
+
+/// Cancels processing of the blocks abandoned by a reorg. Reorg is detected when the received header
+/// conflicts with the block of the same height which is in processing or in the backlog. Blocks at or above
+/// that height, which are not the received block, are abandoned, and removed from the backlog.
+fn cancel_abandoned(
+	in_flight: &HashMap<H256, (u32, CancellationToken)>,
+	backlog: &mut VecDeque<(Header, Instant)>,
+	header: &Header,
+) {
+	let hash = header_hash(header);
+	let is_abandoned = |number: u32, other: H256| number >= header.number && other != hash;
+	let is_reorg = in_flight
+		.iter()
+		.any(|(&other, &(number, _))| number == header.number && other != hash)
+		|| backlog
+			.iter()
+			.any(|(other, _)| other.number == header.number && header_hash(other) != hash);
+	if !is_reorg {
+		return;
+	}
+	warn!(
+		block_number = header.number,
+		"Reorg detected, cancelling processing of the abandoned blocks"
+	);
+	for (&other, (number, cancel)) in in_flight {
+		if is_abandoned(*number, other) {
+			cancel.cancel();
 		}
+	}
+	backlog.retain(|(other, _)| !is_abandoned(other.number, header_hash(other)));
+}
 
-		let process_block_result = process_block(
-			db.clone(),
-			&network_client,
-			&metrics,
-			&cfg,
-			header.clone(),
-			received_at,
-			state.clone(),
-		)
-		.await;
-		let confidence = match process_block_result {
-			Ok(confidence) => confidence,
-			Err(error) => {
-				error!("Cannot process block: {error}");
-				let _ = shutdown.trigger_shutdown(format!("Cannot process block: {error:#}"));
-				return;
-			},
-		};
+/// Checks if the block contains data of the app
+fn has_app_data(header: &Header, app_id: u32) -> bool {
+	matches!(
+		extract_app_lookup(&header.extension),
+		Ok(Some(lookup)) if lookup.range_of(app_id).is_some()
+	)
+}
 
-		let Ok(client_msg) = types::BlockVerified::try_from((header, confidence)) else {
-			error!("Cannot create message from header");
-			continue;
-		};
+/// Takes the next block to sample from the backlog.
+/// If app ID is given, the first block containing the app data is taken before the other blocks,
+/// unless the first block in the backlog is waiting longer than the maximum prioritization delay.
+fn next_block(
+	backlog: &mut VecDeque<(Header, Instant)>,
+	priority_app_id: Option<u32>,
+) -> Option<(Header, Instant)> {
+	let (_, received_at) = backlog.front()?;
+	let starving = received_at.elapsed() >= MAX_PRIORITIZATION_DELAY;
+	let index = priority_app_id
+		.filter(|_| !starving)
+		.and_then(|app_id| {
+			backlog
+				.iter()
+				.position(|(header, _)| has_app_data(header, app_id))
+		})
+		.unwrap_or(0);
+	if index > 0 {
+		debug!(
+			block_number = backlog[index].0.number,
+			"Prioritized verification of the app block"
+		);
+	}
+	backlog.remove(index)
+}
 
-		// notify dht-based application client
-		// that newly mined block has been received
-		if let Err(error) = channels.block_sender.send(client_msg) {
-			error!("Cannot send block verified message: {error}");
-			continue;
+/// Sampling stage of the light client pipeline, postponed by the configured block processing delay.
+/// Returns sampled block header and cancellation token of its processing, along with the sampling result.
+async fn sample_stage(
+	db: &impl Database,
+	network_client: &impl network::Client,
+	metrics: &Arc<impl Metrics>,
+	cfg: &LightClientConfig,
+	header: Header,
+	received_at: Instant,
+	cancel: CancellationToken,
+) -> (Header, CancellationToken, Result<Result<Sampled>>) {
+	if let Some(seconds) = cfg.block_processing_delay.sleep_duration(received_at) {
+		metrics
+			.record(MetricValue::BlockProcessingDelay(seconds.as_secs_f64()))
+			.await;
+		info!("Sleeping for {seconds:?} seconds");
+		tokio::select! {
+			_ = tokio::time::sleep(seconds) => {},
+			_ = cancel.cancelled() => {
+				let error = eyre!("Processing of block {} is cancelled", header.number);
+				return (header, cancel, Ok(Err(error)));
+			},
 		}
 	}
+
+	let sample = sample_block(
+		db,
+		network_client,
+		metrics,
+		cfg,
+		&header,
+		received_at,
+		&cancel,
+	);
+	let result = supervisor::catch_panic("header processing", sample).await;
+	(header, cancel, result)
 }
 
 #[cfg(test)]
@@ -254,21 +667,20 @@ mod tests {
 
 	use super::*;
 	use crate::{
-		data::mem_db,
-		network::rpc::{cell_count_for_confidence, CELL_COUNT_99_99},
-		telemetry,
-		types::RuntimeConfig,
+		confidence::CELL_COUNT_99_99, data::mem_db, telemetry, types::RuntimeConfig,
+		utils::DEFAULT_WITHHELD_FRACTION,
 	};
 	use avail_subxt::{
 		api::runtime_types::avail_core::{
-			data_lookup::compact::CompactDataLookup,
+			data_lookup::compact::{CompactDataLookup, DataLookupItem},
 			header::extension::{v3::HeaderExtension, HeaderExtension::V3},
 			kate_commitment::v3::KateCommitment,
+			AppId,
 		},
 		config::substrate::Digest,
 	};
 	use hex_literal::hex;
-	use kate_recovery::{data::Cell, matrix::Position};
+	use kate_recovery::matrix::Position;
 	use test_case::test_case;
 
 	#[test_case(99.9 => 10)]
@@ -280,7 +692,25 @@ mod tests {
 	#[test_case(50.0 => 1)]
 	#[test_case(50.1 => 2)]
 	fn test_cell_count_for_confidence(confidence: f64) -> u32 {
-		cell_count_for_confidence(confidence)
+		Confidence::new(confidence).cell_count(DEFAULT_WITHHELD_FRACTION)
+	}
+
+	#[test]
+	fn test_sampled_cells() {
+		let cell = |row, col| Cell {
+			position: Position { row, col },
+			content: [0u8; 80],
+		};
+		let sampled = sampled_cells(&[cell(0, 1), cell(1, 2), cell(2, 3)], 1);
+		let sources = sampled
+			.iter()
+			.map(|sampled| sampled.source)
+			.collect::<Vec<_>>();
+		assert_eq!(
+			sources,
+			vec![CellSource::DHT, CellSource::RPC, CellSource::RPC]
+		);
+		assert_eq!((sampled[1].row, sampled[1].col), (1, 2));
 	}
 
 	#[tokio::test]
@@ -335,7 +765,7 @@ mod tests {
 		let recv = Instant::now();
 		mock_network_client
 			.expect_fetch_verified()
-			.returning(move |_, _, _, _, positions| {
+			.returning(move |_, _, _, _, positions, _| {
 				let fetched = cells_fetched.clone();
 				let unfetched = cells_unfetched.clone();
 				let stats = network::FetchStats::new(
@@ -358,8 +788,131 @@ mod tests {
 			header,
 			recv,
 			state,
+			&CancellationToken::new(),
 		)
 		.await
 		.unwrap();
 	}
+
+	fn header_with_apps(number: u32, app_ids: &[u32]) -> Header {
+		let index = app_ids
+			.iter()
+			.enumerate()
+			.map(|(start, &app_id)| DataLookupItem {
+				app_id: AppId(app_id),
+				start: start as u32,
+			})
+			.collect::<Vec<_>>();
+		Header {
+			parent_hash: H256::default(),
+			number,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			digest: Digest { logs: vec![] },
+			extension: V3(HeaderExtension {
+				commitment: KateCommitment::default(),
+				app_lookup: CompactDataLookup {
+					size: app_ids.len() as u32,
+					index,
+				},
+			}),
+		}
+	}
+
+	#[test]
+	fn test_next_block_prioritizes_app_blocks() {
+		let now = Instant::now();
+		let backlog = || {
+			VecDeque::from([
+				(header_with_apps(1, &[]), now),
+				(header_with_apps(2, &[2]), now),
+				(header_with_apps(3, &[1, 3]), now),
+				(header_with_apps(4, &[3]), now),
+			])
+		};
+		let numbers = |mut backlog, app_id| {
+			std::iter::from_fn(|| next_block(&mut backlog, app_id))
+				.map(|(header, _)| header.number)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(numbers(backlog(), None), vec![1, 2, 3, 4]);
+		assert_eq!(numbers(backlog(), Some(3)), vec![3, 4, 1, 2]);
+		assert_eq!(numbers(backlog(), Some(5)), vec![1, 2, 3, 4]);
+	}
+
+	#[test]
+	fn test_next_block_takes_starving_block() {
+		let now = Instant::now();
+		let Some(waiting) = now.checked_sub(MAX_PRIORITIZATION_DELAY) else {
+			return;
+		};
+		let mut backlog = VecDeque::from([
+			(header_with_apps(1, &[]), waiting),
+			(header_with_apps(2, &[3]), now),
+		]);
+		let (header, _) = next_block(&mut backlog, Some(3)).unwrap();
+		assert_eq!(header.number, 1);
+	}
+
+	#[test]
+	fn test_completion_advances_over_completed_prefix() {
+		let mut completion = Completion::default();
+		(1..=4).for_each(|block_number| completion.start(block_number));
+		// Block 3 is prioritized and completed before the blocks received before it
+		assert_eq!(completion.complete(3, true), None);
+		assert_eq!(completion.complete(1, true), Some(1));
+		// Failed block is processed, but it does not advance the range by itself
+		assert_eq!(completion.complete(2, false), Some(3));
+		assert_eq!(completion.complete(4, true), Some(4));
+		assert_eq!(completion.complete(5, false), None);
+	}
+
+	#[test]
+	fn test_cancel_abandoned_blocks() {
+		let now = Instant::now();
+		let token = |header: &Header| {
+			(
+				header_hash(header),
+				(header.number, CancellationToken::new()),
+			)
+		};
+		let in_flight = HashMap::from([
+			token(&header_with_apps(1, &[])),
+			token(&header_with_apps(2, &[])),
+		]);
+		let mut backlog = VecDeque::from([(header_with_apps(3, &[]), now)]);
+
+		// Redelivered header is not a reorg
+		cancel_abandoned(&in_flight, &mut backlog, &header_with_apps(2, &[]));
+		assert!(in_flight.values().all(|(_, cancel)| !cancel.is_cancelled()));
+		assert_eq!(backlog.len(), 1);
+
+		// Block 2 on another fork abandons blocks 2 and 3
+		cancel_abandoned(&in_flight, &mut backlog, &header_with_apps(2, &[1]));
+		for (number, cancel) in in_flight.values() {
+			assert_eq!(cancel.is_cancelled(), *number >= 2);
+		}
+		assert!(backlog.is_empty());
+	}
+
+	#[test]
+	fn test_is_known_empty_block() {
+		let db = mem_db::MemoryDB::default();
+		assert!(!is_known_empty_block(&db, 1, 4, &[1u8; 48]));
+		db.put(Key::EmptyBlockCommitments(1, 4), vec![1u8; 48])
+			.unwrap();
+		assert!(is_known_empty_block(&db, 1, 4, &[1u8; 48]));
+		// Header claiming no data, but committing to a different matrix is sampled
+		assert!(!is_known_empty_block(&db, 1, 4, &[2u8; 48]));
+		assert!(!is_known_empty_block(&db, 2, 4, &[1u8; 48]));
+		assert!(is_known_empty_block(&db, 0, 0, &[]));
+		assert!(!is_known_empty_block(&db, 0, 0, &[1u8; 48]));
+	}
+
+	#[test]
+	fn test_is_empty_block() {
+		assert!(is_empty_block(1, 4, &header_with_apps(1, &[])));
+		assert!(!is_empty_block(1, 4, &header_with_apps(1, &[1])));
+		assert!(is_empty_block(0, 0, &header_with_apps(1, &[1])));
+	}
 }
@@ -1,13 +1,16 @@
 use color_eyre::{eyre::WrapErr, Result};
-use std::sync::Arc;
+use std::{
+	sync::{Arc, Mutex},
+	time::{SystemTime, UNIX_EPOCH},
+};
 use tokio::sync::broadcast;
 use tracing::{debug, error, info};
 
 use crate::{
-	network::p2p::Client as P2pClient,
+	network::p2p::{Client as P2pClient, Reachability},
 	shutdown::Controller,
 	telemetry::{MetricValue, Metrics},
-	types::BlockVerified,
+	types::{BlockVerified, State},
 };
 
 #[derive(Clone, Copy)]
@@ -23,6 +26,7 @@ pub async fn process_block(
 	block_number: u32,
 	p2p_client: &P2pClient,
 	static_config_params: StaticConfigParams,
+	state: &Arc<Mutex<State>>,
 	metrics: &Arc<impl Metrics>,
 ) -> Result<()> {
 	#[cfg(not(feature = "kademlia-rocksdb"))]
@@ -34,6 +38,22 @@ pub async fn process_block(
 		}
 	}
 
+	if block_number % static_config_params.pruning_interval == 0 {
+		match p2p_client.prune_published_records(block_number).await {
+			Ok(0) => (),
+			Ok(pruned) => info!(block_number, pruned, "Removed expired published records"),
+			Err(error) => error!(block_number, "Removing published records failed: {error:#}"),
+		}
+		let pruned_at = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|duration| duration.as_secs())
+			.unwrap_or_default();
+		state
+			.lock()
+			.expect("Lock should be acquired")
+			.last_pruned_at = Some(pruned_at);
+	}
+
 	if block_number % static_config_params.telemetry_flush_interval == 0 {
 		info!(block_number, "Flushing metrics...");
 		match metrics.flush().await {
@@ -61,6 +81,19 @@ pub async fn process_block(
 	let peers_num_metric = MetricValue::DHTConnectedPeers(peers_num);
 	metrics.record(peers_num_metric).await;
 
+	let local_info = p2p_client.get_local_info().await?;
+	debug!(reachability = ?local_info.reachability, "Public reachability");
+	metrics
+		.record(MetricValue::DHTPubliclyReachable(
+			local_info.reachability == Reachability::Public,
+		))
+		.await;
+	{
+		let mut state = state.lock().expect("Lock should be acquired");
+		state.reachability = local_info.reachability;
+		state.public_address = local_info.public_address;
+	}
+
 	metrics
 		.record(MetricValue::BlockConfidenceThreshold(
 			static_config_params.block_confidence_treshold,
@@ -87,6 +120,7 @@ pub async fn run(
 	metrics: Arc<impl Metrics>,
 	mut block_receiver: broadcast::Receiver<BlockVerified>,
 	static_config_params: StaticConfigParams,
+	state: Arc<Mutex<State>>,
 	shutdown: Controller<String>,
 ) {
 	info!("Starting maintenance...");
@@ -94,7 +128,14 @@ pub async fn run(
 	loop {
 		let result = match block_receiver.recv().await {
 			Ok(block) => {
-				process_block(block.block_num, &p2p_client, static_config_params, &metrics).await
+				process_block(
+					block.block_num,
+					&p2p_client,
+					static_config_params,
+					&state,
+					&metrics,
+				)
+				.await
 			},
 			Err(error) => Err(error.into()),
 		};
@@ -1,25 +1,38 @@
 use async_trait::async_trait;
-use color_eyre::{eyre::WrapErr, Result};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
 use dusk_plonk::prelude::PublicParameters;
+use futures::{future, StreamExt};
 use kate_recovery::{
 	config,
 	data::Cell,
 	matrix::{Dimensions, Position},
 };
 use mockall::automock;
+use rand::{thread_rng, Rng};
 use sp_core::H256;
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashSet, sync::Arc, time::Duration};
 use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, info};
 
-use crate::proof;
+use crate::{proof, types::RuntimeConfig};
 
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod header_source;
 pub mod p2p;
+pub mod proxy;
 pub mod rpc;
 
 #[async_trait]
 #[automock]
 pub trait Client {
+	/// Fetches and verifies cells on given positions.
+	/// Returns verified cells (cells fetched from DHT first), unfetched positions and fetch stats.
+	/// Fails if the token is cancelled, abandoning the in-flight DHT queries and RPC calls.
 	async fn fetch_verified(
 		&self,
 		block_number: u32,
@@ -27,6 +40,7 @@ pub trait Client {
 		dimensions: Dimensions,
 		commitments: &[[u8; config::COMMITMENT_SIZE]],
 		positions: &[Position],
+		cancel: &CancellationToken,
 	) -> Result<(Vec<Cell>, Vec<Position>, FetchStats)>;
 }
 
@@ -36,6 +50,10 @@ pub struct FetchStats {
 	pub dht_fetch_duration: f64,
 	pub rpc_fetched: Option<f64>,
 	pub rpc_fetch_duration: Option<f64>,
+	/// Cells served by the full node with invalid proofs, which indicate disputed block
+	pub rpc_invalid_cells: Vec<Cell>,
+	/// Set if cells were sampled from the DHT only, since all full nodes were unreachable
+	pub degraded: bool,
 }
 
 type RPCFetchStats = (usize, Duration);
@@ -53,8 +71,67 @@ impl FetchStats {
 			dht_fetch_duration: dht_fetch_duration.as_secs_f64(),
 			rpc_fetched: rpc_fetch_stats.map(|(rpc_fetched, _)| rpc_fetched as f64),
 			rpc_fetch_duration: rpc_fetch_stats.map(|(_, duration)| duration.as_secs_f64()),
+			rpc_invalid_cells: vec![],
+			degraded: false,
+		}
+	}
+}
+
+/// Extra sampling from the DHT, used when only a few of the sampled cells are found in the DHT,
+/// so cells found in the DHT replace the missing ones, instead of fetching them from RPC
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtraSampling {
+	/// Percentage of the sampled cells found in the DHT, below which extra cells are sampled
+	pub threshold: f64,
+	/// Maximum number of extra cells sampled per block
+	pub max_cells: usize,
+}
+
+/// Maximum number of extra cells sampled from the DHT while the full node is rate limiting,
+/// if extra sampling is not configured
+const RATE_LIMITED_EXTRA_CELLS: usize = 20;
+
+impl ExtraSampling {
+	/// Extra sampling while the full node is rate limiting, replacing all missing cells if possible
+	fn rate_limited(configured: Option<Self>) -> Self {
+		ExtraSampling {
+			threshold: 100.0,
+			max_cells: configured.map_or(RATE_LIMITED_EXTRA_CELLS, |extra| extra.max_cells),
+		}
+	}
+
+	pub fn from_config(cfg: &RuntimeConfig) -> Option<Self> {
+		cfg.dht_extra_sampling_threshold
+			.map(|threshold| ExtraSampling {
+				threshold,
+				max_cells: cfg.dht_extra_sampling_max_cells as usize,
+			})
+	}
+
+	/// Returns number of extra cells to sample, bounded by the number of missing cells
+	fn cell_count(&self, sampled: usize, dht_fetched: usize) -> usize {
+		if sampled == 0 || dht_fetched as f64 * 100.0 / sampled as f64 >= self.threshold {
+			return 0;
+		}
+		(sampled - dht_fetched).min(self.max_cells)
+	}
+}
+
+/// Generates random positions which are not sampled already
+fn extra_positions(dimensions: Dimensions, sampled: &[Position], count: usize) -> Vec<Position> {
+	let sampled = sampled.iter().collect::<HashSet<_>>();
+	let available = (dimensions.extended_size() as usize).saturating_sub(sampled.len());
+	let mut rng = thread_rng();
+	let mut positions = HashSet::new();
+	while positions.len() < count.min(available) {
+		let row = rng.gen_range(0..dimensions.extended_rows());
+		let col = rng.gen_range(0..dimensions.cols().into());
+		let position = Position { row, col };
+		if !sampled.contains(&position) {
+			positions.insert(position);
 		}
 	}
+	positions.into_iter().collect()
 }
 
 struct DHTWithRPCFallbackClient {
@@ -62,6 +139,7 @@ struct DHTWithRPCFallbackClient {
 	rpc_client: rpc::Client,
 	pp: Arc<PublicParameters>,
 	disable_rpc: bool,
+	extra_sampling: Option<ExtraSampling>,
 }
 
 type Commitments = [[u8; config::COMMITMENT_SIZE]];
@@ -73,40 +151,56 @@ impl DHTWithRPCFallbackClient {
 		dimensions: Dimensions,
 		commitments: &Commitments,
 		positions: &[Position],
+		cancel: &CancellationToken,
 	) -> Result<(Vec<Cell>, Vec<Position>, Duration)> {
 		let begin = Instant::now();
 
-		let (mut dht_fetched, mut unfetched) = self
+		// cells are verified as they arrive, elapsed time is updated on each fetched or unfetched cell
+		let mut unfetched = vec![];
+		let mut fetch_elapsed = Duration::ZERO;
+		let cells = self
 			.p2p_client
-			.fetch_cells_from_dht(block_number, positions)
-			.await;
+			.stream_cells_from_dht(block_number, positions)
+			.take_until(cancel.cancelled())
+			.filter_map(|(position, cell)| {
+				fetch_elapsed = begin.elapsed();
+				if cell.is_none() {
+					unfetched.push(position);
+				}
+				future::ready(cell)
+			});
 
-		let fetch_elapsed = begin.elapsed();
-
-		let (verified, mut unverified) = proof::verify(
+		let (verified, mut unverified) = proof::verify_stream(
 			block_number,
 			dimensions,
-			&dht_fetched,
+			cells,
 			commitments,
 			self.pp.clone(),
 		)
 		.await
 		.context("Failed to verify fetched cells")?;
 
+		if cancel.is_cancelled() {
+			// queries of the unyielded cells are still running in the event loop
+			if let Err(error) = self.p2p_client.finish_abandoned_queries().await {
+				debug!("Cannot finish abandoned DHT queries: {error:#}");
+			}
+			return Err(eyre!("Fetching cells of block {block_number} is cancelled"));
+		}
+
 		info!(
 			block_number,
 			cells_total = positions.len(),
-			cells_fetched = dht_fetched.len(),
+			cells_fetched = verified.len() + unverified.len(),
 			cells_verified = verified.len(),
 			fetch_elapsed = ?fetch_elapsed,
 			proof_verification_elapsed = ?(begin.elapsed() - fetch_elapsed),
 			"Cells fetched from DHT"
 		);
 
-		dht_fetched.retain(|cell| verified.contains(&cell.position));
 		unfetched.append(&mut unverified);
 
-		Ok((dht_fetched, unfetched, fetch_elapsed))
+		Ok((verified, unfetched, fetch_elapsed))
 	}
 
 	async fn fetch_verified_from_rpc(
@@ -116,13 +210,16 @@ impl DHTWithRPCFallbackClient {
 		dimensions: Dimensions,
 		commitments: &Commitments,
 		positions: &[Position],
-	) -> Result<(Vec<Cell>, Vec<Position>, Duration)> {
+		cancel: &CancellationToken,
+	) -> Result<(Vec<Cell>, Vec<Position>, Vec<Cell>, Duration)> {
 		let begin = Instant::now();
 
-		let mut fetched = self
-			.rpc_client
-			.request_kate_proof(block_hash, positions)
-			.await?;
+		let fetched = tokio::select! {
+			fetched = self.rpc_client.request_kate_proof(block_hash, positions) => fetched?,
+			_ = cancel.cancelled() => {
+				return Err(eyre!("Fetching cells of block {block_number} is cancelled"));
+			},
+		};
 
 		let fetch_elapsed = begin.elapsed();
 
@@ -146,8 +243,10 @@ impl DHTWithRPCFallbackClient {
 			"Cells fetched from RPC"
 		);
 
-		fetched.retain(|cell| verified.contains(&cell.position));
-		Ok((fetched, unverified, fetch_elapsed))
+		let (fetched, invalid) = fetched
+			.into_iter()
+			.partition::<Vec<_>, _>(|cell| verified.contains(&cell.position));
+		Ok((fetched, unverified, invalid, fetch_elapsed))
 	}
 }
 
@@ -160,24 +259,63 @@ impl Client for DHTWithRPCFallbackClient {
 		dimensions: Dimensions,
 		commitments: &Commitments,
 		positions: &[Position],
+		cancel: &CancellationToken,
 	) -> Result<(Vec<Cell>, Vec<Position>, FetchStats)> {
-		let (dht_fetched, unfetched, dht_fetch_duration) = self
-			.fetch_verified_from_dht(block_number, dimensions, commitments, positions)
+		let (mut dht_fetched, mut unfetched, mut dht_fetch_duration) = self
+			.fetch_verified_from_dht(block_number, dimensions, commitments, positions, cancel)
 			.await?;
 
-		if self.disable_rpc {
-			let stats =
-				FetchStats::new(positions.len(), dht_fetched.len(), dht_fetch_duration, None);
+		// Cells are sampled from the DHT only while all full nodes are unreachable
+		let degraded = !self.disable_rpc && self.rpc_client.is_degraded();
+
+		// Fetches are shifted to the DHT while the full node is rate limiting or unreachable
+		let backing_off = !self.disable_rpc && self.rpc_client.is_backing_off();
+		let extra_sampling = match degraded || backing_off {
+			true => Some(ExtraSampling::rate_limited(self.extra_sampling)),
+			false => self.extra_sampling,
+		};
+		let mut sampled = positions.len();
+		let extra_count = extra_sampling.map_or(0, |extra| {
+			extra.cell_count(positions.len(), dht_fetched.len())
+		});
+		if extra_count > 0 {
+			let extra_positions = extra_positions(dimensions, positions, extra_count);
+			debug!(
+				block_number,
+				dht_fetched = dht_fetched.len(),
+				"Low DHT hit rate, sampling {} extra cells from DHT",
+				extra_positions.len()
+			);
+			let (extra_fetched, _, extra_duration) = self
+				.fetch_verified_from_dht(
+					block_number,
+					dimensions,
+					commitments,
+					&extra_positions,
+					cancel,
+				)
+				.await?;
+			sampled += extra_positions.len();
+			dht_fetch_duration += extra_duration;
+			dht_fetched.extend(extra_fetched);
+			// Cells found with extra sampling replace the missing ones, so fewer cells are fetched from RPC
+			unfetched.truncate(positions.len().saturating_sub(dht_fetched.len()));
+		}
+
+		if self.disable_rpc || degraded {
+			let mut stats = FetchStats::new(sampled, dht_fetched.len(), dht_fetch_duration, None);
+			stats.degraded = degraded;
 			return Ok((dht_fetched, unfetched, stats));
 		};
 
-		let (rpc_fetched, unfetched, rpc_fetch_duration) = self
+		let (rpc_fetched, unfetched, rpc_invalid, rpc_fetch_duration) = self
 			.fetch_verified_from_rpc(
 				block_number,
 				block_hash,
 				dimensions,
 				commitments,
 				&unfetched,
+				cancel,
 			)
 			.await?;
 
@@ -189,12 +327,13 @@ impl Client for DHTWithRPCFallbackClient {
 			debug!("Error inserting cells into DHT: {error}");
 		}
 
-		let stats = FetchStats::new(
-			positions.len(),
+		let mut stats = FetchStats::new(
+			sampled,
 			dht_fetched.len(),
 			dht_fetch_duration,
 			Some((rpc_fetched.len(), rpc_fetch_duration)),
 		);
+		stats.rpc_invalid_cells = rpc_invalid;
 
 		let mut fetched = vec![];
 		fetched.extend(dht_fetched);
@@ -209,11 +348,61 @@ pub fn new(
 	rpc_client: rpc::Client,
 	pp: Arc<PublicParameters>,
 	disable_rpc: bool,
+	extra_sampling: Option<ExtraSampling>,
 ) -> impl Client {
 	DHTWithRPCFallbackClient {
 		p2p_client,
 		rpc_client,
 		pp,
 		disable_rpc,
+		extra_sampling,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{extra_positions, ExtraSampling};
+	use kate_recovery::matrix::{Dimensions, Position};
+
+	#[test]
+	fn extra_sampling_cell_count() {
+		let extra = ExtraSampling {
+			threshold: 50.0,
+			max_cells: 5,
+		};
+		assert_eq!(extra.cell_count(10, 5), 0);
+		assert_eq!(extra.cell_count(10, 4), 5);
+		assert_eq!(extra.cell_count(10, 8), 0);
+		let extra = ExtraSampling {
+			threshold: 100.0,
+			max_cells: 5,
+		};
+		assert_eq!(extra.cell_count(10, 8), 2);
+		assert_eq!(extra.cell_count(0, 0), 0);
+	}
+
+	#[test]
+	fn rate_limited_extra_sampling() {
+		let configured = ExtraSampling {
+			threshold: 50.0,
+			max_cells: 5,
+		};
+		let extra = ExtraSampling::rate_limited(Some(configured));
+		assert_eq!(extra.cell_count(10, 9), 1);
+		assert_eq!(extra.cell_count(10, 0), 5);
+		assert_eq!(extra.cell_count(10, 10), 0);
+		assert_eq!(ExtraSampling::rate_limited(None).cell_count(30, 0), 20);
+	}
+
+	#[test]
+	fn extra_positions_are_not_sampled() {
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		let sampled = (0..4)
+			.map(|col| Position { row: 0, col })
+			.collect::<Vec<_>>();
+		let positions = extra_positions(dimensions, &sampled, 10);
+		// Only the extended row is not sampled
+		assert_eq!(positions.len(), 4);
+		assert!(positions.iter().all(|position| position.row == 1));
 	}
 }
@@ -0,0 +1,115 @@
+//! Fault injection into DHT GET and PUT operations, available with `chaos` feature.
+//!
+//! Used to validate RPC fallback, retries and confidence calculation under adverse network conditions.
+//! Each operation is independently delayed, dropped or corrupted with the configured probabilities.
+
+use color_eyre::{eyre::eyre, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ChaosConfig {
+	/// Probability of delaying DHT GET or PUT operation (default: 0.0)
+	pub chaos_delay_probability: f64,
+	/// Maximum delay of DHT operation in milliseconds, actual delay is random up to this value (default: 0)
+	pub chaos_max_delay_ms: u64,
+	/// Probability of dropping DHT GET or PUT operation (default: 0.0)
+	pub chaos_drop_probability: f64,
+	/// Probability of corrupting record value in DHT GET or PUT operation (default: 0.0)
+	pub chaos_corrupt_probability: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+	Get,
+	Put,
+}
+
+impl fmt::Display for Operation {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Operation::Get => write!(f, "GET"),
+			Operation::Put => write!(f, "PUT"),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Chaos {
+	cfg: ChaosConfig,
+}
+
+impl Chaos {
+	/// Creates chaos layer, returns `None` if no fault is configured
+	pub fn new(cfg: &ChaosConfig) -> Option<Self> {
+		let enabled = cfg.chaos_delay_probability > 0.0
+			|| cfg.chaos_drop_probability > 0.0
+			|| cfg.chaos_corrupt_probability > 0.0;
+		enabled.then(|| Chaos { cfg: cfg.clone() })
+	}
+
+	fn happens(probability: f64) -> bool {
+		rand::thread_rng().gen_bool(probability.clamp(0.0, 1.0))
+	}
+
+	fn delay(&self) -> Option<Duration> {
+		if self.cfg.chaos_max_delay_ms == 0 || !Self::happens(self.cfg.chaos_delay_probability) {
+			return None;
+		}
+		let delay = rand::thread_rng().gen_range(0..=self.cfg.chaos_max_delay_ms);
+		Some(Duration::from_millis(delay))
+	}
+
+	fn corrupt(&self, value: &mut [u8]) -> bool {
+		if value.is_empty() || !Self::happens(self.cfg.chaos_corrupt_probability) {
+			return false;
+		}
+		let index = rand::thread_rng().gen_range(0..value.len());
+		value[index] ^= 0xff;
+		true
+	}
+
+	/// Applies configured faults to the operation on the given record value.
+	/// Returns error if operation is dropped.
+	pub async fn apply(&self, operation: Operation, value: &mut [u8]) -> Result<()> {
+		if let Some(delay) = self.delay() {
+			tokio::time::sleep(delay).await;
+		}
+		if Self::happens(self.cfg.chaos_drop_probability) {
+			return Err(eyre!("Chaos: DHT {operation} operation dropped"));
+		}
+		self.corrupt(value);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Chaos, ChaosConfig, Operation};
+
+	#[test]
+	fn disabled_by_default() {
+		assert!(Chaos::new(&ChaosConfig::default()).is_none());
+	}
+
+	#[tokio::test]
+	async fn drop_and_corrupt() {
+		let chaos = Chaos::new(&ChaosConfig {
+			chaos_drop_probability: 1.0,
+			..Default::default()
+		})
+		.unwrap();
+		assert!(chaos.apply(Operation::Get, &mut [0u8; 80]).await.is_err());
+
+		let chaos = Chaos::new(&ChaosConfig {
+			chaos_corrupt_probability: 1.0,
+			..Default::default()
+		})
+		.unwrap();
+		let mut value = [0u8; 80];
+		chaos.apply(Operation::Put, &mut value).await.unwrap();
+		assert_ne!(value, [0u8; 80]);
+	}
+}
@@ -0,0 +1,445 @@
+//! Pluggable sources of finalized headers.
+//!
+//! Headers are received over the WebSocket subscription, in which case their finality is verified,
+//! polled over HTTP JSON-RPC, or received over gossipsub from the configured trusted publishers.
+//! Sources implement the [`HeaderSource`] trait and send headers to the header event channel.
+//! Multiple sources can be combined, in which case they run concurrently and each block is forwarded
+//! once, by the first source which delivers it. Combined sources run until all of them fail.
+//! Optionally, combined sources are cross-checked: if sources deliver headers with different hashes
+//! for the same block, only the first header is forwarded, and divergence is logged as an error
+//! and counted in metrics, to detect a single equivocating provider.
+
+use async_trait::async_trait;
+use avail_subxt::primitives::Header;
+use codec::{Decode, Encode};
+use color_eyre::{eyre::eyre, Result};
+use futures::{stream::FuturesUnordered, StreamExt};
+use libp2p::PeerId;
+use serde::{Deserialize, Serialize};
+use sp_core::{blake2_256, H256};
+use std::{
+	collections::{BTreeMap, HashSet},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::{
+	wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+	StreamMap,
+};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+	data::Database,
+	network::{
+		p2p,
+		rpc::{Event, HeaderPoller, SubscriptionLoop},
+	},
+	telemetry::{MetricCounter, Metrics},
+	types::{OptionBlockRange, State},
+};
+
+/// Delay before resubscribing to the header gossip, after the p2p event loop is restarted
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+/// Number of headers buffered between each of the combined sources and the deduplication
+const COMBINED_CAPACITY: usize = 1000;
+
+/// Number of the most recent blocks which are cross-checked between the combined sources
+const CROSS_CHECK_WINDOW: u32 = 256;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeaderSourceKind {
+	/// Headers are received over the WebSocket subscription
+	Subscription,
+	/// Headers are polled over HTTP
+	Polling,
+	/// Headers are received over gossipsub from the trusted publishers
+	Gossipsub,
+}
+
+#[async_trait]
+pub trait HeaderSource: Send {
+	/// Name of the source, used in logs
+	fn name(&self) -> &'static str;
+
+	/// Sends finalized headers to the given channel, until the source fails
+	async fn run(self: Box<Self>, event_sender: broadcast::Sender<Event>) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: Database + Send + 'static> HeaderSource for SubscriptionLoop<T> {
+	fn name(&self) -> &'static str {
+		"subscription"
+	}
+
+	async fn run(self: Box<Self>, event_sender: broadcast::Sender<Event>) -> Result<()> {
+		SubscriptionLoop::run(*self, event_sender).await
+	}
+}
+
+#[async_trait]
+impl HeaderSource for HeaderPoller {
+	fn name(&self) -> &'static str {
+		"polling"
+	}
+
+	async fn run(self: Box<Self>, event_sender: broadcast::Sender<Event>) -> Result<()> {
+		HeaderPoller::run(*self, event_sender).await
+	}
+}
+
+/// Runs the primary source, switching to the fallback source if the primary one fails
+pub struct Fallback {
+	primary: Box<dyn HeaderSource>,
+	fallback: Box<dyn HeaderSource>,
+}
+
+impl Fallback {
+	pub fn new(primary: Box<dyn HeaderSource>, fallback: Box<dyn HeaderSource>) -> Self {
+		Fallback { primary, fallback }
+	}
+}
+
+#[async_trait]
+impl HeaderSource for Fallback {
+	fn name(&self) -> &'static str {
+		"fallback"
+	}
+
+	async fn run(self: Box<Self>, event_sender: broadcast::Sender<Event>) -> Result<()> {
+		let (primary, fallback) = (self.primary.name(), self.fallback.name());
+		match self.primary.run(event_sender.clone()).await {
+			Err(error) => {
+				warn!(%error, "Header source {primary} failed, falling back to {fallback}");
+				self.fallback.run(event_sender).await
+			},
+			result => result,
+		}
+	}
+}
+
+/// Runs sources concurrently, forwarding each block once.
+/// With cross-check enabled, headers of the same block delivered by the sources are compared by hash.
+pub struct Combined {
+	sources: Vec<Box<dyn HeaderSource>>,
+	cross_check: Option<Arc<dyn Metrics + Send + Sync>>,
+}
+
+impl Combined {
+	pub fn new(sources: Vec<Box<dyn HeaderSource>>) -> Self {
+		Combined {
+			sources,
+			cross_check: None,
+		}
+	}
+
+	/// Enables cross-check of the sources, divergences are counted in metrics
+	pub fn with_cross_check(mut self, metrics: Arc<dyn Metrics + Send + Sync>) -> Self {
+		self.cross_check = Some(metrics);
+		self
+	}
+}
+
+/// Returns true and updates the last block number if the block is newer than the last one
+fn is_newer(block_number: u32, last: &mut Option<u32>) -> bool {
+	if last.is_some_and(|last| block_number <= last) {
+		return false;
+	}
+	*last = Some(block_number);
+	true
+}
+
+#[derive(Debug, PartialEq)]
+enum CrossCheck {
+	/// First header of the block
+	First,
+	/// Header matches the first header of the block
+	Matching,
+	/// Header differs from the first header of the block, delivered by the given source
+	Diverged {
+		first_source: &'static str,
+		first_hash: H256,
+	},
+	/// Block is too old to be cross-checked
+	Expired,
+}
+
+/// Hashes of the recent blocks, with the source which delivered them first
+#[derive(Default)]
+struct BlockHashes(BTreeMap<u32, (H256, &'static str)>);
+
+impl BlockHashes {
+	fn check(&mut self, block_number: u32, hash: H256, source: &'static str) -> CrossCheck {
+		let latest = self.0.last_key_value().map(|(&latest, _)| latest);
+		if latest.is_some_and(|latest| block_number + CROSS_CHECK_WINDOW <= latest) {
+			return CrossCheck::Expired;
+		}
+		let result = match self.0.get(&block_number) {
+			Some(&(first_hash, _)) if first_hash == hash => CrossCheck::Matching,
+			Some(&(first_hash, first_source)) => CrossCheck::Diverged {
+				first_source,
+				first_hash,
+			},
+			None => {
+				self.0.insert(block_number, (hash, source));
+				CrossCheck::First
+			},
+		};
+		let latest = latest.map_or(block_number, |latest| latest.max(block_number));
+		self.0 = self
+			.0
+			.split_off(&latest.saturating_sub(CROSS_CHECK_WINDOW - 1));
+		result
+	}
+}
+
+#[async_trait]
+impl HeaderSource for Combined {
+	fn name(&self) -> &'static str {
+		"combined"
+	}
+
+	async fn run(self: Box<Self>, event_sender: broadcast::Sender<Event>) -> Result<()> {
+		let mut names = vec![];
+		let mut events = StreamMap::new();
+		let mut running = FuturesUnordered::new();
+		let Combined {
+			sources,
+			cross_check,
+		} = *self;
+		for (index, source) in sources.into_iter().enumerate() {
+			let name = source.name();
+			let (sender, receiver) = broadcast::channel(COMBINED_CAPACITY);
+			names.push(name);
+			events.insert(index, BroadcastStream::new(receiver));
+			running.push(async move {
+				info!("Starting header source {name}...");
+				(name, source.run(sender).await)
+			});
+		}
+
+		let mut last = None;
+		let mut hashes = BlockHashes::default();
+		loop {
+			tokio::select! {
+				Some((name, result)) = running.next() => {
+					match result {
+						Ok(()) => warn!("Header source {name} stopped"),
+						Err(error) => warn!(%error, "Header source {name} failed"),
+					}
+					if running.is_empty() {
+						return Err(eyre!("All header sources stopped"));
+					}
+				},
+				Some((index, event)) = events.next() => match event {
+					Ok(Event::HeaderUpdate { header, received_at }) => {
+						let block_number = header.number;
+						if let Some(metrics) = &cross_check {
+							let hash = H256::from(Encode::using_encoded(&header, blake2_256));
+							match hashes.check(block_number, hash, names[index]) {
+								CrossCheck::Diverged { first_source, first_hash } => {
+									error!(
+										block_number,
+										source = names[index],
+										%hash,
+										first_source,
+										%first_hash,
+										"Header sources diverged on the same block"
+									);
+									metrics.count(MetricCounter::HeaderSourceDivergences).await;
+									continue;
+								},
+								CrossCheck::Matching => continue,
+								CrossCheck::First | CrossCheck::Expired => (),
+							}
+						}
+						if !is_newer(block_number, &mut last) {
+							continue;
+						}
+						event_sender
+							.send(Event::HeaderUpdate { header, received_at })
+							.map_err(|_| eyre!("Header event receivers are dropped"))?;
+					},
+					Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+						warn!("Header source {} is ahead, {skipped} headers are skipped", names[index])
+					},
+				},
+				else => return Err(eyre!("All header sources stopped")),
+			}
+		}
+	}
+}
+
+/// Receives headers over gossipsub, accepting only headers published by the trusted peers.
+/// Since justifications are not received, finality of gossiped headers is not verified by the light client,
+/// so they are tracked in the separate range of unverified headers.
+pub struct Gossip {
+	p2p_client: p2p::Client,
+	publishers: HashSet<PeerId>,
+	state: Arc<Mutex<State>>,
+	while_degraded: bool,
+}
+
+impl Gossip {
+	pub fn new(
+		p2p_client: p2p::Client,
+		publishers: &[String],
+		state: Arc<Mutex<State>>,
+	) -> Result<Self> {
+		if publishers.is_empty() {
+			return Err(eyre!("Header gossip source requires trusted publishers"));
+		}
+		let publishers = publishers
+			.iter()
+			.map(|peer_id| {
+				peer_id
+					.parse()
+					.map_err(|_| eyre!("Invalid header gossip publisher {peer_id}"))
+			})
+			.collect::<Result<HashSet<PeerId>>>()?;
+		Ok(Gossip {
+			p2p_client,
+			publishers,
+			state,
+			while_degraded: false,
+		})
+	}
+
+	/// Forwards gossiped headers only while all full nodes are unreachable,
+	/// so headers keep arriving in degraded mode
+	pub fn while_degraded(mut self) -> Self {
+		self.while_degraded = true;
+		self
+	}
+
+	/// Decodes header published by the trusted peer, returns `None` otherwise
+	fn accept(&self, source: &PeerId, data: &[u8]) -> Option<Header> {
+		if !self.publishers.contains(source) {
+			debug!(%source, "Ignoring header gossip from untrusted peer");
+			return None;
+		}
+		match Header::decode(&mut &data[..]) {
+			Ok(header) => Some(header),
+			Err(error) => {
+				warn!(%source, "Cannot decode gossiped header: {error}");
+				None
+			},
+		}
+	}
+}
+
+#[async_trait]
+impl HeaderSource for Gossip {
+	fn name(&self) -> &'static str {
+		"gossipsub"
+	}
+
+	async fn run(self: Box<Self>, event_sender: broadcast::Sender<Event>) -> Result<()> {
+		info!(publishers = ?self.publishers, "Receiving finalized headers over gossipsub...");
+		let mut receiver = self.p2p_client.subscribe_headers().await?;
+		let mut last = None;
+		loop {
+			let (source, data) = match receiver.recv().await {
+				Ok(message) => message,
+				Err(RecvError::Lagged(skipped)) => {
+					warn!("Header gossip is lagging, {skipped} messages are skipped");
+					continue;
+				},
+				// Channel is closed when the p2p event loop is restarted
+				Err(RecvError::Closed) => {
+					tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+					receiver = self.p2p_client.subscribe_headers().await?;
+					continue;
+				},
+			};
+			let Some(header) = self.accept(&source, &data) else {
+				continue;
+			};
+			let block_number = header.number;
+			if !is_newer(block_number, &mut last) {
+				continue;
+			}
+			{
+				let mut state = self.state.lock().expect("Lock should be acquired");
+				if self.while_degraded && !state.degraded {
+					continue;
+				}
+				info!("Gossiped header no.: {block_number}");
+				state.latest = block_number;
+				state.header_unverified.set(block_number);
+			}
+			event_sender
+				.send(Event::HeaderUpdate {
+					header,
+					received_at: Instant::now(),
+				})
+				.map_err(|_| eyre!("Header event receivers are dropped"))?;
+		}
+	}
+}
+
+/// Publishes received finalized headers over gossipsub, until the header event channel is closed
+pub async fn publish(p2p_client: p2p::Client, mut receiver: broadcast::Receiver<Event>) {
+	loop {
+		let header = match receiver.recv().await {
+			Ok(Event::HeaderUpdate { header, .. }) => header,
+			Err(RecvError::Lagged(skipped)) => {
+				warn!("Header publishing is lagging, {skipped} headers are skipped");
+				continue;
+			},
+			Err(RecvError::Closed) => return,
+		};
+		let block_number = header.number;
+		// Publishing fails if there are no subscribed peers
+		match p2p_client.publish_header(header.encode()).await {
+			Ok(()) => debug!(block_number, "Header published over gossipsub"),
+			Err(error) => debug!(block_number, "Cannot publish header: {error:#}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_newer, BlockHashes, CrossCheck, CROSS_CHECK_WINDOW};
+	use sp_core::H256;
+
+	#[test]
+	fn forward_each_block_once() {
+		let mut last = None;
+		let forwarded = [5, 6, 5, 6, 7, 4, 7]
+			.into_iter()
+			.filter(|&block_number| is_newer(block_number, &mut last))
+			.collect::<Vec<_>>();
+		assert_eq!(forwarded, vec![5, 6, 7]);
+		assert_eq!(last, Some(7));
+	}
+
+	#[test]
+	fn cross_check_block_hashes() {
+		let (first, second) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		let mut hashes = BlockHashes::default();
+		assert_eq!(hashes.check(10, first, "subscription"), CrossCheck::First);
+		assert_eq!(hashes.check(10, first, "polling"), CrossCheck::Matching);
+		assert_eq!(
+			hashes.check(10, second, "polling"),
+			CrossCheck::Diverged {
+				first_source: "subscription",
+				first_hash: first
+			}
+		);
+		assert_eq!(hashes.check(11, second, "polling"), CrossCheck::First);
+
+		let latest = 10 + CROSS_CHECK_WINDOW;
+		assert_eq!(hashes.check(latest, first, "polling"), CrossCheck::First);
+		assert_eq!(hashes.check(10, second, "polling"), CrossCheck::Expired);
+		assert_eq!(
+			hashes.check(11, first, "polling"),
+			CrossCheck::Diverged {
+				first_source: "polling",
+				first_hash: second
+			}
+		);
+	}
+}
@@ -1,15 +1,30 @@
 use allow_block_list::BlockedPeers;
-use color_eyre::{eyre::WrapErr, Report, Result};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Report, Result,
+};
 use libp2p::{
-	autonat, dcutr, identify, identity,
-	kad::{self, PeerRecord, QueryId},
-	mdns, noise, ping, relay,
+	autonat,
+	core::upgrade,
+	dcutr, gossipsub, identify, identity,
+	kad::{self, PeerRecord, QueryId, RecordKey},
+	mdns, noise, ping,
+	pnet::{PnetConfig, PreSharedKey},
+	relay,
+	request_response::{self, OutboundRequestId, ProtocolSupport},
 	swarm::NetworkBehaviour,
-	tcp, upnp, yamux, PeerId, Swarm, SwarmBuilder,
+	tcp, upnp, yamux, Multiaddr, PeerId, StreamProtocol, Swarm, SwarmBuilder, Transport,
 };
 use multihash::{self, Hasher};
-use std::collections::HashMap;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::{HashMap, HashSet},
+	fs,
+	str::FromStr,
+};
 use tokio::sync::{
+	broadcast,
 	mpsc::{self},
 	oneshot,
 };
@@ -22,6 +37,8 @@ mod event_loop;
 mod kad_mem_providers;
 mod kad_mem_store;
 mod kad_rocksdb_store;
+pub mod peer_store;
+pub mod record;
 
 use crate::types::{LibP2PConfig, SecretKey};
 pub use client::Client;
@@ -31,12 +48,51 @@ pub use kad_mem_store::MemoryStoreConfig;
 pub use kad_rocksdb_store::ExpirationCompactionFilterFactory;
 pub use kad_rocksdb_store::RocksDBStoreConfig;
 
-use self::{client::BlockStat, event_loop::ConnectionEstablishedInfo};
+use self::{client::BlockStat, event_loop::ConnectionEstablishedInfo, peer_store::PeerStore};
 use libp2p_allow_block_list as allow_block_list;
 
+/// Gossipsub topic on which finalized headers are published
+pub const HEADER_GOSSIP_TOPIC: &str = "/avail/light/headers/1";
+
+/// Header message received over gossipsub, with the peer which published it
+pub type HeaderGossip = (PeerId, Vec<u8>);
+
+/// Protocol on which DHT records are requested directly from the fat clients
+pub const RECORD_EXCHANGE_PROTOCOL: &str = "/avail/record/1.0.0";
+
+/// Request for the DHT record, sent to the fat client
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordRequest {
+	pub key: Vec<u8>,
+}
+
+/// Value of the requested DHT record, if peer has it in its local store
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordResponse {
+	pub value: Option<Vec<u8>>,
+}
+
+/// GET request sent directly to the fat client, with the response channel of the query
+type RecordExchange = (RecordKey, oneshot::Sender<Result<PeerRecord>>);
+
+/// Connection events of the remote peers
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeerEvent {
+	/// First connection with the peer is established
+	Connected(PeerId),
+	/// Last connection with the peer is closed
+	Disconnected(PeerId),
+}
+
 #[derive(Debug)]
 pub enum QueryChannel {
 	GetRecord(oneshot::Sender<Result<PeerRecord>>),
+	/// Collects distinct peers serving the record, until quorum is reached or query finishes
+	ProbeRecord {
+		quorum: usize,
+		peers: HashSet<PeerId>,
+		sender: oneshot::Sender<Result<usize>>,
+	},
 	PutRecord,
 	Bootstrap(oneshot::Sender<Result<()>>),
 }
@@ -48,6 +104,14 @@ pub struct EventLoopEntries<'a> {
 		&'a mut HashMap<PeerId, oneshot::Sender<Result<ConnectionEstablishedInfo>>>,
 	/// <block_num, (total_cells, result_cell_counter, time_stat)>
 	active_blocks: &'a mut HashMap<u32, BlockStat>,
+	header_gossip: &'a broadcast::Sender<HeaderGossip>,
+	peer_events: &'a broadcast::Sender<PeerEvent>,
+	/// Peers to which the node was successfully connected
+	peer_store: &'a mut PeerStore,
+	/// Fat clients learned over identify, with their listen addresses
+	fat_clients: &'a HashMap<PeerId, Vec<Multiaddr>>,
+	/// GET requests sent directly to the fat clients
+	pending_record_requests: &'a mut HashMap<OutboundRequestId, RecordExchange>,
 }
 
 impl<'a> EventLoopEntries<'a> {
@@ -59,12 +123,22 @@ impl<'a> EventLoopEntries<'a> {
 			oneshot::Sender<Result<ConnectionEstablishedInfo>>,
 		>,
 		active_blocks: &'a mut HashMap<u32, BlockStat>,
+		header_gossip: &'a broadcast::Sender<HeaderGossip>,
+		peer_events: &'a broadcast::Sender<PeerEvent>,
+		peer_store: &'a mut PeerStore,
+		fat_clients: &'a HashMap<PeerId, Vec<Multiaddr>>,
+		pending_record_requests: &'a mut HashMap<OutboundRequestId, RecordExchange>,
 	) -> Self {
 		Self {
 			swarm,
 			pending_kad_queries,
 			pending_swarm_events,
 			active_blocks,
+			header_gossip,
+			peer_events,
+			peer_store,
+			fat_clients,
+			pending_record_requests,
 		}
 	}
 
@@ -76,6 +150,10 @@ impl<'a> EventLoopEntries<'a> {
 		self.swarm.listeners().map(ToString::to_string).collect()
 	}
 
+	pub fn nat_status(&self) -> autonat::NatStatus {
+		self.swarm.behaviour().auto_nat.nat_status()
+	}
+
 	pub fn external_address(&self) -> Vec<String> {
 		self.swarm
 			.external_addresses()
@@ -87,6 +165,55 @@ impl<'a> EventLoopEntries<'a> {
 		self.pending_kad_queries.insert(query_id, result_sender);
 	}
 
+	/// Returns one of the connected fat clients, if any
+	pub fn connected_fat_client(&self) -> Option<PeerId> {
+		let connected = self
+			.fat_clients
+			.keys()
+			.filter(|peer_id| self.swarm.is_connected(peer_id))
+			.collect::<Vec<_>>();
+		connected
+			.choose(&mut rand::thread_rng())
+			.map(|&&peer_id| peer_id)
+	}
+
+	/// Requests the record directly from the fat client, response is sent once peer responds
+	pub fn request_record(
+		&mut self,
+		peer_id: PeerId,
+		key: RecordKey,
+		response_sender: oneshot::Sender<Result<PeerRecord>>,
+	) {
+		let request = RecordRequest { key: key.to_vec() };
+		let request_id = self
+			.swarm
+			.behaviour_mut()
+			.record_exchange
+			.send_request(&peer_id, request);
+		self.pending_record_requests
+			.insert(request_id, (key, response_sender));
+	}
+
+	/// Finishes GET queries which results are no longer awaited, returns the number of finished queries
+	pub fn finish_abandoned_queries(&mut self) -> usize {
+		let abandoned = self
+			.pending_kad_queries
+			.iter()
+			.filter(
+				|(_, channel)| matches!(channel, QueryChannel::GetRecord(sender) if sender.is_closed()),
+			)
+			.map(|(&query_id, _)| query_id)
+			.collect::<Vec<_>>();
+
+		for query_id in &abandoned {
+			self.pending_kad_queries.remove(query_id);
+			if let Some(mut query) = self.swarm.behaviour_mut().kademlia.query_mut(query_id) {
+				query.finish();
+			}
+		}
+		abandoned.len()
+	}
+
 	pub fn insert_swarm_event(
 		&mut self,
 		peer_id: PeerId,
@@ -131,6 +258,29 @@ pub struct Behaviour {
 	dcutr: dcutr::Behaviour,
 	upnp: upnp::tokio::Behaviour,
 	blocked_peers: allow_block_list::Behaviour<BlockedPeers>,
+	gossipsub: gossipsub::Behaviour,
+	record_exchange: request_response::cbor::Behaviour<RecordRequest, RecordResponse>,
+}
+
+/// Public reachability of the local node, as determined by AutoNAT probes
+/// of the addresses observed by the remote peers
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Reachability {
+	#[default]
+	Unknown,
+	Public,
+	Private,
+}
+
+impl From<&autonat::NatStatus> for Reachability {
+	fn from(status: &autonat::NatStatus) -> Self {
+		match status {
+			autonat::NatStatus::Public(_) => Reachability::Public,
+			autonat::NatStatus::Private => Reachability::Private,
+			autonat::NatStatus::Unknown => Reachability::Unknown,
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -138,6 +288,9 @@ pub struct LocalInfo {
 	pub peer_id: String,
 	pub local_listeners: Vec<String>,
 	pub external_listeners: Vec<String>,
+	pub reachability: Reachability,
+	/// Address confirmed as publicly reachable by AutoNAT
+	pub public_address: Option<String>,
 }
 
 fn generate_config(config: libp2p::swarm::Config, cfg: &LibP2PConfig) -> libp2p::swarm::Config {
@@ -149,6 +302,13 @@ fn generate_config(config: libp2p::swarm::Config, cfg: &LibP2PConfig) -> libp2p:
 		.with_per_connection_event_buffer_size(cfg.per_connection_event_buffer_size)
 }
 
+/// Reads pre-shared key of the private network, in the `/key/swarm/psk/1.0.0/` format
+pub fn read_pre_shared_key(path: &str) -> Result<PreSharedKey> {
+	let key = fs::read_to_string(path)
+		.wrap_err_with(|| format!("Cannot read pre-shared key file {path}"))?;
+	PreSharedKey::from_str(&key).wrap_err_with(|| format!("Invalid pre-shared key in {path}"))
+}
+
 async fn build_swarm(
 	cfg: &LibP2PConfig,
 	id_keys: &libp2p::identity::Keypair,
@@ -187,10 +347,56 @@ async fn build_swarm(
 			mdns: mdns::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?,
 			upnp: upnp::tokio::Behaviour::default(),
 			blocked_peers: allow_block_list::Behaviour::default(),
+			gossipsub: gossipsub::Behaviour::new(
+				gossipsub::MessageAuthenticity::Signed(key.clone()),
+				gossipsub::Config::default(),
+			)?,
+			record_exchange: request_response::cbor::Behaviour::new(
+				[(
+					StreamProtocol::new(RECORD_EXCHANGE_PROTOCOL),
+					ProtocolSupport::Full,
+				)],
+				request_response::Config::default(),
+			),
 		})
 	};
 
-	if is_ws_transport {
+	let pre_shared_key = cfg
+		.pnet_key_file
+		.as_deref()
+		.map(read_pre_shared_key)
+		.transpose()?;
+
+	if is_ws_transport && pre_shared_key.is_some() {
+		return Err(eyre!(
+			"Private network is not supported with the WebSocket transport"
+		));
+	}
+
+	if let Some(psk) = pre_shared_key {
+		info!(
+			"Private network enabled, key fingerprint: {}",
+			psk.fingerprint()
+		);
+		let tcp_config = tcp::Config::default().port_reuse(false).nodelay(false);
+		// pre-shared key handshake is done before the security upgrade,
+		// so peers without the key cannot establish connections
+		swarm = tokio_swarm
+			.with_other_transport(|key| {
+				noise::Config::new(key).map(|noise_config| {
+					tcp::tokio::Transport::new(tcp_config)
+						.and_then(move |socket, _| PnetConfig::new(psk).handshake(socket))
+						.upgrade(upgrade::Version::V1Lazy)
+						.authenticate(noise_config)
+						.multiplex(yamux::Config::default())
+				})
+			})?
+			.with_dns()?
+			.with_relay_client(noise::Config::new, yamux::Config::default)?
+			.with_behaviour(behaviour)?
+			.with_swarm_config(|c| generate_config(c, cfg))
+			.build();
+	} else if is_ws_transport {
 		swarm = tokio_swarm
 			.with_websocket(noise::Config::new, yamux::Config::default)
 			.await?
@@ -251,3 +457,24 @@ pub fn keypair(cfg: &LibP2PConfig) -> Result<(libp2p::identity::Keypair, String)
 	let peer_id = PeerId::from(keypair.public()).to_string();
 	Ok((keypair, peer_id))
 }
+
+#[cfg(test)]
+mod tests {
+	use super::read_pre_shared_key;
+	use std::fs;
+
+	#[test]
+	fn pre_shared_key_file() {
+		let dir = std::env::temp_dir();
+		let path = dir.join("avail-light-test-swarm.key");
+		let key = format!("/key/swarm/psk/1.0.0/\n/base16/\n{}\n", "ab".repeat(32));
+		fs::write(&path, key).unwrap();
+		assert!(read_pre_shared_key(path.to_str().unwrap()).is_ok());
+
+		fs::write(&path, "/key/swarm/psk/1.0.0/\n/base16/\nab\n").unwrap();
+		assert!(read_pre_shared_key(path.to_str().unwrap()).is_err());
+		fs::remove_file(&path).unwrap();
+
+		assert!(read_pre_shared_key(path.to_str().unwrap()).is_err());
+	}
+}
@@ -1,26 +1,55 @@
 use super::{
-	event_loop::ConnectionEstablishedInfo, Command, CommandSender, EventLoopEntries, LocalInfo,
-	QueryChannel, SendableCommand,
+	event_loop::{is_dns_address, ConnectionEstablishedInfo},
+	peer_store::{KnownPeer, KNOWN_PEERS_DIAL_LIMIT},
+	record::{self, Reference, ReferenceConfig, ReferenceFormat},
+	Command, CommandSender, EventLoopEntries, HeaderGossip, LocalInfo, PeerEvent, QueryChannel,
+	SendableCommand, HEADER_GOSSIP_TOPIC,
 };
 use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Report, Result,
 };
-use futures::future::join_all;
+use futures::{future::join_all, stream::FuturesUnordered, Stream, StreamExt};
 use kate_recovery::{
-	config,
 	data::Cell,
 	matrix::{Dimensions, Position, RowIndex},
 };
 use libp2p::{
+	autonat,
+	gossipsub::IdentTopic,
 	kad::{store::RecordStore, PeerRecord, Quorum, Record, RecordKey},
-	swarm::dial_opts::DialOpts,
+	swarm::dial_opts::{DialOpts, PeerCondition},
 	Multiaddr, PeerId,
 };
 use std::time::{Duration, Instant};
-use tokio::sync::oneshot;
+use tokio::sync::{broadcast, oneshot};
 use tracing::{debug, trace};
 
+use crate::cell_size::CellSize;
+
+/// Number of dial attempts of the bootstrap peers with DNS addresses
+const BOOTSTRAP_DIAL_ATTEMPTS: usize = 3;
+/// Delay between the dial attempts of the bootstrap peers
+const BOOTSTRAP_DIAL_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+fn decode_cell(
+	block_number: u32,
+	position: Position,
+	value: Vec<u8>,
+	cell_size: CellSize,
+) -> Option<Cell> {
+	match record::decode_cell_value(position, &value, cell_size) {
+		Ok(cell) => Some(cell),
+		Err(error) => {
+			debug!(
+				"Cannot decode cell {}: {error}",
+				position.reference(block_number)
+			);
+			None
+		},
+	}
+}
+
 #[derive(Clone)]
 pub struct Client {
 	command_sender: CommandSender,
@@ -28,39 +57,15 @@ pub struct Client {
 	dht_parallelization_limit: usize,
 	/// Cell time to live in DHT (in seconds)
 	ttl: u64,
-}
-
-struct DHTCell(Cell);
-
-impl DHTCell {
-	fn reference(&self, block: u32) -> String {
-		self.0.reference(block)
-	}
-
-	fn dht_record(&self, block: u32, ttl: u64) -> Record {
-		Record {
-			key: self.0.reference(block).as_bytes().to_vec().into(),
-			value: self.0.content.to_vec(),
-			publisher: None,
-			expires: Instant::now().checked_add(Duration::from_secs(ttl)),
-		}
-	}
-}
-struct DHTRow((RowIndex, Vec<u8>));
-
-impl DHTRow {
-	fn reference(&self, block: u32) -> String {
-		self.0 .0.reference(block)
-	}
-
-	fn dht_record(&self, block: u32, ttl: u64) -> Record {
-		Record {
-			key: self.0 .0.reference(block).as_bytes().to_vec().into(),
-			value: self.0 .1.clone(),
-			publisher: None,
-			expires: Instant::now().checked_add(Duration::from_secs(ttl)),
-		}
-	}
+	/// Format of DHT record references and legacy references migration window
+	references: ReferenceConfig,
+	/// Number of blocks after which published records are removed from the local store
+	published_records_window: Option<u32>,
+	/// Sizes of the cells stored in DHT
+	cell_size: CellSize,
+	/// Fault injection into DHT operations
+	#[cfg(feature = "chaos")]
+	chaos: Option<crate::network::chaos::Chaos>,
 }
 
 #[derive(Debug)]
@@ -193,28 +198,65 @@ impl Command for Bootstrap {
 	}
 }
 
-struct GetKadRecord {
+/// Batch of GET queries, started in one pass of the event loop.
+/// Each query result is sent to its own response channel, as soon as query finishes.
+/// Records are requested from the connected fat clients first, and from the DHT if there are none.
+struct GetKadRecords {
+	requests: Vec<(RecordKey, oneshot::Sender<Result<PeerRecord>>)>,
+}
+
+impl Command for GetKadRecords {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		for (key, response_sender) in self.requests.drain(..) {
+			if let Some(peer_id) = entries.connected_fat_client() {
+				entries.request_record(peer_id, key, response_sender);
+				continue;
+			}
+			let query_id = entries.behavior_mut().kademlia.get_record(key);
+
+			// insert response channel into KAD Queries pending map
+			entries.insert_query(query_id, QueryChannel::GetRecord(response_sender));
+		}
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		for (_, response_sender) in self.requests.drain(..) {
+			// receivers of the abandoned fetches can be dropped
+			_ = response_sender.send(Err(eyre!("{error:#}")));
+		}
+	}
+}
+
+struct ProbeKadRecord {
 	key: RecordKey,
-	response_sender: Option<oneshot::Sender<Result<PeerRecord>>>,
+	quorum: usize,
+	response_sender: Option<oneshot::Sender<Result<usize>>>,
 }
 
-impl Command for GetKadRecord {
+impl Command for ProbeKadRecord {
 	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
 		let query_id = entries.behavior_mut().kademlia.get_record(self.key.clone());
 
 		// insert response channel into KAD Queries pending map
 		let response_sender = self.response_sender.take().unwrap();
-		entries.insert_query(query_id, super::QueryChannel::GetRecord(response_sender));
+		entries.insert_query(
+			query_id,
+			QueryChannel::ProbeRecord {
+				quorum: self.quorum,
+				peers: Default::default(),
+				sender: response_sender,
+			},
+		);
 		Ok(())
 	}
 
 	fn abort(&mut self, error: Report) {
-		// TODO: consider what to do if this results with None
 		self.response_sender
 			.take()
 			.unwrap()
 			.send(Err(error))
-			.expect("GetKadRecord receiver dropped");
+			.expect("ProbeKadRecord receiver dropped");
 	}
 }
 
@@ -255,6 +297,122 @@ impl Command for PutKadRecord {
 	fn abort(&mut self, _: Report) {}
 }
 
+struct PrunePublishedRecords {
+	before_block: u32,
+	response_sender: Option<oneshot::Sender<Result<usize>>>,
+}
+
+impl Command for PrunePublishedRecords {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let local_peer_id = *entries.peer_id();
+		let now = Instant::now();
+		let store = entries.behavior_mut().kademlia.store_mut();
+		// Publisher and expiration are persisted with the record, and block number is part of its key,
+		// so records published before the restart are found as well
+		let pruned = store
+			.records()
+			.filter(|record| record.publisher == Some(local_peer_id))
+			.filter(|record| {
+				record.is_expired(now)
+					|| Reference::decode_key(&record.key)
+						.is_ok_and(|reference| reference.block_number() < self.before_block)
+			})
+			.map(|record| record.key.clone())
+			.collect::<Vec<_>>();
+		for key in &pruned {
+			store.remove(key);
+		}
+		_ = self.response_sender.take().unwrap().send(Ok(pruned.len()));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
+struct FinishAbandonedQueries {
+	response_sender: Option<oneshot::Sender<Result<usize>>>,
+}
+
+impl Command for FinishAbandonedQueries {
+	fn run(&mut self, mut entries: EventLoopEntries) -> Result<()> {
+		let finished = entries.finish_abandoned_queries();
+		// caller may not wait for the result
+		_ = self.response_sender.take().unwrap().send(Ok(finished));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
+struct SubscribePeerEvents {
+	response_sender: Option<oneshot::Sender<Result<broadcast::Receiver<PeerEvent>>>>,
+}
+
+impl Command for SubscribePeerEvents {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		_ = self
+			.response_sender
+			.take()
+			.unwrap()
+			.send(Ok(entries.peer_events.subscribe()));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
+struct SubscribeHeaders {
+	response_sender: Option<oneshot::Sender<Result<broadcast::Receiver<HeaderGossip>>>>,
+}
+
+impl Command for SubscribeHeaders {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		entries
+			.swarm
+			.behaviour_mut()
+			.gossipsub
+			.subscribe(&IdentTopic::new(HEADER_GOSSIP_TOPIC))?;
+		_ = self
+			.response_sender
+			.take()
+			.unwrap()
+			.send(Ok(entries.header_gossip.subscribe()));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
+struct PublishHeader {
+	data: Vec<u8>,
+	response_sender: Option<oneshot::Sender<Result<()>>>,
+}
+
+impl Command for PublishHeader {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		let data = std::mem::take(&mut self.data);
+		entries
+			.swarm
+			.behaviour_mut()
+			.gossipsub
+			.publish(IdentTopic::new(HEADER_GOSSIP_TOPIC), data)?;
+		_ = self.response_sender.take().unwrap().send(Ok(()));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
 struct CountConnectedPeers {
 	response_sender: Option<oneshot::Sender<Result<usize>>>,
 }
@@ -287,6 +445,11 @@ struct GetLocalInfo {
 
 impl Command for GetLocalInfo {
 	fn run(&mut self, entries: EventLoopEntries) -> Result<(), Report> {
+		let nat_status = entries.nat_status();
+		let public_address = match &nat_status {
+			autonat::NatStatus::Public(address) => Some(address.to_string()),
+			_ => None,
+		};
 		// send result back
 		// TODO: consider what to do if this results with None
 		self.response_sender
@@ -296,6 +459,8 @@ impl Command for GetLocalInfo {
 				peer_id: entries.peer_id().to_string(),
 				local_listeners: entries.listeners(),
 				external_listeners: entries.external_address(),
+				reachability: (&nat_status).into(),
+				public_address,
 			}))
 			.expect("GetLocalInfo receiver dropped");
 		Ok(())
@@ -452,15 +617,97 @@ impl Command for AddAutonatServer {
 	}
 }
 
+struct RestorePeers {
+	peers: Vec<KnownPeer>,
+	response_sender: Option<oneshot::Sender<Result<usize>>>,
+}
+
+impl Command for RestorePeers {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		entries.peer_store.restore(std::mem::take(&mut self.peers));
+
+		let mut dialed = 0;
+		for (peer_id, addresses) in entries.peer_store.best(KNOWN_PEERS_DIAL_LIMIT) {
+			let kademlia = &mut entries.swarm.behaviour_mut().kademlia;
+			for address in &addresses {
+				_ = kademlia.add_address(&peer_id, address.clone());
+			}
+			let opts = DialOpts::peer_id(peer_id)
+				.addresses(addresses)
+				.condition(PeerCondition::Disconnected)
+				.build();
+			match entries.swarm.dial(opts) {
+				Ok(()) => dialed += 1,
+				Err(error) => trace!("Dialing known peer {peer_id} failed: {error}"),
+			}
+		}
+		_ = self.response_sender.take().unwrap().send(Ok(dialed));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
+struct GetKnownPeers {
+	response_sender: Option<oneshot::Sender<Result<Vec<KnownPeer>>>>,
+}
+
+impl Command for GetKnownPeers {
+	fn run(&mut self, entries: EventLoopEntries) -> Result<()> {
+		_ = self
+			.response_sender
+			.take()
+			.unwrap()
+			.send(Ok(entries.peer_store.known_peers()));
+		Ok(())
+	}
+
+	fn abort(&mut self, error: Report) {
+		_ = self.response_sender.take().unwrap().send(Err(error));
+	}
+}
+
 impl Client {
 	pub fn new(sender: CommandSender, dht_parallelization_limit: usize, ttl: u64) -> Self {
 		Self {
 			command_sender: sender,
 			dht_parallelization_limit,
 			ttl,
+			references: ReferenceConfig::default(),
+			published_records_window: None,
+			cell_size: CellSize::default(),
+			#[cfg(feature = "chaos")]
+			chaos: None,
 		}
 	}
 
+	/// Sets sizes of the cells stored in DHT
+	pub fn with_cell_size(mut self, cell_size: CellSize) -> Self {
+		self.cell_size = cell_size;
+		self
+	}
+
+	/// Sets format of DHT record references and legacy references migration window
+	pub fn with_references(mut self, references: ReferenceConfig) -> Self {
+		self.references = references;
+		self
+	}
+
+	/// Sets number of blocks after which records published by this node are removed from the local store
+	pub fn with_published_records_window(mut self, window: Option<u32>) -> Self {
+		self.published_records_window = window;
+		self
+	}
+
+	/// Enables fault injection into DHT GET and PUT operations
+	#[cfg(feature = "chaos")]
+	pub fn with_chaos(mut self, chaos: crate::network::chaos::Chaos) -> Self {
+		self.chaos = Some(chaos);
+		self
+	}
+
 	async fn execute_sync<F, T>(&self, command_with_sender: F) -> Result<T>
 	where
 		F: FnOnce(oneshot::Sender<Result<T>>) -> SendableCommand,
@@ -526,9 +773,26 @@ impl Client {
 		.await
 	}
 
+	/// Dials bootstrap peer, retrying failed dials of the DNS addresses,
+	/// which are resolved again on each dial
+	async fn dial_bootstrap(&self, peer: PeerId, addr: &Multiaddr) -> Result<()> {
+		let mut attempt = 1;
+		loop {
+			match self.dial_peer(peer, vec![addr.clone()]).await {
+				Ok(_) => return Ok(()),
+				Err(error) if attempt < BOOTSTRAP_DIAL_ATTEMPTS && is_dns_address(addr) => {
+					debug!("Dialing bootstrap peer {peer} on {addr} failed, retrying: {error:#}");
+					attempt += 1;
+					tokio::time::sleep(BOOTSTRAP_DIAL_RETRY_DELAY).await;
+				},
+				Err(error) => return Err(error),
+			}
+		}
+	}
+
 	pub async fn bootstrap_on_startup(&self, nodes: Vec<(PeerId, Multiaddr)>) -> Result<()> {
 		for (peer, addr) in nodes {
-			self.dial_peer(peer, vec![addr.clone()])
+			self.dial_bootstrap(peer, &addr)
 				.await
 				.wrap_err("Dialing Bootstrap peer failed.")?;
 			self.add_address(peer, addr.clone()).await?;
@@ -538,14 +802,55 @@ impl Client {
 		self.bootstrap().await
 	}
 
-	async fn get_kad_record(&self, key: RecordKey) -> Result<PeerRecord> {
-		self.execute_sync(|response_sender| {
-			Box::new(GetKadRecord {
-				key,
-				response_sender: Some(response_sender),
+	/// Sends GET queries of the given keys in a single command.
+	/// Results are returned in order of the keys.
+	async fn get_kad_records(&self, keys: Vec<RecordKey>) -> Vec<Result<PeerRecord>> {
+		let receive = |receiver| self.receive_kad_record(receiver);
+		join_all(self.send_get_kad_records(keys).into_iter().map(receive)).await
+	}
+
+	/// Sends GET queries of the given keys in a single command, without waiting for the results.
+	/// Returns response receivers in order of the keys.
+	fn send_get_kad_records(
+		&self,
+		keys: Vec<RecordKey>,
+	) -> Vec<oneshot::Receiver<Result<PeerRecord>>> {
+		let (requests, receivers): (Vec<_>, Vec<_>) = keys
+			.into_iter()
+			.map(|key| {
+				let (response_sender, response_receiver) = oneshot::channel();
+				((key, response_sender), response_receiver)
 			})
-		})
-		.await
+			.unzip();
+
+		if !requests.is_empty() {
+			// if command is not sent, response senders are dropped and receivers fail
+			if let Err(error) = self
+				.command_sender
+				.send(Box::new(GetKadRecords { requests }))
+			{
+				debug!("Cannot send DHT GET queries: {error}");
+			}
+		}
+		receivers
+	}
+
+	async fn receive_kad_record(
+		&self,
+		receiver: oneshot::Receiver<Result<PeerRecord>>,
+	) -> Result<PeerRecord> {
+		#[allow(unused_mut)]
+		let mut peer_record = receiver.await.wrap_err("sender should not be dropped")??;
+
+		#[cfg(feature = "chaos")]
+		if let Some(chaos) = self.chaos.as_ref() {
+			use crate::network::chaos::Operation;
+			chaos
+				.apply(Operation::Get, &mut peer_record.record.value)
+				.await?;
+		}
+
+		Ok(peer_record)
 	}
 
 	async fn put_kad_record(
@@ -554,6 +859,22 @@ impl Client {
 		quorum: Quorum,
 		block_num: u32,
 	) -> Result<()> {
+		#[cfg(feature = "chaos")]
+		let records = match self.chaos.as_ref() {
+			Some(chaos) => {
+				use crate::network::chaos::Operation;
+				let mut applied = Vec::with_capacity(records.len());
+				for mut record in records {
+					match chaos.apply(Operation::Put, &mut record.value).await {
+						Ok(()) => applied.push(record),
+						Err(error) => trace!("{error}"),
+					}
+				}
+				applied
+			},
+			None => records,
+		};
+
 		self.command_sender
 			.send(Box::new(PutKadRecord {
 				records,
@@ -563,6 +884,70 @@ impl Client {
 			.context("receiver should not be dropped")
 	}
 
+	/// Finishes DHT GET queries which results are no longer awaited (e.g. cancelled block processing).
+	/// Returns the number of finished queries.
+	pub async fn finish_abandoned_queries(&self) -> Result<usize> {
+		self.execute_sync(|response_sender| {
+			Box::new(FinishAbandonedQueries {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Subscribes to the connection events of the remote peers
+	pub async fn subscribe_peer_events(&self) -> Result<broadcast::Receiver<PeerEvent>> {
+		self.execute_sync(|response_sender| {
+			Box::new(SubscribePeerEvents {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Subscribes to the finalized headers published over gossipsub
+	pub async fn subscribe_headers(&self) -> Result<broadcast::Receiver<HeaderGossip>> {
+		self.execute_sync(|response_sender| {
+			Box::new(SubscribeHeaders {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Publishes encoded finalized header over gossipsub
+	pub async fn publish_header(&self, data: Vec<u8>) -> Result<()> {
+		self.execute_sync(|response_sender| {
+			Box::new(PublishHeader {
+				data,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Restores persisted known peers, adds them to the routing table and dials the most recently seen ones.
+	/// Returns the number of dialed peers.
+	pub async fn restore_peers(&self, peers: Vec<KnownPeer>) -> Result<usize> {
+		self.execute_sync(|response_sender| {
+			Box::new(RestorePeers {
+				peers,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
+	/// Returns peers to which the node was successfully connected, most recently seen first
+	pub async fn known_peers(&self) -> Result<Vec<KnownPeer>> {
+		self.execute_sync(|response_sender| {
+			Box::new(GetKnownPeers {
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
+
 	pub async fn count_dht_entries(&self) -> Result<usize> {
 		self.execute_sync(|response_sender| {
 			Box::new(CountConnectedPeers {
@@ -619,52 +1004,182 @@ impl Client {
 		.await
 	}
 
-	// Since callers ignores DHT errors, debug logs are used to observe DHT behavior.
-	// Return type assumes that cell is not found in case when error is present.
-	async fn fetch_cell_from_dht(&self, block_number: u32, position: Position) -> Option<Cell> {
-		let reference = position.reference(block_number);
-		let record_key = RecordKey::from(reference.as_bytes().to_vec());
-
-		trace!("Getting DHT record for reference {}", reference);
-
-		match self.get_kad_record(record_key).await {
-			Ok(peer_record) => {
-				trace!("Fetched cell {reference} from the DHT");
+	/// Removes records published by this node from the local store, once they are older than
+	/// the published records window, counted from the given block. Returns the number of removed records.
+	/// Records are recognized by the publisher, so records received from other peers are kept.
+	pub async fn prune_published_records(&self, block_number: u32) -> Result<usize> {
+		let Some(window) = self.published_records_window else {
+			return Ok(0);
+		};
+		let before_block = block_number.saturating_sub(window);
+		self.execute_sync(|response_sender| {
+			Box::new(PrunePublishedRecords {
+				before_block,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
 
-				let try_content: Result<[u8; config::COMMITMENT_SIZE + config::CHUNK_SIZE], _> =
-					peer_record.record.value.try_into();
+	/// Counts distinct remote peers serving the record, up to the given quorum.
+	/// Query is not stopped on the first found record, so it is more expensive than regular GET.
+	pub async fn probe_record_replication(
+		&self,
+		reference: Reference,
+		quorum: usize,
+	) -> Result<usize> {
+		let format = self.references.read_formats(reference.block_number())[0];
+		self.execute_sync(|response_sender| {
+			Box::new(ProbeKadRecord {
+				key: reference.key(format),
+				quorum,
+				response_sender: Some(response_sender),
+			})
+		})
+		.await
+	}
 
-				let Ok(content) = try_content else {
-					debug!("Cannot convert cell {reference} into 80 bytes");
-					return None;
-				};
+	// Tries each of the reference formats used for the block, in order of precedence.
+	// Legacy references are read only for blocks in the migration window.
+	// References not found in one format are fetched in the next one as a single batch.
+	async fn fetch_record_values_from_dht(&self, references: &[Reference]) -> Vec<Option<Vec<u8>>> {
+		let mut values = vec![None; references.len()];
+		let formats = references
+			.iter()
+			.map(|reference| self.references.read_formats(reference.block_number()))
+			.collect::<Vec<_>>();
+		let rounds = formats.iter().map(Vec::len).max().unwrap_or_default();
+
+		for round in 0..rounds {
+			let pending = (0..references.len())
+				.filter(|&i| values[i].is_none())
+				.filter_map(|i| formats[i].get(round).map(|&format| (i, format)))
+				.collect::<Vec<_>>();
+
+			let keys = pending
+				.iter()
+				.map(|&(i, format)| {
+					trace!(
+						"Getting DHT record for reference {}",
+						references[i].encode(format)
+					);
+					references[i].key(format)
+				})
+				.collect();
+
+			let results = self.get_kad_records(keys).await;
+			for ((i, format), result) in pending.into_iter().zip(results) {
+				let encoded = references[i].encode(format);
+				match result {
+					Ok(peer_record) => {
+						trace!("Fetched {encoded} from the DHT");
+						values[i] = Some(peer_record.record.value);
+					},
+					Err(error) => trace!("Reference {encoded} not found in the DHT: {error}"),
+				}
+			}
+		}
+		values
+	}
 
-				Some(Cell { position, content })
-			},
-			Err(error) => {
-				trace!("Cell {reference} not found in the DHT: {error}");
-				None
-			},
+	// Receives record value in the first reference format, trying the remaining formats if record is not found.
+	async fn receive_record_value_from_dht(
+		&self,
+		reference: Reference,
+		formats: &[ReferenceFormat],
+		receiver: oneshot::Receiver<Result<PeerRecord>>,
+	) -> Option<Vec<u8>> {
+		let mut receiver = Some(receiver);
+		for &format in formats {
+			let encoded = reference.encode(format);
+			let receiver = match receiver.take() {
+				Some(receiver) => receiver,
+				None => {
+					trace!("Getting DHT record for reference {encoded}");
+					self.send_get_kad_records(vec![reference.key(format)])
+						.remove(0)
+				},
+			};
+			match self.receive_kad_record(receiver).await {
+				Ok(peer_record) => {
+					trace!("Fetched {encoded} from the DHT");
+					return Some(peer_record.record.value);
+				},
+				Err(error) => trace!("Reference {encoded} not found in the DHT: {error}"),
+			}
 		}
+		None
 	}
 
-	async fn fetch_row_from_dht(
+	// Since callers ignores DHT errors, debug logs are used to observe DHT behavior.
+	// Return type assumes that cell is not found in case when error is present.
+	async fn fetch_chunk_of_cells_from_dht(
 		&self,
 		block_number: u32,
-		row_index: u32,
-	) -> Option<(u32, Vec<u8>)> {
-		let row_index = RowIndex(row_index);
-		let reference = row_index.reference(block_number);
-		let record_key = RecordKey::from(reference.as_bytes().to_vec());
-
-		trace!("Getting DHT record for reference {}", reference);
-
-		match self.get_kad_record(record_key).await {
-			Ok(peer_record) => Some((row_index.0, peer_record.record.value)),
-			Err(error) => {
-				debug!("Row {reference} not found in the DHT: {error}");
-				None
-			},
+		positions: &[Position],
+	) -> Vec<Option<Cell>> {
+		let references = positions
+			.iter()
+			.map(|&position| Reference::Cell {
+				block_number,
+				position,
+			})
+			.collect::<Vec<_>>();
+
+		let values = self.fetch_record_values_from_dht(&references).await;
+		positions
+			.iter()
+			.zip(values)
+			.map(|(&position, value)| decode_cell(block_number, position, value?, self.cell_size))
+			.collect()
+	}
+
+	/// Fetches cells from DHT, yielding each position with the fetched cell as soon as its query finishes,
+	/// or with `None` if cell is not found. Queries of up to `dht_parallelization_limit` cells
+	/// are sent as a single batch, and the next batch is sent once all cells of the previous one are yielded.
+	///
+	/// # Arguments
+	///
+	/// * `block_number` - Block number
+	/// * `positions` - Cell positions to fetch
+	pub fn stream_cells_from_dht<'a>(
+		&'a self,
+		block_number: u32,
+		positions: &'a [Position],
+	) -> impl Stream<Item = (Position, Option<Cell>)> + 'a {
+		async_stream::stream! {
+			let formats = self.references.read_formats(block_number);
+			for positions in positions.chunks(self.dht_parallelization_limit) {
+				let references = positions
+					.iter()
+					.map(|&position| Reference::Cell {
+						block_number,
+						position,
+					})
+					.collect::<Vec<_>>();
+				let keys = references
+					.iter()
+					.map(|reference| reference.key(formats[0]))
+					.collect();
+				let receivers = self.send_get_kad_records(keys);
+
+				let formats = &formats;
+				let mut pending = positions
+					.iter()
+					.zip(references)
+					.zip(receivers)
+					.map(|((&position, reference), receiver)| async move {
+						let value = self
+							.receive_record_value_from_dht(reference, formats, receiver)
+							.await;
+						(position, value)
+					})
+					.collect::<FuturesUnordered<_>>();
+
+				while let Some((position, value)) = pending.next().await {
+					yield (position, value.and_then(|value| decode_cell(block_number, position, value, self.cell_size)));
+				}
+			}
 		}
 	}
 
@@ -683,9 +1198,10 @@ impl Client {
 		let mut cells = Vec::<Option<Cell>>::with_capacity(positions.len());
 
 		for positions in positions.chunks(self.dht_parallelization_limit) {
-			let fetch = |&position| self.fetch_cell_from_dht(block_number, position);
-			let results = join_all(positions.iter().map(fetch)).await;
-			cells.extend(results.into_iter().collect::<Vec<_>>());
+			let results = self
+				.fetch_chunk_of_cells_from_dht(block_number, positions)
+				.await;
+			cells.extend(results);
 		}
 
 		let unfetched = cells
@@ -715,25 +1231,26 @@ impl Client {
 	) -> Vec<Option<Vec<u8>>> {
 		let mut rows = vec![None; dimensions.extended_rows() as usize];
 		for row_indexes in row_indexes.chunks(self.dht_parallelization_limit) {
-			let fetch = |row| self.fetch_row_from_dht(block_number, row);
-			let fetched_rows = join_all(row_indexes.iter().cloned().map(fetch)).await;
-			for (row_index, row) in fetched_rows.into_iter().flatten() {
-				rows[row_index as usize] = Some(row);
+			let references = row_indexes
+				.iter()
+				.map(|&row| Reference::Row { block_number, row })
+				.collect::<Vec<_>>();
+			let values = self.fetch_record_values_from_dht(&references).await;
+			for (&row_index, value) in row_indexes.iter().zip(values) {
+				if value.is_none() {
+					debug!("Row {row_index} of block {block_number} not found in the DHT");
+				}
+				rows[row_index as usize] = value;
 			}
 		}
 		rows
 	}
 
-	async fn insert_into_dht(&self, records: Vec<(String, Record)>, block_num: u32) -> Result<()> {
+	async fn insert_into_dht(&self, records: Vec<Record>, block_num: u32) -> Result<()> {
 		if records.is_empty() {
 			return Err(eyre!("Cant send empty record list."));
 		}
-		self.put_kad_record(
-			records.into_iter().map(|e| e.1).collect(),
-			Quorum::One,
-			block_num,
-		)
-		.await
+		self.put_kad_record(records, Quorum::One, block_num).await
 	}
 
 	/// Inserts cells into the DHT.
@@ -747,10 +1264,15 @@ impl Client {
 	/// * `block` - Block number
 	/// * `cells` - Matrix cells to store into DHT
 	pub async fn insert_cells_into_dht(&self, block: u32, cells: Vec<Cell>) -> Result<()> {
-		let records: Vec<_> = cells
+		let records: Vec<_> = self
+			.references
+			.write_formats(block)
 			.into_iter()
-			.map(DHTCell)
-			.map(|cell| (cell.reference(block), cell.dht_record(block, self.ttl)))
+			.flat_map(|format| {
+				cells
+					.iter()
+					.map(move |cell| record::encode_cell(format, block, cell, self.ttl))
+			})
 			.collect::<Vec<_>>();
 		self.insert_into_dht(records, block).await
 	}
@@ -770,10 +1292,15 @@ impl Client {
 		block: u32,
 		rows: Vec<(RowIndex, Vec<u8>)>,
 	) -> Result<()> {
-		let records: Vec<_> = rows
+		let records: Vec<_> = self
+			.references
+			.write_formats(block)
 			.into_iter()
-			.map(DHTRow)
-			.map(|row| (row.reference(block), row.dht_record(block, self.ttl)))
+			.flat_map(|format| {
+				rows.iter().map(move |(row, data)| {
+					record::encode_row(format, block, row.0, data.clone(), self.ttl)
+				})
+			})
 			.collect::<Vec<_>>();
 
 		self.insert_into_dht(records, block).await
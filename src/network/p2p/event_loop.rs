@@ -3,16 +3,16 @@ use futures::StreamExt;
 use libp2p::{
 	autonat::{self, NatStatus},
 	core::ConnectedPoint,
-	dcutr,
+	dcutr, gossipsub,
 	identify::{self, Info},
 	identity::Keypair,
 	kad::{
-		self, store::RecordStore, BootstrapOk, GetRecordOk, InboundRequest, QueryId, QueryResult,
-		QueryStats, RecordKey,
+		self, store::RecordStore, BootstrapOk, GetRecordOk, InboundRequest, PeerRecord, QueryId,
+		QueryResult, QueryStats, Record, RecordKey,
 	},
 	mdns,
 	multiaddr::Protocol,
-	ping,
+	ping, request_response,
 	swarm::{
 		dial_opts::{DialOpts, PeerCondition},
 		ConnectionError, SwarmEvent,
@@ -20,9 +20,15 @@ use libp2p::{
 	upnp, Multiaddr, PeerId, Swarm,
 };
 use rand::seq::SliceRandom;
-use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+use std::{
+	borrow::Cow,
+	collections::HashMap,
+	str::FromStr,
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
 use tokio::{
-	sync::oneshot,
+	sync::{broadcast, oneshot},
 	time::{interval_at, Instant, Interval},
 };
 use tracing::{debug, error, info, trace, warn};
@@ -30,12 +36,16 @@ use tracing::{debug, error, info, trace, warn};
 use crate::{
 	shutdown::Controller,
 	telemetry::{MetricCounter, MetricValue, Metrics},
-	types::{AgentVersion, IdentifyConfig, KademliaMode, LibP2PConfig, TimeToLive},
+	types::{
+		AgentVersion, IdentifyConfig, KademliaMode, LibP2PConfig, TimeToLive,
+		IDENTITY_AGENT_FAT_CLIENT_TYPE,
+	},
 };
 
 use super::{
-	build_swarm, client::BlockStat, Behaviour, BehaviourEvent, CommandReceiver, EventLoopEntries,
-	QueryChannel, SendableCommand,
+	build_swarm, client::BlockStat, peer_store::PeerStore, record::Reference, Behaviour,
+	BehaviourEvent, CommandReceiver, EventLoopEntries, HeaderGossip, PeerEvent, QueryChannel,
+	RecordExchange, RecordResponse, SendableCommand,
 };
 
 // RelayState keeps track of all things relay related
@@ -75,6 +85,82 @@ struct BootstrapState {
 	is_startup_done: bool,
 	// timer that is responsible for firing periodic bootstraps
 	timer: Interval,
+	// configured bootstrap nodes with DNS addresses, which are re-added to the routing table
+	// after failed dials, so the addresses are resolved again on the next dial
+	dns_nodes: Vec<(PeerId, Multiaddr)>,
+}
+
+/// Checks if the address has to be resolved using DNS
+pub(crate) fn is_dns_address(address: &Multiaddr) -> bool {
+	matches!(
+		address.iter().next(),
+		Some(Protocol::Dns(_) | Protocol::Dns4(_) | Protocol::Dns6(_) | Protocol::Dnsaddr(_))
+	)
+}
+
+// PinnedPeersState keeps track of the peers which are always kept connected
+struct PinnedPeersState {
+	// configured peers and their addresses
+	peers: HashMap<PeerId, Vec<Multiaddr>>,
+	// timer that is responsible for redialing disconnected peers
+	timer: Interval,
+}
+
+/// Interval in which disconnected pinned peers are redialed
+const PINNED_PEERS_DIAL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Adds peer addresses to the routing table and dials the peer if it is not connected
+fn keep_connected(swarm: &mut Swarm<Behaviour>, peer_id: &PeerId, addresses: &[Multiaddr]) {
+	let kademlia = &mut swarm.behaviour_mut().kademlia;
+	for address in addresses {
+		_ = kademlia.add_address(peer_id, address.clone());
+	}
+	if swarm.is_connected(peer_id) {
+		return;
+	}
+	let opts = DialOpts::peer_id(*peer_id)
+		.addresses(addresses.to_vec())
+		.condition(PeerCondition::Disconnected)
+		.build();
+	if let Err(error) = swarm.dial(opts) {
+		trace!("Dialing {peer_id} failed: {error}");
+	}
+}
+
+/// Maximum number of tracked fat clients
+const MAX_FAT_CLIENTS: usize = 64;
+
+/// Number of header gossip messages buffered for the slow subscribers
+const HEADER_GOSSIP_CAPACITY: usize = 100;
+
+/// Number of peer events buffered for the slow subscribers
+const PEER_EVENTS_CAPACITY: usize = 100;
+
+/// Interval in which wall clock is compared to the monotonic clock, to detect system suspend
+const CLOCK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+// ClockState keeps track of wall and monotonic clocks,
+// used to detect that the system was suspended
+struct ClockState {
+	// timer that is responsible for firing clock checks
+	timer: Interval,
+	// monotonic time of the last check
+	instant: std::time::Instant,
+	// wall clock time of the last check
+	wall: SystemTime,
+	// tolerated difference between elapsed wall and monotonic time
+	skew_tolerance: Duration,
+}
+
+/// Returns duration of the system suspend, if wall clock advanced more than the monotonic clock
+/// (which is not advanced on suspend on some platforms), beyond the skew tolerance
+fn suspended_for(
+	monotonic: Duration,
+	wall: Duration,
+	skew_tolerance: Duration,
+) -> Option<Duration> {
+	let suspended = wall.saturating_sub(monotonic);
+	(suspended > skew_tolerance).then_some(suspended)
 }
 
 struct EventLoopConfig {
@@ -100,35 +186,24 @@ pub struct EventLoop {
 	pending_swarm_events: HashMap<PeerId, oneshot::Sender<Result<ConnectionEstablishedInfo>>>,
 	relay: RelayState,
 	bootstrap: BootstrapState,
+	pinned: PinnedPeersState,
+	clock: ClockState,
 	/// Blocks we monitor for PUT success rate
 	active_blocks: HashMap<u32, BlockStat>,
+	/// Fat clients learned over identify, with their listen addresses
+	fat_clients: HashMap<PeerId, Vec<Multiaddr>>,
+	/// GET requests sent directly to the fat clients
+	pending_record_requests: HashMap<request_response::OutboundRequestId, RecordExchange>,
+	/// Header messages received over gossipsub
+	header_gossip: broadcast::Sender<HeaderGossip>,
+	/// Connection events of the remote peers
+	peer_events: broadcast::Sender<PeerEvent>,
+	/// Peers to which the node was successfully connected, with their addresses
+	peer_store: PeerStore,
 	shutdown: Controller<String>,
 	event_loop_config: EventLoopConfig,
 }
 
-#[derive(PartialEq, Debug)]
-enum DHTKey {
-	Cell(u32, u32, u32),
-	Row(u32, u32),
-}
-
-impl TryFrom<RecordKey> for DHTKey {
-	type Error = color_eyre::Report;
-
-	fn try_from(key: RecordKey) -> std::result::Result<Self, Self::Error> {
-		match *String::from_utf8(key.to_vec())?
-			.split(':')
-			.map(str::parse::<u32>)
-			.collect::<std::result::Result<Vec<_>, _>>()?
-			.as_slice()
-		{
-			[block_num, row_num] => Ok(DHTKey::Row(block_num, row_num)),
-			[block_num, row_num, col_num] => Ok(DHTKey::Cell(block_num, row_num, col_num)),
-			_ => Err(eyre!("Invalid DHT key")),
-		}
-	}
-}
-
 #[cfg(not(feature = "kademlia-rocksdb"))]
 type Store = super::kad_mem_store::MemoryStore;
 #[cfg(feature = "kademlia-rocksdb")]
@@ -169,8 +244,38 @@ impl EventLoop {
 			bootstrap: BootstrapState {
 				is_startup_done: false,
 				timer: interval_at(Instant::now() + bootstrap_interval, bootstrap_interval),
+				dns_nodes: cfg
+					.bootstraps
+					.iter()
+					.filter(|(_, address)| is_dns_address(address))
+					.cloned()
+					.collect(),
+			},
+			pinned: PinnedPeersState {
+				peers: cfg.pinned_peers.iter().fold(
+					HashMap::new(),
+					|mut peers, (peer_id, address)| {
+						peers
+							.entry(*peer_id)
+							.or_insert_with(Vec::new)
+							.push(address.clone());
+						peers
+					},
+				),
+				timer: tokio::time::interval(PINNED_PEERS_DIAL_INTERVAL),
+			},
+			clock: ClockState {
+				timer: interval_at(Instant::now() + CLOCK_CHECK_INTERVAL, CLOCK_CHECK_INTERVAL),
+				instant: std::time::Instant::now(),
+				wall: SystemTime::now(),
+				skew_tolerance: cfg.kademlia.clock_skew_tolerance,
 			},
 			active_blocks: Default::default(),
+			fat_clients: Default::default(),
+			pending_record_requests: Default::default(),
+			header_gossip: broadcast::channel(HEADER_GOSSIP_CAPACITY).0,
+			peer_events: broadcast::channel(PEER_EVENTS_CAPACITY).0,
+			peer_store: PeerStore::new(cfg.peer_store_size),
 			shutdown,
 			event_loop_config: EventLoopConfig {
 				identity_data: cfg.identify,
@@ -180,7 +285,7 @@ impl EventLoop {
 		}
 	}
 
-	pub async fn run(mut self, metrics: Arc<impl Metrics>, mut command_receiver: CommandReceiver) {
+	pub async fn run(mut self, metrics: Arc<impl Metrics>, command_receiver: &mut CommandReceiver) {
 		// shutdown will wait as long as this token is not dropped
 		let _delay_token = self
 			.shutdown
@@ -199,6 +304,8 @@ impl EventLoop {
 					},
 				},
 				_ = self.bootstrap.timer.tick() => self.handle_periodic_bootstraps(),
+				_ = self.pinned.timer.tick() => self.connect_pinned_peers(),
+				_ = self.clock.timer.tick() => self.handle_clock_check(),
 				// if the shutdown was triggered,
 				// break the loop immediately, proceed to the cleanup phase
 				_ = self.shutdown.triggered_shutdown() => {
@@ -277,20 +384,54 @@ impl EventLoop {
 					} => match result {
 						QueryResult::GetRecord(result) => match result {
 							Ok(GetRecordOk::FoundRecord(record)) => {
-								if let Some(QueryChannel::GetRecord(ch)) =
-									self.pending_kad_queries.remove(&id)
-								{
-									_ = ch.send(Ok(record));
+								match self.pending_kad_queries.remove(&id) {
+									Some(QueryChannel::GetRecord(ch)) => {
+										_ = ch.send(Ok(record));
+									},
+									Some(QueryChannel::ProbeRecord {
+										quorum,
+										mut peers,
+										sender,
+									}) => {
+										peers.extend(record.peer);
+										if peers.len() < quorum {
+											self.pending_kad_queries.insert(
+												id,
+												QueryChannel::ProbeRecord {
+													quorum,
+													peers,
+													sender,
+												},
+											);
+											return;
+										}
+										if let Some(mut query) =
+											self.swarm.behaviour_mut().kademlia.query_mut(&id)
+										{
+											query.finish();
+										}
+										_ = sender.send(Ok(peers.len()));
+									},
+									_ => (),
 								}
 							},
-							Err(err) => {
-								if let Some(QueryChannel::GetRecord(ch)) =
+							Ok(GetRecordOk::FinishedWithNoAdditionalRecord { .. }) => {
+								if let Some(QueryChannel::ProbeRecord { peers, sender, .. }) =
 									self.pending_kad_queries.remove(&id)
 								{
-									_ = ch.send(Err(err.into()));
+									_ = sender.send(Ok(peers.len()));
 								}
 							},
-							_ => (),
+							Err(err) => match self.pending_kad_queries.remove(&id) {
+								Some(QueryChannel::GetRecord(ch)) => {
+									_ = ch.send(Err(err.into()));
+								},
+								// Peers which responded before the error are still counted
+								Some(QueryChannel::ProbeRecord { peers, sender, .. }) => {
+									_ = sender.send(Ok(peers.len()));
+								},
+								_ => (),
+							},
 						},
 						QueryResult::PutRecord(Err(error)) => {
 							if self.pending_kad_queries.remove(&id).is_none() {
@@ -355,7 +496,7 @@ impl EventLoop {
 						},
 				} => {
 					trace!(
-						"Identity Received from: {peer_id:?} on listen address: {listen_addrs:?}"
+						"Identity Received from: {peer_id:?} ({agent_version}) on listen address: {listen_addrs:?}"
 					);
 
 					let incoming_peer_agent_version = match AgentVersion::from_str(&agent_version) {
@@ -371,7 +512,7 @@ impl EventLoop {
 							"Unsupported release version: {}",
 							incoming_peer_agent_version.release_version
 						);
-						self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+						self.remove_peer(&peer_id);
 						return;
 					}
 
@@ -380,7 +521,14 @@ impl EventLoop {
 						if incoming_peer_agent_version.kademlia_mode
 							== KademliaMode::Server.to_string()
 						{
+							if incoming_peer_agent_version.client_type
+								== IDENTITY_AGENT_FAT_CLIENT_TYPE
+							{
+								self.add_fat_client(peer_id, listen_addrs.clone());
+							}
 							trace!("Adding peer {peer_id} to routing table.");
+							self.peer_store
+								.record_addresses(&peer_id, listen_addrs.clone());
 							for addr in listen_addrs {
 								self.swarm
 									.behaviour_mut()
@@ -391,8 +539,10 @@ impl EventLoop {
 					} else {
 						// Block and remove non-Avail peers
 						debug!("Removing and blocking non-avail peer from routing table. Peer: {peer_id}. Agent: {agent_version}. Protocol: {protocol_version}");
-						self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
-						self.swarm.behaviour_mut().blocked_peers.block_peer(peer_id);
+						self.remove_peer(&peer_id);
+						if !self.pinned.peers.contains_key(&peer_id) {
+							self.swarm.behaviour_mut().blocked_peers.block_peer(peer_id);
+						}
 					}
 				},
 				identify::Event::Sent { peer_id } => {
@@ -488,6 +638,77 @@ impl EventLoop {
 					trace!("[UPnP] Gateway address expired: {addr}");
 				},
 			},
+			SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+				message,
+				..
+			})) => {
+				// messages are signed, so the source is always known
+				if let Some(source) = message.source {
+					// header gossip may have no subscribers
+					_ = self.header_gossip.send((source, message.data));
+				}
+			},
+			SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(event)) => {
+				trace!("Gossipsub event: {event:?}");
+			},
+			SwarmEvent::Behaviour(BehaviourEvent::RecordExchange(event)) => match event {
+				request_response::Event::Message { peer, message } => match message {
+					request_response::Message::Request {
+						request, channel, ..
+					} => {
+						let key = RecordKey::from(request.key);
+						let value = self
+							.swarm
+							.behaviour_mut()
+							.kademlia
+							.store_mut()
+							.get(&key)
+							.map(|record| record.value.clone());
+						let response = RecordResponse { value };
+						if self
+							.swarm
+							.behaviour_mut()
+							.record_exchange
+							.send_response(channel, response)
+							.is_err()
+						{
+							trace!("Cannot respond to record request from {peer}");
+						}
+					},
+					request_response::Message::Response {
+						request_id,
+						response,
+					} => {
+						let Some((key, sender)) = self.pending_record_requests.remove(&request_id)
+						else {
+							return;
+						};
+						match response.value {
+							Some(value) => {
+								let record = Record::new(key, value);
+								_ = sender.send(Ok(PeerRecord {
+									peer: Some(peer),
+									record,
+								}));
+							},
+							None => self.get_record_from_dht(key, sender),
+						}
+					},
+				},
+				request_response::Event::OutboundFailure {
+					peer,
+					request_id,
+					error,
+				} => {
+					trace!("Record request to {peer} failed: {error}");
+					if let Some((key, sender)) = self.pending_record_requests.remove(&request_id) {
+						self.get_record_from_dht(key, sender);
+					}
+				},
+				event => {
+					trace!("Record exchange event: {event:?}");
+				},
+			},
 			swarm_event => {
 				match swarm_event {
 					SwarmEvent::NewListenAddr { address, .. } => {
@@ -504,7 +725,16 @@ impl EventLoop {
 
 						if let Some(ConnectionError::IO(_)) = cause {
 							// remove peer with failed connection
-							self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+							self.remove_peer(&peer_id);
+						}
+
+						if num_established == 0 {
+							// peer events may have no subscribers
+							_ = self.peer_events.send(PeerEvent::Disconnected(peer_id));
+							if let Some(addresses) = self.pinned.peers.get(&peer_id) {
+								debug!("Pinned peer {peer_id} disconnected, redialing");
+								keep_connected(&mut self.swarm, &peer_id, addresses);
+							}
 						}
 					},
 					SwarmEvent::IncomingConnection { .. } => {
@@ -527,7 +757,14 @@ impl EventLoop {
 						..
 					} => {
 						metrics.count(MetricCounter::EstablishedConnections).await;
-						endpoint.get_remote_address();
+						if num_established.get() == 1 {
+							_ = self.peer_events.send(PeerEvent::Connected(peer_id));
+						}
+						// remote address of the incoming connection is not a listen address
+						let dialed_address = endpoint
+							.is_dialer()
+							.then(|| endpoint.get_remote_address().clone());
+						self.peer_store.record_success(peer_id, dialed_address);
 						// Notify the connections we're waiting on that we've connected successfully
 						if let Some(ch) = self.pending_swarm_events.remove(&peer_id) {
 							_ = ch.send(Ok(ConnectionEstablishedInfo {
@@ -543,14 +780,19 @@ impl EventLoop {
 						metrics.count(MetricCounter::OutgoingConnectionErrors).await;
 
 						if let Some(peer_id) = peer_id {
+							// unreachable fat client is tracked again once identified
+							self.fat_clients.remove(&peer_id);
+							if !matches!(
+								error,
+								libp2p::swarm::DialError::DialPeerConditionFalse(_)
+									| libp2p::swarm::DialError::Aborted
+							) {
+								self.peer_store.record_failure(&peer_id);
+							}
+
 							// Notify the connections we're waiting on an error has occurred
 							if let libp2p::swarm::DialError::WrongPeerId { .. } = &error {
-								if let Some(peer) =
-									self.swarm.behaviour_mut().kademlia.remove_peer(&peer_id)
-								{
-									let removed_peer_id = peer.node.key.preimage();
-									debug!("Removed peer {removed_peer_id} from the routing table");
-								}
+								self.remove_peer(&peer_id);
 							}
 							if let Some(ch) = self.pending_swarm_events.remove(&peer_id) {
 								_ = ch.send(Err(error.into()));
@@ -562,6 +804,10 @@ impl EventLoop {
 							if self.relay.id == peer_id {
 								self.relay.reset();
 							}
+
+							// failed address is removed from the routing table by Kademlia,
+							// so DNS address of the bootstrap node is added back to be resolved again
+							self.add_dns_bootstrap_addresses(Some(peer_id));
 						}
 					},
 					SwarmEvent::Dialing {
@@ -582,6 +828,11 @@ impl EventLoop {
 			&mut self.pending_kad_queries,
 			&mut self.pending_swarm_events,
 			&mut self.active_blocks,
+			&self.header_gossip,
+			&self.peer_events,
+			&mut self.peer_store,
+			&self.fat_clients,
+			&mut self.pending_record_requests,
 		)) {
 			command.abort(eyre!(err));
 		}
@@ -591,10 +842,137 @@ impl EventLoop {
 		// commence with periodic bootstraps,
 		// only when the initial startup bootstrap is done
 		if self.bootstrap.is_startup_done {
+			self.add_dns_bootstrap_addresses(None);
+			self.connect_fat_clients();
 			_ = self.swarm.behaviour_mut().kademlia.bootstrap();
 		}
 	}
 
+	/// Falls back to the DHT query, if fat client doesn't have the record or fails to respond
+	fn get_record_from_dht(&mut self, key: RecordKey, sender: oneshot::Sender<Result<PeerRecord>>) {
+		if sender.is_closed() {
+			return;
+		}
+		let query_id = self.swarm.behaviour_mut().kademlia.get_record(key);
+		self.pending_kad_queries
+			.insert(query_id, QueryChannel::GetRecord(sender));
+	}
+
+	fn add_fat_client(&mut self, peer_id: PeerId, addresses: Vec<Multiaddr>) {
+		if addresses.is_empty() {
+			return;
+		}
+		if !self.fat_clients.contains_key(&peer_id) && self.fat_clients.len() >= MAX_FAT_CLIENTS {
+			trace!("Not tracking fat client {peer_id}, limit reached");
+			return;
+		}
+		if self.fat_clients.insert(peer_id, addresses).is_none() {
+			debug!("Discovered fat client {peer_id}");
+		}
+	}
+
+	/// Keeps known fat clients in the routing table and connected, since they are
+	/// serving large part of the DHT records, so cells are requested from them first
+	fn connect_fat_clients(&mut self) {
+		for (peer_id, addresses) in &self.fat_clients {
+			keep_connected(&mut self.swarm, peer_id, addresses);
+		}
+	}
+
+	/// Dials disconnected pinned peers, and adds their addresses back to the routing table,
+	/// since Kademlia removes addresses which failed to dial
+	fn connect_pinned_peers(&mut self) {
+		for (peer_id, addresses) in &self.pinned.peers {
+			keep_connected(&mut self.swarm, peer_id, addresses);
+		}
+	}
+
+	/// Removes peer from the routing table, unless peer is pinned
+	fn remove_peer(&mut self, peer_id: &PeerId) {
+		if self.pinned.peers.contains_key(peer_id) {
+			debug!("Keeping pinned peer {peer_id} in the routing table");
+			return;
+		}
+		if let Some(peer) = self.swarm.behaviour_mut().kademlia.remove_peer(peer_id) {
+			let removed_peer_id = peer.node.key.preimage();
+			debug!("Removed peer {removed_peer_id} from the routing table");
+		}
+	}
+
+	/// Adds DNS addresses of the given or all bootstrap nodes to the routing table
+	fn add_dns_bootstrap_addresses(&mut self, peer_id: Option<PeerId>) {
+		let nodes = self
+			.bootstrap
+			.dns_nodes
+			.iter()
+			.filter(|(id, _)| peer_id.is_none_or(|peer_id| peer_id == *id));
+		for (id, address) in nodes {
+			trace!("Adding DNS address {address} of bootstrap node {id} to the routing table");
+			_ = self
+				.swarm
+				.behaviour_mut()
+				.kademlia
+				.add_address(id, address.clone());
+		}
+	}
+
+	fn handle_clock_check(&mut self) {
+		let instant = std::time::Instant::now();
+		let wall = SystemTime::now();
+		let monotonic_elapsed = instant.duration_since(self.clock.instant);
+		let wall_elapsed = wall.duration_since(self.clock.wall).unwrap_or_default();
+		self.clock.instant = instant;
+		self.clock.wall = wall;
+
+		let Some(suspended) =
+			suspended_for(monotonic_elapsed, wall_elapsed, self.clock.skew_tolerance)
+		else {
+			return;
+		};
+		info!("Resumed after being suspended for {suspended:?}, recomputing record expirations");
+		self.recompute_record_expirations(suspended);
+	}
+
+	/// Shortens expirations of stored records for the suspend duration,
+	/// and re-publishes records which expired while suspended.
+	fn recompute_record_expirations(&mut self, suspended: Duration) {
+		let now = std::time::Instant::now();
+		let ttl = self.event_loop_config.kad_record_ttl.expires();
+		let kademlia = &mut self.swarm.behaviour_mut().kademlia;
+		let records = kademlia
+			.store_mut()
+			.records()
+			.map(Cow::into_owned)
+			.collect::<Vec<_>>();
+
+		// RocksDB store expirations are based on the wall clock, so they are already correct
+		let shift = match cfg!(feature = "kademlia-rocksdb") {
+			true => Duration::ZERO,
+			false => suspended,
+		};
+
+		let mut republished = 0;
+		for mut record in records {
+			let Some(expires) = record.expires else {
+				continue;
+			};
+			let expires = expires.checked_sub(shift).unwrap_or(now);
+			if expires > now {
+				if !shift.is_zero() {
+					record.expires = Some(expires);
+					_ = kademlia.store_mut().put(record);
+				}
+				continue;
+			}
+			record.expires = ttl;
+			match kademlia.put_record(record, kad::Quorum::One) {
+				Ok(_) => republished += 1,
+				Err(error) => debug!("Cannot re-publish expired record: {error:?}"),
+			}
+		}
+		info!("Re-published {republished} records expired while suspended");
+	}
+
 	fn establish_relay_circuit(&mut self, peer_id: PeerId) {
 		// before we try and create a circuit with the relay
 		// we have to exchange observed addresses
@@ -651,11 +1029,10 @@ impl EventLoop {
 		is_error: bool,
 		metrics: Arc<impl Metrics>,
 	) {
-		let block_num = match key.clone().try_into() {
-			Ok(DHTKey::Cell(block_num, _, _)) => block_num,
-			Ok(DHTKey::Row(block_num, _)) => block_num,
+		let block_num = match Reference::decode_key(&key) {
+			Ok(reference) => reference.block_number(),
 			Err(error) => {
-				warn!("Unable to cast Kademlia key to DHT key: {error}");
+				warn!("Unable to decode Kademlia key: {error}");
 				return;
 			},
 		};
@@ -702,22 +1079,29 @@ impl EventLoop {
 
 #[cfg(test)]
 mod tests {
-	use crate::network::p2p::event_loop::DHTKey;
-	use color_eyre::Result;
-	use libp2p::kad::RecordKey;
+	use crate::network::p2p::event_loop::{is_dns_address, suspended_for};
+	use libp2p::Multiaddr;
+	use std::time::Duration;
 
 	#[test]
-	fn dht_key_parse_record_key() {
-		let row_key: DHTKey = RecordKey::new(&"1:2").try_into().unwrap();
-		assert_eq!(row_key, DHTKey::Row(1, 2));
-
-		let cell_key: DHTKey = RecordKey::new(&"3:2:1").try_into().unwrap();
-		assert_eq!(cell_key, DHTKey::Cell(3, 2, 1));
-
-		let result: Result<DHTKey> = RecordKey::new(&"1:2:4:3").try_into();
-		_ = result.unwrap_err();
+	fn detect_suspend() {
+		let secs = Duration::from_secs;
+		assert_eq!(suspended_for(secs(30), secs(30), secs(60)), None);
+		assert_eq!(suspended_for(secs(30), secs(80), secs(60)), None);
+		assert_eq!(suspended_for(secs(30), secs(20), secs(60)), None);
+		assert_eq!(
+			suspended_for(secs(30), secs(3630), secs(60)),
+			Some(secs(3600))
+		);
+	}
 
-		let result: Result<DHTKey> = RecordKey::new(&"123").try_into();
-		_ = result.unwrap_err();
+	#[test]
+	fn dns_address() {
+		let address = |value: &str| value.parse::<Multiaddr>().unwrap();
+		assert!(is_dns_address(&address(
+			"/dns/bootnode.1.lightclient.turing.avail.so/tcp/37000"
+		)));
+		assert!(is_dns_address(&address("/dns4/localhost/tcp/39000")));
+		assert!(!is_dns_address(&address("/ip4/127.0.0.1/tcp/39000")));
 	}
 }
@@ -1,5 +1,6 @@
 use super::kad_mem_providers::{Providers, ProvidersConfig};
 use crate::data::KADEMLIA_STORE_CF;
+use crate::utils::unix_time;
 use codec::{Decode, Encode};
 use libp2p::identity::PeerId;
 use libp2p::kad::store::{Error, RecordStore, Result};
@@ -18,18 +19,35 @@ use {rocksdb::WriteBatch, tracing::info};
 #[derive(Serialize, Deserialize, Encode, Decode, Clone)]
 pub struct Entry(pub Vec<u8>, pub KadRecord);
 
+/// Stored record, with expiration as Unix timestamp in seconds (0 means "does not expire").
+/// Wall clock is used, since `Instant` doesn't survive restarts and is not advanced on suspend on some platforms.
 #[derive(Serialize, Deserialize, Encode, Decode, Clone)]
 pub struct KadRecord {
+	value: Vec<u8>,
+	publisher: Vec<u8>,
+	expires_at: u64,
+}
+
+/// Record format with TTL relative to the time of the write, kept for decoding existing records
+#[derive(Encode, Decode)]
+struct LegacyKadRecord {
 	value: Vec<u8>,
 	publisher: Vec<u8>,
 	ttl: u32,
 }
 
-// 1 is minimum value if `expires` is set because 0 means "does not expire"
-fn ttl(expires: Instant) -> u32 {
-	(expires - Instant::now())
-		.max(Duration::from_secs(1))
-		.as_secs() as u32
+// 1 second is minimum remaining time if `expires` is set, since 0 means "does not expire"
+fn expires_at(expires: Instant) -> u64 {
+	let remaining = expires
+		.saturating_duration_since(Instant::now())
+		.max(Duration::from_secs(1));
+	(unix_time() + remaining).as_secs()
+}
+
+/// Converts wall clock expiration into the `Instant`, extending it by clock skew tolerance
+fn expires(expires_at: u64, clock_skew: Duration) -> Instant {
+	let remaining = (Duration::from_secs(expires_at) + clock_skew).saturating_sub(unix_time());
+	Instant::now() + remaining
 }
 
 impl From<kad::Record> for Entry {
@@ -39,23 +57,22 @@ impl From<kad::Record> for Entry {
 			KadRecord {
 				value: record.value,
 				publisher: record.publisher.map(PeerId::to_bytes).unwrap_or_default(),
-				ttl: record.expires.map(ttl).unwrap_or(0),
+				expires_at: record.expires.map(expires_at).unwrap_or(0),
 			},
 		)
 	}
 }
 
-impl From<Entry> for kad::Record {
-	fn from(entry: Entry) -> Self {
-		let Entry(key, record) = entry;
+impl Entry {
+	fn into_record(self, clock_skew: Duration) -> kad::Record {
+		let Entry(key, record) = self;
 
 		kad::Record {
 			key: RecordKey::from(key),
 			value: record.value,
 			publisher: (!record.publisher.is_empty())
 				.then(|| PeerId::from_bytes(&record.publisher).expect("Invalid peer ID")),
-			expires: (record.ttl > 0)
-				.then(|| Instant::now() + Duration::from_secs(record.ttl.into())),
+			expires: (record.expires_at > 0).then(|| expires(record.expires_at, clock_skew)),
 		}
 	}
 }
@@ -80,6 +97,8 @@ pub struct RocksDBStoreConfig {
 	/// The maximum size of record values, in bytes.
 	pub max_value_bytes: usize,
 	pub providers: ProvidersConfig,
+	/// Tolerance added to the stored record expiration, to account for wall clock adjustments.
+	pub clock_skew_tolerance: Duration,
 }
 
 impl Default for RocksDBStoreConfig {
@@ -88,6 +107,7 @@ impl Default for RocksDBStoreConfig {
 		Self {
 			max_value_bytes: 65 * 1024,
 			providers: Default::default(),
+			clock_skew_tolerance: Duration::from_secs(60),
 		}
 	}
 }
@@ -145,11 +165,21 @@ impl RocksDBStore {
 	}
 }
 
-pub fn into_kad_record(record: (Vec<u8>, Vec<u8>)) -> kad::Record {
+pub fn into_kad_record(record: (Vec<u8>, Vec<u8>), clock_skew: Duration) -> kad::Record {
 	let (key, value) = record;
-	KadRecord::decode(&mut &value[..])
-		.map(|record| Entry(key, record).into())
-		.expect("Expected valid encoded record, got invalid")
+	let record = KadRecord::decode(&mut &value[..]).unwrap_or_else(|_| {
+		let legacy = LegacyKadRecord::decode(&mut &value[..])
+			.expect("Expected valid encoded record, got invalid");
+		KadRecord {
+			value: legacy.value,
+			publisher: legacy.publisher,
+			expires_at: match legacy.ttl {
+				0 => 0,
+				ttl => (unix_time() + Duration::from_secs(ttl.into())).as_secs(),
+			},
+		}
+	});
+	Entry(key, record).into_record(clock_skew)
 }
 
 // NOTE: We are using `Error::ValueTooLarge` as default error for the RocksDB store
@@ -169,7 +199,7 @@ impl RecordStore for RocksDBStore {
 		match self.records.get_cf(&self.get_cf()?, key) {
 			Ok(record) => record
 				.map(|value| (key.to_vec(), value))
-				.map(into_kad_record)
+				.map(|record| into_kad_record(record, self.config.clock_skew_tolerance))
 				.map(Cow::Owned),
 			Err(error) => {
 				error!("Failed to get record from database: {error}");
@@ -223,7 +253,7 @@ impl RecordStore for RocksDBStore {
 					result.ok()
 				})
 				.map(|(key, value)| (key.to_vec(), value.to_vec()))
-				.map(into_kad_record)
+				.map(|record| into_kad_record(record, self.config.clock_skew_tolerance))
 				.map(Cow::Owned),
 		)
 	}
@@ -254,7 +284,10 @@ mod ttl {
 		compaction_filter_factory::{CompactionFilterContext, CompactionFilterFactory},
 		CompactionDecision,
 	};
-	use std::{ffi::CString, time::Instant};
+	use std::{
+		ffi::CString,
+		time::{Duration, Instant},
+	};
 
 	pub struct ExpirationCompactionFilter {
 		now: Instant,
@@ -263,7 +296,8 @@ mod ttl {
 
 	impl CompactionFilter for ExpirationCompactionFilter {
 		fn filter(&mut self, _level: u32, key: &[u8], value: &[u8]) -> CompactionDecision {
-			let record = into_kad_record((key.to_vec(), value.to_vec()));
+			// Records are removed on compaction without skew tolerance
+			let record = into_kad_record((key.to_vec(), value.to_vec()), Duration::ZERO);
 			match record.is_expired(self.now) {
 				true => CompactionDecision::Remove,
 				false => CompactionDecision::Keep,
@@ -305,3 +339,42 @@ mod ttl {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{into_kad_record, Entry, LegacyKadRecord};
+	use codec::Encode;
+	use libp2p::kad::{Record, RecordKey};
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn record_expiration() {
+		let record = Record {
+			key: RecordKey::new(&"1:2:3"),
+			value: vec![1, 2, 3],
+			publisher: None,
+			expires: Some(Instant::now() + Duration::from_secs(100)),
+		};
+		let Entry(key, stored) = record.clone().into();
+		let decoded = into_kad_record((key.clone(), stored.encode()), Duration::ZERO);
+		assert_eq!(decoded.value, record.value);
+		let expires = decoded.expires.unwrap();
+		assert!(expires > Instant::now() + Duration::from_secs(98));
+		assert!(expires <= Instant::now() + Duration::from_secs(101));
+
+		let decoded = into_kad_record((key, stored.encode()), Duration::from_secs(60));
+		assert!(decoded.expires.unwrap() > Instant::now() + Duration::from_secs(158));
+	}
+
+	#[test]
+	fn legacy_record() {
+		let legacy = LegacyKadRecord {
+			value: vec![1, 2, 3],
+			publisher: vec![],
+			ttl: 0,
+		};
+		let decoded = into_kad_record((b"1:2".to_vec(), legacy.encode()), Duration::ZERO);
+		assert_eq!(decoded.value, vec![1, 2, 3]);
+		assert!(decoded.expires.is_none());
+	}
+}
@@ -0,0 +1,291 @@
+//! Address book of the peers to which the node was successfully connected.
+//!
+//! Known peers are persisted periodically and restored on startup, so the routing table is populated
+//! and the peers are dialed without waiting for the DHT bootstrap to discover them.
+
+use codec::{Decode, Encode};
+use color_eyre::Result;
+use libp2p::{Multiaddr, PeerId};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, str::FromStr, time::Duration};
+use tracing::{debug, error, info};
+
+use super::Client;
+use crate::{
+	data::{Database, Key},
+	utils::unix_time,
+};
+
+/// Maximum number of the stored addresses of a single peer
+const MAX_PEER_ADDRESSES: usize = 8;
+
+/// Number of failed dials since the last successful connection, after which the peer is forgotten
+const MAX_PEER_FAILURES: u32 = 5;
+
+/// Number of the known peers dialed on startup
+pub const KNOWN_PEERS_DIAL_LIMIT: usize = 20;
+
+/// Interval in which the known peers are persisted
+const PERSIST_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Peer to which the node was successfully connected, in the persisted format
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Decode, Encode)]
+pub struct KnownPeer {
+	pub peer_id: String,
+	pub addresses: Vec<String>,
+	/// Unix timestamp in seconds of the last successful connection
+	pub last_seen: u64,
+	/// Number of successful connections
+	pub successes: u32,
+	/// Number of failed dials since the last successful connection
+	pub failures: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PeerEntry {
+	addresses: Vec<Multiaddr>,
+	last_seen: u64,
+	successes: u32,
+	failures: u32,
+}
+
+/// Known peers, with their addresses and connection stats, limited to the given number of peers
+#[derive(Debug, Default)]
+pub struct PeerStore {
+	peers: HashMap<PeerId, PeerEntry>,
+	limit: usize,
+}
+
+impl PeerStore {
+	pub fn new(limit: usize) -> Self {
+		Self {
+			peers: HashMap::new(),
+			limit,
+		}
+	}
+
+	/// Records successful connection to the peer, with the dialed address if the connection was outgoing
+	pub fn record_success(&mut self, peer_id: PeerId, address: Option<Multiaddr>) {
+		if self.limit == 0 {
+			return;
+		}
+		let entry = self.peers.entry(peer_id).or_insert_with(|| PeerEntry {
+			addresses: vec![],
+			last_seen: 0,
+			successes: 0,
+			failures: 0,
+		});
+		entry.last_seen = unix_time().as_secs();
+		entry.successes = entry.successes.saturating_add(1);
+		entry.failures = 0;
+		if let Some(address) = address {
+			add_addresses(&mut entry.addresses, vec![address]);
+		}
+		self.evict();
+	}
+
+	/// Records listen addresses of the known peer
+	pub fn record_addresses(&mut self, peer_id: &PeerId, addresses: Vec<Multiaddr>) {
+		if let Some(entry) = self.peers.get_mut(peer_id) {
+			add_addresses(&mut entry.addresses, addresses);
+		}
+	}
+
+	/// Records failed dial of the known peer, forgetting the peer after too many failures
+	pub fn record_failure(&mut self, peer_id: &PeerId) {
+		let Some(entry) = self.peers.get_mut(peer_id) else {
+			return;
+		};
+		entry.failures += 1;
+		if entry.failures >= MAX_PEER_FAILURES {
+			debug!("Forgetting known peer {peer_id} after {MAX_PEER_FAILURES} failed dials");
+			self.peers.remove(peer_id);
+		}
+	}
+
+	/// Restores persisted peers, keeping the stats of the peers which are already known
+	pub fn restore(&mut self, peers: Vec<KnownPeer>) {
+		for peer in peers {
+			let Ok(peer_id) = PeerId::from_str(&peer.peer_id) else {
+				debug!("Skipping known peer with invalid ID {}", peer.peer_id);
+				continue;
+			};
+			let addresses = peer
+				.addresses
+				.iter()
+				.filter_map(|address| Multiaddr::from_str(address).ok())
+				.collect::<Vec<_>>();
+			if addresses.is_empty() || self.peers.contains_key(&peer_id) {
+				continue;
+			}
+			self.peers.insert(
+				peer_id,
+				PeerEntry {
+					addresses,
+					last_seen: peer.last_seen,
+					successes: peer.successes,
+					failures: peer.failures,
+				},
+			);
+		}
+		self.evict();
+	}
+
+	/// Known peers with addresses, most recently seen first
+	pub fn best(&self, limit: usize) -> Vec<(PeerId, Vec<Multiaddr>)> {
+		let mut peers = self
+			.peers
+			.iter()
+			.filter(|(_, entry)| !entry.addresses.is_empty())
+			.collect::<Vec<_>>();
+		peers.sort_by_key(|(_, entry)| (std::cmp::Reverse(entry.last_seen), entry.failures));
+		peers
+			.into_iter()
+			.take(limit)
+			.map(|(peer_id, entry)| (*peer_id, entry.addresses.clone()))
+			.collect()
+	}
+
+	/// Known peers with addresses in the persisted format, most recently seen first
+	pub fn known_peers(&self) -> Vec<KnownPeer> {
+		self.best(self.limit)
+			.into_iter()
+			.map(|(peer_id, _)| {
+				let entry = &self.peers[&peer_id];
+				KnownPeer {
+					peer_id: peer_id.to_string(),
+					addresses: entry.addresses.iter().map(ToString::to_string).collect(),
+					last_seen: entry.last_seen,
+					successes: entry.successes,
+					failures: entry.failures,
+				}
+			})
+			.collect()
+	}
+
+	/// Removes the least recently seen peers over the limit
+	fn evict(&mut self) {
+		while self.peers.len() > self.limit {
+			let Some(oldest) = self
+				.peers
+				.iter()
+				.min_by_key(|(_, entry)| entry.last_seen)
+				.map(|(peer_id, _)| *peer_id)
+			else {
+				return;
+			};
+			self.peers.remove(&oldest);
+		}
+	}
+}
+
+fn add_addresses(addresses: &mut Vec<Multiaddr>, new_addresses: Vec<Multiaddr>) {
+	for address in new_addresses {
+		if !addresses.contains(&address) {
+			addresses.push(address);
+		}
+	}
+	if addresses.len() > MAX_PEER_ADDRESSES {
+		addresses.drain(..addresses.len() - MAX_PEER_ADDRESSES);
+	}
+}
+
+/// Restores persisted known peers and dials the most recently seen ones.
+/// Returns the number of dialed peers.
+pub async fn restore(client: &Client, db: &impl Database) -> Result<usize> {
+	let peers = db
+		.get::<Vec<KnownPeer>>(Key::PeerStore)?
+		.unwrap_or_default();
+	if peers.is_empty() {
+		return Ok(0);
+	}
+	let dialed = client.restore_peers(peers).await?;
+	info!("Dialed {dialed} known peers");
+	Ok(dialed)
+}
+
+/// Persists known peers periodically
+pub async fn persist(client: Client, db: impl Database) {
+	let mut interval = tokio::time::interval_at(
+		tokio::time::Instant::now() + PERSIST_INTERVAL,
+		PERSIST_INTERVAL,
+	);
+	loop {
+		interval.tick().await;
+		let peers = match client.known_peers().await {
+			Ok(peers) => peers,
+			Err(error) => {
+				debug!("Cannot get known peers: {error:#}");
+				continue;
+			},
+		};
+		// event loop may be restarted, so persisted peers are not overwritten with an empty store
+		if peers.is_empty() {
+			continue;
+		}
+		if let Err(error) = db.put(Key::PeerStore, peers) {
+			error!("Cannot persist known peers: {error:#}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{KnownPeer, PeerStore, MAX_PEER_FAILURES};
+	use libp2p::{Multiaddr, PeerId};
+
+	fn address(port: u16) -> Multiaddr {
+		format!("/ip4/10.0.0.1/tcp/{port}").parse().unwrap()
+	}
+
+	#[test]
+	fn peer_store_tracks_peers() {
+		let mut store = PeerStore::new(2);
+		let (first, second, third) = (PeerId::random(), PeerId::random(), PeerId::random());
+
+		store.record_success(first, Some(address(1)));
+		// incoming connection address is not stored, only the listen addresses
+		store.record_success(second, None);
+		store.record_addresses(&second, vec![address(2), address(2)]);
+		// failures of the unknown peers are ignored
+		store.record_failure(&third);
+
+		let known = store.known_peers();
+		assert_eq!(known.len(), 2);
+		assert!(known.iter().all(|peer| peer.addresses.len() == 1));
+
+		for _ in 0..MAX_PEER_FAILURES {
+			store.record_failure(&first);
+		}
+		assert_eq!(store.best(10), vec![(second, vec![address(2)])]);
+
+		store.record_success(first, Some(address(1)));
+		store.record_success(third, Some(address(3)));
+		assert_eq!(store.known_peers().len(), 2);
+	}
+
+	#[test]
+	fn peer_store_restores_peers() {
+		let peer_id = PeerId::random();
+		let known = |peer_id: String, last_seen| KnownPeer {
+			peer_id,
+			addresses: vec![address(1).to_string(), "invalid".to_string()],
+			last_seen,
+			successes: 3,
+			failures: 1,
+		};
+		let mut store = PeerStore::new(10);
+		store.restore(vec![
+			known(peer_id.to_string(), 10),
+			known("invalid".to_string(), 20),
+			known(PeerId::random().to_string(), 5),
+		]);
+
+		let best = store.best(1);
+		assert_eq!(best, vec![(peer_id, vec![address(1)])]);
+		let restored = store.known_peers();
+		assert_eq!(restored.len(), 2);
+		assert_eq!(restored[0].addresses, vec![address(1).to_string()]);
+		assert_eq!((restored[0].successes, restored[0].failures), (3, 1));
+	}
+}
@@ -0,0 +1,339 @@
+//! Encoding and decoding of matrix cells and rows stored as DHT records.
+//!
+//! Fat and light clients (and other implementations) are compatible as long as they agree on this format:
+//!
+//! * Cell key is the UTF-8 encoded reference, `{block_number}:{row}:{col}` in legacy format
+//!   or `avail/cell/{block_number}/{row}/{col}` in namespaced format
+//! * Row key is the UTF-8 encoded reference, `{block_number}:{row}` in legacy format
+//!   or `avail/row/{block_number}/{row}` in namespaced format
+//! * Cell value is the KZG proof followed by the cell scalar, of the sizes configured with [`CellSize`]
+//!   (80 bytes long by default: 48 bytes proof and 32 bytes scalar)
+//! * Row value is a concatenation of the 32 bytes scalars of the extended row
+//! * Record expires `ttl` seconds after it is encoded, records don't have publisher set
+//!
+//! During migration to the namespaced format, legacy references can be read (and written)
+//! along with the namespaced ones for blocks inside the configured migration window.
+
+use color_eyre::{eyre::eyre, Result};
+use kate_recovery::{
+	data::Cell,
+	matrix::{Position, RowIndex},
+};
+use libp2p::kad::{Record, RecordKey};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::cell_size::CellSize;
+
+const CELL_NAMESPACE: &str = "avail/cell/";
+const ROW_NAMESPACE: &str = "avail/row/";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferenceFormat {
+	/// `{block_number}:{row}:{col}` for cells and `{block_number}:{row}` for rows
+	#[default]
+	Legacy,
+	/// `avail/cell/{block_number}/{row}/{col}` for cells and `avail/row/{block_number}/{row}` for rows
+	Namespaced,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct ReferenceConfig {
+	/// Format of the DHT record references, `legacy` or `namespaced` (default: legacy)
+	pub dht_reference_format: ReferenceFormat,
+	/// Last block of the migration window, in which legacy references are read if record is not found
+	/// using the namespaced reference (default: None)
+	pub dht_legacy_until_block: Option<u32>,
+	/// Stores records with legacy references too, for blocks in the migration window (default: false)
+	pub dht_legacy_dual_write: bool,
+}
+
+impl ReferenceConfig {
+	fn in_migration_window(&self, block_number: u32) -> bool {
+		self.dht_reference_format != ReferenceFormat::Legacy
+			&& self
+				.dht_legacy_until_block
+				.is_some_and(|until| block_number <= until)
+	}
+
+	/// Reference formats used to read records of the given block, in order of precedence
+	pub fn read_formats(&self, block_number: u32) -> Vec<ReferenceFormat> {
+		let mut formats = vec![self.dht_reference_format];
+		if self.in_migration_window(block_number) {
+			formats.push(ReferenceFormat::Legacy);
+		}
+		formats
+	}
+
+	/// Reference formats used to write records of the given block
+	pub fn write_formats(&self, block_number: u32) -> Vec<ReferenceFormat> {
+		let mut formats = vec![self.dht_reference_format];
+		if self.dht_legacy_dual_write && self.in_migration_window(block_number) {
+			formats.push(ReferenceFormat::Legacy);
+		}
+		formats
+	}
+}
+
+/// Decoded reference of the DHT record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+	Cell {
+		block_number: u32,
+		position: Position,
+	},
+	Row {
+		block_number: u32,
+		row: u32,
+	},
+}
+
+impl Reference {
+	pub fn block_number(&self) -> u32 {
+		match self {
+			Reference::Cell { block_number, .. } => *block_number,
+			Reference::Row { block_number, .. } => *block_number,
+		}
+	}
+
+	pub fn encode(&self, format: ReferenceFormat) -> String {
+		match (format, self) {
+			(
+				ReferenceFormat::Legacy,
+				Reference::Cell {
+					block_number,
+					position,
+				},
+			) => position.reference(*block_number),
+			(ReferenceFormat::Legacy, Reference::Row { block_number, row }) => {
+				RowIndex(*row).reference(*block_number)
+			},
+			(
+				ReferenceFormat::Namespaced,
+				Reference::Cell {
+					block_number,
+					position,
+				},
+			) => format!(
+				"{CELL_NAMESPACE}{block_number}/{}/{}",
+				position.row, position.col
+			),
+			(ReferenceFormat::Namespaced, Reference::Row { block_number, row }) => {
+				format!("{ROW_NAMESPACE}{block_number}/{row}")
+			},
+		}
+	}
+
+	pub fn key(&self, format: ReferenceFormat) -> RecordKey {
+		RecordKey::from(self.encode(format).into_bytes())
+	}
+
+	/// Decodes reference in either legacy or namespaced format
+	pub fn decode(reference: &str) -> Result<Self> {
+		let invalid = || eyre!("Invalid DHT record reference {reference}");
+		let (parts, is_cell) = if let Some(cell) = reference.strip_prefix(CELL_NAMESPACE) {
+			(cell.split('/').collect::<Vec<_>>(), Some(true))
+		} else if let Some(row) = reference.strip_prefix(ROW_NAMESPACE) {
+			(row.split('/').collect::<Vec<_>>(), Some(false))
+		} else {
+			(reference.split(':').collect::<Vec<_>>(), None)
+		};
+
+		let block_number = parts
+			.first()
+			.ok_or_else(invalid)?
+			.parse()
+			.map_err(|_| invalid())?;
+		let row = parts
+			.get(1)
+			.ok_or_else(invalid)?
+			.parse()
+			.map_err(|_| invalid())?;
+		match (parts.get(2), is_cell) {
+			(None, None | Some(false)) => Ok(Reference::Row { block_number, row }),
+			(Some(col), None | Some(true)) if parts.len() == 3 => Ok(Reference::Cell {
+				block_number,
+				position: Position {
+					row,
+					col: col.parse().map_err(|_| invalid())?,
+				},
+			}),
+			_ => Err(invalid()),
+		}
+	}
+
+	pub fn decode_key(key: &RecordKey) -> Result<Self> {
+		let reference = std::str::from_utf8(key.as_ref())
+			.map_err(|_| eyre!("DHT record key is not valid UTF-8"))?;
+		Self::decode(reference)
+	}
+}
+
+fn record(format: ReferenceFormat, reference: Reference, value: Vec<u8>, ttl: u64) -> Record {
+	Record {
+		key: reference.key(format),
+		value,
+		publisher: None,
+		expires: Instant::now().checked_add(Duration::from_secs(ttl)),
+	}
+}
+
+/// Encodes cell of the given block into the DHT record which expires after `ttl` seconds
+pub fn encode_cell(format: ReferenceFormat, block_number: u32, cell: &Cell, ttl: u64) -> Record {
+	let reference = Reference::Cell {
+		block_number,
+		position: cell.position,
+	};
+	record(format, reference, cell.content.to_vec(), ttl)
+}
+
+/// Decodes cell value of the given size, fetched for the given position
+pub fn decode_cell_value(position: Position, value: &[u8], cell_size: CellSize) -> Result<Cell> {
+	cell_size.decode(position, value)
+}
+
+/// Decodes cell record of the given size into the block number and the cell
+pub fn decode_cell(record: &Record, cell_size: CellSize) -> Result<(u32, Cell)> {
+	match Reference::decode_key(&record.key)? {
+		Reference::Cell {
+			block_number,
+			position,
+		} => Ok((
+			block_number,
+			decode_cell_value(position, &record.value, cell_size)?,
+		)),
+		Reference::Row { .. } => Err(eyre!("DHT record is not a cell record")),
+	}
+}
+
+/// Encodes row of the given block into the DHT record which expires after `ttl` seconds
+pub fn encode_row(
+	format: ReferenceFormat,
+	block_number: u32,
+	row: u32,
+	data: Vec<u8>,
+	ttl: u64,
+) -> Record {
+	record(format, Reference::Row { block_number, row }, data, ttl)
+}
+
+/// Decodes row record into the block number, row index and row data
+pub fn decode_row(record: &Record) -> Result<(u32, u32, Vec<u8>)> {
+	match Reference::decode_key(&record.key)? {
+		Reference::Row { block_number, row } => Ok((block_number, row, record.value.clone())),
+		Reference::Cell { .. } => Err(eyre!("DHT record is not a row record")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::types::CELL_WITH_PROOF_SIZE;
+	use proptest::{prelude::any, proptest, strategy::Strategy};
+
+	#[test]
+	fn reference_format() {
+		let position = Position { row: 1, col: 2 };
+		let reference = Reference::Cell {
+			block_number: 3,
+			position,
+		};
+		let row = Reference::Row {
+			block_number: 3,
+			row: 1,
+		};
+		assert_eq!(reference.encode(ReferenceFormat::Legacy), "3:1:2");
+		assert_eq!(row.encode(ReferenceFormat::Legacy), "3:1");
+		assert_eq!(
+			reference.encode(ReferenceFormat::Namespaced),
+			"avail/cell/3/1/2"
+		);
+		assert_eq!(row.encode(ReferenceFormat::Namespaced), "avail/row/3/1");
+		assert_eq!(Reference::decode("avail/cell/3/1/2").unwrap(), reference);
+		assert_eq!(Reference::decode("avail/row/3/1").unwrap(), row);
+		assert!(Reference::decode("avail/row/3/1/2").is_err());
+		assert!(Reference::decode("3:1:2:4").is_err());
+		assert!(Reference::decode("3").is_err());
+		assert!(decode_cell_value(position, &[0u8; 79], CellSize::default()).is_err());
+	}
+
+	#[test]
+	fn decode_record_keys() {
+		let key = |reference: &str| RecordKey::new(&reference);
+		let decoded = Reference::decode_key(&key("1:2")).unwrap();
+		assert_eq!(
+			decoded,
+			Reference::Row {
+				block_number: 1,
+				row: 2
+			}
+		);
+		let decoded = Reference::decode_key(&key("avail/cell/3/2/1")).unwrap();
+		assert_eq!(decoded.block_number(), 3);
+		assert_eq!(
+			Reference::decode_key(&key("avail/row/4/2"))
+				.unwrap()
+				.block_number(),
+			4
+		);
+		assert!(Reference::decode_key(&key("1:2:4:3")).is_err());
+		assert!(Reference::decode_key(&key("123")).is_err());
+	}
+
+	#[test]
+	fn migration_window() {
+		let cfg = ReferenceConfig {
+			dht_reference_format: ReferenceFormat::Namespaced,
+			dht_legacy_until_block: Some(10),
+			dht_legacy_dual_write: true,
+		};
+		let both = vec![ReferenceFormat::Namespaced, ReferenceFormat::Legacy];
+		assert_eq!(cfg.read_formats(10), both);
+		assert_eq!(cfg.write_formats(10), both);
+		assert_eq!(cfg.read_formats(11), vec![ReferenceFormat::Namespaced]);
+		assert_eq!(cfg.write_formats(11), vec![ReferenceFormat::Namespaced]);
+
+		let cfg = ReferenceConfig {
+			dht_legacy_dual_write: false,
+			..cfg
+		};
+		assert_eq!(cfg.write_formats(10), vec![ReferenceFormat::Namespaced]);
+		assert_eq!(
+			ReferenceConfig::default().read_formats(1),
+			vec![ReferenceFormat::Legacy]
+		);
+	}
+
+	fn arb_format() -> impl Strategy<Value = ReferenceFormat> {
+		any::<bool>().prop_map(|namespaced| match namespaced {
+			true => ReferenceFormat::Namespaced,
+			false => ReferenceFormat::Legacy,
+		})
+	}
+
+	proptest! {
+	#[test]
+	fn cell_round_trip(format in arb_format(), block_number in any::<u32>(), row in any::<u32>(), col in any::<u16>(), content in any::<[u8; 32]>()) {
+		let mut cell = Cell { position: Position { row, col }, content: [0u8; CELL_WITH_PROOF_SIZE] };
+		cell.content[CELL_WITH_PROOF_SIZE - 32..].copy_from_slice(&content);
+		let record = encode_cell(format, block_number, &cell, 3600);
+		assert!(record.expires.is_some());
+		let (decoded_block_number, decoded) = decode_cell(&record, CellSize::default()).unwrap();
+		assert_eq!(decoded_block_number, block_number);
+		assert_eq!(decoded.position, cell.position);
+		assert_eq!(decoded.content, cell.content);
+		assert!(decode_row(&record).is_err());
+	}
+	}
+
+	proptest! {
+	#[test]
+	fn row_round_trip(format in arb_format(), block_number in any::<u32>(), row in any::<u32>(), data in any::<Vec<u8>>()) {
+		let record = encode_row(format, block_number, row, data.clone(), 3600);
+		assert_eq!(decode_row(&record).unwrap(), (block_number, row, data));
+		assert!(decode_cell(&record, CellSize::default()).is_err());
+	}
+	}
+}
@@ -1,13 +1,17 @@
 use avail_subxt::{primitives::Header, utils::H256};
 use codec::Decode;
-use color_eyre::{eyre::eyre, Result};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
 use kate_recovery::matrix::{Dimensions, Position};
-use rand::{seq::SliceRandom, thread_rng, Rng};
+use rand::{seq::index::sample, thread_rng, Rng};
 use serde::{de, Deserialize};
 use sp_core::bytes::from_hex;
 use std::{
-	collections::HashSet,
+	collections::{BTreeSet, HashSet},
 	fmt::Display,
+	ops::Range,
 	sync::{Arc, Mutex},
 };
 use tokio::{
@@ -20,19 +24,24 @@ use crate::{
 	data::Database,
 	network::rpc,
 	shutdown::Controller,
-	types::{GrandpaJustification, RetryConfig, State},
+	types::{ConnectionConfig, GrandpaJustification, KeepAliveConfig, RetryConfig, State},
 };
 
+pub mod backoff;
 mod client;
+pub mod header_mapping;
+mod polling;
 mod subscriptions;
 
-use subscriptions::SubscriptionLoop;
-const CELL_SIZE: usize = 32;
-const PROOF_SIZE: usize = 48;
-pub const CELL_WITH_PROOF_SIZE: usize = CELL_SIZE + PROOF_SIZE;
+pub use crate::types::CELL_WITH_PROOF_SIZE;
 pub use subscriptions::Event;
+pub use subscriptions::SubscriptionLoop;
 
-pub use client::Client;
+pub use client::{Client, InvalidProofResponse};
+pub use polling::{
+	finalized_block_number, http_urls, HeaderMode, HeaderPoller, HttpClient,
+	DEFAULT_MAX_RESPONSE_SIZE, DEFAULT_TIMEOUT,
+};
 
 pub enum Subscription {
 	Header(Header),
@@ -151,15 +160,22 @@ impl Nodes {
 	/// associated with the current Subxt client host.
 	/// The purpose of this exclusion is to prevent accidentally reconnecting to the same host in case of errors.
 	/// If there's a need to switch to a different host, the shuffled list provides a randomized order of available Nodes.
-	fn shuffle(&self, current_host: String) -> Vec<Node> {
+	/// Excluded host, used by the paired client in paranoid mode, is never returned.
+	fn shuffle(&self, current_host: String, excluded_host: Option<&str>) -> Vec<Node> {
+		let is_allowed = |host: &String| Some(host.as_str()) != excluded_host;
 		if self.list.len() <= 1 {
-			return self.list.clone();
+			return self
+				.list
+				.iter()
+				.filter(|&Node { host, .. }| is_allowed(host))
+				.cloned()
+				.collect();
 		}
 
 		let mut list = self
 			.list
 			.iter()
-			.filter(|&Node { host, .. }| host != &current_host)
+			.filter(|&Node { host, .. }| host != &current_host && is_allowed(host))
 			.cloned()
 			.collect::<Vec<Node>>();
 		list.shuffle(&mut thread_rng());
@@ -195,6 +211,8 @@ pub async fn init<T: Database>(
 	nodes: &[String],
 	genesis_hash: &str,
 	retry_config: RetryConfig,
+	keep_alive: KeepAliveConfig,
+	connection: ConnectionConfig,
 	shutdown: Controller<String>,
 ) -> Result<(Client, broadcast::Sender<Event>, SubscriptionLoop<T>)> {
 	let rpc_client = Client::new(
@@ -202,69 +220,180 @@ pub async fn init<T: Database>(
 		Nodes::new(nodes),
 		genesis_hash,
 		retry_config,
+		keep_alive,
+		connection,
 		shutdown,
 	)
 	.await?;
 	// create output channel for RPC Subscription Events
 	let (event_sender, _) = broadcast::channel(1000);
-	let subscriptions =
-		SubscriptionLoop::new(state, db, rpc_client.clone(), event_sender.clone()).await?;
+	let subscriptions = SubscriptionLoop::new(state, db, rpc_client.clone()).await?;
 
 	Ok((rpc_client, event_sender, subscriptions))
 }
 
-/// Generates random cell positions for sampling
+/// Creates client used to cross-check proofs in paranoid mode, connected to a node other than the given host.
+/// Once paired with the primary client (see [`Client::with_cross_check`]), clients don't reconnect to each other's node.
+pub async fn cross_check_client(
+	nodes: &[String],
+	host: &str,
+	genesis_hash: &str,
+	retry_config: RetryConfig,
+	connection: ConnectionConfig,
+	shutdown: Controller<String>,
+) -> Result<Client> {
+	let nodes = nodes
+		.iter()
+		.filter(|&node| node != host)
+		.cloned()
+		.collect::<Vec<_>>();
+	if nodes.is_empty() {
+		return Err(eyre!(
+			"Paranoid mode requires at least two configured full nodes"
+		));
+	}
+	// Separate state is used, so the connected node of the primary client is not overwritten
+	let state = Arc::new(Mutex::new(State::default()));
+	let client = Client::new(
+		state,
+		Nodes::new(&nodes),
+		genesis_hash,
+		retry_config,
+		KeepAliveConfig::default(),
+		connection,
+		shutdown,
+	)
+	.await?;
+	info!(host = client.host(), "Connected to the cross-check node");
+	Ok(client)
+}
+
+/// Generates random cell positions for sampling, without duplicates.
+/// Cell count is clamped to the size of the extended matrix.
 pub fn generate_random_cells(dimensions: Dimensions, cell_count: u32) -> Vec<Position> {
-	let max_cells = dimensions.extended_size();
-	let count = if max_cells < cell_count {
+	let cols: u16 = dimensions.cols().into();
+	let max_cells = dimensions.extended_rows() * cols as u32;
+	if max_cells < cell_count {
 		debug!("Max cells count {max_cells} is lesser than cell_count {cell_count}");
-		max_cells
-	} else {
-		cell_count
-	};
-	let mut rng = thread_rng();
-	let mut indices = HashSet::new();
-	while (indices.len() as u16) < count as u16 {
-		let col = rng.gen_range(0..dimensions.cols().into());
-		let row = rng.gen_range(0..dimensions.extended_rows());
-		indices.insert(Position { row, col });
 	}
+	let mut rng = thread_rng();
+	sample(
+		&mut rng,
+		max_cells as usize,
+		cell_count.min(max_cells) as usize,
+	)
+	.into_iter()
+	.map(|index| Position {
+		row: index as u32 / cols as u32,
+		col: (index as u32 % cols as u32) as u16,
+	})
+	.collect()
+}
 
-	indices.into_iter().collect::<Vec<_>>()
+/// Range of the band `index` out of `bands` equal bands of the given length
+fn band(length: u32, bands: u32, index: u32) -> Range<u32> {
+	index * length / bands..(index + 1) * length / bands
 }
 
-/* @note: fn to take the number of cells needs to get equal to or greater than
-the percentage of confidence mentioned in config file */
+/// Generates stratified random cell positions for sampling, without duplicates.
+/// Extended matrix rows are split into bands, one cell is sampled from each band, and
+/// columns of the sampled cells are spread across column bands as well,
+/// so withholding of the adjacent rows or columns is detected with the fewer cells.
+/// If there are more cells to sample than rows, remaining cells are sampled uniformly.
+pub fn generate_stratified_cells(dimensions: Dimensions, cell_count: u32) -> Vec<Position> {
+	let rows = dimensions.extended_rows();
+	let cols: u16 = dimensions.cols().into();
+	let count = cell_count.min(rows * cols as u32);
+	let bands = count.min(rows);
+	let col_bands = bands.min(cols as u32);
 
-pub const CELL_COUNT_99_99: u32 = 14;
+	let mut rng = thread_rng();
+	let col_band_order = sample(&mut rng, bands as usize, bands as usize);
+	let mut positions = (0..bands)
+		.zip(col_band_order)
+		.map(|(row_band, order)| {
+			let col_band = order as u32 * col_bands / bands;
+			Position {
+				row: rng.gen_range(band(rows, bands, row_band)),
+				col: rng.gen_range(band(cols as u32, col_bands, col_band)) as u16,
+			}
+		})
+		.collect::<HashSet<_>>();
+	while (positions.len() as u32) < count {
+		positions.insert(Position {
+			row: rng.gen_range(0..rows),
+			col: rng.gen_range(0..cols),
+		});
+	}
 
-/// Calculates number of cells required to achieve given confidence
-pub fn cell_count_for_confidence(confidence: f64) -> u32 {
-	let mut cell_count: u32;
-	if !(50.0..=100f64).contains(&confidence) {
-		//in this default of 8 cells will be taken
-		info!(
-			"confidence is {} invalid so taking default confidence of 99",
-			confidence
-		);
-		cell_count = (-((1f64 - (99.3f64 / 100f64)).log2())).ceil() as u32;
-	} else {
-		cell_count = (-((1f64 - (confidence / 100f64)).log2())).ceil() as u32;
+	positions.into_iter().collect()
+}
+
+/// Generates random cell positions for sampling, restricted to the given rows, without duplicates.
+/// Rows outside of the extended matrix are ignored, and cell count is clamped to the size of the rows.
+pub fn generate_random_cells_in_rows(
+	dimensions: Dimensions,
+	rows: &[u32],
+	cell_count: u32,
+) -> Vec<Position> {
+	let cols: u16 = dimensions.cols().into();
+	let rows = rows
+		.iter()
+		.copied()
+		.filter(|&row| row < dimensions.extended_rows())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.collect::<Vec<_>>();
+	let max_cells = rows.len() as u32 * cols as u32;
+	if max_cells < cell_count {
+		debug!("Max cells count {max_cells} in rows is lesser than cell_count {cell_count}");
 	}
-	if cell_count <= 1 {
-		info!(
-			"confidence of {} is too low so taking confidence of 50.0",
-			confidence
-		);
-		cell_count = 1;
-	} else if cell_count > CELL_COUNT_99_99 {
-		info!(
-			"confidence of {} is invalid so taking confidence of 99.99",
-			confidence
-		);
-		cell_count = CELL_COUNT_99_99;
+	let mut rng = thread_rng();
+	sample(
+		&mut rng,
+		max_cells as usize,
+		cell_count.min(max_cells) as usize,
+	)
+	.into_iter()
+	.map(|index| Position {
+		row: rows[index / cols as usize],
+		col: (index % cols as usize) as u16,
+	})
+	.collect()
+}
+
+/// RPC methods of the full node required by the light client
+const REQUIRED_RPC_METHODS: &[&str] = &[
+	"kate_queryProof",
+	"kate_queryRows",
+	"grandpa_subscribeJustifications",
+	"chain_subscribeFinalizedHeads",
+];
+
+fn missing_rpc_methods(methods: &[String]) -> Vec<&'static str> {
+	REQUIRED_RPC_METHODS
+		.iter()
+		.filter(|&&required| !methods.iter().any(|method| method == required))
+		.copied()
+		.collect()
+}
+
+/// Checks that connected node is an Avail node which exposes data availability and finality APIs
+pub async fn check_capabilities(rpc_client: &Client, host: &str) -> Result<()> {
+	let methods = rpc_client
+		.get_rpc_methods()
+		.await
+		.wrap_err_with(|| format!("Cannot list RPC methods of the node {host}"))?;
+
+	let missing = missing_rpc_methods(&methods);
+	if !missing.is_empty() {
+		return Err(eyre!(
+			"Node {host} is not an Avail node or lacks data availability APIs, missing RPC methods: {}",
+			missing.join(", ")
+		));
 	}
-	cell_count
+	info!(host, "Node capabilities checked");
+	Ok(())
 }
 
 pub async fn wait_for_finalized_header(
@@ -278,3 +407,91 @@ pub async fn wait_for_finalized_header(
 		Err(_) => Err(eyre!("Timeout on waiting for first finalized header")),
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		generate_random_cells, generate_random_cells_in_rows, generate_stratified_cells,
+		missing_rpc_methods, Node, Nodes,
+	};
+	use kate_recovery::matrix::Dimensions;
+
+	#[test]
+	fn shuffle_excludes_paired_host() {
+		let hosts = |nodes: Vec<Node>| {
+			let mut hosts = nodes.into_iter().map(|node| node.host).collect::<Vec<_>>();
+			hosts.sort();
+			hosts
+		};
+		let nodes = Nodes::new(&["a".to_string(), "b".to_string(), "c".to_string()]);
+		assert_eq!(hosts(nodes.shuffle("a".to_string(), None)), vec!["b", "c"]);
+		assert_eq!(hosts(nodes.shuffle("a".to_string(), Some("b"))), vec!["c"]);
+		assert_eq!(hosts(nodes.shuffle("c".to_string(), Some("b"))), vec!["a"]);
+
+		let nodes = Nodes::new(&["a".to_string(), "b".to_string()]);
+		assert!(nodes.shuffle("a".to_string(), Some("b")).is_empty());
+
+		let nodes = Nodes::new(&["a".to_string()]);
+		assert_eq!(hosts(nodes.shuffle("a".to_string(), None)), vec!["a"]);
+		assert!(nodes.shuffle("a".to_string(), Some("a")).is_empty());
+	}
+
+	#[test]
+	fn random_cells_in_rows() {
+		let dimensions = Dimensions::new(16, 4).unwrap();
+		let positions = generate_random_cells_in_rows(dimensions, &[2, 3], 5);
+		assert_eq!(positions.len(), 5);
+		assert!(positions.iter().all(|p| p.row == 2 || p.row == 3));
+
+		let positions = generate_random_cells_in_rows(dimensions, &[2], 10);
+		assert_eq!(positions.len(), 4);
+		assert!(generate_random_cells_in_rows(dimensions, &[], 10).is_empty());
+
+		// Duplicated rows and rows outside of the extended matrix are ignored
+		let positions = generate_random_cells_in_rows(dimensions, &[1, 1, 40], 10);
+		assert_eq!(positions.len(), 4);
+		assert!(positions.iter().all(|p| p.row == 1));
+	}
+
+	#[test]
+	fn random_cells_clamped_to_matrix() {
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		let mut positions = generate_random_cells(dimensions, 14);
+		assert_eq!(positions.len(), 8);
+		positions.sort_by_key(|p| (p.row, p.col));
+		positions.dedup();
+		assert_eq!(positions.len(), 8);
+		assert!(positions.iter().all(|p| p.row < 2 && p.col < 4));
+		assert_eq!(generate_random_cells(dimensions, 3).len(), 3);
+	}
+
+	#[test]
+	fn stratified_cells() {
+		let dimensions = Dimensions::new(16, 64).unwrap();
+		let positions = generate_stratified_cells(dimensions, 8);
+		assert_eq!(positions.len(), 8);
+		// Each band of 4 rows and each band of 8 columns is sampled once
+		let mut row_bands = positions.iter().map(|p| p.row / 4).collect::<Vec<_>>();
+		row_bands.sort();
+		assert_eq!(row_bands, (0..8).collect::<Vec<_>>());
+		let mut col_bands = positions.iter().map(|p| p.col / 8).collect::<Vec<_>>();
+		col_bands.sort();
+		assert_eq!(col_bands, (0..8).collect::<Vec<_>>());
+
+		// More cells than rows, and more cells than the matrix size
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		assert_eq!(generate_stratified_cells(dimensions, 5).len(), 5);
+		assert_eq!(generate_stratified_cells(dimensions, 14).len(), 8);
+	}
+
+	#[test]
+	fn missing_methods() {
+		let methods = ["kate_queryProof", "chain_subscribeFinalizedHeads"]
+			.map(String::from)
+			.to_vec();
+		assert_eq!(
+			missing_rpc_methods(&methods),
+			vec!["kate_queryRows", "grandpa_subscribeJustifications"]
+		);
+	}
+}
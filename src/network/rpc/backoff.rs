@@ -0,0 +1,139 @@
+//! Backoff from the rate limiting full node.
+//!
+//! Full node sheds load by responding with `-32005` JSON-RPC error code. Backoff is tracked for each full node.
+//! On each rate limited response, backoff is doubled (starting from [`MIN_BACKOFF`], up to [`MAX_BACKOFF`]),
+//! and RPC calls are postponed until it expires. Each successful call after the backoff expired halves it,
+//! until it drops below the minimum and is reset. While backoff is active, more cells are fetched from the DHT.
+
+use color_eyre::Report;
+use subxt::error::RpcError;
+use tokio::time::{Duration, Instant};
+
+/// Initial backoff after the rate limited response
+pub const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Maximum backoff after the consecutive rate limited responses
+pub const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Error code used by the full nodes for rate limited JSON-RPC calls
+const RATE_LIMITED_ERROR_CODE: i32 = -32005;
+
+fn is_rate_limited_call(error: &jsonrpsee_core::Error) -> bool {
+	matches!(error, jsonrpsee_core::Error::Call(error) if error.code() == RATE_LIMITED_ERROR_CODE)
+}
+
+/// Checks if RPC call failed because the full node is rate limiting
+pub fn is_rate_limited(error: &Report) -> bool {
+	error.chain().any(|cause| {
+		if let Some(error) = cause.downcast_ref::<jsonrpsee_core::Error>() {
+			return is_rate_limited_call(error);
+		}
+		match cause.downcast_ref::<subxt::Error>() {
+			Some(subxt::Error::Rpc(RpcError::ClientError(error))) => error
+				.downcast_ref::<jsonrpsee_core::Error>()
+				.is_some_and(is_rate_limited_call),
+			_ => false,
+		}
+	})
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitBackoff {
+	/// Current backoff, zero if the full node is not rate limiting
+	pub backoff: Duration,
+	/// Time until which RPC calls are postponed
+	pub until: Option<Instant>,
+	/// Number of rate limited responses since the start
+	pub rate_limited: u64,
+}
+
+impl RateLimitBackoff {
+	pub fn on_rate_limited(&mut self, now: Instant) {
+		self.backoff = (self.backoff * 2).clamp(MIN_BACKOFF, MAX_BACKOFF);
+		self.until = Some(now + self.backoff);
+		self.rate_limited += 1;
+	}
+
+	pub fn on_success(&mut self, now: Instant) {
+		if self.is_active() && self.remaining(now).is_none() {
+			self.backoff /= 2;
+			if self.backoff < MIN_BACKOFF {
+				self.backoff = Duration::ZERO;
+				self.until = None;
+			}
+		}
+	}
+
+	/// Remaining time until RPC calls are resumed, if they are postponed
+	pub fn remaining(&self, now: Instant) -> Option<Duration> {
+		self.until
+			.map(|until| until.saturating_duration_since(now))
+			.filter(|remaining| !remaining.is_zero())
+	}
+
+	/// Checks if the full node was recently rate limiting
+	pub fn is_active(&self) -> bool {
+		!self.backoff.is_zero()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_rate_limited, RateLimitBackoff, MAX_BACKOFF, MIN_BACKOFF};
+	use color_eyre::{eyre::eyre, Report};
+	use subxt::error::RpcError;
+	use tokio::time::{Duration, Instant};
+
+	fn call_error(code: i32, message: &str) -> jsonrpsee_core::Error {
+		let error = serde_json::json!({ "code": code, "message": message });
+		jsonrpsee_core::Error::Call(serde_json::from_value(error).unwrap())
+	}
+
+	#[test]
+	fn rate_limited_errors() {
+		let rate_limited = call_error(-32005, "Rate limit exceeded");
+		let client_error = |error| subxt::Error::Rpc(RpcError::ClientError(Box::new(error)));
+		assert!(is_rate_limited(&Report::new(client_error(rate_limited))));
+		let rate_limited = call_error(-32005, "Rate limit exceeded");
+		assert!(is_rate_limited(
+			&Report::new(rate_limited).wrap_err("Cannot query proofs")
+		));
+
+		let not_found = call_error(-32000, "Block 429 not found");
+		assert!(!is_rate_limited(&Report::new(client_error(not_found))));
+		assert!(!is_rate_limited(&eyre!("Block 0xab429cd not found")));
+		assert!(!is_rate_limited(&eyre!("Too many requests")));
+	}
+
+	#[test]
+	fn adaptive_backoff() {
+		let now = Instant::now();
+		let mut backoff = RateLimitBackoff::default();
+		backoff.on_success(now);
+		assert!(!backoff.is_active());
+
+		backoff.on_rate_limited(now);
+		assert_eq!(backoff.backoff, MIN_BACKOFF);
+		backoff.on_rate_limited(now);
+		assert_eq!(backoff.remaining(now), Some(2 * MIN_BACKOFF));
+		// Successful call before the backoff expires doesn't decrease it
+		backoff.on_success(now);
+		assert_eq!(backoff.backoff, 2 * MIN_BACKOFF);
+
+		for _ in 0..10 {
+			backoff.on_rate_limited(now);
+		}
+		assert_eq!(backoff.backoff, MAX_BACKOFF);
+		assert_eq!(backoff.rate_limited, 12);
+
+		let expired = now + MAX_BACKOFF;
+		assert_eq!(backoff.remaining(expired), None);
+		backoff.on_success(expired);
+		assert_eq!(backoff.backoff, MAX_BACKOFF / 2);
+		while backoff.is_active() {
+			backoff.on_success(expired);
+		}
+		assert_eq!(backoff.backoff, Duration::ZERO);
+		assert_eq!(backoff.until, None);
+	}
+}
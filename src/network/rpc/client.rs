@@ -14,32 +14,202 @@ use color_eyre::{
 	Report, Result,
 };
 use futures::{Stream, TryFutureExt, TryStreamExt};
+use hyper::Uri;
+use jsonrpsee::{
+	client_transport::ws::{Url, WsTransportClientBuilder},
+	core::client::ClientBuilder,
+};
 use kate_recovery::{data::Cell, matrix::Position};
+use serde::Deserialize;
 use sp_core::{bytes::from_hex, ed25519::Public, U256};
-use std::sync::{Arc, Mutex};
+use std::{
+	fmt::{self, Display, Formatter},
+	sync::{Arc, Mutex, OnceLock},
+};
 use subxt::{
 	backend::{
 		legacy::rpc_methods::{BlockNumber, StorageKey},
+		rpc::RpcClient,
 		RuntimeVersion,
 	},
+	error::RpcError,
 	rpc_params,
 	tx::SubmittableExtrinsic,
 	utils::AccountId32,
 };
 use subxt_signer::sr25519::Keypair;
-use tokio::sync::RwLock;
+use tokio::{
+	sync::RwLock,
+	time::{self, Duration, Instant, Interval},
+};
 use tokio_retry::Retry;
 use tokio_stream::StreamExt;
-use tracing::{info, warn};
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tracing::{debug, error, info, warn};
 
-use super::{Node, Nodes, Subscription, WrappedProof};
+use super::{backoff, http_urls, Node, Nodes, Subscription, WrappedProof};
 use crate::{
 	api::v2::types::Base64,
+	cell_size::CellSize,
 	consts::ExpectedNodeVariant,
 	shutdown::Controller,
-	types::{RetryConfig, State, DEV_FLAG_GENHASH},
+	types::{ConnectionConfig, KeepAliveConfig, RetryConfig, State, DEV_FLAG_GENHASH},
 };
 
+/// Maximum time to wait for the keep-alive probe response
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval in which the full nodes are called while they are unreachable, to detect restored connectivity
+const DEGRADED_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// None of the configured full nodes could be connected to
+#[derive(Debug)]
+struct Unreachable;
+
+impl Display for Unreachable {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.write_str("Failed to connect any appropriate working node")
+	}
+}
+
+impl std::error::Error for Unreachable {}
+
+/// Checks if the call failed because the full node couldn't be reached,
+/// as opposed to the full node responding with an error
+fn is_connection_error(error: &Report) -> bool {
+	error.chain().any(|cause| {
+		if cause.is::<Unreachable>() || cause.is::<std::io::Error>() {
+			return true;
+		}
+		if let Some(error) = cause.downcast_ref::<jsonrpsee_core::Error>() {
+			return !matches!(error, jsonrpsee_core::Error::Call(_));
+		}
+		match cause.downcast_ref::<subxt::Error>() {
+			Some(subxt::Error::Io(_)) => true,
+			Some(subxt::Error::Rpc(RpcError::SubscriptionDropped)) => true,
+			Some(subxt::Error::Rpc(RpcError::ClientError(error))) => !matches!(
+				error.downcast_ref::<jsonrpsee_core::Error>(),
+				Some(jsonrpsee_core::Error::Call(_))
+			),
+			_ => false,
+		}
+	})
+}
+
+/// Malformed `kate_queryProof` response
+#[derive(Debug, PartialEq)]
+pub enum InvalidProofResponse {
+	/// Number of proofs doesn't match the number of requested cells
+	Length { expected: usize, actual: usize },
+	/// Proof of the cell has invalid length
+	ProofLength {
+		position: Position,
+		length: usize,
+		expected: usize,
+	},
+	/// Configured cell size cannot be verified
+	UnsupportedCellSize(CellSize),
+}
+
+impl std::fmt::Display for InvalidProofResponse {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			InvalidProofResponse::Length { expected, actual } => {
+				write!(f, "Expected {expected} proofs, received {actual}")
+			},
+			InvalidProofResponse::ProofLength {
+				position,
+				length,
+				expected,
+			} => write!(
+				f,
+				"Invalid proof length {length} of cell {}:{}, expected {expected}",
+				position.row, position.col,
+			),
+			InvalidProofResponse::UnsupportedCellSize(cell_size) => write!(
+				f,
+				"Cells of {} bytes chunks and {} bytes proofs are not supported",
+				cell_size.chunk_size, cell_size.proof_size
+			),
+		}
+	}
+}
+
+impl std::error::Error for InvalidProofResponse {}
+
+/// Validates proofs received for the requested positions, and concatenates them with the cell scalars
+fn cells_from_proofs(
+	positions: &[Position],
+	proofs: Vec<(U256, Vec<u8>)>,
+	cell_size: CellSize,
+) -> Result<Vec<Cell>, InvalidProofResponse> {
+	if proofs.len() != positions.len() {
+		return Err(InvalidProofResponse::Length {
+			expected: positions.len(),
+			actual: proofs.len(),
+		});
+	}
+
+	positions
+		.iter()
+		.zip(proofs)
+		.map(|(&position, (scalar, proof))| {
+			if proof.len() != cell_size.proof_size {
+				return Err(InvalidProofResponse::ProofLength {
+					position,
+					length: proof.len(),
+					expected: cell_size.proof_size,
+				});
+			}
+			let mut chunk = [0u8; 32];
+			scalar.to_big_endian(&mut chunk);
+			cell_size
+				.cell(position, &proof, &chunk)
+				.map_err(|_| InvalidProofResponse::UnsupportedCellSize(cell_size))
+		})
+		.collect()
+}
+
+/// Splits cells into the ones matching the other cells, and positions of the discrepant ones
+fn cross_checked(cells: Vec<Cell>, other_cells: &[Cell]) -> (Vec<Cell>, Vec<Position>) {
+	let mut discrepant = vec![];
+	let matching = cells
+		.into_iter()
+		.filter(|cell| {
+			let matches = other_cells
+				.iter()
+				.any(|other| other.position == cell.position && other.content == cell.content);
+			if !matches {
+				discrepant.push(cell.position);
+			}
+			matches
+		})
+		.collect();
+	(matching, discrepant)
+}
+
+/// Reason to break out of the subscriptions stream
+enum KeepAliveEvent {
+	Stale(Duration),
+	PingFailed(String),
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+	match deadline {
+		Some(deadline) => time::sleep_until(deadline).await,
+		None => futures::future::pending().await,
+	}
+}
+
+async fn tick(interval: &mut Option<Interval>) {
+	match interval {
+		Some(interval) => {
+			interval.tick().await;
+		},
+		None => futures::future::pending().await,
+	}
+}
+
 #[derive(Clone)]
 pub struct Client {
 	subxt_client: Arc<RwLock<Arc<AvailClient>>>,
@@ -47,7 +217,16 @@ pub struct Client {
 	nodes: Nodes,
 	retry_config: RetryConfig,
 	expected_genesis_hash: String,
+	keep_alive: KeepAliveConfig,
+	connection: ConnectionConfig,
 	shutdown: Controller<String>,
+	/// Client connected to another node, used to cross-check proofs in paranoid mode
+	cross_check: Option<Arc<Client>>,
+	/// State of the client paired in paranoid mode, which connected node is not used on reconnect.
+	/// It is shared with the clones created before pairing, like the one used by the subscription loop.
+	paired: Arc<OnceLock<Arc<Mutex<State>>>>,
+	/// Sizes of the cells returned by the full node
+	cell_size: CellSize,
 }
 
 impl Client {
@@ -56,6 +235,8 @@ impl Client {
 		nodes: Nodes,
 		expected_genesis_hash: &str,
 		retry_config: RetryConfig,
+		keep_alive: KeepAliveConfig,
+		connection: ConnectionConfig,
 		shutdown: Controller<String>,
 	) -> Result<Self> {
 		// try and connect appropriate Node from the provided list
@@ -63,9 +244,10 @@ impl Client {
 		let (client, node, _) = match shutdown
 			.with_cancel(Retry::spawn(retry_config.clone(), || async {
 				Self::try_connect_and_execute(
-					nodes.shuffle(Default::default()),
+					nodes.shuffle(Default::default(), None),
 					ExpectedNodeVariant::default(),
 					expected_genesis_hash,
+					&connection,
 					|_| futures::future::ok(()),
 				)
 				.await
@@ -89,18 +271,63 @@ impl Client {
 			nodes,
 			retry_config,
 			expected_genesis_hash: expected_genesis_hash.to_string(),
+			keep_alive,
+			connection,
 			shutdown,
+			cross_check: None,
+			paired: Default::default(),
+			cell_size: CellSize::default(),
 		})
 	}
 
+	/// Sets sizes of the cells returned by the full node
+	pub fn with_cell_size(mut self, cell_size: CellSize) -> Self {
+		self.cell_size = cell_size;
+		self
+	}
+
+	/// Cross-checks requested proofs with the given client, which should be connected to another node.
+	/// Clients are paired, so neither of them reconnects to the node connected by the other one.
+	pub fn with_cross_check(mut self, client: Client) -> Self {
+		let _ = client.paired.set(self.state.clone());
+		let _ = self.paired.set(client.state.clone());
+		self.cross_check = Some(Arc::new(client));
+		self
+	}
+
+	/// Host of the currently connected node
+	pub fn host(&self) -> String {
+		self.state.lock().unwrap().connected_node.host.clone()
+	}
+
+	/// Opens WebSocket connection to the full node, through the proxy configured for its host, if any
+	async fn connect(host: &str, connection: &ConnectionConfig) -> Result<AvailClient> {
+		let url = Url::parse(host).wrap_err_with(|| format!("Invalid full node URL {host}"))?;
+		let uri = http_urls(&[host.to_string()])
+			.remove(0)
+			.parse::<Uri>()
+			.wrap_err_with(|| format!("Invalid full node URL {host}"))?;
+		// TLS is established by the connector, so the WebSocket handshake is done over the secured stream
+		let stream = connection.proxies.connect(uri).await?;
+		// Larger responses (e.g. proofs of many cells) are rejected, instead of being buffered
+		let max_response_size = u32::try_from(connection.max_response_size).unwrap_or(u32::MAX);
+		let (sender, receiver) = WsTransportClientBuilder::default()
+			.max_response_size(max_response_size)
+			.build_with_stream(url, stream.compat())
+			.await?;
+		let client = ClientBuilder::default().build_with_tokio(sender, receiver);
+		AvailClient::from_rpc_client(RpcClient::new(client))
+			.await
+			.map_err(|e| eyre!(e))
+	}
+
 	async fn create_subxt_client(
 		host: &str,
+		connection: &ConnectionConfig,
 		expected_node: ExpectedNodeVariant,
 		expected_genesis_hash: &str,
 	) -> Result<(AvailClient, Node)> {
-		let client = AvailClient::new_insecure(host)
-			.await
-			.map_err(|e| eyre!(e))?;
+		let client = Self::connect(host, connection).await?;
 
 		// check genesis hash
 		let genesis_hash = client.genesis_hash();
@@ -149,6 +376,7 @@ impl Client {
 		nodes: Vec<Node>,
 		expected_node: ExpectedNodeVariant,
 		expected_genesis_hash: &str,
+		connection: &ConnectionConfig,
 		mut f: F,
 	) -> Result<(Arc<AvailClient>, Node, T)>
 	where
@@ -157,22 +385,102 @@ impl Client {
 	{
 		// go through the provided list of Nodes to try and find and appropriate one,
 		// after a successful connection, try to execute passed function call
+		let mut call_error = None;
 		for Node { host, .. } in nodes.iter() {
-			let result =
-				Self::create_subxt_client(host, expected_node.clone(), expected_genesis_hash)
-					.and_then(move |(client, node)| {
-						let client = Arc::new(client);
-						f(client.clone()).map_ok(move |res| (client, node, res))
-					})
-					.await;
-
-			match result {
-				Err(error) => warn!(host, %error, "Skipping connection with this node"),
-				ok => return ok,
+			let (client, node) = match Self::create_subxt_client(
+				host,
+				connection,
+				expected_node.clone(),
+				expected_genesis_hash,
+			)
+			.await
+			{
+				Ok(connected) => connected,
+				Err(error) => {
+					warn!(host, %error, "Skipping connection with this node");
+					continue;
+				},
+			};
+			let client = Arc::new(client);
+			match f(client.clone()).await {
+				Ok(result) => return Ok((client, node, result)),
+				Err(error) => {
+					warn!(host, %error, "Skipping connection with this node");
+					// node is reachable, so application errors are reported instead of connectivity loss
+					if !is_connection_error(&error) {
+						call_error = Some(error);
+					}
+				},
 			}
 		}
 
-		Err(eyre!("Failed to connect any appropriate working node"))
+		Err(call_error.unwrap_or_else(|| Report::new(Unreachable)))
+	}
+
+	/// Checks if the full node was recently rate limiting, so more cells should be fetched from the DHT
+	pub fn is_backing_off(&self) -> bool {
+		let state = self.state.lock().unwrap();
+		state
+			.rpc_backoff()
+			.is_some_and(|backoff| backoff.is_active())
+	}
+
+	/// Checks if all configured full nodes are unreachable, so cells should be sampled from the DHT only
+	pub fn is_degraded(&self) -> bool {
+		self.state.lock().unwrap().degraded
+	}
+
+	/// Enters degraded mode if no node could be reached, and leaves it on the first response from the full node.
+	/// Error responses (e.g. unknown block) don't affect degraded mode, since the full node is reachable.
+	fn observe_connectivity<T>(&self, result: &Result<T>) {
+		let reachable = result
+			.as_ref()
+			.map_or_else(|error| !is_connection_error(error), |_| true);
+		let mut state = self.state.lock().unwrap();
+		match (reachable, state.degraded) {
+			(true, true) => {
+				info!("Full node connectivity is restored, leaving degraded mode");
+				state.degraded = false;
+			},
+			(false, false) => {
+				warn!("All full nodes are unreachable, sampling from the DHT only");
+				state.degraded = true;
+			},
+			_ => (),
+		}
+	}
+
+	/// Postpones the call until the rate limit backoff expires
+	async fn wait_for_backoff(&self) {
+		let remaining = self
+			.state
+			.lock()
+			.unwrap()
+			.rpc_backoff()
+			.and_then(|backoff| backoff.remaining(Instant::now()));
+		if let Some(remaining) = remaining {
+			debug!("Full node is rate limiting, postponing RPC call by {remaining:?}");
+			time::sleep(remaining).await;
+		}
+	}
+
+	/// Adapts the rate limit backoff to the result of the call
+	fn observe_rate_limit<T>(&self, result: &Result<T>) {
+		let mut state = self.state.lock().unwrap();
+		let host = state.connected_node.host.clone();
+		match result {
+			Err(error) if backoff::is_rate_limited(error) => {
+				let backoff = state.rpc_backoffs.entry(host.clone()).or_default();
+				backoff.on_rate_limited(Instant::now());
+				warn!(host, backoff = ?backoff.backoff, "Full node is rate limiting, backing off");
+			},
+			Ok(_) => {
+				if let Some(backoff) = state.rpc_backoffs.get_mut(&host) {
+					backoff.on_success(Instant::now());
+				}
+			},
+			Err(_) => (),
+		}
 	}
 
 	async fn with_retries<F, Fut, T>(&self, mut f: F) -> Result<T>
@@ -185,12 +493,20 @@ impl Client {
 			.shutdown
 			.with_cancel(Retry::spawn(
 				self.retry_config.clone(),
-				move || async move { f(self.current_client().await).await },
+				move || async move {
+					self.wait_for_backoff().await;
+					let result = f(self.current_client().await).await;
+					self.observe_rate_limit(&result);
+					result
+				},
 			))
 			.await
 		{
 			// this was successful, return early
-			Ok(Ok(result)) => return Ok(result),
+			Ok(Ok(result)) => {
+				self.observe_connectivity(&Ok(()));
+				return Ok(result);
+			},
 			// if there was an error, skip ahead and try to find a new Node
 			Ok(Err(_)) => {},
 			// shutdown happened, stop everything
@@ -206,17 +522,36 @@ impl Client {
 			"Executing RPC call with host: {} failed. Trying to create a new RPC connection.",
 			connected_node.host
 		);
-		// shuffle nodes, if possible
-		let nodes = self.nodes.shuffle(connected_node.host);
+		let result = self.reconnect_and_execute(f).await;
+		self.observe_connectivity(&result);
+		result
+	}
+
+	/// Creates a new connection, preferring Nodes other than the currently connected one,
+	/// and executes passed function call on it. Connecting is retried using the Retry strategy.
+	async fn reconnect_and_execute<F, Fut, T>(&self, mut f: F) -> Result<T>
+	where
+		F: FnMut(Arc<AvailClient>) -> Fut + Copy,
+		Fut: std::future::Future<Output = Result<T>>,
+	{
+		let connected_node = self.state.lock().unwrap().connected_node.clone();
 		// go through available Nodes, try to connect, Retry connecting if needed
 		let (client, node, result) = match self
 			.shutdown
 			.with_cancel(Retry::spawn(self.retry_config.clone(), || async {
-				let nodes = nodes.clone();
+				// shuffle nodes, if possible, skipping the node of the paired client, which can change between retries
+				let paired_host = self
+					.paired
+					.get()
+					.map(|state| state.lock().unwrap().connected_node.host.clone());
+				let nodes = self
+					.nodes
+					.shuffle(connected_node.host.clone(), paired_host.as_deref());
 				Self::try_connect_and_execute(
 					nodes,
 					ExpectedNodeVariant::default(),
 					&self.expected_genesis_hash,
+					&self.connection,
 					move |client| f(client).map_err(Report::from),
 				)
 				.await
@@ -260,13 +595,30 @@ impl Client {
 		Ok(headers.merge(justifications))
 	}
 
+	/// Probes the current connection with a lightweight RPC call
+	async fn ping(&self) -> Result<()> {
+		let client = self.current_client().await;
+		match time::timeout(PING_TIMEOUT, client.legacy_rpc().chain_get_finalized_head()).await {
+			Ok(Ok(_)) => Ok(()),
+			Ok(Err(error)) => Err(eyre!(error)),
+			Err(_) => Err(eyre!("No response in {PING_TIMEOUT:?}")),
+		}
+	}
+
 	pub async fn subscription_stream(self) -> impl Stream<Item = Result<Subscription>> {
 		async_stream::stream! {
+			let mut force_reconnect = false;
 			'outer: loop{
-				let mut stream = match self.with_retries(|client| async move{
+				let create = |client: Arc<AvailClient>| async move {
 					Self::create_subxt_subscriptions(client)
 						.await
-				}).await {
+				};
+				// stale connection is replaced without retrying on the current client
+				let result = match std::mem::take(&mut force_reconnect) {
+					true => self.reconnect_and_execute(create).await,
+					false => self.with_retries(create).await,
+				};
+				let mut stream = match result {
 					Ok(s) => s,
 					Err(err) => {
 						yield Err(err);
@@ -274,19 +626,56 @@ impl Client {
 					}
 				};
 
+				let mut last_header = Instant::now();
+				let mut ping = self
+					.keep_alive
+					.ping_interval
+					.map(|period| time::interval_at(Instant::now() + period, period));
+
 				loop {
-					// no more subscriptions left on stream, we have to try and create a new stream
-					let Some(result) = stream.next().await else {
-						warn!("No more items on Subscriptions Stream. Trying to create a new one.");
-						continue 'outer
+					let stale_deadline = self
+						.keep_alive
+						.stale_timeout
+						.map(|timeout| last_header + timeout);
+					let next = tokio::select! {
+						next = stream.next() => Ok(next),
+						_ = sleep_until(stale_deadline) => {
+							Err(KeepAliveEvent::Stale(last_header.elapsed()))
+						},
+						_ = tick(&mut ping) => match self.ping().await {
+							Ok(()) => continue,
+							Err(error) => Err(KeepAliveEvent::PingFailed(error.to_string())),
+						},
 					};
-					match result {
-						Ok(item) => yield Ok(item),
+
+					let host = self.state.lock().unwrap().connected_node.host.clone();
+					match next {
+						Ok(Some(Ok(item))) => {
+							if let Subscription::Header(_) = item {
+								last_header = Instant::now();
+							}
+							yield Ok(item)
+						},
+						// no more subscriptions left on stream, we have to try and create a new stream
+						Ok(None) => {
+							warn!("No more items on Subscriptions Stream. Trying to create a new one.");
+							continue 'outer
+						},
 						// if Error was received, we need to switch to another RPC Client
-						Err(err)=> {
+						Ok(Some(Err(err))) => {
 							warn!(%err, "Received Error on stream. Trying to create a new one.");
 							continue 'outer
-						}
+						},
+						Err(KeepAliveEvent::Stale(elapsed)) => {
+							warn!(host, ?elapsed, "No finalized header received from the node. Reconnecting.");
+							force_reconnect = true;
+							continue 'outer
+						},
+						Err(KeepAliveEvent::PingFailed(error)) => {
+							warn!(host, error, "Node connection keep-alive probe failed. Reconnecting.");
+							force_reconnect = true;
+							continue 'outer
+						},
 					}
 				}
 			}
@@ -298,18 +687,21 @@ impl Client {
 	}
 
 	pub async fn get_block_hash(&self, block_number: u32) -> Result<H256> {
-		let hash = self
-			.with_retries(|client| async move {
-				client
-					.legacy_rpc()
-					.chain_get_block_hash(Some(BlockNumber::from(block_number)))
-					.await
-					.map_err(Into::into)
-			})
+		self.find_block_hash(block_number)
 			.await?
-			.ok_or_else(|| eyre!("Block with number: {} not found", block_number))?;
+			.ok_or_else(|| eyre!("Block with number: {} not found", block_number))
+	}
 
-		Ok(hash)
+	/// Returns hash of the block, or `None` if the node doesn't have the block
+	pub async fn find_block_hash(&self, block_number: u32) -> Result<Option<H256>> {
+		self.with_retries(|client| async move {
+			client
+				.legacy_rpc()
+				.chain_get_block_hash(Some(BlockNumber::from(block_number)))
+				.await
+				.map_err(Into::into)
+		})
+		.await
 	}
 
 	pub async fn get_header_by_hash(&self, block_hash: H256) -> Result<Header> {
@@ -364,6 +756,18 @@ impl Client {
 		Ok(head)
 	}
 
+	/// Periodically calls the full node while in degraded mode, so the mode is left once connectivity is restored
+	pub async fn probe_while_degraded(self) {
+		loop {
+			time::sleep(DEGRADED_PROBE_INTERVAL).await;
+			if self.is_degraded() {
+				if let Err(error) = self.get_finalized_head_hash().await {
+					debug!("Full node is still unreachable: {error:#}");
+				}
+			}
+		}
+	}
+
 	pub async fn get_chain_head_header(&self) -> Result<Header> {
 		let finalized_hash = self.get_finalized_head_hash().await?;
 		self.get_header_by_hash(finalized_hash).await
@@ -378,11 +782,7 @@ impl Client {
 		self.with_retries(|client| {
 			let rows = rows.clone();
 			async move {
-				let rows = client
-					.rpc_methods()
-					.query_rows(rows, block_hash)
-					.await
-					.map_err(|error| subxt::Error::Other(format!("{error}")))?;
+				let rows = client.rpc_methods().query_rows(rows, block_hash).await?;
 				Ok(rows
 					.iter()
 					.map(|row| {
@@ -400,23 +800,40 @@ impl Client {
 		.await
 	}
 
+	/// Requests cells with proofs. In paranoid mode, cells are requested from the cross-check node too,
+	/// and only cells on which both nodes agree are returned.
 	pub async fn request_kate_proof(
 		&self,
 		block_hash: H256,
 		positions: &[Position],
 	) -> Result<Vec<Cell>> {
-		fn concat_content(scalar: U256, proof: GProof) -> Result<[u8; 80]> {
-			let proof: Vec<u8> = proof.into();
-			if proof.len() != 48 {
-				return Err(eyre!("Invalid proof length"));
-			}
+		let cells = self.query_kate_proof(block_hash, positions).await?;
+		let Some(cross_check) = &self.cross_check else {
+			return Ok(cells);
+		};
+		let other_cells = cross_check
+			.query_kate_proof(block_hash, positions)
+			.await
+			.wrap_err("Cannot cross-check proofs")?;
 
-			let mut result = [0u8; 80];
-			scalar.to_big_endian(&mut result[48..]);
-			result[..48].copy_from_slice(&proof);
-			Ok(result)
+		let (matching, discrepant) = cross_checked(cells, &other_cells);
+		if !discrepant.is_empty() {
+			error!(
+				?block_hash,
+				host = self.host(),
+				other_host = cross_check.host(),
+				?discrepant,
+				"Full nodes returned different cells or proofs"
+			);
 		}
+		Ok(matching)
+	}
 
+	async fn query_kate_proof(
+		&self,
+		block_hash: H256,
+		positions: &[Position],
+	) -> Result<Vec<Cell>> {
 		let cells: Cells = positions
 			.iter()
 			.map(|p| avail_subxt::Cell {
@@ -427,30 +844,25 @@ impl Client {
 			.try_into()
 			.map_err(|_| eyre!("Failed to convert to cells"))?;
 
-		let proofs: Vec<(GRawScalar, GProof)> = self
-			.with_retries(|client| {
-				let cells = cells.clone();
-				async move {
-					client
-						.rpc_methods()
-						.query_proof(cells, block_hash)
-						.await
-						.map_err(|error| subxt::Error::Other(format!("{error}")))
-						.map_err(Into::into)
-				}
-			})
-			.await
-			.map_err(Report::from)?;
-
-		let contents = proofs
-			.into_iter()
-			.map(|(scalar, proof)| concat_content(scalar, proof).expect("TODO"));
+		let cell_size = self.cell_size;
+		if !cell_size.is_supported() {
+			return Err(InvalidProofResponse::UnsupportedCellSize(cell_size).into());
+		}
 
-		Ok(positions
-			.iter()
-			.zip(contents)
-			.map(|(&position, content)| Cell { position, content })
-			.collect::<Vec<_>>())
+		// malformed response is retried, and then requested from another node
+		self.with_retries(|client| {
+			let cells = cells.clone();
+			async move {
+				let proofs: Vec<(GRawScalar, GProof)> =
+					client.rpc_methods().query_proof(cells, block_hash).await?;
+				let proofs = proofs
+					.into_iter()
+					.map(|(scalar, proof)| (scalar, proof.into()))
+					.collect();
+				cells_from_proofs(positions, proofs, cell_size).map_err(Report::from)
+			}
+		})
+		.await
 	}
 
 	pub async fn get_system_version(&self) -> Result<String> {
@@ -467,6 +879,23 @@ impl Client {
 		Ok(res)
 	}
 
+	pub async fn get_rpc_methods(&self) -> Result<Vec<String>> {
+		#[derive(Deserialize)]
+		struct RpcMethods {
+			methods: Vec<String>,
+		}
+
+		self.with_retries(|client| async move {
+			client
+				.rpc()
+				.request::<RpcMethods>("rpc_methods", rpc_params![])
+				.await
+				.map(|rpc_methods| rpc_methods.methods)
+				.map_err(Into::into)
+		})
+		.await
+	}
+
 	pub async fn get_runtime_version(&self) -> Result<RuntimeVersion> {
 		self.with_retries(|client| async move { Ok(client.runtime_version()) })
 			.await
@@ -635,3 +1064,86 @@ impl Client {
 		Ok(gen_hash)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		cells_from_proofs, cross_checked, is_connection_error, InvalidProofResponse, Unreachable,
+	};
+	use crate::cell_size::CellSize;
+	use color_eyre::{eyre::eyre, Report};
+	use kate_recovery::data::Cell;
+	use kate_recovery::matrix::Position;
+	use sp_core::U256;
+
+	#[test]
+	fn classify_connection_errors() {
+		assert!(is_connection_error(&Report::new(Unreachable)));
+		let timeout = subxt::Error::Rpc(subxt::error::RpcError::ClientError(Box::new(
+			jsonrpsee_core::Error::RequestTimeout,
+		)));
+		assert!(is_connection_error(&Report::new(timeout)));
+		assert!(is_connection_error(&Report::new(subxt::Error::Rpc(
+			subxt::error::RpcError::SubscriptionDropped
+		))));
+
+		assert!(!is_connection_error(&eyre!("Invalid proof response")));
+		let other = subxt::Error::Other("Block not found".to_string());
+		assert!(!is_connection_error(&Report::new(other)));
+	}
+
+	#[test]
+	fn proof_response_validation() {
+		let positions = [Position { row: 0, col: 1 }, Position { row: 1, col: 2 }];
+		let proof = |length| (U256::from(7), vec![1u8; length]);
+		let cell_size = CellSize::default();
+
+		let cells = cells_from_proofs(&positions, vec![proof(48), proof(48)], cell_size).unwrap();
+		assert_eq!(cells.len(), 2);
+		assert_eq!(cells[1].position, positions[1]);
+		assert_eq!(&cells[0].content[..48], &[1u8; 48]);
+		assert_eq!(cells[0].content[79], 7);
+
+		assert_eq!(
+			cells_from_proofs(&positions, vec![proof(48)], cell_size).unwrap_err(),
+			InvalidProofResponse::Length {
+				expected: 2,
+				actual: 1
+			}
+		);
+		assert_eq!(
+			cells_from_proofs(&positions, vec![proof(48), proof(47)], cell_size).unwrap_err(),
+			InvalidProofResponse::ProofLength {
+				position: positions[1],
+				length: 47,
+				expected: 48
+			}
+		);
+
+		let unsupported = CellSize {
+			chunk_size: 32,
+			proof_size: 47,
+		};
+		assert_eq!(
+			cells_from_proofs(&positions, vec![proof(47), proof(47)], unsupported).unwrap_err(),
+			InvalidProofResponse::UnsupportedCellSize(unsupported)
+		);
+	}
+
+	#[test]
+	fn cross_check() {
+		let cell = |row, value| Cell {
+			position: Position { row, col: 0 },
+			content: [value; 80],
+		};
+		let cells = vec![cell(0, 1), cell(1, 1), cell(2, 1)];
+		let other_cells = vec![cell(2, 1), cell(1, 2)];
+		let (matching, discrepant) = cross_checked(cells, &other_cells);
+		assert_eq!(matching.len(), 1);
+		assert_eq!(matching[0].position, Position { row: 2, col: 0 });
+		assert_eq!(
+			discrepant,
+			vec![Position { row: 0, col: 0 }, Position { row: 1, col: 0 }]
+		);
+	}
+}
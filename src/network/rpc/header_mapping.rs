@@ -0,0 +1,189 @@
+//! Mapping of the header fields of the Avail forks.
+//!
+//! Forks of Avail may serialize compatible headers with different field names. Paths of the commitment,
+//! matrix dimensions, data root and data lookup fields in the fork header are configurable as dot-separated
+//! JSON paths (e.g. `extension.V1.kate.commitment`). Before decoding, mapped fields are moved to their paths
+//! in the Avail header, and the header extension is rebuilt from them. Mapping is applied to the headers
+//! received over HTTP JSON-RPC, so it is supported only if `polling` is the only header source.
+
+use avail_subxt::primitives::Header;
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Header field holding the extension, which is rebuilt from the mapped fields
+const EXTENSION_FIELD: &str = "extension";
+
+/// Paths of the mapped fields in the Avail header
+const COMMITMENT_PATH: &str = "extension.V3.commitment.commitment";
+const ROWS_PATH: &str = "extension.V3.commitment.rows";
+const COLS_PATH: &str = "extension.V3.commitment.cols";
+const DATA_ROOT_PATH: &str = "extension.V3.commitment.dataRoot";
+const APP_LOOKUP_PATH: &str = "extension.V3.appLookup";
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct HeaderMappingConfig {
+	/// Path of the KZG commitments in the fork header (default: None)
+	pub header_commitment_path: Option<String>,
+	/// Path of the number of the matrix rows in the fork header (default: None)
+	pub header_rows_path: Option<String>,
+	/// Path of the number of the matrix columns in the fork header (default: None)
+	pub header_cols_path: Option<String>,
+	/// Path of the data root in the fork header (default: None)
+	pub header_data_root_path: Option<String>,
+	/// Path of the data lookup in the fork header (default: None)
+	pub header_app_lookup_path: Option<String>,
+}
+
+/// Mapping of the fork header fields to the fields of the Avail header
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderMapping {
+	/// Source and target paths of the mapped fields
+	fields: Vec<(String, &'static str)>,
+}
+
+impl HeaderMapping {
+	/// Creates the mapping, if any header field path is configured
+	pub fn from_config(cfg: &HeaderMappingConfig) -> Result<Option<Self>> {
+		let paths = [
+			(&cfg.header_commitment_path, COMMITMENT_PATH),
+			(&cfg.header_rows_path, ROWS_PATH),
+			(&cfg.header_cols_path, COLS_PATH),
+			(&cfg.header_data_root_path, DATA_ROOT_PATH),
+			(&cfg.header_app_lookup_path, APP_LOOKUP_PATH),
+		];
+		if paths.iter().all(|(source, _)| source.is_none()) {
+			return Ok(None);
+		}
+
+		let mut fields = vec![];
+		for (source, target) in paths {
+			let source = source.clone().unwrap_or_else(|| target.to_string());
+			if source.split('.').any(str::is_empty) {
+				return Err(eyre!("Invalid header field path {source:?}"));
+			}
+			fields.push((source, target));
+		}
+		Ok(Some(HeaderMapping { fields }))
+	}
+
+	/// Moves the mapped fields of the fork header to their paths in the Avail header
+	pub fn apply(&self, mut header: Value) -> Result<Value> {
+		let mut values = vec![];
+		for (source, target) in &self.fields {
+			let value = take(&mut header, source)
+				.ok_or_else(|| eyre!("Header field {source} is not found"))?;
+			values.push((*target, value));
+		}
+
+		let Value::Object(fields) = &mut header else {
+			return Err(eyre!("Header is not a JSON object"));
+		};
+		fields.remove(EXTENSION_FIELD);
+		for (target, value) in values {
+			insert(&mut header, target, value);
+		}
+		Ok(header)
+	}
+
+	/// Decodes the fork header
+	pub fn decode(&self, header: Value) -> Result<Header> {
+		serde_json::from_value(self.apply(header)?).wrap_err("Cannot decode mapped header")
+	}
+}
+
+/// Removes the value at the given path
+fn take(value: &mut Value, path: &str) -> Option<Value> {
+	let (parent, field) = match path.rsplit_once('.') {
+		Some((parent, field)) => (get_mut(value, parent)?, field),
+		None => (value, path),
+	};
+	parent.as_object_mut()?.remove(field)
+}
+
+fn get_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+	path.split('.')
+		.try_fold(value, |value, field| value.as_object_mut()?.get_mut(field))
+}
+
+/// Inserts the value at the given path, creating the missing objects
+fn insert(value: &mut Value, path: &str, field_value: Value) {
+	let mut current = value;
+	let mut fields = path.split('.').peekable();
+	while let Some(field) = fields.next() {
+		if !current.is_object() {
+			*current = Value::Object(Map::new());
+		}
+		let object = current.as_object_mut().expect("Value should be an object");
+		if fields.peek().is_none() {
+			object.insert(field.to_string(), field_value);
+			return;
+		}
+		current = object
+			.entry(field)
+			.or_insert_with(|| Value::Object(Map::new()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{HeaderMapping, HeaderMappingConfig};
+	use serde_json::json;
+
+	#[test]
+	fn map_fork_header() {
+		assert_eq!(
+			HeaderMapping::from_config(&HeaderMappingConfig::default()).unwrap(),
+			None
+		);
+		let invalid = HeaderMappingConfig {
+			header_rows_path: Some("extension..rows".to_string()),
+			..Default::default()
+		};
+		assert!(HeaderMapping::from_config(&invalid).is_err());
+
+		let cfg = HeaderMappingConfig {
+			header_commitment_path: Some("extension.V1.kate.commitments".to_string()),
+			header_rows_path: Some("extension.V1.kate.height".to_string()),
+			header_cols_path: Some("extension.V1.kate.width".to_string()),
+			header_data_root_path: Some("dataRoot".to_string()),
+			header_app_lookup_path: Some("extension.V1.lookup".to_string()),
+		};
+		let mapping = HeaderMapping::from_config(&cfg).unwrap().unwrap();
+		let header = json!({
+			"number": "0x1",
+			"dataRoot": "0x00",
+			"extension": {
+				"V1": {
+					"lookup": { "size": 1, "index": [] },
+					"kate": { "commitments": [1, 2], "height": 1, "width": 4 },
+					"extra": true
+				}
+			}
+		});
+		assert_eq!(
+			mapping.apply(header).unwrap(),
+			json!({
+				"number": "0x1",
+				"extension": {
+					"V3": {
+						"appLookup": { "size": 1, "index": [] },
+						"commitment": {
+							"commitment": [1, 2],
+							"rows": 1,
+							"cols": 4,
+							"dataRoot": "0x00"
+						}
+					}
+				}
+			})
+		);
+
+		let missing = json!({ "number": "0x1", "extension": { "V1": {} } });
+		assert!(mapping.apply(missing).is_err());
+	}
+}
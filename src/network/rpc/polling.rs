@@ -0,0 +1,358 @@
+//! Polling of finalized headers over HTTP JSON-RPC, for full nodes which don't expose WebSockets.
+//!
+//! Finalized head is polled with `chain_getFinalizedHead` in configured interval, and headers of all
+//! blocks since the last polled one are fetched using `chain_getBlockHash` and `chain_getHeader`.
+//! Headers are sent to the same pipeline as headers received over the WebSocket subscription.
+//! Since justifications are not received, finality of polled headers is not verified by the light client.
+//! Response bodies are read incrementally, and requests fail as soon as the configured size limit is exceeded.
+//! Requests fail if the response is not received within the configured timeout, or if its status is not successful.
+//! Headers of the Avail forks are decoded using the configured header field mapping.
+
+use avail_subxt::{primitives::Header, utils::H256};
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use hyper::{
+	body::{Buf, HttpBody},
+	header::CONTENT_LENGTH,
+	Body, Method, Request, StatusCode, Uri,
+};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+use tokio::{sync::broadcast, time};
+use tracing::{debug, info, warn};
+
+use super::{header_mapping::HeaderMapping, Event};
+use crate::network::proxy::{Proxies, ProxyConnector};
+use crate::types::{OptionBlockRange, State};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeaderMode {
+	/// Headers are received over the WebSocket subscription
+	#[default]
+	Subscription,
+	/// Headers are polled over HTTP
+	Polling,
+	/// Headers are received over the WebSocket subscription, falling back to polling if subscription fails
+	SubscriptionWithPollingFallback,
+}
+
+/// Derives HTTP endpoints from the WebSocket endpoints of full nodes
+pub fn http_urls(ws_urls: &[String]) -> Vec<String> {
+	ws_urls
+		.iter()
+		.map(|url| {
+			if let Some(rest) = url.strip_prefix("wss://") {
+				format!("https://{rest}")
+			} else if let Some(rest) = url.strip_prefix("ws://") {
+				format!("http://{rest}")
+			} else {
+				url.clone()
+			}
+		})
+		.collect()
+}
+
+#[derive(Deserialize)]
+struct Response<T> {
+	result: Option<T>,
+	error: Option<Value>,
+}
+
+/// Default maximum size of the HTTP response body
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Default timeout in seconds of the HTTP requests, including reading of the response body
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+/// HTTP client supporting both HTTP and HTTPS endpoints, with limited size of the response bodies.
+/// Connections are established through the proxy configured for the target host, if any.
+#[derive(Clone)]
+pub struct HttpClient {
+	client: hyper::Client<HttpsConnector<ProxyConnector>>,
+	max_response_size: usize,
+	timeout: Duration,
+}
+
+impl HttpClient {
+	pub fn new(max_response_size: usize, timeout: Duration, proxies: Proxies) -> Self {
+		let connector = HttpsConnectorBuilder::new()
+			.with_native_roots()
+			.https_or_http()
+			.enable_http1()
+			.wrap_connector(ProxyConnector::new(proxies));
+		HttpClient {
+			client: hyper::Client::builder().build(connector),
+			max_response_size,
+			timeout,
+		}
+	}
+
+	/// Sends the request, returning response body.
+	/// Fails if the response status is not successful, or if the response is not read within the timeout.
+	pub async fn send(&self, request: Request<Body>) -> Result<Vec<u8>> {
+		let uri = request.uri().clone();
+		time::timeout(self.timeout, self.exchange(request))
+			.await
+			.map_err(|_| eyre!("Request to {uri} timed out after {:?}", self.timeout))?
+	}
+
+	async fn exchange(&self, request: Request<Body>) -> Result<Vec<u8>> {
+		let uri = request.uri().clone();
+		let response = self.client.request(request).await?;
+		check_status(&uri, response.status())?;
+		if let Some(length) = response
+			.headers()
+			.get(CONTENT_LENGTH)
+			.and_then(|length| length.to_str().ok()?.parse::<usize>().ok())
+		{
+			if length > self.max_response_size {
+				return Err(eyre!(
+					"Response body of {length} bytes exceeds the limit of {} bytes",
+					self.max_response_size
+				));
+			}
+		}
+		read_body(response.into_body(), self.max_response_size).await
+	}
+}
+
+fn check_status(uri: &Uri, status: StatusCode) -> Result<()> {
+	if !status.is_success() {
+		return Err(eyre!("Request to {uri} failed with status {status}"));
+	}
+	Ok(())
+}
+
+/// Reads the body chunk by chunk, failing as soon as it exceeds the limit
+async fn read_body<B>(body: B, limit: usize) -> Result<Vec<u8>>
+where
+	B: HttpBody,
+	B::Error: std::error::Error + Send + Sync + 'static,
+{
+	futures::pin_mut!(body);
+	let mut bytes = vec![];
+	while let Some(chunk) = body.data().await {
+		let mut chunk = chunk?;
+		if bytes.len() + chunk.remaining() > limit {
+			return Err(eyre!("Response body exceeds the limit of {limit} bytes"));
+		}
+		while chunk.has_remaining() {
+			let slice = chunk.chunk();
+			let length = slice.len();
+			bytes.extend_from_slice(slice);
+			chunk.advance(length);
+		}
+	}
+	Ok(bytes)
+}
+
+/// Sends JSON-RPC request to the given HTTP endpoint
+async fn request<T: DeserializeOwned>(client: &HttpClient, url: &str, body: &Value) -> Result<T> {
+	let request = Request::builder()
+		.method(Method::POST)
+		.uri(url)
+		.header("content-type", "application/json")
+		.body(Body::from(body.to_string()))?;
+	let bytes = client.send(request).await?;
+	let response: Response<T> =
+		serde_json::from_slice(&bytes).wrap_err("Cannot parse JSON-RPC response")?;
+	if let Some(error) = response.error {
+		return Err(eyre!("JSON-RPC error: {error}"));
+	}
+	response
+		.result
+		.ok_or_else(|| eyre!("JSON-RPC response without result"))
+}
+
+/// Fetches finalized block number from the first responding HTTP endpoint
+pub async fn finalized_block_number(client: &HttpClient, urls: &[String]) -> Result<u32> {
+	let head =
+		json!({ "jsonrpc": "2.0", "id": 1, "method": "chain_getFinalizedHead", "params": [] });
+	for url in urls {
+		let result = async {
+			let hash: H256 = request(client, url, &head).await?;
+			let body =
+				json!({ "jsonrpc": "2.0", "id": 2, "method": "chain_getHeader", "params": [hash] });
+			request::<Header>(client, url, &body).await
+		};
+		match result.await {
+			Ok(header) => return Ok(header.number),
+			Err(error) => debug!(url, "Finalized head request failed: {error:#}"),
+		}
+	}
+	Err(eyre!("Finalized head request failed on all HTTP endpoints"))
+}
+
+pub struct HeaderPoller {
+	client: HttpClient,
+	urls: Vec<String>,
+	interval: Duration,
+	state: Arc<Mutex<State>>,
+	last_block_number: Option<u32>,
+	request_id: u64,
+	mapping: Option<HeaderMapping>,
+}
+
+impl HeaderPoller {
+	pub fn new(
+		urls: Vec<String>,
+		interval: Duration,
+		client: HttpClient,
+		state: Arc<Mutex<State>>,
+	) -> Self {
+		Self {
+			client,
+			urls,
+			interval,
+			state,
+			last_block_number: None,
+			request_id: 0,
+			mapping: None,
+		}
+	}
+
+	/// Decodes polled headers using the given header field mapping
+	pub fn with_mapping(mut self, mapping: HeaderMapping) -> Self {
+		self.mapping = Some(mapping);
+		self
+	}
+
+	/// Sends request to the configured endpoints in order, until one of them responds
+	async fn request<T: DeserializeOwned>(&mut self, method: &str, params: Value) -> Result<T> {
+		self.request_id += 1;
+		let body =
+			json!({ "jsonrpc": "2.0", "id": self.request_id, "method": method, "params": params });
+
+		for url in &self.urls {
+			match request(&self.client, url, &body).await {
+				Ok(result) => return Ok(result),
+				Err(error) => debug!(url, method, "Polling request failed: {error:#}"),
+			}
+		}
+		Err(eyre!("Request {method} failed on all HTTP endpoints"))
+	}
+
+	async fn header(&mut self, hash: H256) -> Result<Header> {
+		let header: Value = self.request("chain_getHeader", json!([hash])).await?;
+		match &self.mapping {
+			Some(mapping) => mapping.decode(header),
+			None => serde_json::from_value(header).wrap_err("Cannot decode header"),
+		}
+	}
+
+	fn send(&mut self, header: Header, event_sender: &broadcast::Sender<Event>) -> Result<()> {
+		let block_number = header.number;
+		info!("Polled header no.: {block_number}");
+		{
+			let mut state = self.state.lock().expect("Lock should be acquired");
+			state.latest = block_number;
+			state.header_verified.set(block_number);
+		}
+		event_sender
+			.send(Event::HeaderUpdate {
+				header,
+				received_at: Instant::now(),
+			})
+			.map_err(|_| eyre!("Header event receivers are dropped"))?;
+		self.last_block_number = Some(block_number);
+		Ok(())
+	}
+
+	async fn poll(&mut self, event_sender: &broadcast::Sender<Event>) -> Result<()> {
+		let finalized_hash: H256 = self.request("chain_getFinalizedHead", json!([])).await?;
+		let finalized = self.header(finalized_hash).await?;
+
+		let first = self
+			.last_block_number
+			.map_or(finalized.number, |last| last + 1);
+
+		for block_number in first..finalized.number {
+			let hash: H256 = self
+				.request("chain_getBlockHash", json!([block_number]))
+				.await?;
+			let header = self.header(hash).await?;
+			self.send(header, event_sender)?;
+		}
+
+		if first <= finalized.number {
+			self.send(finalized, event_sender)?;
+		}
+		Ok(())
+	}
+
+	/// Sends polled finalized headers to the given channel
+	pub async fn run(mut self, event_sender: broadcast::Sender<Event>) -> Result<()> {
+		info!(urls = ?self.urls, "Polling finalized headers over HTTP...");
+		let mut interval = time::interval(self.interval);
+		loop {
+			interval.tick().await;
+			if let Err(error) = self.poll(&event_sender).await {
+				warn!("Polling finalized headers failed: {error:#}");
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{check_status, http_urls, read_body, Response};
+	use avail_subxt::utils::H256;
+	use hyper::{Body, StatusCode, Uri};
+
+	#[test]
+	fn derive_http_urls() {
+		let urls = http_urls(&[
+			"ws://127.0.0.1:9944".to_string(),
+			"wss://rpc.example.com/ws".to_string(),
+			"http://127.0.0.1:9933".to_string(),
+		]);
+		assert_eq!(
+			urls,
+			vec![
+				"http://127.0.0.1:9944",
+				"https://rpc.example.com/ws",
+				"http://127.0.0.1:9933"
+			]
+		);
+	}
+
+	#[test]
+	fn parse_response() {
+		let response: Response<Option<H256>> =
+			serde_json::from_str(r#"{"jsonrpc":"2.0","id":1,"result":null}"#).unwrap();
+		assert!(response.error.is_none());
+		let response: Response<H256> = serde_json::from_str(
+			r#"{"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"Method not found"}}"#,
+		)
+		.unwrap();
+		assert!(response.error.is_some());
+	}
+
+	#[test]
+	fn reject_unsuccessful_status() {
+		let uri = Uri::from_static("http://127.0.0.1:9944");
+		assert!(check_status(&uri, StatusCode::OK).is_ok());
+		assert!(check_status(&uri, StatusCode::NO_CONTENT).is_ok());
+		let error = check_status(&uri, StatusCode::SERVICE_UNAVAILABLE).unwrap_err();
+		assert!(error.to_string().contains("503"));
+		assert!(check_status(&uri, StatusCode::NOT_FOUND).is_err());
+	}
+
+	#[tokio::test]
+	async fn response_size_limit() {
+		let body = read_body(Body::from(vec![1u8; 10]), 10).await.unwrap();
+		assert_eq!(body.len(), 10);
+
+		let chunks = vec![Ok::<_, std::io::Error>(vec![1u8; 6]), Ok(vec![1u8; 6])];
+		let body = Body::wrap_stream(futures::stream::iter(chunks));
+		assert!(read_body(body, 10).await.is_err());
+	}
+}
@@ -40,19 +40,13 @@ struct BlockData {
 
 pub struct SubscriptionLoop<T: Database> {
 	rpc_client: Client,
-	event_sender: Sender<Event>,
 	state: Arc<Mutex<State>>,
 	db: T,
 	block_data: BlockData,
 }
 
 impl<T: Database> SubscriptionLoop<T> {
-	pub async fn new(
-		state: Arc<Mutex<State>>,
-		db: T,
-		rpc_client: Client,
-		event_sender: Sender<Event>,
-	) -> Result<Self> {
+	pub async fn new(state: Arc<Mutex<State>>, db: T, rpc_client: Client) -> Result<Self> {
 		// get the Hash of the Finalized Head [with Retries]
 		let last_finalized_block_hash = rpc_client.get_finalized_head_hash().await?;
 
@@ -73,7 +67,6 @@ impl<T: Database> SubscriptionLoop<T> {
 
 		Ok(Self {
 			rpc_client,
-			event_sender,
 			state,
 			db,
 			block_data: BlockData {
@@ -89,7 +82,8 @@ impl<T: Database> SubscriptionLoop<T> {
 		})
 	}
 
-	pub async fn run(mut self) -> Result<()> {
+	/// Sends verified finalized headers to the given channel, until the subscription stream fails
+	pub async fn run(mut self, event_sender: Sender<Event>) -> Result<()> {
 		// create subscriptions stream
 		let subscriptions = self.rpc_client.clone().subscription_stream().await;
 		futures::pin_mut!(subscriptions);
@@ -97,7 +91,7 @@ impl<T: Database> SubscriptionLoop<T> {
 		while let Some(result) = subscriptions.next().await {
 			match result {
 				Ok(sub) => {
-					self.handle_new_subscription(sub).await;
+					self.handle_new_subscription(sub, &event_sender).await;
 				},
 				Err(err) => return Err(eyre!(err)),
 			};
@@ -106,7 +100,11 @@ impl<T: Database> SubscriptionLoop<T> {
 		Ok(())
 	}
 
-	async fn handle_new_subscription(&mut self, subscription: Subscription) {
+	async fn handle_new_subscription(
+		&mut self,
+		subscription: Subscription,
+		event_sender: &Sender<Event>,
+	) {
 		match subscription {
 			Subscription::Header(header) => {
 				let received_at = Instant::now();
@@ -157,10 +155,10 @@ impl<T: Database> SubscriptionLoop<T> {
 			},
 		}
 		// check headers
-		self.verify_and_output_block_headers().await;
+		self.verify_and_output_block_headers(event_sender).await;
 	}
 
-	async fn verify_and_output_block_headers(&mut self) {
+	async fn verify_and_output_block_headers(&mut self, event_sender: &Sender<Event>) {
 		let mut finality_synced = false;
 		while let Some(justification) = self.block_data.justifications.pop() {
 			// iterate through Headers and try to find a matching one
@@ -225,7 +223,7 @@ impl<T: Database> SubscriptionLoop<T> {
 							},
 						};
 						// send as output event
-						self.event_sender
+						event_sender
 							.send(Event::HeaderUpdate {
 								header,
 								received_at,
@@ -239,12 +237,12 @@ impl<T: Database> SubscriptionLoop<T> {
 				self.block_data.last_finalized_block_header = Some(header.clone());
 
 				// finally, send the Verified Block Header
-				self.state
-					.lock()
-					.unwrap()
-					.header_verified
-					.set(header.number);
-				self.event_sender
+				{
+					let mut state = self.state.lock().unwrap();
+					state.header_verified.set(header.number);
+					state.finality_verified = state.finality_verified.max(Some(header.number));
+				}
+				event_sender
 					.send(Event::HeaderUpdate {
 						header,
 						received_at,
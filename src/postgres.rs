@@ -0,0 +1,375 @@
+//! Mirroring of block confidence and app data availability into Postgres.
+//!
+//! Rows are written in batches from a dedicated task, so Postgres is never on the block processing path.
+//! If mirror falls behind, skipped blocks are logged and not written.
+//! Mirror can be gated (see [`crate::export_gate`]), so only blocks with sufficient confidence
+//! and/or confirmed finality are written.
+
+use crate::{
+	confidence::Confidence,
+	data::Database,
+	export_gate::{Gate, GateConfig, FINALITY_CHECK_INTERVAL},
+	types::{BlockVerified, RetryConfig, State},
+};
+use color_eyre::{eyre::WrapErr, Result};
+use kate_recovery::com::AppData;
+use serde::{Deserialize, Serialize};
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{sync::broadcast, time};
+use tokio_postgres::{Client, NoTls};
+use tokio_retry::Retry;
+use tracing::{error, info, warn};
+
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS blocks (
+	block_number BIGINT PRIMARY KEY,
+	header_hash TEXT NOT NULL,
+	confidence DOUBLE PRECISION,
+	updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE TABLE IF NOT EXISTS app_data (
+	block_number BIGINT NOT NULL,
+	app_id BIGINT NOT NULL,
+	transactions_count INTEGER NOT NULL,
+	size BIGINT NOT NULL,
+	updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (block_number, app_id)
+);";
+
+const UPSERT_BLOCK: &str = "
+INSERT INTO blocks (block_number, header_hash, confidence) VALUES ($1, $2, $3)
+ON CONFLICT (block_number) DO UPDATE
+SET header_hash = EXCLUDED.header_hash, confidence = EXCLUDED.confidence, updated_at = now()";
+
+const UPSERT_APP_DATA: &str = "
+INSERT INTO app_data (block_number, app_id, transactions_count, size) VALUES ($1, $2, $3, $4)
+ON CONFLICT (block_number, app_id) DO UPDATE
+SET transactions_count = EXCLUDED.transactions_count, size = EXCLUDED.size, updated_at = now()";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PostgresConfig {
+	/// Postgres connection string, mirror is disabled if not set (default: None)
+	pub postgres_dsn: Option<String>,
+	/// Maximum number of rows written in a single transaction (default: 100)
+	pub postgres_batch_size: usize,
+	/// Interval in seconds after which pending rows are written, regardless of batch size (default: 5)
+	pub postgres_flush_interval: u64,
+	/// Minimum confidence in percents of the written blocks (default: None)
+	pub postgres_min_confidence: Option<Confidence>,
+	/// Write blocks only once their finality is confirmed (default: false)
+	pub postgres_require_finality: bool,
+}
+
+impl Default for PostgresConfig {
+	fn default() -> Self {
+		Self {
+			postgres_dsn: None,
+			postgres_batch_size: 100,
+			postgres_flush_interval: 5,
+			postgres_min_confidence: None,
+			postgres_require_finality: false,
+		}
+	}
+}
+
+impl PostgresConfig {
+	pub fn gate(&self) -> GateConfig {
+		GateConfig {
+			min_confidence: self.postgres_min_confidence,
+			require_finality: self.postgres_require_finality,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Row {
+	Block {
+		block_number: u32,
+		header_hash: String,
+		confidence: Option<f64>,
+	},
+	AppData {
+		block_number: u32,
+		app_id: u32,
+		transactions_count: usize,
+		size: usize,
+	},
+}
+
+impl From<&BlockVerified> for Row {
+	fn from(block: &BlockVerified) -> Self {
+		Row::Block {
+			block_number: block.block_num,
+			header_hash: format!("{:#x}", block.header_hash),
+			confidence: block.confidence.map(f64::from),
+		}
+	}
+}
+
+impl Row {
+	fn block_number(&self) -> u32 {
+		match self {
+			Row::Block { block_number, .. } => *block_number,
+			Row::AppData { block_number, .. } => *block_number,
+		}
+	}
+
+	fn app_data(app_id: u32, block_number: u32, app_data: &AppData) -> Self {
+		Row::AppData {
+			block_number,
+			app_id,
+			transactions_count: app_data.len(),
+			size: app_data.iter().map(Vec::len).sum(),
+		}
+	}
+}
+
+struct Mirror {
+	dsn: String,
+	client: Option<Client>,
+}
+
+impl Mirror {
+	async fn client(&mut self) -> Result<&Client> {
+		if self.client.as_ref().is_none_or(Client::is_closed) {
+			let (client, connection) = tokio_postgres::connect(&self.dsn, NoTls)
+				.await
+				.wrap_err("Cannot connect to Postgres")?;
+			tokio::spawn(async move {
+				if let Err(error) = connection.await {
+					warn!("Postgres connection closed: {error}");
+				}
+			});
+			client
+				.batch_execute(CREATE_TABLES)
+				.await
+				.wrap_err("Cannot create Postgres tables")?;
+			self.client = Some(client);
+		}
+		Ok(self.client.as_ref().expect("Client is connected"))
+	}
+
+	async fn write(&mut self, rows: &[Row]) -> Result<()> {
+		let client = self.client().await?;
+		let upsert_block = client.prepare(UPSERT_BLOCK).await?;
+		let upsert_app_data = client.prepare(UPSERT_APP_DATA).await?;
+
+		client.batch_execute("BEGIN").await?;
+		for row in rows {
+			let result = match row {
+				Row::Block {
+					block_number,
+					header_hash,
+					confidence,
+				} => {
+					client
+						.execute(
+							&upsert_block,
+							&[&(*block_number as i64), header_hash, confidence],
+						)
+						.await
+				},
+				Row::AppData {
+					block_number,
+					app_id,
+					transactions_count,
+					size,
+				} => {
+					client
+						.execute(
+							&upsert_app_data,
+							&[
+								&(*block_number as i64),
+								&(*app_id as i64),
+								&(*transactions_count as i32),
+								&(*size as i64),
+							],
+						)
+						.await
+				},
+			};
+			if let Err(error) = result {
+				client.batch_execute("ROLLBACK").await?;
+				return Err(error).wrap_err("Cannot write rows to Postgres");
+			}
+		}
+		client.batch_execute("COMMIT").await?;
+		Ok(())
+	}
+}
+
+/// Writes pending rows, returns the highest written block
+async fn flush(
+	mirror: &mut Mirror,
+	rows: &mut Vec<Row>,
+	retry_config: &RetryConfig,
+) -> Option<u32> {
+	if rows.is_empty() {
+		return None;
+	}
+	let highest = rows.iter().map(Row::block_number).max();
+	let mirror = tokio::sync::Mutex::new(mirror);
+	let batch: &[Row] = rows;
+	let result = Retry::spawn(retry_config.clone(), || async {
+		mirror.lock().await.write(batch).await
+	})
+	.await;
+
+	let written = match result {
+		Ok(()) => {
+			info!(rows = rows.len(), "Rows written to Postgres");
+			highest
+		},
+		Err(error) => {
+			error!(
+				rows = rows.len(),
+				"Dropping rows not written to Postgres: {error:#}"
+			);
+			None
+		},
+	};
+	rows.clear();
+	written
+}
+
+/// Runs Postgres mirror, writing verified blocks and app data availability in batches
+pub async fn run(
+	cfg: PostgresConfig,
+	retry_config: RetryConfig,
+	withheld_fraction: f64,
+	db: impl Database,
+	state: Arc<Mutex<State>>,
+	app_id: Option<u32>,
+	mut block_receiver: broadcast::Receiver<BlockVerified>,
+	mut data_receiver: Option<broadcast::Receiver<(u32, AppData)>>,
+) {
+	let Some(dsn) = cfg.postgres_dsn.clone() else {
+		return;
+	};
+	info!("Starting Postgres mirror...");
+	let mut gate = match Gate::new(
+		"postgres".to_string(),
+		cfg.gate(),
+		withheld_fraction,
+		db,
+		state,
+	) {
+		Ok(gate) => gate,
+		Err(error) => {
+			error!("Cannot load Postgres mirror watermark: {error:#}");
+			return;
+		},
+	};
+
+	let mut mirror = Mirror { dsn, client: None };
+	let mut rows = Vec::with_capacity(cfg.postgres_batch_size);
+	let mut interval = time::interval(Duration::from_secs(cfg.postgres_flush_interval));
+	let mut finality_check = time::interval(FINALITY_CHECK_INTERVAL);
+
+	loop {
+		tokio::select! {
+			_ = finality_check.tick() => release(&mut gate, &mut rows),
+			block = block_receiver.recv() => match block {
+				Ok(block) => admit(&mut gate, &mut rows, block.block_num, Row::from(&block)),
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					warn!("Postgres mirror lagged, {skipped} blocks are skipped");
+				},
+				Err(broadcast::error::RecvError::Closed) => break,
+			},
+			Some(data) = async {
+				match data_receiver.as_mut() {
+					Some(receiver) => Some(receiver.recv().await),
+					None => None,
+				}
+			} => match data {
+				Ok((block_number, app_data)) => {
+					let row = Row::app_data(app_id.unwrap_or(0), block_number, &app_data);
+					admit(&mut gate, &mut rows, block_number, row);
+				},
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					warn!("Postgres mirror lagged, {skipped} app data messages are skipped");
+				},
+				Err(broadcast::error::RecvError::Closed) => data_receiver = None,
+			},
+			_ = interval.tick() => {
+				let written = flush(&mut mirror, &mut rows, &retry_config).await;
+				advance(&mut gate, written);
+			},
+		}
+
+		if rows.len() >= cfg.postgres_batch_size {
+			let written = flush(&mut mirror, &mut rows, &retry_config).await;
+			advance(&mut gate, written);
+		}
+	}
+
+	let written = flush(&mut mirror, &mut rows, &retry_config).await;
+	advance(&mut gate, written);
+}
+
+/// Adds the row to the batch, or queues it by the gate
+fn admit(
+	gate: &mut Option<Gate<impl Database, Row>>,
+	rows: &mut Vec<Row>,
+	block_number: u32,
+	row: Row,
+) {
+	match gate {
+		Some(gate) => {
+			gate.admit(block_number, row);
+			rows.extend(gate.release().into_iter().map(|(_, row)| row));
+		},
+		None => rows.push(row),
+	}
+}
+
+/// Adds the rows released by the gate to the batch
+fn release(gate: &mut Option<Gate<impl Database, Row>>, rows: &mut Vec<Row>) {
+	if let Some(gate) = gate {
+		rows.extend(gate.release().into_iter().map(|(_, row)| row));
+	}
+}
+
+fn advance(gate: &mut Option<Gate<impl Database, Row>>, written: Option<u32>) {
+	if let (Some(gate), Some(block_number)) = (gate, written) {
+		gate.emitted(block_number);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Row;
+	use crate::{confidence::Confidence, types::BlockVerified};
+	use sp_core::H256;
+
+	#[test]
+	fn rows() {
+		let block = BlockVerified {
+			header_hash: H256::zero(),
+			block_num: 1,
+			extension: None,
+			confidence: Some(Confidence::new(99.9)),
+		};
+		assert_eq!(
+			Row::from(&block),
+			Row::Block {
+				block_number: 1,
+				header_hash: format!("{:#x}", H256::zero()),
+				confidence: Some(99.9),
+			}
+		);
+		assert_eq!(
+			Row::app_data(2, 1, &vec![vec![0u8; 10], vec![0u8; 5]]),
+			Row::AppData {
+				block_number: 1,
+				app_id: 2,
+				transactions_count: 2,
+				size: 15,
+			}
+		);
+	}
+}
@@ -1,17 +1,31 @@
-//! Parallelized proof verification
+//! Parallelized proof verification, and generation of the cell proofs from the row data
 
-use color_eyre::eyre;
-use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
+use codec::{Compact, CompactLen};
+use color_eyre::eyre::{self, eyre};
+use dusk_plonk::{
+	commitment_scheme::kzg10::PublicParameters,
+	fft::{EvaluationDomain, Evaluations, Polynomial},
+	prelude::BlsScalar,
+};
+use futures::{Stream, StreamExt};
 use itertools::{Either, Itertools};
 use kate_recovery::{
+	config,
 	data::Cell,
 	matrix::{Dimensions, Position},
 	proof,
 };
-use std::sync::Arc;
+use std::{pin::pin, sync::Arc};
 use tokio::{task::JoinSet, time::Instant};
 use tracing::debug;
 
+use crate::types::{DataLookup, CELL_WITH_PROOF_SIZE};
+
+/// Number of data bytes in the matrix cell, last byte of the chunk is zero
+const DATA_CHUNK_SIZE: usize = config::CHUNK_SIZE - 1;
+/// Original rows are at the even rows of the extended matrix
+const EXTENSION_FACTOR: u32 = 2;
+
 async fn verify_proof(
 	public_parameters: Arc<PublicParameters>,
 	dimensions: Dimensions,
@@ -61,3 +75,215 @@ pub async fn verify(
 			false => Either::Right(position),
 		}))
 }
+
+/// Verifies proofs of the cells as they are received from the stream,
+/// so verification of the received cells overlaps with fetching of the remaining ones.
+/// Returns verified cells and positions of the cells which are not verified.
+pub async fn verify_stream(
+	block_num: u32,
+	dimensions: Dimensions,
+	cells: impl Stream<Item = Cell>,
+	commitments: &[[u8; 48]],
+	public_parameters: Arc<PublicParameters>,
+) -> eyre::Result<(Vec<Cell>, Vec<Position>)> {
+	let start_time = Instant::now();
+
+	let mut tasks = JoinSet::new();
+	let mut cells = pin!(cells);
+
+	while let Some(cell) = cells.next().await {
+		let public_parameters = public_parameters.clone();
+		let commitment = commitments[cell.position.row as usize];
+		tasks.spawn(async move {
+			proof::verify(&public_parameters, dimensions, &commitment, &cell)
+				.map(|verified| (cell, verified))
+		});
+	}
+
+	let mut results = Vec::new();
+	while let Some(result) = tasks.join_next().await {
+		results.push(result??)
+	}
+
+	debug!(block_num, duration = ?start_time.elapsed(), "Proof verification completed");
+
+	Ok(results
+		.into_iter()
+		.partition_map(|(cell, is_verified)| match is_verified {
+			true => Either::Left(cell),
+			false => Either::Right(cell.position),
+		}))
+}
+
+/// Interpolates row polynomial from the row scalars, which are its evaluations over the row domain
+fn row_polynomial(scalars: &[BlsScalar]) -> eyre::Result<(EvaluationDomain, Polynomial)> {
+	let domain = EvaluationDomain::new(scalars.len())
+		.map_err(|error| eyre!("Cannot create evaluation domain: {error:?}"))?;
+	let polynomial = Evaluations::from_vec_and_domain(scalars.to_vec(), domain).interpolate();
+	Ok((domain, polynomial))
+}
+
+/// Generates cells with proofs for the given columns of the extended matrix row.
+/// Row data is a sequence of 32 bytes scalars, as returned by `kate_queryRows` RPC.
+/// Row data is rejected if it doesn't match the row commitment from the header.
+pub fn generate(
+	public_parameters: &PublicParameters,
+	row: u32,
+	row_data: &[u8],
+	commitment: &[u8; config::COMMITMENT_SIZE],
+	cols: &[u16],
+) -> eyre::Result<Vec<Cell>> {
+	if row_data.is_empty() || row_data.len() % config::CHUNK_SIZE != 0 {
+		return Err(eyre!(
+			"Row {row} has invalid length of {} bytes",
+			row_data.len()
+		));
+	}
+	let chunks = row_data
+		.chunks_exact(config::CHUNK_SIZE)
+		.collect::<Vec<_>>();
+	let scalars = chunks
+		.iter()
+		.map(|chunk| {
+			let bytes: [u8; config::CHUNK_SIZE] = (*chunk).try_into()?;
+			Option::from(BlsScalar::from_bytes(&bytes))
+				.ok_or_else(|| eyre!("Row {row} contains invalid scalar"))
+		})
+		.collect::<eyre::Result<Vec<_>>>()?;
+
+	let (domain, polynomial) = row_polynomial(&scalars)?;
+	let (commit_key, _) = public_parameters
+		.trim(scalars.len())
+		.map_err(|error| eyre!("Cannot trim public parameters: {error:?}"))?;
+	let row_commitment = commit_key
+		.commit(&polynomial)
+		.map_err(|error| eyre!("Cannot commit to row {row}: {error:?}"))?;
+	if row_commitment.0.to_compressed() != *commitment {
+		return Err(eyre!("Row {row} doesn't match its commitment"));
+	}
+	let points = domain.elements().collect::<Vec<_>>();
+
+	cols.iter()
+		.map(|&col| {
+			let point = points
+				.get(col as usize)
+				.ok_or_else(|| eyre!("Column {col} is out of row {row}"))?;
+			let witness = commit_key.compute_single_witness(&polynomial, point);
+			let proof = commit_key
+				.commit(&witness)
+				.map_err(|error| eyre!("Cannot commit to witness: {error:?}"))?;
+			let mut content = [0u8; CELL_WITH_PROOF_SIZE];
+			content[..config::COMMITMENT_SIZE].copy_from_slice(&proof.0.to_compressed());
+			content[config::COMMITMENT_SIZE..].copy_from_slice(chunks[col as usize]);
+			let position = Position { row, col };
+			Ok(Cell { position, content })
+		})
+		.collect()
+}
+
+fn compact_len(len: usize) -> usize {
+	Compact::<u32>::compact_len(&(len as u32))
+}
+
+/// Returns positions of the extended matrix cells containing app extrinsic with the given index.
+/// App extrinsics are SCALE encoded as a vector of byte vectors, and split into 31 bytes data chunks,
+/// one chunk per cell, starting from the first cell of the app range.
+pub fn extrinsic_positions(
+	lookup: &DataLookup,
+	dimensions: Dimensions,
+	app_id: u32,
+	extrinsics: &[Vec<u8>],
+	index: usize,
+) -> eyre::Result<Vec<Position>> {
+	let range = lookup
+		.range_of(app_id)
+		.ok_or_else(|| eyre!("App {app_id} has no data in the block"))?;
+	let extrinsic = extrinsics
+		.get(index)
+		.ok_or_else(|| eyre!("Extrinsic {index} of app {app_id} doesn't exist"))?;
+
+	let start = compact_len(extrinsics.len())
+		+ extrinsics[..index]
+			.iter()
+			.map(|extrinsic| compact_len(extrinsic.len()) + extrinsic.len())
+			.sum::<usize>();
+	let end = start + compact_len(extrinsic.len()) + extrinsic.len();
+
+	let first_cell = range.start + (start / DATA_CHUNK_SIZE) as u32;
+	let last_cell = range.start + ((end - 1) / DATA_CHUNK_SIZE) as u32;
+	if last_cell >= range.end {
+		return Err(eyre!(
+			"Extrinsic {index} of app {app_id} is out of app range"
+		));
+	}
+
+	let cols: u32 = dimensions.cols().get().into();
+	Ok((first_cell..=last_cell)
+		.map(|cell| Position {
+			row: (cell / cols) * EXTENSION_FACTOR,
+			col: (cell % cols) as u16,
+		})
+		.collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{extrinsic_positions, generate, row_polynomial};
+	use crate::types::DataLookup;
+	use dusk_plonk::prelude::BlsScalar;
+	use kate_recovery::{
+		matrix::{Dimensions, Position},
+		proof, testnet,
+	};
+
+	#[test]
+	fn generated_proofs_are_verified() {
+		let pp = testnet::public_params(1024);
+		let scalars = (1..=4u64).map(BlsScalar::from).collect::<Vec<_>>();
+		let row_data = scalars
+			.iter()
+			.flat_map(|scalar| scalar.to_bytes())
+			.collect::<Vec<_>>();
+
+		let (_, polynomial) = row_polynomial(&scalars).unwrap();
+		let (commit_key, _) = pp.trim(scalars.len()).unwrap();
+		let commitment = commit_key.commit(&polynomial).unwrap().0.to_compressed();
+
+		let dimensions = Dimensions::new(1, 4).unwrap();
+		let cells = generate(&pp, 1, &row_data, &commitment, &[0, 3]).unwrap();
+		assert_eq!(cells.len(), 2);
+		assert_eq!(cells[1].position, Position { row: 1, col: 3 });
+		for cell in &cells {
+			assert!(proof::verify(&pp, dimensions, &commitment, cell).unwrap());
+		}
+
+		assert!(generate(&pp, 1, &row_data, &commitment, &[4]).is_err());
+		assert!(generate(&pp, 1, &row_data[..40], &commitment, &[0]).is_err());
+
+		// Row data which doesn't match the commitment is rejected
+		let mut invalid_data = row_data.clone();
+		invalid_data[..32].copy_from_slice(&BlsScalar::from(5u64).to_bytes());
+		assert!(generate(&pp, 1, &invalid_data, &commitment, &[0]).is_err());
+	}
+
+	#[test]
+	fn positions_of_extrinsics() {
+		let lookup = DataLookup::from_id_and_len(&[(0, 2), (1, 6)]).unwrap();
+		let dimensions = Dimensions::new(2, 4).unwrap();
+		let extrinsics = vec![vec![0u8; 29], vec![1u8; 40]];
+
+		// Vector length prefix (1 byte), extrinsic length prefix (1 byte), 29 bytes of data
+		let positions = extrinsic_positions(&lookup, dimensions, 1, &extrinsics, 0).unwrap();
+		assert_eq!(positions, vec![Position { row: 0, col: 2 }]);
+
+		// Starts at byte 31, length prefix (1 byte) and 40 bytes of data, spanning two cells
+		let positions = extrinsic_positions(&lookup, dimensions, 1, &extrinsics, 1).unwrap();
+		assert_eq!(
+			positions,
+			vec![Position { row: 0, col: 3 }, Position { row: 2, col: 0 }]
+		);
+
+		assert!(extrinsic_positions(&lookup, dimensions, 1, &extrinsics, 2).is_err());
+		assert!(extrinsic_positions(&lookup, dimensions, 2, &extrinsics, 0).is_err());
+	}
+}
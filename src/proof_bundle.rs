@@ -0,0 +1,386 @@
+//! Export and offline verification of the proof bundles.
+//!
+//! Proof bundle is the `/v2/blocks/{block_number}/proof` response extended with the block header,
+//! in the format returned by the `chain_getHeader` RPC. Cell proofs are verified against the
+//! commitments from the header, without network access, so auditors can re-check availability
+//! claims from archives. Block hash and row commitments of the bundle, if present, have to match the header.
+//!
+//! Bundles of the disputed blocks (cells with invalid proofs served by the full node, or bad encoding
+//! of the reconstructed rows) are written to the configured disputes directory, and optionally uploaded to the export target.
+
+use avail_subxt::primitives::Header;
+use codec::Encode;
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use dusk_plonk::commitment_scheme::kzg10::PublicParameters;
+use kate_recovery::{
+	commitments,
+	data::Cell,
+	matrix::{Dimensions, Position},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sp_core::{blake2_256, H256};
+use std::{
+	collections::BTreeSet,
+	fmt::{self, Display, Formatter},
+	fs,
+	path::Path,
+	sync::Arc,
+};
+
+use tracing::{error, info};
+
+use crate::{
+	api::v2::types::{ProofCell, RowCommitment},
+	cell_size::CellSize,
+	export::{self, ExportTarget},
+	proof,
+	types::RuntimeConfig,
+	utils::extract_kate,
+};
+
+#[derive(Serialize, Deserialize)]
+pub struct ProofBundle {
+	pub header: Header,
+	/// Hash of the block, checked against the header hash if present
+	#[serde(default)]
+	pub block_hash: Option<H256>,
+	pub cells: Vec<ProofCell>,
+	/// Row commitments, checked against the header commitments if present
+	#[serde(default)]
+	pub commitments: Vec<RowCommitment>,
+	/// Reason for which the bundle of the disputed block is exported
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub reason: Option<String>,
+}
+
+impl ProofBundle {
+	/// Creates bundle of the disputed block, with commitments of the rows of the given cells
+	pub fn disputed(header: Header, cells: &[Cell], reason: String) -> Result<Self> {
+		let commitment = extract_kate(&header.extension)
+			.map(|(_, _, _, commitment)| commitment)
+			.ok_or_else(|| eyre!("Block {} has no data", header.number))?;
+		let commitments = commitments::from_slice(&commitment)?;
+		let rows = cells
+			.iter()
+			.map(|cell| cell.position.row)
+			.collect::<BTreeSet<_>>();
+		let commitments = rows
+			.into_iter()
+			.filter_map(|row| {
+				let commitment = commitments.get(row as usize)?;
+				Some(RowCommitment::new(row, *commitment))
+			})
+			.collect();
+		Ok(ProofBundle {
+			block_hash: Some(Encode::using_encoded(&header, blake2_256).into()),
+			header,
+			cells: cells.iter().map(ProofCell::from).collect(),
+			commitments,
+			reason: Some(reason),
+		})
+	}
+
+	pub fn load(path: &Path) -> Result<Self> {
+		let bundle = fs::read_to_string(path)
+			.wrap_err_with(|| format!("Cannot read proof bundle {}", path.display()))?;
+		serde_json::from_str(&bundle).wrap_err("Cannot parse proof bundle")
+	}
+}
+
+/// Result of the proof bundle verification
+#[derive(Debug)]
+pub struct Verdict {
+	pub block_number: u32,
+	pub block_hash: H256,
+	pub verified: usize,
+	/// Positions of the cells with invalid proofs
+	pub unverified: Vec<Position>,
+}
+
+impl Verdict {
+	/// Returns true if all cells of the bundle are verified
+	pub fn passed(&self) -> bool {
+		self.verified > 0 && self.unverified.is_empty()
+	}
+
+	/// Verdict as a single JSON line, used with the JSON console output
+	pub fn to_json(&self) -> String {
+		let unverified = self
+			.unverified
+			.iter()
+			.map(|position| json!({ "row": position.row, "col": position.col }))
+			.collect::<Vec<_>>();
+		json!({
+			"block_number": self.block_number,
+			"block_hash": self.block_hash,
+			"verified": self.verified,
+			"unverified": unverified,
+			"passed": self.passed(),
+		})
+		.to_string()
+	}
+}
+
+impl Display for Verdict {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		let total = self.verified + self.unverified.len();
+		writeln!(
+			f,
+			"Block {} ({:?}): verified {} of {total} cells",
+			self.block_number, self.block_hash, self.verified
+		)?;
+		for Position { row, col } in &self.unverified {
+			writeln!(f, "Invalid proof of cell ({row}, {col})")?;
+		}
+		let result = if self.passed() { "PASSED" } else { "FAILED" };
+		write!(f, "Verification {result}")
+	}
+}
+
+/// Export of the proof bundles of the disputed blocks (see [RuntimeConfig] for details)
+#[derive(Clone, Debug)]
+pub struct DisputeConfig {
+	/// Directory to which the bundles are written
+	pub path: String,
+	/// Export target to which the bundles are uploaded
+	pub upload: Option<ExportTarget>,
+}
+
+impl DisputeConfig {
+	pub fn from_config(cfg: &RuntimeConfig) -> Option<Self> {
+		let path = cfg.dispute_bundles_path.clone()?;
+		let upload = cfg
+			.export
+			.as_ref()
+			.filter(|_| cfg.dispute_bundles_upload)
+			.map(|export| export.target.clone());
+		Some(DisputeConfig { path, upload })
+	}
+}
+
+fn dispute_key(block_number: u32) -> String {
+	format!("disputes/{block_number}.json")
+}
+
+/// Writes proof bundle of the disputed block to the disputes directory, and uploads it if configured
+pub async fn export_disputed(
+	cfg: &DisputeConfig,
+	header: Header,
+	cells: &[Cell],
+	reason: String,
+) -> Result<()> {
+	let block_number = header.number;
+	let bundle = ProofBundle::disputed(header, cells, reason)?;
+	let bundle = serde_json::to_vec_pretty(&bundle).wrap_err("Cannot serialize proof bundle")?;
+
+	let directory = ExportTarget::Directory {
+		path: cfg.path.clone(),
+	};
+	export::storage(&directory)?
+		.put(&format!("{block_number}.json"), bundle.clone())
+		.await?;
+	info!(
+		block_number,
+		"Proof bundle of the disputed block is written to {}", cfg.path
+	);
+
+	if let Some(target) = &cfg.upload {
+		export::storage(target)?
+			.put(&dispute_key(block_number), bundle)
+			.await?;
+		info!(
+			block_number,
+			"Proof bundle of the disputed block is uploaded"
+		);
+	}
+	Ok(())
+}
+
+/// Exports proof bundle of the disputed block in the background, logging the failure
+pub fn spawn_export(cfg: DisputeConfig, header: Header, cells: Vec<Cell>, reason: String) {
+	let block_number = header.number;
+	tokio::spawn(async move {
+		if let Err(error) = export_disputed(&cfg, header, &cells, reason).await {
+			error!(
+				block_number,
+				"Cannot export proof bundle of the disputed block: {error:#}"
+			);
+		}
+	});
+}
+
+/// Verifies cell proofs of the bundle against the commitments from the bundle header.
+/// Returns an error if the bundle is inconsistent with the header.
+pub async fn verify(
+	bundle: ProofBundle,
+	public_parameters: Arc<PublicParameters>,
+) -> Result<Verdict> {
+	let header = bundle.header;
+	let block_number = header.number;
+	let block_hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+	if let Some(expected) = bundle.block_hash {
+		if expected != block_hash {
+			return Err(eyre!(
+				"Block hash {expected:?} doesn't match header hash {block_hash:?}"
+			));
+		}
+	}
+
+	let Some((rows, cols, _, commitment)) = extract_kate(&header.extension) else {
+		return Err(eyre!("Block {block_number} has no data"));
+	};
+	// Bundle cells are verified with the cell size supported by `kate_recovery`
+	CellSize::default().check(&header)?;
+	let dimensions =
+		Dimensions::new(rows, cols).ok_or_else(|| eyre!("Invalid block dimensions"))?;
+	let commitments = commitments::from_slice(&commitment)?;
+
+	if let Some(row_commitment) = bundle
+		.commitments
+		.iter()
+		.find(|row_commitment| !row_commitment.matches(&commitments))
+	{
+		return Err(eyre!(
+			"Commitment of row {} doesn't match header commitment",
+			row_commitment.row()
+		));
+	}
+
+	if bundle.cells.is_empty() {
+		return Err(eyre!("Proof bundle contains no cells"));
+	}
+	let cells = bundle
+		.cells
+		.iter()
+		.map(Cell::try_from)
+		.collect::<Result<Vec<_>>>()?;
+	if let Some(Cell { position, .. }) = cells
+		.iter()
+		.find(|cell| cell.position.row >= dimensions.extended_rows() || cell.position.col >= cols)
+	{
+		return Err(eyre!(
+			"Cell ({}, {}) is out of {}x{cols} extended matrix",
+			position.row,
+			position.col,
+			dimensions.extended_rows()
+		));
+	}
+
+	let (verified, unverified) = proof::verify(
+		block_number,
+		dimensions,
+		&cells,
+		&commitments,
+		public_parameters,
+	)
+	.await?;
+	Ok(Verdict {
+		block_number,
+		block_hash,
+		verified: verified.len(),
+		unverified,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{verify, ProofBundle};
+	use crate::{api::v2::types::ProofCell, proof};
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		primitives::Header,
+	};
+	use dusk_plonk::{
+		fft::{EvaluationDomain, Evaluations},
+		prelude::BlsScalar,
+	};
+	use kate_recovery::{matrix::Position, testnet};
+	use serde_json::json;
+	use sp_core::H256;
+	use std::sync::Arc;
+	use subxt::config::substrate::Digest;
+
+	fn header(commitment: Vec<u8>) -> Header {
+		Header {
+			parent_hash: H256::default(),
+			number: 1,
+			state_root: H256::default(),
+			extrinsics_root: H256::default(),
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment {
+					rows: 1,
+					cols: 4,
+					commitment,
+					data_root: H256::default(),
+				},
+				app_lookup: CompactDataLookup {
+					size: 1,
+					index: vec![],
+				},
+			}),
+			digest: Digest { logs: vec![] },
+		}
+	}
+
+	fn bundle(commitment: Vec<u8>, cells: Vec<ProofCell>, block_hash: Option<H256>) -> ProofBundle {
+		let header = header(commitment);
+		let bundle = json!({ "header": header, "block_hash": block_hash, "cells": cells });
+		serde_json::from_value(bundle).unwrap()
+	}
+
+	#[tokio::test]
+	async fn proof_bundle_verification() {
+		let pp = Arc::new(testnet::public_params(1024));
+		let scalars = (1..=4u64).map(BlsScalar::from).collect::<Vec<_>>();
+		let row_data = scalars
+			.iter()
+			.flat_map(|scalar| scalar.to_bytes())
+			.collect::<Vec<_>>();
+		let domain = EvaluationDomain::new(scalars.len()).unwrap();
+		let polynomial = Evaluations::from_vec_and_domain(scalars, domain).interpolate();
+		let (commit_key, _) = pp.trim(4).unwrap();
+		let row_commitment = commit_key.commit(&polynomial).unwrap().0.to_compressed();
+		// Both rows of the extended matrix have the same commitment, only the first one is sampled
+		let commitment = [row_commitment, row_commitment].concat();
+
+		let mut cells = proof::generate(&pp, 0, &row_data, &row_commitment, &[0, 3]).unwrap();
+		let proof_cells = cells.iter().map(ProofCell::from).collect::<Vec<_>>();
+		let verdict = verify(bundle(commitment.clone(), proof_cells, None), pp.clone())
+			.await
+			.unwrap();
+		assert!(verdict.passed());
+		assert_eq!(verdict.verified, 2);
+
+		cells[1].position.col = 1;
+		let proof_cells = cells.iter().map(ProofCell::from).collect::<Vec<_>>();
+		let verdict = verify(
+			bundle(commitment.clone(), proof_cells.clone(), None),
+			pp.clone(),
+		)
+		.await
+		.unwrap();
+		assert!(!verdict.passed());
+		assert_eq!(verdict.unverified, vec![Position { row: 0, col: 1 }]);
+
+		// Disputed bundle contains the block hash and the commitments of the sampled rows
+		let disputed = ProofBundle::disputed(header(commitment.clone()), &cells, "test".into());
+		let disputed = serde_json::to_string(&disputed.unwrap()).unwrap();
+		let disputed: ProofBundle = serde_json::from_str(&disputed).unwrap();
+		assert_eq!(disputed.commitments.len(), 1);
+		let verdict = verify(disputed, pp.clone()).await.unwrap();
+		assert_eq!(verdict.unverified, vec![Position { row: 0, col: 1 }]);
+
+		let wrong_hash = Some(H256::repeat_byte(1));
+		assert!(verify(bundle(commitment, proof_cells, wrong_hash), pp)
+			.await
+			.is_err());
+	}
+}
@@ -0,0 +1,202 @@
+//! Reconciliation of the stored state with the chain on startup.
+//!
+//! Latest verified block is stored as the chain checkpoint, together with the genesis hash.
+//! On startup, the checkpoint is compared with the chain of the connected node. If the genesis hash differs,
+//! or the node doesn't have the checkpoint block (or has a different one), the node was switched
+//! to another network or resynced, and the light client refuses to start, unless the `--wipe` flag
+//! is used to remove the stored chain data. If the node is behind the checkpoint, reconciliation waits
+//! for the node to catch up, up to [`CATCH_UP_TIMEOUT`]. State is reconciled before the P2P network is started.
+//! Blocks finalized while the light client was stopped are backfilled by the sync client, up to the configured limit.
+
+use avail_subxt::utils::H256;
+use color_eyre::Result;
+use std::{ops::Range, time::Duration};
+use tokio::{
+	sync::broadcast::{self, error::RecvError},
+	time::{self, Instant},
+};
+use tracing::warn;
+
+use crate::{
+	data::{ChainCheckpoint, Database, Key},
+	network::rpc,
+	types::BlockVerified,
+};
+
+/// Maximum time to wait for the node which is behind the checkpoint, after which it is considered resynced
+pub const CATCH_UP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Interval in which the head of the node, which is behind the checkpoint, is checked
+const CATCH_UP_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Outcome of the comparison of the stored checkpoint with the chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reconciliation {
+	/// No block was verified with the stored state
+	Fresh,
+	/// Stored state belongs to the chain, blocks in the range are to be backfilled
+	Continue { backfill: Range<u32> },
+	/// Stored state doesn't belong to the chain of the connected node
+	Mismatch(String),
+}
+
+/// Compares the checkpoint with the chain which has the given genesis hash and head. Hash of the checkpoint
+/// block on the chain is `None` if the chain doesn't have it. At most `max_backfill` latest missed blocks are backfilled.
+pub fn reconcile(
+	checkpoint: Option<ChainCheckpoint>,
+	genesis_hash: H256,
+	head: u32,
+	checkpoint_block_hash: Option<H256>,
+	max_backfill: u32,
+) -> Reconciliation {
+	let Some(checkpoint) = checkpoint else {
+		return Reconciliation::Fresh;
+	};
+
+	if checkpoint.genesis_hash != genesis_hash {
+		return Reconciliation::Mismatch(format!(
+			"Stored state belongs to the network with genesis hash {:?}, but the connected node has genesis hash {genesis_hash:?}",
+			checkpoint.genesis_hash
+		));
+	}
+
+	if checkpoint.block_number > head {
+		return Reconciliation::Mismatch(format!(
+			"Block {} is already verified, but the connected node is still at block {head}, it was probably resynced",
+			checkpoint.block_number
+		));
+	}
+
+	if checkpoint_block_hash != Some(checkpoint.block_hash) {
+		return Reconciliation::Mismatch(format!(
+			"Verified block {} has hash {:?}, but the connected node has hash {checkpoint_block_hash:?}",
+			checkpoint.block_number, checkpoint.block_hash
+		));
+	}
+
+	let start = (checkpoint.block_number + 1).max(head.saturating_sub(max_backfill));
+	Reconciliation::Continue {
+		backfill: start..head,
+	}
+}
+
+/// Compares the stored checkpoint with the chain of the connected node, at its finalized head
+pub async fn check(
+	db: impl Database,
+	client: &rpc::Client,
+	max_backfill: u32,
+) -> Result<Reconciliation> {
+	let checkpoint: Option<ChainCheckpoint> = db.get(Key::ChainCheckpoint)?;
+	let genesis_hash = client.current_client().await.genesis_hash();
+
+	let deadline = Instant::now() + CATCH_UP_TIMEOUT;
+	let head = loop {
+		let head = client.get_chain_head_header().await?.number;
+		match &checkpoint {
+			Some(checkpoint)
+				if checkpoint.genesis_hash == genesis_hash
+					&& checkpoint.block_number > head
+					&& Instant::now() < deadline =>
+			{
+				warn!(
+					"Connected node is at block {head}, behind the verified block {}, waiting for it to catch up",
+					checkpoint.block_number
+				);
+				time::sleep(CATCH_UP_CHECK_INTERVAL).await;
+			},
+			_ => break head,
+		}
+	};
+
+	let checkpoint_block_hash = match &checkpoint {
+		Some(checkpoint) if checkpoint.block_number <= head => {
+			client.find_block_hash(checkpoint.block_number).await?
+		},
+		_ => None,
+	};
+
+	Ok(reconcile(
+		checkpoint,
+		genesis_hash,
+		head,
+		checkpoint_block_hash,
+		max_backfill,
+	))
+}
+
+/// Stores the latest verified block as the chain checkpoint. Blocks verified out of order don't move it back.
+pub async fn store_checkpoints(
+	db: impl Database,
+	genesis_hash: H256,
+	mut receiver: broadcast::Receiver<BlockVerified>,
+) {
+	let mut latest = None;
+	loop {
+		let block = match receiver.recv().await {
+			Ok(block) => block,
+			Err(RecvError::Lagged(_)) => continue,
+			Err(RecvError::Closed) => return,
+		};
+		if latest.is_some_and(|latest| latest >= block.block_num) {
+			continue;
+		}
+		latest = Some(block.block_num);
+
+		let checkpoint = ChainCheckpoint {
+			genesis_hash,
+			block_number: block.block_num,
+			block_hash: block.header_hash,
+		};
+		if let Err(error) = db.put(Key::ChainCheckpoint, checkpoint) {
+			warn!("Cannot store chain checkpoint: {error:#}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{reconcile, Reconciliation};
+	use crate::data::ChainCheckpoint;
+	use avail_subxt::utils::H256;
+
+	const GENESIS: H256 = H256::repeat_byte(1);
+
+	fn checkpoint(block_number: u32) -> Option<ChainCheckpoint> {
+		Some(ChainCheckpoint {
+			genesis_hash: GENESIS,
+			block_number,
+			block_hash: H256::repeat_byte(2),
+		})
+	}
+
+	#[test]
+	fn reconcile_with_chain() {
+		let hash = Some(H256::repeat_byte(2));
+		assert_eq!(
+			reconcile(None, GENESIS, 100, None, 10),
+			Reconciliation::Fresh
+		);
+		assert_eq!(
+			reconcile(checkpoint(95), GENESIS, 100, hash, 10),
+			Reconciliation::Continue { backfill: 96..100 }
+		);
+		// Only the latest blocks are backfilled
+		assert_eq!(
+			reconcile(checkpoint(50), GENESIS, 100, hash, 10),
+			Reconciliation::Continue { backfill: 90..100 }
+		);
+		assert_eq!(
+			reconcile(checkpoint(50), GENESIS, 100, hash, 0),
+			Reconciliation::Continue { backfill: 100..100 }
+		);
+
+		let mismatches = [
+			reconcile(checkpoint(95), H256::repeat_byte(3), 100, hash, 10),
+			reconcile(checkpoint(105), GENESIS, 100, None, 10),
+			reconcile(checkpoint(95), GENESIS, 100, Some(H256::zero()), 10),
+		];
+		for reconciliation in mismatches {
+			assert!(matches!(reconciliation, Reconciliation::Mismatch(_)));
+		}
+	}
+}
@@ -0,0 +1,124 @@
+//! Network-wide replication statistics, gathered by probing the DHT.
+//!
+//! For each received header, after configured delay, random cells of the block are looked up
+//! in the DHT without stopping on the first found record. Distinct peers serving each cell are counted
+//! (up to configured quorum), and average count is reported as a replication estimate of the block.
+
+use crate::{
+	network::{
+		p2p::{record::Reference, Client},
+		rpc::{self, Event},
+	},
+	telemetry::{MetricValue, Metrics},
+	types::{self, Delay},
+};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ReplicationProbeConfig {
+	/// Probe DHT for replication of recent blocks records, more expensive than regular lookups (default: false)
+	pub replication_probe: bool,
+	/// Delay in seconds after the header is received, before its records are probed (default: 20)
+	pub replication_probe_delay: u64,
+	/// Number of random cells probed per block (default: 5)
+	pub replication_probe_cells: usize,
+	/// Maximum number of distinct peers counted per record (default: 20)
+	pub replication_probe_quorum: usize,
+}
+
+impl Default for ReplicationProbeConfig {
+	fn default() -> Self {
+		Self {
+			replication_probe: false,
+			replication_probe_delay: 20,
+			replication_probe_cells: 5,
+			replication_probe_quorum: 20,
+		}
+	}
+}
+
+/// Average number of peers serving the probed records
+fn replication_estimate(peer_counts: &[usize]) -> f64 {
+	if peer_counts.is_empty() {
+		return 0.0;
+	}
+	peer_counts.iter().sum::<usize>() as f64 / peer_counts.len() as f64
+}
+
+pub async fn run(
+	mut message_rx: broadcast::Receiver<Event>,
+	network_client: Client,
+	cfg: ReplicationProbeConfig,
+	metrics: Arc<impl Metrics>,
+) {
+	info!("Starting replication prober...");
+
+	let delay = Delay(Some(Duration::from_secs(cfg.replication_probe_delay)));
+
+	while let Ok(rpc::Event::HeaderUpdate {
+		header,
+		received_at,
+	}) = message_rx.recv().await
+	{
+		let block = match types::BlockVerified::try_from((header, None)) {
+			Ok(block) => block,
+			Err(error) => {
+				error!("Header is not valid: {error}");
+				continue;
+			},
+		};
+
+		let Some(extension) = &block.extension else {
+			debug!("Skipping block without header extension");
+			continue;
+		};
+
+		if let Some(duration) = delay.sleep_duration(received_at) {
+			tokio::time::sleep(duration).await;
+		}
+
+		let block_number = block.block_num;
+		let mut peer_counts = vec![];
+		let positions =
+			rpc::generate_random_cells(extension.dimensions, cfg.replication_probe_cells as u32);
+		for position in positions {
+			let reference = Reference::Cell {
+				block_number,
+				position,
+			};
+			match network_client
+				.probe_record_replication(reference, cfg.replication_probe_quorum)
+				.await
+			{
+				Ok(peers) => peer_counts.push(peers),
+				Err(error) => debug!(block_number, "Probing cell replication failed: {error}"),
+			}
+		}
+
+		let estimate = replication_estimate(&peer_counts);
+		info!(
+			block_number,
+			estimate,
+			probed = peer_counts.len(),
+			"Estimated DHT replication"
+		);
+		metrics
+			.record(MetricValue::DHTReplicationEstimate(estimate))
+			.await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::replication_estimate;
+
+	#[test]
+	fn estimate() {
+		assert_eq!(replication_estimate(&[]), 0.0);
+		assert_eq!(replication_estimate(&[2, 4, 6]), 4.0);
+	}
+}
@@ -0,0 +1,334 @@
+//! Availability report over a range of blocks.
+//!
+//! Report aggregates per-block confidence, confidence computed only from the cells fetched from the DHT,
+//! share of the sampled cells fetched from the DHT, and processing failures, as stored by the light client.
+//! Report is signed with the Avail identity key (sr25519), so operators can attest availability over a period.
+//! Signature is calculated over the [`SIGNING_CONTEXT`] followed by the `report` field serialized as compact JSON,
+//! so the signature cannot be used for anything else (e.g. as a transaction signature).
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use serde::{Deserialize, Serialize};
+use sp_core::crypto::{AccountId32, Ss58Codec};
+use std::{
+	ops::RangeInclusive,
+	time::{SystemTime, UNIX_EPOCH},
+};
+use subxt_signer::sr25519::{self, Keypair};
+
+use crate::{
+	block_state::{self, BlockState},
+	confidence::Confidence,
+	data::{CellSource, Database, Key, SampledCell},
+};
+
+/// Maximum number of blocks in a single report
+pub const MAX_BLOCKS: u32 = 100_000;
+
+/// Context prefixed to the signed report
+pub const SIGNING_CONTEXT: &[u8] = b"avail-light-availability-report:";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockReport {
+	pub block_number: u32,
+	/// Confidence, if block is verified
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub confidence: Option<Confidence>,
+	/// Confidence computed only from the sampled cells fetched from the DHT, if sampled cells are stored
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub p2p_confidence: Option<Confidence>,
+	/// Percentage of sampled cells fetched from the DHT, if sampled cells are stored
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub dht_hit_rate: Option<f64>,
+	/// Failure reason, if block processing failed
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub failure: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Summary {
+	/// Number of blocks in the range
+	pub blocks: u32,
+	/// Number of verified blocks
+	pub verified: u32,
+	/// Number of blocks which processing failed
+	pub failed: u32,
+	/// Number of blocks which are neither verified nor failed
+	pub missing: u32,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub average_confidence: Option<f64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub min_confidence: Option<f64>,
+	/// Average confidence computed only from the sampled cells fetched from the DHT
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub average_p2p_confidence: Option<f64>,
+	/// Percentage of all sampled cells fetched from the DHT
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub dht_hit_rate: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AvailabilityReport {
+	pub first: u32,
+	pub last: u32,
+	/// Unix timestamp in seconds
+	pub generated_at: u64,
+	/// Light client version
+	pub version: String,
+	pub summary: Summary,
+	pub blocks: Vec<BlockReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SignedReport {
+	pub report: AvailabilityReport,
+	/// Avail ss58 address of the signer
+	pub signer: String,
+	/// Hex encoded sr25519 public key of the signer
+	pub public_key: String,
+	/// Hex encoded sr25519 signature of the report
+	pub signature: String,
+}
+
+fn percentage(part: usize, total: usize) -> Option<f64> {
+	(total > 0).then(|| part as f64 * 100.0 / total as f64)
+}
+
+fn dht_cells(sampled_cells: &[SampledCell]) -> usize {
+	sampled_cells
+		.iter()
+		.filter(|cell| cell.source == CellSource::DHT)
+		.count()
+}
+
+fn p2p_confidence(sampled_cells: &[SampledCell], withheld_fraction: f64) -> Option<Confidence> {
+	(!sampled_cells.is_empty())
+		.then(|| Confidence::from_cell_count(dht_cells(sampled_cells) as u32, withheld_fraction))
+}
+
+/// Loads confidence computed only from the sampled cells of the block fetched from the DHT,
+/// in contrast to the block confidence which is computed from the cells fetched from all sources
+pub fn block_p2p_confidence(
+	db: &impl Database,
+	block_number: u32,
+	withheld_fraction: f64,
+) -> Result<Option<Confidence>> {
+	let sampled_cells = db
+		.get::<Vec<SampledCell>>(Key::SampledCells(block_number))?
+		.unwrap_or_default();
+	Ok(p2p_confidence(&sampled_cells, withheld_fraction))
+}
+
+fn average(values: &[f64]) -> Option<f64> {
+	(!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// Generates availability report for the given range of blocks
+pub fn generate(
+	db: &impl Database,
+	range: RangeInclusive<u32>,
+	version: String,
+	withheld_fraction: f64,
+) -> Result<AvailabilityReport> {
+	let (first, last) = (*range.start(), *range.end());
+	if range.is_empty() {
+		return Err(eyre!("Invalid block range {first}..={last}"));
+	}
+	if last - first >= MAX_BLOCKS {
+		return Err(eyre!("Report can contain at most {MAX_BLOCKS} blocks"));
+	}
+
+	let mut blocks = vec![];
+	let (mut sampled_total, mut sampled_dht) = (0, 0);
+	for block_number in range {
+		let confidence = db
+			.get::<u32>(Key::VerifiedCellCount(block_number))?
+			.map(|count| Confidence::from_cell_count(count, withheld_fraction));
+		let sampled_cells = db
+			.get::<Vec<SampledCell>>(Key::SampledCells(block_number))?
+			.unwrap_or_default();
+		let dht = dht_cells(&sampled_cells);
+		sampled_total += sampled_cells.len();
+		sampled_dht += dht;
+		let failure = match block_state::get(db, block_number)? {
+			Some(BlockState::Failed(reason)) => Some(reason),
+			_ => None,
+		};
+		blocks.push(BlockReport {
+			block_number,
+			confidence,
+			p2p_confidence: p2p_confidence(&sampled_cells, withheld_fraction),
+			dht_hit_rate: percentage(dht, sampled_cells.len()),
+			failure,
+		});
+	}
+
+	let confidences = blocks
+		.iter()
+		.filter_map(|block| block.confidence.map(f64::from))
+		.collect::<Vec<_>>();
+	let failed = blocks
+		.iter()
+		.filter(|block| block.confidence.is_none() && block.failure.is_some())
+		.count() as u32;
+	let p2p_confidences = blocks
+		.iter()
+		.filter_map(|block| block.p2p_confidence.map(f64::from))
+		.collect::<Vec<_>>();
+	let verified = confidences.len() as u32;
+	let summary = Summary {
+		blocks: blocks.len() as u32,
+		verified,
+		failed,
+		missing: blocks.len() as u32 - verified - failed,
+		average_confidence: average(&confidences),
+		min_confidence: confidences.iter().copied().reduce(f64::min),
+		average_p2p_confidence: average(&p2p_confidences),
+		dht_hit_rate: percentage(sampled_dht, sampled_total),
+	};
+
+	let generated_at = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or_default();
+
+	Ok(AvailabilityReport {
+		first,
+		last,
+		generated_at,
+		version,
+		summary,
+		blocks,
+	})
+}
+
+fn signed_payload(report: &AvailabilityReport) -> Result<Vec<u8>> {
+	let report = serde_json::to_vec(report).wrap_err("Failed to serialize report")?;
+	Ok([SIGNING_CONTEXT, &report].concat())
+}
+
+/// Signs the report with the given key pair
+pub fn sign(report: AvailabilityReport, key_pair: &Keypair) -> Result<SignedReport> {
+	let signature = key_pair.sign(&signed_payload(&report)?);
+	let public_key = key_pair.public_key();
+	Ok(SignedReport {
+		report,
+		signer: AccountId32::from(public_key.0).to_ss58check(),
+		public_key: format!("0x{}", hex::encode(public_key.0)),
+		signature: format!("0x{}", hex::encode(signature.0)),
+	})
+}
+
+/// Verifies the report signature against the public key of the signer
+pub fn verify(signed: &SignedReport) -> Result<bool> {
+	let decode = |value: &str| hex::decode(value.trim_start_matches("0x"));
+	let public_key = decode(&signed.public_key)
+		.ok()
+		.and_then(|key| key.try_into().ok())
+		.map(sr25519::PublicKey)
+		.ok_or_else(|| eyre!("Invalid public key"))?;
+	let signature = decode(&signed.signature)
+		.ok()
+		.and_then(|signature| signature.try_into().ok())
+		.map(sr25519::Signature)
+		.ok_or_else(|| eyre!("Invalid signature"))?;
+	let payload = signed_payload(&signed.report)?;
+	Ok(sr25519::verify(&signature, payload, &public_key))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{generate, sign, verify};
+	use crate::{
+		block_state::BlockState,
+		data::{mem_db::MemoryDB, CellSource, Database, Key, SampledCell},
+		utils::DEFAULT_WITHHELD_FRACTION,
+	};
+	use std::ops::RangeInclusive;
+	use subxt_signer::sr25519::Keypair;
+
+	fn cell(source: CellSource) -> SampledCell {
+		SampledCell {
+			row: 0,
+			col: 0,
+			source,
+		}
+	}
+
+	#[test]
+	fn report_summary() {
+		let db = MemoryDB::default();
+		db.put(Key::VerifiedCellCount(1), 8u32).unwrap();
+		db.put(
+			Key::SampledCells(1),
+			vec![
+				cell(CellSource::DHT),
+				cell(CellSource::DHT),
+				cell(CellSource::RPC),
+			],
+		)
+		.unwrap();
+		db.put(Key::VerifiedCellCount(2), 10u32).unwrap();
+		db.put(Key::SampledCells(2), vec![cell(CellSource::RPC)])
+			.unwrap();
+		db.put(
+			Key::BlockState(3),
+			BlockState::Failed("timeout".to_string()),
+		)
+		.unwrap();
+
+		let report = generate(&db, 1..=4, "v1".to_string(), DEFAULT_WITHHELD_FRACTION).unwrap();
+		assert_eq!(report.blocks.len(), 4);
+		assert_eq!(report.summary.verified, 2);
+		assert_eq!(report.summary.failed, 1);
+		assert_eq!(report.summary.missing, 1);
+		assert_eq!(report.summary.dht_hit_rate, Some(50.0));
+		assert_eq!(report.blocks[2].failure.as_deref(), Some("timeout"));
+		assert_eq!(report.blocks[3].confidence, None);
+		assert!(report.summary.min_confidence < report.summary.average_confidence);
+		assert!(report.blocks[0].p2p_confidence < report.blocks[0].confidence);
+		assert_eq!(report.blocks[3].p2p_confidence, None);
+		assert!(report.summary.average_p2p_confidence < report.summary.average_confidence);
+	}
+
+	#[test]
+	fn invalid_range() {
+		let db = MemoryDB::default();
+		let reversed = RangeInclusive::new(2, 1);
+		assert!(generate(&db, reversed, "v1".to_string(), DEFAULT_WITHHELD_FRACTION).is_err());
+		assert!(generate(
+			&db,
+			0..=super::MAX_BLOCKS,
+			"v1".to_string(),
+			DEFAULT_WITHHELD_FRACTION
+		)
+		.is_err());
+	}
+
+	#[test]
+	fn signed_report() {
+		let db = MemoryDB::default();
+		db.put(Key::VerifiedCellCount(1), 8u32).unwrap();
+		let report = generate(&db, 1..=1, "v1".to_string(), DEFAULT_WITHHELD_FRACTION).unwrap();
+		let key_pair = Keypair::from_uri(&"//Alice".parse().unwrap()).unwrap();
+		let signed = sign(report, &key_pair).unwrap();
+		assert!(verify(&signed).unwrap());
+
+		// Signature survives JSON round trip
+		let json = serde_json::to_string(&signed).unwrap();
+		let mut signed: super::SignedReport = serde_json::from_str(&json).unwrap();
+		assert!(verify(&signed).unwrap());
+
+		// Report is signed with the context
+		let key_pair = Keypair::from_uri(&"//Alice".parse().unwrap()).unwrap();
+		let payload = serde_json::to_vec(&signed.report).unwrap();
+		signed.signature = format!("0x{}", hex::encode(key_pair.sign(&payload).0));
+		assert!(!verify(&signed).unwrap());
+
+		let mut signed = sign(signed.report, &key_pair).unwrap();
+		signed.report.summary.verified = 0;
+		assert!(!verify(&signed).unwrap());
+	}
+}
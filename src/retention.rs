@@ -0,0 +1,208 @@
+//! Retention re-check of the blocks within the availability window.
+//!
+//! Optionally, a small number of random verified blocks within the availability window is periodically
+//! re-sampled from the DHT, to confirm that their data remains retrievable after verification.
+//! Result of the latest check is stored per block, separately from the confidence achieved at verification,
+//! and confidence calculated from the retrieved cells is recorded as a retention confidence metric.
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use kate_recovery::{commitments, matrix::Dimensions};
+use rand::seq::IteratorRandom;
+use serde::{Deserialize, Serialize};
+use std::{
+	sync::{Arc, Mutex},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, error, info};
+
+use crate::{
+	confidence::Confidence,
+	data::{Database, Key, RetentionCheck},
+	network::{self, rpc},
+	sync_client,
+	telemetry::{MetricValue, Metrics},
+	types::State,
+	utils::extract_kate,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct RetentionCheckConfig {
+	/// Periodically re-sample random verified blocks within the availability window from the DHT (default: false)
+	pub retention_check: bool,
+	/// Interval in seconds between the retention checks (default: 600)
+	pub retention_check_interval: u64,
+	/// Number of random blocks re-sampled per check (default: 2)
+	pub retention_check_blocks: usize,
+	/// Number of random cells re-sampled per block (default: 8)
+	pub retention_check_cells: u32,
+	/// Availability window in blocks, counted from the latest block (default: 4320)
+	pub retention_check_window: u32,
+}
+
+impl Default for RetentionCheckConfig {
+	fn default() -> Self {
+		Self {
+			retention_check: false,
+			retention_check_interval: 600,
+			retention_check_blocks: 2,
+			retention_check_cells: 8,
+			retention_check_window: 4320,
+		}
+	}
+}
+
+/// Picks random verified blocks within the window ending with the latest block
+fn pick_blocks(db: &impl Database, latest: u32, window: u32, count: usize) -> Result<Vec<u32>> {
+	let first = latest.saturating_sub(window).max(1);
+	let mut verified = vec![];
+	for block_number in first..=latest {
+		if db
+			.get::<u32>(Key::VerifiedCellCount(block_number))?
+			.is_some()
+		{
+			verified.push(block_number);
+		}
+	}
+	Ok(verified
+		.into_iter()
+		.choose_multiple(&mut rand::thread_rng(), count))
+}
+
+/// Re-samples the block from the DHT and stores the result.
+/// Returns `None` if block has no data to sample.
+async fn check_block(
+	db: &impl Database,
+	client: &impl sync_client::Client,
+	network_client: &impl network::Client,
+	block_number: u32,
+	cells: u32,
+	cancel: &CancellationToken,
+) -> Result<Option<RetentionCheck>> {
+	let (header, header_hash) = client.get_header_by_block_number(block_number).await?;
+	let Some((rows, cols, _, commitment)) = extract_kate(&header.extension) else {
+		return Ok(None);
+	};
+	let dimensions = Dimensions::new(rows, cols).ok_or_else(|| eyre!("Invalid dimensions"))?;
+	let commitments = commitments::from_slice(&commitment)?;
+	let positions = rpc::generate_random_cells(dimensions, cells);
+
+	let (fetched, _, _) = network_client
+		.fetch_verified(
+			block_number,
+			header_hash,
+			dimensions,
+			&commitments,
+			&positions,
+			cancel,
+		)
+		.await?;
+
+	let checked_at = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or_default();
+	let check = RetentionCheck {
+		sampled: positions.len() as u32,
+		retrieved: fetched.len() as u32,
+		checked_at,
+	};
+	db.put(Key::RetentionCheck(block_number), check.clone())
+		.wrap_err("Failed to store retention check")?;
+	Ok(Some(check))
+}
+
+/// Periodically re-checks retention of the random blocks within the availability window.
+/// Network client is expected to fetch cells from the DHT only.
+/// Checks stop once the token is cancelled, cancelling the in-flight check.
+pub async fn run(
+	db: impl Database,
+	client: impl sync_client::Client,
+	network_client: impl network::Client,
+	cfg: RetentionCheckConfig,
+	withheld_fraction: f64,
+	state: Arc<Mutex<State>>,
+	metrics: Arc<impl Metrics>,
+	cancel: CancellationToken,
+) {
+	info!("Starting retention checks...");
+
+	let mut interval = tokio::time::interval(Duration::from_secs(cfg.retention_check_interval));
+	loop {
+		interval.tick().await;
+		let latest = state.lock().expect("Lock should be acquired").latest;
+		let blocks = match pick_blocks(
+			&db,
+			latest,
+			cfg.retention_check_window,
+			cfg.retention_check_blocks,
+		) {
+			Ok(blocks) => blocks,
+			Err(error) => {
+				error!("Cannot pick blocks for retention check: {error:#}");
+				continue;
+			},
+		};
+
+		for block_number in blocks {
+			let check = check_block(
+				&db,
+				&client,
+				&network_client,
+				block_number,
+				cfg.retention_check_cells,
+				&cancel,
+			)
+			.await;
+			if cancel.is_cancelled() {
+				info!("Retention checks are cancelled");
+				return;
+			}
+			match check {
+				Ok(Some(RetentionCheck {
+					sampled, retrieved, ..
+				})) => {
+					let confidence =
+						Confidence::from_cell_count(retrieved, withheld_fraction).value();
+					info!(
+						block_number,
+						sampled, retrieved, confidence, "Retention check completed"
+					);
+					metrics
+						.record(MetricValue::BlockRetentionConfidence(confidence))
+						.await;
+				},
+				Ok(None) => debug!(block_number, "Skipping retention check of empty block"),
+				Err(error) => error!(block_number, "Retention check failed: {error:#}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::pick_blocks;
+	use crate::data::{mem_db::MemoryDB, Database, Key};
+
+	#[test]
+	fn pick_verified_blocks() {
+		let db = MemoryDB::default();
+		for block_number in [1, 5, 8, 9] {
+			db.put(Key::VerifiedCellCount(block_number), 8u32).unwrap();
+		}
+
+		let mut blocks = pick_blocks(&db, 10, 5, 10).unwrap();
+		blocks.sort();
+		assert_eq!(blocks, vec![5, 8, 9]);
+
+		let blocks = pick_blocks(&db, 10, 5, 2).unwrap();
+		assert_eq!(blocks.len(), 2);
+		assert!(blocks.iter().all(|block| [5, 8, 9].contains(block)));
+
+		assert!(pick_blocks(&db, 0, 5, 2).unwrap().is_empty());
+	}
+}
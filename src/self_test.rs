@@ -0,0 +1,405 @@
+//! Self-test of the light client deployment.
+//!
+//! Checks configuration parsing, data directory and database write access, full node reachability
+//! and capabilities, DHT bootstrap, and proof verification of the cells sampled from a known block,
+//! without affecting the running light client. Checks which depend on a failed check are skipped.
+//! Temporary database is created in the data directory and removed once the checks are completed.
+
+use async_trait::async_trait;
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use kate_recovery::{commitments, matrix::Dimensions};
+use libp2p::{multiaddr::Protocol, Multiaddr};
+use serde_json::json;
+use std::{
+	fmt::{self, Display, Formatter},
+	fs,
+	future::Future,
+	net::Ipv4Addr,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{sync::mpsc, time};
+
+use crate::{
+	data::{rocks_db::RocksDB, Database, Key},
+	data_dir::DataDir,
+	network::{
+		p2p,
+		rpc::{self, Nodes},
+	},
+	proof,
+	shutdown::Controller,
+	telemetry::{MetricCounter, MetricValue, Metrics},
+	types::{CliOpts, ConnectionConfig, KeepAliveConfig, LibP2PConfig, RuntimeConfig, State},
+	utils::extract_kate,
+};
+
+/// Maximum duration of a single network check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of blocks before the finalized head searched for a block with data
+const MAX_LOOKBACK: u32 = 20;
+
+/// Name of the temporary database directory, created in the data directory
+const DB_DIR: &str = "self-test-db";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+	Pass(String),
+	Fail(String),
+	Skip(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+	pub name: &'static str,
+	pub outcome: Outcome,
+}
+
+/// Results of the self-test checks, in order of execution
+#[derive(Debug, Default)]
+pub struct Report {
+	pub checks: Vec<Check>,
+}
+
+impl Report {
+	fn add<T>(&mut self, name: &'static str, result: Result<(T, String)>) -> Option<T> {
+		let (value, outcome) = match result {
+			Ok((value, details)) => (Some(value), Outcome::Pass(details)),
+			Err(error) => (None, Outcome::Fail(format!("{error:#}"))),
+		};
+		self.checks.push(Check { name, outcome });
+		value
+	}
+
+	fn skip(&mut self, name: &'static str, reason: &str) {
+		let outcome = Outcome::Skip(reason.to_string());
+		self.checks.push(Check { name, outcome });
+	}
+
+	/// Returns true if none of the checks failed
+	pub fn passed(&self) -> bool {
+		!self
+			.checks
+			.iter()
+			.any(|check| matches!(check.outcome, Outcome::Fail(_)))
+	}
+
+	/// Report as a single JSON line, used with the JSON console output
+	pub fn to_json(&self) -> String {
+		let checks = self
+			.checks
+			.iter()
+			.map(|Check { name, outcome }| {
+				let (status, details) = match outcome {
+					Outcome::Pass(details) => ("pass", details),
+					Outcome::Fail(details) => ("fail", details),
+					Outcome::Skip(details) => ("skip", details),
+				};
+				json!({ "check": name, "status": status, "details": details })
+			})
+			.collect::<Vec<_>>();
+		json!({ "checks": checks, "passed": self.passed() }).to_string()
+	}
+}
+
+impl Display for Report {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		for Check { name, outcome } in &self.checks {
+			let (status, details) = match outcome {
+				Outcome::Pass(details) => ("PASS", details),
+				Outcome::Fail(details) => ("FAIL", details),
+				Outcome::Skip(details) => ("SKIP", details),
+			};
+			writeln!(f, "[{status}] {name:<20} {details}")?;
+		}
+		let result = if self.passed() { "PASSED" } else { "FAILED" };
+		write!(f, "Self-test {result}")
+	}
+}
+
+struct NoopMetrics;
+
+#[async_trait]
+impl Metrics for NoopMetrics {
+	async fn count(&self, _: MetricCounter) {}
+	async fn record(&self, _: MetricValue) {}
+	async fn flush(&self) -> Result<()> {
+		Ok(())
+	}
+}
+
+async fn with_timeout<T>(check: impl Future<Output = Result<T>>) -> Result<T> {
+	time::timeout(CHECK_TIMEOUT, check)
+		.await
+		.map_err(|_| eyre!("Timed out after {CHECK_TIMEOUT:?}"))?
+}
+
+fn check_config(opts: &CliOpts) -> Result<(RuntimeConfig, String)> {
+	let mut cfg = RuntimeConfig::default();
+	cfg.load_runtime_config(opts)?;
+	let source = opts.config.as_deref().unwrap_or("defaults");
+	Ok((cfg, format!("Configuration loaded from {source}")))
+}
+
+/// Creates temporary database in the data directory, and checks that it is writable
+fn check_disk(
+	cfg: &RuntimeConfig,
+	opts: &CliOpts,
+) -> Result<((PathBuf, Arc<rocksdb::DB>), String)> {
+	let data_dir = DataDir::new(cfg.data_dir.as_deref(), &[&cfg.avail_path, &opts.identity]);
+	data_dir.prepare()?;
+	let path = data_dir.path().join(DB_DIR);
+	let (db, rocks_db) =
+		RocksDB::open(&path.to_string_lossy()).wrap_err("Cannot create temporary database")?;
+	let value = rand::random::<u32>();
+	db.put(Key::VerifiedCellCount(0), value)?;
+	if db.get::<u32>(Key::VerifiedCellCount(0))? != Some(value) {
+		return Err(eyre!(
+			"Value read from the database doesn't match written value"
+		));
+	}
+	let details = format!("Data directory {} is writable", data_dir.path().display());
+	Ok(((path, rocks_db), details))
+}
+
+async fn check_full_node(
+	cfg: &RuntimeConfig,
+	shutdown: Controller<String>,
+) -> Result<(rpc::Client, String)> {
+	let state = Arc::new(Mutex::new(State::default()));
+	let client = rpc::Client::new(
+		state,
+		Nodes::new(&cfg.full_node_ws),
+		&cfg.genesis_hash,
+		cfg.retry_config.clone(),
+		KeepAliveConfig::from(cfg),
+		ConnectionConfig::try_from(cfg)?,
+		shutdown,
+	)
+	.await?
+	.with_cell_size(cfg.cell_size);
+	let host = client.host();
+	rpc::check_capabilities(&client, &host).await?;
+	let version = client.get_system_version().await?;
+	let head = client.get_chain_head_header().await?;
+	let details = format!(
+		"Connected to {host} (version {version}), finalized block {}",
+		head.number
+	);
+	Ok((client, details))
+}
+
+async fn check_dht(
+	cfg: &RuntimeConfig,
+	shutdown: Controller<String>,
+	_rocks_db: Arc<rocksdb::DB>,
+) -> Result<((), String)> {
+	if cfg.bootstraps.is_empty() {
+		return Err(eyre!("Bootstrap node list is empty"));
+	}
+	let mut cfg_libp2p = LibP2PConfig::from(cfg);
+	// Random identity and port, so the check doesn't conflict with the running light client
+	cfg_libp2p.secret_key = None;
+	cfg_libp2p.port = 0;
+	let (id_keys, _) = p2p::keypair(&cfg_libp2p)?;
+
+	let (sender, mut receiver) = mpsc::unbounded_channel();
+	let p2p_client = p2p::Client::new(sender, cfg.dht_parallelization_limit, cfg.kad_record_ttl)
+		.with_cell_size(cfg.cell_size);
+	let bootstraps = cfg_libp2p.bootstraps.clone();
+	let event_loop = p2p::EventLoop::new(
+		cfg_libp2p,
+		&id_keys,
+		false,
+		false,
+		shutdown.clone(),
+		#[cfg(feature = "kademlia-rocksdb")]
+		_rocks_db,
+	)
+	.await;
+	tokio::spawn(
+		shutdown
+			.with_cancel(async move { event_loop.run(Arc::new(NoopMetrics), &mut receiver).await }),
+	);
+
+	let address = Multiaddr::empty()
+		.with(Protocol::from(Ipv4Addr::UNSPECIFIED))
+		.with(Protocol::Tcp(0));
+	p2p_client.start_listening(address).await?;
+	p2p_client.bootstrap_on_startup(bootstraps).await?;
+	let peers = p2p_client.list_connected_peers().await?;
+	if peers.is_empty() {
+		return Err(eyre!("Bootstrap completed, but no peers are connected"));
+	}
+	Ok(((), format!("Bootstrapped, {} peers connected", peers.len())))
+}
+
+/// Verifies proofs of random cells of the given block, or of the latest finalized block with data
+async fn check_proof(
+	cfg: &RuntimeConfig,
+	client: &rpc::Client,
+	block: Option<u32>,
+) -> Result<Option<String>> {
+	let candidates = match block {
+		Some(block_number) => block_number..=block_number,
+		None => {
+			let head = client.get_chain_head_header().await?.number;
+			head.saturating_sub(MAX_LOOKBACK).max(1)..=head
+		},
+	};
+
+	for block_number in candidates.rev() {
+		let (header, block_hash) = client.get_header_by_block_number(block_number).await?;
+		let Some((rows, cols, _, commitment)) = extract_kate(&header.extension) else {
+			if block.is_some() {
+				return Err(eyre!("Block {block_number} has no data to sample"));
+			}
+			continue;
+		};
+		cfg.cell_size.check(&header)?;
+		let dimensions =
+			Dimensions::new(rows, cols).ok_or_else(|| eyre!("Invalid block dimensions"))?;
+		let commitments = commitments::from_slice(&commitment)?;
+		let cell_count = cfg.confidence.cell_count(cfg.withheld_fraction);
+		let positions = rpc::generate_random_cells(dimensions, cell_count);
+		let cells = client.request_kate_proof(block_hash, &positions).await?;
+		let pp = Arc::new(kate_recovery::couscous::public_params());
+		let (verified, unverified) =
+			proof::verify(block_number, dimensions, &cells, &commitments, pp).await?;
+		if !unverified.is_empty() || verified.len() < positions.len() {
+			return Err(eyre!(
+				"Verified {} of {} cells of block {block_number}",
+				verified.len(),
+				positions.len()
+			));
+		}
+		return Ok(Some(format!(
+			"Verified {} cells of block {block_number}",
+			verified.len()
+		)));
+	}
+	Ok(None)
+}
+
+/// Runs the checks, returning the report
+pub async fn run(opts: &CliOpts, block: Option<u32>) -> Report {
+	let mut report = Report::default();
+
+	let Some(cfg) = report.add("config", check_config(opts)) else {
+		report.skip("disk", "Configuration is invalid");
+		report.skip("full node", "Configuration is invalid");
+		report.skip("dht bootstrap", "Configuration is invalid");
+		report.skip("proof verification", "Configuration is invalid");
+		report.skip("cleanup", "Configuration is invalid");
+		return report;
+	};
+
+	let shutdown = Controller::new();
+	let disk = report.add("disk", check_disk(&cfg, opts));
+
+	let rpc_client = report.add(
+		"full node",
+		with_timeout(check_full_node(&cfg, shutdown.clone())).await,
+	);
+
+	match &disk {
+		Some((_, rocks_db)) => {
+			let dht = with_timeout(check_dht(&cfg, shutdown.clone(), rocks_db.clone())).await;
+			report.add("dht bootstrap", dht);
+		},
+		None => report.skip("dht bootstrap", "Database cannot be created"),
+	}
+
+	match &rpc_client {
+		Some(client) => match with_timeout(check_proof(&cfg, client, block)).await {
+			Ok(None) => report.skip(
+				"proof verification",
+				&format!("No block with data within last {MAX_LOOKBACK} blocks"),
+			),
+			result => {
+				report.add(
+					"proof verification",
+					result.map(|details| ((), details.unwrap_or_default())),
+				);
+			},
+		},
+		None => report.skip("proof verification", "Full node is not reachable"),
+	}
+
+	let _ = shutdown.trigger_shutdown("Self-test completed".to_string());
+	drop(rpc_client);
+	let _ = time::timeout(CHECK_TIMEOUT, shutdown.completed_shutdown()).await;
+	match disk {
+		Some((path, rocks_db)) => {
+			drop(rocks_db);
+			report.add("cleanup", remove_db(&path));
+		},
+		None => report.skip("cleanup", "Database cannot be created"),
+	}
+	report
+}
+
+/// Removes temporary database, so the self-test leaves the data directory as it was
+fn remove_db(path: &Path) -> Result<((), String)> {
+	fs::remove_dir_all(path)
+		.wrap_err_with(|| format!("Cannot remove temporary database {}", path.display()))?;
+	Ok(((), "Temporary database removed".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{remove_db, Check, Outcome, Report};
+
+	#[test]
+	fn report_outcome() {
+		let mut report = Report::default();
+		report.add("config", Ok(((), "Loaded".to_string())));
+		report.skip("disk", "Skipped");
+		assert!(report.passed());
+		assert!(report.to_string().ends_with("Self-test PASSED"));
+
+		assert_eq!(
+			report.add::<()>("full node", Err(color_eyre::eyre::eyre!("Timed out"))),
+			None
+		);
+		assert!(!report.passed());
+		assert_eq!(
+			report.checks[2],
+			Check {
+				name: "full node",
+				outcome: Outcome::Fail("Timed out".to_string())
+			}
+		);
+		let output = report.to_string();
+		assert!(output.contains("[SKIP] disk"));
+		assert!(output.ends_with("Self-test FAILED"));
+
+		let output: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+		assert_eq!(output["passed"], false);
+		assert_eq!(output["checks"][1]["status"], "skip");
+		assert_eq!(output["checks"][2]["details"], "Timed out");
+	}
+
+	#[test]
+	fn cleanup_outcome() {
+		let path = std::env::temp_dir().join(format!("self-test-db-{}", rand::random::<u32>()));
+		std::fs::create_dir_all(path.join("db")).unwrap();
+		let mut report = Report::default();
+		report.add("cleanup", remove_db(&path));
+		assert!(report.passed());
+		assert!(!path.exists());
+
+		report.add("cleanup", remove_db(&path));
+		let output: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+		assert_eq!(output["checks"][1]["status"], "fail");
+		assert!(output["checks"][1]["details"]
+			.as_str()
+			.unwrap()
+			.starts_with("Cannot remove temporary database"));
+	}
+}
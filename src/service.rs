@@ -0,0 +1,264 @@
+//! Running light client as a managed system service.
+//!
+//! On Unix, process can be detached from the terminal with [`daemonize`], and [`PidFile`]
+//! prevents running several instances with the same pid file.
+//! On Windows, [`windows::run`] runs the light client under the service control manager,
+//! triggering graceful shutdown when service is stopped.
+
+use color_eyre::{
+	eyre::{eyre, WrapErr},
+	Result,
+};
+use std::{
+	fs,
+	path::{Path, PathBuf},
+	process,
+};
+
+/// Pid file which is removed when dropped
+#[derive(Debug)]
+pub struct PidFile {
+	path: PathBuf,
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+	fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Checks if process with given ID is running (always `false` on non-Unix platforms)
+#[cfg(unix)]
+pub(crate) fn is_running(pid: u32) -> bool {
+	let Ok(pid) = libc::pid_t::try_from(pid) else {
+		return false;
+	};
+	// Signal 0 only checks if process exists and can be signaled
+	if unsafe { libc::kill(pid, 0) } == 0 {
+		return true;
+	}
+	std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_running(_pid: u32) -> bool {
+	false
+}
+
+impl PidFile {
+	/// Fails if pid file exists and the process it points to is still running.
+	/// Stale pid files are ignored.
+	pub fn check(path: &Path) -> Result<()> {
+		match read_pid(path) {
+			Some(pid) if pid != process::id() && is_running(pid) => Err(eyre!(
+				"Light client is already running with PID {pid} (pid file {})",
+				path.display()
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	/// Writes current process ID to the pid file
+	pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+		let path = path.as_ref();
+		Self::check(path)?;
+		fs::write(path, format!("{}\n", process::id()))
+			.wrap_err_with(|| format!("Failed to write pid file {}", path.display()))?;
+		Ok(PidFile {
+			path: path.to_path_buf(),
+		})
+	}
+}
+
+impl Drop for PidFile {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.path);
+	}
+}
+
+/// Detaches process from the controlling terminal and runs it in the background.
+///
+/// Standard streams are redirected to `/dev/null`, so logs should be written to a file.
+/// Working directory is not changed, so relative paths from the configuration remain valid.
+/// Must be called before any threads are spawned (e.g. before starting the async runtime).
+#[cfg(unix)]
+pub fn daemonize() -> Result<()> {
+	use std::io::Error;
+
+	fn fork() -> Result<()> {
+		match unsafe { libc::fork() } {
+			-1 => Err(Error::last_os_error()).wrap_err("Failed to fork process"),
+			0 => Ok(()),
+			// Parent process exits, leaving the child running
+			_ => unsafe { libc::_exit(0) },
+		}
+	}
+
+	fork()?;
+	if unsafe { libc::setsid() } == -1 {
+		return Err(Error::last_os_error()).wrap_err("Failed to create new session");
+	}
+	// Second fork ensures daemon cannot reacquire controlling terminal
+	fork()?;
+	unsafe { libc::umask(0o027) };
+
+	let dev_null = unsafe { libc::open("/dev/null\0".as_ptr().cast(), libc::O_RDWR) };
+	if dev_null == -1 {
+		return Err(Error::last_os_error()).wrap_err("Failed to open /dev/null");
+	}
+	for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+		if unsafe { libc::dup2(dev_null, fd) } == -1 {
+			return Err(Error::last_os_error()).wrap_err("Failed to redirect standard streams");
+		}
+	}
+	if dev_null > libc::STDERR_FILENO {
+		unsafe { libc::close(dev_null) };
+	}
+	Ok(())
+}
+
+#[cfg(windows)]
+pub mod windows {
+	use crate::shutdown::Controller;
+	use color_eyre::{eyre::WrapErr, Result};
+	use std::{
+		ffi::OsString,
+		sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc, OnceLock,
+		},
+		time::Duration,
+	};
+	use tracing::{error, info};
+	use windows_service::{
+		define_windows_service,
+		service::{
+			ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+			ServiceType,
+		},
+		service_control_handler::{self, ServiceControlHandlerResult},
+		service_dispatcher,
+	};
+
+	/// Name under which the light client service is registered
+	pub const SERVICE_NAME: &str = "avail-light";
+
+	type Main = fn(Controller<String>) -> Result<()>;
+
+	static MAIN: OnceLock<Main> = OnceLock::new();
+
+	define_windows_service!(ffi_service_main, service_main);
+
+	/// Runs given main function under the service control manager, blocking until service is stopped.
+	/// Service stop and system shutdown requests trigger graceful shutdown of the light client.
+	pub fn run(main: Main) -> Result<()> {
+		let _ = MAIN.set(main);
+		service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+			.wrap_err("Failed to start service dispatcher")
+	}
+
+	fn status(state: ServiceState, exit_code: ServiceExitCode) -> ServiceStatus {
+		let controls_accepted = match state {
+			ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+			_ => ServiceControlAccept::empty(),
+		};
+		ServiceStatus {
+			service_type: ServiceType::OWN_PROCESS,
+			current_state: state,
+			controls_accepted,
+			exit_code,
+			checkpoint: 0,
+			wait_hint: Duration::from_secs(30),
+			process_id: None,
+		}
+	}
+
+	fn service_main(_arguments: Vec<OsString>) {
+		let Some(main) = MAIN.get() else {
+			return;
+		};
+		let shutdown = Controller::new();
+		let stop_requested = Arc::new(AtomicBool::new(false));
+
+		let handler = {
+			let shutdown = shutdown.clone();
+			let stop_requested = stop_requested.clone();
+			move |control| match control {
+				ServiceControl::Stop | ServiceControl::Shutdown => {
+					stop_requested.store(true, Ordering::Relaxed);
+					let _ = shutdown.trigger_shutdown("Service stop requested".to_string());
+					ServiceControlHandlerResult::NoError
+				},
+				ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+				_ => ServiceControlHandlerResult::NotImplemented,
+			}
+		};
+
+		let status_handle = match service_control_handler::register(SERVICE_NAME, handler) {
+			Ok(status_handle) => status_handle,
+			Err(error) => {
+				error!("Failed to register service control handler: {error}");
+				return;
+			},
+		};
+
+		let running = status(ServiceState::Running, ServiceExitCode::Win32(0));
+		if let Err(error) = status_handle.set_service_status(running) {
+			error!("Failed to set service status: {error}");
+		}
+
+		// Light client returns the shutdown reason as an error, which is expected on service stop
+		let exit_code = match main(shutdown) {
+			Err(error) if !stop_requested.load(Ordering::Relaxed) => {
+				error!("{error:#}");
+				ServiceExitCode::ServiceSpecific(1)
+			},
+			_ => {
+				info!("Service stopped");
+				ServiceExitCode::Win32(0)
+			},
+		};
+
+		let stopped = status(ServiceState::Stopped, exit_code);
+		if let Err(error) = status_handle.set_service_status(stopped) {
+			error!("Failed to set service status: {error}");
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::PidFile;
+	use std::{fs, path::PathBuf, process};
+
+	fn pid_file_path(name: &str) -> PathBuf {
+		std::env::temp_dir().join(format!("avail_light_{name}_{}.pid", process::id()))
+	}
+
+	#[test]
+	fn pid_file_removed_on_drop() {
+		let path = pid_file_path("drop");
+		let pid_file = PidFile::create(&path).unwrap();
+		let pid = fs::read_to_string(&path).unwrap();
+		assert_eq!(pid.trim(), process::id().to_string());
+		drop(pid_file);
+		assert!(!path.exists());
+	}
+
+	#[test]
+	fn stale_pid_file_is_replaced() {
+		let path = pid_file_path("stale");
+		fs::write(&path, "not a pid").unwrap();
+		let _pid_file = PidFile::create(&path).unwrap();
+		let pid = fs::read_to_string(&path).unwrap();
+		assert_eq!(pid.trim(), process::id().to_string());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn running_process_is_detected() {
+		let path = pid_file_path("running");
+		// Init process is always running
+		fs::write(&path, "1").unwrap();
+		assert!(PidFile::create(&path).is_err());
+		fs::remove_file(&path).unwrap();
+	}
+}
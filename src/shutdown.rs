@@ -5,6 +5,7 @@ use std::{
 	sync::{Arc, Mutex},
 	task::Waker,
 };
+use tokio_util::sync::CancellationToken;
 
 use self::{
 	completed::Completed, signal::Signal, with_cancel::WithCancel, with_delay::WithDelay,
@@ -98,6 +99,26 @@ impl<T: Clone> Controller<T> {
 		}
 	}
 
+	/// Creates a cancellation token, which is cancelled when the shutdown is triggered.
+	///
+	/// Child tokens can be derived from it to cancel in-flight work of a single unit (e.g. a block),
+	/// while still being cancelled on shutdown. Must be called within the Tokio runtime.
+	pub fn cancellation_token(&self) -> CancellationToken
+	where
+		T: Send + 'static,
+	{
+		let token = CancellationToken::new();
+		let cancel = token.clone();
+		let signal = self.triggered_shutdown();
+		tokio::spawn(async move {
+			tokio::select! {
+				_ = signal => cancel.cancel(),
+				_ = cancel.cancelled() => (),
+			}
+		});
+		token
+	}
+
 	/// Wraps a future to cancel it upon a triggered shutdown.
 	///
 	/// The returned future completes with `Err(reason)` if the shutdown is triggered before the wrapped future.
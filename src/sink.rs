@@ -0,0 +1,522 @@
+//! Publishing of availability events to external event sinks.
+//!
+//! Verified block and app data events are serialized to JSON and published to every configured sink.
+//! Kafka sink is available with `kafka` feature, NATS sink is available with `nats` feature.
+//! Webhook sink posts events to the HTTP endpoint, optionally rendering the payload from a template
+//! and signing it with HMAC-SHA256, so the receiver can verify that the payload was sent by the light client.
+//! Each sink can be gated (see [`crate::export_gate`]), so events are published only for blocks with sufficient
+//! confidence and/or confirmed finality.
+//! Publishing to each sink is limited by the configured timeout, so unresponsive sink doesn't hold up the others.
+
+use crate::{
+	confidence::Confidence,
+	data::Database,
+	export_gate::{Gate, GateConfig, FINALITY_CHECK_INTERVAL},
+	network::rpc::HttpClient,
+	types::{BlockVerified, State},
+};
+use async_trait::async_trait;
+use color_eyre::{eyre::eyre, Result};
+use hmac::{Hmac, Mac};
+use hyper::{Body, Method, Request};
+use kate_recovery::com::AppData;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tokio::{sync::broadcast, time};
+use tracing::{debug, error, info, warn};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum EventSinkConfig {
+	/// Publishes events to the Kafka topic
+	Kafka {
+		/// Comma separated list of Kafka brokers
+		brokers: String,
+		topic: String,
+		#[serde(default)]
+		gate: GateConfig,
+	},
+	/// Publishes events to the NATS subjects `{subject_prefix}.block-verified` and `{subject_prefix}.app-data-verified`
+	Nats {
+		url: String,
+		subject_prefix: String,
+		#[serde(default)]
+		gate: GateConfig,
+	},
+	/// Posts events to the HTTP endpoint
+	Webhook {
+		url: String,
+		/// Names of the events posted to the endpoint, all events are posted if empty
+		#[serde(default)]
+		events: Vec<String>,
+		/// Additional request headers
+		#[serde(default)]
+		headers: BTreeMap<String, String>,
+		/// JSON payload template, `{{field}}` placeholders are replaced with the event fields (default: event JSON)
+		#[serde(default)]
+		template: Option<String>,
+		/// Secret used to sign the payload, signature is sent in the `X-Avail-Signature` header
+		#[serde(default)]
+		secret: Option<String>,
+		#[serde(default)]
+		gate: GateConfig,
+	},
+}
+
+impl EventSinkConfig {
+	/// Conditions which block must meet before its events are published to the sink
+	pub fn gate(&self) -> &GateConfig {
+		match self {
+			EventSinkConfig::Kafka { gate, .. } => gate,
+			EventSinkConfig::Nats { gate, .. } => gate,
+			EventSinkConfig::Webhook { gate, .. } => gate,
+		}
+	}
+}
+
+/// Header with the hex encoded HMAC-SHA256 signature of the webhook payload
+pub const SIGNATURE_HEADER: &str = "X-Avail-Signature";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+	BlockVerified {
+		block_number: u32,
+		header_hash: String,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		confidence: Option<Confidence>,
+	},
+	AppDataVerified {
+		block_number: u32,
+		app_id: u32,
+		transactions_count: usize,
+		size: usize,
+	},
+}
+
+const EVENT_NAMES: [&str; 2] = ["block-verified", "app-data-verified"];
+
+impl Event {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Event::BlockVerified { .. } => "block-verified",
+			Event::AppDataVerified { .. } => "app-data-verified",
+		}
+	}
+
+	pub fn block_number(&self) -> u32 {
+		match self {
+			Event::BlockVerified { block_number, .. } => *block_number,
+			Event::AppDataVerified { block_number, .. } => *block_number,
+		}
+	}
+
+	pub fn app_data(app_id: u32, block_number: u32, app_data: &AppData) -> Self {
+		Event::AppDataVerified {
+			block_number,
+			app_id,
+			transactions_count: app_data.len(),
+			size: app_data.iter().map(Vec::len).sum(),
+		}
+	}
+}
+
+impl From<&BlockVerified> for Event {
+	fn from(block: &BlockVerified) -> Self {
+		Event::BlockVerified {
+			block_number: block.block_num,
+			header_hash: format!("{:#x}", block.header_hash),
+			confidence: block.confidence,
+		}
+	}
+}
+
+#[async_trait]
+pub trait EventSink {
+	fn name(&self) -> &'static str;
+	async fn publish(&self, event: &Event) -> Result<()>;
+}
+
+#[cfg(feature = "kafka")]
+pub struct Kafka {
+	producer: rdkafka::producer::FutureProducer,
+	topic: String,
+}
+
+#[cfg(feature = "kafka")]
+#[async_trait]
+impl EventSink for Kafka {
+	fn name(&self) -> &'static str {
+		"kafka"
+	}
+
+	async fn publish(&self, event: &Event) -> Result<()> {
+		use rdkafka::producer::FutureRecord;
+		use std::time::Duration;
+
+		let payload = serde_json::to_vec(event)?;
+		let key = event.block_number().to_string();
+		let record = FutureRecord::to(&self.topic).key(&key).payload(&payload);
+		self.producer
+			.send(record, Duration::from_secs(0))
+			.await
+			.map_err(|(error, _)| eyre!("Cannot publish event to Kafka: {error}"))?;
+		Ok(())
+	}
+}
+
+#[cfg(feature = "nats")]
+pub struct Nats {
+	client: async_nats::Client,
+	subject_prefix: String,
+}
+
+#[cfg(feature = "nats")]
+#[async_trait]
+impl EventSink for Nats {
+	fn name(&self) -> &'static str {
+		"nats"
+	}
+
+	async fn publish(&self, event: &Event) -> Result<()> {
+		let payload = serde_json::to_vec(event)?;
+		let subject = format!("{}.{}", self.subject_prefix, event.name());
+		self.client
+			.publish(subject, payload.into())
+			.await
+			.map_err(|error| eyre!("Cannot publish event to NATS: {error}"))
+	}
+}
+
+pub struct Webhook {
+	client: HttpClient,
+	url: String,
+	events: Vec<String>,
+	headers: BTreeMap<String, String>,
+	template: Option<String>,
+	secret: Option<String>,
+}
+
+/// Renders payload template, replacing `{{field}}` placeholders with the event fields.
+/// String fields are inserted without quotes, missing fields are replaced with `null`.
+fn render(template: &str, event: &Event) -> Result<Vec<u8>> {
+	let Value::Object(fields) = serde_json::to_value(event)? else {
+		return Err(eyre!("Event is not serialized as an object"));
+	};
+	let mut payload = String::with_capacity(template.len());
+	let mut rest = template;
+	while let Some(start) = rest.find("{{") {
+		let Some(end) = rest[start..].find("}}") else {
+			break;
+		};
+		payload.push_str(&rest[..start]);
+		let field = rest[start + 2..start + end].trim();
+		match fields.get(field) {
+			Some(Value::String(value)) => {
+				// Escaped as a JSON string, without the surrounding quotes
+				let escaped = serde_json::to_string(value)?;
+				payload.push_str(&escaped[1..escaped.len() - 1]);
+			},
+			Some(value) => payload.push_str(&value.to_string()),
+			None => payload.push_str("null"),
+		}
+		rest = &rest[start + end + 2..];
+	}
+	payload.push_str(rest);
+	serde_json::from_str::<Value>(&payload)
+		.map_err(|error| eyre!("Rendered webhook payload is not valid JSON: {error}"))?;
+	Ok(payload.into_bytes())
+}
+
+fn sign(secret: &str, payload: &[u8]) -> Result<String> {
+	let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+		.map_err(|error| eyre!("Invalid webhook secret: {error}"))?;
+	mac.update(payload);
+	Ok(format!(
+		"sha256={}",
+		hex::encode(mac.finalize().into_bytes())
+	))
+}
+
+#[async_trait]
+impl EventSink for Webhook {
+	fn name(&self) -> &'static str {
+		"webhook"
+	}
+
+	async fn publish(&self, event: &Event) -> Result<()> {
+		if !self.events.is_empty() && !self.events.iter().any(|name| name == event.name()) {
+			return Ok(());
+		}
+		let payload = match &self.template {
+			Some(template) => render(template, event)?,
+			None => serde_json::to_vec(event)?,
+		};
+		let mut request = Request::builder()
+			.method(Method::POST)
+			.uri(&self.url)
+			.header("content-type", "application/json");
+		for (name, value) in &self.headers {
+			request = request.header(name, value);
+		}
+		if let Some(secret) = &self.secret {
+			request = request.header(SIGNATURE_HEADER, sign(secret, &payload)?);
+		}
+		self.client.send(request.body(Body::from(payload))?).await?;
+		Ok(())
+	}
+}
+
+pub async fn connect(
+	cfg: &EventSinkConfig,
+	client: &HttpClient,
+) -> Result<Box<dyn EventSink + Send + Sync>> {
+	match cfg {
+		#[cfg(feature = "kafka")]
+		EventSinkConfig::Kafka { brokers, topic, .. } => {
+			let producer = rdkafka::ClientConfig::new()
+				.set("bootstrap.servers", brokers)
+				.set("message.timeout.ms", "5000")
+				.create()
+				.map_err(|error| eyre!("Cannot create Kafka producer: {error}"))?;
+			Ok(Box::new(Kafka {
+				producer,
+				topic: topic.clone(),
+			}))
+		},
+		#[cfg(not(feature = "kafka"))]
+		EventSinkConfig::Kafka { .. } => Err(eyre!(
+			"Kafka event sink requires light client to be compiled with `kafka` feature"
+		)),
+		#[cfg(feature = "nats")]
+		EventSinkConfig::Nats {
+			url,
+			subject_prefix,
+			..
+		} => {
+			let client = async_nats::connect(url)
+				.await
+				.map_err(|error| eyre!("Cannot connect to NATS: {error}"))?;
+			Ok(Box::new(Nats {
+				client,
+				subject_prefix: subject_prefix.clone(),
+			}))
+		},
+		#[cfg(not(feature = "nats"))]
+		EventSinkConfig::Nats { .. } => Err(eyre!(
+			"NATS event sink requires light client to be compiled with `nats` feature"
+		)),
+		EventSinkConfig::Webhook {
+			url,
+			events,
+			headers,
+			template,
+			secret,
+			..
+		} => {
+			if let Some(event) = events
+				.iter()
+				.find(|event| !EVENT_NAMES.contains(&event.as_str()))
+			{
+				return Err(eyre!("Unknown webhook event {event}"));
+			}
+			Ok(Box::new(Webhook {
+				client: client.clone(),
+				url: url.clone(),
+				events: events.clone(),
+				headers: headers.clone(),
+				template: template.clone(),
+				secret: secret.clone(),
+			}))
+		},
+	}
+}
+
+type GatedSink<T> = (Box<dyn EventSink + Send + Sync>, Option<Gate<T, Event>>);
+
+async fn publish_to(
+	sink: &(dyn EventSink + Send + Sync),
+	event: &Event,
+	timeout: Duration,
+) -> bool {
+	match time::timeout(timeout, sink.publish(event)).await {
+		Ok(Ok(())) => {
+			debug!(sink = sink.name(), event = event.name(), "Event published");
+			true
+		},
+		Ok(Err(error)) => {
+			error!(sink = sink.name(), "{error:#}");
+			false
+		},
+		Err(_) => {
+			error!(
+				sink = sink.name(),
+				event = event.name(),
+				"Publishing timed out after {timeout:?}"
+			);
+			false
+		},
+	}
+}
+
+/// Publishes the event to the sinks, events of the gated sinks are queued by their gates
+async fn publish<T: Database>(sinks: &mut [GatedSink<T>], event: Option<Event>, timeout: Duration) {
+	for (sink, gate) in sinks {
+		let Some(gate) = gate else {
+			if let Some(event) = &event {
+				publish_to(sink.as_ref(), event, timeout).await;
+			}
+			continue;
+		};
+		if let Some(event) = &event {
+			gate.admit(event.block_number(), event.clone());
+		}
+		for (block_number, event) in gate.release() {
+			if publish_to(sink.as_ref(), &event, timeout).await {
+				gate.emitted(block_number);
+			}
+		}
+	}
+}
+
+/// Runs publishing of verified block and app data events to the configured sinks
+pub async fn run(
+	configs: Vec<EventSinkConfig>,
+	timeout: Duration,
+	client: HttpClient,
+	withheld_fraction: f64,
+	db: impl Database + Clone,
+	state: Arc<Mutex<State>>,
+	app_id: Option<u32>,
+	mut block_receiver: broadcast::Receiver<BlockVerified>,
+	mut data_receiver: Option<broadcast::Receiver<(u32, AppData)>>,
+) -> Result<()> {
+	if configs.is_empty() {
+		return Ok(());
+	}
+	info!("Starting event sinks...");
+
+	let mut sinks = vec![];
+	for (index, config) in configs.iter().enumerate() {
+		let sink = connect(config, &client).await?;
+		// Watermark is named by the sink type and its position in the configuration
+		let name = format!("{}:{index}", sink.name());
+		let gate = Gate::new(
+			name,
+			config.gate().clone(),
+			withheld_fraction,
+			db.clone(),
+			state.clone(),
+		)?;
+		sinks.push((sink, gate));
+	}
+
+	let mut finality_check = time::interval(FINALITY_CHECK_INTERVAL);
+	loop {
+		tokio::select! {
+			_ = finality_check.tick() => publish(&mut sinks, None, timeout).await,
+			block = block_receiver.recv() => match block {
+				Ok(block) => publish(&mut sinks, Some(Event::from(&block)), timeout).await,
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					warn!("Event sinks lagged, {skipped} blocks are skipped");
+				},
+				Err(broadcast::error::RecvError::Closed) => return Ok(()),
+			},
+			Some(data) = async {
+				match data_receiver.as_mut() {
+					Some(receiver) => Some(receiver.recv().await),
+					None => None,
+				}
+			} => match data {
+				Ok((block_number, app_data)) => {
+					let event = Event::app_data(app_id.unwrap_or(0), block_number, &app_data);
+					publish(&mut sinks, Some(event), timeout).await;
+				},
+				Err(broadcast::error::RecvError::Lagged(skipped)) => {
+					warn!("Event sinks lagged, {skipped} app data messages are skipped");
+				},
+				Err(broadcast::error::RecvError::Closed) => data_receiver = None,
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{publish_to, render, sign, Event, EventSink, EventSinkConfig};
+	use async_trait::async_trait;
+	use color_eyre::Result;
+	use std::time::Duration;
+
+	struct SlowSink;
+
+	#[async_trait]
+	impl EventSink for SlowSink {
+		fn name(&self) -> &'static str {
+			"slow"
+		}
+
+		async fn publish(&self, _: &Event) -> Result<()> {
+			tokio::time::sleep(Duration::from_secs(60)).await;
+			Ok(())
+		}
+	}
+
+	#[tokio::test]
+	async fn publish_timeout() {
+		let event = Event::app_data(1, 10, &vec![]);
+		assert!(!publish_to(&SlowSink, &event, Duration::from_millis(10)).await);
+	}
+
+	#[test]
+	fn event_serialization() {
+		let event = Event::app_data(1, 10, &vec![vec![0u8; 4]]);
+		assert_eq!(
+			serde_json::to_string(&event).unwrap(),
+			r#"{"event":"app-data-verified","block_number":10,"app_id":1,"transactions_count":1,"size":4}"#
+		);
+		assert_eq!(event.name(), "app-data-verified");
+	}
+
+	#[test]
+	fn sink_config_deserialization() {
+		let config: EventSinkConfig = serde_json::from_str(
+			r#"{"type":"nats","url":"localhost:4222","subject_prefix":"avail"}"#,
+		)
+		.unwrap();
+		assert!(matches!(config, EventSinkConfig::Nats { .. }));
+	}
+
+	#[test]
+	fn webhook_payload_template() {
+		let event = Event::BlockVerified {
+			block_number: 10,
+			header_hash: "0x01".to_string(),
+			confidence: None,
+		};
+		let template = r#"{"text": "Block {{block_number}} ({{ header_hash }}) verified", "confidence": {{confidence}}, "kind": "{{event}}"}"#;
+		let payload: serde_json::Value =
+			serde_json::from_slice(&render(template, &event).unwrap()).unwrap();
+		assert_eq!(payload["text"], "Block 10 (0x01) verified");
+		assert_eq!(payload["confidence"], serde_json::Value::Null);
+		assert_eq!(payload["kind"], "block-verified");
+
+		assert!(render(r#"{"block": {{header_hash}}}"#, &event).is_err());
+	}
+
+	#[test]
+	fn webhook_signature() {
+		// RFC 4231 test case 2
+		let signature = sign("Jefe", b"what do ya want for nothing?").unwrap();
+		assert_eq!(
+			signature,
+			"sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+		);
+	}
+}
@@ -0,0 +1,292 @@
+//! Supervision of the long running subsystems.
+//!
+//! Supervised task is restarted with backoff if it fails or panics.
+//! If task fails too many times within the restart window, shutdown is triggered
+//! and the process exits with [`EXIT_CODE_SUPERVISOR`].
+
+use color_eyre::{eyre::eyre, Result};
+use futures::FutureExt;
+use std::{
+	any::Any,
+	collections::VecDeque,
+	future::Future,
+	panic::AssertUnwindSafe,
+	sync::atomic::{AtomicBool, Ordering},
+	time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::{shutdown::Controller, types::RetryConfig};
+
+/// Process exit code used when supervised task exceeded the restart limit (`EX_SOFTWARE`)
+pub const EXIT_CODE_SUPERVISOR: i32 = 70;
+
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+	static SUPERVISED_TASK: &'static str;
+}
+
+/// Name of the supervised task or panic boundary in which the current code is running, if any.
+/// Panic hook uses it to leave the handling of the panic to the supervisor or the boundary.
+pub fn supervised_task() -> Option<&'static str> {
+	SUPERVISED_TASK.try_with(|name| *name).ok()
+}
+
+/// Runs the future within a panic boundary, converting its panic into an error.
+/// Panic hook does not trigger shutdown for panics caught by the boundary.
+pub async fn catch_panic<F: Future>(name: &'static str, future: F) -> Result<F::Output> {
+	SUPERVISED_TASK
+		.scope(name, AssertUnwindSafe(future).catch_unwind())
+		.await
+		.map_err(|payload| eyre!("{name} panicked: {}", panic_message(payload.as_ref())))
+}
+
+/// Returns `true` if any supervised task exceeded the restart limit
+pub fn has_failed() -> bool {
+	FAILED.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Debug)]
+pub struct SupervisorConfig {
+	/// Delays between the consecutive restarts, last delay is used when exhausted
+	pub backoff: RetryConfig,
+	/// Maximum number of restarts within the restart window
+	pub max_restarts: u32,
+	/// Window in which restarts are counted
+	pub restart_window: Duration,
+}
+
+/// Tracks the failures within the restart window
+struct RestartPolicy {
+	max_restarts: u32,
+	window: Duration,
+	failures: VecDeque<Instant>,
+}
+
+impl RestartPolicy {
+	fn new(max_restarts: u32, window: Duration) -> Self {
+		RestartPolicy {
+			max_restarts,
+			window,
+			failures: VecDeque::new(),
+		}
+	}
+
+	/// Records the failure and returns number of failures within the window,
+	/// or `None` if the restart limit is exceeded.
+	fn record_failure(&mut self, now: Instant) -> Option<u32> {
+		while let Some(&failure) = self.failures.front() {
+			if now.saturating_duration_since(failure) <= self.window {
+				break;
+			}
+			self.failures.pop_front();
+		}
+		self.failures.push_back(now);
+		let failures = self.failures.len() as u32;
+		(failures <= self.max_restarts).then_some(failures)
+	}
+}
+
+fn restart_delay(backoff: &RetryConfig, failures: u32) -> Duration {
+	let delays = backoff.clone().into_iter().collect::<Vec<_>>();
+	let index = (failures as usize).saturating_sub(1);
+	delays
+		.get(index)
+		.or(delays.last())
+		.copied()
+		.unwrap_or(Duration::from_secs(1))
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+	payload
+		.downcast_ref::<&str>()
+		.copied()
+		.or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+		.unwrap_or("unknown panic")
+}
+
+#[derive(Clone)]
+pub struct Supervisor {
+	cfg: SupervisorConfig,
+	shutdown: Controller<String>,
+}
+
+impl Supervisor {
+	pub fn new(cfg: SupervisorConfig, shutdown: Controller<String>) -> Self {
+		Supervisor { cfg, shutdown }
+	}
+
+	/// Spawns supervised task, created by the given factory on each (re)start.
+	/// Task which completes successfully is not restarted.
+	pub fn spawn<F, Fut>(&self, name: &'static str, factory: F) -> JoinHandle<()>
+	where
+		F: FnMut() -> Fut + Send + 'static,
+		Fut: Future<Output = Result<()>> + Send + 'static,
+	{
+		tokio::spawn(self.clone().supervise(name, factory))
+	}
+
+	async fn supervise<F, Fut>(self, name: &'static str, mut factory: F)
+	where
+		F: FnMut() -> Fut,
+		Fut: Future<Output = Result<()>> + Send + 'static,
+	{
+		let mut policy = RestartPolicy::new(self.cfg.max_restarts, self.cfg.restart_window);
+		loop {
+			let task = SUPERVISED_TASK.scope(name, self.shutdown.with_cancel(factory()));
+			let error = match tokio::spawn(task).await {
+				Ok(Ok(Ok(()))) => {
+					info!(task = name, "Supervised task completed");
+					return;
+				},
+				// Task is cancelled on shutdown
+				Ok(Err(_)) => return,
+				Ok(Ok(Err(error))) => format!("{error:#}"),
+				Err(error) if error.is_panic() => {
+					let payload = error.into_panic();
+					format!("panicked: {}", panic_message(payload.as_ref()))
+				},
+				// Task is aborted on runtime shutdown
+				Err(_) => return,
+			};
+
+			if self.shutdown.is_shutdown_triggered() {
+				return;
+			}
+
+			let Some(failures) = policy.record_failure(Instant::now()) else {
+				error!(
+					task = name,
+					"Supervised task failed: {error}, restart limit exceeded"
+				);
+				FAILED.store(true, Ordering::Relaxed);
+				let _ = self.shutdown.trigger_shutdown(format!(
+					"Task {name} failed more than {} times within {:?}",
+					self.cfg.max_restarts, self.cfg.restart_window
+				));
+				return;
+			};
+
+			let delay = restart_delay(&self.cfg.backoff, failures);
+			warn!(
+				task = name,
+				failures, "Supervised task failed: {error}, restarting in {delay:?}"
+			);
+			tokio::select! {
+				_ = tokio::time::sleep(delay) => {},
+				_ = self.shutdown.triggered_shutdown() => return,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		catch_panic, restart_delay, supervised_task, RestartPolicy, Supervisor, SupervisorConfig,
+	};
+	use crate::{
+		shutdown::Controller,
+		types::{FibonacciConfig, RetryConfig},
+	};
+	use color_eyre::eyre::eyre;
+	use std::{
+		sync::{
+			atomic::{AtomicU32, Ordering},
+			Arc,
+		},
+		time::{Duration, Instant},
+	};
+
+	#[test]
+	fn restart_policy_window() {
+		let mut policy = RestartPolicy::new(2, Duration::from_secs(10));
+		let now = Instant::now();
+		assert_eq!(policy.record_failure(now), Some(1));
+		assert_eq!(policy.record_failure(now + Duration::from_secs(5)), Some(2));
+		assert_eq!(policy.record_failure(now + Duration::from_secs(6)), None);
+		// Failures older than the window are forgotten
+		assert_eq!(
+			policy.record_failure(now + Duration::from_secs(30)),
+			Some(1)
+		);
+	}
+
+	#[test]
+	fn restart_delay_exhausted() {
+		let backoff = |retries| {
+			RetryConfig::Fibonacci(FibonacciConfig {
+				base: 1,
+				max_delay: 10,
+				retries,
+			})
+		};
+		assert!(restart_delay(&backoff(2), 10) <= Duration::from_millis(10));
+		assert_eq!(restart_delay(&backoff(0), 1), Duration::from_secs(1));
+	}
+
+	fn config(max_restarts: u32) -> SupervisorConfig {
+		SupervisorConfig {
+			backoff: RetryConfig::Fibonacci(FibonacciConfig {
+				base: 1,
+				max_delay: 1,
+				retries: 1,
+			}),
+			max_restarts,
+			restart_window: Duration::from_secs(60),
+		}
+	}
+
+	#[tokio::test]
+	async fn catch_panic_boundary() {
+		assert_eq!(catch_panic("test", async { 1 }).await.unwrap(), 1);
+		let error = catch_panic("test", async {
+			assert_eq!(supervised_task(), Some("test"));
+			panic!("malformed header")
+		})
+		.await
+		.unwrap_err();
+		assert_eq!(error.to_string(), "test panicked: malformed header");
+		assert_eq!(supervised_task(), None);
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn restart_after_panic() {
+		let shutdown = Controller::new();
+		let supervisor = Supervisor::new(config(3), shutdown.clone());
+		let starts = Arc::new(AtomicU32::new(0));
+
+		let task_starts = starts.clone();
+		supervisor
+			.spawn("test", move || {
+				let starts = task_starts.clone();
+				async move {
+					if starts.fetch_add(1, Ordering::Relaxed) == 0 {
+						panic!("first start fails");
+					}
+					Ok(())
+				}
+			})
+			.await
+			.unwrap();
+
+		assert_eq!(starts.load(Ordering::Relaxed), 2);
+		assert!(!shutdown.is_shutdown_triggered());
+	}
+
+	#[tokio::test(start_paused = true)]
+	async fn shutdown_after_restart_limit() {
+		let shutdown = Controller::new();
+		let supervisor = Supervisor::new(config(2), shutdown.clone());
+
+		supervisor
+			.spawn("test", || async { Err(eyre!("always fails")) })
+			.await
+			.unwrap();
+
+		assert!(shutdown.is_shutdown_triggered());
+		assert!(super::has_failed());
+	}
+}
@@ -16,13 +16,14 @@
 //! In case RPC is disabled, RPC calls will be skipped.
 
 use crate::{
-	data::{Database, Key},
+	confidence::Confidence,
+	data::{self, Database, Key},
 	network::{
 		self,
 		rpc::{self, Client as RpcClient},
 	},
 	types::{BlockVerified, OptionBlockRange, State, SyncClientConfig},
-	utils::{calculate_confidence, extract_kate},
+	utils::{extract_kate, random_delay, unix_timestamp_nanos},
 };
 
 use async_trait::async_trait;
@@ -41,6 +42,7 @@ use std::{
 	time::Instant,
 };
 use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use tracing::{error, info, warn};
 
 #[async_trait]
@@ -105,10 +107,14 @@ impl<T: Database + Sync> Client for SyncClient<T> {
 	fn store_confidence(&self, count: u32, block_number: u32) -> Result<()> {
 		self.db
 			.put(Key::VerifiedCellCount(block_number), count)
-			.wrap_err("Sync Client failed to store Confidence Factor")
+			.wrap_err("Sync Client failed to store Confidence Factor")?;
+		data::put_verified_at(&self.db, block_number, unix_timestamp_nanos())
+			.wrap_err("Sync Client failed to store verification time")
 	}
 }
 
+/// Samples and verifies the block, storing its confidence.
+/// Returns `false` if not enough cells were fetched to verify the block.
 async fn process_block(
 	client: &impl Client,
 	network_client: &impl network::Client,
@@ -116,7 +122,8 @@ async fn process_block(
 	header_hash: H256,
 	cfg: &SyncClientConfig,
 	block_verified_sender: broadcast::Sender<BlockVerified>,
-) -> Result<()> {
+	cancel: &CancellationToken,
+) -> Result<bool> {
 	let block_number = header.number;
 	let begin = Instant::now();
 
@@ -126,7 +133,7 @@ async fn process_block(
 		None => {
 			info!("Skipping block without header extension");
 
-			return Ok(());
+			return Ok(true);
 		},
 		Some((rows, cols, _, commitment)) => {
 			let dimensions =
@@ -135,8 +142,14 @@ async fn process_block(
 			let commitments = commitments::from_slice(&commitment)?;
 
 			// now this is in `u64`
-			let cell_count = rpc::cell_count_for_confidence(cfg.confidence);
-			let positions = rpc::generate_random_cells(dimensions, cell_count);
+			let cell_count = cfg
+				.confidence
+				.cell_count(cfg.withheld_fraction)
+				.max(cfg.min_cell_count);
+			let positions = match cfg.stratified_sampling {
+				true => rpc::generate_stratified_cells(dimensions, cell_count),
+				false => rpc::generate_random_cells(dimensions, cell_count),
+			};
 
 			let (fetched, unfetched, _fetch_stats) = network_client
 				.fetch_verified(
@@ -145,6 +158,7 @@ async fn process_block(
 					dimensions,
 					&commitments,
 					&positions,
+					cancel,
 				)
 				.await?;
 			(positions.len(), fetched.len(), unfetched.len())
@@ -153,13 +167,16 @@ async fn process_block(
 
 	if required > verified {
 		error!(block_number, "Failed to fetch {} cells", unverified);
-		return Ok(());
+		return Ok(false);
 	}
 
 	// write confidence factor into on-disk database
 	client.store_confidence(verified.try_into()?, block_number)?;
 
-	let confidence = Some(calculate_confidence(verified as u32));
+	let confidence = Some(Confidence::from_cell_count(
+		verified as u32,
+		cfg.withheld_fraction,
+	));
 	let client_msg =
 		BlockVerified::try_from((header, confidence)).wrap_err("converting to message failed")?;
 
@@ -167,7 +184,30 @@ async fn process_block(
 		error!("Cannot send block verified message: {error}");
 	}
 
-	Ok(())
+	Ok(true)
+}
+
+/// Fetches the header of the given block and verifies the block using sync client pipeline.
+/// Returns `false` if not enough cells were fetched to verify the block.
+pub async fn verify_block(
+	client: &impl Client,
+	network_client: &impl network::Client,
+	block_number: u32,
+	cfg: &SyncClientConfig,
+	block_verified_sender: broadcast::Sender<BlockVerified>,
+	cancel: &CancellationToken,
+) -> Result<bool> {
+	let (header, header_hash) = client.get_header_by_block_number(block_number).await?;
+	process_block(
+		client,
+		network_client,
+		header,
+		header_hash,
+		cfg,
+		block_verified_sender,
+		cancel,
+	)
+	.await
 }
 
 /// Runs sync client.
@@ -178,6 +218,7 @@ async fn process_block(
 /// * `start_block` - Sync start block
 /// * `end_block` - Sync end block
 /// * `block_verified_sender` - Optional channel to send verified blocks
+/// * `cancel` - Token which cancels the sync, including the in-flight block processing
 pub async fn run(
 	client: impl Client,
 	network_client: impl network::Client,
@@ -185,6 +226,7 @@ pub async fn run(
 	sync_range: Range<u32>,
 	block_verified_sender: broadcast::Sender<BlockVerified>,
 	state: Arc<Mutex<State>>,
+	cancel: CancellationToken,
 ) {
 	if sync_range.is_empty() {
 		warn!("There are no blocks to sync for range {sync_range:?}");
@@ -195,8 +237,25 @@ pub async fn run(
 		warn!("In order to process {sync_blocks_depth} blocks behind latest block, connected nodes needs to be archive nodes!");
 	}
 
+	if cfg.start_jitter > 0 {
+		let delay = random_delay(cfg.start_jitter);
+		info!("Delaying sync start by {delay:?}");
+		tokio::select! {
+			_ = tokio::time::sleep(delay) => (),
+			_ = cancel.cancelled() => {
+				info!("Sync is cancelled");
+				return;
+			},
+		}
+	}
+
 	info!("Syncing block headers for {sync_range:?}");
 	for block_number in sync_range {
+		if cancel.is_cancelled() {
+			info!(block_number, "Sync is cancelled");
+			return;
+		}
+
 		// TODO: This is still an ambiguous check since data fetch can fail.
 		// We should write block status in DB explicitly.
 		match client.is_confidence_stored(block_number) {
@@ -233,6 +292,7 @@ pub async fn run(
 			header_hash,
 			&cfg,
 			block_verified_sender,
+			&cancel,
 		)
 		.await
 		{
@@ -317,7 +377,7 @@ mod tests {
 
 		mock_network_client
 			.expect_fetch_verified()
-			.returning(move |_, _, _, _, positions| {
+			.returning(move |_, _, _, _, positions, _| {
 				let unfetched = vec![];
 				let fetched: Vec<Cell> = vec![
 					Cell {
@@ -388,6 +448,7 @@ mod tests {
 			header_hash,
 			&cfg,
 			block_tx,
+			&CancellationToken::new(),
 		)
 		.await
 		.unwrap();
@@ -405,8 +466,8 @@ mod tests {
 
 		mock_network_client
 			.expect_fetch_verified()
-			.withf(|&x, _, _, _, _| x == 2)
-			.returning(move |_, _, _, _, positions| {
+			.withf(|&x, _, _, _, _, _| x == 2)
+			.returning(move |_, _, _, _, positions, _| {
 				let unfetched = vec![Position { row: 0, col: 3 }];
 				let dht_fetched: Vec<Cell> = vec![
 					Cell {
@@ -474,6 +535,7 @@ mod tests {
 			header_hash,
 			&cfg,
 			block_tx,
+			&CancellationToken::new(),
 		)
 		.await
 		.unwrap();
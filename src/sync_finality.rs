@@ -307,6 +307,11 @@ pub async fn sync(
 			validator_set,
 		};
 		check_finality(&valset, &proof.0.justification.0).context("Finality sync check failed")?;
+		{
+			// Justification of the proof block confirms finality of its ancestors as well
+			let mut state = state.lock().unwrap();
+			state.finality_verified = state.finality_verified.max(Some(p_h.number));
+		}
 
 		trace!("Proof in block: {}", p_h.number);
 		curr_block_num += 1;
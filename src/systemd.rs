@@ -0,0 +1,192 @@
+//! Systemd service notifications.
+//!
+//! When light client runs as a systemd service with `Type=notify`, readiness is reported
+//! once the client is bootstrapped. If watchdog is enabled (`WatchdogSec`), watchdog is
+//! pinged only while the pipeline is alive, which means that a block has been processed
+//! or a new header has been received within the configured liveness timeout.
+//! Hung light client stops pinging, and systemd restarts it.
+//! Notifications are not sent if the light client is not run by systemd.
+
+use color_eyre::Result;
+use std::{
+	env,
+	time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{network::rpc::Event, types::BlockVerified};
+
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+const WATCHDOG_USEC: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID: &str = "WATCHDOG_PID";
+
+#[cfg(unix)]
+fn notify_socket(socket: &str, state: &str) -> Result<()> {
+	use color_eyre::eyre::WrapErr;
+	use std::os::unix::net::UnixDatagram;
+
+	let datagram = UnixDatagram::unbound().wrap_err("Failed to create notify socket")?;
+	match socket.strip_prefix('@') {
+		#[cfg(target_os = "linux")]
+		Some(name) => {
+			use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+			let address = SocketAddr::from_abstract_name(name)?;
+			datagram.send_to_addr(state.as_bytes(), &address)
+		},
+		_ => datagram.send_to(state.as_bytes(), socket),
+	}
+	.wrap_err_with(|| format!("Failed to send {state} notification"))?;
+	Ok(())
+}
+
+#[cfg(not(unix))]
+fn notify_socket(_socket: &str, _state: &str) -> Result<()> {
+	Ok(())
+}
+
+/// Sends state notification to systemd, if notify socket is set
+pub fn notify(state: &str) -> Result<()> {
+	match env::var(NOTIFY_SOCKET) {
+		Ok(socket) => notify_socket(&socket, state),
+		Err(_) => Ok(()),
+	}
+}
+
+/// Notifies systemd that the light client is ready, logging possible error
+pub fn notify_ready() {
+	if let Err(error) = notify("READY=1") {
+		warn!("Systemd notification failed: {error:#}");
+	}
+}
+
+fn parse_watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+	let usec = usec?.parse::<u64>().ok().filter(|&usec| usec > 0)?;
+	match pid.map(str::parse::<u32>) {
+		Some(Ok(pid)) if pid != std::process::id() => None,
+		_ => Some(Duration::from_micros(usec)),
+	}
+}
+
+/// Watchdog interval configured by systemd, if watchdog is enabled for this process
+pub fn watchdog_interval() -> Option<Duration> {
+	let usec = env::var(WATCHDOG_USEC).ok();
+	let pid = env::var(WATCHDOG_PID).ok();
+	parse_watchdog_interval(usec.as_deref(), pid.as_deref())
+}
+
+fn is_alive(last_activity: Instant, now: Instant, liveness_timeout: Duration) -> bool {
+	now.saturating_duration_since(last_activity) <= liveness_timeout
+}
+
+fn is_active<T>(result: &Result<T, RecvError>) -> bool {
+	!matches!(result, Err(RecvError::Closed))
+}
+
+/// Pings systemd watchdog while the pipeline is alive. Returns immediately if watchdog is disabled.
+pub async fn run(
+	liveness_timeout: Duration,
+	mut block_receiver: broadcast::Receiver<BlockVerified>,
+	mut rpc_event_receiver: broadcast::Receiver<Event>,
+) {
+	let Some(watchdog_interval) = watchdog_interval() else {
+		debug!("Systemd watchdog is not enabled");
+		return;
+	};
+	info!(
+		?watchdog_interval,
+		?liveness_timeout,
+		"Systemd watchdog enabled"
+	);
+
+	// Watchdog is pinged twice within the interval, as recommended by systemd
+	let mut ping = tokio::time::interval(watchdog_interval / 2);
+	let mut last_activity = Instant::now();
+	let mut blocks_open = true;
+	let mut headers_open = true;
+	let mut stalled = false;
+
+	loop {
+		tokio::select! {
+			result = block_receiver.recv(), if blocks_open => {
+				blocks_open = is_active(&result);
+				last_activity = Instant::now();
+			},
+			result = rpc_event_receiver.recv(), if headers_open => {
+				headers_open = is_active(&result);
+				last_activity = Instant::now();
+			},
+			_ = ping.tick() => {
+				let alive = is_alive(last_activity, Instant::now(), liveness_timeout);
+				if alive == stalled {
+					stalled = !alive;
+					match alive {
+						true => info!("Pipeline is alive again, resuming watchdog pings"),
+						false => warn!(
+							"No blocks processed in the last {}s, stopping watchdog pings",
+							liveness_timeout.as_secs()
+						),
+					}
+				}
+				if !alive {
+					continue;
+				}
+				if let Err(error) = notify("WATCHDOG=1") {
+					warn!("Systemd watchdog notification failed: {error:#}");
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_alive, parse_watchdog_interval};
+	use std::time::{Duration, Instant};
+	use test_case::test_case;
+
+	#[test_case(None, None => None ; "watchdog disabled")]
+	#[test_case(Some("0"), None => None ; "zero interval")]
+	#[test_case(Some("invalid"), None => None ; "invalid interval")]
+	#[test_case(Some("30000000"), None => Some(Duration::from_secs(30)) ; "no pid")]
+	#[test_case(Some("30000000"), Some("1") => None ; "different pid")]
+	fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+		parse_watchdog_interval(usec, pid)
+	}
+
+	#[test]
+	fn watchdog_interval_own_pid() {
+		let pid = std::process::id().to_string();
+		let interval = parse_watchdog_interval(Some("1000000"), Some(&pid));
+		assert_eq!(interval, Some(Duration::from_secs(1)));
+	}
+
+	#[test]
+	fn liveness() {
+		let now = Instant::now();
+		let timeout = Duration::from_secs(60);
+		assert!(is_alive(now, now, timeout));
+		assert!(is_alive(now, now + timeout, timeout));
+		assert!(!is_alive(
+			now,
+			now + timeout + Duration::from_secs(1),
+			timeout
+		));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn notify_socket() {
+		use std::os::unix::net::UnixDatagram;
+
+		let path = std::env::temp_dir().join(format!("avail_light_notify_{}", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+		let socket = UnixDatagram::bind(&path).unwrap();
+
+		super::notify_socket(path.to_str().unwrap(), "READY=1").unwrap();
+		let mut buffer = [0u8; 16];
+		let len = socket.recv(&mut buffer).unwrap();
+		assert_eq!(&buffer[..len], b"READY=1");
+		std::fs::remove_file(&path).unwrap();
+	}
+}
@@ -4,6 +4,7 @@ use color_eyre::Result;
 use mockall::automock;
 
 pub mod otlp;
+pub mod reporter;
 
 #[derive(Debug)]
 pub enum MetricCounter {
@@ -15,6 +16,11 @@ pub enum MetricCounter {
 	EstablishedConnections,
 	IncomingPutRecord,
 	IncomingGetRecord,
+	ChainStalls,
+	ConnectionStalls,
+	HeaderSourceDivergences,
+	EmptyBlocksSkipped,
+	PeerDisconnections,
 }
 
 pub trait MetricName {
@@ -33,6 +39,11 @@ impl MetricName for MetricCounter {
 			EstablishedConnections => "avail.light.established_connections",
 			IncomingPutRecord => "avail.light.incoming_put_record",
 			IncomingGetRecord => "avail.light.incoming_get_record",
+			ChainStalls => "avail.light.chain_stalls",
+			ConnectionStalls => "avail.light.connection_stalls",
+			HeaderSourceDivergences => "avail.light.header_source_divergences",
+			EmptyBlocksSkipped => "avail.light.empty_blocks_skipped",
+			PeerDisconnections => "avail.light.peer_disconnections",
 		}
 	}
 }
@@ -55,10 +66,20 @@ impl MetricCounter {
 pub enum MetricValue {
 	BlockHeight(u32),
 	BlockConfidence(f64),
+	BlockP2PConfidence(f64),
 	BlockConfidenceThreshold(f64),
 	BlockProcessingDelay(f64),
+	ExpectedBlockTime(f64),
+	BlockRetentionConfidence(f64),
+
+	/// Histograms of the block matrix dimensions, data size (in KiB) and number of sampled cells
+	BlockMatrixRows(u16),
+	BlockMatrixCols(u16),
+	BlockDataSize(u64),
+	BlockSampledCells(u32),
 
 	DHTReplicationFactor(u16),
+	DHTReplicationEstimate(f64),
 
 	DHTFetched(f64),
 	DHTFetchedPercentage(f64),
@@ -69,6 +90,8 @@ pub enum MetricValue {
 	DHTConnectedPeers(usize),
 	DHTQueryTimeout(u32),
 	DHTPingLatency(f64),
+	/// Whether the node is publicly reachable, according to AutoNAT
+	DHTPubliclyReachable(bool),
 
 	RPCFetched(f64),
 	RPCFetchDuration(f64),
@@ -76,6 +99,19 @@ pub enum MetricValue {
 
 	Up(),
 
+	ApiCacheHitRate(f64),
+	/// Latency percentiles (in milliseconds) of the HTTP API route, with route as the first field
+	ApiRouteLatencyP50(String, f64),
+	ApiRouteLatencyP95(String, f64),
+	ApiRouteLatencyP99(String, f64),
+
+	/// Per-app metrics, with app ID as the first field
+	AppBytesReconstructed(u32, u64),
+	AppReconstructionLatency(u32, f64),
+	AppBlocksWithData(u32),
+	AppReconstructionFailures(u32),
+	AppBadEncodings(u32),
+
 	#[cfg(feature = "crawl")]
 	CrawlCellsSuccessRate(f64),
 	#[cfg(feature = "crawl")]
@@ -91,10 +127,19 @@ impl MetricName for MetricValue {
 		match self {
 			BlockHeight(_) => "avail.light.block.height",
 			BlockConfidence(_) => "avail.light.block.confidence",
+			BlockP2PConfidence(_) => "avail.light.block.p2p_confidence",
 			BlockConfidenceThreshold(_) => "avail.light.block.confidence_threshold",
 			BlockProcessingDelay(_) => "avail.light.block.processing_delay",
+			ExpectedBlockTime(_) => "avail.light.block.expected_time",
+			BlockRetentionConfidence(_) => "avail.light.block.retention_confidence",
+
+			BlockMatrixRows(_) => "avail.light.block.matrix_rows",
+			BlockMatrixCols(_) => "avail.light.block.matrix_cols",
+			BlockDataSize(_) => "avail.light.block.data_size",
+			BlockSampledCells(_) => "avail.light.block.sampled_cells",
 
 			DHTReplicationFactor(_) => "avail.light.dht.replication_factor",
+			DHTReplicationEstimate(_) => "avail.light.dht.replication_estimate",
 			DHTFetched(_) => "avail.light.dht.fetched",
 			DHTFetchedPercentage(_) => "avail.light.dht.fetched_percentage",
 			DHTFetchDuration(_) => "avail.light.dht.fetch_duration",
@@ -102,6 +147,7 @@ impl MetricName for MetricValue {
 			DHTPutSuccess(_) => "avail.light.dht.put_success",
 
 			DHTConnectedPeers(_) => "avail.light.dht.connected_peers",
+			DHTPubliclyReachable(_) => "avail.light.dht.publicly_reachable",
 			DHTQueryTimeout(_) => "avail.light.dht.query_timeout",
 			DHTPingLatency(_) => "avail.light.dht.ping_latency",
 
@@ -111,6 +157,17 @@ impl MetricName for MetricValue {
 
 			Up() => "avail.light.up",
 
+			ApiCacheHitRate(_) => "avail.light.api.cache_hit_rate",
+			ApiRouteLatencyP50(..) => "avail.light.api.route_latency_p50",
+			ApiRouteLatencyP95(..) => "avail.light.api.route_latency_p95",
+			ApiRouteLatencyP99(..) => "avail.light.api.route_latency_p99",
+
+			AppBytesReconstructed(..) => "avail.light.app.bytes_reconstructed",
+			AppReconstructionLatency(..) => "avail.light.app.reconstruction_latency",
+			AppBlocksWithData(_) => "avail.light.app.blocks_with_data",
+			AppReconstructionFailures(_) => "avail.light.app.reconstruction_failures",
+			AppBadEncodings(_) => "avail.light.app.bad_encodings",
+
 			#[cfg(feature = "crawl")]
 			CrawlCellsSuccessRate(_) => "avail.light.crawl.cells_success_rate",
 			#[cfg(feature = "crawl")]
@@ -122,6 +179,45 @@ impl MetricName for MetricValue {
 }
 
 impl MetricValue {
+	/// Histogram value of the metric. Histograms record each value, so they are not buffered.
+	fn histogram(&self) -> Option<u64> {
+		use MetricValue::*;
+
+		match self {
+			BlockMatrixRows(rows) => Some(*rows as u64),
+			BlockMatrixCols(cols) => Some(*cols as u64),
+			BlockDataSize(size) => Some(*size),
+			BlockSampledCells(cells) => Some(*cells as u64),
+			_ => None,
+		}
+	}
+
+	/// App ID of the per-app metric, which is recorded with the `app_id` attribute
+	fn app_id(&self) -> Option<u32> {
+		use MetricValue::*;
+
+		match self {
+			AppBytesReconstructed(app_id, _)
+			| AppReconstructionLatency(app_id, _)
+			| AppBlocksWithData(app_id)
+			| AppReconstructionFailures(app_id)
+			| AppBadEncodings(app_id) => Some(*app_id),
+			_ => None,
+		}
+	}
+
+	/// HTTP API route of the per-route metric, which is recorded with the `route` attribute
+	fn route(&self) -> Option<&str> {
+		use MetricValue::*;
+
+		match self {
+			ApiRouteLatencyP50(route, _)
+			| ApiRouteLatencyP95(route, _)
+			| ApiRouteLatencyP99(route, _) => Some(route),
+			_ => None,
+		}
+	}
+
 	// Metric filter for external peers
 	// Only the metrics we wish to send to OTel should be in this list
 	fn is_allowed(&self, origin: &Origin) -> bool {
@@ -130,6 +226,7 @@ impl MetricValue {
 				self,
 				MetricValue::DHTFetchedPercentage(_)
 					| MetricValue::BlockConfidence(_)
+					| MetricValue::BlockP2PConfidence(_)
 					| MetricValue::Up()
 			),
 			_ => true,
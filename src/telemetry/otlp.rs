@@ -7,14 +7,14 @@ use async_trait::async_trait;
 use color_eyre::Result;
 use opentelemetry_api::{
 	global,
-	metrics::{Counter, Meter},
+	metrics::{Counter, Histogram, Meter},
 	KeyValue,
 };
 use opentelemetry_otlp::{ExportConfig, Protocol, WithExportConfig};
 use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::sync::Mutex;
 
-const ATTRIBUTE_NUMBER: usize = 8;
+const ATTRIBUTE_NUMBER: usize = 9;
 
 // NOTE: Buffers are less space efficient, as opposed to the solution with in place compute.
 // That can be optimized by using dedicated data structure with proper bounds.
@@ -22,6 +22,7 @@ const ATTRIBUTE_NUMBER: usize = 8;
 pub struct Metrics {
 	meter: Meter,
 	counters: HashMap<&'static str, Counter<u64>>,
+	histograms: HashMap<&'static str, Histogram<u64>>,
 	attributes: MetricAttributes,
 	metric_buffer: Arc<Mutex<Vec<MetricValue>>>,
 	counter_buffer: Arc<Mutex<Vec<MetricCounter>>>,
@@ -36,6 +37,7 @@ pub struct MetricAttributes {
 	pub operating_mode: String,
 	pub partition_size: String,
 	pub network: String,
+	pub operator_label: String,
 }
 
 impl Metrics {
@@ -49,12 +51,30 @@ impl Metrics {
 			KeyValue::new("partition_size", self.attributes.partition_size.clone()),
 			KeyValue::new("operating_mode", self.attributes.operating_mode.clone()),
 			KeyValue::new("network", self.attributes.network.clone()),
+			KeyValue::new("operator_label", self.attributes.operator_label.clone()),
 		]
 	}
 
-	async fn record_u64(&self, name: &'static str, value: u64) -> Result<()> {
+	/// Common attributes, with the `app_id` attribute for per-app metrics
+	/// and the `route` attribute for per-route metrics
+	fn metric_attributes(&self, app_id: Option<u32>, route: Option<&str>) -> Vec<KeyValue> {
+		let mut attributes = self.attributes().to_vec();
+		if let Some(app_id) = app_id {
+			attributes.push(KeyValue::new("app_id", app_id as i64));
+		}
+		if let Some(route) = route {
+			attributes.push(KeyValue::new("route", route.to_string()));
+		}
+		attributes
+	}
+
+	async fn record_u64(
+		&self,
+		name: &'static str,
+		value: u64,
+		attributes: Vec<KeyValue>,
+	) -> Result<()> {
 		let instrument = self.meter.u64_observable_gauge(name).try_init()?;
-		let attributes = self.attributes();
 		self.meter
 			.register_callback(&[instrument.as_any()], move |observer| {
 				observer.observe_u64(&instrument, value, &attributes)
@@ -62,9 +82,13 @@ impl Metrics {
 		Ok(())
 	}
 
-	async fn record_f64(&self, name: &'static str, value: f64) -> Result<()> {
+	async fn record_f64(
+		&self,
+		name: &'static str,
+		value: f64,
+		attributes: Vec<KeyValue>,
+	) -> Result<()> {
 		let instrument = self.meter.f64_observable_gauge(name).try_init()?;
-		let attributes = self.attributes();
 		self.meter
 			.register_callback(&[instrument.as_any()], move |observer| {
 				observer.observe_f64(&instrument, value, &attributes)
@@ -75,6 +99,7 @@ impl Metrics {
 
 enum Record {
 	MaxU64(&'static str, u64),
+	SumU64(&'static str, u64),
 	AvgF64(&'static str, f64),
 }
 
@@ -88,10 +113,14 @@ impl From<MetricValue> for Record {
 		match value {
 			BlockHeight(number) => MaxU64(name, number as u64),
 			BlockConfidence(number) => AvgF64(name, number),
+			BlockP2PConfidence(number) => AvgF64(name, number),
 			BlockConfidenceThreshold(number) => AvgF64(name, number),
 			BlockProcessingDelay(number) => AvgF64(name, number),
+			ExpectedBlockTime(number) => AvgF64(name, number),
+			BlockRetentionConfidence(number) => AvgF64(name, number),
 
 			DHTReplicationFactor(number) => AvgF64(name, number as f64),
+			DHTReplicationEstimate(number) => AvgF64(name, number),
 
 			DHTFetched(number) => AvgF64(name, number),
 			DHTFetchedPercentage(number) => AvgF64(name, number),
@@ -100,6 +129,7 @@ impl From<MetricValue> for Record {
 			DHTPutSuccess(number) => AvgF64(name, number),
 
 			DHTConnectedPeers(number) => AvgF64(name, number as f64),
+			DHTPubliclyReachable(reachable) => MaxU64(name, reachable as u64),
 			DHTQueryTimeout(number) => AvgF64(name, number as f64),
 			DHTPingLatency(number) => AvgF64(name, number),
 
@@ -109,6 +139,23 @@ impl From<MetricValue> for Record {
 
 			Up() => MaxU64(name, 1),
 
+			// Histograms are recorded directly, without buffering
+			BlockMatrixRows(number) => MaxU64(name, number as u64),
+			BlockMatrixCols(number) => MaxU64(name, number as u64),
+			BlockDataSize(number) => MaxU64(name, number),
+			BlockSampledCells(number) => MaxU64(name, number as u64),
+
+			ApiCacheHitRate(number) => AvgF64(name, number),
+			ApiRouteLatencyP50(_, number) => AvgF64(name, number),
+			ApiRouteLatencyP95(_, number) => AvgF64(name, number),
+			ApiRouteLatencyP99(_, number) => AvgF64(name, number),
+
+			AppBytesReconstructed(_, bytes) => SumU64(name, bytes),
+			AppReconstructionLatency(_, number) => AvgF64(name, number),
+			AppBlocksWithData(_) => SumU64(name, 1),
+			AppReconstructionFailures(_) => SumU64(name, 1),
+			AppBadEncodings(_) => SumU64(name, 1),
+
 			#[cfg(feature = "crawl")]
 			CrawlCellsSuccessRate(number) => AvgF64(name, number),
 			#[cfg(feature = "crawl")]
@@ -140,11 +187,13 @@ fn flatten_metrics(
 	buffer: &[impl Into<Record> + Clone],
 ) -> (HashMap<&'static str, u64>, HashMap<&'static str, f64>) {
 	let mut u64_maximums: HashMap<&'static str, Vec<u64>> = HashMap::new();
+	let mut u64_sums: HashMap<&'static str, u64> = HashMap::new();
 	let mut f64_averages: HashMap<&'static str, Vec<f64>> = HashMap::new();
 
 	for value in buffer {
 		match value.clone().into() {
 			Record::MaxU64(name, number) => u64_maximums.entry(name).or_default().push(number),
+			Record::SumU64(name, number) => *u64_sums.entry(name).or_default() += number,
 			Record::AvgF64(name, number) => f64_averages.entry(name).or_default().push(number),
 		}
 	}
@@ -152,6 +201,7 @@ fn flatten_metrics(
 	let u64_metrics = u64_maximums
 		.into_iter()
 		.map(|(name, v)| (name, v.into_iter().max().unwrap_or(0)))
+		.chain(u64_sums)
 		.collect();
 
 	let f64_metrics = f64_averages
@@ -179,10 +229,15 @@ impl super::Metrics for Metrics {
 	}
 
 	/// Puts metric to the metric buffer if it is allowed.
+	/// If metric is a histogram, value is recorded.
 	async fn record(&self, value: super::MetricValue) {
 		if !value.is_allowed(&self.attributes.origin) {
 			return;
 		}
+		if let Some(number) = value.histogram() {
+			self.histograms[&value.name()].record(number, &self.attributes());
+			return;
+		}
 
 		let mut metric_buffer = self.metric_buffer.lock().await;
 		metric_buffer.push(value);
@@ -195,20 +250,29 @@ impl super::Metrics for Metrics {
 		counter_buffer.clear();
 
 		let mut metric_buffer = self.metric_buffer.lock().await;
-		let (metrics_u64, metrics_f64) = flatten_metrics(&metric_buffer);
-		metric_buffer.clear();
+		// Per-app and per-route metrics are aggregated separately for each app and route
+		let mut buffers: HashMap<(Option<u32>, Option<String>), Vec<MetricValue>> = HashMap::new();
+		for value in metric_buffer.drain(..) {
+			let key = (value.app_id(), value.route().map(ToString::to_string));
+			buffers.entry(key).or_default().push(value);
+		}
 
 		for (counter, value) in counters {
 			self.counters[&counter].add(value, &self.attributes());
 		}
 
-		// TODO: Aggregate errors instead of early return
-		for (metric, value) in metrics_u64.into_iter() {
-			self.record_u64(metric, value).await?;
-		}
+		for ((app_id, route), buffer) in buffers {
+			let (metrics_u64, metrics_f64) = flatten_metrics(&buffer);
+			let attributes = self.metric_attributes(app_id, route.as_deref());
+
+			// TODO: Aggregate errors instead of early return
+			for (metric, value) in metrics_u64.into_iter() {
+				self.record_u64(metric, value, attributes.clone()).await?;
+			}
 
-		for (metric, value) in metrics_f64.into_iter() {
-			self.record_f64(metric, value).await?;
+			for (metric, value) in metrics_f64.into_iter() {
+				self.record_f64(metric, value, attributes.clone()).await?;
+			}
 		}
 
 		Ok(())
@@ -225,6 +289,11 @@ fn init_counters(meter: Meter, origin: Origin) -> HashMap<&'static str, Counter<
 		MetricCounter::EstablishedConnections,
 		MetricCounter::IncomingPutRecord,
 		MetricCounter::IncomingGetRecord,
+		MetricCounter::ChainStalls,
+		MetricCounter::ConnectionStalls,
+		MetricCounter::HeaderSourceDivergences,
+		MetricCounter::EmptyBlocksSkipped,
+		MetricCounter::PeerDisconnections,
 	]
 	.iter()
 	.filter(|counter| MetricCounter::is_allowed(counter, &origin))
@@ -232,6 +301,19 @@ fn init_counters(meter: Meter, origin: Origin) -> HashMap<&'static str, Counter<
 	.collect()
 }
 
+fn init_histograms(meter: Meter, origin: Origin) -> HashMap<&'static str, Histogram<u64>> {
+	[
+		MetricValue::BlockMatrixRows(0),
+		MetricValue::BlockMatrixCols(0),
+		MetricValue::BlockDataSize(0),
+		MetricValue::BlockSampledCells(0),
+	]
+	.iter()
+	.filter(|value| value.is_allowed(&origin))
+	.map(|value| (value.name(), meter.u64_histogram(value.name()).init()))
+	.collect()
+}
+
 pub fn initialize(
 	endpoint: String,
 	attributes: MetricAttributes,
@@ -258,11 +340,13 @@ pub fn initialize(
 	let meter = global::meter("avail_light_client");
 
 	// Initialize counters - they need to persist unlike Gauges that are recreated on every record
-	let counters = init_counters(meter.clone(), origin);
+	let counters = init_counters(meter.clone(), origin.clone());
+	let histograms = init_histograms(meter.clone(), origin);
 	Ok(Metrics {
 		meter,
 		attributes,
 		counters,
+		histograms,
 		metric_buffer: Arc::new(Mutex::new(vec![])),
 		counter_buffer: Arc::new(Mutex::new(vec![])),
 	})
@@ -372,4 +456,26 @@ mod tests {
 		assert_eq!(m_f64.get("avail.light.block.confidence"), Some(&98.5));
 		assert_eq!(m_f64.get("avail.light.dht.connected_peers"), Some(&85.0));
 	}
+
+	#[test]
+	fn test_flatten_app_metrics() {
+		let buffer = &[
+			MetricValue::AppBytesReconstructed(1, 100),
+			MetricValue::AppBlocksWithData(1),
+			MetricValue::AppBytesReconstructed(1, 50),
+			MetricValue::AppBlocksWithData(1),
+			MetricValue::AppReconstructionLatency(1, 1.0),
+			MetricValue::AppReconstructionLatency(1, 2.0),
+		];
+		assert!(buffer.iter().all(|value| value.app_id() == Some(1)));
+		let (m_u64, m_f64) = flatten_metrics(buffer);
+		assert_eq!(m_u64.len(), 2);
+		assert_eq!(m_u64.get("avail.light.app.bytes_reconstructed"), Some(&150));
+		assert_eq!(m_u64.get("avail.light.app.blocks_with_data"), Some(&2));
+		assert_eq!(m_f64.len(), 1);
+		assert_eq!(
+			m_f64.get("avail.light.app.reconstruction_latency"),
+			Some(&1.5)
+		);
+	}
 }
@@ -0,0 +1,166 @@
+//! Opt-in reporting of anonymous stats to the telemetry aggregation server.
+//!
+//! Reporting is disabled unless the telemetry report endpoint is configured.
+//! Reports contain light client version, latest block, sync status, and confidence and DHT hit rate
+//! summary of the blocks processed since the previous report. Instance is identified by a random ID,
+//! generated on the first report and persisted in the database, which is not derived from
+//! the peer ID or Avail identity, so reports cannot be linked to the node on the network.
+
+use color_eyre::{eyre::WrapErr, Result};
+use hyper::{Body, Method, Request};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tokio::time::{self, Instant};
+use tracing::{debug, info, warn};
+
+use crate::{
+	data::{Database, Key},
+	network::rpc::HttpClient,
+	report::{self, Summary},
+	types::{State, TelemetryReportConfig},
+};
+
+/// Maximum number of blocks summarized in a single report
+const MAX_REPORTED_BLOCKS: u32 = 1000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TelemetryReport {
+	/// Random ID of the light client instance
+	pub instance_id: String,
+	/// Light client version
+	pub version: String,
+	pub latest_block: u32,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub synced: Option<bool>,
+	/// Summary of the blocks since the previous report, if there are any
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub summary: Option<Summary>,
+}
+
+/// Loads instance ID, generating and storing a random one if it doesn't exist
+pub fn instance_id(db: &impl Database) -> Result<String> {
+	if let Some(instance_id) = db.get::<String>(Key::TelemetryInstanceId)? {
+		return Ok(instance_id);
+	}
+	let instance_id = hex::encode(rand::random::<[u8; 16]>());
+	db.put(Key::TelemetryInstanceId, instance_id.clone())
+		.wrap_err("Failed to store telemetry instance ID")?;
+	Ok(instance_id)
+}
+
+/// Creates report summarizing blocks after the last reported block, up to the latest block
+fn create_report(
+	db: &impl Database,
+	instance_id: &str,
+	version: &str,
+	latest: u32,
+	synced: Option<bool>,
+	last_reported: Option<u32>,
+) -> Result<TelemetryReport> {
+	let first = last_reported
+		.map_or(1, |last| last + 1)
+		.max(latest.saturating_sub(MAX_REPORTED_BLOCKS - 1))
+		.max(1);
+	let summary = match first <= latest {
+		true => Some(report::generate(db, first..=latest, version.to_string())?.summary),
+		false => None,
+	};
+	Ok(TelemetryReport {
+		instance_id: instance_id.to_string(),
+		version: version.to_string(),
+		latest_block: latest,
+		synced,
+		summary,
+	})
+}
+
+async fn post_report(client: &HttpClient, url: &str, report: &TelemetryReport) -> Result<()> {
+	let request = Request::builder()
+		.method(Method::POST)
+		.uri(url)
+		.header("content-type", "application/json")
+		.body(Body::from(serde_json::to_vec(report)?))?;
+	client.send(request).await?;
+	Ok(())
+}
+
+/// Periodically posts telemetry reports. Returns immediately if reporting is disabled.
+pub async fn run(
+	cfg: TelemetryReportConfig,
+	client: HttpClient,
+	db: impl Database,
+	state: Arc<Mutex<State>>,
+	version: String,
+) {
+	let Some(endpoint) = cfg.endpoint else {
+		debug!("Telemetry reporting is disabled");
+		return;
+	};
+
+	let instance_id = match instance_id(&db) {
+		Ok(instance_id) => instance_id,
+		Err(error) => {
+			warn!("Telemetry reporting is disabled, cannot load instance ID: {error:#}");
+			return;
+		},
+	};
+	info!("Reporting anonymous telemetry to {endpoint} as instance {instance_id}");
+
+	let mut interval = time::interval_at(Instant::now() + cfg.interval, cfg.interval);
+	let mut last_reported = None;
+	loop {
+		interval.tick().await;
+		let (latest, synced) = {
+			let state = state.lock().expect("Lock should be acquired");
+			(state.latest, state.synced)
+		};
+		let report = match create_report(&db, &instance_id, &version, latest, synced, last_reported)
+		{
+			Ok(report) => report,
+			Err(error) => {
+				warn!("Cannot create telemetry report: {error:#}");
+				continue;
+			},
+		};
+		match post_report(&client, &endpoint, &report).await {
+			Ok(()) => {
+				debug!(latest_block = report.latest_block, "Telemetry report sent");
+				last_reported = Some(report.latest_block);
+			},
+			Err(error) => warn!("Telemetry report failed: {error:#}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{create_report, instance_id};
+	use crate::data::{mem_db::MemoryDB, Database, Key};
+
+	#[test]
+	fn persistent_instance_id() {
+		let db = MemoryDB::default();
+		let id = instance_id(&db).unwrap();
+		assert_eq!(id.len(), 32);
+		assert_eq!(instance_id(&db).unwrap(), id);
+	}
+
+	#[test]
+	fn report_since_last_reported() {
+		let db = MemoryDB::default();
+		for block_number in 1..=10 {
+			db.put(Key::VerifiedCellCount(block_number), 8u32).unwrap();
+		}
+		let report = create_report(&db, "id", "v1", 10, Some(true), None).unwrap();
+		let summary = report.summary.unwrap();
+		assert_eq!(summary.blocks, 10);
+		assert_eq!(summary.verified, 10);
+
+		let report = create_report(&db, "id", "v1", 10, Some(true), Some(7)).unwrap();
+		assert_eq!(report.summary.unwrap().blocks, 3);
+
+		let report = create_report(&db, "id", "v1", 10, Some(true), Some(10)).unwrap();
+		assert_eq!(report.latest_block, 10);
+		assert!(report.summary.is_none());
+	}
+}
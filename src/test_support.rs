@@ -0,0 +1,3 @@
+//! Support for hermetic end-to-end testing of the light client, available with `test-support` feature.
+
+pub mod mock_node;
@@ -0,0 +1,472 @@
+//! Mock full node serving the subset of JSON-RPC over WebSocket used by the light client.
+//!
+//! Supported methods are `system_version`, `chain_getBlockHash`, `chain_getHeader`, `chain_getFinalizedHead`,
+//! head subscriptions (`chain_subscribeNewHeads`, `chain_subscribeFinalizedHeads` and `subscribe_newHead`),
+//! `kate_queryProof` and `kate_queryRows`. Blocks are produced by the test using [`MockNode::produce_block`].
+//!
+//! Faults are scripted per method with [`Faults::inject`], so responses (or head notifications,
+//! using `chain_newHead` and `chain_finalizedHead` as method names) can be delayed, dropped,
+//! or, in case of `kate_queryProof`, returned with invalid proofs.
+
+use crate::utils::extract_kate;
+use avail_subxt::primitives::Header;
+use codec::Encode;
+use color_eyre::{eyre::WrapErr, Result};
+use futures::{FutureExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sp_core::{blake2_256, H256, U256};
+use std::{
+	collections::HashMap,
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex, RwLock,
+	},
+	time::Duration,
+};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{debug, error};
+use warp::{
+	ws::{Message, WebSocket},
+	Filter,
+};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+	/// Delays the response by the given duration
+	Delay(Duration),
+	/// Drops the response, request is never answered
+	Drop,
+	/// Corrupts proofs returned by `kate_queryProof`
+	BadProof,
+}
+
+struct Rule {
+	method: String,
+	fault: Fault,
+	remaining: Option<usize>,
+}
+
+/// Script of faults applied to the mock node responses
+#[derive(Clone, Default)]
+pub struct Faults(Arc<Mutex<Vec<Rule>>>);
+
+impl Faults {
+	/// Injects fault for the given method, applied `times` times or always if `times` is `None`
+	pub fn inject(&self, method: &str, fault: Fault, times: Option<usize>) {
+		let mut rules = self.0.lock().expect("Lock should be acquired");
+		rules.push(Rule {
+			method: method.to_string(),
+			fault,
+			remaining: times,
+		});
+	}
+
+	pub fn clear(&self) {
+		self.0.lock().expect("Lock should be acquired").clear();
+	}
+
+	fn take(&self, method: &str) -> Vec<Fault> {
+		let mut rules = self.0.lock().expect("Lock should be acquired");
+		let mut faults = vec![];
+		for rule in rules.iter_mut().filter(|rule| rule.method == method) {
+			if let Some(remaining) = rule.remaining.as_mut() {
+				if *remaining == 0 {
+					continue;
+				}
+				*remaining -= 1;
+			}
+			faults.push(rule.fault.clone());
+		}
+		rules.retain(|rule| rule.remaining != Some(0));
+		faults
+	}
+
+	/// Applies delays and returns `false` if response should be dropped
+	async fn apply(faults: &[Fault]) -> bool {
+		for fault in faults {
+			match fault {
+				Fault::Delay(duration) => tokio::time::sleep(*duration).await,
+				Fault::Drop => return false,
+				Fault::BadProof => (),
+			}
+		}
+		true
+	}
+}
+
+struct MockBlock {
+	hash: H256,
+	header: Header,
+	/// Cell contents (proof followed by the scalar) by row and column, missing cells have zero content
+	cells: HashMap<(u32, u16), [u8; 80]>,
+}
+
+impl MockBlock {
+	fn content(&self, row: u32, col: u16) -> [u8; 80] {
+		self.cells.get(&(row, col)).copied().unwrap_or([0u8; 80])
+	}
+
+	fn cols(&self) -> u16 {
+		extract_kate(&self.header.extension).map_or(0, |(_, cols, ..)| cols)
+	}
+}
+
+#[derive(Deserialize)]
+struct Request {
+	id: Value,
+	method: String,
+	#[serde(default)]
+	params: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct CellParam {
+	row: u32,
+	col: u32,
+}
+
+type RpcResult = std::result::Result<Value, (i64, String)>;
+
+fn invalid_params(error: impl std::fmt::Display) -> (i64, String) {
+	(INVALID_PARAMS, format!("Invalid params: {error}"))
+}
+
+fn param<T: serde::de::DeserializeOwned>(
+	params: &[Value],
+	index: usize,
+) -> Result<Option<T>, (i64, String)> {
+	match params.get(index) {
+		None | Some(Value::Null) => Ok(None),
+		Some(value) => serde_json::from_value(value.clone())
+			.map(Some)
+			.map_err(invalid_params),
+	}
+}
+
+#[derive(Clone)]
+pub struct MockNode {
+	system_version: String,
+	blocks: Arc<RwLock<Vec<MockBlock>>>,
+	faults: Faults,
+	heads: broadcast::Sender<Header>,
+	subscription_id: Arc<AtomicU64>,
+}
+
+impl MockNode {
+	/// Creates mock node reporting the given system version (it has to match expected node variant)
+	pub fn new(system_version: &str) -> Self {
+		let (heads, _) = broadcast::channel(1024);
+		MockNode {
+			system_version: system_version.to_string(),
+			blocks: Default::default(),
+			faults: Default::default(),
+			heads,
+			subscription_id: Default::default(),
+		}
+	}
+
+	pub fn faults(&self) -> &Faults {
+		&self.faults
+	}
+
+	/// Adds finalized block with given cell contents and notifies head subscribers
+	pub fn produce_block(&self, header: Header, cells: HashMap<(u32, u16), [u8; 80]>) -> H256 {
+		let hash: H256 = Encode::using_encoded(&header, blake2_256).into();
+		{
+			let mut blocks = self.blocks.write().expect("Lock should be acquired");
+			blocks.push(MockBlock {
+				hash,
+				header: header.clone(),
+				cells,
+			});
+		}
+		// Sending fails only if there are no subscribers
+		let _ = self.heads.send(header);
+		hash
+	}
+
+	fn with_block<T>(&self, hash: Option<H256>, f: impl FnOnce(&MockBlock) -> T) -> Option<T> {
+		let blocks = self.blocks.read().expect("Lock should be acquired");
+		match hash {
+			Some(hash) => blocks.iter().find(|block| block.hash == hash),
+			None => blocks.last(),
+		}
+		.map(f)
+	}
+
+	fn block_hash(&self, block_number: Option<u32>) -> Option<H256> {
+		let blocks = self.blocks.read().expect("Lock should be acquired");
+		match block_number {
+			Some(number) => blocks.iter().find(|block| block.header.number == number),
+			None => blocks.last(),
+		}
+		.map(|block| block.hash)
+	}
+
+	/// Handles request, returning `None` if response is dropped
+	async fn call(&self, method: &str, params: &[Value]) -> Option<RpcResult> {
+		let faults = self.faults.take(method);
+		if !Faults::apply(&faults).await {
+			debug!(method, "Dropping mock node response");
+			return None;
+		}
+		let bad_proof = faults.contains(&Fault::BadProof);
+
+		let result = match method {
+			"system_version" => Ok(json!(self.system_version)),
+			"chain_getBlockHash" => {
+				param::<u32>(params, 0).map(|number| json!(self.block_hash(number)))
+			},
+			"chain_getFinalizedHead" => Ok(json!(self.block_hash(None))),
+			"chain_getHeader" => param::<H256>(params, 0)
+				.map(|hash| json!(self.with_block(hash, |block| block.header.clone()))),
+			"kate_queryProof" => self.query_proof(params, bad_proof),
+			"kate_queryRows" => self.query_rows(params),
+			"chain_unsubscribeNewHeads"
+			| "chain_unsubscribeFinalizedHeads"
+			| "unsubscribe_newHead" => Ok(json!(true)),
+			_ => Err((METHOD_NOT_FOUND, format!("Method not found: {method}"))),
+		};
+		Some(result)
+	}
+
+	fn query_proof(&self, params: &[Value], bad_proof: bool) -> RpcResult {
+		let cells = param::<Vec<CellParam>>(params, 0)?.unwrap_or_default();
+		let hash = param::<H256>(params, 1)?;
+		self.with_block(hash, |block| {
+			let proofs = cells
+				.iter()
+				.map(|cell| {
+					let mut content = block.content(cell.row, cell.col as u16);
+					if bad_proof {
+						content[0] ^= 0xff;
+					}
+					let scalar = U256::from_big_endian(&content[48..]);
+					json!([scalar, content[..48].to_vec()])
+				})
+				.collect::<Vec<_>>();
+			json!(proofs)
+		})
+		.ok_or_else(|| invalid_params("block not found"))
+	}
+
+	fn query_rows(&self, params: &[Value]) -> RpcResult {
+		let rows = param::<Vec<u32>>(params, 0)?.unwrap_or_default();
+		let hash = param::<H256>(params, 1)?;
+		self.with_block(hash, |block| {
+			let rows = rows
+				.iter()
+				.map(|&row| {
+					(0..block.cols())
+						.map(|col| U256::from_big_endian(&block.content(row, col)[48..]))
+						.collect::<Vec<_>>()
+				})
+				.collect::<Vec<_>>();
+			json!(rows)
+		})
+		.ok_or_else(|| invalid_params("block not found"))
+	}
+
+	fn subscribe(
+		&self,
+		notification: &'static str,
+		sender: mpsc::UnboundedSender<Message>,
+	) -> Value {
+		let subscription = self
+			.subscription_id
+			.fetch_add(1, Ordering::Relaxed)
+			.to_string();
+		let mut heads = self.heads.subscribe();
+		let faults = self.faults.clone();
+		let result = json!(subscription);
+
+		tokio::spawn(async move {
+			while let Ok(header) = heads.recv().await {
+				if !Faults::apply(&faults.take(notification)).await {
+					continue;
+				}
+				let message = json!({
+					"jsonrpc": "2.0",
+					"method": notification,
+					"params": { "subscription": subscription, "result": header },
+				});
+				if sender.send(Message::text(message.to_string())).is_err() {
+					return;
+				}
+			}
+		});
+		result
+	}
+
+	async fn handle(self, message: Message, sender: mpsc::UnboundedSender<Message>) {
+		let Ok(text) = message.to_str() else {
+			return;
+		};
+		let request: Request = match serde_json::from_str(text) {
+			Ok(request) => request,
+			Err(error) => {
+				error!("Cannot parse mock node request: {error}");
+				return;
+			},
+		};
+
+		let notification = match request.method.as_str() {
+			"chain_subscribeNewHeads" | "subscribe_newHead" => Some("chain_newHead"),
+			"chain_subscribeFinalizedHeads" => Some("chain_finalizedHead"),
+			_ => None,
+		};
+
+		let result = match notification {
+			Some(notification) => Some(Ok(self.subscribe(notification, sender.clone()))),
+			None => self.call(&request.method, &request.params).await,
+		};
+
+		let response = match result {
+			None => return,
+			Some(Ok(result)) => json!({ "jsonrpc": "2.0", "id": request.id, "result": result }),
+			Some(Err((code, message))) => json!({
+				"jsonrpc": "2.0",
+				"id": request.id,
+				"error": { "code": code, "message": message },
+			}),
+		};
+		let _ = sender.send(Message::text(response.to_string()));
+	}
+
+	async fn connect(self, web_socket: WebSocket) {
+		let (web_socket_sender, mut web_socket_receiver) = web_socket.split();
+		let (sender, receiver) = mpsc::unbounded_channel();
+
+		tokio::spawn(
+			UnboundedReceiverStream::new(receiver)
+				.map(Ok)
+				.forward(web_socket_sender)
+				.map(|result| {
+					if let Err(error) = result {
+						error!("Error sending mock node message: {error}");
+					}
+				}),
+		);
+
+		while let Some(Ok(message)) = web_socket_receiver.next().await {
+			if message.is_text() {
+				// Requests are handled concurrently, so delayed responses don't block others
+				tokio::spawn(self.clone().handle(message, sender.clone()));
+			}
+		}
+	}
+
+	/// Starts mock node on the local ephemeral port and returns its address
+	pub async fn run(self) -> Result<SocketAddr> {
+		let node = warp::any().map(move || self.clone());
+		let routes = warp::ws()
+			.and(node)
+			.map(|ws: warp::ws::Ws, node: MockNode| {
+				ws.on_upgrade(move |web_socket| node.connect(web_socket))
+			});
+
+		let (address, server) = warp::serve(routes)
+			.try_bind_ephemeral(([127, 0, 0, 1], 0))
+			.wrap_err("Cannot bind mock node")?;
+		tokio::spawn(server);
+		Ok(address)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Fault, MockNode};
+	use avail_subxt::{
+		api::runtime_types::avail_core::{
+			data_lookup::compact::CompactDataLookup,
+			header::extension::{v3, HeaderExtension},
+			kate_commitment::v3::KateCommitment,
+		},
+		config::substrate::Digest,
+		primitives::Header,
+	};
+	use serde_json::json;
+	use sp_core::H256;
+	use std::{collections::HashMap, time::Duration};
+
+	fn header(number: u32) -> Header {
+		Header {
+			parent_hash: H256::zero(),
+			number,
+			state_root: H256::zero(),
+			extrinsics_root: H256::zero(),
+			digest: Digest { logs: vec![] },
+			extension: HeaderExtension::V3(v3::HeaderExtension {
+				commitment: KateCommitment {
+					rows: 1,
+					cols: 4,
+					data_root: H256::zero(),
+					commitment: vec![0u8; 48],
+				},
+				app_lookup: CompactDataLookup {
+					size: 1,
+					index: vec![],
+				},
+			}),
+		}
+	}
+
+	#[tokio::test]
+	async fn block_hash_and_header() {
+		let node = MockNode::new("1.0.0");
+		let hash = node.produce_block(header(1), HashMap::new());
+
+		let result = node.call("chain_getBlockHash", &[json!(1)]).await.unwrap();
+		assert_eq!(result.unwrap(), json!(hash));
+		let result = node.call("chain_getFinalizedHead", &[]).await.unwrap();
+		assert_eq!(result.unwrap(), json!(hash));
+		let result = node.call("chain_getHeader", &[json!(hash)]).await.unwrap();
+		assert_eq!(result.unwrap(), json!(header(1)));
+		assert!(node.call("unknown", &[]).await.unwrap().is_err());
+	}
+
+	#[tokio::test]
+	async fn kate_proof_with_faults() {
+		let node = MockNode::new("1.0.0");
+		let mut content = [0u8; 80];
+		content[79] = 1;
+		let hash = node.produce_block(header(1), HashMap::from([((0, 1), content)]));
+		let params = [json!([{ "row": 0, "col": 1 }]), json!(hash)];
+
+		let proofs = node
+			.call("kate_queryProof", &params)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_eq!(proofs[0][1], json!(vec![0u8; 48]));
+
+		node.faults()
+			.inject("kate_queryProof", Fault::BadProof, Some(1));
+		let proofs = node
+			.call("kate_queryProof", &params)
+			.await
+			.unwrap()
+			.unwrap();
+		assert_ne!(proofs[0][1], json!(vec![0u8; 48]));
+
+		node.faults()
+			.inject("kate_queryProof", Fault::Drop, Some(1));
+		assert!(node.call("kate_queryProof", &params).await.is_none());
+		assert!(node.call("kate_queryProof", &params).await.is_some());
+
+		node.faults().inject(
+			"system_version",
+			Fault::Delay(Duration::from_millis(50)),
+			None,
+		);
+		let begin = std::time::Instant::now();
+		node.call("system_version", &[]).await.unwrap().unwrap();
+		assert!(begin.elapsed() >= Duration::from_millis(50));
+	}
+}
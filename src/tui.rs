@@ -0,0 +1,397 @@
+//! Terminal dashboard of the running light client.
+//!
+//! Dashboard shows latest and verified block heights, confidence and fetch source split of the recent blocks,
+//! number of connected DHT peers, and the tail of the log, refreshed every second.
+//! While the dashboard is shown, logs are written to the in-memory buffer instead of the standard output.
+//! Pressing `q` or `Ctrl-C` closes the dashboard and shuts down the light client.
+
+use color_eyre::Result;
+use crossterm::{
+	event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers},
+	execute,
+	terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+	backend::CrosstermBackend,
+	layout::{Constraint, Direction, Layout},
+	style::{Color, Style},
+	text::Line,
+	widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Row, Table},
+	Frame, Terminal,
+};
+use std::{
+	collections::VecDeque,
+	io::{self, Stdout, Write},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+use tracing::error;
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::{
+	confidence::Confidence,
+	data::{CellSource, Database, Key, SampledCell},
+	network::p2p,
+	shutdown::Controller,
+	types::{OptionBlockRange, State},
+};
+
+/// Number of the most recent blocks shown on the dashboard
+const RECENT_BLOCKS: u32 = 20;
+
+/// Number of log lines kept in the buffer
+const LOG_LINES: usize = 500;
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Buffer of the most recent log lines, used as a log writer while the dashboard is shown
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<VecDeque<String>>>);
+
+impl LogBuffer {
+	fn lines(&self) -> Vec<String> {
+		let lines = self.0.lock().expect("Lock should be acquired");
+		lines.iter().cloned().collect()
+	}
+}
+
+impl Write for LogBuffer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut lines = self.0.lock().expect("Lock should be acquired");
+		for line in String::from_utf8_lossy(buf).lines() {
+			if lines.len() == LOG_LINES {
+				lines.pop_front();
+			}
+			lines.push_back(line.to_string());
+		}
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl<'a> MakeWriter<'a> for LogBuffer {
+	type Writer = LogBuffer;
+
+	fn make_writer(&'a self) -> Self::Writer {
+		self.clone()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct RecentBlock {
+	block_number: u32,
+	confidence: Option<Confidence>,
+	dht_cells: usize,
+	rpc_cells: usize,
+}
+
+#[derive(Debug, Default)]
+struct Snapshot {
+	latest: u32,
+	verified: Option<u32>,
+	synced: Option<bool>,
+	peers: Option<usize>,
+	blocks: Vec<RecentBlock>,
+}
+
+impl Snapshot {
+	/// Share of the recent blocks sampled cells fetched from the DHT
+	fn dht_ratio(&self) -> Option<f64> {
+		let dht = self
+			.blocks
+			.iter()
+			.map(|block| block.dht_cells)
+			.sum::<usize>();
+		let rpc = self
+			.blocks
+			.iter()
+			.map(|block| block.rpc_cells)
+			.sum::<usize>();
+		(dht + rpc > 0).then(|| dht as f64 / (dht + rpc) as f64)
+	}
+}
+
+fn recent_blocks(
+	db: &impl Database,
+	latest: u32,
+	withheld_fraction: f64,
+) -> Result<Vec<RecentBlock>> {
+	let first = latest.saturating_sub(RECENT_BLOCKS - 1).max(1);
+	let mut blocks = vec![];
+	for block_number in (first..=latest).rev() {
+		let confidence = db
+			.get::<u32>(Key::VerifiedCellCount(block_number))?
+			.map(|count| Confidence::from_cell_count(count, withheld_fraction));
+		let sampled_cells = db
+			.get::<Vec<SampledCell>>(Key::SampledCells(block_number))?
+			.unwrap_or_default();
+		let dht_cells = sampled_cells
+			.iter()
+			.filter(|cell| cell.source == CellSource::DHT)
+			.count();
+		blocks.push(RecentBlock {
+			block_number,
+			confidence,
+			dht_cells,
+			rpc_cells: sampled_cells.len() - dht_cells,
+		});
+	}
+	Ok(blocks)
+}
+
+async fn snapshot(
+	db: &impl Database,
+	state: &Arc<Mutex<State>>,
+	p2p_client: &p2p::Client,
+	withheld_fraction: f64,
+) -> Result<Snapshot> {
+	let (latest, verified, synced) = {
+		let state = state.lock().expect("Lock should be acquired");
+		(state.latest, state.confidence_achieved.last(), state.synced)
+	};
+	Ok(Snapshot {
+		latest,
+		verified,
+		synced,
+		peers: p2p_client.count_dht_entries().await.ok(),
+		blocks: recent_blocks(db, latest, withheld_fraction)?,
+	})
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+	value.map_or("-".to_string(), |value| value.to_string())
+}
+
+fn draw(frame: &mut Frame, snapshot: &Snapshot, logs: &[String]) {
+	let areas = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints([
+			Constraint::Length(3),
+			Constraint::Length(RECENT_BLOCKS as u16 + 3),
+			Constraint::Min(5),
+		])
+		.split(frame.size());
+	let middle = Layout::default()
+		.direction(Direction::Horizontal)
+		.constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+		.split(areas[1]);
+
+	let synced = match snapshot.synced {
+		Some(true) => "synced",
+		Some(false) => "syncing",
+		None => "-",
+	};
+	let status = format!(
+		"Latest block: {}   Verified block: {}   Sync: {synced}   DHT peers: {}   (press q to quit)",
+		snapshot.latest,
+		optional(snapshot.verified),
+		optional(snapshot.peers),
+	);
+	let title = Block::default()
+		.borders(Borders::ALL)
+		.title("Avail light client");
+	frame.render_widget(Paragraph::new(status).block(title), areas[0]);
+
+	let rows = snapshot.blocks.iter().map(|block| {
+		let confidence = block
+			.confidence
+			.map_or("-".to_string(), |confidence| format!("{confidence:.3}%"));
+		Row::new(vec![
+			block.block_number.to_string(),
+			confidence,
+			block.dht_cells.to_string(),
+			block.rpc_cells.to_string(),
+		])
+	});
+	let widths = [
+		Constraint::Length(12),
+		Constraint::Length(12),
+		Constraint::Length(10),
+		Constraint::Length(10),
+	];
+	let table = Table::new(rows, widths)
+		.header(
+			Row::new(vec!["Block", "Confidence", "DHT cells", "RPC cells"])
+				.style(Style::default().fg(Color::Yellow)),
+		)
+		.block(
+			Block::default()
+				.borders(Borders::ALL)
+				.title("Recent blocks"),
+		);
+	frame.render_widget(table, middle[0]);
+
+	let ratio = snapshot.dht_ratio();
+	let gauge = Gauge::default()
+		.block(
+			Block::default()
+				.borders(Borders::ALL)
+				.title("Cells fetched from DHT (vs RPC)"),
+		)
+		.gauge_style(Style::default().fg(Color::Green))
+		.ratio(ratio.unwrap_or_default())
+		.label(ratio.map_or("-".to_string(), |ratio| format!("{:.1}%", ratio * 100.0)));
+	let gauge_area = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints([Constraint::Length(3), Constraint::Min(0)])
+		.split(middle[1]);
+	frame.render_widget(gauge, gauge_area[0]);
+
+	let visible = areas[2].height.saturating_sub(2) as usize;
+	let items = logs
+		.iter()
+		.skip(logs.len().saturating_sub(visible))
+		.map(|line| ListItem::new(Line::from(line.as_str())));
+	let list = List::new(items).block(Block::default().borders(Borders::ALL).title("Log"));
+	frame.render_widget(list, areas[2]);
+}
+
+fn restore(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+	disable_raw_mode()?;
+	execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+	terminal.show_cursor()?;
+	Ok(())
+}
+
+/// Terminal in raw mode on the alternate screen, which is restored when dropped,
+/// including when the dashboard is cancelled on shutdown
+struct DashboardTerminal(Terminal<CrosstermBackend<Stdout>>);
+
+impl DashboardTerminal {
+	fn enter() -> Result<Self> {
+		let terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+		enable_raw_mode()?;
+		let mut terminal = DashboardTerminal(terminal);
+		execute!(terminal.0.backend_mut(), EnterAlternateScreen)?;
+		Ok(terminal)
+	}
+}
+
+impl Drop for DashboardTerminal {
+	fn drop(&mut self) {
+		if let Err(error) = restore(&mut self.0) {
+			error!("Cannot restore the terminal: {error:#}");
+		}
+	}
+}
+
+/// Returns true if quit key is pressed
+fn quit_pressed() -> Result<bool> {
+	while event::poll(Duration::ZERO)? {
+		if let TermEvent::Key(key) = event::read()? {
+			let ctrl_c =
+				key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+			if key.kind == KeyEventKind::Press && (key.code == KeyCode::Char('q') || ctrl_c) {
+				return Ok(true);
+			}
+		}
+	}
+	Ok(false)
+}
+
+async fn show(
+	terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+	db: impl Database,
+	state: Arc<Mutex<State>>,
+	p2p_client: p2p::Client,
+	logs: LogBuffer,
+	withheld_fraction: f64,
+) -> Result<()> {
+	let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+	let mut input = tokio::time::interval(INPUT_POLL_INTERVAL);
+	let mut current = Snapshot::default();
+	loop {
+		tokio::select! {
+			_ = refresh.tick() => {
+				current = snapshot(&db, &state, &p2p_client, withheld_fraction).await?;
+				terminal.draw(|frame| draw(frame, &current, &logs.lines()))?;
+			},
+			_ = input.tick() => {
+				if quit_pressed()? {
+					return Ok(());
+				}
+			},
+		}
+	}
+}
+
+/// Shows the dashboard until the quit key is pressed, and then triggers the shutdown
+pub async fn run(
+	db: impl Database,
+	state: Arc<Mutex<State>>,
+	p2p_client: p2p::Client,
+	logs: LogBuffer,
+	withheld_fraction: f64,
+	shutdown: Controller<String>,
+) {
+	let result = async {
+		let mut terminal = DashboardTerminal::enter()?;
+		show(
+			&mut terminal.0,
+			db,
+			state,
+			p2p_client,
+			logs,
+			withheld_fraction,
+		)
+		.await
+	};
+	let reason = match result.await {
+		Ok(()) => "Dashboard closed".to_string(),
+		Err(error) => {
+			error!("Dashboard failed: {error:#}");
+			format!("Dashboard failed: {error:#}")
+		},
+	};
+	let _ = shutdown.trigger_shutdown(reason);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{recent_blocks, LogBuffer, LOG_LINES};
+	use crate::{
+		data::{mem_db::MemoryDB, CellSource, Database, Key, SampledCell},
+		utils::DEFAULT_WITHHELD_FRACTION,
+	};
+	use std::io::Write;
+
+	#[test]
+	fn recent_blocks_fetch_sources() {
+		let db = MemoryDB::default();
+		let cell = |source| SampledCell {
+			row: 0,
+			col: 0,
+			source,
+		};
+		db.put(Key::VerifiedCellCount(5), 8u32).unwrap();
+		let cells = vec![
+			cell(CellSource::DHT),
+			cell(CellSource::DHT),
+			cell(CellSource::RPC),
+		];
+		db.put(Key::SampledCells(5), cells).unwrap();
+
+		let blocks = recent_blocks(&db, 5, DEFAULT_WITHHELD_FRACTION).unwrap();
+		assert_eq!(blocks.len(), 5);
+		assert_eq!(blocks[0].block_number, 5);
+		assert!(blocks[0].confidence.is_some());
+		assert_eq!((blocks[0].dht_cells, blocks[0].rpc_cells), (2, 1));
+		assert_eq!(blocks[1].confidence, None);
+	}
+
+	#[test]
+	fn log_buffer_keeps_tail() {
+		let mut logs = LogBuffer::default();
+		for line in 0..LOG_LINES + 10 {
+			writeln!(logs, "line {line}").unwrap();
+		}
+		let lines = logs.lines();
+		assert_eq!(lines.len(), LOG_LINES);
+		assert_eq!(lines[0], "line 10");
+	}
+}
@@ -1,17 +1,23 @@
 //! Shared light client structs and enums.
-use crate::network::p2p::{MemoryStoreConfig, ProvidersConfig, RocksDBStoreConfig};
-use crate::network::rpc::{Event, Node as RpcNode};
-use crate::utils::{extract_app_lookup, extract_kate};
-use avail_core::DataLookup;
+use crate::confidence::Confidence;
+use crate::data::encryption::EncryptionSecret;
+use crate::features::{Feature, Features};
+use crate::network::p2p::{MemoryStoreConfig, ProvidersConfig, Reachability, RocksDBStoreConfig};
+use crate::network::proxy::Proxies;
+use crate::network::rpc::{backoff::RateLimitBackoff, Event, Node as RpcNode};
+use crate::proof_bundle::DisputeConfig;
+use crate::utils::{extract_app_lookup, extract_kate, DEFAULT_WITHHELD_FRACTION};
+use avail_core::{compact::CompactDataLookup, data_lookup::compact::DataLookupItem, AppId};
 use avail_subxt::{primitives::Header as DaHeader, utils::H256};
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use codec::{Decode, Encode};
 use color_eyre::{
 	eyre::{eyre, WrapErr},
 	Report, Result,
 };
 use kate_recovery::{
-	commitments,
+	com::app_specific_rows,
+	commitments, config,
 	matrix::{Dimensions, Partition},
 };
 use libp2p::kad::Mode as KadMode;
@@ -20,6 +26,7 @@ use semver::Version;
 use serde::{de::Error, Deserialize, Serialize};
 use sp_core::crypto::Ss58Codec;
 use sp_core::{blake2_256, bytes, ed25519};
+use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
 use std::fs;
 use std::num::{NonZeroU8, NonZeroUsize};
@@ -31,17 +38,18 @@ use subxt_signer::sr25519::Keypair;
 use subxt_signer::{SecretString, SecretUri};
 use tokio::sync::broadcast;
 use tokio_retry::strategy::{jitter, ExponentialBackoff, FibonacciBackoff};
-use tracing::warn;
+use tracing::{info, warn};
 
-const CELL_SIZE: usize = 32;
-const PROOF_SIZE: usize = 48;
-pub const CELL_WITH_PROOF_SIZE: usize = CELL_SIZE + PROOF_SIZE;
+/// Size of the cell scalar followed by its proof, as supported by `kate_recovery`
+pub const CELL_WITH_PROOF_SIZE: usize = config::CHUNK_SIZE + config::COMMITMENT_SIZE;
 
 const MINIMUM_SUPPORTED_VERSION: &str = "1.9.2";
 pub const DEV_FLAG_GENHASH: &str = "DEV";
 pub const IDENTITY_PROTOCOL: &str = "/avail_kad/id/1.0.0";
 pub const IDENTITY_AGENT_BASE: &str = "avail-light-client";
 pub const IDENTITY_AGENT_CLIENT_TYPE: &str = "rust-client";
+/// Client type advertised by fat clients, so other clients can keep them in the routing table
+pub const IDENTITY_AGENT_FAT_CLIENT_TYPE: &str = "rust-fat-client";
 
 #[derive(Parser)]
 #[command(version)]
@@ -49,9 +57,12 @@ pub struct CliOpts {
 	/// Path to the yaml configuration file
 	#[arg(short, long, value_name = "FILE")]
 	pub config: Option<String>,
-	/// Path to the toml identity file
+	/// Path to the toml identity file, relative path is resolved against the data directory
 	#[arg(short, long, value_name = "FILE", default_value = "identity.toml")]
 	pub identity: String,
+	/// Data directory under which on-disk artifacts are stored
+	#[arg(long, value_name = "DIR")]
+	pub data_dir: Option<String>,
 	/// AppID for application client
 	#[arg(long, value_name = "app-id")]
 	pub app_id: Option<u32>,
@@ -61,6 +72,9 @@ pub struct CliOpts {
 	/// Run a clean light client, deleting existing avail_path folder
 	#[arg(long)]
 	pub clean: bool,
+	/// Remove stored chain data if it doesn't belong to the chain of the connected node, instead of refusing to start
+	#[arg(long)]
+	pub wipe: bool,
 	/// Enable finality sync
 	#[arg(short, long, value_name = "finality_sync_enable")]
 	pub finality_sync_enable: bool,
@@ -92,6 +106,59 @@ pub struct CliOpts {
 	/// fraction and number of the block matrix part to fetch (e.g. 2/20 means second 1/20 part of a matrix) (default: None)
 	#[arg(long, value_parser = block_matrix_partition_format::parse)]
 	pub block_matrix_partition: Option<Partition>,
+	/// Run light client in the background, detached from the terminal (Unix only)
+	#[arg(long)]
+	pub daemon: bool,
+	/// Run light client under the Windows service control manager (Windows only)
+	#[arg(long)]
+	pub service: bool,
+	/// Replace data directory lock file left by a process which is no longer running,
+	/// on file systems without advisory locks
+	#[arg(long)]
+	pub force_unlock: bool,
+	/// Path to the pid file, removed on shutdown
+	#[arg(long, value_name = "FILE")]
+	pub pid_file: Option<String>,
+	/// Path to the log file, logs are written to standard output if not set
+	#[arg(long, value_name = "FILE")]
+	pub log_file: Option<String>,
+	/// Console output format, with `json` every message is written as a JSON line
+	#[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+	pub output: OutputFormat,
+	/// Show terminal dashboard instead of the log output (requires `tui` feature)
+	#[arg(long)]
+	pub tui: bool,
+	#[command(subcommand)]
+	pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+pub enum Command {
+	/// Check configuration, disk access, full node, DHT bootstrap and proof verification, and print a pass/fail report
+	SelfTest {
+		/// Block used to check proof verification, latest finalized block with data is used if not set
+		#[arg(long)]
+		block: Option<u32>,
+	},
+	/// Verify exported proof bundle (header, cells with proofs and commitments) without network access, and print a verdict
+	VerifyBundle {
+		/// Path to the proof bundle JSON file
+		#[arg(value_name = "FILE")]
+		file: String,
+	},
+	/// Run offline maintenance of the database, while the light client is stopped
+	Db {
+		#[command(subcommand)]
+		command: DbCommand,
+	},
+}
+
+#[derive(Subcommand, Clone, Debug, PartialEq)]
+pub enum DbCommand {
+	/// Remove orphaned entries and compact the database, reporting reclaimed space
+	Compact,
+	/// Repair database files, check integrity of the entries and remove corrupted ones, then compact the database
+	Repair,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -106,6 +173,100 @@ pub struct RuntimeVersion {
 	transaction_version: u32,
 }
 
+/// Application data lookup of the block, with validated ranges of data cells for each app
+#[derive(Clone, Debug)]
+pub struct DataLookup {
+	/// App IDs with their ranges of data cells, ordered by range start
+	ranges: Vec<(u32, Range<u32>)>,
+	/// Lookup used by data reconstruction and verification functions
+	lookup: avail_core::DataLookup,
+}
+
+impl DataLookup {
+	/// Creates data lookup from the compact `(app_id, start)` index and total size of data in cells.
+	/// Cells before the first index item implicitly belong to app 0.
+	/// Index items must have increasing starts within the size, and each app must appear only once.
+	pub fn new(size: u32, index: &[(u32, u32)]) -> Result<Self> {
+		let mut ranges = vec![];
+		let mut previous = (0, 0);
+		for &(app_id, start) in index.iter().chain(&[(u32::MAX, size)]) {
+			let (previous_app_id, previous_start) = previous;
+			if start > size {
+				return Err(eyre!(
+					"Range of app {app_id} starts at {start}, after size {size}"
+				));
+			}
+			if start < previous_start {
+				return Err(eyre!(
+					"Range of app {app_id} starting at {start} overlaps with range of app {previous_app_id}"
+				));
+			}
+			if start > previous_start || !ranges.is_empty() {
+				if ranges.iter().any(|(id, _)| *id == previous_app_id) {
+					return Err(eyre!("App {previous_app_id} has multiple ranges"));
+				}
+				if start == previous_start {
+					return Err(eyre!("Range of app {previous_app_id} is empty"));
+				}
+				ranges.push((previous_app_id, previous_start..start));
+			}
+			previous = (app_id, start);
+		}
+
+		let items = index
+			.iter()
+			.map(|&(app_id, start)| DataLookupItem::new(AppId(app_id), start))
+			.collect::<Vec<_>>();
+		let lookup = avail_core::DataLookup::try_from(CompactDataLookup::new(size, items))
+			.map_err(|error| eyre!("Invalid DataLookup: {error}"))?;
+
+		Ok(Self { ranges, lookup })
+	}
+
+	/// Creates data lookup from the `(app_id, length)` pairs, in order of their ranges
+	pub fn from_id_and_len(id_lens: &[(u32, u32)]) -> Result<Self> {
+		let mut start = 0;
+		let mut index = vec![];
+		for &(app_id, len) in id_lens {
+			index.push((app_id, start));
+			start += len;
+		}
+		Self::new(start, &index)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.ranges.is_empty()
+	}
+
+	/// Total number of data cells in the block
+	pub fn len(&self) -> u32 {
+		self.ranges.last().map(|(_, range)| range.end).unwrap_or(0)
+	}
+
+	/// IDs of the apps which have data in the block
+	pub fn apps_in_block(&self) -> Vec<u32> {
+		self.ranges.iter().map(|(app_id, _)| *app_id).collect()
+	}
+
+	/// Range of data cells of the app, if app has data in the block
+	pub fn range_of(&self, app_id: u32) -> Option<Range<u32>> {
+		self.ranges
+			.iter()
+			.find(|(id, _)| *id == app_id)
+			.map(|(_, range)| range.clone())
+	}
+
+	/// Rows of the extended matrix containing app data
+	pub fn rows_for_app(&self, dimensions: Dimensions, app_id: u32) -> Vec<u32> {
+		app_specific_rows(&self.lookup, dimensions, AppId(app_id))
+	}
+
+	/// Data lookup used by data reconstruction and verification functions
+	pub fn inner(&self) -> &avail_core::DataLookup {
+		&self.lookup
+	}
+}
+
 #[derive(Clone, Debug)]
 pub struct Extension {
 	pub dimensions: Dimensions,
@@ -119,7 +280,16 @@ pub struct BlockVerified {
 	pub header_hash: H256,
 	pub block_num: u32,
 	pub extension: Option<Extension>,
-	pub confidence: Option<f64>,
+	pub confidence: Option<Confidence>,
+}
+
+/// Emitted when block confidence doesn't reach the app threshold within the configured timeout
+#[derive(Clone, Debug)]
+pub struct ConfidenceTimeout {
+	pub block_num: u32,
+	pub app_id: u32,
+	pub confidence: Option<Confidence>,
+	pub threshold: Confidence,
 }
 
 pub struct ClientChannels {
@@ -127,9 +297,9 @@ pub struct ClientChannels {
 	pub rpc_event_receiver: broadcast::Receiver<Event>,
 }
 
-impl TryFrom<(DaHeader, Option<f64>)> for BlockVerified {
+impl TryFrom<(DaHeader, Option<Confidence>)> for BlockVerified {
 	type Error = Report;
-	fn try_from((header, confidence): (DaHeader, Option<f64>)) -> Result<Self, Self::Error> {
+	fn try_from((header, confidence): (DaHeader, Option<Confidence>)) -> Result<Self, Self::Error> {
 		let hash: H256 = Encode::using_encoded(&header, blake2_256).into();
 		let mut block = BlockVerified {
 			header_hash: hash,
@@ -394,6 +564,24 @@ pub struct RuntimeConfig {
 	pub http_server_host: String,
 	/// Light client HTTP server port (default: 7007).
 	pub http_server_port: u16,
+	/// Additional addresses of the HTTP server, as `host:port`, serving the same API (default: empty).
+	pub http_server_addresses: Vec<String>,
+	/// Share of the HTTP requests logged with method, path, status, latency and API key ID, from 0 to 1.
+	/// Set to 0 to disable access logs (default: 0).
+	pub http_access_log_sample_rate: f64,
+	/// Paths of the HTTP requests which are not logged in access logs (default: ["/health"]).
+	pub http_access_log_excluded_paths: Vec<String>,
+	/// Duration in milliseconds after which HTTP requests are logged as slow, with their path and query parameters (default: None).
+	pub http_slow_request_threshold: Option<u64>,
+	/// Time in seconds for which confidence and application data reads are cached for the API, 0 disables the cache (default: 2).
+	pub api_cache_ttl: u64,
+	/// Maximum number of cached entries per cached value type (default: 1024).
+	pub api_cache_capacity: usize,
+	/// Maximum number of blocks in a single bulk verification request (default: 10000).
+	pub bulk_verification_max_blocks: usize,
+	/// API keys of the consumers of the verified blocks, for which cursors are persisted.
+	/// Requests with other API keys are rejected (default: empty).
+	pub consumer_api_keys: Vec<String>,
 	/// Secret key for libp2p keypair. Can be either set to `seed` or to `key`.
 	/// If set to seed, keypair will be generated from that seed.
 	/// If set to key, a valid ed25519 private key must be provided, else the client will fail
@@ -419,19 +607,113 @@ pub struct RuntimeConfig {
 	pub operation_mode: KademliaMode,
 	/// Vector of Relay nodes, which are used for hole punching
 	pub relays: Vec<MultiaddrConfig>,
+	/// Vector of pinned peers, which are always kept connected and never removed from the routing table (default: empty).
+	pub pinned_peers: Vec<MultiaddrConfig>,
+	/// Maximum number of the peers to which the node was successfully connected, persisted with their addresses
+	/// and dialed on startup for faster bootstrap. Set to 0 to disable (default: 100).
+	pub peer_store_size: usize,
+	/// Path to the pre-shared key file of the private network, only nodes with the same key can connect (default: None).
+	pub pnet_key_file: Option<String>,
 	/// WebSocket endpoint of full node for subscribing to latest header, etc (default: [ws://127.0.0.1:9944]).
 	pub full_node_ws: Vec<String>,
+	/// Source of finalized headers: `subscription`, `polling` or `subscription-with-polling-fallback` (default: subscription).
+	pub header_mode: crate::network::rpc::HeaderMode,
+	/// HTTP endpoints of full nodes used for header polling, derived from `full_node_ws` if empty (default: empty).
+	pub full_node_http: Vec<String>,
+	/// Interval in seconds in which finalized headers are polled (default: 5).
+	pub header_polling_interval: u64,
+	/// Combined sources of finalized headers: `subscription`, `polling` and `gossipsub`.
+	/// If empty, source is selected by `header_mode` (default: empty).
+	pub header_sources: Vec<crate::network::header_source::HeaderSourceKind>,
+	/// Peer IDs of the trusted publishers of the header gossip, required by the `gossipsub` header source.
+	/// If `gossipsub` is not configured, gossiped headers are received only while all full nodes are unreachable (default: empty).
+	pub header_gossip_publishers: Vec<String>,
+	/// Publish received finalized headers over gossipsub, not supported with the `gossipsub` header source (default: false).
+	/// Deprecated, use the `gossip-headers` feature instead.
+	pub header_gossip_publish: bool,
+	/// Cross-check headers of the combined header sources by hash, alerting if sources diverge on the same block.
+	/// Requires at least two `header_sources` (default: false).
+	pub header_cross_check: bool,
+	/// Maximum size in bytes of the WebSocket and HTTP JSON-RPC responses received from full nodes (default: 16777216).
+	pub rpc_max_response_size: usize,
+	/// Timeout in seconds of the HTTP requests (header polling, probes, telemetry and webhooks), including reading of the response.
+	/// Requests with unsuccessful response status fail as well (default: 30).
+	pub rpc_http_timeout: u64,
+	/// Proxies used for WebSocket and HTTP JSON-RPC connections to full nodes, as `[host, proxy_url]` pairs,
+	/// where host `*` matches any host and proxy URL is `socks5://` or `http://` (default: empty).
+	pub full_node_proxies: Vec<(String, String)>,
+	/// Checks on startup that connected node exposes RPC methods required by the light client (default: true).
+	pub node_capability_check: bool,
+	/// Paranoid mode, in which proofs are requested from two different full nodes,
+	/// using only the cells on which both nodes agree. Requires at least two `full_node_ws` (default: false).
+	/// Deprecated, use the `paranoid` feature instead.
+	pub paranoid: bool,
+	/// Enabled experimental features: `row-records`, `gossip-headers`, `paranoid`, `bulk-verification` and `signed-reports` (default: empty).
+	/// Default features (`row-records`) are enabled regardless.
+	pub features: Vec<Feature>,
+	/// Default features which are disabled, to opt out of `row-records` (default: empty).
+	pub disabled_features: Vec<Feature>,
+	/// Interval in seconds in which the full node connection is probed with a lightweight RPC call,
+	/// reconnecting if the probe fails. Set to 0 to disable (default: 30).
+	pub rpc_ping_interval: u64,
+	/// Expected block time of the network in seconds (default: 20).
+	pub expected_block_time: u64,
+	/// Reconnects to the full node if no finalized header is received within `expected_block_time`
+	/// multiplied by this factor. Set to 0 to disable (default: 3).
+	pub stale_header_multiplier: u32,
+	/// Reports missed blocks if no finalized header is received within the estimated block time
+	/// multiplied by this factor. Set to 0 to disable (default: 5).
+	pub missed_block_multiplier: u32,
+	/// URL to which missed block events are posted as JSON (default: None).
+	pub missed_block_webhook: Option<String>,
 	/// Genesis hash of the network to be connected to. Set to a string beginning with "DEV" to connect to any network.
 	pub genesis_hash: String,
 	/// If set, application client is started with given app_id (default: None).
 	pub app_id: Option<u32>,
+	/// Restricts random sampling to the rows of the configured `app_id`, reducing bandwidth,
+	/// with confidence guaranteeing availability of the app data only (default: false).
+	pub app_only_sampling: bool,
+	/// When blocks are backlogged, verifies blocks containing data of the configured `app_id`
+	/// before the other blocks. Blocks waiting longer than a minute are not overtaken anymore (default: false).
+	pub prioritize_app_blocks: bool,
+	/// Name of the built-in decoder (`hex` or `utf8`) of the configured `app_id` data payloads,
+	/// served on the app data endpoint when decoding is requested (default: None).
+	pub app_data_decoder: Option<String>,
+	/// Per app confidence thresholds, as `[app_id, threshold]` pairs, which verified block has to reach
+	/// before the app data is processed. Global `confidence` is used for apps not listed (default: []).
+	pub app_confidence_thresholds: Vec<(u32, Confidence)>,
+	/// Time in seconds to wait for block confidence to reach the app threshold,
+	/// before the block is skipped and confidence timeout event is emitted (default: 10).
+	pub app_confidence_timeout: u64,
+	/// Per app storage quotas in bytes, as `[app_id, bytes]` pairs. Data of the oldest blocks
+	/// is pruned when stored app data exceeds the quota (default: []).
+	pub app_storage_quotas: Vec<(u32, u64)>,
 	/// Confidence threshold, used to calculate how many cells need to be sampled to achieve desired confidence (default: 92.0).
-	pub confidence: f64,
-	/// File system path where RocksDB used by light client, stores its data.
+	pub confidence: Confidence,
+	/// Assumed fraction of the block cells withheld by an adversary, used to calculate confidence
+	/// and the number of cells to sample. Data is unrecoverable if more than half of the extended
+	/// matrix cells is withheld (default: 0.5).
+	pub withheld_fraction: f64,
+	/// Minimum number of cells sampled per block, even if fewer cells are required for the confidence.
+	/// Sampled cells are limited by the size of the extended matrix (default: 1).
+	pub min_cell_count: u32,
+	/// Samples one cell from each band of the extended matrix rows, with columns spread across the column bands,
+	/// instead of uniform sampling. Improves detection of withholding of the adjacent rows (default: false).
+	pub stratified_sampling: bool,
+	/// Data directory under which on-disk artifacts (database, identity file and exported data) with relative paths are stored.
+	/// If not set, platform specific data directory is used, or current directory if it contains the existing light client state (default: None).
+	pub data_dir: Option<String>,
+	/// File system path where RocksDB used by light client, stores its data. Relative path is resolved against the data directory.
 	pub avail_path: String,
+	/// Enables encryption of the database and identity file at rest. Encryption key is read from `AVAIL_LIGHT_STORAGE_KEY`
+	/// environment variable (hex encoded 32 bytes), or derived from the `AVAIL_LIGHT_STORAGE_PASSPHRASE` (default: false).
+	pub storage_encryption: bool,
 	/// Log level, default is `INFO`. See `<https://docs.rs/log/0.4.14/log/enum.LevelFilter.html>` for possible log level values. (default: `INFO`).
 	pub log_level: String,
 	pub origin: Origin,
+	/// Short operator label (e.g. fleet and node name), included in the identify agent version
+	/// and in the telemetry attributes. Up to 32 ASCII letters, digits, `.`, `_` and `-` (default: None).
+	pub operator_label: Option<String>,
 	/// If set to true, logs are displayed in JSON format, which is used for structured logging. Otherwise, plain text format is used (default: false).
 	pub log_format_json: bool,
 	/// OpenTelemetry Collector endpoint (default: `http://otelcollector.avail.tools:4317`)
@@ -439,23 +721,50 @@ pub struct RuntimeConfig {
 	pub ot_export_period: u64,
 	pub ot_export_timeout: u64,
 	pub ot_flush_block_interval: u32,
+	/// Endpoint to which anonymous telemetry reports are posted as JSON. Reports contain version, latest block,
+	/// sync status and confidence and DHT hit rate summary. Reporting is disabled if not set (default: None).
+	pub telemetry_report_endpoint: Option<String>,
+	/// Interval in seconds between the telemetry reports (default: 3600).
+	pub telemetry_report_interval: u64,
 	/// Disables fetching of cells from RPC, set to true if client expects cells to be available in DHT (default: false).
 	pub disable_rpc: bool,
+	/// Percentage of the sampled cells found in the DHT, below which extra cells are sampled from the DHT before falling back to RPC,
+	/// so confidence reflects the availability in the DHT. Extra sampling is disabled if not set (default: None).
+	pub dht_extra_sampling_threshold: Option<f64>,
+	/// Maximum number of extra cells sampled from the DHT per block (default: 20).
+	pub dht_extra_sampling_max_cells: u32,
 	/// Maximum number of parallel tasks spawned for GET and PUT operations on DHT (default: 20).
 	pub dht_parallelization_limit: usize,
 	/// Number of parallel queries for cell fetching via RPC from node (default: 8).
 	pub query_proof_rpc_parallel_tasks: usize,
 	/// Number of seconds to postpone block processing after block finalized message arrives (default: 0).
 	pub block_processing_delay: Option<u32>,
+	/// Maximum number of blocks sampled concurrently by the light client, while blocks are stored in order.
+	/// Fetching of the next block overlaps with verification of the previous one (default: 2).
+	pub block_pipeline_depth: usize,
+	/// Blocks without application data are not sampled, if their commitments match the commitments of the already sampled
+	/// empty block with the same dimensions. Such blocks are stored in the `empty` state, without confidence (default: false).
+	pub skip_empty_blocks: bool,
 	/// Fraction and number of the block matrix part to fetch (e.g. 2/20 means second 1/20 part of a matrix) (default: None)
 	#[serde(with = "block_matrix_partition_format")]
 	pub block_matrix_partition: Option<Partition>,
 	/// Starting block of the syncing process. Omitting it will disable syncing. (default: None).
 	pub sync_start_block: Option<u32>,
+	/// Maximum number of blocks finalized while the light client was stopped, which are backfilled on startup
+	/// by the sync client, if `sync_start_block` is not set. If more blocks were missed, only the latest ones are backfilled (default: 0).
+	pub max_startup_backfill: u32,
+	/// Maximum random delay in seconds before the historical sync is started,
+	/// so clients restarted at the same time don't sync at once (default: 0).
+	pub sync_start_jitter: u64,
 	/// Enable or disable synchronizing finality. If disabled, finality is assumed to be verified until the starting block at the point the LC is started and is only checked for new blocks. (default: true)
 	pub sync_finality_enable: bool,
+	/// Maximum random delay in seconds before connecting to the full node on startup,
+	/// so clients restarted at the same time (e.g. on fleet upgrade) don't connect at once (default: 0).
+	pub startup_jitter: u64,
 	/// Maximum number of cells per request for proof queries (default: 30).
 	pub max_cells_per_rpc: Option<usize>,
+	/// Fat client generates cell proofs locally from the rows fetched from the full node, instead of requesting cell proofs. Rows which do not match the header commitments are rejected (default: false).
+	pub fat_client_local_proofs: bool,
 	/// Threshold for the number of cells fetched via DHT for the app client (default: 5000)
 	pub threshold: usize,
 	/// Kademlia configuration - WARNING: Changing the default values might cause the peer to suffer poor performance!
@@ -466,6 +775,12 @@ pub struct RuntimeConfig {
 	/// value - not greater than 1hr.
 	/// Record TTL, publication and replication intervals are co-dependent, meaning that TTL >> publication_interval >> replication_interval.
 	pub kad_record_ttl: u64,
+	/// Tolerated wall clock skew in seconds, added to the expiration of persisted records.
+	/// Wall clock jumps greater than this value are handled as resume from suspend (default: 60).
+	pub kad_record_clock_skew: u64,
+	/// Number of blocks after which records published by this node are removed from the local record store,
+	/// regardless of their TTL. Disabled if not set (default: None).
+	pub published_records_window: Option<u32>,
 	/// Sets the (re-)publication interval of stored records in seconds. (default: 12h).
 	/// Default value is set for light clients. Fat client value needs to be inferred from the TTL value.
 	/// This interval should be significantly shorter than the record TTL, to ensure records do not expire prematurely.
@@ -510,9 +825,47 @@ pub struct RuntimeConfig {
 	///     retries: 6,
 	/// )
 	pub retry_config: RetryConfig,
+	/// Export of verified headers and application data to a local directory or S3-compatible storage, requires `app_id` to be set (default: None).
+	pub export: Option<crate::export::ExportConfig>,
+	/// Directory to which proof bundles of the disputed blocks are written, relative to the data directory.
+	/// Bundles are written when the full node serves cells with invalid proofs, or bad encoding is detected.
+	/// Proof bundles are not written if not set (default: None).
+	pub dispute_bundles_path: Option<String>,
+	/// Upload proof bundles of the disputed blocks to the `export` target as `disputes/{block_number}.json` (default: false).
+	pub dispute_bundles_upload: bool,
+	/// Event sinks (Kafka, NATS or webhook) to which verified block and app data events are published (default: empty).
+	pub event_sinks: Vec<crate::sink::EventSinkConfig>,
+	/// Maximum time in seconds to publish a single event to one of the event sinks (default: 10).
+	pub event_sink_timeout: u64,
+	/// Maximum time in seconds without a processed block or a new header, after which systemd watchdog is no longer pinged (default: 120).
+	pub systemd_liveness_timeout: u64,
+	/// Maximum number of restarts of a crashed subsystem (e.g. P2P event loop or HTTP server) within the restart window,
+	/// after which the light client exits (default: 5).
+	pub supervisor_max_restarts: u32,
+	/// Window in seconds in which subsystem restarts are counted (default: 300).
+	pub supervisor_restart_window: u64,
+	#[serde(flatten)]
+	pub dht_references: crate::network::p2p::record::ReferenceConfig,
+	#[serde(flatten)]
+	pub cell_size: crate::cell_size::CellSize,
+	#[serde(flatten)]
+	pub replication_probe: crate::replication_prober::ReplicationProbeConfig,
+	#[serde(flatten)]
+	pub retention_check: crate::retention::RetentionCheckConfig,
+	#[serde(flatten)]
+	pub header_mapping: crate::network::rpc::header_mapping::HeaderMappingConfig,
 	#[cfg(feature = "crawl")]
 	#[serde(flatten)]
 	pub crawl: crate::crawl_client::CrawlConfig,
+	#[cfg(feature = "grpc")]
+	#[serde(flatten)]
+	pub grpc: crate::api::grpc::GrpcConfig,
+	#[cfg(feature = "postgres")]
+	#[serde(flatten)]
+	pub postgres: crate::postgres::PostgresConfig,
+	#[cfg(feature = "chaos")]
+	#[serde(flatten)]
+	pub chaos: crate::network::chaos::ChaosConfig,
 }
 
 impl RuntimeConfig {
@@ -525,8 +878,26 @@ pub struct Delay(pub Option<Duration>);
 
 /// Light client configuration (see [RuntimeConfig] for details)
 pub struct LightClientConfig {
-	pub confidence: f64,
+	pub confidence: Confidence,
+	/// Assumed fraction of the block cells withheld by an adversary, used to calculate confidence
+	pub withheld_fraction: f64,
+	/// Minimum number of cells sampled per block
+	pub min_cell_count: u32,
+	/// Cells are sampled across row and column bands, instead of uniformly
+	pub stratified_sampling: bool,
 	pub block_processing_delay: Delay,
+	/// Maximum number of concurrently sampled blocks
+	pub pipeline_depth: usize,
+	/// App ID to which random sampling is restricted, if app only sampling is enabled
+	pub sampling_app_id: Option<u32>,
+	/// Skips sampling of the blocks without application data
+	pub skip_empty_blocks: bool,
+	/// App ID which blocks are verified first when blocks are backlogged, if prioritization is enabled
+	pub priority_app_id: Option<u32>,
+	/// Light client is the last step of block processing, if application client is not running
+	pub is_last_step: bool,
+	/// Export of the proof bundles of the blocks for which full node serves cells with invalid proofs
+	pub disputes: Option<DisputeConfig>,
 }
 
 impl Delay {
@@ -545,7 +916,16 @@ impl From<&RuntimeConfig> for LightClientConfig {
 
 		LightClientConfig {
 			confidence: val.confidence,
+			withheld_fraction: val.withheld_fraction,
+			min_cell_count: val.min_cell_count,
+			stratified_sampling: val.stratified_sampling,
 			block_processing_delay: Delay(block_processing_delay),
+			pipeline_depth: val.block_pipeline_depth.max(1),
+			sampling_app_id: val.app_id.filter(|_| val.app_only_sampling),
+			skip_empty_blocks: val.skip_empty_blocks,
+			priority_app_id: val.app_id.filter(|_| val.prioritize_app_blocks),
+			is_last_step: val.app_id.is_none(),
+			disputes: DisputeConfig::from_config(val),
 		}
 	}
 }
@@ -560,6 +940,8 @@ pub struct FatClientConfig {
 	pub block_processing_delay: Delay,
 	pub block_matrix_partition: Option<Partition>,
 	pub max_cells_per_rpc: usize,
+	/// Publish rows of the partition into the DHT as row records
+	pub row_records: bool,
 }
 
 impl From<&RuntimeConfig> for FatClientConfig {
@@ -577,6 +959,7 @@ impl From<&RuntimeConfig> for FatClientConfig {
 			block_processing_delay: Delay(block_processing_delay),
 			block_matrix_partition: val.block_matrix_partition,
 			max_cells_per_rpc: val.max_cells_per_rpc.unwrap_or(30),
+			row_records: Features::from(val).is_enabled(Feature::RowRecords),
 		}
 	}
 }
@@ -589,6 +972,10 @@ pub struct LibP2PConfig {
 	pub autonat: AutoNATConfig,
 	pub kademlia: KademliaConfig,
 	pub relays: Vec<(PeerId, Multiaddr)>,
+	pub bootstraps: Vec<(PeerId, Multiaddr)>,
+	pub pinned_peers: Vec<(PeerId, Multiaddr)>,
+	pub peer_store_size: usize,
+	pub pnet_key_file: Option<String>,
 	pub bootstrap_interval: Duration,
 	pub connection_idle_timeout: Duration,
 	pub max_negotiating_inbound_streams: usize,
@@ -643,6 +1030,7 @@ impl From<&LibP2PConfig> for RocksDBStoreConfig {
 				max_providers_per_key: usize::from(cfg.kademlia.record_replication_factor), // Needs to match the replication factor, per libp2p docs
 				max_provided_keys: cfg.kademlia.max_kad_provided_keys,
 			},
+			clock_skew_tolerance: cfg.kademlia.clock_skew_tolerance,
 		}
 	}
 }
@@ -656,6 +1044,10 @@ impl From<&RuntimeConfig> for LibP2PConfig {
 			autonat: val.into(),
 			kademlia: val.into(),
 			relays: val.relays.iter().map(Into::into).collect(),
+			bootstraps: val.bootstraps.iter().map(Into::into).collect(),
+			pinned_peers: val.pinned_peers.iter().map(Into::into).collect(),
+			peer_store_size: val.peer_store_size,
+			pnet_key_file: val.pnet_key_file.clone(),
 			bootstrap_interval: Duration::from_secs(val.bootstrap_period),
 			connection_idle_timeout: Duration::from_secs(val.connection_idle_timeout),
 			max_negotiating_inbound_streams: val.max_negotiating_inbound_streams,
@@ -672,6 +1064,7 @@ impl From<&RuntimeConfig> for LibP2PConfig {
 #[derive(Clone)]
 pub struct KademliaConfig {
 	pub kad_record_ttl: Duration,
+	pub clock_skew_tolerance: Duration,
 	pub record_replication_factor: NonZeroUsize,
 	pub record_replication_interval: Option<Duration>,
 	pub publication_interval: Option<Duration>,
@@ -689,6 +1082,7 @@ impl From<&RuntimeConfig> for KademliaConfig {
 	fn from(val: &RuntimeConfig) -> Self {
 		Self {
 			kad_record_ttl: Duration::from_secs(val.kad_record_ttl),
+			clock_skew_tolerance: Duration::from_secs(val.kad_record_clock_skew),
 			record_replication_factor: std::num::NonZeroUsize::new(val.replication_factor as usize)
 				.expect("Invalid replication factor"),
 			record_replication_interval: Some(Duration::from_secs(val.replication_interval.into())),
@@ -742,14 +1136,38 @@ pub struct AgentVersion {
 	// Kademlia client or server mode
 	pub kademlia_mode: String,
 	pub release_version: String,
+	/// Operator label, appended to the base version in parentheses,
+	/// so it is ignored by the peers which don't parse it
+	pub label: Option<String>,
+}
+
+/// Maximum length of the operator label
+const MAX_OPERATOR_LABEL_LENGTH: usize = 32;
+
+/// Checks that operator label is short, and contains only ASCII letters, digits, `.`, `_` and `-`
+pub fn validate_operator_label(label: &str) -> Result<()> {
+	let is_valid_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+	if label.is_empty()
+		|| label.len() > MAX_OPERATOR_LABEL_LENGTH
+		|| !label.chars().all(is_valid_char)
+	{
+		return Err(eyre!(
+			"Invalid operator label {label:?}, expected up to {MAX_OPERATOR_LABEL_LENGTH} ASCII letters, digits, '.', '_' or '-'"
+		));
+	}
+	Ok(())
 }
 
 impl fmt::Display for AgentVersion {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.base_version)?;
+		if let Some(label) = &self.label {
+			write!(f, " ({label})")?;
+		}
 		write!(
 			f,
-			"{}/{}/{}/{}",
-			self.base_version, self.release_version, self.client_type, self.kademlia_mode
+			"/{}/{}/{}",
+			self.release_version, self.client_type, self.kademlia_mode
 		)
 	}
 }
@@ -763,11 +1181,20 @@ impl FromStr for AgentVersion {
 			return Err("Failed to parse agent version".to_owned());
 		}
 
+		let (base_version, label) = match parts[0]
+			.strip_suffix(')')
+			.and_then(|base| base.split_once(" ("))
+		{
+			Some((base_version, label)) => (base_version, Some(label.to_string())),
+			None => (parts[0], None),
+		};
+
 		Ok(AgentVersion {
-			base_version: parts[0].to_string(),
+			base_version: base_version.to_string(),
 			release_version: parts[1].to_string(),
 			client_type: parts[2].to_string(),
 			kademlia_mode: parts[3].to_string(),
+			label,
 		})
 	}
 }
@@ -777,18 +1204,22 @@ impl From<&RuntimeConfig> for IdentifyConfig {
 		let mut genhash_short = val.genesis_hash.trim_start_matches("0x").to_string();
 		genhash_short.truncate(6);
 
-		let kademlia_mode = if val.is_fat_client() {
+		let (kademlia_mode, client_type) = if val.is_fat_client() {
 			// Fat client is implicitly server mode
-			KademliaMode::Server.to_string()
+			(
+				KademliaMode::Server.to_string(),
+				IDENTITY_AGENT_FAT_CLIENT_TYPE,
+			)
 		} else {
-			val.operation_mode.to_string()
+			(val.operation_mode.to_string(), IDENTITY_AGENT_CLIENT_TYPE)
 		};
 
 		let agent_version = AgentVersion {
 			base_version: IDENTITY_AGENT_BASE.to_string(),
 			release_version: clap::crate_version!().to_string(),
-			client_type: IDENTITY_AGENT_CLIENT_TYPE.to_string(),
+			client_type: client_type.to_string(),
 			kademlia_mode,
+			label: val.operator_label.clone(),
 		};
 
 		Self {
@@ -817,19 +1248,27 @@ impl AgentVersion {
 /// Sync client configuration (see [RuntimeConfig] for details)
 #[derive(Clone)]
 pub struct SyncClientConfig {
-	pub confidence: f64,
+	pub confidence: Confidence,
+	pub withheld_fraction: f64,
+	pub min_cell_count: u32,
+	pub stratified_sampling: bool,
 	pub disable_rpc: bool,
 	pub dht_parallelization_limit: usize,
 	pub is_last_step: bool,
+	pub start_jitter: u64,
 }
 
 impl From<&RuntimeConfig> for SyncClientConfig {
 	fn from(val: &RuntimeConfig) -> Self {
 		SyncClientConfig {
 			confidence: val.confidence,
+			withheld_fraction: val.withheld_fraction,
+			min_cell_count: val.min_cell_count,
+			stratified_sampling: val.stratified_sampling,
 			disable_rpc: val.disable_rpc,
 			dht_parallelization_limit: val.dht_parallelization_limit,
 			is_last_step: val.app_id.is_none(),
+			start_jitter: val.sync_start_jitter,
 		}
 	}
 }
@@ -839,6 +1278,38 @@ pub struct AppClientConfig {
 	pub dht_parallelization_limit: usize,
 	pub disable_rpc: bool,
 	pub threshold: usize,
+	/// Confidence threshold gating app data processing, not set in fat client mode where blocks are not sampled
+	pub confidence_threshold: Option<Confidence>,
+	pub confidence_timeout: Duration,
+	/// Assumed fraction of the block cells withheld by an adversary, used to calculate confidence
+	pub withheld_fraction: f64,
+	/// Backoff of the failed app data reconstruction retries
+	pub retry_config: RetryConfig,
+	/// Maximum number of stored bytes of the app data, oldest data above the quota is pruned
+	pub storage_quota: Option<u64>,
+	/// Export of the proof bundles of the blocks with bad encoding
+	pub disputes: Option<DisputeConfig>,
+	/// Publish app rows fetched from the full node into the DHT as row records
+	pub row_records: bool,
+}
+
+impl RuntimeConfig {
+	/// Confidence threshold of the given app, falling back to the global confidence
+	pub fn app_confidence_threshold(&self, app_id: u32) -> Confidence {
+		self.app_confidence_thresholds
+			.iter()
+			.find(|(id, _)| *id == app_id)
+			.map(|&(_, threshold)| threshold)
+			.unwrap_or(self.confidence)
+	}
+
+	/// Storage quota of the given app in bytes, if configured
+	pub fn app_storage_quota(&self, app_id: u32) -> Option<u64> {
+		self.app_storage_quotas
+			.iter()
+			.find(|(id, _)| *id == app_id)
+			.map(|&(_, quota)| quota)
+	}
 }
 
 impl From<&RuntimeConfig> for AppClientConfig {
@@ -847,6 +1318,106 @@ impl From<&RuntimeConfig> for AppClientConfig {
 			dht_parallelization_limit: val.dht_parallelization_limit,
 			disable_rpc: val.disable_rpc,
 			threshold: val.threshold,
+			confidence_threshold: match val.block_matrix_partition {
+				Some(_) => None,
+				None => Some(val.app_confidence_threshold(val.app_id.unwrap_or(0))),
+			},
+			confidence_timeout: Duration::from_secs(val.app_confidence_timeout),
+			withheld_fraction: val.withheld_fraction,
+			retry_config: val.retry_config.clone(),
+			storage_quota: val.app_storage_quota(val.app_id.unwrap_or(0)),
+			disputes: DisputeConfig::from_config(val),
+			row_records: Features::from(val).is_enabled(Feature::RowRecords),
+		}
+	}
+}
+
+/// Full node connection keep-alive configuration (see [RuntimeConfig] for details)
+#[derive(Clone, Debug, Default)]
+pub struct KeepAliveConfig {
+	pub ping_interval: Option<Duration>,
+	/// Maximum time without a finalized header before reconnecting
+	pub stale_timeout: Option<Duration>,
+}
+
+impl From<&RuntimeConfig> for KeepAliveConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		let stale_timeout = val.expected_block_time * val.stale_header_multiplier as u64;
+		KeepAliveConfig {
+			ping_interval: (val.rpc_ping_interval > 0)
+				.then(|| Duration::from_secs(val.rpc_ping_interval)),
+			stale_timeout: (stale_timeout > 0).then(|| Duration::from_secs(stale_timeout)),
+		}
+	}
+}
+
+/// Full node WebSocket connection configuration (see [RuntimeConfig] for details)
+#[derive(Clone, Debug)]
+pub struct ConnectionConfig {
+	/// Proxies of the full node hosts
+	pub proxies: Proxies,
+	/// Maximum size in bytes of the received JSON-RPC response
+	pub max_response_size: usize,
+}
+
+impl Default for ConnectionConfig {
+	fn default() -> Self {
+		ConnectionConfig {
+			proxies: Proxies::default(),
+			max_response_size: crate::network::rpc::DEFAULT_MAX_RESPONSE_SIZE,
+		}
+	}
+}
+
+impl TryFrom<&RuntimeConfig> for ConnectionConfig {
+	type Error = Report;
+
+	fn try_from(val: &RuntimeConfig) -> Result<Self> {
+		let proxies = Proxies::new(&val.full_node_proxies).wrap_err("Invalid full node proxy")?;
+		Ok(ConnectionConfig {
+			proxies,
+			max_response_size: val.rpc_max_response_size,
+		})
+	}
+}
+
+/// Missed block detection configuration (see [RuntimeConfig] for details)
+#[derive(Clone, Debug)]
+pub struct MissedBlockConfig {
+	/// Initial expected block time, used until enough headers are received
+	pub expected_block_time: Duration,
+	pub multiplier: u32,
+	/// HTTP endpoints of full nodes used to probe the chain head
+	pub probe_urls: Vec<String>,
+	pub webhook_url: Option<String>,
+}
+
+impl From<&RuntimeConfig> for MissedBlockConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		MissedBlockConfig {
+			expected_block_time: Duration::from_secs(val.expected_block_time),
+			multiplier: val.missed_block_multiplier,
+			probe_urls: match val.full_node_http.is_empty() {
+				true => crate::network::rpc::http_urls(&val.full_node_ws),
+				false => val.full_node_http.clone(),
+			},
+			webhook_url: val.missed_block_webhook.clone(),
+		}
+	}
+}
+
+/// Telemetry reporting configuration (see [RuntimeConfig] for details)
+#[derive(Clone, Debug)]
+pub struct TelemetryReportConfig {
+	pub endpoint: Option<String>,
+	pub interval: Duration,
+}
+
+impl From<&RuntimeConfig> for TelemetryReportConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		TelemetryReportConfig {
+			endpoint: val.telemetry_report_endpoint.clone(),
+			interval: Duration::from_secs(val.telemetry_report_interval.max(1)),
 		}
 	}
 }
@@ -868,11 +1439,29 @@ impl From<&RuntimeConfig> for OtelConfig {
 	}
 }
 
+impl From<&RuntimeConfig> for crate::supervisor::SupervisorConfig {
+	fn from(val: &RuntimeConfig) -> Self {
+		crate::supervisor::SupervisorConfig {
+			backoff: val.retry_config.clone(),
+			max_restarts: val.supervisor_max_restarts,
+			restart_window: Duration::from_secs(val.supervisor_restart_window),
+		}
+	}
+}
+
 impl Default for RuntimeConfig {
 	fn default() -> Self {
 		RuntimeConfig {
 			http_server_host: "127.0.0.1".to_owned(),
 			http_server_port: 7007,
+			http_server_addresses: vec![],
+			http_access_log_sample_rate: 0.0,
+			http_access_log_excluded_paths: vec!["/health".to_string()],
+			http_slow_request_threshold: None,
+			api_cache_ttl: 2,
+			api_cache_capacity: 1024,
+			bulk_verification_max_blocks: 10000,
+			consumer_api_keys: vec![],
 			port: 37000,
 			ws_transport_enable: false,
 			secret_key: None,
@@ -884,26 +1473,71 @@ impl Default for RuntimeConfig {
 			bootstraps: vec![],
 			bootstrap_period: 3600,
 			relays: Vec::new(),
+			pinned_peers: Vec::new(),
+			peer_store_size: 100,
+			pnet_key_file: None,
 			full_node_ws: vec!["ws://127.0.0.1:9944".to_owned()],
+			header_mode: Default::default(),
+			full_node_http: vec![],
+			header_polling_interval: 5,
+			header_sources: vec![],
+			header_gossip_publishers: vec![],
+			header_gossip_publish: false,
+			header_cross_check: false,
+			rpc_max_response_size: crate::network::rpc::DEFAULT_MAX_RESPONSE_SIZE,
+			rpc_http_timeout: crate::network::rpc::DEFAULT_TIMEOUT,
+			full_node_proxies: vec![],
+			node_capability_check: true,
+			paranoid: false,
+			features: vec![],
+			disabled_features: vec![],
+			rpc_ping_interval: 30,
+			expected_block_time: 20,
+			stale_header_multiplier: 3,
+			missed_block_multiplier: 5,
+			missed_block_webhook: None,
 			genesis_hash: "DEV".to_owned(),
 			app_id: None,
-			confidence: 99.9,
+			app_only_sampling: false,
+			prioritize_app_blocks: false,
+			app_data_decoder: None,
+			app_confidence_thresholds: vec![],
+			app_confidence_timeout: 10,
+			app_storage_quotas: vec![],
+			confidence: Confidence::new(99.9),
+			withheld_fraction: DEFAULT_WITHHELD_FRACTION,
+			min_cell_count: 1,
+			stratified_sampling: false,
+			data_dir: None,
 			avail_path: "avail_path".to_owned(),
+			storage_encryption: false,
 			log_level: "INFO".to_owned(),
 			log_format_json: false,
 			ot_collector_endpoint: "http://127.0.0.1:4317".to_string(),
 			ot_export_period: 300,
 			ot_export_timeout: 10,
 			ot_flush_block_interval: 15,
+			telemetry_report_endpoint: None,
+			telemetry_report_interval: 3600,
 			disable_rpc: false,
+			dht_extra_sampling_threshold: None,
+			dht_extra_sampling_max_cells: 20,
 			dht_parallelization_limit: 20,
 			query_proof_rpc_parallel_tasks: 8,
 			block_processing_delay: Some(20),
+			block_pipeline_depth: 2,
+			skip_empty_blocks: false,
 			block_matrix_partition: None,
 			sync_start_block: None,
+			max_startup_backfill: 0,
+			sync_start_jitter: 0,
 			sync_finality_enable: false,
+			startup_jitter: 0,
 			max_cells_per_rpc: Some(30),
+			fat_client_local_proofs: false,
 			kad_record_ttl: 24 * 60 * 60,
+			kad_record_clock_skew: 60,
+			published_records_window: None,
 			threshold: 5000,
 			replication_factor: 5,
 			publication_interval: 12 * 60 * 60,
@@ -921,9 +1555,29 @@ impl Default for RuntimeConfig {
 			max_kad_record_number: 2400000,
 			max_kad_record_size: 8192,
 			max_kad_provided_keys: 1024,
+			export: None,
+			dispute_bundles_path: None,
+			dispute_bundles_upload: false,
+			event_sinks: vec![],
+			event_sink_timeout: 10,
+			systemd_liveness_timeout: 120,
+			supervisor_max_restarts: 5,
+			supervisor_restart_window: 300,
+			dht_references: Default::default(),
+			cell_size: Default::default(),
+			replication_probe: Default::default(),
+			retention_check: Default::default(),
+			header_mapping: Default::default(),
 			#[cfg(feature = "crawl")]
 			crawl: crate::crawl_client::CrawlConfig::default(),
+			#[cfg(feature = "grpc")]
+			grpc: crate::api::grpc::GrpcConfig::default(),
+			#[cfg(feature = "postgres")]
+			postgres: crate::postgres::PostgresConfig::default(),
+			#[cfg(feature = "chaos")]
+			chaos: crate::network::chaos::ChaosConfig::default(),
 			origin: Origin::External,
+			operator_label: None,
 			operation_mode: KademliaMode::Client,
 			retry_config: RetryConfig::Fibonacci(FibonacciConfig {
 				base: 1,
@@ -942,7 +1596,7 @@ pub enum Network {
 }
 
 impl Network {
-	fn bootstrap_peer_id(&self) -> &str {
+	pub(crate) fn bootstrap_peer_id(&self) -> &str {
 		match self {
 			Network::Local => "12D3KooWStAKPADXqJ7cngPYXd2mSANpdgh1xQ34aouufHA2xShz",
 			Network::Hex => "12D3KooWBMwfo5qyoLQDRat86kFcGAiJ2yxKM63rXHMw2rDuNZMA",
@@ -950,7 +1604,7 @@ impl Network {
 		}
 	}
 
-	fn bootstrap_multiaddrr(&self) -> &str {
+	pub(crate) fn bootstrap_multiaddrr(&self) -> &str {
 		match self {
 			Network::Local => "/ip4/127.0.0.1/tcp/39000",
 			Network::Hex => "/dns/bootnode.1.lightclient.hex.avail.so/tcp/37000",
@@ -958,7 +1612,7 @@ impl Network {
 		}
 	}
 
-	fn full_node_ws(&self) -> &str {
+	pub(crate) fn full_node_ws(&self) -> &str {
 		match self {
 			Network::Local => "ws://127.0.0.1:9944",
 			Network::Hex => "wss://rpc-hex-devnet.avail.tools/ws",
@@ -966,7 +1620,7 @@ impl Network {
 		}
 	}
 
-	fn ot_collector_endpoint(&self) -> &str {
+	pub(crate) fn ot_collector_endpoint(&self) -> &str {
 		match self {
 			Network::Local => "http://127.0.0.1:4317",
 			Network::Hex => "http://otel.lightclient.hex.avail.so:4317",
@@ -974,7 +1628,7 @@ impl Network {
 		}
 	}
 
-	fn genesis_hash(&self) -> &str {
+	pub(crate) fn genesis_hash(&self) -> &str {
 		match self {
 			Network::Local => "DEV",
 			Network::Hex => "9d5ea6a5d7631e13028b684a1a0078e3970caa78bd677eaecaf2160304f174fb",
@@ -1018,6 +1672,12 @@ pub enum LogLevel {
 	Error,
 }
 
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+	Text,
+	Json,
+}
+
 impl Display for LogLevel {
 	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
 		f.write_str(match self {
@@ -1059,6 +1719,14 @@ impl RuntimeConfig {
 			self.genesis_hash = network.genesis_hash().to_string();
 		}
 
+		if let Some(data_dir) = &opts.data_dir {
+			self.data_dir = Some(data_dir.clone());
+		}
+
+		if opts.output == OutputFormat::Json {
+			self.log_format_json = true;
+		}
+
 		if let Some(loglvl) = &opts.verbosity {
 			self.log_level = loglvl.to_string();
 		}
@@ -1092,6 +1760,7 @@ impl RuntimeConfig {
 	}
 }
 
+#[derive(Clone)]
 pub struct IdentityConfig {
 	/// Avail account secret key. (secret is generated if it is not configured)
 	pub avail_key_pair: Keypair,
@@ -1102,12 +1771,20 @@ pub struct IdentityConfig {
 }
 
 impl IdentityConfig {
-	pub fn load_or_init(path: &str, password: Option<&str>) -> Result<Self> {
+	/// Loads identity from the file, or generates a new one.
+	/// If encryption secret is provided, secret URI is stored encrypted, and existing plaintext secret is encrypted in place.
+	pub fn load_or_init(
+		path: &str,
+		password: Option<&str>,
+		encryption: Option<&EncryptionSecret>,
+	) -> Result<Self> {
 		#[derive(Default, Serialize, Deserialize)]
 		struct Config {
 			pub avail_secret_uri: Option<String>,
 			// TODO: Deprecated since 1.9.0, remove it once it is safe
 			pub avail_secret_seed_phrase: Option<String>,
+			/// Hex encoded encrypted secret URI
+			pub avail_secret_uri_encrypted: Option<String>,
 		}
 
 		let mut config: Config = confy::load_path(path)?;
@@ -1116,17 +1793,48 @@ impl IdentityConfig {
 			warn!("Using deprecated configuration parameter `avail_secret_seed_phrase`, use `avail_secret_uri` instead.");
 		}
 
-		let avail_secret_uri = config.avail_secret_uri.as_ref();
-		let avail_secret_seed_phrase = config.avail_secret_seed_phrase.as_ref();
-		let mut suri = match avail_secret_uri.or(avail_secret_seed_phrase) {
+		let avail_secret_uri = match (&config.avail_secret_uri_encrypted, encryption) {
+			(Some(encrypted), Some(encryption)) => {
+				let encrypted = hex::decode(encrypted).wrap_err("Invalid encrypted secret URI")?;
+				let decrypted = encryption
+					.open(&encrypted)
+					.wrap_err("Cannot decrypt identity file")?;
+				Some(String::from_utf8(decrypted).wrap_err("Invalid decrypted secret URI")?)
+			},
+			(Some(_), None) => Err(eyre!(
+				"Identity file is encrypted, encryption key or passphrase must be provided"
+			))?,
+			(None, _) => config
+				.avail_secret_uri
+				.clone()
+				.or(config.avail_secret_seed_phrase.clone()),
+		};
+
+		let avail_secret_uri = match avail_secret_uri {
+			Some(suri) => suri,
 			None => {
-				let mnemonic = Mnemonic::generate_in(Language::English, 24)?;
-				config.avail_secret_uri = Some(mnemonic.to_string());
-				confy::store_path(path, &config)?;
-				SecretUri::from_str(&mnemonic.to_string())
+				let mnemonic = Mnemonic::generate_in(Language::English, 24)?.to_string();
+				// Generated secret is stored encrypted below, if encryption is enabled
+				if encryption.is_none() {
+					config.avail_secret_uri = Some(mnemonic.clone());
+					confy::store_path(path, &config)?;
+				}
+				mnemonic
 			},
-			Some(suri) => SecretUri::from_str(suri),
-		}?;
+		};
+
+		if let (Some(encryption), None) = (encryption, &config.avail_secret_uri_encrypted) {
+			let encrypted = encryption.seal(avail_secret_uri.as_bytes())?;
+			let config = Config {
+				avail_secret_uri: None,
+				avail_secret_seed_phrase: None,
+				avail_secret_uri_encrypted: Some(hex::encode(encrypted)),
+			};
+			confy::store_path(path, &config)?;
+			info!("Secret URI in the identity file is encrypted");
+		}
+
+		let mut suri = SecretUri::from_str(&avail_secret_uri)?;
 
 		if let Some(password) = password {
 			suri.password = Some(SecretString::from_str(password)?);
@@ -1167,6 +1875,8 @@ pub struct State {
 	pub synced: Option<bool>,
 	pub latest: u32,
 	pub header_verified: Option<BlockRange>,
+	/// Headers received from the trusted publishers over gossipsub, which finality is not verified
+	pub header_unverified: Option<BlockRange>,
 	pub confidence_achieved: Option<BlockRange>,
 	pub data_verified: Option<BlockRange>,
 	pub sync_latest: Option<u32>,
@@ -1175,10 +1885,29 @@ pub struct State {
 	pub sync_data_verified: Option<BlockRange>,
 	pub finality_synced: bool,
 	pub connected_node: RpcNode,
+	pub reachability: Reachability,
+	pub public_address: Option<String>,
+	/// Unix timestamp in seconds of the last pruning of the DHT records
+	pub last_pruned_at: Option<u64>,
+	/// Backoffs from the rate limiting full nodes, by host
+	pub rpc_backoffs: HashMap<String, RateLimitBackoff>,
+	/// All configured full nodes are unreachable, so cells are sampled from the DHT only
+	pub degraded: bool,
+	/// Latest block which finality is verified with the justification
+	pub finality_verified: Option<u32>,
+}
+
+impl State {
+	/// Backoff from the connected full node, if it was rate limiting
+	pub fn rpc_backoff(&self) -> Option<&RateLimitBackoff> {
+		self.rpc_backoffs.get(&self.connected_node.host)
+	}
 }
 
 pub trait OptionBlockRange {
 	fn set(&mut self, block_number: u32);
+	/// Sets the last block of the range, unless it is already past the block
+	fn advance(&mut self, block_number: u32);
 	fn first(&self) -> Option<u32>;
 	fn last(&self) -> Option<u32>;
 	fn contains(&self, block_number: u32) -> bool;
@@ -1192,6 +1921,12 @@ impl OptionBlockRange for Option<BlockRange> {
 		};
 	}
 
+	fn advance(&mut self, block_number: u32) {
+		if self.last().is_none_or(|last| last < block_number) {
+			self.set(block_number);
+		}
+	}
+
 	fn first(&self) -> Option<u32> {
 		self.as_ref().map(|range| range.first)
 	}
@@ -1263,3 +1998,128 @@ impl TimeToLive {
 		Instant::now().checked_add(self.0)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		validate_operator_label, AgentVersion, DataLookup, IdentityConfig, KeepAliveConfig,
+		RuntimeConfig,
+	};
+	use crate::{confidence::Confidence, data::encryption::EncryptionSecret};
+	use std::time::Duration;
+
+	#[test]
+	fn encrypted_identity() {
+		let path =
+			std::env::temp_dir().join(format!("avail_light_identity_{}.toml", std::process::id()));
+		let path = path.to_str().unwrap();
+		let plain = IdentityConfig::load_or_init(path, None, None).unwrap();
+
+		// Existing plaintext secret is encrypted in place
+		let secret = EncryptionSecret::Key([1u8; 32]);
+		let encrypted = IdentityConfig::load_or_init(path, None, Some(&secret)).unwrap();
+		assert_eq!(encrypted.avail_address, plain.avail_address);
+		let content = std::fs::read_to_string(path).unwrap();
+		assert!(content.contains("avail_secret_uri_encrypted"));
+		assert!(!content.contains("avail_secret_uri ="));
+
+		let reloaded = IdentityConfig::load_or_init(path, None, Some(&secret)).unwrap();
+		assert_eq!(reloaded.avail_address, plain.avail_address);
+		assert!(IdentityConfig::load_or_init(path, None, None).is_err());
+		std::fs::remove_file(path).unwrap();
+	}
+
+	#[test]
+	fn keep_alive_config() {
+		let keep_alive = KeepAliveConfig::from(&RuntimeConfig::default());
+		assert_eq!(keep_alive.ping_interval, Some(Duration::from_secs(30)));
+		assert_eq!(keep_alive.stale_timeout, Some(Duration::from_secs(60)));
+
+		let keep_alive = KeepAliveConfig::from(&RuntimeConfig {
+			rpc_ping_interval: 0,
+			stale_header_multiplier: 0,
+			..Default::default()
+		});
+		assert_eq!(keep_alive.ping_interval, None);
+		assert_eq!(keep_alive.stale_timeout, None);
+	}
+
+	#[test]
+	fn app_confidence_threshold() {
+		let cfg = RuntimeConfig {
+			app_confidence_thresholds: vec![(1, Confidence::new(95.0))],
+			..Default::default()
+		};
+		assert_eq!(cfg.app_confidence_threshold(1), Confidence::new(95.0));
+		assert_eq!(cfg.app_confidence_threshold(2), cfg.confidence);
+	}
+
+	#[test]
+	fn app_storage_quota() {
+		let cfg = RuntimeConfig {
+			app_storage_quotas: vec![(1, 1024)],
+			..Default::default()
+		};
+		assert_eq!(cfg.app_storage_quota(1), Some(1024));
+		assert_eq!(cfg.app_storage_quota(2), None);
+	}
+
+	#[test]
+	fn data_lookup() {
+		let lookup = DataLookup::new(10, &[(1, 2), (3, 5)]).unwrap();
+		assert_eq!(lookup.apps_in_block(), vec![0, 1, 3]);
+		assert_eq!(lookup.range_of(0), Some(0..2));
+		assert_eq!(lookup.range_of(1), Some(2..5));
+		assert_eq!(lookup.range_of(3), Some(5..10));
+		assert_eq!(lookup.range_of(2), None);
+		assert_eq!(lookup.len(), 10);
+
+		let lookup = DataLookup::from_id_and_len(&[(0, 1), (1, 69)]).unwrap();
+		assert_eq!(lookup.apps_in_block(), vec![0, 1]);
+		assert_eq!(lookup.range_of(1), Some(1..70));
+
+		let lookup = DataLookup::new(4, &[]).unwrap();
+		assert_eq!(lookup.apps_in_block(), vec![0]);
+		assert!(DataLookup::new(0, &[]).unwrap().is_empty());
+		assert_eq!(DataLookup::new(0, &[]).unwrap().len(), 0);
+	}
+
+	#[test]
+	fn invalid_data_lookup() {
+		// Overlapping ranges
+		assert!(DataLookup::new(10, &[(1, 5), (2, 3)]).is_err());
+		// Range starting after size
+		assert!(DataLookup::new(10, &[(1, 11)]).is_err());
+		// Empty range
+		assert!(DataLookup::new(10, &[(1, 2), (2, 2)]).is_err());
+		// Duplicated app
+		assert!(DataLookup::new(10, &[(1, 2), (2, 4), (1, 6)]).is_err());
+		assert!(DataLookup::new(10, &[(0, 2)]).is_err());
+	}
+
+	#[test]
+	fn agent_version_operator_label() {
+		let cfg = RuntimeConfig {
+			operator_label: Some("acme-fleet-03".to_string()),
+			..Default::default()
+		};
+		let agent_version = super::IdentifyConfig::from(&cfg).agent_version;
+		let agent = agent_version.to_string();
+		assert!(agent.starts_with("avail-light-client (acme-fleet-03)/"));
+
+		let parsed: AgentVersion = agent.parse().unwrap();
+		assert_eq!(parsed.base_version, "avail-light-client");
+		assert_eq!(parsed.label.as_deref(), Some("acme-fleet-03"));
+		assert_eq!(parsed.kademlia_mode, agent_version.kademlia_mode);
+
+		let unlabeled: AgentVersion = "avail-light-client/1.0.0/rust-client/server"
+			.parse()
+			.unwrap();
+		assert_eq!(unlabeled.label, None);
+
+		assert!(validate_operator_label("node_1.eu-west").is_ok());
+		assert!(validate_operator_label("").is_err());
+		assert!(validate_operator_label("fleet (03)").is_err());
+		assert!(validate_operator_label(&"a".repeat(33)).is_err());
+	}
+}
@@ -1,6 +1,3 @@
-use avail_core::{
-	compact::CompactDataLookup, data_lookup::compact::DataLookupItem, AppId, DataLookup,
-};
 use avail_subxt::{
 	api::runtime_types::{
 		avail_core::{header::extension::v3, header::extension::HeaderExtension},
@@ -21,6 +18,21 @@ use kate_recovery::{
 	data::Cell,
 	matrix::{Dimensions, Position},
 };
+use rand::Rng;
+use sp_core::blake2_256;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::types::DataLookup;
+
+/// Hash of the app data extrinsic, which matches the on-chain extrinsic hash
+pub fn extrinsic_hash(extrinsic: &[u8]) -> H256 {
+	blake2_256(extrinsic).into()
+}
+
+/// Hash of the concatenated app data extrinsics of the block
+pub fn app_data_hash(app_data: &[Vec<u8>]) -> H256 {
+	blake2_256(&app_data.concat()).into()
+}
 
 pub fn decode_app_data(data: &[u8]) -> Result<Option<Vec<u8>>> {
 	let extrisic: AppUncheckedExtrinsic =
@@ -32,9 +44,41 @@ pub fn decode_app_data(data: &[u8]) -> Result<Option<Vec<u8>>> {
 	}
 }
 
-/// Calculates confidence from given number of verified cells
-pub fn calculate_confidence(count: u32) -> f64 {
-	100f64 * (1f64 - 1f64 / 2u32.pow(count) as f64)
+/// Assumed fraction of the cells withheld by an adversary, at which the block data is not recoverable.
+/// With erasure coding which extends the matrix by the factor of 2, it is 50%.
+pub const DEFAULT_WITHHELD_FRACTION: f64 = 0.5;
+
+/// Checks that the assumed withheld fraction can be used in confidence calculations
+pub fn check_withheld_fraction(fraction: f64) -> Result<()> {
+	if !(fraction > 0.0 && fraction < 1.0) {
+		return Err(eyre!(
+			"Withheld fraction {fraction} must be greater than 0 and less than 1"
+		));
+	}
+	Ok(())
+}
+
+/// Time elapsed since the unix epoch, zero if system clock is set before the epoch
+pub fn unix_time() -> Duration {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+}
+
+/// Current unix timestamp in nanoseconds
+pub fn unix_timestamp_nanos() -> u64 {
+	unix_time().as_nanos() as u64
+}
+
+/// Nearest-rank percentile (0 to 100) of the sorted values, which must not be empty
+pub fn percentile(sorted: &[f64], percentile: f64) -> f64 {
+	let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+	sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Random delay up to the given number of seconds, with millisecond precision
+pub fn random_delay(max_secs: u64) -> Duration {
+	Duration::from_millis(rand::thread_rng().gen_range(0..=max_secs.saturating_mul(1000)))
 }
 
 pub trait OptionalExtension {
@@ -71,17 +115,13 @@ pub(crate) fn extract_app_lookup(extension: &HeaderExtension) -> eyre::Result<Op
 		HeaderExtension::V3(v3::HeaderExtension { app_lookup, .. }) => app_lookup,
 	};
 
-	let size = compact.size;
 	let index = compact
 		.index
 		.iter()
-		.map(|item| DataLookupItem::new(AppId(item.app_id.0), item.start))
+		.map(|item| (item.app_id.0, item.start))
 		.collect::<Vec<_>>();
 
-	let compact = CompactDataLookup::new(size, index);
-	DataLookup::try_from(compact)
-		.map(Some)
-		.map_err(|e| eyre!("Invalid DataLookup: {}", e))
+	DataLookup::new(compact.size, &index).map(Some)
 }
 
 pub fn filter_auth_set_changes(header: &DaHeader) -> Vec<Vec<(AuthorityId, u64)>> {
@@ -129,11 +169,15 @@ fn diff_positions(positions: &[Position], cells: &[Cell]) -> Vec<Position> {
 
 #[cfg(test)]
 mod tests {
-	use super::{can_reconstruct, diff_positions};
+	use super::{
+		app_data_hash, can_reconstruct, diff_positions, extrinsic_hash, percentile, random_delay,
+	};
+	use crate::confidence::Confidence;
 	use kate_recovery::{
 		data::Cell,
 		matrix::{Dimensions, Position},
 	};
+	use std::time::Duration;
 
 	fn position(row: u32, col: u16) -> Position {
 		Position { row, col }
@@ -176,6 +220,25 @@ mod tests {
 		assert!(!can_reconstruct(dimensions, &columns, &cells));
 	}
 
+	#[test]
+	fn test_confidence_for_fraction() {
+		assert_eq!(Confidence::from_cell_count(1, 0.5).value(), 50.0);
+		assert_eq!(
+			Confidence::from_cell_count(10, 0.5).value(),
+			100.0 * (1.0 - 1.0 / 1024.0)
+		);
+		assert!(
+			Confidence::from_cell_count(10, 0.25).value()
+				< Confidence::from_cell_count(10, 0.5).value()
+		);
+
+		assert_eq!(Confidence::new(99.9).cell_count(0.5), 10);
+		assert_eq!(Confidence::new(99.99).cell_count(0.5), 14);
+		// Less withheld cells require more samples to reach the same confidence
+		assert_eq!(Confidence::new(99.9).cell_count(0.25), 25);
+		assert!(Confidence::from_cell_count(25, 0.25).value() >= 99.9);
+	}
+
 	#[test]
 	fn test_diff_positions() {
 		let positions = vec![position(0, 0), position(1, 1)];
@@ -193,4 +256,28 @@ mod tests {
 		assert_eq!(diff_positions(&positions, &cells)[0], position(0, 0));
 		assert_eq!(diff_positions(&positions, &cells)[1], position(1, 1));
 	}
+
+	#[test]
+	fn test_app_data_hash() {
+		let expected = "0xbddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319";
+		assert_eq!(format!("{:?}", extrinsic_hash(b"abc")), expected);
+		let app_data = vec![b"a".to_vec(), b"bc".to_vec()];
+		assert_eq!(format!("{:?}", app_data_hash(&app_data)), expected);
+	}
+
+	#[test]
+	fn test_random_delay() {
+		assert_eq!(random_delay(0), Duration::ZERO);
+		assert!((0..100).all(|_| random_delay(5) <= Duration::from_secs(5)));
+	}
+
+	#[test]
+	fn nearest_rank_percentile() {
+		let sorted = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+		assert_eq!(percentile(&sorted, 0.0), 1.0);
+		assert_eq!(percentile(&sorted, 50.0), 5.0);
+		assert_eq!(percentile(&sorted, 90.0), 9.0);
+		assert_eq!(percentile(&sorted, 99.0), 10.0);
+		assert_eq!(percentile(&[3.0], 99.0), 3.0);
+	}
 }